@@ -6,6 +6,7 @@ layout(location = 2) in vec3 tangent;
 layout(location = 3) in vec3 bitangent;
 layout(location = 4) in vec2 uv_0;
 layout(location = 5) in vec2 uv_1;
+layout(location = 6) in float handedness;
 
 layout(location = 0) out vec4 f_color;
 
@@ -31,12 +32,71 @@ layout(push_constant) uniform Material {
     int ao_set;
     int em_set;
     int nm_set;
+
+    vec3 at;
+    float ad;
+    float tr;
+    float th;
+    float ior;
+
+    int tr_set;
+    int th_set;
+
+    // pushed once per frame by the renderer
+    int debug_view;
 } m;
 layout(set = 2, binding = 0) uniform sampler2D bc_sampler;
 layout(set = 2, binding = 1) uniform sampler2D rm_sampler;
 layout(set = 2, binding = 2) uniform sampler2D ao_sampler;
 layout(set = 2, binding = 3) uniform sampler2D em_sampler;
 layout(set = 2, binding = 4) uniform sampler2D nm_sampler;
+layout(set = 2, binding = 5) uniform sampler2D tr_sampler;
+layout(set = 2, binding = 6) uniform sampler2D th_sampler;
+
+#define MAX_LIGHTS 16
+#define LIGHT_DIRECTIONAL 0
+#define LIGHT_POINT 1
+#define LIGHT_SPOT 2
+struct Light {
+    vec3 position;
+    float range;
+    vec3 direction;
+    float intensity;
+    vec3 color;
+    int kind;
+    float inner_cone_cos;
+    float outer_cone_cos;
+};
+layout(set = 3, binding = 0) uniform Lights {
+    Light lights[MAX_LIGHTS];
+    mat4 shadow_view_proj;
+    uint count;
+    int shadow_light;
+    float shadow_bias;
+    float env_intensity;
+    float env_yaw;
+    int traced_occlusion;
+    int ssao;
+    int tone_mapping;
+} l;
+layout(set = 3, binding = 1) uniform sampler2DShadow shadow_map;
+// opaque scene rendered with the same camera, already tone mapped
+layout(set = 3, binding = 2) uniform sampler2D transmission_map;
+// ray traced visibility of the visible surfaces, see traced_occlusion
+layout(set = 3, binding = 3) uniform sampler2D occlusion_map;
+// screen space ambient occlusion, see ssao
+layout(set = 3, binding = 4) uniform sampler2D ssao_map;
+
+#define TRACED_NONE 0
+#define TRACED_AMBIENT_OCCLUSION 1
+#define TRACED_SHADOWS 2
+
+// rotates a direction into the space of the environment maps
+vec3 env_direction(vec3 dir) {
+    float s = sin(l.env_yaw);
+    float c = cos(l.env_yaw);
+    return vec3(c * dir.x + s * dir.z, dir.y, -s * dir.x + c * dir.z);
+}
 
 vec2 get_uv(uint set) {
     if (set == 0) {
@@ -80,12 +140,40 @@ vec3 get_normal() {
         vec3 t = normalize(tangent);
         vec3 b = normalize(bitangent);
         mat3 tbn = mat3(t, b, n);
-        vec3 nm = (texture(nm_sampler, get_uv(m.nm_set)).rgb * 2.0 - 1.0);
+        vec3 nm;
+        nm.xy = texture(nm_sampler, get_uv(m.nm_set)).rg * 2.0 - 1.0;
+        // compressed normal maps only store x and y
+        nm.z = sqrt(max(1.0 - dot(nm.xy, nm.xy), 0.0));
         nm.xy *= m.nm;
         return tbn * normalize(nm);
     }
     return n;
 }
+// filtered normal map mips store how much the averaged normals shortened in alpha,
+// the detail lost to filtering widens the highlight instead (Toksvig)
+float filtered_roughness(float roughness) {
+    if (m.nm_set < 0) {
+        return roughness;
+    }
+    float len = clamp(texture(nm_sampler, get_uv(m.nm_set)).a, 1e-4, 1.0);
+    float variance = (1.0 - len) / len;
+    float a = roughness * roughness;
+    return sqrt(sqrt(clamp(a * a + 2.0 * variance, 0.0, 1.0)));
+}
+float get_transmission() {
+    float tr = 1.0;
+    if (m.tr_set >= 0) {
+        tr = texture(tr_sampler, get_uv(m.tr_set)).r;
+    }
+    return tr * m.tr;
+}
+float get_thickness() {
+    float th = 1.0;
+    if (m.th_set >= 0) {
+        th = texture(th_sampler, get_uv(m.th_set)).g;
+    }
+    return th * m.th;
+}
 
 const float PI = 3.14159265358979323846264338327950288;
 
@@ -120,6 +208,151 @@ vec3 fresnel_shlick(float cos_theta, vec3 f0, float roughness) {
     return f0 + ((1.0 - roughness) - f0) * pow(1.0 - cos_theta, 5.0);
 }
 
+vec3 fresnel_shlick(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Khronos/KHR_lights_punctual#range-property
+float range_attenuation(float range, float dist) {
+    if (range <= 0.0) {
+        return 1.0 / (dist * dist);
+    }
+    return clamp(1.0 - pow(dist / range, 4.0), 0.0, 1.0) / (dist * dist);
+}
+float spot_attenuation(Light light, vec3 L) {
+    float cd = dot(light.direction, -L);
+    if (cd <= light.outer_cone_cos) {
+        return 0.0;
+    }
+    if (cd >= light.inner_cone_cos) {
+        return 1.0;
+    }
+    return smoothstep(light.outer_cone_cos, light.inner_cone_cos, cd);
+}
+
+vec2 screen_uv() {
+    vec4 clip = cam.proj * cam.view * vec4(position, 1.0);
+    return clip.xy / clip.w * 0.5 + 0.5;
+}
+float traced_visibility() {
+    return texture(occlusion_map, screen_uv()).r;
+}
+
+// 3x3 PCF on top of the hardware 2x2 comparison filtering
+float shadow_factor() {
+    vec4 light_space = l.shadow_view_proj * vec4(position, 1.0);
+    vec3 ndc = light_space.xyz / light_space.w;
+    vec2 uv = ndc.xy * 0.5 + 0.5;
+    if (ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0));
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            lit += texture(shadow_map, vec3(uv + vec2(x, y) * texel, ndc.z - l.shadow_bias));
+        }
+    }
+    return lit / 9.0;
+}
+
+vec3 direct_lighting(vec3 N, vec3 V, vec3 bc, vec3 f0, vec2 rm) {
+    vec3 color = vec3(0.0);
+    float n_dot_v = max(dot(N, V), 0.0);
+    uint count = min(l.count, uint(MAX_LIGHTS));
+    for (uint i = 0u; i < count; i++) {
+        Light light = l.lights[i];
+
+        vec3 L;
+        float attenuation = 1.0;
+        if (light.kind == LIGHT_DIRECTIONAL) {
+            L = -normalize(light.direction);
+        } else {
+            vec3 to_light = light.position - position;
+            float dist = length(to_light);
+            L = to_light / dist;
+            attenuation = range_attenuation(light.range, dist);
+            if (light.kind == LIGHT_SPOT) {
+                attenuation *= spot_attenuation(light, L);
+            }
+        }
+        if (int(i) == l.shadow_light) {
+            attenuation *= l.traced_occlusion == TRACED_SHADOWS ? traced_visibility() : shadow_factor();
+        }
+
+        float n_dot_l = max(dot(N, L), 0.0);
+        if (n_dot_l <= 0.0 || attenuation <= 0.0) {
+            continue;
+        }
+        vec3 H = normalize(V + L);
+        float n_dot_h = max(dot(N, H), 0.0);
+        float h_dot_v = max(dot(H, V), 0.0);
+
+        float D = distribution_ggx(n_dot_h, rm.x);
+        float G = geometry_smith(n_dot_v, n_dot_l, rm.x);
+        vec3 F = fresnel_shlick(h_dot_v, f0);
+
+        vec3 specular = D * G * F / (4.0 * n_dot_v * n_dot_l + 0.0001);
+        vec3 kd = (1.0 - F) * (1.0 - rm.y);
+
+        vec3 radiance = light.color * light.intensity * attenuation;
+        color += (kd * bc / PI + specular) * radiance * n_dot_l;
+    }
+    return color;
+}
+
+// https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Khronos/KHR_materials_volume
+vec3 transmitted_light(vec3 N, vec3 V, vec3 bc, float roughness) {
+    float thickness = get_thickness();
+    vec3 refracted = normalize(refract(-V, N, 1.0 / m.ior));
+    vec3 exit = position + refracted * thickness;
+
+    vec4 clip = cam.proj * cam.view * vec4(exit, 1.0);
+    vec2 uv = clip.xy / clip.w * 0.5 + 0.5;
+
+    // rougher and more refractive surfaces blur more
+    float max_lod = log2(float(textureSize(transmission_map, 0).x));
+    float lod = max_lod * roughness * clamp(m.ior * 2.0 - 2.0, 0.0, 1.0);
+    vec3 light = textureLod(transmission_map, uv, lod).rgb;
+
+    vec3 attenuation = vec3(1.0);
+    if (m.ad > 0.0) {
+        attenuation = pow(m.at, vec3(thickness / m.ad));
+    }
+    return light * bc * attenuation;
+}
+
+#define DEBUG_NONE 0
+#define DEBUG_BASE_COLOR 1
+#define DEBUG_NORMAL 2
+#define DEBUG_GEOMETRY_NORMAL 3
+#define DEBUG_TANGENT 4
+#define DEBUG_HANDEDNESS 5
+#define DEBUG_UV_0 6
+#define DEBUG_UV_1 7
+#define DEBUG_METALLIC 8
+#define DEBUG_ROUGHNESS 9
+#define DEBUG_OCCLUSION 10
+#define DEBUG_EMISSIVE 11
+#define DEBUG_EXPOSURE 12
+vec3 debug_color(vec3 bc, vec3 N, vec2 rm, float ao, vec3 em) {
+    switch (m.debug_view) {
+        case DEBUG_BASE_COLOR: return bc;
+        case DEBUG_NORMAL: return N * 0.5 + 0.5;
+        case DEBUG_GEOMETRY_NORMAL: return normalize(normal) * 0.5 + 0.5;
+        case DEBUG_TANGENT: return normalize(tangent) * 0.5 + 0.5;
+        case DEBUG_HANDEDNESS: return handedness < 0.0 ? vec3(1.0, 0.0, 0.0) : vec3(0.0, 1.0, 0.0);
+        case DEBUG_UV_0: return vec3(fract(uv_0), 0.0);
+        case DEBUG_UV_1: return vec3(fract(uv_1), 0.0);
+        case DEBUG_METALLIC: return vec3(rm.y);
+        case DEBUG_ROUGHNESS: return vec3(rm.x);
+        case DEBUG_OCCLUSION: return vec3(ao);
+        case DEBUG_EMISSIVE: return em;
+    }
+    return vec3(1.0, 0.0, 1.0);
+}
+
 vec3 pbr_neutral_tone_mapping(vec3 color) {
     const float startCompression = 0.8 - 0.04;
     const float desaturation = 0.15;
@@ -139,13 +372,38 @@ vec3 pbr_neutral_tone_mapping(vec3 color) {
     return mix(color, newPeak * vec3(1, 1, 1), g);
 }
 
+// one colour per EV relative to middle grey before tone mapping, from -5 to +5
+vec3 false_color(vec3 color) {
+    const vec3 bands[11] = vec3[](
+        vec3(0.3, 0.0, 0.4),
+        vec3(0.0, 0.0, 0.6),
+        vec3(0.0, 0.3, 1.0),
+        vec3(0.0, 0.6, 0.6),
+        vec3(0.0, 0.6, 0.2),
+        vec3(0.5, 0.5, 0.5),
+        vec3(0.5, 0.85, 0.3),
+        vec3(1.0, 0.9, 0.0),
+        vec3(1.0, 0.55, 0.0),
+        vec3(1.0, 0.0, 0.0),
+        vec3(1.0, 1.0, 1.0)
+    );
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    float ev = log2(max(luminance, 1e-10) / 0.18);
+    return bands[clamp(int(round(ev)) + 5, 0, 10)];
+}
+
 void main() {
     vec3 bc = get_base_color().rgb;
     float ao = get_ambient_occlusion();
     vec2 rm = get_roughness_metallic();
+    rm.x = filtered_roughness(rm.x);
     vec3 em = get_emmissive();
 
     vec3 N = get_normal();
+    if (m.debug_view != DEBUG_NONE && m.debug_view != DEBUG_EXPOSURE) {
+        f_color = vec4(debug_color(bc, N, rm, ao, em), 1.0);
+        return;
+    }
     vec3 V = normalize(cam.view_inv[3].xyz - position);
     vec3 R = reflect(-V, N);
     vec3 f0 = mix(vec3(0.04), bc, rm.y);
@@ -155,15 +413,33 @@ void main() {
     vec3 f = fresnel_shlick(n_dot_v, f0, rm.x);
     vec3 kd = (1.0 - f) * (1.0 - rm.y);
 
-    vec3 diffuse = texture(envMap, N).rgb * bc * kd;
+    // transmission replaces the diffuse lobe of dielectrics
+    float transmission = get_transmission() * (1.0 - rm.y);
 
-    const float MAX_REFLECTION_LOD = 4.0;
+    vec3 diffuse = texture(envMap, env_direction(N)).rgb * bc * kd * (1.0 - transmission);
+
+    float MAX_REFLECTION_LOD = float(textureQueryLevels(spcMap) - 1);
     vec2 brdf = texture(lutMap, vec2(n_dot_v, rm.x)).rg;
-    vec3 specular = textureLod(spcMap, R, rm.x * MAX_REFLECTION_LOD).rgb * (f * brdf.x + brdf.y);
+    vec3 specular = textureLod(spcMap, env_direction(R), rm.x * MAX_REFLECTION_LOD).rgb * (f * brdf.x + brdf.y);
 
-    vec3 ambient = (diffuse + specular) * ao;
-    vec3 color = ambient + em;
-    f_color = vec4(pbr_neutral_tone_mapping(color), 1.0);
+    if (l.traced_occlusion == TRACED_AMBIENT_OCCLUSION) {
+        ao *= traced_visibility();
+    }
+    if (l.ssao != 0) {
+        ao *= texture(ssao_map, screen_uv()).r;
+    }
+    vec3 ambient = (diffuse + specular) * ao * l.env_intensity;
+    vec3 direct = direct_lighting(N, V, bc * (1.0 - transmission), f0, rm);
+    vec3 color = ambient + direct + em;
+    if (m.debug_view == DEBUG_EXPOSURE) {
+        f_color = vec4(false_color(color), 1.0);
+        return;
+    }
+    vec3 mapped = l.tone_mapping != 0 ? pbr_neutral_tone_mapping(color) : color;
+    if (transmission > 0.0) {
+        mapped += (1.0 - f) * transmission * transmitted_light(N, V, bc, rm.x);
+    }
+    f_color = vec4(mapped, 1.0);
 
     // vec3 t = normalize(tangent);
     // vec3 b = normalize(bitangent);
@@ -1,5 +1,8 @@
 #version 450
 
+#define MORPH_SET 4
+#include "morph.glsl"
+
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec3 normal;
 layout(location = 2) in vec4 tangent;
@@ -23,18 +26,20 @@ layout(location = 2) out vec3 f_tangent;
 layout(location = 3) out vec3 f_bitangent;
 layout(location = 4) out vec2 f_uv_0;
 layout(location = 5) out vec2 f_uv_1;
+layout(location = 6) out float f_handedness;
 
 void main() {
     mat4 model = mat4(model_x, model_y, model_z, model_w);
     mat3 model_inv_t = transpose(inverse(mat3(model)));
-    vec4 pos = model * vec4(position, 1.0);
+    vec4 pos = model * vec4(position + morph_delta(MORPH_POSITION), 1.0);
 
     f_position = pos.xyz;
-    f_normal = model_inv_t * normal;
-    f_tangent = model_inv_t * tangent.xyz;
+    f_normal = model_inv_t * (normal + morph_delta(MORPH_NORMAL));
+    f_tangent = model_inv_t * (tangent.xyz + morph_delta(MORPH_TANGENT));
     f_bitangent = cross(f_normal, f_tangent) * tangent.w;
     f_uv_0 = uv_0;
     f_uv_1 = uv_1;
+    f_handedness = tangent.w;
 
     gl_Position = cam.proj * cam.view * pos;
 }
@@ -0,0 +1,29 @@
+// Morph target deltas, expects `MORPH_SET` to be defined.
+
+#define MAX_MORPH_TARGETS 32
+
+#define MORPH_POSITION 0
+#define MORPH_NORMAL 1
+#define MORPH_TANGENT 2
+
+// [vertex][target][attribute], vertices are relative to the first vertex of the primitive
+// since primitives share their vertex buffer
+layout(set = MORPH_SET, binding = 0) readonly buffer MorphDeltas {
+    uint base_vertex;
+    vec4 deltas[];
+} morph_deltas;
+
+layout(set = MORPH_SET, binding = 1) uniform MorphWeights {
+    uint count;
+    vec4 weights[MAX_MORPH_TARGETS / 4];
+} morph;
+
+vec3 morph_delta(uint attribute) {
+    vec3 delta = vec3(0.0);
+    uint base = (uint(gl_VertexIndex) - morph_deltas.base_vertex) * morph.count;
+    for (uint i = 0; i < morph.count; i++) {
+        float weight = morph.weights[i / 4][i % 4];
+        delta += weight * morph_deltas.deltas[(base + i) * 3 + attribute].xyz;
+    }
+    return delta;
+}
@@ -0,0 +1,423 @@
+use crate::{
+    Allocators,
+    camera::Camera,
+    memory::MemoryCategory,
+    vktf::{GltfRenderInfo, ModelTransform, loader::PrimitiveVertex},
+};
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use vulkano::{
+    Packed24_8,
+    acceleration_structure::{
+        AccelerationStructure, AccelerationStructureBuildGeometryInfo,
+        AccelerationStructureBuildRangeInfo, AccelerationStructureBuildType,
+        AccelerationStructureCreateInfo, AccelerationStructureGeometries,
+        AccelerationStructureGeometryInstancesData, AccelerationStructureGeometryInstancesDataType,
+        AccelerationStructureGeometryTrianglesData, AccelerationStructureInstance,
+        AccelerationStructureType, BuildAccelerationStructureFlags, BuildAccelerationStructureMode,
+    },
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, IndexBuffer, Subbuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, PrimaryCommandBufferAbstract,
+        allocator::CommandBufferAllocator,
+    },
+    device::{Device, Queue},
+    format::Format,
+    memory::{
+        DeviceAlignment,
+        allocator::{AllocationCreateInfo, DeviceLayout, MemoryAllocator, MemoryTypeFilter},
+    },
+    sync::GpuFuture,
+};
+
+/// Per geometry data for the shaders, found with the instance custom index.
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+pub struct Geometry {
+    vertices: u64,
+    /// 0 if the geometry isn't indexed.
+    indices: u64,
+    base_color: [f32; 4],
+    /// `w` is unused.
+    emissive: [f32; 4],
+    /// Bytes per index, 0 if the geometry isn't indexed.
+    index_size: u32,
+    _pad: [u32; 3],
+}
+
+/// Inverse camera matrices to generate primary rays from.
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+pub struct RaytracerCamera {
+    view_inverse: glm::Mat4,
+    proj_inverse: glm::Mat4,
+}
+impl RaytracerCamera {
+    pub fn new(camera: &Camera, aspect: f32) -> Self {
+        Self {
+            view_inverse: camera.look_at().try_inverse().unwrap(),
+            proj_inverse: camera.perspective(aspect).try_inverse().unwrap(),
+        }
+    }
+}
+
+/// Identifies the geometry and placement of a model to know when to rebuild.
+#[derive(Clone, PartialEq)]
+struct SceneKey {
+    vktf: usize,
+    /// The simplified geometry being drawn.
+    lod: Option<usize>,
+    /// The material variant, the geometries hold the materials.
+    variant: Option<usize>,
+    transform: ModelTransform,
+}
+
+/// The loaded models as acceleration structures, shared by the ray traced render modes.
+/// Models are in their rest pose, morph targets are ignored.
+#[derive(Clone)]
+pub struct SceneAcceleration {
+    pub tlas: Option<Arc<AccelerationStructure>>,
+    pub geometries: Option<Subbuffer<[Geometry]>>,
+    allocators: Allocators,
+    scene: Vec<SceneKey>,
+
+    _blas: Vec<Arc<AccelerationStructure>>,
+}
+impl SceneAcceleration {
+    pub fn new(allocators: Allocators) -> Self {
+        Self {
+            tlas: None,
+            geometries: None,
+            allocators,
+            scene: vec![],
+            _blas: vec![],
+        }
+    }
+    /// Rebuilds the acceleration structures if models were added, removed, moved, simplified or
+    /// switched to another material variant.
+    pub fn build(&mut self, queue: Arc<Queue>, models: &[GltfRenderInfo]) {
+        let scene: Vec<_> = models
+            .iter()
+            .map(|info| SceneKey {
+                vktf: Arc::as_ptr(&info.vktf) as usize,
+                lod: info
+                    .lod()
+                    .filter(|_| info.lod_shown())
+                    .map(|lod| Arc::as_ptr(lod) as usize),
+                variant: info.variant(),
+                transform: info.transform(),
+            })
+            .collect();
+        if scene == self.scene {
+            return;
+        }
+        self.scene = scene;
+
+        let mut blas = vec![];
+        let mut instances = vec![];
+        let mut geometries = vec![];
+        for info in models {
+            let root = info.transform().matrix();
+            for mesh in &info.meshes {
+                for (material, primitive) in mesh.primitives() {
+                    let primitive_blas = unsafe {
+                        build_acceleration_structure_triangles(
+                            primitive.vertices().clone(),
+                            primitive.indices().cloned(),
+                            self.allocators
+                                .memory
+                                .allocator(MemoryCategory::AccelerationStructures),
+                            self.allocators.cmd.clone(),
+                            queue.device().clone(),
+                            queue.clone(),
+                        )
+                    };
+                    let push = info.materials.get(material).unwrap().push;
+                    instances.extend(mesh.transforms().iter().map(|transform| {
+                        AccelerationStructureInstance {
+                            acceleration_structure_reference: primitive_blas
+                                .device_address()
+                                .into(),
+                            transform: (root * transform).remove_row(3).transpose().into(),
+                            instance_custom_index_and_mask: Packed24_8::new(
+                                geometries.len() as u32,
+                                0xFF,
+                            ),
+                            ..Default::default()
+                        }
+                    }));
+                    geometries.push(Geometry {
+                        vertices: primitive.vertices().device_address().unwrap().get(),
+                        indices: primitive.indices().map_or(0, |indices| {
+                            indices.as_bytes().device_address().unwrap().get()
+                        }),
+                        base_color: push.bc.into(),
+                        emissive: glm::vec3_to_vec4(&push.em).into(),
+                        index_size: primitive
+                            .indices()
+                            .map_or(0, |indices| indices.index_type().size() as u32),
+                        _pad: [0; 3],
+                    });
+                    blas.push(primitive_blas);
+                }
+            }
+        }
+
+        if instances.is_empty() {
+            self.tlas = None;
+            self.geometries = None;
+            self._blas = vec![];
+            return;
+        }
+
+        let tlas = unsafe {
+            build_top_level_acceleration_structure(
+                instances,
+                self.allocators
+                    .memory
+                    .allocator(MemoryCategory::AccelerationStructures),
+                self.allocators.cmd.clone(),
+                queue.device().clone(),
+                queue.clone(),
+            )
+        };
+        let geometries = Buffer::from_iter(
+            self.allocators
+                .memory
+                .allocator(MemoryCategory::AccelerationStructures),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            geometries,
+        )
+        .unwrap();
+
+        self.tlas = Some(tlas);
+        self.geometries = Some(geometries);
+        self._blas = blas;
+    }
+}
+
+/// A helper function to build a acceleration structure and wait for its completion.
+///
+/// # Safety
+///
+/// - If you are referencing a bottom-level acceleration structure in a top-level acceleration
+///   structure, you must ensure that the bottom-level acceleration structure is kept alive.
+unsafe fn build_acceleration_structure_common(
+    geometries: AccelerationStructureGeometries,
+    primitive_count: u32,
+    ty: AccelerationStructureType,
+    memory_allocator: Arc<dyn MemoryAllocator>,
+    command_buffer_allocator: Arc<dyn CommandBufferAllocator>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+) -> Arc<AccelerationStructure> {
+    let mut as_build_geometry_info = AccelerationStructureBuildGeometryInfo {
+        mode: BuildAccelerationStructureMode::Build,
+        flags: BuildAccelerationStructureFlags::PREFER_FAST_TRACE,
+        ..AccelerationStructureBuildGeometryInfo::new(geometries)
+    };
+
+    let as_build_sizes_info = device
+        .acceleration_structure_build_sizes(
+            AccelerationStructureBuildType::Device,
+            &as_build_geometry_info,
+            &[primitive_count],
+        )
+        .unwrap();
+
+    // We create a new scratch buffer for each acceleration structure for simplicity. You may want
+    // to reuse scratch buffers if you need to build many acceleration structures.
+    // let scratch_buffer = Buffer::new_slice::<u8>(
+    //     memory_allocator.clone(),
+    //     BufferCreateInfo {
+    //         usage: BufferUsage::SHADER_DEVICE_ADDRESS | BufferUsage::STORAGE_BUFFER,
+    //         ..Default::default()
+    //     },
+    //     AllocationCreateInfo::default(),
+    //     as_build_sizes_info.build_scratch_size,
+    // )
+    // .unwrap()
+    // .align_to(
+    //     DeviceLayout::new(
+    //         as_build_sizes_info.build_scratch_size.try_into().unwrap(),
+    //         DeviceAlignment::new(
+    //             device
+    //                 .physical_device()
+    //                 .properties()
+    //                 .min_acceleration_structure_scratch_offset_alignment
+    //                 .unwrap()
+    //                 .try_into()
+    //                 .unwrap(),
+    //         )
+    //         .unwrap(),
+    //     )
+    //     .unwrap(),
+    // );
+
+    let scratch_buffer = Buffer::new(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::SHADER_DEVICE_ADDRESS | BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+        DeviceLayout::new(
+            as_build_sizes_info.build_scratch_size.try_into().unwrap(),
+            DeviceAlignment::new(
+                device
+                    .physical_device()
+                    .properties()
+                    .min_acceleration_structure_scratch_offset_alignment
+                    .unwrap()
+                    .into(),
+            )
+            .unwrap(),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    let as_create_info = AccelerationStructureCreateInfo {
+        ty,
+        ..AccelerationStructureCreateInfo::new(
+            Buffer::new_slice::<u8>(
+                memory_allocator,
+                BufferCreateInfo {
+                    usage: BufferUsage::ACCELERATION_STRUCTURE_STORAGE
+                        | BufferUsage::SHADER_DEVICE_ADDRESS,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+                as_build_sizes_info.acceleration_structure_size,
+            )
+            .unwrap(),
+        )
+    };
+
+    let acceleration = unsafe { AccelerationStructure::new(device, as_create_info) }.unwrap();
+
+    as_build_geometry_info.dst_acceleration_structure = Some(acceleration.clone());
+    as_build_geometry_info.scratch_data = Some(scratch_buffer.into());
+
+    let as_build_range_info = AccelerationStructureBuildRangeInfo {
+        primitive_count,
+        ..Default::default()
+    };
+
+    // For simplicity, we build a single command buffer that builds the acceleration structure,
+    // then waits for its execution to complete.
+    let mut builder = AutoCommandBufferBuilder::primary(
+        command_buffer_allocator,
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+
+    unsafe {
+        builder
+            .build_acceleration_structure(
+                as_build_geometry_info,
+                std::iter::once(as_build_range_info).collect(),
+            )
+            .unwrap()
+    };
+
+    builder
+        .build()
+        .unwrap()
+        .execute(queue)
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+
+    acceleration
+}
+
+unsafe fn build_acceleration_structure_triangles(
+    vertex_buffer: Subbuffer<[PrimitiveVertex]>,
+    index_buffer: Option<IndexBuffer>,
+    memory_allocator: Arc<dyn MemoryAllocator>,
+    command_buffer_allocator: Arc<dyn CommandBufferAllocator>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+) -> Arc<AccelerationStructure> {
+    let primitive_count = (index_buffer
+        .as_ref()
+        .map_or(vertex_buffer.len(), IndexBuffer::len)
+        / 3) as u32;
+    let as_geometry_triangles_data = AccelerationStructureGeometryTrianglesData {
+        max_vertex: vertex_buffer.len() as _,
+        vertex_data: Some(vertex_buffer.into_bytes()),
+        vertex_stride: size_of::<PrimitiveVertex>() as _,
+        index_data: index_buffer,
+        ..AccelerationStructureGeometryTrianglesData::new(Format::R32G32B32_SFLOAT)
+    };
+
+    let geometries = AccelerationStructureGeometries::Triangles(vec![as_geometry_triangles_data]);
+
+    unsafe {
+        build_acceleration_structure_common(
+            geometries,
+            primitive_count,
+            AccelerationStructureType::BottomLevel,
+            memory_allocator,
+            command_buffer_allocator,
+            device,
+            queue,
+        )
+    }
+}
+
+unsafe fn build_top_level_acceleration_structure(
+    as_instances: Vec<AccelerationStructureInstance>,
+    allocator: Arc<dyn MemoryAllocator>,
+    command_buffer_allocator: Arc<dyn CommandBufferAllocator>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+) -> Arc<AccelerationStructure> {
+    let primitive_count = as_instances.len() as u32;
+
+    let instance_buffer = Buffer::from_iter(
+        allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::SHADER_DEVICE_ADDRESS
+                | BufferUsage::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        as_instances,
+    )
+    .unwrap();
+
+    let as_geometry_instances_data = AccelerationStructureGeometryInstancesData::new(
+        AccelerationStructureGeometryInstancesDataType::Values(Some(instance_buffer)),
+    );
+
+    let geometries = AccelerationStructureGeometries::Instances(as_geometry_instances_data);
+
+    unsafe {
+        build_acceleration_structure_common(
+            geometries,
+            primitive_count,
+            AccelerationStructureType::TopLevel,
+            allocator,
+            command_buffer_allocator,
+            device,
+            queue,
+        )
+    }
+}
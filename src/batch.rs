@@ -0,0 +1,252 @@
+use crate::{
+    Allocators, GltfViewerRenderer, RenderTarget,
+    camera::Camera,
+    frameinfo::{Depth, Msaa},
+    memory::MemoryCategory,
+};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use vulkano::{
+    DeviceSize,
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo,
+        PrimaryCommandBufferAbstract,
+    },
+    device::Queue,
+    format::Format,
+    image::{Image, ImageCreateInfo, ImageType, ImageUsage, view::ImageView},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    sync::GpuFuture,
+};
+
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// The image the thumbnails are drawn in and the buffer they are read back from.
+struct Target {
+    image: Arc<Image>,
+    buffer: Subbuffer<[u8]>,
+}
+
+/// Renders a thumbnail of each of a list of models into a folder, one after another by a
+/// second viewer that loads the models in the background, and keeps the ones that failed.
+#[derive(Default)]
+pub struct BatchExport {
+    pub open: bool,
+    output: PathBuf,
+    queued: VecDeque<PathBuf>,
+    total: usize,
+    written: usize,
+    failed: Vec<(PathBuf, String)>,
+    /// Made when the first batch is started.
+    renderer: Option<Box<GltfViewerRenderer>>,
+    target: Option<Target>,
+    /// The model the renderer is loading.
+    rendering: Option<PathBuf>,
+}
+impl BatchExport {
+    /// Writes a thumbnail of each of `models` to `output`, after the ones already queued.
+    pub fn start(&mut self, models: Vec<PathBuf>, output: PathBuf) {
+        if self.queued.is_empty() && self.rendering.is_none() {
+            self.total = 0;
+            self.written = 0;
+            self.failed.clear();
+        }
+        self.total += models.len();
+        self.queued.extend(models);
+        self.output = output;
+        self.open = true;
+    }
+    pub fn running(&self) -> bool {
+        !self.queued.is_empty() || self.rendering.is_some()
+    }
+
+    /// Renders the next thumbnail, call once per frame.
+    pub fn update(&mut self, allocators: &Allocators, queue: &Arc<Queue>) {
+        if self.rendering.is_none() {
+            let Some(path) = self.queued.pop_front() else {
+                return;
+            };
+            let target = self
+                .target
+                .get_or_insert_with(|| thumbnail_target(allocators));
+            let view = ImageView::new_default(target.image.clone()).unwrap();
+            let renderer = self.renderer.get_or_insert_with(|| {
+                let mut renderer = GltfViewerRenderer::new(
+                    allocators,
+                    queue.clone(),
+                    None,
+                    RenderTarget {
+                        views: vec![view],
+                        msaa: Msaa::default(),
+                        depth: Depth::default(),
+                    },
+                    Camera::default(),
+                );
+                renderer.load_sky(Default::default());
+                Box::new(renderer)
+            });
+            renderer.load_model(path.clone());
+            self.rendering = Some(path);
+        }
+        let (Some(renderer), Some(target), Some(path)) =
+            (&mut self.renderer, &self.target, &self.rendering)
+        else {
+            return;
+        };
+
+        // drawn and copied every frame while loading, the model is framed and drawn
+        // by the update adding it
+        let mut builder = AutoCommandBufferBuilder::primary(
+            allocators.cmd.clone(),
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        renderer.render(&mut builder, 0);
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                target.image.clone(),
+                target.buffer.clone(),
+            ))
+            .unwrap();
+        let result = builder
+            .build()
+            .unwrap()
+            .execute(queue.clone())
+            .map_err(|err| err.to_string())
+            .and_then(|future| {
+                future
+                    .then_signal_fence_and_flush()
+                    .and_then(|fence| fence.wait(None))
+                    .map_err(|err| err.to_string())
+            });
+        let state = renderer.state_mut();
+        if state.loading() {
+            return;
+        }
+
+        let result = match result {
+            Err(err) => Err(err),
+            Ok(()) if !state.errors.is_empty() => Err(state.errors.join("\n")),
+            Ok(()) => save(&target.buffer, &self.output, path),
+        };
+        state.errors.clear();
+        while !state.viewer.renderer.models.is_empty() {
+            state.viewer.remove(0);
+        }
+        match result {
+            Ok(file) => {
+                log::info!("wrote {}", file.display());
+                self.written += 1;
+            }
+            Err(err) => {
+                log::error!("failed to export a thumbnail of {}: {err}", path.display());
+                self.failed.push((path.clone(), err));
+            }
+        }
+        self.rendering = None;
+    }
+
+    pub fn window(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        egui::Window::new("Batch export")
+            .open(&mut open)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                ui.label(format!("Thumbnails in {}", self.output.display()));
+                let done = self.written + self.failed.len();
+                ui.add(
+                    egui::ProgressBar::new(done as f32 / self.total.max(1) as f32)
+                        .text(format!("{done} / {}", self.total)),
+                );
+                ui.horizontal(|ui| {
+                    if self.running() {
+                        ui.spinner();
+                        if let Some(path) = &self.rendering {
+                            ui.add(egui::Label::new(path.display().to_string()).truncate());
+                        }
+                    } else {
+                        ui.label(format!(
+                            "{} written, {} failed",
+                            self.written,
+                            self.failed.len()
+                        ));
+                    }
+                });
+                if ui
+                    .add_enabled(!self.queued.is_empty(), egui::Button::new("Cancel"))
+                    .on_hover_text("Skip the models that aren't loaded yet")
+                    .clicked()
+                {
+                    self.total -= self.queued.len();
+                    self.queued.clear();
+                }
+                if !self.failed.is_empty() {
+                    ui.separator();
+                    ui.label("Failed");
+                    egui::ScrollArea::vertical()
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            for (path, err) in &self.failed {
+                                ui.label(path.display().to_string()).on_hover_text(err);
+                            }
+                        });
+                }
+            });
+        // the batch continues while the window is closed
+        self.open = open;
+    }
+}
+
+/// Writes the thumbnail in `buffer` as a .png named after the model into `output`.
+fn save(buffer: &Subbuffer<[u8]>, output: &Path, model: &Path) -> Result<PathBuf, String> {
+    let name = model
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "model".to_owned());
+    let file = output.join(format!("{name}.png"));
+    let pixels = buffer.read().map_err(|err| err.to_string())?;
+    image::save_buffer(
+        &file,
+        &pixels,
+        THUMBNAIL_SIZE,
+        THUMBNAIL_SIZE,
+        image::ColorType::Rgba8,
+    )
+    .map_err(|err| err.to_string())?;
+    Ok(file)
+}
+
+fn thumbnail_target(allocators: &Allocators) -> Target {
+    let image = Image::new(
+        allocators.memory.allocator(MemoryCategory::RenderTargets),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::R8G8B8A8_SRGB,
+            extent: [THUMBNAIL_SIZE, THUMBNAIL_SIZE, 1],
+            usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+    let buffer = Buffer::new_slice::<u8>(
+        allocators.memory.allocator(MemoryCategory::RenderTargets),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        (THUMBNAIL_SIZE * THUMBNAIL_SIZE * 4) as DeviceSize,
+    )
+    .unwrap();
+    Target { image, buffer }
+}
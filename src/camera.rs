@@ -1,9 +1,296 @@
 use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
 use std::f32::consts::FRAC_PI_2;
 use std::f32::consts::FRAC_PI_3;
+use std::f32::consts::PI;
 use std::f32::consts::TAU;
 
-#[derive(Debug, Clone, Copy)]
+/// How fast a released orbit rotation slows down, per second.
+const SPIN_DECAY: f32 = 4.0;
+/// Spin in radians per second below which the camera stops.
+const MIN_SPIN: f32 = 0.01;
+/// Smallest ratio of the near to the far plane when the camera is inside the scene, so the
+/// depth buffer keeps enough precision.
+const MIN_NEAR_RATIO: f32 = 0.0005;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CameraMode {
+    #[default]
+    Orbit,
+    Fly,
+}
+
+/// Axis aligned views of the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewPreset {
+    Front,
+    Top,
+    Side,
+}
+impl ViewPreset {
+    pub const ALL: [ViewPreset; 3] = [ViewPreset::Front, ViewPreset::Top, ViewPreset::Side];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ViewPreset::Front => "Front",
+            ViewPreset::Top => "Top",
+            ViewPreset::Side => "Side",
+        }
+    }
+    /// Yaw and pitch of the orbit camera.
+    fn angles(&self) -> (f32, f32) {
+        match self {
+            ViewPreset::Front => (0.0, 0.0),
+            // straight down would make the up vector parallel to the view
+            ViewPreset::Top => (0.0, FRAC_PI_2 - 0.001),
+            ViewPreset::Side => (FRAC_PI_2, 0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Camera {
+    pub mode: CameraMode,
+    pub orbit: OrbitCamera,
+    pub fly: FlyCamera,
+    /// Keep the near and far plane set by hand instead of fitting them to the scene.
+    #[serde(default)]
+    pub manual_clip: bool,
+    /// Yaw and pitch per second the orbit camera keeps turning with after a drag.
+    #[serde(skip)]
+    spin: egui::Vec2,
+}
+impl Camera {
+    pub fn eye(&self) -> glm::Vec3 {
+        match self.mode {
+            CameraMode::Orbit => self.orbit.eye(),
+            CameraMode::Fly => self.fly.position,
+        }
+    }
+    /// The point the camera is focused on.
+    pub fn target(&self) -> glm::Vec3 {
+        match self.mode {
+            CameraMode::Orbit => self.orbit.target,
+            CameraMode::Fly => self.fly.position + self.fly.forward() * self.orbit.zoom,
+        }
+    }
+    pub fn look_at(&self) -> glm::Mat4 {
+        match self.mode {
+            CameraMode::Orbit => self.orbit.look_at(),
+            CameraMode::Fly => self.fly.look_at(),
+        }
+    }
+    pub fn perspective(&self, aspect: f32) -> glm::Mat4 {
+        match self.mode {
+            CameraMode::Orbit => self.orbit.perspective(aspect),
+            CameraMode::Fly => self.fly.perspective(aspect),
+        }
+    }
+    /// The projection used for rendering, reverse-Z swaps the depth of the near and far plane.
+    pub fn projection(&self, aspect: f32, reverse_z: bool) -> glm::Mat4 {
+        if !reverse_z {
+            return self.perspective(aspect);
+        }
+        let (fov, near, far) = match self.mode {
+            CameraMode::Orbit => (self.orbit.fov, self.orbit.near, self.orbit.far),
+            CameraMode::Fly => (self.fly.fov, self.fly.near, self.fly.far),
+        };
+        glm::perspective_lh_zo(aspect, fov, far, near)
+    }
+
+    /// Sets the vertical field of view of both modes in radians.
+    pub fn set_fov(&mut self, fov: f32) {
+        self.orbit.fov = fov;
+        self.fly.fov = fov;
+    }
+
+    /// Moves the near and far plane as close as possible around a sphere at `center` with
+    /// `radius` and the orbit target, unless they are set by hand. Call every frame.
+    pub fn fit_clip(&mut self, center: glm::Vec3, radius: f32) {
+        if self.manual_clip {
+            return;
+        }
+        let distance = glm::distance(&self.eye(), &center);
+        let mut near = distance - radius;
+        let mut far = distance + radius;
+        // zooming is limited to the range, so it can't shrink around the target
+        if self.mode == CameraMode::Orbit {
+            near = near.min(self.orbit.zoom);
+            far = far.max(self.orbit.zoom);
+        }
+        // a margin so the closest and furthest geometry isn't clipped
+        let far = (far * 1.01).max(0.01);
+        let near = (near * 0.99).max(far * MIN_NEAR_RATIO);
+        match self.mode {
+            CameraMode::Orbit => (self.orbit.near, self.orbit.far) = (near, far),
+            CameraMode::Fly => (self.fly.near, self.fly.far) = (near, far),
+        }
+    }
+
+    /// Moves the camera so a sphere at `center` with `radius` fills the view.
+    pub fn frame(&mut self, center: glm::Vec3, radius: f32) {
+        let radius = radius.max(0.001);
+        let fov = match self.mode {
+            CameraMode::Orbit => self.orbit.fov,
+            CameraMode::Fly => self.fly.fov,
+        };
+        let distance = radius / (fov * 0.5).sin();
+        let near = distance * 0.01;
+        let far = (distance + radius) * 2.0;
+
+        self.orbit.target = center;
+        self.orbit.zoom = distance;
+        self.orbit.near = near;
+        self.orbit.far = far;
+
+        self.fly.position = center - self.fly.forward() * distance;
+        self.fly.near = near;
+        self.fly.far = far;
+        self.fly.speed = radius;
+        self.spin = egui::Vec2::ZERO;
+    }
+
+    /// Moves the camera to `eye` looking at `target`, keeping the mode and the projection.
+    pub fn look_from(&mut self, eye: glm::Vec3, target: glm::Vec3) {
+        let offset = eye - target;
+        let distance = offset.norm();
+        if !distance.is_normal() {
+            return;
+        }
+        let mode = self.mode;
+        self.mode = CameraMode::Orbit;
+        let dir = offset / distance;
+        self.orbit.target = target;
+        self.orbit.zoom = distance;
+        self.orbit.pitch = dir.y.clamp(-1.0, 1.0).asin();
+        self.orbit.yaw = dir.x.atan2(dir.z);
+        self.orbit.wrap();
+        self.set_mode(mode);
+        self.spin = egui::Vec2::ZERO;
+    }
+
+    /// Switches mode while keeping the current view.
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if self.mode == mode {
+            return;
+        }
+        match mode {
+            CameraMode::Orbit => {
+                self.orbit.target = self.target();
+                self.orbit.yaw = self.fly.yaw;
+                self.orbit.pitch = self.fly.pitch;
+                self.orbit.fov = self.fly.fov;
+                self.orbit.near = self.fly.near;
+                self.orbit.far = self.fly.far;
+                self.orbit.wrap();
+            }
+            CameraMode::Fly => {
+                self.fly.position = self.orbit.eye();
+                self.fly.yaw = self.orbit.yaw;
+                self.fly.pitch = self.orbit.pitch;
+                self.fly.fov = self.orbit.fov;
+                self.fly.near = self.orbit.near;
+                self.fly.far = self.orbit.far;
+                self.fly.clamp();
+            }
+        }
+        self.mode = mode;
+    }
+
+    /// Looks at the target from one of the presets, keeping the distance and mode.
+    pub fn view_from(&mut self, preset: ViewPreset) {
+        let mode = self.mode;
+        self.set_mode(CameraMode::Orbit);
+        (self.orbit.yaw, self.orbit.pitch) = preset.angles();
+        self.set_mode(mode);
+        self.spin = egui::Vec2::ZERO;
+    }
+
+    /// Mouse, keyboard, touchpad and touch screen input over the viewport.
+    pub fn input(&mut self, response: &egui::Response) {
+        // gestures over the side panel belong to the UI
+        let touch = response
+            .ctx
+            .input(|i| i.multi_touch())
+            .filter(|_| response.contains_pointer());
+        match self.mode {
+            CameraMode::Orbit => {
+                let rotation = self.orbit.input(response, touch);
+                self.spin(response, rotation);
+            }
+            CameraMode::Fly => self.fly.input(response, touch),
+        }
+    }
+    /// Keeps the orbit camera turning after a quick drag and slows it down smoothly.
+    fn spin(&mut self, response: &egui::Response, rotation: egui::Vec2) {
+        let dt = response.ctx.input(|i| i.stable_dt).max(0.001);
+        if response.dragged() {
+            // averaged over a few frames so an uneven last frame doesn't decide the speed
+            self.spin += (rotation / dt - self.spin) * 0.3;
+        } else if self.spin.length() > MIN_SPIN {
+            self.orbit.yaw += self.spin.x * dt;
+            self.orbit.pitch += self.spin.y * dt;
+            self.orbit.wrap();
+            self.spin *= (-SPIN_DECAY * dt).exp();
+        } else {
+            self.spin = egui::Vec2::ZERO;
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let mut mode = self.mode;
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut mode, CameraMode::Orbit, "Orbit");
+            ui.selectable_value(&mut mode, CameraMode::Fly, "Fly");
+        });
+        self.set_mode(mode);
+        ui.horizontal(|ui| {
+            for preset in ViewPreset::ALL {
+                if ui.button(preset.name()).clicked() {
+                    self.view_from(preset);
+                }
+            }
+        });
+
+        ui.separator();
+
+        match self.mode {
+            CameraMode::Orbit => self.orbit.ui(ui),
+            CameraMode::Fly => self.fly.ui(ui),
+        }
+
+        ui.separator();
+
+        let mut auto_clip = !self.manual_clip;
+        ui.checkbox(&mut auto_clip, "Fit near/far to the scene")
+            .on_hover_text("Place the near and far plane around the loaded models and the target");
+        self.manual_clip = !auto_clip;
+        let (near, far) = match self.mode {
+            CameraMode::Orbit => (&mut self.orbit.near, &mut self.orbit.far),
+            CameraMode::Fly => (&mut self.fly.near, &mut self.fly.far),
+        };
+        ui.add_enabled_ui(self.manual_clip, |ui| clip_ui(ui, near, far));
+    }
+}
+
+fn clip_ui(ui: &mut egui::Ui, near: &mut f32, far: &mut f32) {
+    ui.label("Near");
+    let diff = 0.01;
+    let old_near = *near;
+    ui.add(
+        egui::DragValue::new(near)
+            .range(diff..=*far - diff)
+            .speed(0.1),
+    );
+    ui.label("Far");
+    ui.add(
+        egui::DragValue::new(far)
+            .range(old_near + diff..=f32::MAX)
+            .speed(0.1),
+    );
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct OrbitCamera {
     pub target: glm::Vec3,
     pub zoom: f32,
@@ -47,6 +334,50 @@ impl OrbitCamera {
     pub fn clamp(&mut self) {
         self.zoom = self.zoom.clamp(self.near, self.far);
     }
+
+    /// Two fingers pan and pinch, returns the yaw and pitch the drag rotated by.
+    pub fn input(
+        &mut self,
+        response: &egui::Response,
+        touch: Option<egui::MultiTouchInfo>,
+    ) -> egui::Vec2 {
+        let (modifiers, smooth_scroll, zoom_delta) = response
+            .ctx
+            .input(|i| (i.modifiers, i.smooth_scroll_delta, i.zoom_delta()));
+
+        let mut rotation = egui::Vec2::ZERO;
+        // pan
+        if let Some(touch) = touch {
+            self.pan(touch.translation_delta);
+        } else if modifiers.shift {
+            self.pan(response.drag_motion());
+        }
+        // rotate
+        else {
+            let drag_delta = response.drag_motion() * 0.005;
+            rotation = egui::vec2(-drag_delta.x, drag_delta.y);
+            self.yaw += rotation.x;
+            self.pitch += rotation.y;
+            self.wrap();
+        }
+
+        self.zoom += self.zoom * -smooth_scroll.y * 0.003;
+        // pinch on a touchpad or touch screen
+        if response.contains_pointer() {
+            self.zoom /= zoom_delta;
+        }
+        self.clamp();
+        rotation
+    }
+    /// Moves the target with the screen space `delta` in points.
+    fn pan(&mut self, delta: egui::Vec2) {
+        let cam = self.look_at().try_inverse().unwrap();
+        let right = cam.transform_vector(&glm::Vec3::x());
+        let up = cam.transform_vector(&glm::Vec3::y());
+        let delta = delta * 0.002 * self.zoom;
+        self.target -= right * delta.x;
+        self.target -= up * delta.y;
+    }
 }
 impl Default for OrbitCamera {
     fn default() -> Self {
@@ -61,6 +392,157 @@ impl Default for OrbitCamera {
         }
     }
 }
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FlyCamera {
+    pub position: glm::Vec3,
+
+    pub pitch: f32,
+    pub yaw: f32,
+
+    /// units per second
+    pub speed: f32,
+
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+}
+impl FlyCamera {
+    const MAX_PITCH: f32 = FRAC_PI_2 - 0.001;
+
+    pub fn forward(&self) -> glm::Vec3 {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        -glm::vec3(sin_yaw * cos_pitch, sin_pitch, cos_yaw * cos_pitch)
+    }
+    pub fn right(&self) -> glm::Vec3 {
+        self.forward().cross(&glm::Vec3::y()).normalize()
+    }
+
+    pub fn look_at(&self) -> glm::Mat4 {
+        glm::look_at_lh(
+            &self.position,
+            &(self.position + self.forward()),
+            &-glm::Vec3::y(),
+        )
+    }
+    pub fn perspective(&self, aspect: f32) -> glm::Mat4 {
+        glm::perspective_lh_zo(aspect, self.fov, self.near, self.far)
+    }
+
+    pub fn clamp(&mut self) {
+        if self.pitch > PI {
+            self.pitch -= TAU;
+        }
+        self.pitch = self.pitch.clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        self.yaw = self.yaw.rem_euclid(TAU);
+    }
+
+    /// Two fingers move sideways and pinching moves forward, for touch screens without keys.
+    pub fn input(&mut self, response: &egui::Response, touch: Option<egui::MultiTouchInfo>) {
+        if let Some(touch) = touch {
+            let cam = self.look_at().try_inverse().unwrap();
+            let right = cam.transform_vector(&glm::Vec3::x());
+            let up = cam.transform_vector(&glm::Vec3::y());
+            let delta = touch.translation_delta * 0.002 * self.speed;
+            self.position -= right * delta.x;
+            self.position -= up * delta.y;
+            self.position += self.forward() * touch.zoom_delta.ln() * self.speed;
+        } else {
+            let drag_delta = response.drag_motion() * 0.005;
+            self.yaw -= drag_delta.x;
+            self.pitch += drag_delta.y;
+            self.clamp();
+        }
+
+        let (dt, scroll, modifiers) = response
+            .ctx
+            .input(|i| (i.stable_dt, i.smooth_scroll_delta, i.modifiers));
+        self.speed = (self.speed + self.speed * scroll.y * 0.003).max(0.01);
+
+        if response.ctx.wants_keyboard_input() {
+            return;
+        }
+        let key = |key| response.ctx.input(|i| i.key_down(key));
+        let mut direction = glm::Vec3::zeros();
+        if key(egui::Key::W) {
+            direction += self.forward();
+        }
+        if key(egui::Key::S) {
+            direction -= self.forward();
+        }
+        if key(egui::Key::D) {
+            direction += self.right();
+        }
+        if key(egui::Key::A) {
+            direction -= self.right();
+        }
+        if key(egui::Key::E) {
+            direction += glm::Vec3::y();
+        }
+        if key(egui::Key::Q) {
+            direction -= glm::Vec3::y();
+        }
+        if direction != glm::Vec3::zeros() {
+            let sprint = if modifiers.shift { 4.0 } else { 1.0 };
+            self.position += direction.normalize() * self.speed * sprint * dt;
+        }
+    }
+}
+impl Default for FlyCamera {
+    fn default() -> Self {
+        Self {
+            position: glm::vec3(0.0, 0.0, 3.0),
+            pitch: 0.0,
+            yaw: 0.0,
+            speed: 2.0,
+            fov: FRAC_PI_3,
+            near: 0.01,
+            far: 100.0,
+        }
+    }
+}
+impl FlyCamera {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("WASD to move, Q/E for down/up, Shift to sprint");
+
+        ui.label("Position");
+        ui.add(
+            egui::DragValue::new(&mut self.position.x)
+                .prefix("x: ")
+                .speed(0.1),
+        );
+        ui.add(
+            egui::DragValue::new(&mut self.position.y)
+                .prefix("y: ")
+                .speed(0.1),
+        );
+        ui.add(
+            egui::DragValue::new(&mut self.position.z)
+                .prefix("z: ")
+                .speed(0.1),
+        );
+
+        ui.separator();
+
+        ui.label("Speed");
+        ui.add(
+            egui::DragValue::new(&mut self.speed)
+                .range(0.01..=f32::MAX)
+                .speed(0.1),
+        );
+        ui.label("FOV");
+        ui.drag_angle(&mut self.fov);
+
+        ui.separator();
+
+        ui.label("Pitch");
+        ui.drag_angle(&mut self.pitch);
+        ui.label("Yaw");
+        ui.drag_angle(&mut self.yaw);
+        self.clamp();
+    }
+}
+
 impl OrbitCamera {
     pub fn ui(&mut self, ui: &mut egui::Ui) {
         ui.label("Target");
@@ -100,22 +582,456 @@ impl OrbitCamera {
         ui.drag_angle(&mut self.pitch);
         ui.label("Yaw");
         ui.drag_angle(&mut self.yaw);
+    }
+}
 
-        ui.separator();
+/// A named view, stored as the equivalent orbit camera so any two can be blended.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    pub mode: CameraMode,
+    pub orbit: OrbitCamera,
+}
+impl Bookmark {
+    pub fn new(name: String, camera: &Camera) -> Self {
+        let mut orbit = *camera;
+        orbit.set_mode(CameraMode::Orbit);
+        Self {
+            name,
+            mode: camera.mode,
+            orbit: orbit.orbit,
+        }
+    }
+    /// Moves `camera` to the bookmark, keeping the speed of the fly camera.
+    fn apply(&self, camera: &mut Camera, orbit: OrbitCamera) {
+        camera.mode = CameraMode::Orbit;
+        camera.orbit = orbit;
+        camera.set_mode(self.mode);
+        camera.spin = egui::Vec2::ZERO;
+    }
+}
 
-        ui.label("Near");
-        let diff = 0.01;
-        let old_near = self.near;
-        ui.add(
-            egui::DragValue::new(&mut self.near)
-                .range(diff..=self.far - diff)
-                .speed(0.1),
-        );
-        ui.label("Far");
-        ui.add(
-            egui::DragValue::new(&mut self.far)
-                .range(old_near + diff..=f32::MAX)
-                .speed(0.1),
-        );
+/// Blends the view parameters, angles take the short way around and zoom is interpolated
+/// logarithmically so the speed feels constant.
+fn blend(a: &OrbitCamera, b: &OrbitCamera, t: f32) -> OrbitCamera {
+    let angle = |a: f32, b: f32| {
+        let delta = (b - a + PI).rem_euclid(TAU) - PI;
+        a + delta * t
+    };
+    let mut orbit = OrbitCamera {
+        target: glm::lerp(&a.target, &b.target, t),
+        zoom: (a.zoom.ln() + (b.zoom.ln() - a.zoom.ln()) * t).exp(),
+        pitch: angle(a.pitch, b.pitch),
+        yaw: angle(a.yaw, b.yaw),
+        fov: a.fov + (b.fov - a.fov) * t,
+        near: a.near + (b.near - a.near) * t,
+        far: a.far + (b.far - a.far) * t,
+    };
+    orbit.wrap();
+    orbit
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Transition {
+    from: OrbitCamera,
+    to: usize,
+    elapsed: f32,
+    /// Continue with the following bookmarks.
+    play: bool,
+}
+
+/// Saved views of the Camera panel, restored instantly or with a smooth transition.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bookmarks {
+    pub list: Vec<Bookmark>,
+    /// Length of a transition in seconds, 0 jumps.
+    pub duration: f32,
+    #[serde(skip)]
+    name: String,
+    #[serde(skip)]
+    transition: Option<Transition>,
+}
+impl Default for Bookmarks {
+    fn default() -> Self {
+        Self {
+            list: vec![],
+            duration: 2.0,
+            name: String::new(),
+            transition: None,
+        }
+    }
+}
+impl Bookmarks {
+    /// Moves towards bookmark `index`.
+    pub fn go_to(&mut self, index: usize, camera: &mut Camera, play: bool) {
+        let mut from = *camera;
+        from.set_mode(CameraMode::Orbit);
+        self.transition = Some(Transition {
+            from: from.orbit,
+            to: index,
+            elapsed: 0.0,
+            play,
+        });
+        self.update(camera, 0.0);
+    }
+    /// Stops the transition, e.g. when the user moves the camera.
+    pub fn stop(&mut self) {
+        self.transition = None;
+    }
+    pub fn playing(&self) -> bool {
+        self.transition.is_some()
+    }
+    /// Advances the transition by `dt` seconds.
+    pub fn update(&mut self, camera: &mut Camera, dt: f32) {
+        let Some(transition) = &mut self.transition else {
+            return;
+        };
+        let Some(bookmark) = self.list.get(transition.to) else {
+            self.transition = None;
+            return;
+        };
+        transition.elapsed += dt;
+        let t = if self.duration > 0.0 {
+            (transition.elapsed / self.duration).min(1.0)
+        } else {
+            1.0
+        };
+        // ease in and out
+        let eased = t * t * (3.0 - 2.0 * t);
+        bookmark.apply(camera, blend(&transition.from, &bookmark.orbit, eased));
+        if t < 1.0 {
+            return;
+        }
+        let next = transition.to + 1;
+        if transition.play && next < self.list.len() {
+            *transition = Transition {
+                from: bookmark.orbit,
+                to: next,
+                elapsed: 0.0,
+                play: true,
+            };
+        } else {
+            self.transition = None;
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, camera: &mut Camera) {
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.name).desired_width(120.0));
+            if ui.button("Add bookmark").clicked() {
+                let name = match self.name.trim() {
+                    "" => format!("View {}", self.list.len() + 1),
+                    name => name.to_owned(),
+                };
+                self.list.push(Bookmark::new(name, camera));
+                self.name.clear();
+            }
+        });
+
+        let mut go_to = None;
+        let mut remove = None;
+        for (i, bookmark) in self.list.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let current = self.transition.is_some_and(|t| t.to == i);
+                if ui.selectable_label(current, &bookmark.name).clicked() {
+                    go_to = Some(i);
+                }
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = go_to {
+            self.go_to(i, camera, false);
+        }
+        if let Some(i) = remove {
+            self.list.remove(i);
+            self.stop();
+        }
+
+        ui.add(egui::Slider::new(&mut self.duration, 0.0..=10.0).text("Transition (s)"));
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!self.list.is_empty(), egui::Button::new("Play all"))
+                .on_hover_text("Fly through every bookmark in order")
+                .clicked()
+            {
+                self.go_to(0, camera, true);
+            }
+            if ui
+                .add_enabled(self.playing(), egui::Button::new("Stop"))
+                .clicked()
+            {
+                self.stop();
+            }
+        });
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CameraPathError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CameraKey {
+    /// Seconds from the start of the path.
+    pub time: f32,
+    pub orbit: OrbitCamera,
+}
+
+/// Moves `camera` to a view of the path.
+fn show(camera: &mut Camera, orbit: OrbitCamera) {
+    camera.mode = CameraMode::Orbit;
+    camera.orbit = orbit;
+    camera.spin = egui::Vec2::ZERO;
+}
+
+/// Cubic Hermite spline through `p[1]` and `p[2]` with Catmull-Rom tangents scaled by the key
+/// times, so uneven spacing doesn't change speed abruptly.
+fn catmull_rom<T>(p: [T; 4], t: [f32; 4], s: f32) -> T
+where
+    T: Copy
+        + std::ops::Add<Output = T>
+        + std::ops::Sub<Output = T>
+        + std::ops::Mul<f32, Output = T>,
+{
+    let span = t[2] - t[1];
+    let m1 = (p[2] - p[0]) * (span / (t[2] - t[0]).max(f32::EPSILON));
+    let m2 = (p[3] - p[1]) * (span / (t[3] - t[1]).max(f32::EPSILON));
+    let s2 = s * s;
+    let s3 = s2 * s;
+    p[1] * (2.0 * s3 - 3.0 * s2 + 1.0)
+        + m1 * (s3 - 2.0 * s2 + s)
+        + p[2] * (-2.0 * s3 + 3.0 * s2)
+        + m2 * (s3 - s2)
+}
+
+/// Keyframed camera poses played back along a smooth curve for repeatable shots.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraPath {
+    /// Sorted by time.
+    pub keys: Vec<CameraKey>,
+    /// Seconds between a new key and the last one.
+    #[serde(skip)]
+    pub spacing: f32,
+    #[serde(skip)]
+    pub looping: bool,
+    /// Playback position in seconds.
+    #[serde(skip)]
+    time: Option<f32>,
+}
+impl Default for CameraPath {
+    fn default() -> Self {
+        Self {
+            keys: vec![],
+            spacing: 2.0,
+            looping: false,
+            time: None,
+        }
+    }
+}
+impl CameraPath {
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map_or(0.0, |key| key.time)
+    }
+    pub fn playing(&self) -> bool {
+        self.time.is_some()
+    }
+    pub fn play(&mut self, camera: &mut Camera) {
+        self.time = Some(0.0);
+        self.update(camera, 0.0);
+    }
+    pub fn stop(&mut self) {
+        self.time = None;
+    }
+    /// Appends the current view of `camera` after the last key.
+    pub fn record(&mut self, camera: &Camera) {
+        let mut orbit = *camera;
+        orbit.set_mode(CameraMode::Orbit);
+        let time = match self.keys.last() {
+            Some(key) => key.time + self.spacing,
+            None => 0.0,
+        };
+        self.keys.push(CameraKey {
+            time,
+            orbit: orbit.orbit,
+        });
+    }
+    fn sort(&mut self) {
+        self.keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+
+    /// The view at `time` seconds, held at the first and last key outside of the path.
+    pub fn sample(&self, time: f32) -> Option<OrbitCamera> {
+        let last = self.keys.len().checked_sub(1)?;
+        let i = self.keys.partition_point(|key| key.time <= time);
+        if i == 0 || i > last {
+            return Some(self.keys[i.min(last)].orbit);
+        }
+        let keys = [
+            self.keys[(i - 1).saturating_sub(1)],
+            self.keys[i - 1],
+            self.keys[i],
+            self.keys[(i + 1).min(last)],
+        ];
+        let t = keys.map(|key| key.time);
+        let span = t[2] - t[1];
+        let s = if span > 0.0 {
+            (time - t[1]) / span
+        } else {
+            1.0
+        };
+
+        // unwrap the angles around the start of the segment so each step takes the short way
+        let unwrap = |angle: fn(&OrbitCamera) -> f32| {
+            let short = |a: f32, b: f32| (b - a + PI).rem_euclid(TAU) - PI;
+            let a1 = angle(&keys[1].orbit);
+            let a0 = a1 + short(a1, angle(&keys[0].orbit));
+            let a2 = a1 + short(a1, angle(&keys[2].orbit));
+            let a3 = a2 + short(a2, angle(&keys[3].orbit));
+            [a0, a1, a2, a3]
+        };
+        let (a, b) = (&keys[1].orbit, &keys[2].orbit);
+        let mut orbit = OrbitCamera {
+            target: catmull_rom(keys.map(|key| key.orbit.target), t, s),
+            zoom: catmull_rom(keys.map(|key| key.orbit.zoom.ln()), t, s).exp(),
+            pitch: catmull_rom(unwrap(|orbit| orbit.pitch), t, s),
+            yaw: catmull_rom(unwrap(|orbit| orbit.yaw), t, s),
+            fov: catmull_rom(keys.map(|key| key.orbit.fov), t, s).clamp(0.01, PI - 0.01),
+            // the spline can overshoot, keep the clip planes between the keys
+            near: a.near + (b.near - a.near) * s,
+            far: a.far + (b.far - a.far) * s,
+        };
+        orbit.wrap();
+        Some(orbit)
+    }
+    /// Advances the playback by `dt` seconds.
+    pub fn update(&mut self, camera: &mut Camera, dt: f32) {
+        let Some(mut time) = self.time else {
+            return;
+        };
+        time += dt;
+        self.time = Some(time);
+        let duration = self.duration();
+        if time > duration {
+            if self.looping && duration > 0.0 {
+                time %= duration;
+                self.time = Some(time);
+            } else {
+                self.time = None;
+            }
+        }
+        match self.sample(time) {
+            Some(orbit) => show(camera, orbit),
+            None => self.time = None,
+        }
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> Result<(), CameraPathError> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+    /// Replaces the keys with the ones in the file.
+    pub fn load(&mut self, path: &std::path::Path) -> Result<(), CameraPathError> {
+        let json = std::fs::read_to_string(path)?;
+        let loaded: CameraPath = serde_json::from_str(&json)?;
+        self.keys = loaded.keys;
+        self.sort();
+        self.stop();
+        Ok(())
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, camera: &mut Camera) {
+        ui.horizontal(|ui| {
+            if ui
+                .button("Add key")
+                .on_hover_text("Record the current view at the end of the path")
+                .clicked()
+            {
+                self.record(camera);
+            }
+            ui.add(
+                egui::DragValue::new(&mut self.spacing)
+                    .range(0.0..=60.0)
+                    .speed(0.05)
+                    .suffix(" s"),
+            )
+            .on_hover_text("Time after the last key");
+        });
+
+        let mut go_to = None;
+        let mut set = None;
+        let mut remove = None;
+        let mut retimed = false;
+        for (i, key) in self.keys.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{}", i + 1));
+                retimed |= ui
+                    .add(
+                        egui::DragValue::new(&mut key.time)
+                            .range(0.0..=f32::MAX)
+                            .speed(0.05)
+                            .suffix(" s"),
+                    )
+                    .changed();
+                if ui.small_button("View").clicked() {
+                    go_to = Some(key.time);
+                }
+                if ui
+                    .small_button("Set")
+                    .on_hover_text("Replace the key with the current view")
+                    .clicked()
+                {
+                    set = Some(i);
+                }
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = set {
+            let time = self.keys[i].time;
+            self.keys.remove(i);
+            self.record(camera);
+            self.keys.last_mut().unwrap().time = time;
+            retimed = true;
+        }
+        if let Some(i) = remove {
+            self.keys.remove(i);
+            self.stop();
+        }
+        if retimed {
+            self.sort();
+        }
+        if let Some(orbit) = go_to.and_then(|time| self.sample(time)) {
+            self.stop();
+            show(camera, orbit);
+        }
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.keys.len() > 1, egui::Button::new("Play"))
+                .clicked()
+            {
+                self.play(camera);
+            }
+            if ui
+                .add_enabled(self.playing(), egui::Button::new("Stop"))
+                .clicked()
+            {
+                self.stop();
+            }
+            ui.checkbox(&mut self.looping, "Loop");
+            if let Some(time) = self.time {
+                ui.label(format!("{time:.1} / {:.1} s", self.duration()));
+            }
+        });
     }
 }
@@ -0,0 +1,139 @@
+use crate::{
+    progress::panic_message,
+    vktf::{
+        GltfRenderInfo, ModelTransform,
+        deviation::{PlacedModel, TriangleGrid},
+        loader::{LoadGltfError, VktfDocument},
+    },
+};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, thread::JoinHandle};
+
+/// Most steps a single move is split into.
+const MAX_STEPS: f32 = 64.0;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollisionSettings {
+    pub enabled: bool,
+    /// The camera is a sphere of this radius in world units.
+    pub radius: f32,
+}
+impl Default for CollisionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            radius: 0.2,
+        }
+    }
+}
+
+/// Keeps the fly camera from moving through the models, for walking through buildings.
+/// The triangles are read again on another thread and sorted into a grid whenever the
+/// models or their placement change, animation isn't followed.
+#[derive(Default)]
+pub struct Collision {
+    pub settings: CollisionSettings,
+    /// The models and placements the collider is built for.
+    built: Vec<(Arc<VktfDocument>, ModelTransform)>,
+    job: Option<JoinHandle<Result<TriangleGrid, LoadGltfError>>>,
+    collider: Option<TriangleGrid>,
+}
+impl Collision {
+    pub fn building(&self) -> bool {
+        self.job.is_some()
+    }
+
+    /// Builds the collider again if the models changed since, call once per frame.
+    pub fn update(&mut self, models: &[GltfRenderInfo], errors: &mut Vec<String>) {
+        if let Some(result) = self
+            .job
+            .take_if(|job| job.is_finished())
+            .map(|job| job.join())
+        {
+            match result {
+                Ok(Ok(collider)) => self.collider = Some(collider),
+                Ok(Err(err)) => errors.push(format!("Failed to build the collision: {err}")),
+                Err(panic) => errors.push(format!(
+                    "Failed to build the collision: {}",
+                    panic_message(panic)
+                )),
+            }
+        }
+        if !self.settings.enabled || self.job.is_some() {
+            return;
+        }
+        let unchanged = self.built.len() == models.len()
+            && self
+                .built
+                .iter()
+                .zip(models)
+                .all(|((vktf, transform), info)| {
+                    Arc::ptr_eq(vktf, &info.vktf) && *transform == info.transform()
+                });
+        if !unchanged {
+            self.rebuild(models);
+        }
+    }
+    /// Reads the triangles of `models` in their current pose.
+    pub fn rebuild(&mut self, models: &[GltfRenderInfo]) {
+        self.built = models
+            .iter()
+            .map(|info| (info.vktf.clone(), info.transform()))
+            .collect();
+        if models.is_empty() {
+            self.collider = None;
+            return;
+        }
+        let placed: Vec<_> = models.iter().map(PlacedModel::new).collect();
+        self.job = Some(std::thread::spawn(move || {
+            let mut triangles = vec![];
+            for model in &placed {
+                triangles.extend(model.read_triangles()?);
+            }
+            Ok(TriangleGrid::new(triangles))
+        }));
+    }
+
+    /// Where a camera moving from `from` to `to` stops, in steps shorter than its radius
+    /// so fast moves don't pass through thin walls.
+    pub fn slide(&self, from: glm::Vec3, to: glm::Vec3) -> glm::Vec3 {
+        let Some(collider) = self.collider.as_ref().filter(|_| self.settings.enabled) else {
+            return to;
+        };
+        let radius = self.settings.radius;
+        let delta = to - from;
+        let steps = (delta.norm() / (radius * 0.5)).ceil().clamp(1.0, MAX_STEPS);
+        let mut position = from;
+        for _ in 0..steps as usize {
+            position = collider.push_out(&(position + delta / steps), radius);
+        }
+        position
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, models: &[GltfRenderInfo]) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.settings.enabled, "Collide with the models")
+                .on_hover_text("Stop the fly camera at floors and walls");
+            if self.building() {
+                ui.spinner();
+            }
+        });
+        ui.add_enabled_ui(self.settings.enabled, |ui| {
+            ui.add(
+                egui::DragValue::new(&mut self.settings.radius)
+                    .prefix("Radius: ")
+                    .range(0.001..=f32::MAX)
+                    .speed(0.01),
+            );
+            if ui
+                .add_enabled(!self.building(), egui::Button::new("Rebuild"))
+                .on_hover_text("Read the triangles again after moving nodes or animating")
+                .clicked()
+            {
+                self.rebuild(models);
+            }
+        });
+    }
+}
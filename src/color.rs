@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+
+/// How the tone mapped scene is encoded for the display, applied by the last pass of the
+/// [`PostChain`](crate::post::PostChain). The scene is rendered with sRGB primaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OutputTransform {
+    #[default]
+    Srgb,
+    /// The wider gamut of most recent laptops and phones, with the sRGB transfer function.
+    DisplayP3,
+    /// sRGB primaries for a BT.1886 video display, gamma 2.4.
+    Rec709,
+}
+impl OutputTransform {
+    pub const ALL: [OutputTransform; 3] = [
+        OutputTransform::Srgb,
+        OutputTransform::DisplayP3,
+        OutputTransform::Rec709,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputTransform::Srgb => "sRGB",
+            OutputTransform::DisplayP3 => "Display P3",
+            OutputTransform::Rec709 => "Rec.709",
+        }
+    }
+    /// The same as the `OUTPUT_*` defines of the output pass.
+    pub fn shader_value(&self) -> i32 {
+        match self {
+            OutputTransform::Srgb => 0,
+            OutputTransform::DisplayP3 => 1,
+            OutputTransform::Rec709 => 2,
+        }
+    }
+    /// Colour primaries, transfer characteristics, matrix coefficients and full range flag
+    /// of ITU-T H.273, as stored in the `cICP` chunk of a PNG.
+    fn code_points(&self) -> [u8; 4] {
+        match self {
+            OutputTransform::Srgb => [1, 13, 0, 1],
+            OutputTransform::DisplayP3 => [12, 13, 0, 1],
+            OutputTransform::Rec709 => [1, 1, 0, 1],
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Output transform")
+            .selected_text(self.name())
+            .show_ui(ui, |ui| {
+                for transform in Self::ALL {
+                    ui.selectable_value(self, transform, transform.name());
+                }
+            })
+            .response
+            .on_hover_text("Pick the colour space of the display, screenshots are tagged with it");
+    }
+
+    /// Adds a `cICP` chunk after the header of `png`, so viewers know how the pixels
+    /// are encoded.
+    pub fn tag_png(&self, png: &mut Vec<u8>) {
+        // the 8 byte signature and the 25 bytes of the IHDR chunk
+        const HEADER_END: usize = 8 + 25;
+        if png.len() < HEADER_END || &png[12..16] != b"IHDR" {
+            log::warn!("not tagging a PNG without a header");
+            return;
+        }
+        let mut chunk = Vec::with_capacity(16);
+        chunk.extend_from_slice(&4u32.to_be_bytes());
+        chunk.extend_from_slice(b"cICP");
+        chunk.extend_from_slice(&self.code_points());
+        let crc = crc32(&chunk[4..]);
+        chunk.extend_from_slice(&crc.to_be_bytes());
+        png.splice(HEADER_END..HEADER_END, chunk);
+    }
+}
+
+/// The CRC of PNG chunks.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
@@ -0,0 +1,174 @@
+use crate::{
+    skybox::renderer::SkyboxRenderer,
+    viewer::renderer::ViewerRenderer,
+    vktf::{GltfRenderInfo, material::MaterialPush},
+};
+use std::{path::PathBuf, sync::Arc};
+use vulkano::{descriptor_set::DescriptorSet, pipeline::graphics::viewport::Scissor};
+
+/// Width of the grabbable area around the split line in points.
+const HANDLE_WIDTH: f32 = 12.0;
+
+/// The environment and material factors the live scene is compared to.
+#[derive(Clone)]
+struct Reference {
+    env_set: Arc<DescriptorSet>,
+    skybox: Option<Arc<DescriptorSet>>,
+    /// The factors of every material by model file.
+    materials: Vec<(PathBuf, Vec<MaterialPush>)>,
+}
+
+/// Split view that shows a captured reference (A) left of the split and the live scene (B)
+/// right of it, to compare environments or material edits.
+pub struct Compare {
+    pub enabled: bool,
+    /// Position of the split as a fraction of the viewport width.
+    pub split: f32,
+    reference: Option<Reference>,
+}
+impl Default for Compare {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            split: 0.5,
+            reference: None,
+        }
+    }
+}
+impl Compare {
+    /// Remembers the current environment and materials as A.
+    pub fn capture(&mut self, viewer: &ViewerRenderer, skybox: &SkyboxRenderer) {
+        self.reference = Some(Reference {
+            env_set: viewer.env_set.clone(),
+            skybox: skybox.skybox.clone(),
+            materials: viewer
+                .models
+                .iter()
+                .map(|info| {
+                    let pushes = info.materials.index.iter().map(|m| m.push).collect();
+                    (info.vktf.path.clone(), pushes)
+                })
+                .collect(),
+        });
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, viewer: &ViewerRenderer, skybox: &SkyboxRenderer) {
+        ui.horizontal(|ui| {
+            if ui.checkbox(&mut self.enabled, "Split view").changed()
+                && self.enabled
+                && self.reference.is_none()
+            {
+                self.capture(viewer, skybox);
+            }
+            if ui
+                .button("Capture A")
+                .on_hover_text("Show the current environment and materials left of the split")
+                .clicked()
+            {
+                self.capture(viewer, skybox);
+                self.enabled = true;
+            }
+        });
+        ui.add_enabled(
+            self.enabled,
+            egui::Slider::new(&mut self.split, 0.0..=1.0).text("Split"),
+        );
+    }
+
+    /// The renderers of the left side, `None` if the split view is off.
+    pub fn reference(
+        &self,
+        viewer: &ViewerRenderer,
+        skybox: &SkyboxRenderer,
+    ) -> Option<(ViewerRenderer, SkyboxRenderer)> {
+        let reference = self.reference.as_ref().filter(|_| self.enabled)?;
+        let mut viewer = viewer.clone();
+        viewer.env_set = reference.env_set.clone();
+        for info in &mut viewer.models {
+            apply_materials(info, &reference.materials);
+        }
+        let skybox = SkyboxRenderer {
+            skybox: reference.skybox.clone().or_else(|| skybox.skybox.clone()),
+            ..skybox.clone()
+        };
+        Some((viewer, skybox))
+    }
+
+    /// Draws the split line over the viewport and lets it be dragged.
+    pub fn handle(&mut self, ui: &mut egui::Ui, rect: egui::Rect) {
+        if !self.enabled || self.reference.is_none() {
+            return;
+        }
+        let x = rect.left() + rect.width() * self.split;
+        let handle = egui::Rect::from_x_y_ranges(
+            x - HANDLE_WIDTH / 2.0..=x + HANDLE_WIDTH / 2.0,
+            rect.y_range(),
+        );
+        let response = ui
+            .interact(handle, ui.id().with("compare_split"), egui::Sense::drag())
+            .on_hover_cursor(egui::CursorIcon::ResizeHorizontal);
+        if let Some(pointer) = response.interact_pointer_pos() {
+            self.split = ((pointer.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+        }
+        let painter = ui.painter_at(rect);
+        painter.vline(x, rect.y_range(), ui.visuals().widgets.active.fg_stroke);
+        let text = egui::TextStyle::Button.resolve(ui.style());
+        let color = ui.visuals().strong_text_color();
+        let top = rect.top() + 8.0;
+        painter.text(
+            egui::pos2(x - 8.0, top),
+            egui::Align2::RIGHT_TOP,
+            "A",
+            text.clone(),
+            color,
+        );
+        painter.text(
+            egui::pos2(x + 8.0, top),
+            egui::Align2::LEFT_TOP,
+            "B",
+            text,
+            color,
+        );
+    }
+}
+
+/// Replaces the factors of models that still have the materials they had when captured.
+fn apply_materials(info: &mut GltfRenderInfo, materials: &[(PathBuf, Vec<MaterialPush>)]) {
+    let captured = materials.iter().find(|(path, pushes)| {
+        *path == info.vktf.path && pushes.len() == info.materials.index.len()
+    });
+    if let Some((_, pushes)) = captured {
+        for (material, push) in info.materials.index.iter_mut().zip(pushes) {
+            material.push = *push;
+        }
+    }
+}
+
+/// The scissors of the left and right side of the split, `None` for a side with nothing to draw.
+pub fn split_scissors(info: &egui::PaintCallbackInfo, split: f32) -> [Option<Scissor>; 2] {
+    let viewport = info.viewport_in_pixels();
+    let clip = info.clip_rect_in_pixels();
+    let split = viewport.left_px + (viewport.width_px as f32 * split).round() as i32;
+    let [left, right] = [clip.left_px, clip.left_px + clip.width_px].map(|x| x.max(0));
+    let top = clip.top_px.max(0) as u32;
+    let height = clip.height_px.max(0) as u32;
+    let split = split.clamp(left, right.max(left));
+    [(left, split), (split, right)].map(|(start, end)| {
+        (end > start && height > 0).then(|| Scissor {
+            offset: [start as u32, top],
+            extent: [(end - start) as u32, height],
+        })
+    })
+}
+
+/// [`split_scissors`] of an offscreen image that only holds the viewport.
+pub fn split_image_scissors(extent: [u32; 2], split: f32) -> [Option<Scissor>; 2] {
+    let [width, height] = extent;
+    let split = ((width as f32 * split).round() as u32).min(width);
+    [(0, split), (split, width)].map(|(start, end)| {
+        (end > start && height > 0).then(|| Scissor {
+            offset: [start, 0],
+            extent: [end - start, height],
+        })
+    })
+}
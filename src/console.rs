@@ -0,0 +1,438 @@
+use crate::{
+    camera::Camera,
+    vktf::{GltfRenderInfo, material::MaterialPush},
+};
+use nalgebra_glm as glm;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope};
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+const MAX_HISTORY: usize = 100;
+const MAX_LOG: usize = 500;
+/// Scripts run on the UI thread, longer ones are stopped so a `loop {}` can't freeze the viewer.
+const MAX_OPERATIONS: u64 = 10_000_000;
+const MAX_DURATION: Duration = Duration::from_secs(1);
+
+const HELP: &str = "\
+set_camera(eye_x, eye_y, eye_z, target_x, target_y, target_z), frame_scene(), set_fov(degrees)
+set_base_color(model, material, r, g, b, a), set_emissive(model, material, r, g, b)
+set_metallic(model, material, value), set_roughness(model, material, value)
+play(model), play(model, animation), pause(model), seek(model, seconds)
+set_node_visible(model, node, visible), screenshot()
+camera_eye, camera_target, models[i].name/materials/animations/nodes";
+
+/// What a script asked the viewer to do. Scripts only queue commands, the viewer applies them
+/// between frames, see [`Console::next_command`].
+#[derive(Debug, Clone, Copy)]
+pub enum ScriptCommand {
+    CameraPose {
+        eye: glm::Vec3,
+        target: glm::Vec3,
+    },
+    FrameScene,
+    /// Vertical field of view in degrees.
+    Fov(f32),
+    Material {
+        model: usize,
+        material: usize,
+        edit: MaterialEdit,
+    },
+    /// Plays the animation or the selected one if `None`.
+    Play {
+        model: usize,
+        animation: Option<usize>,
+    },
+    Pause {
+        model: usize,
+    },
+    Seek {
+        model: usize,
+        time: f32,
+    },
+    NodeVisible {
+        model: usize,
+        node: usize,
+        visible: bool,
+    },
+    /// The commands after it wait for the next frame, each screenshot shows the edits before it.
+    Screenshot,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MaterialEdit {
+    BaseColor(glm::Vec4),
+    Emissive(glm::Vec3),
+    Metallic(f32),
+    Roughness(f32),
+}
+impl MaterialEdit {
+    pub fn apply(&self, push: &mut MaterialPush) {
+        match *self {
+            MaterialEdit::BaseColor(color) => push.bc = color,
+            MaterialEdit::Emissive(color) => push.em = color,
+            MaterialEdit::Metallic(metallic) => push.rm.y = metallic,
+            MaterialEdit::Roughness(roughness) => push.rm.x = roughness,
+        }
+    }
+}
+
+enum LogLine {
+    Input(String),
+    Output(String),
+    Error(String),
+}
+
+type Commands = Rc<RefCell<VecDeque<ScriptCommand>>>;
+
+/// A [Rhai](https://rhai.rs) console to tweak the scene and automate screenshots.
+pub struct Console {
+    pub open: bool,
+    /// Scripts that were run, the newest last.
+    pub history: Vec<String>,
+    /// The entry of the history shown in the input while browsing it.
+    browsing: Option<usize>,
+    input: String,
+    log: Rc<RefCell<Vec<LogLine>>>,
+    commands: Commands,
+    engine: Engine,
+    /// When the running script started, for the time budget.
+    started: Rc<Cell<Instant>>,
+}
+impl Default for Console {
+    fn default() -> Self {
+        let log = Rc::new(RefCell::new(vec![]));
+        let commands = Commands::default();
+        let mut engine = Engine::new();
+        {
+            let log = log.clone();
+            engine.on_print(move |text| log.borrow_mut().push(LogLine::Output(text.to_owned())));
+        }
+        let started = Rc::new(Cell::new(Instant::now()));
+        {
+            let started = started.clone();
+            engine.on_progress(move |_| {
+                (started.get().elapsed() > MAX_DURATION).then_some(Dynamic::UNIT)
+            });
+        }
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(64)
+            .set_max_expr_depths(64, 64);
+        register(&mut engine, &commands);
+
+        Self {
+            open: false,
+            history: vec![],
+            browsing: None,
+            input: String::new(),
+            log,
+            commands,
+            engine,
+            started,
+        }
+    }
+}
+impl Console {
+    /// The next queued command of the scripts that ran.
+    pub fn next_command(&mut self) -> Option<ScriptCommand> {
+        self.commands.borrow_mut().pop_front()
+    }
+    /// Shows why a command of a script couldn't be applied.
+    pub fn error(&mut self, error: String) {
+        self.log.borrow_mut().push(LogLine::Error(error));
+    }
+    pub fn run(&mut self, script: String, mut scope: Scope) {
+        if self.history.last() != Some(&script) {
+            self.history.push(script.clone());
+            let excess = self.history.len().saturating_sub(MAX_HISTORY);
+            self.history.drain(..excess);
+        }
+        self.log.borrow_mut().push(LogLine::Input(script.clone()));
+        self.started.set(Instant::now());
+        match self.engine.eval_with_scope::<Dynamic>(&mut scope, &script) {
+            Ok(value) if !value.is_unit() => {
+                self.log
+                    .borrow_mut()
+                    .push(LogLine::Output(value.to_string()));
+            }
+            Ok(_) => {}
+            Err(err) => {
+                // what ran before the error is still applied
+                let error = match *err {
+                    EvalAltResult::ErrorTooManyOperations(_) => {
+                        format!("stopped after {MAX_OPERATIONS} operations")
+                    }
+                    EvalAltResult::ErrorTerminated(..) => {
+                        format!("stopped after {} s", MAX_DURATION.as_secs())
+                    }
+                    err => err.to_string(),
+                };
+                self.log.borrow_mut().push(LogLine::Error(error));
+            }
+        }
+        let mut log = self.log.borrow_mut();
+        let excess = log.len().saturating_sub(MAX_LOG);
+        log.drain(..excess);
+    }
+
+    /// `scope` makes the variables scripts can read about the scene, only when one is run.
+    pub fn window(&mut self, ctx: &egui::Context, scope: impl FnOnce() -> Scope<'static>) {
+        let mut open = self.open;
+        egui::Window::new("Console")
+            .open(&mut open)
+            .default_size([480.0, 320.0])
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(240.0)
+                    .auto_shrink([false, true])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for line in self.log.borrow().iter() {
+                            match line {
+                                LogLine::Input(script) => {
+                                    ui.monospace(format!("> {script}"));
+                                }
+                                LogLine::Output(text) => {
+                                    ui.label(egui::RichText::new(text).monospace().weak());
+                                }
+                                LogLine::Error(error) => {
+                                    ui.colored_label(ui.visuals().error_fg_color, error);
+                                }
+                            }
+                        }
+                    });
+                ui.separator();
+                let response = ui.add(
+                    egui::TextEdit::multiline(&mut self.input)
+                        .code_editor()
+                        .desired_rows(2)
+                        .desired_width(f32::INFINITY)
+                        .hint_text("Ctrl+Enter runs, Up and Down browse the history"),
+                );
+                let mut run = response.has_focus()
+                    && ui.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Enter));
+                // multi-line scripts keep the arrows for moving the cursor
+                if response.has_focus() && !self.input.contains('\n') {
+                    if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp)) {
+                        self.browse(-1);
+                    }
+                    if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown))
+                    {
+                        self.browse(1);
+                    }
+                }
+                ui.horizontal(|ui| {
+                    run |= ui
+                        .add_enabled(!self.input.trim().is_empty(), egui::Button::new("Run"))
+                        .clicked();
+                    if ui.button("Clear").clicked() {
+                        self.log.borrow_mut().clear();
+                    }
+                    ui.label("Functions").on_hover_text(HELP);
+                });
+                if run && !self.input.trim().is_empty() {
+                    let script = std::mem::take(&mut self.input);
+                    self.browsing = None;
+                    self.run(script.trim().to_owned(), scope());
+                    response.request_focus();
+                }
+            });
+        self.open = open;
+    }
+    /// Moves through the history, past the newest entry the input is empty again.
+    fn browse(&mut self, step: isize) {
+        if self.history.is_empty() {
+            return;
+        }
+        let next = match self.browsing {
+            Some(i) => i.checked_add_signed(step),
+            None if step < 0 => Some(self.history.len() - 1),
+            None => None,
+        };
+        self.browsing = next.filter(|i| *i < self.history.len());
+        self.input = self
+            .browsing
+            .map_or_else(String::new, |i| self.history[i].clone());
+    }
+}
+
+/// The variables scripts can read: the camera and what each loaded model has.
+pub fn scope(camera: &Camera, models: &[GltfRenderInfo]) -> Scope<'static> {
+    let vec3 = |v: glm::Vec3| -> Array { v.iter().map(|x| Dynamic::from(*x as f64)).collect() };
+    let models: Array = models
+        .iter()
+        .map(|info| {
+            let mut model = Map::new();
+            let name = info
+                .vktf
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            model.insert("name".into(), name.into());
+            model.insert(
+                "materials".into(),
+                (info.materials.index.len() as i64).into(),
+            );
+            model.insert(
+                "animations".into(),
+                (info.vktf.animations.len() as i64).into(),
+            );
+            model.insert("nodes".into(), (info.scene.nodes.len() as i64).into());
+            model.into()
+        })
+        .collect();
+
+    let mut scope = Scope::new();
+    scope.push_constant("camera_eye", vec3(camera.eye()));
+    scope.push_constant("camera_target", vec3(camera.target()));
+    scope.push_constant("models", models);
+    scope
+}
+
+/// Indices below zero turn into ones that don't exist, they are reported when applied.
+fn index(i: i64) -> usize {
+    usize::try_from(i).unwrap_or(usize::MAX)
+}
+
+/// Rhai doesn't pass integers to float parameters, so numbers are taken as either.
+fn number(value: Dynamic) -> Result<f32, Box<EvalAltResult>> {
+    let type_name = value.type_name();
+    value
+        .as_float()
+        .or_else(|_| value.as_int().map(|value| value as f64))
+        .map(|value| value as f32)
+        .map_err(|_| format!("expected a number, got {type_name}").into())
+}
+
+type Number = Dynamic;
+type ScriptResult = Result<(), Box<EvalAltResult>>;
+
+fn register(engine: &mut Engine, commands: &Commands) {
+    let queue = |commands: &Commands| {
+        let commands = commands.clone();
+        move |command| commands.borrow_mut().push_back(command)
+    };
+
+    let push = queue(commands);
+    engine.register_fn(
+        "set_camera",
+        move |ex: Number,
+              ey: Number,
+              ez: Number,
+              tx: Number,
+              ty: Number,
+              tz: Number|
+              -> ScriptResult {
+            push(ScriptCommand::CameraPose {
+                eye: glm::vec3(number(ex)?, number(ey)?, number(ez)?),
+                target: glm::vec3(number(tx)?, number(ty)?, number(tz)?),
+            });
+            Ok(())
+        },
+    );
+    let push = queue(commands);
+    engine.register_fn("frame_scene", move || push(ScriptCommand::FrameScene));
+    let push = queue(commands);
+    engine.register_fn("set_fov", move |degrees: Number| -> ScriptResult {
+        push(ScriptCommand::Fov(number(degrees)?));
+        Ok(())
+    });
+
+    let material = |commands: &Commands| {
+        let push = queue(commands);
+        move |model: i64, material: i64, edit| {
+            push(ScriptCommand::Material {
+                model: index(model),
+                material: index(material),
+                edit,
+            })
+        }
+    };
+    let edit = material(commands);
+    engine.register_fn(
+        "set_base_color",
+        move |model: i64,
+              material: i64,
+              r: Number,
+              g: Number,
+              b: Number,
+              a: Number|
+              -> ScriptResult {
+            let color = glm::vec4(number(r)?, number(g)?, number(b)?, number(a)?);
+            edit(model, material, MaterialEdit::BaseColor(color));
+            Ok(())
+        },
+    );
+    let edit = material(commands);
+    engine.register_fn(
+        "set_emissive",
+        move |model: i64, material: i64, r: Number, g: Number, b: Number| -> ScriptResult {
+            let color = glm::vec3(number(r)?, number(g)?, number(b)?);
+            edit(model, material, MaterialEdit::Emissive(color));
+            Ok(())
+        },
+    );
+    let edit = material(commands);
+    engine.register_fn(
+        "set_metallic",
+        move |model: i64, material: i64, value: Number| -> ScriptResult {
+            edit(model, material, MaterialEdit::Metallic(number(value)?));
+            Ok(())
+        },
+    );
+    let edit = material(commands);
+    engine.register_fn(
+        "set_roughness",
+        move |model: i64, material: i64, value: Number| -> ScriptResult {
+            edit(model, material, MaterialEdit::Roughness(number(value)?));
+            Ok(())
+        },
+    );
+
+    let push = queue(commands);
+    engine.register_fn("play", move |model: i64| {
+        push(ScriptCommand::Play {
+            model: index(model),
+            animation: None,
+        })
+    });
+    let push = queue(commands);
+    engine.register_fn("play", move |model: i64, animation: i64| {
+        push(ScriptCommand::Play {
+            model: index(model),
+            animation: Some(index(animation)),
+        })
+    });
+    let push = queue(commands);
+    engine.register_fn("pause", move |model: i64| {
+        push(ScriptCommand::Pause {
+            model: index(model),
+        })
+    });
+    let push = queue(commands);
+    engine.register_fn("seek", move |model: i64, time: Number| -> ScriptResult {
+        push(ScriptCommand::Seek {
+            model: index(model),
+            time: number(time)?,
+        });
+        Ok(())
+    });
+    let push = queue(commands);
+    engine.register_fn(
+        "set_node_visible",
+        move |model: i64, node: i64, visible: bool| {
+            push(ScriptCommand::NodeVisible {
+                model: index(model),
+                node: index(node),
+                visible,
+            })
+        },
+    );
+    let push = queue(commands);
+    engine.register_fn("screenshot", move || push(ScriptCommand::Screenshot));
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use vulkano::{
+    buffer::BufferContents,
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor_set::{
+        DescriptorSet, WriteDescriptorSet, allocator::StandardDescriptorSetAllocator,
+    },
+    image::{
+        Image, ImageSubresourceRange,
+        sampler::{Sampler, SamplerCreateInfo},
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+    },
+    pipeline::{
+        ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo, compute::ComputePipelineCreateInfo,
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+    },
+    shader::EntryPoint,
+};
+
+/// Fills one mip of a cubemap from a sampled source cubemap.
+/// Shaders read the source at set 0 binding 0, write the target at binding 1
+/// and run one 8x8 workgroup per tile with `z` being the face.
+#[derive(Clone)]
+pub struct CubemapComputePipeline {
+    pub pipeline: Arc<ComputePipeline>,
+    pub sampler: Arc<Sampler>,
+}
+impl CubemapComputePipeline {
+    pub fn new(cs: EntryPoint) -> Self {
+        let device = cs.module().device().clone();
+        let stage = PipelineShaderStageCreateInfo::new(cs);
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+        let pipeline = ComputePipeline::new(
+            device.clone(),
+            None,
+            ComputePipelineCreateInfo::stage_layout(stage, layout),
+        )
+        .unwrap();
+        let sampler = Sampler::new(device, SamplerCreateInfo::simple_repeat_linear()).unwrap();
+
+        Self { pipeline, sampler }
+    }
+
+    pub fn dispatch<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        set_allocator: Arc<StandardDescriptorSetAllocator>,
+        source: &Arc<Image>,
+        target: &Arc<Image>,
+        mip: u32,
+        push: impl BufferContents,
+    ) {
+        let source_view = ImageView::new(
+            source.clone(),
+            ImageViewCreateInfo {
+                view_type: ImageViewType::Cube,
+                ..ImageViewCreateInfo::from_image(source)
+            },
+        )
+        .unwrap();
+        let target_view = ImageView::new(
+            target.clone(),
+            ImageViewCreateInfo {
+                view_type: ImageViewType::Cube,
+                subresource_range: ImageSubresourceRange {
+                    mip_levels: mip..mip + 1,
+                    ..target.subresource_range()
+                },
+                ..ImageViewCreateInfo::from_image(target)
+            },
+        )
+        .unwrap();
+        let set = DescriptorSet::new(
+            set_allocator,
+            self.pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view_sampler(0, source_view, self.sampler.clone()),
+                WriteDescriptorSet::image_view(1, target_view),
+            ],
+            [],
+        )
+        .unwrap();
+
+        let size = (target.extent()[0] >> mip).max(1);
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .unwrap()
+            .push_constants(self.pipeline.layout().clone(), 0, push)
+            .unwrap();
+        unsafe { builder.dispatch([size.div_ceil(8), size.div_ceil(8), 6]) }.unwrap();
+    }
+}
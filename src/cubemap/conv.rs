@@ -1,37 +1,58 @@
-use super::{CubemapPipelineBuilder, CubemapVertexShader};
-use vulkano::device::DeviceOwned;
-
-impl CubemapPipelineBuilder {
-    pub fn new_conv(vertex: CubemapVertexShader) -> Self {
-        let device = vertex.vs.module().device();
-        let fs = fs::load(device.clone())
-            .unwrap()
-            .entry_point("main")
-            .unwrap();
-
-        Self {
-            vs: vertex.vs,
-            vis: vertex.vis,
-            fs,
-        }
+use super::compute::CubemapComputePipeline;
+use std::sync::Arc;
+use vulkano::{buffer::BufferContents, device::Device};
+
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+pub struct ConvolutionPush {
+    /// Angle between samples in radians.
+    pub delta: f32,
+}
+
+impl CubemapComputePipeline {
+    pub fn new_conv(device: Arc<Device>) -> Self {
+        let cs = cs::load(device).unwrap().entry_point("main").unwrap();
+        Self::new(cs)
     }
 }
 
-mod fs {
+mod cs {
     vulkano_shaders::shader! {
-        ty: "fragment",
+        ty: "compute",
         src: r#"
 #version 450
 
-layout(location = 0) in vec3 v_position;
-layout(set = 1, binding = 0) uniform samplerCube envMap;
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
 
-layout(location = 0) out vec4 f_color;
+layout(set = 0, binding = 0) uniform samplerCube envMap;
+layout(set = 0, binding = 1, rgba16f) uniform writeonly imageCube target;
+layout(push_constant) uniform PushConstants {
+    float delta;
+} push;
 
 const float PI = 3.14159265358979323846264338327950288;
 
+// direction a samplerCube would look up for this texel
+vec3 cube_direction(ivec3 id, int size) {
+    vec2 uv = (vec2(id.xy) + 0.5) / float(size) * 2.0 - 1.0;
+    switch (id.z) {
+        case 0: return normalize(vec3( 1.0, -uv.y, -uv.x));
+        case 1: return normalize(vec3(-1.0, -uv.y,  uv.x));
+        case 2: return normalize(vec3( uv.x,  1.0,  uv.y));
+        case 3: return normalize(vec3( uv.x, -1.0, -uv.y));
+        case 4: return normalize(vec3( uv.x, -uv.y,  1.0));
+        default: return normalize(vec3(-uv.x, -uv.y, -1.0));
+    }
+}
+
 void main() {
-    vec3 N = normalize(v_position);
+    ivec3 id = ivec3(gl_GlobalInvocationID);
+    ivec2 size = imageSize(target);
+    if (any(greaterThanEqual(id.xy, size))) {
+        return;
+    }
+
+    vec3 N = cube_direction(id, size.x);
     vec3 irradiance = vec3(0.0);
 
     vec3 up    = vec3(0.0, 1.0, 0.0);
@@ -39,11 +60,11 @@ void main() {
     up         = normalize(cross(N, right));
 
     float samples = 0.0;
-    for(float phi = 0.0; phi < 2.0 * PI; phi += 0.01){
+    for(float phi = 0.0; phi < 2.0 * PI; phi += push.delta){
         float cos_phi = cos(phi);
         float sin_phi = sin(phi);
 
-        for(float theta = 0.0; theta < 0.5 * PI; theta += 0.01){
+        for(float theta = 0.0; theta < 0.5 * PI; theta += push.delta){
             float cos_theta = cos(theta);
             float sin_theta = sin(theta);
 
@@ -54,7 +75,7 @@ void main() {
         }
     }
     irradiance *= PI / samples;
-    f_color = vec4(irradiance, 1.0);
+    imageStore(target, id, vec4(irradiance, 1.0));
 }
         "#
     }
@@ -1,5 +1,35 @@
 use super::{CubemapPipelineBuilder, CubemapVertexShader};
-use vulkano::device::DeviceOwned;
+use std::sync::Arc;
+use vulkano::{
+    descriptor_set::layout::DescriptorSetLayout,
+    device::DeviceOwned,
+    pipeline::{
+        PipelineLayout,
+        layout::{PipelineLayoutCreateInfo, PushConstantRange},
+    },
+    shader::ShaderStages,
+};
+
+/// Skybox pipelines push the environment intensity and rotation.
+pub fn skybox_pipeline_layout(
+    camera_set_layout: Arc<DescriptorSetLayout>,
+    texture_set_layout: Arc<DescriptorSetLayout>,
+) -> Arc<PipelineLayout> {
+    let device = camera_set_layout.device();
+    PipelineLayout::new(
+        device.clone(),
+        PipelineLayoutCreateInfo {
+            set_layouts: vec![camera_set_layout, texture_set_layout],
+            push_constant_ranges: vec![PushConstantRange {
+                stages: ShaderStages::FRAGMENT,
+                offset: 0,
+                size: 8,
+            }],
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
 
 impl CubemapPipelineBuilder {
     pub fn new_cube(vertex: CubemapVertexShader) -> Self {
@@ -25,11 +55,18 @@ mod fs {
 
 layout(location = 0) in vec3 v_position;
 layout(set = 1, binding = 0) uniform samplerCube cubemap;
+layout(push_constant) uniform Environment {
+    float intensity;
+    float yaw;
+} env;
 
 layout(location = 0) out vec4 f_color;
 
 void main() {
-    f_color = texture(cubemap, v_position);
+    float s = sin(env.yaw);
+    float c = cos(env.yaw);
+    vec3 dir = vec3(c * v_position.x + s * v_position.z, v_position.y, -s * v_position.x + c * v_position.z);
+    f_color = vec4(texture(cubemap, dir).rgb * env.intensity, 1.0);
 }
         "#
     }
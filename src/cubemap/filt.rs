@@ -1,67 +1,54 @@
-use super::{CubemapPipelineBuilder, CubemapVertexShader};
+use super::compute::CubemapComputePipeline;
 use std::sync::Arc;
-use vulkano::{
-    descriptor_set::layout::DescriptorSetLayout,
-    device::DeviceOwned,
-    pipeline::{
-        PipelineLayout,
-        layout::{PipelineLayoutCreateInfo, PushConstantRange},
-    },
-    shader::ShaderStages,
-};
-
-pub fn filter_pipeline_layout(
-    camera_set_layout: Arc<DescriptorSetLayout>,
-    texture_set_layout: Arc<DescriptorSetLayout>,
-) -> Arc<PipelineLayout> {
-    let device = camera_set_layout.device();
-    PipelineLayout::new(
-        device.clone(),
-        PipelineLayoutCreateInfo {
-            set_layouts: vec![camera_set_layout, texture_set_layout],
-            push_constant_ranges: vec![PushConstantRange {
-                stages: ShaderStages::FRAGMENT,
-                offset: 0,
-                size: 4,
-            }],
-            ..Default::default()
-        },
-    )
-    .unwrap()
+use vulkano::{buffer::BufferContents, device::Device};
+
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+pub struct FilterPush {
+    pub roughness: f32,
+    pub samples: u32,
+    /// Face size of the source cubemap.
+    pub resolution: f32,
 }
 
-impl CubemapPipelineBuilder {
-    pub fn new_filt(vertex: CubemapVertexShader) -> Self {
-        let device = vertex.vs.module().device();
-        let fs = fs::load(device.clone())
-            .unwrap()
-            .entry_point("main")
-            .unwrap();
-
-        Self {
-            vs: vertex.vs,
-            vis: vertex.vis,
-            fs,
-        }
+impl CubemapComputePipeline {
+    pub fn new_filt(device: Arc<Device>) -> Self {
+        let cs = cs::load(device).unwrap().entry_point("main").unwrap();
+        Self::new(cs)
     }
 }
 
-mod fs {
+mod cs {
     vulkano_shaders::shader! {
-        ty: "fragment",
+        ty: "compute",
         src: r#"
 #version 450
 
-layout(location = 0) in vec3 v_position;
-layout(set = 1, binding = 0) uniform samplerCube envMap;
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(set = 0, binding = 0) uniform samplerCube envMap;
+layout(set = 0, binding = 1, rgba16f) uniform writeonly imageCube target;
 layout(push_constant) uniform PushConstants {
     float roughness;
+    uint samples;
+    float resolution;
 } push;
 
-layout(location = 0) out vec4 f_color;
-
 const float PI = 3.14159265358979323846264338327950288;
 
+// direction a samplerCube would look up for this texel
+vec3 cube_direction(ivec3 id, int size) {
+    vec2 uv = (vec2(id.xy) + 0.5) / float(size) * 2.0 - 1.0;
+    switch (id.z) {
+        case 0: return normalize(vec3( 1.0, -uv.y, -uv.x));
+        case 1: return normalize(vec3(-1.0, -uv.y,  uv.x));
+        case 2: return normalize(vec3( uv.x,  1.0,  uv.y));
+        case 3: return normalize(vec3( uv.x, -1.0, -uv.y));
+        case 4: return normalize(vec3( uv.x, -uv.y,  1.0));
+        default: return normalize(vec3(-uv.x, -uv.y, -1.0));
+    }
+}
+
 float distribution_ggx(vec3 N, vec3 H, float roughness) {
     float a = roughness*roughness;
     float a2 = a*a;
@@ -106,11 +93,17 @@ vec3 importance_sample_ggx(vec2 Xi, vec3 N, float roughness) {
 }
 
 void main(){
-    vec3 N = normalize(v_position);
+    ivec3 id = ivec3(gl_GlobalInvocationID);
+    ivec2 size = imageSize(target);
+    if (any(greaterThanEqual(id.xy, size))) {
+        return;
+    }
+
+    vec3 N = cube_direction(id, size.x);
     vec3 R = N;
     vec3 V = R;
 
-    const uint SAMPLE_COUNT = 1024u;
+    uint SAMPLE_COUNT = push.samples;
     vec3 prefiltered_color = vec3(0.0);
     float total_weight = 0.0;
 
@@ -126,7 +119,7 @@ void main(){
             float h_dot_v = max(dot(H, V), 0.0);
             float pdf = D * n_dot_h / (4.0 * h_dot_v) + 0.0001;
 
-            float resolution = 512.0; // resolution of source cubemap (per face)
+            float resolution = push.resolution; // resolution of source cubemap (per face)
             float sa_texel  = 4.0 * PI / (6.0 * resolution * resolution);
             float sa_sample = 1.0 / (float(SAMPLE_COUNT) * pdf + 0.0001);
 
@@ -139,7 +132,7 @@ void main(){
 
     prefiltered_color /= total_weight;
 
-    f_color = vec4(prefiltered_color, 1.0);
+    imageStore(target, id, vec4(prefiltered_color, 1.0));
 }
         "#
     }
@@ -1,3 +1,4 @@
+use crate::frameinfo::Depth;
 use mesh::CubemapVertex;
 use std::sync::Arc;
 use vulkano::{
@@ -9,7 +10,7 @@ use vulkano::{
         graphics::{
             GraphicsPipelineCreateInfo,
             color_blend::{ColorBlendAttachmentState, ColorBlendState},
-            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            depth_stencil::{CompareOp, DepthStencilState},
             input_assembly::InputAssemblyState,
             multisample::MultisampleState,
             rasterization::{CullMode, FrontFace, RasterizationState},
@@ -22,12 +23,14 @@ use vulkano::{
     shader::EntryPoint,
 };
 
+pub mod compute;
 pub mod conv;
 pub mod cube;
 pub mod equi;
 pub mod filt;
 mod mesh;
 pub mod renderer;
+pub mod sky;
 
 pub use mesh::CubeMesh;
 
@@ -71,7 +74,13 @@ pub struct CubemapPipelineBuilder {
     vis: VertexInputState,
 }
 impl CubemapPipelineBuilder {
-    pub fn build(self, layout: Arc<PipelineLayout>, subpass: Subpass) -> Arc<GraphicsPipeline> {
+    /// `depth` only matters if the subpass has a depth buffer.
+    pub fn build(
+        self,
+        layout: Arc<PipelineLayout>,
+        subpass: Subpass,
+        depth: Depth,
+    ) -> Arc<GraphicsPipeline> {
         let stages = [
             PipelineShaderStageCreateInfo::new(self.vs),
             PipelineShaderStageCreateInfo::new(self.fs),
@@ -90,10 +99,7 @@ impl CubemapPipelineBuilder {
 
         let depth_stencil_state = if has_depth_buffer {
             Some(DepthStencilState {
-                depth: Some(DepthState {
-                    write_enable: true,
-                    compare_op: CompareOp::LessOrEqual,
-                }),
+                depth: Some(depth.state(CompareOp::LessOrEqual, true)),
                 ..Default::default()
             })
         } else {
@@ -149,7 +155,10 @@ layout(set = 0, binding = 0) uniform Camera {
 layout(location = 0) out vec3 f_position;
 
 void main() {
-    gl_Position = (cam.proj * cam.view * vec4(position, 0.0)).xyww;
+    vec4 clip = cam.proj * cam.view * vec4(position, 0.0);
+    // depth of a point at infinity, kept on the far plane for normal and reverse-Z
+    float far_depth = clamp(cam.proj[2][2] / cam.proj[2][3], 0.0, 1.0);
+    gl_Position = vec4(clip.xy, far_depth * clip.w, clip.w);
     f_position = position;
 }
         "#
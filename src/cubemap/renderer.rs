@@ -16,33 +16,42 @@ use vulkano::{
         Image, ImageCreateFlags, ImageCreateInfo, ImageSubresourceRange, ImageType, ImageUsage,
         view::{ImageView, ImageViewCreateInfo, ImageViewType},
     },
-    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    memory::allocator::{
+        AllocationCreateInfo, MemoryAllocator, MemoryTypeFilter, StandardMemoryAllocator,
+    },
     pipeline::{
         GraphicsPipeline, Pipeline, PipelineBindPoint,
         graphics::viewport::{Scissor, Viewport},
     },
     render_pass::{Framebuffer, FramebufferCreateInfo, Subpass},
+    sync::Sharing,
 };
 
+/// The views from `eye` onto the faces of a cubemap in layer order,
+/// together with a 90 degree right handed projection.
+pub fn face_views(eye: &glm::Vec3) -> [glm::Mat4; 6] {
+    #[rustfmt::skip]
+    let faces = [
+        (glm::vec3( 1.0,  0.0,  0.0), glm::vec3( 0.0, -1.0,  0.0)),
+        (glm::vec3(-1.0,  0.0,  0.0), glm::vec3( 0.0, -1.0,  0.0)),
+        (glm::vec3( 0.0,  1.0,  0.0), glm::vec3( 0.0,  0.0,  1.0)),
+        (glm::vec3( 0.0, -1.0,  0.0), glm::vec3( 0.0,  0.0, -1.0)),
+        (glm::vec3( 0.0,  0.0,  1.0), glm::vec3( 0.0, -1.0,  0.0)),
+        (glm::vec3( 0.0,  0.0, -1.0), glm::vec3( 0.0, -1.0,  0.0)),
+    ];
+    faces.map(|(direction, up)| glm::look_at_rh(eye, &(eye + direction), &up))
+}
+
 fn create_cubemap_cameras(
     mem_allocator: Arc<StandardMemoryAllocator>,
     set_allocator: Arc<StandardDescriptorSetAllocator>,
     camera_set_layout: Arc<DescriptorSetLayout>,
 ) -> Vec<Arc<DescriptorSet>> {
     let proj = glm::perspective_rh_zo(1.0, std::f32::consts::FRAC_PI_2, 0.1, 10.0);
-    let eye = glm::Vec3::zeros();
-    #[rustfmt::skip]
-    let views = [
-        [glm::look_at_rh(&eye, &glm::vec3( 1.0,  0.0,  0.0), &glm::vec3( 0.0, -1.0,  0.0)), proj],
-        [glm::look_at_rh(&eye, &glm::vec3(-1.0,  0.0,  0.0), &glm::vec3( 0.0, -1.0,  0.0)), proj],
-        [glm::look_at_rh(&eye, &glm::vec3( 0.0,  1.0,  0.0), &glm::vec3( 0.0,  0.0,  1.0)), proj],
-        [glm::look_at_rh(&eye, &glm::vec3( 0.0, -1.0,  0.0), &glm::vec3( 0.0,  0.0, -1.0)), proj],
-        [glm::look_at_rh(&eye, &glm::vec3( 0.0,  0.0,  1.0), &glm::vec3( 0.0, -1.0,  0.0)), proj],
-        [glm::look_at_rh(&eye, &glm::vec3( 0.0,  0.0, -1.0), &glm::vec3( 0.0, -1.0,  0.0)), proj],
-    ];
 
-    views
+    face_views(&glm::Vec3::zeros())
         .into_iter()
+        .map(|view| [view, proj])
         .map(|view| {
             let buffer = Buffer::from_data(
                 mem_allocator.clone(),
@@ -189,10 +198,12 @@ impl CubemapRenderPipeline {
     }
 }
 
+/// `queue_families` are the distinct families that use the image.
 pub fn create_cubemap_image(
-    allocator: Arc<StandardMemoryAllocator>,
+    allocator: Arc<dyn MemoryAllocator>,
     size: u32,
     mips: u32,
+    queue_families: &[u32],
 ) -> Arc<Image> {
     Image::new(
         allocator,
@@ -200,6 +211,7 @@ pub fn create_cubemap_image(
             flags: ImageCreateFlags::CUBE_COMPATIBLE,
             usage: ImageUsage::COLOR_ATTACHMENT
                 | ImageUsage::SAMPLED
+                | ImageUsage::STORAGE
                 | if mips > 0 {
                     ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST
                 } else {
@@ -210,6 +222,11 @@ pub fn create_cubemap_image(
             extent: [size, size, 1],
             mip_levels: mips,
             format: Format::R16G16B16A16_SFLOAT,
+            sharing: if queue_families.len() > 1 {
+                Sharing::Concurrent(queue_families.iter().copied().collect())
+            } else {
+                Sharing::Exclusive
+            },
             ..Default::default()
         },
         AllocationCreateInfo::default(),
@@ -0,0 +1,58 @@
+use super::{CubemapPipelineBuilder, CubemapVertexShader};
+use vulkano::device::DeviceOwned;
+
+impl CubemapPipelineBuilder {
+    /// Renders a procedural sky described by a [`SkyUniform`](crate::skybox::sky::SkyUniform) at set 1.
+    pub fn new_sky(vertex: CubemapVertexShader) -> Self {
+        let device = vertex.vs.module().device();
+        let fs = fs::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+
+        Self {
+            vs: vertex.vs,
+            vis: vertex.vis,
+            fs,
+        }
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec3 v_pos;
+layout(set = 1, binding = 0) uniform Sky {
+    vec4 zenith;
+    vec4 horizon;
+    vec4 ground;
+    // w is the cosine of the sun's angular radius
+    vec4 sun_direction;
+    vec4 sun_color;
+} sky;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    vec3 dir = normalize(v_pos);
+
+    vec3 color;
+    if (dir.y >= 0.0) {
+        color = mix(sky.horizon.rgb, sky.zenith.rgb, sqrt(dir.y));
+    } else {
+        color = mix(sky.horizon.rgb, sky.ground.rgb, pow(-dir.y, 0.3));
+    }
+
+    float cos_sun = dot(dir, normalize(sky.sun_direction.xyz));
+    float disc = smoothstep(sky.sun_direction.w - 0.0002, sky.sun_direction.w, cos_sun);
+    float glow = pow(max(cos_sun, 0.0), 256.0) * 0.05;
+    color += sky.sun_color.rgb * (disc + glow);
+
+    f_color = vec4(color, 1.0);
+}
+        "#
+    }
+}
@@ -1,32 +1,326 @@
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use vulkano::{
     command_buffer::RenderPassBeginInfo,
-    device::DeviceOwned,
-    format::Format,
+    device::{Device, DeviceOwned},
+    format::{Format, FormatFeatures},
     image::{Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount, view::ImageView},
     memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator},
+    pipeline::graphics::depth_stencil::{CompareOp, DepthState},
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
 };
 
+/// Anti-aliasing of the scene, multisampling in the main render pass or a post-process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Msaa {
+    Off,
+    X2,
+    #[default]
+    X4,
+    X8,
+    /// Single sampled, the edges are smoothed by a full screen pass afterwards.
+    Fxaa,
+    /// Single sampled with a jittered camera, the frames are accumulated over time.
+    Taa,
+}
+impl Msaa {
+    pub const ALL: [Msaa; 6] = [
+        Msaa::Off,
+        Msaa::X2,
+        Msaa::X4,
+        Msaa::X8,
+        Msaa::Fxaa,
+        Msaa::Taa,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Msaa::Off => "Off",
+            Msaa::X2 => "2x",
+            Msaa::X4 => "4x",
+            Msaa::X8 => "8x",
+            Msaa::Fxaa => "FXAA",
+            Msaa::Taa => "TAA",
+        }
+    }
+    pub fn samples(&self) -> SampleCount {
+        match self {
+            Msaa::Off | Msaa::Fxaa | Msaa::Taa => SampleCount::Sample1,
+            Msaa::X2 => SampleCount::Sample2,
+            Msaa::X4 => SampleCount::Sample4,
+            Msaa::X8 => SampleCount::Sample8,
+        }
+    }
+    /// Whether both the color and depth attachments can use this many samples.
+    pub fn supported(&self, device: &Device) -> bool {
+        let properties = device.physical_device().properties();
+        let counts =
+            properties.framebuffer_color_sample_counts & properties.framebuffer_depth_sample_counts;
+        counts.contains_enum(self.samples())
+    }
+    /// Whether the scene has to be rendered offscreen for the post-process.
+    pub fn post_process(&self) -> bool {
+        matches!(self, Msaa::Fxaa | Msaa::Taa)
+    }
+    fn multisampled(&self) -> bool {
+        self.samples() != SampleCount::Sample1
+    }
+    /// This sample count or the highest lower one the device supports.
+    pub fn or_supported(self, device: &Device) -> Self {
+        Self::ALL
+            .into_iter()
+            .rev()
+            .skip_while(|msaa| *msaa != self)
+            .find(|msaa| msaa.supported(device))
+            .unwrap_or(Msaa::Off)
+    }
+
+    /// Returns true if the sample count was changed.
+    pub fn ui(&mut self, ui: &mut egui::Ui, device: &Device) -> bool {
+        let old = *self;
+        egui::ComboBox::from_label("Anti-aliasing")
+            .selected_text(self.name())
+            .show_ui(ui, |ui| {
+                for msaa in Self::ALL.into_iter().filter(|msaa| msaa.supported(device)) {
+                    ui.selectable_value(self, msaa, msaa.name());
+                }
+            });
+        old != *self
+    }
+}
+
+/// Bits of the main depth buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DepthPrecision {
+    D16,
+    D24,
+    #[default]
+    D32,
+}
+impl DepthPrecision {
+    pub const ALL: [DepthPrecision; 3] = [
+        DepthPrecision::D16,
+        DepthPrecision::D24,
+        DepthPrecision::D32,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DepthPrecision::D16 => "16 bit",
+            DepthPrecision::D24 => "24 bit",
+            DepthPrecision::D32 => "32 bit float",
+        }
+    }
+    pub fn format(&self) -> Format {
+        match self {
+            DepthPrecision::D16 => Format::D16_UNORM,
+            DepthPrecision::D24 => Format::X8_D24_UNORM_PACK32,
+            DepthPrecision::D32 => Format::D32_SFLOAT,
+        }
+    }
+    /// Whether the format can be a depth attachment, 24 bit is optional.
+    pub fn supported(&self, device: &Device) -> bool {
+        device
+            .physical_device()
+            .format_properties(self.format())
+            .unwrap()
+            .optimal_tiling_features
+            .intersects(FormatFeatures::DEPTH_STENCIL_ATTACHMENT)
+    }
+    /// This precision or the highest one the device supports, 16 bit is always supported.
+    pub fn or_supported(self, device: &Device) -> Self {
+        if self.supported(device) {
+            return self;
+        }
+        Self::ALL
+            .into_iter()
+            .rev()
+            .find(|precision| precision.supported(device))
+            .unwrap_or(DepthPrecision::D16)
+    }
+}
+
+/// How depth is stored in the main render pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Depth {
+    /// The near plane is at depth 1 and the far plane at 0, together with a float buffer
+    /// this keeps the precision even over the whole distance.
+    pub reverse_z: bool,
+    pub precision: DepthPrecision,
+}
+impl Default for Depth {
+    fn default() -> Self {
+        Self {
+            reverse_z: true,
+            precision: DepthPrecision::default(),
+        }
+    }
+}
+impl Depth {
+    /// The depth of the far plane, which the buffer is cleared to.
+    pub fn clear_value(&self) -> f32 {
+        if self.reverse_z { 0.0 } else { 1.0 }
+    }
+    /// The depth test of a pipeline, `op` is given as if closer meant smaller.
+    pub fn state(&self, op: CompareOp, write_enable: bool) -> DepthState {
+        let compare_op = match (self.reverse_z, op) {
+            (false, op) => op,
+            (true, CompareOp::Less) => CompareOp::Greater,
+            (true, CompareOp::LessOrEqual) => CompareOp::GreaterOrEqual,
+            (true, CompareOp::Greater) => CompareOp::Less,
+            (true, CompareOp::GreaterOrEqual) => CompareOp::LessOrEqual,
+            (true, op) => op,
+        };
+        DepthState {
+            write_enable,
+            compare_op,
+        }
+    }
+    /// The same depth with a precision the device supports.
+    pub fn or_supported(self, device: &Device) -> Self {
+        Self {
+            precision: self.precision.or_supported(device),
+            ..self
+        }
+    }
+
+    /// Returns true if the render pass has to be recreated.
+    pub fn ui(&mut self, ui: &mut egui::Ui, device: &Device) -> bool {
+        let old = *self;
+        ui.checkbox(&mut self.reverse_z, "Reverse-Z")
+            .on_hover_text("Store depth from 1 at the near plane to 0 at the far plane");
+        egui::ComboBox::from_label("Depth precision")
+            .selected_text(self.precision.name())
+            .show_ui(ui, |ui| {
+                for precision in DepthPrecision::ALL
+                    .into_iter()
+                    .filter(|precision| precision.supported(device))
+                {
+                    ui.selectable_value(&mut self.precision, precision, precision.name());
+                }
+            });
+        old != *self
+    }
+}
+
 pub struct FrameInfo {
     frame_buffers: Vec<Arc<Framebuffer>>,
     subpass: Subpass,
+    msaa: Msaa,
+    depth: Depth,
     mem_alloc: Arc<StandardMemoryAllocator>,
 }
 impl FrameInfo {
-    const DEPTH_FORMAT: Format = Format::D32_SFLOAT;
-    const SAMPLES: SampleCount = SampleCount::Sample4;
-
-    pub fn new(mem_alloc: Arc<StandardMemoryAllocator>, views: &[Arc<ImageView>]) -> Self {
-        let format = views[0].image().format();
+    pub fn new(
+        mem_alloc: Arc<StandardMemoryAllocator>,
+        views: &[Arc<ImageView>],
+        msaa: Msaa,
+        depth: Depth,
+    ) -> Self {
+        let render_pass =
+            Self::create_render_pass(&mem_alloc, views[0].image().format(), msaa, depth);
+        let mut frame_info = Self {
+            frame_buffers: vec![],
+            subpass: Subpass::from(render_pass, 0).unwrap(),
+            msaa,
+            depth,
+            mem_alloc,
+        };
+        frame_info.recreate(views);
+        frame_info
+    }
+    /// Makes a new render pass, everything made for the old [`subpass`](Self::subpass) has to be recreated.
+    pub fn set_attachments(&mut self, msaa: Msaa, depth: Depth, views: &[Arc<ImageView>]) {
+        let render_pass =
+            Self::create_render_pass(&self.mem_alloc, views[0].image().format(), msaa, depth);
+        self.subpass = Subpass::from(render_pass, 0).unwrap();
+        self.msaa = msaa;
+        self.depth = depth;
+        self.recreate(views);
+    }
+    pub fn recreate(&mut self, views: &[Arc<ImageView>]) {
         let extent = views[0].image().extent();
+        let format = views[0].image().format();
+        let samples = self.msaa.samples();
+        let depth_buffer = Self::create_depth_buffer(
+            self.mem_alloc.clone(),
+            self.depth.precision.format(),
+            extent,
+            samples,
+        );
+        let msaa_buffer = self
+            .msaa
+            .multisampled()
+            .then(|| Self::create_mssa_buffer(self.mem_alloc.clone(), format, extent, samples));
+        self.frame_buffers = Self::create_frame_buffers(
+            self.subpass.render_pass(),
+            msaa_buffer.as_ref(),
+            &depth_buffer,
+            views,
+        );
+    }
+    /// Clears the frame with `background`.
+    pub fn render_pass_info(&self, index: usize, background: [f32; 4]) -> RenderPassBeginInfo {
+        let color = Some(background.into());
+        let depth = Some(self.depth.clear_value().into());
+        RenderPassBeginInfo {
+            clear_values: if self.msaa.multisampled() {
+                vec![color, None, depth]
+            } else {
+                vec![color, depth]
+            },
+            ..RenderPassBeginInfo::framebuffer(self.frame_buffers[index].clone())
+        }
+    }
+    pub fn subpass(&self) -> &Subpass {
+        &self.subpass
+    }
+    pub fn msaa(&self) -> Msaa {
+        self.msaa
+    }
+    pub fn depth(&self) -> Depth {
+        self.depth
+    }
 
-        let render_pass = vulkano::single_pass_renderpass!(
-            mem_alloc.device().clone(),
+    fn create_render_pass(
+        mem_alloc: &Arc<StandardMemoryAllocator>,
+        format: Format,
+        msaa: Msaa,
+        depth: Depth,
+    ) -> Arc<RenderPass> {
+        let device = mem_alloc.device().clone();
+        let depth_format = depth.precision.format();
+        if !msaa.multisampled() {
+            return vulkano::single_pass_renderpass!(
+                device,
+                attachments: {
+                    color: {
+                        format: format,
+                        samples: 1,
+                        load_op: Clear,
+                        store_op: Store,
+                    },
+                    depth_stencil: {
+                        format: depth_format,
+                        samples: 1,
+                        load_op: Clear,
+                        store_op: DontCare,
+                    },
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {depth_stencil}
+                },
+            )
+            .unwrap();
+        }
+        vulkano::single_pass_renderpass!(
+            device,
             attachments: {
                 intermediary: {
                   format: format,
-                  samples: Self::SAMPLES as u32,
+                  samples: msaa.samples() as u32,
                   load_op: Clear,
                   store_op: DontCare,
                 },
@@ -37,8 +331,8 @@ impl FrameInfo {
                     store_op: Store,
                 },
                 depth_stencil: {
-                    format: Self::DEPTH_FORMAT,
-                    samples: Self::SAMPLES as u32,
+                    format: depth_format,
+                    samples: msaa.samples() as u32,
                     load_op: Clear,
                     store_op: DontCare,
                 },
@@ -49,54 +343,22 @@ impl FrameInfo {
                 depth_stencil: {depth_stencil}
             },
         )
-        .unwrap();
-        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
-
-        let depth_buffer = Self::create_depth_buffer(mem_alloc.clone(), extent);
-        let msaa_buffer = Self::create_mssa_buffer(mem_alloc.clone(), format, extent);
-        let frame_buffers =
-            Self::create_frame_buffers(&render_pass, &msaa_buffer, &depth_buffer, views);
-
-        Self {
-            frame_buffers,
-            subpass,
-            mem_alloc,
-        }
-    }
-    pub fn recreate(&mut self, views: &[Arc<ImageView>]) {
-        let extent = views[0].image().extent();
-        let format = views[0].image().format();
-        let depth_buffer = Self::create_depth_buffer(self.mem_alloc.clone(), extent);
-        let msaa_buffer = Self::create_mssa_buffer(self.mem_alloc.clone(), format, extent);
-        self.frame_buffers = Self::create_frame_buffers(
-            self.subpass.render_pass(),
-            &msaa_buffer,
-            &depth_buffer,
-            views,
-        );
-    }
-    pub fn render_pass_info(&self, index: usize) -> RenderPassBeginInfo {
-        RenderPassBeginInfo {
-            clear_values: vec![Some([0.0, 0.0, 0.0, 1.0].into()), None, Some(1f32.into())],
-            ..RenderPassBeginInfo::framebuffer(self.frame_buffers[index].clone())
-        }
-    }
-    pub fn subpass(&self) -> &Subpass {
-        &self.subpass
+        .unwrap()
     }
-
     fn create_depth_buffer(
         allocator: Arc<StandardMemoryAllocator>,
+        format: Format,
         extent: [u32; 3],
+        samples: SampleCount,
     ) -> Arc<ImageView> {
         ImageView::new_default(
             Image::new(
                 allocator,
                 ImageCreateInfo {
                     image_type: ImageType::Dim2d,
-                    format: Self::DEPTH_FORMAT,
+                    format,
                     extent,
-                    samples: Self::SAMPLES,
+                    samples,
                     usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
                     ..Default::default()
                 },
@@ -110,6 +372,7 @@ impl FrameInfo {
         allocator: Arc<StandardMemoryAllocator>,
         format: Format,
         extent: [u32; 3],
+        samples: SampleCount,
     ) -> Arc<ImageView> {
         ImageView::new_default(
             Image::new(
@@ -118,7 +381,7 @@ impl FrameInfo {
                     image_type: ImageType::Dim2d,
                     format,
                     extent,
-                    samples: Self::SAMPLES,
+                    samples,
                     usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
                     ..Default::default()
                 },
@@ -130,7 +393,7 @@ impl FrameInfo {
     }
     fn create_frame_buffers(
         render_pass: &Arc<RenderPass>,
-        msaa_buffer: &Arc<ImageView>,
+        msaa_buffer: Option<&Arc<ImageView>>,
         depth_buffer: &Arc<ImageView>,
         views: &[Arc<ImageView>],
     ) -> Vec<Arc<Framebuffer>> {
@@ -140,7 +403,11 @@ impl FrameInfo {
                 Framebuffer::new(
                     render_pass.clone(),
                     FramebufferCreateInfo {
-                        attachments: vec![msaa_buffer.clone(), view.clone(), depth_buffer.clone()],
+                        attachments: msaa_buffer
+                            .into_iter()
+                            .chain([view, depth_buffer])
+                            .cloned()
+                            .collect(),
                         ..Default::default()
                     },
                 )
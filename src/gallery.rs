@@ -0,0 +1,390 @@
+use crate::{
+    Allocators, GltfViewerRenderer, RenderTarget,
+    camera::Camera,
+    frameinfo::{Depth, Msaa},
+    memory::MemoryCategory,
+};
+use egui_winit_vulkano::Gui;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        mpsc::{Receiver, channel},
+    },
+};
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferExecFuture, CommandBufferUsage,
+        PrimaryCommandBufferAbstract,
+    },
+    device::Queue,
+    format::Format,
+    image::{
+        Image, ImageCreateInfo, ImageType, ImageUsage, sampler::SamplerCreateInfo, view::ImageView,
+    },
+    memory::allocator::AllocationCreateInfo,
+    sync::{
+        GpuFuture,
+        future::{FenceSignalFuture, NowFuture},
+    },
+};
+
+const THUMBNAIL_SIZE: u32 = 128;
+const EXTENSIONS: [&str; 2] = ["glb", "gltf"];
+
+enum Thumbnail {
+    Pending,
+    Rendered {
+        view: Arc<ImageView>,
+        texture: Option<egui::TextureId>,
+    },
+    Failed(String),
+}
+
+struct Entry {
+    path: PathBuf,
+    thumbnail: Thumbnail,
+}
+
+pub enum GalleryAction {
+    Load(PathBuf),
+    PickFolder,
+}
+
+/// The glTF files of a folder as a grid of thumbnails, kept up to date while files are added,
+/// changed or removed. The thumbnails are rendered one after another by a second viewer
+/// that loads the models in the background, its frames are polled without blocking the UI.
+#[derive(Default)]
+pub struct Gallery {
+    pub open: bool,
+    dir: Option<PathBuf>,
+    entries: Vec<Entry>,
+    /// Made when the first folder is opened.
+    renderer: Option<Box<GltfViewerRenderer>>,
+    /// The entry whose model the renderer is loading and the image its thumbnail is drawn in.
+    rendering: Option<(PathBuf, Arc<ImageView>)>,
+    /// The frame of the thumbnail on the GPU, and whether it drew the loaded model.
+    submitted: Option<(Frame, bool)>,
+    watcher: Option<(RecommendedWatcher, Receiver<notify::Result<notify::Event>>)>,
+    /// Textures of removed thumbnails, unregistered with the next [`register`](Self::register).
+    unused: Vec<egui::TextureId>,
+}
+impl Gallery {
+    /// Shows the models in `dir` and watches it for changes.
+    pub fn open_dir(&mut self, dir: PathBuf) {
+        let (sender, events) = channel();
+        self.watcher = notify::recommended_watcher(sender)
+            .and_then(|mut watcher| {
+                watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+                Ok((watcher, events))
+            })
+            .inspect_err(|err| log::warn!("failed to watch {}: {err}", dir.display()))
+            .ok();
+        let textures = self
+            .entries
+            .drain(..)
+            .filter_map(|entry| match entry.thumbnail {
+                Thumbnail::Rendered { texture, .. } => texture,
+                _ => None,
+            });
+        self.unused.extend(textures);
+        self.dir = Some(dir);
+        self.open = true;
+        self.scan();
+    }
+    /// The folder shown, if one was opened.
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+    /// Adds the new files of the folder and drops the removed ones.
+    fn scan(&mut self) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| is_model(path))
+                .collect(),
+            Err(err) => {
+                log::error!("failed to read {}: {err}", dir.display());
+                vec![]
+            }
+        };
+        paths.sort();
+        let mut old = std::mem::take(&mut self.entries);
+        self.entries = paths
+            .into_iter()
+            .map(
+                |path| match old.iter().position(|entry| entry.path == path) {
+                    Some(i) => old.swap_remove(i),
+                    None => Entry {
+                        path,
+                        thumbnail: Thumbnail::Pending,
+                    },
+                },
+            )
+            .collect();
+        let textures = old.into_iter().filter_map(|entry| match entry.thumbnail {
+            Thumbnail::Rendered { texture, .. } => texture,
+            _ => None,
+        });
+        self.unused.extend(textures);
+    }
+    /// Follows the changes of the folder and renders the next thumbnail, call once per frame.
+    pub fn update(&mut self, allocators: &Allocators, queue: &Arc<Queue>) {
+        let mut rescan = false;
+        let mut changed = vec![];
+        if let Some((_, events)) = &self.watcher {
+            for event in events.try_iter().flatten() {
+                match event.kind {
+                    EventKind::Create(_) | EventKind::Remove(_) => rescan = true,
+                    EventKind::Modify(_) => changed.extend(event.paths),
+                    _ => {}
+                }
+            }
+        }
+        if rescan {
+            self.scan();
+        }
+        for entry in &mut self.entries {
+            if changed.contains(&entry.path) {
+                if let Thumbnail::Rendered {
+                    texture: Some(texture),
+                    ..
+                } = entry.thumbnail
+                {
+                    self.unused.push(texture);
+                }
+                entry.thumbnail = Thumbnail::Pending;
+            }
+        }
+        if self.open {
+            self.render_next(allocators, queue);
+        }
+    }
+    fn render_next(&mut self, allocators: &Allocators, queue: &Arc<Queue>) {
+        // one frame is in flight at a time, the UI polls it instead of waiting
+        if let Some((fence, drawn)) = &self.submitted {
+            let result = match fence.is_signaled() {
+                Ok(false) => return,
+                Ok(true) => Ok(()),
+                Err(err) => Err(err.to_string()),
+            };
+            let drawn = *drawn;
+            self.submitted = None;
+            if drawn {
+                self.finish(result);
+                return;
+            }
+        }
+        if self.rendering.is_none() {
+            let Some(entry) = self
+                .entries
+                .iter()
+                .find(|entry| matches!(entry.thumbnail, Thumbnail::Pending))
+            else {
+                return;
+            };
+            let target = thumbnail_target(allocators);
+            let renderer = self.renderer.get_or_insert_with(|| {
+                let mut renderer = GltfViewerRenderer::new(
+                    allocators,
+                    queue.clone(),
+                    None,
+                    RenderTarget {
+                        views: vec![target.clone()],
+                        msaa: Msaa::default(),
+                        depth: Depth::default(),
+                    },
+                    Camera::default(),
+                );
+                renderer.load_sky(Default::default());
+                Box::new(renderer)
+            });
+            renderer.resize(&[target.clone()]);
+            renderer.load_model(entry.path.clone());
+            self.rendering = Some((entry.path.clone(), target));
+        }
+        let (Some(renderer), Some(_)) = (&mut self.renderer, &self.rendering) else {
+            return;
+        };
+
+        // drawn every frame while loading, a model is framed and drawn by the update adding it
+        let submitted = submit(renderer, allocators, queue);
+        let drawn = !renderer.state().loading();
+        match submitted {
+            Ok(fence) => self.submitted = Some((fence, drawn)),
+            Err(err) if drawn => self.finish(Err(err)),
+            Err(_) => {}
+        }
+    }
+    /// Stores the thumbnail of the model whose last frame finished and removes the model.
+    fn finish(&mut self, result: Result<(), String>) {
+        let (Some(renderer), Some((path, target))) = (&mut self.renderer, self.rendering.take())
+        else {
+            return;
+        };
+        let state = renderer.state_mut();
+        let thumbnail = match result {
+            Err(err) => Thumbnail::Failed(err),
+            Ok(()) if !state.errors.is_empty() => Thumbnail::Failed(state.errors.join("\n")),
+            Ok(()) => Thumbnail::Rendered {
+                view: target,
+                texture: None,
+            },
+        };
+        state.errors.clear();
+        while !state.viewer.renderer.models.is_empty() {
+            state.viewer.remove(0);
+        }
+        // the folder can change while the model loads
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.path == path) {
+            entry.thumbnail = thumbnail;
+        }
+    }
+
+    /// Makes the new thumbnails available to the UI, call before [`window`](Self::window).
+    pub fn register(&mut self, gui: &mut Gui) {
+        for texture in self.unused.drain(..) {
+            gui.unregister_user_image(texture);
+        }
+        for entry in &mut self.entries {
+            if let Thumbnail::Rendered {
+                view,
+                texture: texture @ None,
+            } = &mut entry.thumbnail
+            {
+                *texture =
+                    Some(gui.register_user_image_view(view.clone(), SamplerCreateInfo::default()));
+            }
+        }
+    }
+    /// Forgets the thumbnails of a dropped [`Gui`], they are registered again with the next one.
+    pub fn forget_textures(&mut self) {
+        self.unused.clear();
+        for entry in &mut self.entries {
+            if let Thumbnail::Rendered { texture, .. } = &mut entry.thumbnail {
+                *texture = None;
+            }
+        }
+    }
+
+    pub fn window(&mut self, ctx: &egui::Context) -> Option<GalleryAction> {
+        let mut action = None;
+        let mut open = self.open;
+        egui::Window::new("Gallery")
+            .open(&mut open)
+            .default_size([560.0, 420.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Open folder...").clicked() {
+                        action = Some(GalleryAction::PickFolder);
+                    }
+                    if let Some(dir) = &self.dir {
+                        ui.label(dir.display().to_string());
+                    }
+                });
+                let pending = self
+                    .entries
+                    .iter()
+                    .filter(|entry| matches!(entry.thumbnail, Thumbnail::Pending))
+                    .count();
+                if pending > 0 {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(format!("Rendering {pending} thumbnails"));
+                    });
+                }
+                if self.dir.is_some() && self.entries.is_empty() {
+                    ui.label("No glTF files in this folder");
+                }
+                let size = egui::Vec2::splat(THUMBNAIL_SIZE as f32);
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.horizontal_wrapped(|ui| {
+                        for entry in &self.entries {
+                            let name = entry
+                                .path
+                                .file_name()
+                                .map(|name| name.to_string_lossy().into_owned())
+                                .unwrap_or_default();
+                            let response = ui
+                                .vertical(|ui| {
+                                    ui.set_width(size.x);
+                                    let response = match &entry.thumbnail {
+                                        Thumbnail::Rendered {
+                                            texture: Some(texture),
+                                            ..
+                                        } => ui.add(egui::ImageButton::new(
+                                            egui::load::SizedTexture::new(*texture, size),
+                                        )),
+                                        Thumbnail::Failed(err) => ui
+                                            .add_sized(size, egui::Button::new("Failed"))
+                                            .on_hover_text(err),
+                                        _ => ui.add_sized(size, egui::Button::new("...")),
+                                    };
+                                    ui.add(egui::Label::new(name).truncate());
+                                    response
+                                })
+                                .inner;
+                            if response
+                                .on_hover_text(entry.path.display().to_string())
+                                .clicked()
+                            {
+                                action = Some(GalleryAction::Load(entry.path.clone()));
+                            }
+                        }
+                    });
+                });
+            });
+        self.open = open;
+        action
+    }
+}
+
+fn is_model(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+type Frame = FenceSignalFuture<CommandBufferExecFuture<NowFuture>>;
+
+/// Records a frame of `renderer` and submits it without waiting for it.
+fn submit(
+    renderer: &mut GltfViewerRenderer,
+    allocators: &Allocators,
+    queue: &Arc<Queue>,
+) -> Result<Frame, String> {
+    let mut builder = AutoCommandBufferBuilder::primary(
+        allocators.cmd.clone(),
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .map_err(|err| err.to_string())?;
+    renderer.render(&mut builder, 0);
+    builder
+        .build()
+        .map_err(|err| err.to_string())?
+        .execute(queue.clone())
+        .map_err(|err| err.to_string())?
+        .then_signal_fence_and_flush()
+        .map_err(|err| err.to_string())
+}
+
+fn thumbnail_target(allocators: &Allocators) -> Arc<ImageView> {
+    let image = Image::new(
+        allocators.memory.allocator(MemoryCategory::RenderTargets),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::R8G8B8A8_SRGB,
+            extent: [THUMBNAIL_SIZE, THUMBNAIL_SIZE, 1],
+            usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+    ImageView::new_default(image).unwrap()
+}
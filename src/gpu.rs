@@ -0,0 +1,174 @@
+use crate::settings::Settings;
+use std::sync::Arc;
+use vulkano::{
+    Version,
+    device::{
+        Device,
+        physical::{PhysicalDevice, PhysicalDeviceType},
+    },
+    memory::MemoryHeapFlags,
+};
+
+/// Lower is preferred: the device asked for on the command line or in the settings,
+/// otherwise discrete GPUs before integrated ones.
+pub fn priority(device: &PhysicalDevice, preference: Option<&str>) -> u32 {
+    if preference.is_some_and(|preference| matches(device, preference)) {
+        return 0;
+    }
+    match device.properties().device_type {
+        PhysicalDeviceType::DiscreteGpu => 1,
+        PhysicalDeviceType::IntegratedGpu => 2,
+        PhysicalDeviceType::VirtualGpu => 3,
+        PhysicalDeviceType::Cpu => 4,
+        _ => 5,
+    }
+}
+
+/// `preference` is the index in the device list or a part of the name, ignoring case.
+pub fn matches(device: &PhysicalDevice, preference: &str) -> bool {
+    if let Ok(index) = preference.parse::<usize>() {
+        return device
+            .instance()
+            .enumerate_physical_devices()
+            .ok()
+            .and_then(|mut devices| devices.nth(index))
+            .is_some_and(|other| std::ptr::eq(other.as_ref(), device));
+    }
+    device
+        .properties()
+        .device_name
+        .to_lowercase()
+        .contains(&preference.to_lowercase())
+}
+
+/// The GPU picked in the UI last time.
+pub fn saved_preference() -> Option<String> {
+    Settings::load().gpu
+}
+
+/// Description of the device for the GPU panel.
+pub struct GpuInfo {
+    pub name: String,
+    pub device_type: PhysicalDeviceType,
+    pub api_version: Version,
+    pub driver: String,
+    /// Size of the largest device local memory heap in bytes.
+    pub memory: u64,
+    /// Optional features of the viewer and whether the device has them enabled.
+    pub features: Vec<(&'static str, bool)>,
+    /// Names of every device of the instance, to switch to another one.
+    pub devices: Vec<String>,
+}
+impl GpuInfo {
+    pub fn new(device: &Arc<Device>) -> Self {
+        let physical = device.physical_device();
+        let properties = physical.properties();
+        let features = device.enabled_features();
+        let extensions = device.enabled_extensions();
+        let memory = physical
+            .memory_properties()
+            .memory_heaps
+            .iter()
+            .filter(|heap| heap.flags.intersects(MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .max()
+            .unwrap_or_default();
+        let devices = physical
+            .instance()
+            .enumerate_physical_devices()
+            .map(|devices| {
+                devices
+                    .map(|device| device.properties().device_name.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            name: properties.device_name.clone(),
+            device_type: properties.device_type,
+            api_version: physical.api_version(),
+            driver: driver_version(physical),
+            memory,
+            features: vec![
+                ("Ray tracing preview", extensions.khr_ray_tracing_pipeline),
+                ("Ray queries", extensions.khr_ray_query),
+                ("BC texture compression", features.texture_compression_bc),
+                ("Anisotropic filtering", features.sampler_anisotropy),
+                ("Large points", features.large_points),
+                ("Wireframe", features.fill_mode_non_solid),
+            ],
+            devices,
+        }
+    }
+
+    /// Returns the preference for another device if one was picked.
+    pub fn ui(&self, ui: &mut egui::Ui) -> Option<String> {
+        let mut picked = None;
+        let current = self.devices.iter().position(|name| *name == self.name);
+        egui::ComboBox::from_label("Device")
+            .selected_text(&self.name)
+            .show_ui(ui, |ui| {
+                for (i, name) in self.devices.iter().enumerate() {
+                    if ui.selectable_label(Some(i) == current, name).clicked() && Some(i) != current
+                    {
+                        picked = Some(i);
+                    }
+                }
+            })
+            .response
+            .on_hover_text("Switching reloads the scene on the new device");
+
+        egui::Grid::new("gpu_info").num_columns(2).show(ui, |ui| {
+            ui.label("Type");
+            ui.label(format!("{:?}", self.device_type));
+            ui.end_row();
+            ui.label("Vulkan");
+            ui.label(self.api_version.to_string());
+            ui.end_row();
+            ui.label("Driver");
+            ui.label(&self.driver);
+            ui.end_row();
+            ui.label("Memory");
+            ui.label(format!(
+                "{:.1} GiB",
+                self.memory as f64 / (1u64 << 30) as f64
+            ));
+            ui.end_row();
+            for (name, enabled) in &self.features {
+                ui.label(*name);
+                ui.label(if *enabled { "✔" } else { "✖" });
+                ui.end_row();
+            }
+        });
+        picked.map(|i| self.preference(i))
+    }
+    /// The name of the device, or its index if several devices have the same name.
+    fn preference(&self, index: usize) -> String {
+        let name = &self.devices[index];
+        if self.devices.iter().filter(|other| *other == name).count() > 1 {
+            index.to_string()
+        } else {
+            name.clone()
+        }
+    }
+}
+
+/// The driver name and version as the driver reports it, or decoded from the packed number.
+fn driver_version(device: &PhysicalDevice) -> String {
+    let properties = device.properties();
+    if let (Some(name), Some(info)) = (&properties.driver_name, &properties.driver_info) {
+        return format!("{name} {info}");
+    }
+    let version = properties.driver_version;
+    // NVIDIA packs 10.8.8.6 bits, most others use the Vulkan version layout
+    if properties.vendor_id == 0x10DE {
+        format!(
+            "{}.{}.{}",
+            version >> 22,
+            (version >> 14) & 0xff,
+            (version >> 6) & 0xff
+        )
+    } else {
+        Version::from(version).to_string()
+    }
+}
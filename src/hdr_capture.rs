@@ -0,0 +1,278 @@
+use crate::{
+    Allocators, GltfViewerRenderer, RenderTarget,
+    camera::Camera,
+    frameinfo::{Depth, Msaa},
+    memory::MemoryCategory,
+    screenshot::ScreenshotFormat,
+    skybox::quality::IblQuality,
+    undo::EditSnapshot,
+    viewer::{
+        grid::GridSettings, shadow::ShadowSettings, shadow_catcher::ShadowCatcherSettings,
+        ssao::SsaoSettings,
+    },
+    vktf::debug::DebugView,
+    workspace::Workspace,
+};
+use exr::prelude::f16;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use vulkano::{
+    DeviceSize,
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo,
+        PrimaryCommandBufferAbstract,
+    },
+    device::Queue,
+    format::{Format, FormatFeatures},
+    image::{Image, ImageCreateInfo, ImageType, ImageUsage, view::ImageView},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    sync::GpuFuture,
+};
+
+/// The settings of the viewport a capture is drawn with.
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureSettings {
+    pub debug_view: DebugView,
+    pub ibl_quality: IblQuality,
+    pub shadows: ShadowSettings,
+    pub ssao: SsaoSettings,
+    pub grid: GridSettings,
+    pub shadow_catcher: ShadowCatcherSettings,
+}
+
+/// What to capture and where to write it.
+pub struct HdrRequest {
+    /// The models, environment and camera of the viewport.
+    pub scene: Workspace,
+    /// The node and material edits of the models, applied once they are loaded.
+    pub edits: EditSnapshot,
+    pub settings: CaptureSettings,
+    pub extent: [u32; 2],
+    pub path: PathBuf,
+    pub format: ScreenshotFormat,
+}
+
+struct Capture {
+    path: PathBuf,
+    format: ScreenshotFormat,
+    image: Arc<Image>,
+    buffer: Subbuffer<[u8]>,
+    /// Taken when the models are loaded, the capture is drawn in the next frame.
+    edits: Option<EditSnapshot>,
+}
+
+/// Screenshots of the colour before tone mapping. The viewport is drawn in floating point by
+/// a second viewer that loads the scene again, without tone mapping, and written as OpenEXR.
+#[derive(Default)]
+pub struct HdrCapture {
+    /// Made for the format of the first capture in it.
+    renderer: Option<(Format, Box<GltfViewerRenderer>)>,
+    capture: Option<Capture>,
+}
+impl HdrCapture {
+    pub fn capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+    /// Starts loading the scene of `request`, a capture in progress is dropped.
+    pub fn start(&mut self, allocators: &Allocators, queue: &Arc<Queue>, request: HdrRequest) {
+        let format = target_format(queue, request.format);
+        let [width, height] = request.extent;
+        let image = Image::new(
+            allocators.memory.allocator(MemoryCategory::RenderTargets),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format,
+                extent: [width, height, 1],
+                usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+        let buffer = Buffer::new_slice::<u8>(
+            allocators.memory.allocator(MemoryCategory::RenderTargets),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            (width * height) as DeviceSize * format.block_size(),
+        )
+        .unwrap();
+        let view = ImageView::new_default(image.clone()).unwrap();
+
+        if self
+            .renderer
+            .as_ref()
+            .is_some_and(|(renderer_format, _)| *renderer_format != format)
+        {
+            self.renderer = None;
+        }
+        let (_, renderer) = self.renderer.get_or_insert_with(|| {
+            let mut renderer = GltfViewerRenderer::new(
+                allocators,
+                queue.clone(),
+                None,
+                RenderTarget {
+                    views: vec![view.clone()],
+                    msaa: Msaa::default(),
+                    depth: Depth::default(),
+                },
+                Camera::default(),
+            );
+            renderer.load_sky(Default::default());
+            (format, Box::new(renderer))
+        });
+        renderer.resize(&[view]);
+
+        let state = renderer.state_mut();
+        state.tone_mapping = false;
+        let settings = request.settings;
+        state.viewer.renderer.debug_view = settings.debug_view;
+        state.skybox.quality = settings.ibl_quality;
+        state.viewer.shadows.settings = settings.shadows;
+        state.viewer.ssao.settings = settings.ssao;
+        state.viewer.grid.settings = settings.grid;
+        state.viewer.shadow_catcher.settings = settings.shadow_catcher;
+        state.errors.clear();
+        state.open_workspace(request.scene);
+
+        self.capture = Some(Capture {
+            path: request.path,
+            format: request.format,
+            image,
+            buffer,
+            edits: Some(request.edits),
+        });
+    }
+
+    /// Draws the capture and writes it once the scene is loaded, call once per frame.
+    pub fn update(&mut self, allocators: &Allocators, queue: &Arc<Queue>) {
+        let (Some((_, renderer)), Some(capture)) = (&mut self.renderer, &mut self.capture) else {
+            return;
+        };
+
+        // drawn and copied every frame while loading
+        let mut builder = AutoCommandBufferBuilder::primary(
+            allocators.cmd.clone(),
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        renderer.render(&mut builder, 0);
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                capture.image.clone(),
+                capture.buffer.clone(),
+            ))
+            .unwrap();
+        let result = builder
+            .build()
+            .unwrap()
+            .execute(queue.clone())
+            .map_err(|err| err.to_string())
+            .and_then(|future| {
+                future
+                    .then_signal_fence_and_flush()
+                    .and_then(|fence| fence.wait(None))
+                    .map_err(|err| err.to_string())
+            });
+        let state = renderer.state_mut();
+        if state.loading() {
+            return;
+        }
+        if let Some(edits) = capture.edits.take() {
+            edits.apply(&mut state.viewer.renderer.models, &mut state.bookmarks);
+            return;
+        }
+
+        let Some(capture) = self.capture.take() else {
+            return;
+        };
+        if let Err(err) = result.and_then(|()| match state.errors.first() {
+            Some(error) => Err(error.clone()),
+            None => Ok(()),
+        }) {
+            log::error!("failed to capture {}: {err}", capture.path.display());
+            return;
+        }
+        let colors = read_colors(&capture.buffer, capture.image.format());
+        let [width, height, _] = capture.image.extent();
+        std::thread::spawn(move || {
+            match save(&capture.path, [width, height], &colors, capture.format) {
+                Ok(()) => log::info!("wrote {}", capture.path.display()),
+                Err(err) => log::error!(
+                    "failed to save screenshot {}: {err}",
+                    capture.path.display()
+                ),
+            }
+        });
+    }
+}
+
+/// 32 bit floats if they were asked for and can be blended, half floats otherwise.
+fn target_format(queue: &Queue, format: ScreenshotFormat) -> Format {
+    let blendable = queue
+        .device()
+        .physical_device()
+        .format_properties(Format::R32G32B32A32_SFLOAT)
+        .is_ok_and(|properties| {
+            properties
+                .optimal_tiling_features
+                .contains(FormatFeatures::COLOR_ATTACHMENT_BLEND)
+        });
+    match format {
+        ScreenshotFormat::ExrFloat if blendable => Format::R32G32B32A32_SFLOAT,
+        _ => Format::R16G16B16A16_SFLOAT,
+    }
+}
+
+/// The colours of the pixels of a 16 or 32 bit float RGBA image, without alpha.
+fn read_colors(buffer: &Subbuffer<[u8]>, format: Format) -> Vec<[f32; 3]> {
+    let bytes = buffer.read().unwrap();
+    match format {
+        Format::R32G32B32A32_SFLOAT => bytes
+            .chunks_exact(16)
+            .map(|pixel| {
+                std::array::from_fn(|c| {
+                    f32::from_ne_bytes(pixel[c * 4..c * 4 + 4].try_into().unwrap())
+                })
+            })
+            .collect(),
+        _ => bytes
+            .chunks_exact(8)
+            .map(|pixel| {
+                std::array::from_fn(|c| {
+                    f16::from_ne_bytes([pixel[c * 2], pixel[c * 2 + 1]]).to_f32()
+                })
+            })
+            .collect(),
+    }
+}
+
+fn save(
+    path: &Path,
+    [width, height]: [u32; 2],
+    colors: &[[f32; 3]],
+    format: ScreenshotFormat,
+) -> exr::error::UnitResult {
+    let (width, height) = (width as usize, height as usize);
+    let color = |x: usize, y: usize| colors[y * width + x];
+    match format {
+        ScreenshotFormat::ExrFloat => exr::prelude::write_rgb_file(path, width, height, |x, y| {
+            let [r, g, b] = color(x, y);
+            (r, g, b)
+        }),
+        _ => exr::prelude::write_rgb_file(path, width, height, |x, y| {
+            let [r, g, b] = color(x, y).map(f16::from_f32);
+            (r, g, b)
+        }),
+    }
+}
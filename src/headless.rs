@@ -0,0 +1,142 @@
+use crate::{
+    Allocators, GltfViewerRenderer, RenderTarget,
+    camera::Camera,
+    frameinfo::{Depth, Msaa},
+    gpu,
+};
+use std::{path::PathBuf, sync::Arc};
+use vulkano::{
+    DeviceSize,
+    buffer::{Buffer, BufferCreateInfo, BufferUsage},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo,
+        PrimaryCommandBufferAbstract,
+    },
+    device::{DeviceExtensions, DeviceFeatures},
+    format::Format,
+    image::{Image, ImageCreateInfo, ImageType, ImageUsage, view::ImageView},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    sync::GpuFuture,
+};
+use vulkano_util::context::{VulkanoConfig, VulkanoContext};
+
+pub struct HeadlessOptions {
+    pub model: Option<PathBuf>,
+    pub skybox: Option<PathBuf>,
+    pub output: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    /// Index or part of the name of the GPU to render on.
+    pub gpu: Option<String>,
+}
+
+/// Renders a single frame without creating a window and writes it to `options.output`.
+pub fn render_to_file(options: HeadlessOptions) -> anyhow::Result<()> {
+    let preference = options.gpu.clone();
+    let context = VulkanoContext::new(VulkanoConfig {
+        device_extensions: DeviceExtensions::empty(),
+        device_features: DeviceFeatures {
+            sampler_anisotropy: true,
+            ..Default::default()
+        },
+        print_device_name: true,
+        device_priority_fn: Arc::new(move |device| gpu::priority(device, preference.as_deref())),
+        ..Default::default()
+    });
+    let allocators = Allocators::new(context.device().clone(), context.memory_allocator().clone());
+    let queue = context.graphics_queue().clone();
+
+    let [width, height] = [options.width, options.height];
+    let max = context
+        .device()
+        .physical_device()
+        .properties()
+        .max_image_dimension2_d;
+    anyhow::ensure!(
+        (1..=max).contains(&width) && (1..=max).contains(&height),
+        "cannot render {width}x{height}, both sides must be between 1 and {max}"
+    );
+    let target = Image::new(
+        allocators.mem.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::R8G8B8A8_SRGB,
+            extent: [width, height, 1],
+            usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+    let mut renderer = GltfViewerRenderer::new(
+        &allocators,
+        queue.clone(),
+        Some(context.compute_queue().clone()),
+        RenderTarget {
+            views: vec![ImageView::new_default(target.clone()).unwrap()],
+            msaa: Msaa::default(),
+            depth: Depth::default(),
+        },
+        Camera::default(),
+    );
+    if let Some(path) = options.skybox {
+        renderer.load_skybox(path);
+    } else {
+        renderer.load_sky(Default::default());
+    }
+    if let Some(path) = options.model {
+        renderer.load_model(path);
+    }
+    renderer.wait_for_loads();
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        allocators.cmd.clone(),
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+    renderer.render(&mut builder, 0);
+    if let Some(error) = renderer.errors().first() {
+        anyhow::bail!("{error}");
+    }
+
+    let output = Buffer::new_slice::<u8>(
+        allocators.mem.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        width as DeviceSize * height as DeviceSize * 4,
+    )
+    .unwrap();
+    builder
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(target, output.clone()))
+        .unwrap();
+
+    builder
+        .build()
+        .unwrap()
+        .execute(queue)
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+
+    let pixels = output.read().unwrap();
+    image::save_buffer(
+        &options.output,
+        &pixels,
+        width,
+        height,
+        image::ColorType::Rgba8,
+    )?;
+    log::info!("wrote {}", options.output.display());
+
+    Ok(())
+}
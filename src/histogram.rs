@@ -0,0 +1,281 @@
+use crate::{Allocators, memory::MemoryCategory};
+use std::sync::Arc;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    image::{
+        sampler::{Sampler, SamplerCreateInfo},
+        view::ImageView,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::{
+        ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo, compute::ComputePipelineCreateInfo,
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+    },
+};
+
+/// Bins of the histogram, the same as `BINS` in the shader.
+const BINS: usize = 64;
+/// Exposure range of the bins relative to middle grey, the same as in the shader.
+/// Darker and brighter pixels are counted in the first and last bin. Above about 4 EV the
+/// tone mapped 8 bit colours are too far apart to undo the tone mapping.
+const EV_MIN: f32 = -8.0;
+const EV_MAX: f32 = 4.0;
+
+/// A luminance histogram of the rendered scene, built on the GPU every frame while it is shown.
+///
+/// The scene is stored tone mapped, so the luminance is estimated by undoing the tone mapping,
+/// which clips about 4 EV above middle grey, where the bins end. The false colour debug view
+/// shows the exposure before tone mapping.
+pub struct Histogram {
+    pipeline: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+    allocators: Allocators,
+    /// One per frame in flight, read when the frame is recorded again.
+    buffers: Vec<Subbuffer<[u32]>>,
+    /// Whether the buffer of a frame was written since it was last read.
+    written: Vec<bool>,
+    /// The last read counts.
+    bins: [u32; BINS],
+    /// Shows the window and forces the scene to be drawn offscreen.
+    pub enabled: bool,
+}
+impl Histogram {
+    pub fn new(allocators: &Allocators, num_frames: usize) -> Self {
+        let device = allocators.mem.device().clone();
+        let cs = cs::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+        let stage = PipelineShaderStageCreateInfo::new(cs);
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+        let pipeline = ComputePipeline::new(
+            device.clone(),
+            None,
+            ComputePipelineCreateInfo::stage_layout(stage, layout),
+        )
+        .unwrap();
+        let sampler = Sampler::new(device, SamplerCreateInfo::default()).unwrap();
+        let buffers = (0..num_frames)
+            .map(|_| {
+                Buffer::new_slice(
+                    allocators.memory.allocator(MemoryCategory::RenderTargets),
+                    BufferCreateInfo {
+                        usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                            | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                        ..Default::default()
+                    },
+                    BINS as u64,
+                )
+                .unwrap()
+            })
+            .collect();
+        Self {
+            pipeline,
+            sampler,
+            allocators: allocators.clone(),
+            buffers,
+            written: vec![false; num_frames],
+            bins: [0; BINS],
+            enabled: false,
+        }
+    }
+
+    /// Reads the counts of the last frame that used `index` and counts the pixels of `scene`,
+    /// after it is finished.
+    pub fn record<L>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        index: usize,
+        scene: &Arc<ImageView>,
+    ) {
+        let buffer = &self.buffers[index];
+        if self.written[index] {
+            // the frame has finished before it is recorded again
+            if let Ok(bins) = buffer.read() {
+                self.bins.copy_from_slice(&bins);
+            }
+        }
+        let set = DescriptorSet::new(
+            self.allocators.set.clone(),
+            self.pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view_sampler(0, scene.clone(), self.sampler.clone()),
+                WriteDescriptorSet::buffer(1, buffer.clone()),
+            ],
+            [],
+        )
+        .unwrap();
+        builder
+            .fill_buffer(buffer.clone(), 0)
+            .unwrap()
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .unwrap();
+        let [width, height, _] = scene.image().extent();
+        unsafe { builder.dispatch([width.div_ceil(16), height.div_ceil(16), 1]) }.unwrap();
+        self.written[index] = true;
+    }
+
+    pub fn window(&mut self, ctx: &egui::Context) {
+        let mut open = self.enabled;
+        egui::Window::new("Exposure")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                self.graph(ui);
+                let total = self.bins.iter().map(|&n| n as u64).sum::<u64>().max(1) as f32;
+                let percent = |n: u32| format!("{:.1}%", n as f32 / total * 100.0);
+                egui::Grid::new("exposure").num_columns(2).show(ui, |ui| {
+                    ui.label("Median");
+                    ui.label(match self.median() {
+                        Some(ev) => format!("{ev:+.1} EV"),
+                        None => "-".to_owned(),
+                    });
+                    ui.end_row();
+                    ui.label("Crushed")
+                        .on_hover_text(format!("Darker than {EV_MIN:+} EV"));
+                    ui.label(percent(self.bins[0]));
+                    ui.end_row();
+                    ui.label("Clipped").on_hover_text(format!(
+                        "Brighter than {:+.1} EV, where the tone mapping can't be undone",
+                        EV_MAX - bin_width()
+                    ));
+                    ui.label(percent(self.bins[BINS - 1]));
+                    ui.end_row();
+                });
+                ui.weak("EV relative to middle grey, estimated from the tone mapped image");
+            });
+        self.enabled = open;
+    }
+    /// The bins as bars, from dark on the left to bright on the right.
+    fn graph(&self, ui: &mut egui::Ui) {
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(256.0, 80.0), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+        let max = self.bins.iter().copied().max().unwrap_or(0).max(1) as f32;
+        let width = rect.width() / BINS as f32;
+        for (i, &count) in self.bins.iter().enumerate() {
+            let height = count as f32 / max * rect.height();
+            let left = rect.left() + i as f32 * width;
+            painter.rect_filled(
+                egui::Rect::from_min_max(
+                    egui::pos2(left, rect.bottom() - height),
+                    egui::pos2(left + width, rect.bottom()),
+                ),
+                0.0,
+                ui.visuals().text_color(),
+            );
+        }
+        // a line every 4 EV, middle grey stronger
+        for ev in (EV_MIN as i32..EV_MAX as i32).step_by(4) {
+            let x = rect.left() + (ev as f32 - EV_MIN) / (EV_MAX - EV_MIN) * rect.width();
+            let color = if ev == 0 {
+                egui::Color32::ORANGE
+            } else {
+                ui.visuals().weak_text_color()
+            };
+            painter.vline(x, rect.y_range(), egui::Stroke::new(1.0, color));
+            painter.text(
+                egui::pos2(x + 2.0, rect.top() + 2.0),
+                egui::Align2::LEFT_TOP,
+                format!("{ev:+}"),
+                egui::FontId::monospace(10.0),
+                ui.visuals().weak_text_color(),
+            );
+        }
+    }
+    /// Exposure of the middle of the bin holding the median pixel.
+    fn median(&self) -> Option<f32> {
+        let total: u64 = self.bins.iter().map(|&n| n as u64).sum();
+        let mut counted = 0;
+        self.bins
+            .iter()
+            .position(|&n| {
+                counted += n as u64;
+                total > 0 && counted * 2 >= total
+            })
+            .map(|bin| EV_MIN + (bin as f32 + 0.5) * bin_width())
+    }
+}
+
+fn bin_width() -> f32 {
+    (EV_MAX - EV_MIN) / BINS as f32
+}
+
+mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+#version 450
+
+#define BINS 64
+#define EV_MIN -8.0
+#define EV_MAX 4.0
+#define MIDDLE_GREY 0.18
+
+layout(local_size_x = 16, local_size_y = 16) in;
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+layout(set = 0, binding = 1) buffer Bins {
+    uint bins[BINS];
+};
+
+shared uint local_bins[BINS];
+
+// the inverse of the compression of the PBR Neutral tone mapping in gltf.frag,
+// ignoring its offset of the shadows and the desaturation of the highlights
+vec3 untone_map(vec3 color) {
+    const float start = 0.8 - 0.04;
+    const float d = 1.0 - start;
+    float peak = max(color.r, max(color.g, color.b));
+    if (peak <= start) {
+        return color;
+    }
+    peak = min(peak, 0.999);
+    float original = d * d / (1.0 - peak) - d + start;
+    return color * original / peak;
+}
+
+void main() {
+    if (gl_LocalInvocationIndex < BINS) {
+        local_bins[gl_LocalInvocationIndex] = 0;
+    }
+    barrier();
+
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    if (all(lessThan(pixel, textureSize(scene, 0)))) {
+        vec3 color = untone_map(texelFetch(scene, pixel, 0).rgb);
+        float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+        float ev = log2(max(luminance, 1e-10) / MIDDLE_GREY);
+        int bin = clamp(int(floor((ev - EV_MIN) / (EV_MAX - EV_MIN) * BINS)), 0, BINS - 1);
+        atomicAdd(local_bins[bin], 1u);
+    }
+    barrier();
+
+    if (gl_LocalInvocationIndex < BINS) {
+        atomicAdd(bins[gl_LocalInvocationIndex], local_bins[gl_LocalInvocationIndex]);
+    }
+}
+        "#
+    }
+}
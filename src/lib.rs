@@ -1,12 +1,53 @@
-use camera::OrbitCamera;
+use acceleration::SceneAcceleration;
+use batch::BatchExport;
+use camera::{Bookmarks, Camera, CameraMode, CameraPath, ViewPreset};
+use collision::Collision;
+use color::OutputTransform;
+use compare::{Compare, split_image_scissors, split_scissors};
+use console::{Console, ScriptCommand};
+use cubemap::renderer::{create_cubemap_image, face_views};
 use egui_file::FileDialog;
-use egui_winit_vulkano::CallbackFn;
+use egui_winit_vulkano::{CallbackFn, Gui};
+use frameinfo::{Depth, Msaa};
+use gallery::{Gallery, GalleryAction};
+use gpu::GpuInfo;
+use hdr_capture::{CaptureSettings, HdrCapture, HdrRequest};
+use histogram::Histogram;
+use material_presets::MaterialPresets;
+use memory::{MemoryCategory, MemoryTracker};
 use nalgebra_glm as glm;
+use pathtracer::PathTracer;
+use post::PostChain;
+use preferences::UiPreferences;
+use raytracer::{Raytracer, RenderMode};
+use screenshot::ScreenshotFormat;
 use set_layouts::SetLayouts;
-use skybox::Skybox;
-use std::{env::current_dir, path::PathBuf, sync::Arc};
-use viewer::Viewer;
-use vktf::material::MaterialPush;
+use settings::Settings;
+use shortcuts::{Action, Keymap};
+use skybox::{
+    Skybox, SkyboxSource, export::export_environment, loader::gen_mipmaps, probe::ProbeTarget,
+    renderer::SkyboxRenderer, sky::SkyPreset,
+};
+use stats::{DrawStats, Stats};
+use std::{collections::VecDeque, env::current_dir, f32::consts::PI, path::PathBuf, sync::Arc};
+use texture_inspector::TextureInspector;
+use undo::{EditSnapshot, UndoStack};
+use uv_layout::UvLayout;
+use viewer::{
+    Viewer, debug_geometry::DebugGeometry, grid::Grid, heatmap::HeatmapFrame,
+    occlusion::OcclusionMode, points::Points, renderer::ViewerRenderer, shadow::light_view_proj,
+    shadow_catcher::ShadowCatcher, transmission::Transmission,
+};
+use vktf::{
+    GltfRenderInfo,
+    export::export_glb,
+    light::{Light, LightsUniform},
+    loader::VktfDocument,
+    material::{MaterialPush, TextureSlot},
+    morph::Morph,
+    report::ModelReport,
+    scene::SceneGraph,
+};
 use vulkano::{
     buffer::{
         Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer,
@@ -14,33 +55,97 @@ use vulkano::{
     },
     command_buffer::{
         AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferInfo, PrimaryCommandBufferAbstract,
-        allocator::StandardCommandBufferAllocator,
+        allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo},
     },
     descriptor_set::{
         DescriptorSet, WriteDescriptorSet, allocator::StandardDescriptorSetAllocator,
         layout::DescriptorSetLayout,
     },
-    device::{DeviceOwned, Queue},
+    device::{Device, DeviceOwned, Queue},
+    image::Image,
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
     pipeline::{Pipeline, PipelineBindPoint},
     render_pass::Subpass,
     sync::GpuFuture,
 };
+use workspace::{PendingWorkspace, UiLayout, Workspace, WorkspaceEnvironment, WorkspaceModel};
 
-mod camera;
+mod acceleration;
+mod batch;
+pub mod camera;
+mod collision;
+pub mod color;
+mod compare;
+mod console;
 mod cubemap;
+pub mod frameinfo;
+mod gallery;
+pub mod gpu;
+mod hdr_capture;
+pub mod headless;
+mod histogram;
+mod material_presets;
+pub mod memory;
+mod metadata;
+mod pathtracer;
+mod post;
+mod preferences;
+mod progress;
 mod vktf;
 
-// mod raytracer;
+mod raytracer;
+pub mod recovery;
+pub mod remote_control;
+pub mod renderer;
+pub mod screenshot;
 mod set_layouts;
+mod settings;
+mod shortcuts;
+mod skeleton;
 mod skybox;
+mod stats;
+mod texture_inspector;
+mod undo;
+mod uv_layout;
 mod viewer;
+mod workspace;
+
+pub use renderer::{GltfViewerRenderer, RenderTarget};
+pub use skybox::sky::SkyPreset;
+pub use vktf::bounds::Aabb;
 
 #[derive(Clone)]
 pub struct Allocators {
     pub cmd: Arc<StandardCommandBufferAllocator>,
     pub mem: Arc<StandardMemoryAllocator>,
     pub set: Arc<StandardDescriptorSetAllocator>,
+    /// Per category allocators on top of `mem` that track how much memory is used.
+    pub memory: MemoryTracker,
+}
+impl Allocators {
+    pub fn new(device: Arc<Device>, mem: Arc<StandardMemoryAllocator>) -> Self {
+        let cmd = Arc::new(StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo {
+                primary_buffer_count: 16,
+                secondary_buffer_count: 16,
+                ..Default::default()
+            },
+        ));
+        let set = Arc::new(StandardDescriptorSetAllocator::new(
+            device,
+            Default::default(),
+        ));
+
+        let memory = MemoryTracker::new(mem.clone());
+
+        Self {
+            cmd,
+            mem,
+            set,
+            memory,
+        }
+    }
 }
 
 #[repr(C)]
@@ -51,11 +156,20 @@ pub struct CameraUniform {
     view_inv: glm::Mat4,
 }
 impl CameraUniform {
-    pub fn new(camera: &OrbitCamera, aspect: f32) -> Self {
+    /// `jitter` moves the image by a part of a pixel, in normalized device coordinates.
+    pub fn new(camera: &Camera, aspect: f32, depth: Depth, jitter: glm::Vec2) -> Self {
+        let mut proj = camera.projection(aspect, depth.reverse_z);
+        // scaled by w so perspective and orthographic projections move the same
+        let w = proj.row(3).clone_owned();
+        proj.set_row(0, &(proj.row(0) + w * jitter.x));
+        proj.set_row(1, &(proj.row(1) + w * jitter.y));
+        Self::from_view(camera.look_at(), proj)
+    }
+    pub fn from_view(view: glm::Mat4, proj: glm::Mat4) -> Self {
         Self {
-            view: camera.look_at(),
-            proj: camera.perspective(aspect),
-            view_inv: camera.look_at().try_inverse().unwrap(),
+            view,
+            proj,
+            view_inv: view.try_inverse().unwrap(),
         }
     }
 }
@@ -64,6 +178,24 @@ impl CameraUniform {
 pub enum FilePicker {
     Skybox(FileDialog),
     Gltf(FileDialog),
+    Render(FileDialog),
+    /// Saves the model at the index as .glb, with its animated pose baked if set.
+    Export(FileDialog, usize, bool),
+    /// Writes a JSON summary of the model at the index.
+    Report(FileDialog, usize),
+    Environment(FileDialog),
+    SaveCameraPath(FileDialog),
+    LoadCameraPath(FileDialog),
+    /// Picks the folder shown by the gallery.
+    Gallery(FileDialog),
+    SaveWorkspace(FileDialog),
+    OpenWorkspace(FileDialog),
+    /// Picks the models a thumbnail is exported of.
+    BatchModels(FileDialog),
+    /// Picks the folder the thumbnails of the models are written to.
+    BatchOutput(FileDialog, Vec<PathBuf>),
+    /// Replaces a texture of a material of the model at the index, `None` for the default material.
+    Texture(FileDialog, usize, Option<usize>, TextureSlot),
     #[default]
     None,
 }
@@ -83,11 +215,11 @@ impl FilePicker {
         *self = Self::Skybox(file_picker)
     }
     pub fn gltf(&mut self) {
-        let extensions = ["glb", "gltf"];
+        let extensions = ["glb", "gltf", "obj", "stl", "ply"];
         let mut file_picker = FileDialog::open_file(self.initial_path())
             .show_rename(false)
             .show_new_folder(false)
-            .multi_select(false)
+            .multi_select(true)
             .show_files_filter(Box::new(move |path| {
                 path.extension()
                     .and_then(|ext| ext.to_str())
@@ -96,24 +228,176 @@ impl FilePicker {
         file_picker.open();
         *self = Self::Gltf(file_picker)
     }
+    pub fn texture(&mut self, model: usize, material: Option<usize>, slot: TextureSlot) {
+        let extensions = ["png", "jpg", "jpeg", "tga", "bmp", "webp", "avif"];
+        let mut file_picker = FileDialog::open_file(self.initial_path())
+            .show_rename(false)
+            .show_new_folder(false)
+            .multi_select(false)
+            .show_files_filter(Box::new(move |path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| extensions.contains(&ext))
+            }));
+        file_picker.open();
+        *self = Self::Texture(file_picker, model, material, slot)
+    }
+    pub fn render(&mut self) {
+        let mut file_picker = FileDialog::save_file(self.initial_path())
+            .default_filename("render.png")
+            .show_files_filter(Box::new(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ["png", "exr"].contains(&ext))
+            }));
+        file_picker.open();
+        *self = Self::Render(file_picker)
+    }
+    pub fn export(&mut self, model: usize, name: &str, bake_pose: bool) {
+        let name = if bake_pose {
+            format!("{name}_pose.glb")
+        } else {
+            format!("{name}.glb")
+        };
+        let mut file_picker = FileDialog::save_file(self.initial_path())
+            .default_filename(name)
+            .show_files_filter(Box::new(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext == "glb")
+            }));
+        file_picker.open();
+        *self = Self::Export(file_picker, model, bake_pose)
+    }
+    pub fn report(&mut self, model: usize, name: &str) {
+        let mut file_picker = FileDialog::save_file(self.initial_path())
+            .default_filename(format!("{name}.json"))
+            .show_files_filter(Box::new(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext == "json")
+            }));
+        file_picker.open();
+        *self = Self::Report(file_picker, model)
+    }
+    pub fn environment(&mut self) {
+        let mut file_picker = FileDialog::save_file(self.initial_path())
+            .default_filename("environment.ktx2")
+            .show_files_filter(Box::new(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext == "ktx2")
+            }));
+        file_picker.open();
+        *self = Self::Environment(file_picker)
+    }
+    pub fn save_camera_path(&mut self) {
+        let mut file_picker = FileDialog::save_file(self.initial_path())
+            .default_filename("camera_path.json")
+            .show_files_filter(Box::new(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext == "json")
+            }));
+        file_picker.open();
+        *self = Self::SaveCameraPath(file_picker)
+    }
+    pub fn load_camera_path(&mut self) {
+        let mut file_picker = FileDialog::open_file(self.initial_path())
+            .show_rename(false)
+            .show_new_folder(false)
+            .multi_select(false)
+            .show_files_filter(Box::new(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext == "json")
+            }));
+        file_picker.open();
+        *self = Self::LoadCameraPath(file_picker)
+    }
+    pub fn gallery(&mut self) {
+        let mut file_picker = FileDialog::select_folder(self.initial_path())
+            .show_rename(false)
+            .show_new_folder(false);
+        file_picker.open();
+        *self = Self::Gallery(file_picker)
+    }
+    pub fn save_workspace(&mut self) {
+        let mut file_picker = FileDialog::save_file(self.initial_path())
+            .default_filename("workspace.json")
+            .show_files_filter(Box::new(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext == "json")
+            }));
+        file_picker.open();
+        *self = Self::SaveWorkspace(file_picker)
+    }
+    pub fn open_workspace(&mut self) {
+        let mut file_picker = FileDialog::open_file(self.initial_path())
+            .show_rename(false)
+            .show_new_folder(false)
+            .multi_select(false)
+            .show_files_filter(Box::new(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext == "json")
+            }));
+        file_picker.open();
+        *self = Self::OpenWorkspace(file_picker)
+    }
+    pub fn batch_models(&mut self) {
+        let extensions = ["glb", "gltf", "obj", "stl", "ply"];
+        let mut file_picker = FileDialog::open_file(self.initial_path())
+            .title("Export thumbnails of")
+            .show_rename(false)
+            .show_new_folder(false)
+            .multi_select(true)
+            .show_files_filter(Box::new(move |path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| extensions.contains(&ext))
+            }));
+        file_picker.open();
+        *self = Self::BatchModels(file_picker)
+    }
+    pub fn batch_output(&mut self, models: Vec<PathBuf>) {
+        let mut file_picker =
+            FileDialog::select_folder(self.initial_path()).title("Write the thumbnails to");
+        file_picker.open();
+        *self = Self::BatchOutput(file_picker, models)
+    }
     fn initial_path(&self) -> Option<PathBuf> {
         match self {
             FilePicker::Skybox(file_dialog) => Some(file_dialog.directory().to_owned()),
             FilePicker::Gltf(file_dialog) => Some(file_dialog.directory().to_owned()),
+            FilePicker::Render(file_dialog) => Some(file_dialog.directory().to_owned()),
+            FilePicker::Export(file_dialog, ..) => Some(file_dialog.directory().to_owned()),
+            FilePicker::Report(file_dialog, _) => Some(file_dialog.directory().to_owned()),
+            FilePicker::Environment(file_dialog) => Some(file_dialog.directory().to_owned()),
+            FilePicker::SaveCameraPath(file_dialog) => Some(file_dialog.directory().to_owned()),
+            FilePicker::LoadCameraPath(file_dialog) => Some(file_dialog.directory().to_owned()),
+            FilePicker::Gallery(file_dialog) => Some(file_dialog.directory().to_owned()),
+            FilePicker::SaveWorkspace(file_dialog) => Some(file_dialog.directory().to_owned()),
+            FilePicker::OpenWorkspace(file_dialog) => Some(file_dialog.directory().to_owned()),
+            FilePicker::BatchModels(file_dialog) => Some(file_dialog.directory().to_owned()),
+            FilePicker::BatchOutput(file_dialog, _) => Some(file_dialog.directory().to_owned()),
+            FilePicker::Texture(file_dialog, ..) => Some(file_dialog.directory().to_owned()),
             FilePicker::None => current_dir().ok(),
         }
     }
 }
 
-struct CameraResource {
-    buffer: Subbuffer<CameraUniform>,
+struct UniformResource<T> {
+    buffer: Subbuffer<T>,
     set: Arc<DescriptorSet>,
 }
-impl CameraResource {
+impl<T: BufferContents> UniformResource<T> {
     pub fn new(
         mem_allocator: Arc<StandardMemoryAllocator>,
         set_allocator: Arc<StandardDescriptorSetAllocator>,
         layout: Arc<DescriptorSetLayout>,
+        writes: impl IntoIterator<Item = WriteDescriptorSet>,
     ) -> Self {
         let buffer = Buffer::new_sized(
             mem_allocator.clone(),
@@ -127,37 +411,181 @@ impl CameraResource {
         let set = DescriptorSet::new(
             set_allocator.clone(),
             layout,
-            [WriteDescriptorSet::buffer(0, buffer.clone())],
+            [WriteDescriptorSet::buffer(0, buffer.clone())]
+                .into_iter()
+                .chain(writes),
             [],
         )
         .unwrap();
 
         Self { buffer, set }
     }
+    pub fn upload<L>(
+        &self,
+        allocator: &SubbufferAllocator,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        data: T,
+    ) {
+        let buffer = allocator.allocate_sized().unwrap();
+        *buffer.write().unwrap() = data;
+        builder
+            .copy_buffer(CopyBufferInfo::buffers(buffer, self.buffer.clone()))
+            .unwrap();
+    }
+}
+
+fn lights_resources(
+    allocators: &Allocators,
+    layout: &Arc<DescriptorSetLayout>,
+    viewer: &Viewer,
+    num_frames: usize,
+) -> Vec<UniformResource<LightsUniform>> {
+    (0..num_frames)
+        .map(|i| {
+            UniformResource::new(
+                allocators.mem.clone(),
+                allocators.set.clone(),
+                layout.clone(),
+                [
+                    viewer.shadows.write(1, i),
+                    viewer.transmission.write(2, i),
+                    viewer.occlusion.write(3, i),
+                    viewer.ssao.write(4, i),
+                ],
+            )
+        })
+        .collect()
+}
+/// Lights sets used while rendering the transmission source, which can't sample itself.
+fn opaque_lights_sets(
+    allocators: &Allocators,
+    layout: &Arc<DescriptorSetLayout>,
+    viewer: &Viewer,
+    lights: &[UniformResource<LightsUniform>],
+) -> Vec<Arc<DescriptorSet>> {
+    lights
+        .iter()
+        .enumerate()
+        .map(|(i, resource)| {
+            DescriptorSet::new(
+                allocators.set.clone(),
+                layout.clone(),
+                [
+                    WriteDescriptorSet::buffer(0, resource.buffer.clone()),
+                    viewer.shadows.write(1, i),
+                    viewer.transmission.write_empty(2),
+                    viewer.occlusion.write(3, i),
+                    viewer.ssao.write(4, i),
+                ],
+                [],
+            )
+            .unwrap()
+        })
+        .collect()
 }
 
+/// Headers of the settings panel whose open state is kept between runs.
+const SETTINGS_SECTIONS: [&str; 13] = [
+    "Camera",
+    "Environment",
+    "Compare",
+    "Debug",
+    "Shortcuts",
+    "Interface",
+    "Textures",
+    "Shadows",
+    "Ambient occlusion",
+    "Hybrid ray tracing",
+    "Beauty render",
+    "GPU",
+    "Stats",
+];
+
 pub struct State {
     queue: Arc<Queue>,
+    allocators: Allocators,
+    set_layouts: SetLayouts,
     subbuffer_allocator: SubbufferAllocator,
 
-    camera: OrbitCamera,
-    cameras: Vec<CameraResource>,
+    camera: Camera,
+    /// Keeps the fly camera out of the models.
+    collision: Collision,
+    cameras: Vec<UniformResource<CameraUniform>>,
+    lights: Vec<UniformResource<LightsUniform>>,
+    opaque_lights: Vec<Arc<DescriptorSet>>,
 
     aspect: f32,
 
     skybox: Skybox,
     viewer: Viewer,
-    // pub raytracer: Raytracer,
+    /// Only available if the device supports acceleration structures.
+    acceleration: Option<SceneAcceleration>,
+    /// Only available if the device supports ray tracing.
+    raytracer: Option<Raytracer>,
+    /// Only available if the device supports ray queries.
+    pathtracer: Option<PathTracer>,
+    render_mode: RenderMode,
+    post: PostChain,
+    /// The viewport shows the output of `post`, the scene is drawn in [`update`](Self::update).
+    post_process: bool,
+    /// Requested anti-aliasing, the window applies it with [`set_subpass`](Self::set_subpass).
+    msaa: Msaa,
+    /// Requested depth buffer, applied together with the anti-aliasing.
+    depth: Depth,
+    /// The depth the pipelines were made for, the projection has to match it.
+    render_depth: Depth,
     file_picker: FilePicker,
+    /// Failed loads and recovered GPU errors waiting to be dismissed.
+    errors: Vec<String>,
+    settings: Settings,
+    stats: Stats,
+    histogram: Histogram,
+    texture_inspector: TextureInspector,
+    uv_layout: UvLayout,
+    gallery: Gallery,
+    batch: BatchExport,
+    /// Models loaded one after another once the current load is done.
+    queued_models: VecDeque<PathBuf>,
+    /// The UI asked for another window showing the same scene.
+    new_window: bool,
+    /// Off while the models of another window are loaded so its camera is kept.
+    frame_new_models: bool,
+    compare: Compare,
+    bookmarks: Bookmarks,
+    material_presets: MaterialPresets,
+    undo: UndoStack,
+    /// What an opened workspace still has to restore.
+    workspace: PendingWorkspace,
+    camera_path: CameraPath,
+    keymap: Keymap,
+    preferences: UiPreferences,
+    /// The viewport in physical pixels, offset and extent.
+    viewport: ([u32; 2], [u32; 2]),
+    /// A screenshot of the viewport was requested.
+    screenshot: bool,
+    /// Where the requested screenshot is written, the pictures directory if `None`.
+    screenshot_path: Option<PathBuf>,
+    screenshot_format: ScreenshotFormat,
+    hdr_capture: HdrCapture,
+    /// Off while an HDR capture is drawn.
+    tone_mapping: bool,
+    gpu: GpuInfo,
+    /// The UI picked another device, the window recreates everything on it.
+    gpu_switch: Option<String>,
+    console: Console,
 }
 impl State {
     pub fn new(
         allocators: &Allocators,
         queue: Arc<Queue>,
+        compute_queue: Arc<Queue>,
         num_frames: usize,
         subpass: Subpass,
+        msaa: Msaa,
+        depth: Depth,
     ) -> Self {
-        let camera = OrbitCamera::default();
+        let camera = Camera::default();
+        let stats = Stats::new(&queue, num_frames);
 
         let subbuffer_allocator = SubbufferAllocator::new(
             allocators.mem.clone(),
@@ -173,10 +601,11 @@ impl State {
 
         let cameras = (0..num_frames)
             .map(|_| {
-                CameraResource::new(
+                UniformResource::new(
                     allocators.mem.clone(),
                     allocators.set.clone(),
                     set_layouts.camera.clone(),
+                    [],
                 )
             })
             .collect();
@@ -188,8 +617,31 @@ impl State {
         )
         .unwrap();
 
-        let skybox = Skybox::new(allocators, &mut builder, &set_layouts, subpass.clone());
-        let viewer = Viewer::new(allocators, &mut builder, &set_layouts, subpass);
+        let skybox = Skybox::new(
+            allocators,
+            &mut builder,
+            &set_layouts,
+            subpass.clone(),
+            depth,
+            &queue,
+            compute_queue.clone(),
+        );
+        let post = PostChain::new(allocators, subpass.clone(), msaa, depth, num_frames);
+        let mut viewer = Viewer::new(
+            allocators,
+            &mut builder,
+            &set_layouts,
+            subpass,
+            depth,
+            num_frames,
+        );
+        // the context only creates a separate compute queue if the device has a dedicated
+        // family for it, which can do transfers as well
+        if compute_queue.queue_family_index() != queue.queue_family_index() {
+            viewer.loader.transfer_queue = Some(compute_queue);
+        }
+        let lights = lights_resources(allocators, &set_layouts.lights, &viewer, num_frames);
+        let opaque_lights = opaque_lights_sets(allocators, &set_layouts.lights, &viewer, &lights);
 
         builder
             .build()
@@ -201,223 +653,1916 @@ impl State {
             .wait(None)
             .unwrap();
 
-        // let raytracer = Raytracer::new(queue.device(), allocators.clone());
+        let extensions = queue.device().enabled_extensions();
+        let acceleration = extensions
+            .khr_acceleration_structure
+            .then(|| SceneAcceleration::new(allocators.clone()));
+        let raytracer = extensions
+            .khr_ray_tracing_pipeline
+            .then(|| Raytracer::new(queue.device(), allocators.clone(), num_frames));
+        let pathtracer = extensions
+            .khr_ray_query
+            .then(|| PathTracer::new(allocators));
 
         Self {
             camera,
+            collision: Collision::default(),
             subbuffer_allocator,
             aspect: 1.0,
             skybox,
             file_picker: FilePicker::default(),
+            errors: vec![],
+            settings: Settings::default(),
             queue,
+            allocators: allocators.clone(),
+            set_layouts,
             cameras,
+            lights,
+            opaque_lights,
             viewer,
-            // raytracer,
+            acceleration,
+            raytracer,
+            pathtracer,
+            render_mode: RenderMode::default(),
+            post,
+            post_process: false,
+            msaa,
+            depth,
+            render_depth: depth,
+            stats,
+            histogram: Histogram::new(allocators, num_frames),
+            texture_inspector: TextureInspector::default(),
+            uv_layout: UvLayout::default(),
+            gallery: Gallery::default(),
+            batch: BatchExport::default(),
+            queued_models: VecDeque::new(),
+            new_window: false,
+            frame_new_models: true,
+            compare: Compare::default(),
+            bookmarks: Bookmarks::default(),
+            material_presets: MaterialPresets::default(),
+            undo: UndoStack::default(),
+            workspace: PendingWorkspace::default(),
+            camera_path: CameraPath::default(),
+            keymap: Keymap::default(),
+            preferences: UiPreferences::default(),
+            viewport: ([0, 0], [0, 0]),
+            screenshot: false,
+            screenshot_path: None,
+            screenshot_format: ScreenshotFormat::default(),
+            hdr_capture: HdrCapture::default(),
+            tone_mapping: true,
+            gpu: GpuInfo::new(queue.device()),
+            gpu_switch: None,
+            console: Console::default(),
         }
     }
     pub fn update<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>, index: usize) {
-        if let Some((conv, filt)) = self.skybox.update() {
-            self.viewer.renderer.new_env(conv, filt);
+        self.stats.begin_frame(builder, index);
+        if let Some(maps) = self.skybox.update(&mut self.errors) {
+            self.set_environment(maps);
+        }
+        self.viewer.poll_reload(self.queue.clone());
+        self.viewer.poll_experiment();
+        if !self.viewer.loading() {
+            if let Some(path) = self.queued_models.pop_front() {
+                self.viewer.load(path, self.queue.clone());
+            }
         }
-        if self.viewer.update() {
-            // self.raytracer.build(
-            //     self.queue.clone(),
-            //     self.viewer.renderer.info.as_ref().unwrap(),
-            // );
+        if self.viewer.update(&mut self.errors) {
+            if let Some(info) = self.viewer.renderer.models.last_mut() {
+                self.workspace.model_loaded(info);
+            }
+            if self.frame_new_models {
+                self.frame_scene();
+            }
+        }
+        self.viewer.heatmap.update(&mut self.errors);
+        self.collision
+            .update(&self.viewer.renderer.models, &mut self.errors);
+        let aabb = self.scene_aabb();
+        if self.viewer.shadow_catcher.settings.enabled {
+            self.viewer.shadow_catcher.bounds = aabb;
+        }
+        if !aabb.is_empty() {
+            self.camera.fit_clip(aabb.center(), aabb.radius());
         }
-
         if self.aspect.is_normal() {
-            let data = CameraUniform::new(&self.camera, self.aspect);
-            let buffer = self.subbuffer_allocator.allocate_sized().unwrap();
-            *buffer.write().unwrap() = data;
-            builder
-                .copy_buffer(CopyBufferInfo::buffers(
-                    buffer,
-                    self.cameras[index].buffer.clone(),
-                ))
-                .unwrap();
+            let view_proj = self.camera.perspective(self.aspect) * self.camera.look_at();
+            self.viewer.prioritize(&view_proj);
         }
-    }
-    pub fn show(&mut self, ctx: &egui::Context, index: usize) {
-        match &mut self.file_picker {
-            FilePicker::Skybox(file_dialog) => {
-                if file_dialog.show(ctx).selected() {
-                    let file = file_dialog.path().unwrap();
-                    self.skybox.load(file.into(), self.queue.clone());
-                }
-            }
-            FilePicker::Gltf(file_dialog) => {
-                if file_dialog.show(ctx).selected() {
-                    let file = file_dialog.path().unwrap();
-                    self.viewer.load(file.into(), self.queue.clone());
-                }
+        let occlusion = self.viewer.occlusion.mode();
+        let traced = self.active_raytracer().is_some()
+            || occlusion != OcclusionMode::Off
+            || self
+                .pathtracer
+                .as_ref()
+                .is_some_and(|pathtracer| pathtracer.rendering());
+        if let Some(acceleration) = &mut self.acceleration {
+            if traced {
+                acceleration.build(self.queue.clone(), &self.viewer.renderer.models);
             }
-            FilePicker::None => {}
         }
 
-        egui::SidePanel::right("state_right_panel").show(ctx, |ui| {
-            ui.heading("Settings");
+        if self.aspect.is_normal() {
+            let jitter = if self.post_process {
+                self.post.jitter()
+            } else {
+                glm::Vec2::zeros()
+            };
+            let data = CameraUniform::new(&self.camera, self.aspect, self.render_depth, jitter);
+            self.cameras[index].upload(&self.subbuffer_allocator, builder, data);
+        }
 
-            ui.horizontal(|ui| {
-                if ui
-                    .add_enabled(!self.skybox.loading(), egui::Button::new("Open Skybox"))
-                    .clicked()
-                {
-                    self.file_picker.skybox();
-                }
-                if self.skybox.loading() {
-                    ui.spinner();
-                }
-            });
-            ui.horizontal(|ui| {
-                if ui
-                    .add_enabled(!self.viewer.loading(), egui::Button::new("Open glTF"))
-                    .clicked()
-                {
-                    self.file_picker.gltf();
-                }
-                if self.viewer.loading() {
-                    ui.spinner();
-                }
-            });
+        if self.viewer.shadows.resize() | self.viewer.occlusion.resize() | self.viewer.ssao.resize()
+        {
+            self.lights = lights_resources(
+                &self.allocators,
+                &self.set_layouts.lights,
+                &self.viewer,
+                self.lights.len(),
+            );
+            self.opaque_lights = opaque_lights_sets(
+                &self.allocators,
+                &self.set_layouts.lights,
+                &self.viewer,
+                &self.lights,
+            );
+        }
 
-            ui.separator();
+        for info in &mut self.viewer.renderer.models {
+            info.upload(&self.subbuffer_allocator, builder, index);
+        }
+        let models = &self.viewer.renderer.models;
+        for morph in models
+            .iter()
+            .flat_map(|info| &info.meshes)
+            .filter_map(|mesh| mesh.morph.as_ref())
+        {
+            morph.upload(&self.subbuffer_allocator, builder, index);
+        }
 
-            ui.collapsing("Camera", |ui| {
-                self.camera.ui(ui);
-            });
+        let world_lights: Vec<Light> = models.iter().flat_map(|info| info.world_lights()).collect();
+        let mut lights = LightsUniform::new(&world_lights);
+        lights.env_intensity = self
+            .skybox
+            .renderer
+            .background
+            .environment_intensity(&self.skybox.renderer.environment);
+        // a probe is captured in world space
+        lights.env_yaw = match self.skybox.probe.active {
+            true => 0.0,
+            false => self.skybox.renderer.environment.yaw,
+        };
+        lights.traced_occlusion = occlusion.shader_value();
+        lights.tone_mapping = self.tone_mapping as i32;
+        let ssao = &self.viewer.ssao;
+        if ssao.settings.enabled && self.aspect.is_normal() {
+            lights.ssao = 1;
+            self.stats.record(ssao.render(
+                builder,
+                index,
+                models,
+                self.camera.look_at(),
+                self.camera.perspective(self.aspect),
+            ));
+        }
+        let shadows = &self.viewer.shadows;
+        let mut traced_light = None;
+        if let Some((i, light)) = LightsUniform::primary_directional(&world_lights) {
+            if occlusion == OcclusionMode::Shadows {
+                lights.shadow_light = i as i32;
+                traced_light = Some(light.direction);
+            } else if shadows.settings.enabled {
+                let view_proj = light_view_proj(
+                    &light.direction,
+                    &self.camera.target(),
+                    shadows.settings.extent,
+                );
+                self.stats
+                    .record(shadows.render(builder, index, models, view_proj));
 
-            if let Some(info) = &mut self.viewer.renderer.info {
-                ui.separator();
+                lights.shadow_view_proj = view_proj;
+                lights.shadow_light = i as i32;
+                lights.shadow_bias = shadows.settings.bias;
+            }
+        }
+        self.lights[index].upload(&self.subbuffer_allocator, builder, lights);
+        // the screen space maps don't fit the faces of a probe
+        let probe_lights = LightsUniform {
+            traced_occlusion: 0,
+            ssao: 0,
+            ..lights
+        };
 
-                ui.collapsing("Scene", |ui| {
-                    egui::ScrollArea::vertical().show(ui, |ui| {
-                        for (name, material) in info
-                            .vktf
-                            .document
-                            .materials()
-                            .map(|m| m.name())
-                            .zip(info.materials.index.iter_mut())
-                        {
-                            ui.label(format!("{:?}", name));
-                            material_ui(ui, &mut material.push);
-                        }
-                        ui.label("Default");
-                        material_ui(ui, &mut info.materials.default.push);
-                    });
-                });
+        let trace = match occlusion {
+            OcclusionMode::Off => false,
+            OcclusionMode::AmbientOcclusion => true,
+            OcclusionMode::Shadows => traced_light.is_some(),
+        };
+        if let Some(acceleration) = self.acceleration.as_ref().filter(|_| trace) {
+            self.viewer.occlusion.render(
+                builder,
+                index,
+                acceleration,
+                &self.camera,
+                traced_light.unwrap_or_default().into(),
+            );
+        }
+
+        if models.iter().any(|info| info.materials.has_transmission()) {
+            self.stats
+                .record(self.frame(index).render_transmission_source(
+                    builder,
+                    &self.viewer.transmission,
+                    self.opaque_lights[index].clone(),
+                ));
+        }
+
+        if let (Some(pathtracer), Some(acceleration)) = (&mut self.pathtracer, &self.acceleration) {
+            match pathtracer.update(builder, acceleration) {
+                Some(Ok(path)) => log::info!("wrote {}", path.display()),
+                Some(Err(err)) => self
+                    .errors
+                    .push(format!("Failed to save the render: {err}")),
+                None => {}
             }
+        }
 
-            ui.separator();
-        });
+        if let (Some(raytracer), Some(acceleration)) = (self.active_raytracer(), &self.acceleration)
+        {
+            raytracer.render(
+                builder,
+                index,
+                acceleration,
+                &self.camera,
+                self.skybox.renderer.environment,
+            );
+        }
 
-        egui::CentralPanel::default()
-            .frame(egui::Frame::NONE)
-            .show(ctx, |ui| {
-                let (rect, response) =
-                    ui.allocate_exact_size(ui.available_size(), egui::Sense::all());
-                self.aspect = rect.aspect_ratio();
+        if std::mem::take(&mut self.skybox.probe.requested) {
+            self.bake_probe(builder, index, probe_lights);
+        }
+        self.uv_layout
+            .render(builder, &self.allocators, &self.viewer.renderer.models);
 
-                let modifiers = response.ctx.input(|i| i.modifiers);
-
-                // pan
-                if modifiers.shift {
-                    let cam = self.camera.look_at().try_inverse().unwrap();
-                    let right = cam.transform_vector(&glm::Vec3::x());
-                    let up = cam.transform_vector(&glm::Vec3::y());
-                    let delta = response.drag_motion() * 0.002 * self.camera.zoom;
-                    self.camera.target -= right * delta.x;
-                    self.camera.target -= up * delta.y;
-                }
-                // rotate
-                else {
-                    let drag_delta = response.drag_motion() * 0.005;
-                    self.camera.yaw -= drag_delta.x;
-                    self.camera.pitch += drag_delta.y;
-                    self.camera.wrap();
-                }
-
-                let smooth_scroll = response.ctx.input(|i| i.smooth_scroll_delta);
-                self.camera.zoom += self.camera.zoom * -smooth_scroll.y * 0.003;
-                self.camera.clamp();
-
-                let skybox = self.skybox.renderer.clone();
-                let viewer = self.viewer.renderer.clone();
-                let camera_set = self.cameras[index].set.clone();
-
-                // self.raytracer
-                //     .resize([rect.width() as u32, rect.height() as u32]);
-                // let raytracer = self.raytracer.clone();
-                // let camera = self.camera;
-                // let aspect = self.aspect;
-                let callback = egui::PaintCallback {
-                    rect,
-                    callback: Arc::new(CallbackFn::new(move |_info, context| {
-                        context
-                            .builder
-                            .bind_descriptor_sets(
-                                PipelineBindPoint::Graphics,
-                                viewer.pipeline.pipeline.layout().clone(),
-                                0,
-                                camera_set.clone(),
-                            )
-                            .unwrap();
-                        viewer.render(context.builder);
-                        context
-                            .builder
-                            .bind_descriptor_sets(
-                                PipelineBindPoint::Graphics,
-                                skybox.pipeline.layout().clone(),
-                                0,
-                                camera_set.clone(),
-                            )
+        if self.post_process && self.post.begin(builder, index, self.background()) {
+            let frame = self.frame(index);
+            let reference = self
+                .compare
+                .reference(&self.viewer.renderer, &self.skybox.renderer);
+            match reference {
+                Some((viewer, skybox)) => {
+                    // A left of the split, B right of it
+                    let reference = SceneFrame {
+                        viewer,
+                        skybox,
+                        heatmap: None,
+                        ..frame.clone()
+                    };
+                    let scissors = split_image_scissors(self.post.extent(), self.compare.split);
+                    for (frame, scissor) in [&reference, &frame].into_iter().zip(scissors) {
+                        let Some(scissor) = scissor else {
+                            continue;
+                        };
+                        builder
+                            .set_scissor(0, [scissor].into_iter().collect())
                             .unwrap();
-                        skybox.render(context.builder);
-                        // raytracer.render(camera, aspect, context.resources.queue.clone());
-                    })),
-                };
-                ui.painter().add(callback);
-            });
+                        self.stats.record(frame.render(builder));
+                    }
+                }
+                None => self.stats.record(frame.render(builder)),
+            }
+            self.post.end(builder, index);
+            if self.histogram.enabled {
+                self.histogram
+                    .record(builder, index, self.post.output(index));
+            }
+        }
     }
-}
+    /// Renders the scene into a cubemap from the position of the probe
+    /// and lights the models with it instead of the skybox.
+    fn bake_probe<L>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        index: usize,
+        lights: LightsUniform,
+    ) {
+        let probe = &self.skybox.probe;
+        let proj = probe.projection(&self.scene_aabb(), self.render_depth.reverse_z);
+        let size = probe.resolution.size();
+        let mips = 5;
 
-fn material_ui(ui: &mut egui::Ui, material_push: &mut MaterialPush) {
-    ui.horizontal(|ui| {
-        let mut rgba = egui::Rgba::from_rgba_unmultiplied(
-            material_push.bc.x,
-            material_push.bc.y,
-            material_push.bc.z,
-            material_push.bc.w,
-        );
-        egui::color_picker::color_edit_button_rgba(
-            ui,
-            &mut rgba,
-            egui::color_picker::Alpha::OnlyBlend,
+        let viewer = &self.viewer;
+        let probe_lights = UniformResource::new(
+            self.allocators.mem.clone(),
+            self.allocators.set.clone(),
+            self.set_layouts.lights.clone(),
+            [
+                viewer.shadows.write(1, index),
+                viewer.transmission.write_empty(2),
+                viewer.occlusion.write(3, index),
+                viewer.ssao.write(4, index),
+            ],
         );
-        material_push.bc = rgba.to_rgba_unmultiplied().into();
-        ui.label("Base colour factor");
-    });
-
-    ui.horizontal(|ui| {
-        ui.add(
-            egui::DragValue::new(&mut material_push.rm.x)
-                .range(0.0..=1.0)
-                .speed(0.01),
+        probe_lights.upload(&self.subbuffer_allocator, builder, lights);
+        let frame = SceneFrame {
+            lights_set: probe_lights.set.clone(),
+            ..self.frame(index)
+        };
+        let target = ProbeTarget::new(
+            &self.allocators,
+            viewer.renderer.subpass().render_pass(),
+            size,
         );
-        ui.label("Roughness factor");
-    });
-    ui.horizontal(|ui| {
-        ui.add(
-            egui::DragValue::new(&mut material_push.rm.y)
-                .range(0.0..=1.0)
-                .speed(0.01),
+        let cube = create_cubemap_image(
+            self.allocators
+                .memory
+                .allocator(MemoryCategory::Environment),
+            size,
+            mips,
+            &self.skybox.loader.queue_families,
         );
-        ui.label("Metallness factor");
-    });
+        for (face, view) in face_views(&probe.position).into_iter().enumerate() {
+            let camera = UniformResource::new(
+                self.allocators.mem.clone(),
+                self.allocators.set.clone(),
+                self.set_layouts.camera.clone(),
+                [],
+            );
+            camera.upload(
+                &self.subbuffer_allocator,
+                builder,
+                CameraUniform::from_view(view, proj),
+            );
+            target.begin(builder, self.background(), self.render_depth);
+            let face_frame = SceneFrame {
+                camera_set: camera.set,
+                ..frame.clone()
+            };
+            self.stats.record(face_frame.render_scene(builder));
+            target.end(builder, &cube, face as u32);
+        }
+        gen_mipmaps(builder, cube.clone(), mips);
+        let (conv, filt) = self.skybox.loader.bake(&cube, self.skybox.quality, builder);
+        self.viewer.renderer.new_env(conv, filt);
+        self.skybox.probe.active = true;
+    }
+    /// Lights and shows a loaded environment, or one of the history.
+    fn set_environment(&mut self, (cube, conv, filt): (Arc<Image>, Arc<Image>, Arc<Image>)) {
+        if let Some(raytracer) = &mut self.raytracer {
+            raytracer.set_environment(cube.clone(), conv.clone());
+        }
+        if let Some(pathtracer) = &mut self.pathtracer {
+            pathtracer.set_environment(cube);
+        }
+        self.viewer.renderer.new_env(conv, filt);
+        self.skybox.probe.active = false;
+    }
+    /// Lights the models with the skybox again after a probe was baked.
+    fn restore_skybox_lighting(&mut self) {
+        if let Some((_, conv, filt)) = &self.skybox.maps {
+            self.viewer.renderer.new_env(conv.clone(), filt.clone());
+        }
+        self.skybox.probe.active = false;
+    }
+    /// Clear colour of the frame, seen where there is no skybox.
+    pub fn background(&self) -> [f32; 4] {
+        self.skybox.renderer.background.clear_color()
+    }
+    pub fn msaa(&self) -> Msaa {
+        self.msaa
+    }
+    pub fn depth(&self) -> Depth {
+        self.depth
+    }
+    /// Rebuilds the pipelines drawn in the main render pass after it was recreated.
+    /// The ray traced images have to be registered again if the [`Gui`] was recreated too.
+    pub fn set_subpass(&mut self, subpass: Subpass, depth: Depth) {
+        self.viewer.renderer.set_subpass(subpass.clone(), depth);
+        self.viewer.grid.set_subpass(subpass.clone(), depth);
+        self.viewer
+            .shadow_catcher
+            .set_subpass(subpass.clone(), depth);
+        self.viewer
+            .debug_geometry
+            .set_subpass(subpass.clone(), depth);
+        self.viewer.points.set_subpass(subpass.clone(), depth);
+        self.viewer.heatmap.set_subpass(subpass.clone(), depth);
+        self.skybox.set_subpass(subpass.clone(), depth);
+        self.post.set_subpass(subpass, self.msaa, depth);
+        if depth != self.render_depth {
+            self.viewer.transmission.set_depth(depth);
+            self.render_depth = depth;
+        }
+        if let Some(raytracer) = &mut self.raytracer {
+            raytracer.forget_textures();
+        }
+        self.texture_inspector.forget_textures();
+        self.uv_layout.forget_textures();
+        self.skybox.history.forget_textures();
+        self.gallery.forget_textures();
+    }
+    /// Call after everything of the frame is recorded, after the UI too.
+    pub fn end_frame<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>, index: usize) {
+        self.stats.end_frame(builder, index);
+    }
+    /// The ray tracer if it is the selected render mode.
+    fn active_raytracer(&self) -> Option<&Raytracer> {
+        self.raytracer
+            .as_ref()
+            .filter(|_| self.render_mode == RenderMode::Raytracer)
+    }
+    /// Makes the ray traced images and the texture preview available to the UI,
+    /// call before [`show`](Self::show).
+    pub fn register_textures(&mut self, gui: &mut Gui) {
+        if let Some(raytracer) = &mut self.raytracer {
+            raytracer.register(gui);
+        }
+        self.post.register(gui);
+        self.texture_inspector.register(gui);
+        self.uv_layout.register(gui);
+        self.skybox.history.register(gui);
+        self.gallery.register(gui);
+    }
+    fn frame(&self, index: usize) -> SceneFrame {
+        let mut viewer = self.viewer.renderer.clone();
+        let heatmap = self.viewer.heatmap.frame(&mut viewer.models);
+        SceneFrame {
+            index,
+            skybox: self.skybox.renderer.clone(),
+            viewer,
+            heatmap,
+            grid: self.viewer.grid.clone(),
+            shadow_catcher: self.viewer.shadow_catcher.clone(),
+            debug_geometry: self.viewer.debug_geometry.clone(),
+            points: self.viewer.points.clone(),
+            camera_set: self.cameras[index].set.clone(),
+            lights_set: self.lights[index].set.clone(),
+        }
+    }
+    pub fn load_skybox(&mut self, path: PathBuf) {
+        if !self.skybox.loading() {
+            self.settings.add_recent_skybox(&path);
+        }
+        self.skybox.load(path, self.queue.clone());
+    }
+    pub fn load_sky(&mut self, preset: SkyPreset) {
+        self.skybox.load_sky(preset, self.queue.clone());
+    }
+    pub fn load_model(&mut self, path: PathBuf) {
+        self.frame_new_models = true;
+        if !self.viewer.loading() {
+            self.settings.add_recent_model(&path);
+        }
+        self.viewer.load(path, self.queue.clone());
+    }
+    /// Loads a .gltf or .glb from memory, like a file called `name`.
+    /// Only data URIs and allowed downloads can be read, it isn't added to the recent models.
+    pub fn load_model_bytes(&mut self, name: impl Into<String>, bytes: impl Into<Arc<[u8]>>) {
+        self.frame_new_models = true;
+        self.viewer
+            .load_bytes(name, bytes.into(), self.queue.clone());
+    }
+    /// Shows `error` in the error window until it is dismissed, once if it repeats every frame.
+    pub fn report_error(&mut self, error: String) {
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+    /// What a new window needs to show the same scene, if the UI asked for one.
+    pub fn take_new_window(&mut self) -> Option<SceneSnapshot> {
+        std::mem::take(&mut self.new_window).then(|| self.snapshot())
+    }
+    /// The device picked in the UI, the settings are saved with it.
+    pub fn take_gpu_switch(&mut self) -> Option<String> {
+        let gpu = self.gpu_switch.take()?;
+        self.settings.gpu = Some(gpu.clone());
+        self.save_settings();
+        Some(gpu)
+    }
+    /// The scene of this window, to open it again in another window or on another device.
+    pub fn snapshot(&self) -> SceneSnapshot {
+        SceneSnapshot {
+            models: self
+                .viewer
+                .renderer
+                .models
+                .iter()
+                .map(|info| info.vktf.path.clone())
+                .collect(),
+            skybox: self.skybox.source.clone(),
+            camera: self.camera,
+        }
+    }
+    /// Loads the scene of another window, the camera moves independently afterwards.
+    pub fn open_snapshot(&mut self, snapshot: SceneSnapshot) {
+        self.camera = snapshot.camera;
+        self.frame_new_models = false;
+        self.load_environment(snapshot.skybox);
+        self.queued_models.extend(snapshot.models);
+    }
+    fn load_environment(&mut self, source: Option<SkyboxSource>) {
+        match source {
+            Some(SkyboxSource::Image(path)) => self.skybox.load(path, self.queue.clone()),
+            Some(SkyboxSource::Sky(preset)) => self.load_sky(preset),
+            None => self.load_sky(Default::default()),
+        }
+    }
+    /// The scene and the layout of the UI, to be restored with [`open_workspace`](Self::open_workspace).
+    pub fn workspace(&self, ctx: &egui::Context) -> Workspace {
+        Workspace {
+            layout: UiLayout {
+                sections: self.preferences.open_sections.clone(),
+                stats: self.stats.overlay,
+                histogram: self.histogram.enabled,
+                console: self.console.open,
+                gallery: self
+                    .gallery
+                    .dir()
+                    .filter(|_| self.gallery.open)
+                    .map(Into::into),
+                windows: UiLayout::window_positions(ctx),
+            },
+            ..self.scene_workspace()
+        }
+    }
+    /// The workspace without the layout of the UI.
+    fn scene_workspace(&self) -> Workspace {
+        let environment = &self.skybox.renderer.environment;
+        Workspace {
+            models: self
+                .viewer
+                .renderer
+                .models
+                .iter()
+                .map(WorkspaceModel::new)
+                .collect(),
+            environment: WorkspaceEnvironment {
+                source: self.skybox.source.clone(),
+                intensity: environment.intensity,
+                yaw: environment.yaw,
+                background: self.skybox.renderer.background,
+            },
+            camera: self.camera,
+            bookmarks: self.bookmarks.clone(),
+            material_override: self.viewer.renderer.material_override,
+            layout: UiLayout::default(),
+        }
+    }
+    /// Replaces the models, environment, camera and layout with the ones of `workspace`.
+    /// The models are set up like in the workspace as they finish loading.
+    pub fn open_workspace(&mut self, workspace: Workspace) {
+        if self.viewer.loading() {
+            self.report_error("Wait for the models to load before opening a workspace".to_owned());
+            return;
+        }
+        while !self.viewer.renderer.models.is_empty() {
+            self.viewer.remove(0);
+        }
+        self.queued_models.clear();
+        self.frame_new_models = false;
+        self.camera = workspace.camera;
+        self.bookmarks = workspace.bookmarks;
+        self.viewer.renderer.material_override = workspace.material_override;
 
-    ui.horizontal(|ui| {
-        ui.add(
-            egui::DragValue::new(&mut material_push.ao)
+        let environment = workspace.environment;
+        if environment.source != self.skybox.source {
+            self.load_environment(environment.source);
+        }
+        self.skybox.renderer.environment.intensity = environment.intensity;
+        self.skybox.renderer.environment.yaw = environment.yaw;
+        self.skybox.renderer.background = environment.background;
+
+        let layout = workspace.layout;
+        self.preferences.restore_sections(layout.sections);
+        self.stats.overlay = layout.stats;
+        self.histogram.enabled = layout.histogram;
+        self.console.open = layout.console;
+        match layout.gallery {
+            Some(dir) => self.gallery.open_dir(dir),
+            None => self.gallery.open = false,
+        }
+
+        self.queued_models
+            .extend(workspace.models.iter().map(|model| model.path.clone()));
+        self.workspace.start(workspace.models, layout.windows);
+    }
+    /// Restores the settings of the last run.
+    pub fn load_settings(&mut self) {
+        self.settings = Settings::load();
+        self.camera = self.settings.camera;
+        self.bookmarks = self.settings.bookmarks.clone();
+        self.material_presets = self.settings.material_presets.clone();
+        self.camera_path = self.settings.camera_path.clone();
+        self.keymap = self.settings.keymap.clone();
+        self.preferences = self.settings.ui.clone();
+        self.console.history = self.settings.console_history.clone();
+        self.skybox.quality = self.settings.ibl_quality;
+        if self.raytracer.is_some() {
+            self.render_mode = self.settings.render_mode;
+        }
+        self.viewer.occlusion.settings = self.settings.occlusion;
+        self.viewer.ssao.settings = self.settings.ssao;
+        self.post.scale = self.settings.render_scale.unwrap_or(1.0);
+        if let Some(pathtracer) = &mut self.pathtracer {
+            pathtracer.settings = self.settings.beauty;
+        }
+        self.viewer.loader.texture_options = self.settings.textures;
+        self.viewer.loader.geometry_options = self.settings.geometry;
+        self.viewer.loader.disk_cache = self.settings.disk_cache.clone();
+        self.stats.overlay = self.settings.show_stats;
+        self.screenshot_format = self.settings.screenshot_format;
+        self.post.output_transform = self.settings.output_transform;
+        self.skybox.renderer.background = self.settings.background;
+        self.viewer.grid.settings = self.settings.grid;
+        self.viewer.shadow_catcher.settings = self.settings.shadow_catcher;
+        self.collision.settings = self.settings.collision;
+        self.viewer.debug_geometry.settings = self.settings.debug_geometry;
+        self.viewer.points.settings = self.settings.points;
+        self.viewer.spaces = self.settings.asset_spaces.clone();
+        if let Some(path) = &self.settings.experiment_shader {
+            self.viewer.experiment.set_path(path.clone());
+        }
+        // the settings can come from another device
+        self.msaa = self.settings.msaa.or_supported(self.queue.device());
+        self.depth = self.settings.depth.or_supported(self.queue.device());
+    }
+    /// Overrides the saved setting, only affects models loaded afterwards.
+    pub fn set_texture_compression(&mut self, compress: bool) {
+        self.viewer.loader.texture_options.compress = compress;
+    }
+    pub fn save_settings(&mut self) {
+        self.settings.camera = self.camera;
+        self.settings.bookmarks = self.bookmarks.clone();
+        self.settings.material_presets = self.material_presets.clone();
+        self.settings.camera_path = self.camera_path.clone();
+        self.settings.keymap = self.keymap.clone();
+        self.settings.ui = self.preferences.clone();
+        self.settings.console_history = self.console.history.clone();
+        self.settings.ibl_quality = self.skybox.quality;
+        self.settings.render_mode = self.render_mode;
+        self.settings.occlusion = self.viewer.occlusion.settings;
+        self.settings.ssao = self.viewer.ssao.settings;
+        self.settings.render_scale = Some(self.post.scale);
+        if let Some(pathtracer) = &self.pathtracer {
+            self.settings.beauty = pathtracer.settings;
+        }
+        self.settings.textures = self.viewer.loader.texture_options;
+        self.settings.geometry = self.viewer.loader.geometry_options;
+        self.settings.disk_cache = self.viewer.loader.disk_cache.clone();
+        self.settings.show_stats = self.stats.overlay;
+        self.settings.screenshot_format = self.screenshot_format;
+        self.settings.output_transform = self.post.output_transform;
+        self.settings.msaa = self.msaa;
+        self.settings.depth = self.depth;
+        self.settings.background = self.skybox.renderer.background;
+        self.settings.grid = self.viewer.grid.settings;
+        self.settings.shadow_catcher = self.viewer.shadow_catcher.settings;
+        self.settings.collision = self.collision.settings;
+        self.settings.debug_geometry = self.viewer.debug_geometry.settings;
+        self.settings.points = self.viewer.points.settings;
+        self.settings.asset_spaces = self.viewer.spaces.clone();
+        self.settings.experiment_shader = Some(self.viewer.experiment.path().to_owned());
+        self.settings.save();
+    }
+    /// Points the camera at the loaded scene.
+    pub fn frame_scene(&mut self) {
+        let aabb = self.scene_aabb();
+        if !aabb.is_empty() {
+            self.camera.frame(aabb.center(), aabb.radius());
+        }
+    }
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.camera = camera;
+    }
+    /// Moves the camera to `eye` looking at `target`, keeping its mode and projection.
+    pub fn set_camera_pose(&mut self, eye: glm::Vec3, target: glm::Vec3) {
+        self.camera.look_from(eye, target);
+    }
+    /// Bounds of the loaded models in world space, empty without models.
+    pub fn scene_aabb(&self) -> Aabb {
+        let mut aabb = Aabb::empty();
+        for info in &self.viewer.renderer.models {
+            aabb.union(&info.world_aabb());
+        }
+        aabb
+    }
+    /// The nodes of the shown scene of every loaded model, parents before their children.
+    pub fn list_nodes(&self) -> Vec<NodeInfo> {
+        let mut nodes = vec![];
+        for (model, info) in self.viewer.renderer.models.iter().enumerate() {
+            let scene = &info.scene;
+            let mut stack: Vec<_> = scene.roots.iter().rev().map(|root| (*root, None)).collect();
+            while let Some((index, parent)) = stack.pop() {
+                let node = &scene.nodes[index];
+                nodes.push(NodeInfo {
+                    model,
+                    index,
+                    parent,
+                    name: node.name.clone(),
+                    visible: node.is_visible(),
+                });
+                stack.extend(
+                    node.children
+                        .iter()
+                        .rev()
+                        .map(|child| (*child, Some(index))),
+                );
+            }
+        }
+        nodes
+    }
+    /// Shows or hides a node of a loaded model and its children, like `KHR_node_visibility`.
+    /// `false` if there is no such model or node.
+    pub fn set_node_visible(&mut self, model: usize, node: usize, visible: bool) -> bool {
+        let Some(info) = self.viewer.renderer.models.get_mut(model) else {
+            return false;
+        };
+        if node >= info.scene.nodes.len() {
+            return false;
+        }
+        info.scene.set_visible(node, visible);
+        true
+    }
+    /// The viewport region to copy and where to write it if a screenshot was requested this frame.
+    /// HDR screenshots are drawn and written by the viewer itself, they return `None`.
+    pub fn take_screenshot(&mut self) -> Option<([u32; 2], [u32; 2], Option<PathBuf>)> {
+        let path = self.screenshot_path.take();
+        if !std::mem::take(&mut self.screenshot) {
+            return None;
+        }
+        match self.screenshot_format.for_path(path.as_deref()) {
+            ScreenshotFormat::Png => Some((self.viewport.0, self.viewport.1, path)),
+            format => {
+                self.capture_hdr(format, path);
+                None
+            }
+        }
+    }
+    /// Draws the viewport again without tone mapping and writes it as OpenEXR.
+    fn capture_hdr(&mut self, format: ScreenshotFormat, path: Option<PathBuf>) {
+        if self.hdr_capture.capturing() {
+            log::warn!("an HDR screenshot is already being captured");
+            return;
+        }
+        let [width, height] = self.viewport.1;
+        if width == 0 || height == 0 {
+            return;
+        }
+        let request = HdrRequest {
+            scene: self.scene_workspace(),
+            edits: self.edit_snapshot(),
+            settings: CaptureSettings {
+                debug_view: self.viewer.renderer.debug_view,
+                ibl_quality: self.skybox.quality,
+                shadows: self.viewer.shadows.settings,
+                ssao: self.viewer.ssao.settings,
+                grid: self.viewer.grid.settings,
+                shadow_catcher: self.viewer.shadow_catcher.settings,
+            },
+            extent: [width, height],
+            path: path.unwrap_or_else(|| screenshot::screenshot_path(format.extension())),
+            format,
+        };
+        self.hdr_capture
+            .start(&self.allocators, &self.queue, request);
+    }
+    /// The colour space the viewport is encoded in.
+    pub fn output_transform(&self) -> OutputTransform {
+        self.post.output_transform
+    }
+    /// Takes a screenshot of the viewport at the end of the next frame.
+    pub fn request_screenshot(&mut self, path: Option<PathBuf>) {
+        self.screenshot = true;
+        self.screenshot_path = path;
+    }
+    /// A model or environment is being loaded.
+    pub fn loading(&self) -> bool {
+        self.viewer.loading() || self.skybox.loading() || !self.queued_models.is_empty()
+    }
+    pub fn model_count(&self) -> usize {
+        self.viewer.renderer.models.len()
+    }
+    /// Failed loads and recovered GPU errors, the UI shows them until they are dismissed.
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+    fn shortcut(&mut self, action: Action) {
+        match action {
+            Action::OpenModel => {
+                if !self.viewer.loading() {
+                    self.file_picker.gltf();
+                }
+            }
+            Action::FrameScene => self.frame_scene(),
+            Action::ToggleWireframe => {
+                if self.viewer.renderer.pipeline.wireframe.is_some() {
+                    self.viewer.renderer.wireframe ^= true;
+                }
+            }
+            Action::Screenshot => self.screenshot = true,
+            Action::FrontView => self.camera.view_from(ViewPreset::Front),
+            Action::TopView => self.camera.view_from(ViewPreset::Top),
+            Action::SideView => self.camera.view_from(ViewPreset::Side),
+            Action::Undo => self.undo(),
+            Action::Redo => self.redo(),
+        }
+    }
+    fn edit_snapshot(&self) -> EditSnapshot {
+        EditSnapshot::new(&self.viewer.renderer.models, &self.bookmarks)
+    }
+    /// Reverts the last edit of the materials, nodes or bookmarks.
+    pub fn undo(&mut self) {
+        let current = self.edit_snapshot();
+        if let Some(snapshot) = self.undo.undo(current) {
+            snapshot.apply(&mut self.viewer.renderer.models, &mut self.bookmarks);
+        }
+    }
+    pub fn redo(&mut self) {
+        let current = self.edit_snapshot();
+        if let Some(snapshot) = self.undo.redo(current) {
+            snapshot.apply(&mut self.viewer.renderer.models, &mut self.bookmarks);
+        }
+    }
+    /// Applies the commands of the scripts run in the console, up to the next screenshot.
+    fn run_script_commands(&mut self) {
+        while let Some(command) = self.console.next_command() {
+            if let Err(err) = self.apply_script_command(command) {
+                self.console.error(err);
+            }
+            if matches!(command, ScriptCommand::Screenshot) {
+                break;
+            }
+        }
+    }
+    fn script_model(&mut self, index: usize) -> Result<&mut GltfRenderInfo, String> {
+        self.viewer
+            .renderer
+            .models
+            .get_mut(index)
+            .ok_or_else(|| format!("There is no model {index}"))
+    }
+    fn apply_script_command(&mut self, command: ScriptCommand) -> Result<(), String> {
+        match command {
+            ScriptCommand::CameraPose { eye, target } => self.camera.look_from(eye, target),
+            ScriptCommand::FrameScene => self.frame_scene(),
+            ScriptCommand::Fov(degrees) => self.camera.set_fov(degrees.to_radians()),
+            ScriptCommand::Material {
+                model: index,
+                material,
+                edit,
+            } => {
+                let info = self.script_model(index)?;
+                let material = info
+                    .materials
+                    .index
+                    .get_mut(material)
+                    .ok_or_else(|| format!("Model {index} has no material {material}"))?;
+                edit.apply(&mut material.push);
+            }
+            ScriptCommand::Play {
+                model: index,
+                animation,
+            } => {
+                let info = self.script_model(index)?;
+                let player = &mut info.player;
+                if let Some(animation) = animation.filter(|a| *a != player.animation) {
+                    if animation >= info.vktf.animations.len() {
+                        return Err(format!("Model {index} has no animation {animation}"));
+                    }
+                    player.animation = animation;
+                    player.time = 0.0;
+                }
+                player.playing = true;
+            }
+            ScriptCommand::Pause { model: index } => {
+                self.script_model(index)?.player.playing = false
+            }
+            ScriptCommand::Seek { model: index, time } => {
+                let player = &mut self.script_model(index)?.player;
+                player.time = time.max(0.0);
+                player.scrubbed = true;
+            }
+            ScriptCommand::NodeVisible {
+                model,
+                node,
+                visible,
+            } => {
+                if !self.set_node_visible(model, node, visible) {
+                    return Err(format!("Model {model} has no node {node}"));
+                }
+            }
+            ScriptCommand::Screenshot => self.screenshot = true,
+        }
+        Ok(())
+    }
+    pub fn show(&mut self, ctx: &egui::Context, index: usize) {
+        self.preferences.apply(ctx);
+        for action in self.keymap.triggered(ctx) {
+            self.shortcut(action);
+        }
+        let before = self.edit_snapshot();
+        self.run_script_commands();
+        self.workspace.place_windows(ctx);
+
+        match &mut self.file_picker {
+            FilePicker::Skybox(file_dialog) => {
+                if file_dialog.show(ctx).selected() {
+                    let file = file_dialog.path().unwrap();
+                    self.load_skybox(file.into());
+                }
+            }
+            FilePicker::Gltf(file_dialog) => {
+                if file_dialog.show(ctx).selected() {
+                    let files: Vec<PathBuf> = file_dialog
+                        .selection()
+                        .into_iter()
+                        .map(Into::into)
+                        .collect();
+                    for file in files {
+                        self.load_model(file);
+                    }
+                }
+            }
+            FilePicker::Render(file_dialog) => {
+                if file_dialog.show(ctx).selected() {
+                    let file = file_dialog.path().unwrap();
+                    if let Some(pathtracer) = &mut self.pathtracer {
+                        pathtracer.start(
+                            file.into(),
+                            &self.camera,
+                            self.skybox.renderer.environment,
+                        );
+                    }
+                }
+            }
+            FilePicker::Texture(file_dialog, model, material, slot) => {
+                if file_dialog.show(ctx).selected() {
+                    let file = file_dialog.path().unwrap();
+                    if let Err(err) = self.viewer.replace_texture(
+                        *model,
+                        *material,
+                        *slot,
+                        file,
+                        self.queue.clone(),
+                    ) {
+                        self.errors
+                            .push(format!("Failed to replace the texture: {err}"));
+                    }
+                }
+            }
+            FilePicker::Export(file_dialog, model, bake_pose) => {
+                if file_dialog.show(ctx).selected() {
+                    let file = file_dialog.path().unwrap();
+                    if let Some(info) = self.viewer.renderer.models.get(*model) {
+                        match export_glb(info, file, *bake_pose) {
+                            Ok(()) => log::info!("wrote {}", file.display()),
+                            Err(err) => self.errors.push(format!("Failed to export: {err}")),
+                        }
+                    }
+                }
+            }
+            FilePicker::Report(file_dialog, model) => {
+                if file_dialog.show(ctx).selected() {
+                    let file = file_dialog.path().unwrap();
+                    if let Some(info) = self.viewer.renderer.models.get(*model) {
+                        match ModelReport::new(info).write(file) {
+                            Ok(()) => log::info!("wrote {}", file.display()),
+                            Err(err) => self
+                                .errors
+                                .push(format!("Failed to write the report: {err}")),
+                        }
+                    }
+                }
+            }
+            FilePicker::SaveCameraPath(file_dialog) => {
+                if file_dialog.show(ctx).selected() {
+                    let file = file_dialog.path().unwrap();
+                    match self.camera_path.save(file) {
+                        Ok(()) => log::info!("wrote {}", file.display()),
+                        Err(err) => self
+                            .errors
+                            .push(format!("Failed to save the camera path: {err}")),
+                    }
+                }
+            }
+            FilePicker::LoadCameraPath(file_dialog) => {
+                if file_dialog.show(ctx).selected() {
+                    let file = file_dialog.path().unwrap();
+                    if let Err(err) = self.camera_path.load(file) {
+                        self.errors
+                            .push(format!("Failed to load the camera path: {err}"));
+                    }
+                }
+            }
+            FilePicker::Environment(file_dialog) => {
+                if file_dialog.show(ctx).selected() {
+                    let file = file_dialog.path().unwrap();
+                    if let Some((cube, conv, filt)) = &self.skybox.maps {
+                        match export_environment(
+                            self.queue.clone(),
+                            &self.allocators,
+                            (cube, conv, filt),
+                            file,
+                        ) {
+                            Ok(files) => {
+                                for file in files {
+                                    log::info!("wrote {}", file.display());
+                                }
+                            }
+                            Err(err) => self
+                                .errors
+                                .push(format!("Failed to export the environment: {err}")),
+                        }
+                    }
+                }
+            }
+            FilePicker::Gallery(file_dialog) => {
+                if file_dialog.show(ctx).selected() {
+                    let dir = file_dialog.path().unwrap();
+                    self.gallery.open_dir(dir.into());
+                }
+            }
+            FilePicker::SaveWorkspace(file_dialog) => {
+                if file_dialog.show(ctx).selected() {
+                    let file = file_dialog.path().unwrap().to_owned();
+                    match self.workspace(ctx).save(&file) {
+                        Ok(()) => log::info!("wrote {}", file.display()),
+                        Err(err) => self
+                            .errors
+                            .push(format!("Failed to save the workspace: {err}")),
+                    }
+                }
+            }
+            FilePicker::OpenWorkspace(file_dialog) => {
+                if file_dialog.show(ctx).selected() {
+                    let file = file_dialog.path().unwrap();
+                    match Workspace::load(file) {
+                        Ok(workspace) => self.open_workspace(workspace),
+                        Err(err) => self
+                            .errors
+                            .push(format!("Failed to open the workspace: {err}")),
+                    }
+                }
+            }
+            FilePicker::BatchModels(file_dialog) => {
+                if file_dialog.show(ctx).selected() {
+                    let models = file_dialog
+                        .selection()
+                        .into_iter()
+                        .map(Into::into)
+                        .collect();
+                    self.file_picker.batch_output(models);
+                }
+            }
+            FilePicker::BatchOutput(file_dialog, models) => {
+                if file_dialog.show(ctx).selected() {
+                    let dir = file_dialog.path().unwrap();
+                    self.batch.start(std::mem::take(models), dir.into());
+                }
+            }
+            FilePicker::None => {}
+        }
+
+        if let Some((path, hosts)) = self.viewer.remote_prompt.take() {
+            let mut open = true;
+            egui::Window::new("Download model files?")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label(format!(
+                        "{} references buffers or images on:",
+                        path.file_name().unwrap_or_default().to_string_lossy()
+                    ));
+                    for host in &hosts {
+                        ui.monospace(host);
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Allow and load").clicked() {
+                            self.viewer.loader.remote.allow(hosts.iter().cloned());
+                            self.load_model(path.clone());
+                            open = false;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            open = false;
+                        }
+                    });
+                });
+            if open {
+                self.viewer.remote_prompt = Some((path, hosts));
+            }
+        }
+
+        if !self.errors.is_empty() {
+            egui::Window::new("Error")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    for error in &self.errors {
+                        ui.label(error);
+                    }
+                    if ui.button("Dismiss").clicked() {
+                        self.errors.clear();
+                    }
+                });
+        }
+
+        egui::SidePanel::right("state_right_panel").show(ctx, |ui| {
+            self.preferences.persist_sections(ui, &SETTINGS_SECTIONS);
+            ui.heading("Settings");
+
+            self.render_mode.ui(ui, self.raytracer.is_some());
+            self.msaa.ui(ui, self.queue.device());
+            self.depth.ui(ui, self.queue.device());
+            ui.add(
+                egui::Slider::new(&mut self.post.scale, 0.5..=2.0)
+                    .step_by(0.05)
+                    .custom_formatter(|scale, _| format!("{:.0}%", scale * 100.0))
+                    .custom_parser(|text| {
+                        let percent = text.trim().trim_end_matches('%').trim();
+                        percent.parse::<f64>().ok().map(|percent| percent / 100.0)
+                    })
+                    .text("Render scale"),
+            )
+            .on_hover_text(
+                "Resolution of the viewport relative to the window, above 100% supersamples",
+            );
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!self.skybox.loading(), egui::Button::new("Open Skybox"))
+                    .clicked()
+                {
+                    self.file_picker.skybox();
+                }
+                if let Some(progress) = &self.skybox.progress {
+                    progress::progress_ui(ui, progress);
+                }
+            });
+            if !self.skybox.history.entries.is_empty() {
+                let selected = ui
+                    .add_enabled_ui(!self.skybox.loading(), |ui| {
+                        self.skybox.history.ui(ui, self.skybox.source.as_ref())
+                    })
+                    .inner;
+                if let Some(maps) = selected.and_then(|i| self.skybox.select(i)) {
+                    self.set_environment(maps);
+                }
+            }
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        !self.viewer.loading(),
+                        egui::Button::new("Open glTF")
+                            .shortcut_text(self.keymap.label(ctx, Action::OpenModel)),
+                    )
+                    .clicked()
+                {
+                    self.file_picker.gltf();
+                }
+                if ui.button("Browse folder...").clicked() {
+                    self.file_picker.gallery();
+                }
+                if ui
+                    .button("Export thumbnails...")
+                    .on_hover_text("Render a thumbnail of each of several models into a folder")
+                    .clicked()
+                {
+                    self.file_picker.batch_models();
+                }
+                if ui
+                    .add_enabled(
+                        self.undo.can_undo(),
+                        egui::Button::new("Undo")
+                            .shortcut_text(self.keymap.label(ctx, Action::Undo)),
+                    )
+                    .clicked()
+                {
+                    self.undo();
+                }
+                if ui
+                    .add_enabled(
+                        self.undo.can_redo(),
+                        egui::Button::new("Redo")
+                            .shortcut_text(self.keymap.label(ctx, Action::Redo)),
+                    )
+                    .clicked()
+                {
+                    self.redo();
+                }
+                if let Some(progress) = &self.viewer.progress {
+                    progress::progress_ui(ui, progress);
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Save workspace...")
+                    .on_hover_text(
+                        "Save the models with their placement and materials, the environment, \
+                         the bookmarks and the layout of the windows",
+                    )
+                    .clicked()
+                {
+                    self.file_picker.save_workspace();
+                }
+                if ui
+                    .add_enabled(
+                        !self.viewer.loading(),
+                        egui::Button::new("Open workspace..."),
+                    )
+                    .on_hover_text("Replace the scene with a saved workspace")
+                    .clicked()
+                {
+                    self.file_picker.open_workspace();
+                }
+            });
+            if let Some(watcher) = &mut self.viewer.watcher {
+                ui.checkbox(&mut watcher.enabled, "Reload on file change");
+            }
+
+            let mut recent_model = None;
+            let mut recent_skybox = None;
+            ui.menu_button("Recent", |ui| {
+                ui.label("glTF");
+                ui.add_enabled_ui(!self.viewer.loading(), |ui| {
+                    for path in &self.settings.recent_models {
+                        if ui.button(path.display().to_string()).clicked() {
+                            recent_model = Some(path.clone());
+                            ui.close_menu();
+                        }
+                    }
+                });
+                ui.separator();
+                ui.label("Skybox");
+                ui.add_enabled_ui(!self.skybox.loading(), |ui| {
+                    for path in &self.settings.recent_skyboxes {
+                        if ui.button(path.display().to_string()).clicked() {
+                            recent_skybox = Some(path.clone());
+                            ui.close_menu();
+                        }
+                    }
+                });
+            });
+            if let Some(path) = recent_model {
+                self.load_model(path);
+            }
+            if let Some(path) = recent_skybox {
+                self.load_skybox(path);
+            }
+
+            ui.separator();
+
+            ui.collapsing("Camera", |ui| {
+                if ui
+                    .add_enabled(
+                        !self.viewer.renderer.models.is_empty(),
+                        egui::Button::new("Frame scene")
+                            .shortcut_text(self.keymap.label(ctx, Action::FrameScene)),
+                    )
+                    .clicked()
+                {
+                    self.frame_scene();
+                }
+                if ui
+                    .button("New window")
+                    .on_hover_text("Show the scene in another window with its own camera")
+                    .clicked()
+                {
+                    self.new_window = true;
+                }
+                self.camera.ui(ui);
+                if self.camera.mode == CameraMode::Fly {
+                    ui.separator();
+                    self.collision.ui(ui, &self.viewer.renderer.models);
+                }
+                ui.collapsing("Bookmarks", |ui| {
+                    self.bookmarks.ui(ui, &mut self.camera);
+                });
+                ui.collapsing("Camera path", |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Load").clicked() {
+                            self.file_picker.load_camera_path();
+                        }
+                        if ui
+                            .add_enabled(
+                                !self.camera_path.keys.is_empty(),
+                                egui::Button::new("Save"),
+                            )
+                            .clicked()
+                        {
+                            self.file_picker.save_camera_path();
+                        }
+                    });
+                    let playing = self.camera_path.playing();
+                    self.camera_path.ui(ui, &mut self.camera);
+                    if !playing && self.camera_path.playing() {
+                        self.bookmarks.stop();
+                    }
+                });
+            });
+
+            if !self.viewer.renderer.models.is_empty() {
+                ui.separator();
+
+                let mut action = None;
+                let loading = self.viewer.loading();
+                for (i, info) in self.viewer.renderer.models.iter_mut().enumerate() {
+                    ui.push_id(i, |ui| {
+                        if let Some(clicked) =
+                            model_ui(ui, info, &mut self.material_presets, loading)
+                        {
+                            action = Some((i, clicked));
+                        }
+                    });
+                }
+                self.viewer.remember_spaces();
+                if self
+                    .viewer
+                    .renderer
+                    .models
+                    .iter()
+                    .any(|info| info.points() > 0)
+                {
+                    let max_size = self.viewer.points.max_size;
+                    self.viewer.points.settings.ui(ui, max_size);
+                }
+                let file_stem = |i: usize| {
+                    self.viewer.renderer.models[i]
+                        .vktf
+                        .path
+                        .file_stem()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default()
+                };
+                match action {
+                    Some((i, ModelAction::Export)) => {
+                        self.file_picker.export(i, &file_stem(i), false);
+                    }
+                    Some((i, ModelAction::ExportPose)) => {
+                        self.file_picker.export(i, &file_stem(i), true);
+                    }
+                    Some((i, ModelAction::Report)) => {
+                        self.file_picker.report(i, &file_stem(i));
+                    }
+                    Some((i, ModelAction::Remove)) => self.viewer.remove(i),
+                    Some((i, ModelAction::Reload)) => self.viewer.reload(i, self.queue.clone()),
+                    Some((i, ModelAction::ReplaceTexture(material, slot))) => {
+                        self.file_picker.texture(i, material, slot);
+                    }
+                    Some((i, ModelAction::UvLayout(mesh, primitive))) => {
+                        let info = &self.viewer.renderer.models[i];
+                        if let Err(err) = self.uv_layout.select(info, mesh, primitive) {
+                            self.errors.push(format!("Failed to read the UVs: {err}"));
+                        }
+                    }
+                    Some((i, ModelAction::Simplify)) => {
+                        if let Err(err) = self.viewer.simplify(i, self.queue.clone()) {
+                            self.errors
+                                .push(format!("Failed to simplify the model: {err}"));
+                        }
+                    }
+                    None => {}
+                }
+            }
+
+            ui.collapsing("Environment", |ui| {
+                let mut sky = None;
+                ui.add_enabled_ui(!self.skybox.loading(), |ui| {
+                    ui.horizontal_wrapped(|ui| {
+                        for preset in SkyPreset::ALL {
+                            if ui.button(preset.name()).clicked() {
+                                sky = Some(preset);
+                            }
+                        }
+                    });
+                });
+                if let Some(preset) = sky {
+                    self.load_sky(preset);
+                }
+                self.skybox.renderer.environment.ui(ui);
+                self.skybox.renderer.background.ui(ui);
+                self.viewer.grid.settings.ui(ui);
+                let changed = ui
+                    .add_enabled_ui(!self.skybox.loading(), |ui| self.skybox.quality.ui(ui))
+                    .inner;
+                if changed {
+                    self.skybox.reload(self.queue.clone());
+                }
+                if ui
+                    .add_enabled(
+                        self.skybox.maps.is_some() && !self.skybox.loading(),
+                        egui::Button::new("Export environment..."),
+                    )
+                    .on_hover_text("Save the cubemap, irradiance and prefiltered maps as KTX2")
+                    .clicked()
+                {
+                    self.file_picker.environment();
+                }
+                ui.separator();
+                ui.label("Reflection probe");
+                if self.skybox.probe.ui(ui, self.camera.eye()) {
+                    self.restore_skybox_lighting();
+                }
+            });
+
+            ui.collapsing("Compare", |ui| {
+                self.compare
+                    .ui(ui, &self.viewer.renderer, &self.skybox.renderer);
+                ui.separator();
+                ui.label("Deviation heatmap");
+                let loading = self.viewer.loading();
+                self.viewer
+                    .heatmap
+                    .ui(ui, &self.viewer.renderer.models, loading);
+            });
+
+            ui.collapsing("Debug", |ui| {
+                self.viewer.renderer.debug_view.ui(ui);
+                ui.checkbox(&mut self.histogram.enabled, "Exposure histogram")
+                    .on_hover_text("Luminance of the viewport, see the false colour view");
+                ui.horizontal(|ui| {
+                    self.screenshot_format.ui(ui);
+                    if ui
+                        .add_enabled(
+                            !self.hdr_capture.capturing(),
+                            egui::Button::new("Screenshot")
+                                .shortcut_text(self.keymap.label(ctx, Action::Screenshot)),
+                        )
+                        .clicked()
+                    {
+                        self.screenshot = true;
+                    }
+                    if self.hdr_capture.capturing() {
+                        ui.spinner();
+                    }
+                });
+                self.viewer.renderer.material_override.ui(ui);
+                ui.add_enabled(
+                    self.viewer.renderer.pipeline.wireframe.is_some(),
+                    egui::Checkbox::new(&mut self.viewer.renderer.wireframe, "Wireframe"),
+                )
+                .on_disabled_hover_text("Wireframes are not supported by the device");
+                let mut replicas = self.viewer.replicas();
+                if ui
+                    .add(egui::Slider::new(&mut replicas, 1..=32).text("Replicas"))
+                    .on_hover_text("Draw every model N×N times to test instancing throughput")
+                    .changed()
+                {
+                    self.viewer.set_replicas(replicas);
+                }
+                self.viewer.debug_geometry.settings.ui(ui);
+                ui.separator();
+                self.viewer.experiment.ui(ui);
+            });
+
+            ui.collapsing("Shortcuts", |ui| {
+                self.keymap.ui(ui);
+            });
+
+            ui.collapsing("Interface", |ui| {
+                self.preferences.ui(ui);
+                self.post.output_transform.ui(ui);
+                ui.checkbox(&mut self.console.open, "Script console")
+                    .on_hover_text("Run Rhai scripts to edit the scene and take screenshots");
+            });
+
+            ui.collapsing("Textures", |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        self.texture_inspector.ui(ui, &self.viewer.renderer.models);
+                    });
+            });
+
+            ui.collapsing("Shadows", |ui| {
+                self.viewer.shadows.settings.ui(ui);
+                ui.separator();
+                self.viewer.shadow_catcher.settings.ui(ui);
+            });
+
+            ui.collapsing("Ambient occlusion", |ui| {
+                self.viewer.ssao.settings.ui(ui);
+            });
+
+            ui.collapsing("Hybrid ray tracing", |ui| {
+                let supported = self.viewer.occlusion.supported();
+                self.viewer.occlusion.settings.ui(ui, supported);
+            });
+
+            ui.collapsing("Beauty render", |ui| {
+                let Some(pathtracer) = &mut self.pathtracer else {
+                    ui.label("Ray queries are not supported by this GPU");
+                    return;
+                };
+                ui.add_enabled_ui(!pathtracer.rendering(), |ui| {
+                    pathtracer.settings.ui(ui);
+                });
+                if let Some(progress) = pathtracer.progress() {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::ProgressBar::new(progress)
+                                .desired_width(120.0)
+                                .show_percentage(),
+                        );
+                        if ui.button("Cancel").clicked() {
+                            pathtracer.cancel();
+                        }
+                    });
+                } else if ui
+                    .add_enabled(
+                        !self.viewer.renderer.models.is_empty(),
+                        egui::Button::new("Render..."),
+                    )
+                    .clicked()
+                {
+                    self.file_picker.render();
+                }
+            });
+
+            ui.collapsing("GPU", |ui| {
+                self.gpu_switch = self.gpu.ui(ui).or(self.gpu_switch.take());
+            });
+
+            ui.collapsing("Stats", |ui| {
+                ui.checkbox(&mut self.stats.overlay, "Show overlay");
+                self.allocators.memory.ui(ui);
+                let (samplers, sets) = self.viewer.loader.cache.counts();
+                ui.label(format!(
+                    "Cached samplers: {samplers}, material sets: {sets}"
+                ));
+                let duplicates: usize = self
+                    .viewer
+                    .renderer
+                    .models
+                    .iter()
+                    .map(|model| model.vktf.vktf.duplicate_images())
+                    .sum();
+                if duplicates > 0 {
+                    ui.label(format!("Duplicate images shared: {duplicates}"));
+                }
+                let compression = self
+                    .allocators
+                    .mem
+                    .device()
+                    .enabled_features()
+                    .texture_compression_bc;
+                self.viewer.loader.geometry_options.ui(ui);
+                self.viewer.loader.texture_options.ui(ui, compression);
+                self.viewer.loader.disk_cache.ui(ui);
+                if let Some(stats) = self
+                    .viewer
+                    .renderer
+                    .models
+                    .iter()
+                    .filter_map(GltfRenderInfo::vertex_cache)
+                    .reduce(|mut sum, stats| {
+                        sum += stats;
+                        sum
+                    })
+                {
+                    ui.label(format!(
+                        "Vertex cache ACMR: {:.2} → {:.2}",
+                        stats.acmr_before(),
+                        stats.acmr_after()
+                    ))
+                    .on_hover_text("Vertices shaded per triangle before and after optimizing");
+                }
+            });
+
+            ui.separator();
+        });
+
+        if self.stats.overlay {
+            let triangles = self
+                .viewer
+                .renderer
+                .models
+                .iter()
+                .map(GltfRenderInfo::triangles)
+                .sum();
+            self.stats.ui(ctx, triangles);
+        }
+        if self.histogram.enabled {
+            self.histogram.window(ctx);
+        }
+        self.texture_inspector
+            .window(ctx, &self.viewer.renderer.models);
+        self.uv_layout.window(ctx);
+        self.console.window(ctx, || {
+            console::scope(&self.camera, &self.viewer.renderer.models)
+        });
+        self.gallery.update(&self.allocators, &self.queue);
+        match self.gallery.window(ctx) {
+            Some(GalleryAction::Load(path)) => self.load_model(path),
+            Some(GalleryAction::PickFolder) => self.file_picker.gallery(),
+            None => {}
+        }
+        self.batch.update(&self.allocators, &self.queue);
+        self.hdr_capture.update(&self.allocators, &self.queue);
+        self.batch.window(ctx);
+        let after = self.edit_snapshot();
+        self.undo.record(ctx, before, &after);
+
+        let dt = ctx.input(|i| i.stable_dt);
+        for info in &mut self.viewer.renderer.models {
+            if let Some(fov) = info.animate(dt) {
+                self.camera.set_fov(fov);
+            }
+        }
+
+        egui::CentralPanel::default()
+            .frame(egui::Frame::NONE)
+            .show(ctx, |ui| {
+                let (rect, response) =
+                    ui.allocate_exact_size(ui.available_size(), egui::Sense::all());
+                self.aspect = rect.aspect_ratio();
+
+                let eye = self.camera.eye();
+                self.camera.input(&response);
+                if self.camera.mode == CameraMode::Fly {
+                    self.camera.fly.position = self.collision.slide(eye, self.camera.fly.position);
+                }
+                if response.dragged() {
+                    self.bookmarks.stop();
+                    self.camera_path.stop();
+                }
+                if self.bookmarks.playing() {
+                    self.camera_path.stop();
+                }
+                self.bookmarks.update(&mut self.camera, dt);
+                self.camera_path.update(&mut self.camera, dt);
+
+                let size = rect.size() * ctx.pixels_per_point();
+                let size = [size.x as u32, size.y as u32];
+                let offset = rect.min * ctx.pixels_per_point();
+                self.viewport = ([offset.x as u32, offset.y as u32], size);
+                let raytraced = match (&mut self.raytracer, &self.acceleration) {
+                    (Some(raytracer), Some(acceleration))
+                        if self.render_mode == RenderMode::Raytracer =>
+                    {
+                        raytracer.resize(size);
+                        raytracer
+                            .ready(acceleration)
+                            .then(|| raytracer.texture(index))
+                            .flatten()
+                    }
+                    _ => None,
+                };
+                self.post.offscreen = self.histogram.enabled;
+                self.post_process = raytraced.is_none() && self.post.active();
+                // the screen space effects follow the scaled scene
+                let scene_size = if self.post_process {
+                    self.post.resize(size);
+                    self.post.extent()
+                } else {
+                    size
+                };
+                self.viewer.occlusion.extent = scene_size;
+                self.viewer.ssao.extent = scene_size;
+                let texture = raytraced.or_else(|| {
+                    self.post_process
+                        .then(|| self.post.texture(index))
+                        .flatten()
+                });
+                if let Some(texture) = texture {
+                    ui.painter().image(
+                        texture,
+                        rect,
+                        egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                        egui::Color32::WHITE,
+                    );
+                    if self.post_process {
+                        self.compare.handle(ui, rect);
+                    }
+                } else if !self.post_process {
+                    let frame = self.frame(index);
+                    let reference = self
+                        .compare
+                        .reference(&self.viewer.renderer, &self.skybox.renderer)
+                        .map(|(viewer, skybox)| SceneFrame {
+                            viewer,
+                            skybox,
+                            heatmap: None,
+                            ..frame.clone()
+                        });
+                    let split = self.compare.split;
+                    let recorder = self.stats.recorder();
+                    let callback = egui::PaintCallback {
+                        rect,
+                        callback: Arc::new(CallbackFn::new(move |info, context| {
+                            let Some(reference) = &reference else {
+                                *recorder.lock().unwrap() += frame.render(context.builder);
+                                return;
+                            };
+                            // A left of the split, B right of it
+                            let [left, right] = split_scissors(&info, split);
+                            for (frame, scissor) in [(reference, left), (&frame, right)] {
+                                let Some(scissor) = scissor else {
+                                    continue;
+                                };
+                                context
+                                    .builder
+                                    .set_scissor(0, [scissor].into_iter().collect())
+                                    .unwrap();
+                                *recorder.lock().unwrap() += frame.render(context.builder);
+                            }
+                        })),
+                    };
+                    ui.painter().add(callback);
+                    self.compare.handle(ui, rect);
+                }
+                if self.viewer.debug_geometry.settings.skeletons {
+                    let view_proj = self.camera.perspective(self.aspect) * self.camera.look_at();
+                    skeleton::paint_skeletons(
+                        ui.painter(),
+                        rect,
+                        response.hover_pos(),
+                        &self.viewer.renderer.models,
+                        &view_proj,
+                    );
+                }
+            });
+    }
+}
+
+/// The models, environment and camera of a window, used to open another one like it.
+pub struct SceneSnapshot {
+    pub models: Vec<PathBuf>,
+    pub skybox: Option<SkyboxSource>,
+    pub camera: Camera,
+}
+
+/// A node of a loaded model, see [`State::list_nodes`].
+#[derive(Debug, Clone)]
+pub struct NodeInfo {
+    /// Index of the model in load order.
+    pub model: usize,
+    /// glTF index of the node in its model.
+    pub index: usize,
+    pub parent: Option<usize>,
+    pub name: Option<String>,
+    /// Hidden nodes also hide their children.
+    pub visible: bool,
+}
+
+/// Everything needed to record the scene for one frame inside the main subpass.
+#[derive(Clone)]
+struct SceneFrame {
+    index: usize,
+    skybox: SkyboxRenderer,
+    viewer: ViewerRenderer,
+    grid: Grid,
+    shadow_catcher: ShadowCatcher,
+    debug_geometry: DebugGeometry,
+    points: Points,
+    /// Drawn in place of the compared models.
+    heatmap: Option<HeatmapFrame>,
+    camera_set: Arc<DescriptorSet>,
+    lights_set: Arc<DescriptorSet>,
+}
+impl SceneFrame {
+    fn render<L>(&self, builder: &mut AutoCommandBufferBuilder<L>) -> DrawStats {
+        let stats = self.render_scene(builder);
+        self.debug_geometry.render(
+            builder,
+            self.camera_set.clone(),
+            &self.viewer.models,
+            self.index,
+        );
+        if self.skybox.background.show_models {
+            self.shadow_catcher
+                .render(builder, self.camera_set.clone(), self.lights_set.clone());
+        }
+        self.grid.render(builder, self.camera_set.clone());
+        stats
+    }
+    /// The models and the skybox without the grid and debug geometry.
+    fn render_scene<L>(&self, builder: &mut AutoCommandBufferBuilder<L>) -> DrawStats {
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.viewer.pipeline.pipeline.layout().clone(),
+                0,
+                self.camera_set.clone(),
+            )
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.viewer.pipeline.pipeline.layout().clone(),
+                3,
+                self.lights_set.clone(),
+            )
+            .unwrap();
+        let mut stats = DrawStats::default();
+        if self.skybox.background.show_models {
+            stats += self.viewer.render(builder, self.index);
+            if let Some(heatmap) = &self.heatmap {
+                stats += heatmap.render(builder, self.camera_set.clone(), self.index);
+            }
+            stats += self.points.render(
+                builder,
+                self.camera_set.clone(),
+                &self.viewer.models,
+                self.index,
+            );
+        }
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.skybox.pipeline.layout().clone(),
+                0,
+                self.camera_set.clone(),
+            )
+            .unwrap();
+        self.skybox.render(builder);
+        stats
+    }
+    /// Renders everything but transmissive primitives into the transmission source of this frame.
+    fn render_transmission_source<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        transmission: &Transmission,
+        lights_set: Arc<DescriptorSet>,
+    ) -> DrawStats {
+        let layout = transmission.pipeline.pipeline.layout().clone();
+        transmission.begin(builder, self.index);
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                layout.clone(),
+                0,
+                self.camera_set.clone(),
+            )
+            .unwrap()
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, layout, 3, lights_set)
+            .unwrap();
+        let mut stats = DrawStats::default();
+        if self.skybox.background.show_models {
+            stats += self
+                .viewer
+                .render_with(&transmission.pipeline, builder, self.index, true);
+        }
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                transmission.skybox_pipeline.layout().clone(),
+                0,
+                self.camera_set.clone(),
+            )
+            .unwrap();
+        SkyboxRenderer {
+            pipeline: transmission.skybox_pipeline.clone(),
+            ..self.skybox.clone()
+        }
+        .render(builder);
+        transmission.end(builder, self.index);
+        stats
+    }
+}
+
+fn material_ui(ui: &mut egui::Ui, material_push: &mut MaterialPush) {
+    ui.horizontal(|ui| {
+        let mut rgba = egui::Rgba::from_rgba_unmultiplied(
+            material_push.bc.x,
+            material_push.bc.y,
+            material_push.bc.z,
+            material_push.bc.w,
+        );
+        egui::color_picker::color_edit_button_rgba(
+            ui,
+            &mut rgba,
+            egui::color_picker::Alpha::OnlyBlend,
+        );
+        material_push.bc = rgba.to_rgba_unmultiplied().into();
+        ui.label("Base colour factor");
+    });
+
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::DragValue::new(&mut material_push.rm.x)
+                .range(0.0..=1.0)
+                .speed(0.01),
+        );
+        ui.label("Roughness factor");
+    });
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::DragValue::new(&mut material_push.rm.y)
+                .range(0.0..=1.0)
+                .speed(0.01),
+        );
+        ui.label("Metallness factor");
+    });
+
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::DragValue::new(&mut material_push.ao)
                 .range(0.0..=1.0)
                 .speed(0.01),
         );
@@ -433,4 +2578,456 @@ fn material_ui(ui: &mut egui::Ui, material_push: &mut MaterialPush) {
         ui.add(egui::DragValue::new(&mut material_push.nm).speed(0.01));
         ui.label("Normal scale");
     });
+
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::DragValue::new(&mut material_push.tr)
+                .range(0.0..=1.0)
+                .speed(0.01),
+        );
+        ui.label("Transmission factor");
+    });
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::DragValue::new(&mut material_push.th)
+                .range(0.0..=f32::MAX)
+                .speed(0.01),
+        );
+        ui.label("Thickness factor");
+    });
+    ui.horizontal(|ui| {
+        let mut rgb = material_push.at.data.0[0];
+        egui::color_picker::color_edit_button_rgb(ui, &mut rgb);
+        material_push.at = rgb.into();
+        ui.label("Attenuation colour");
+    });
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::DragValue::new(&mut material_push.ad)
+                .range(0.0..=f32::MAX)
+                .speed(0.01),
+        );
+        ui.label("Attenuation distance");
+    });
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::DragValue::new(&mut material_push.ior)
+                .range(1.0..=3.0)
+                .speed(0.01),
+        );
+        ui.label("IOR");
+    });
+}
+
+/// A button of a model in the settings panel that needs more than the model itself.
+enum ModelAction {
+    Reload,
+    Remove,
+    Export,
+    /// Export with the current frame of the animation as the rest pose.
+    ExportPose,
+    Report,
+    Simplify,
+    /// Pick an image for a texture of a material, `None` for the default material.
+    ReplaceTexture(Option<usize>, TextureSlot),
+    /// Show the UV layout of a primitive, by glTF mesh and primitive index.
+    UvLayout(usize, usize),
+}
+
+/// A menu of the textures of a material, returns the one to replace with an image file.
+fn texture_slot_ui(ui: &mut egui::Ui, loading: bool) -> Option<TextureSlot> {
+    let mut picked = None;
+    ui.add_enabled_ui(!loading, |ui| {
+        ui.menu_button("Replace texture", |ui| {
+            for slot in TextureSlot::ALL {
+                if ui.button(slot.name()).clicked() {
+                    picked = Some(slot);
+                    ui.close_menu();
+                }
+            }
+        });
+    });
+    picked
+}
+
+fn model_ui(
+    ui: &mut egui::Ui,
+    info: &mut GltfRenderInfo,
+    presets: &mut MaterialPresets,
+    loading: bool,
+) -> Option<ModelAction> {
+    let mut action = None;
+    let name = info
+        .vktf
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    ui.horizontal(|ui| {
+        ui.strong(name);
+        if ui
+            .add_enabled(!loading, egui::Button::new("Reload").small())
+            .clicked()
+        {
+            action = Some(ModelAction::Reload);
+        }
+        if ui
+            .add_enabled(!loading, egui::Button::new("Remove").small())
+            .clicked()
+        {
+            action = Some(ModelAction::Remove);
+        }
+        if ui
+            .add(egui::Button::new("Export").small())
+            .on_hover_text("Save as .glb with the edited materials")
+            .clicked()
+        {
+            action = Some(ModelAction::Export);
+        }
+        if ui
+            .add(egui::Button::new("Report").small())
+            .on_hover_text("Save a JSON summary of the meshes, textures, materials and animations")
+            .clicked()
+        {
+            action = Some(ModelAction::Report);
+        }
+    });
+    let points = info.points();
+    if points > 0 {
+        ui.label(format!("{points} points"));
+    }
+
+    ui.collapsing("Transform", |ui| {
+        let mut transform = info.transform();
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut transform.translation.x)
+                    .prefix("x: ")
+                    .speed(0.1),
+            );
+            ui.add(
+                egui::DragValue::new(&mut transform.translation.y)
+                    .prefix("y: ")
+                    .speed(0.1),
+            );
+            ui.add(
+                egui::DragValue::new(&mut transform.translation.z)
+                    .prefix("z: ")
+                    .speed(0.1),
+            );
+        });
+        ui.add(egui::Slider::new(&mut transform.yaw, -PI..=PI).text("Yaw"));
+        ui.add(
+            egui::DragValue::new(&mut transform.scale)
+                .prefix("Scale: ")
+                .range(0.001..=f32::MAX)
+                .speed(0.01),
+        );
+        transform.space.ui(ui);
+        info.set_transform(transform);
+    });
+
+    ui.collapsing("Simplify", |ui| {
+        ui.add(
+            egui::Slider::new(&mut info.lod_ratio, 0.01..=1.0)
+                .text("Triangle ratio")
+                .custom_formatter(|ratio, _| format!("{:.0}%", ratio * 100.0)),
+        );
+        if ui
+            .add_enabled(!loading, egui::Button::new("Generate"))
+            .on_hover_text("Simplify every mesh, the file is read again")
+            .clicked()
+        {
+            action = Some(ModelAction::Simplify);
+        }
+        if let Some((original, simplified)) = info.lod_triangles() {
+            let mut shown = info.lod_shown();
+            if ui.checkbox(&mut shown, "Show simplified").changed() {
+                info.show_lod(shown);
+            }
+            let saved = 1.0 - simplified as f64 / original.max(1) as f64;
+            ui.label(format!(
+                "Triangles: {original} → {simplified} ({:.0}% fewer)",
+                saved * 100.0
+            ));
+            if let Some(lod) = info.lod() {
+                ui.label(format!(
+                    "Ratio {:.0}%, error {:.2}%",
+                    lod.ratio * 100.0,
+                    lod.error * 100.0
+                ));
+            }
+        }
+    });
+
+    if !info.vktf.animations.is_empty() {
+        ui.collapsing("Animations", |ui| {
+            animations_ui(ui, info);
+            if ui
+                .button("Export pose")
+                .on_hover_text(
+                    "Save as .glb with the current frame as the rest pose, without animations",
+                )
+                .clicked()
+            {
+                action = Some(ModelAction::ExportPose);
+            }
+        });
+    }
+
+    ui.collapsing("Nodes", |ui| {
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| {
+                for root in info.scene.roots.clone() {
+                    node_ui(ui, &mut info.scene, root);
+                }
+            });
+    });
+
+    ui.collapsing("Scene", |ui| {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            variants_ui(ui, info);
+            ui.collapsing("Meshes", |ui| {
+                if let Some((mesh, primitive)) = meshes_ui(ui, &info.vktf) {
+                    action = Some(ModelAction::UvLayout(mesh, primitive));
+                }
+            });
+            for morph in info
+                .meshes
+                .iter_mut()
+                .filter_map(|mesh| mesh.morph.as_mut())
+            {
+                morph_ui(ui, morph);
+            }
+            for (i, (name, material)) in info
+                .vktf
+                .document
+                .materials()
+                .map(|m| m.name())
+                .zip(info.materials.index.iter_mut())
+                .enumerate()
+            {
+                ui.label(format!("{:?}", name));
+                material_ui(ui, &mut material.push);
+                presets.ui(ui, &mut material.push);
+                if let Some(slot) = texture_slot_ui(ui, loading) {
+                    action = Some(ModelAction::ReplaceTexture(Some(i), slot));
+                }
+            }
+            ui.label("Default");
+            material_ui(ui, &mut info.materials.default.push);
+            presets.ui(ui, &mut info.materials.default.push);
+            if let Some(slot) = texture_slot_ui(ui, loading) {
+                action = Some(ModelAction::ReplaceTexture(None, slot));
+            }
+        });
+    });
+
+    ui.collapsing("Lights", |ui| {
+        lights_ui(ui, &mut info.lights);
+    });
+
+    ui.collapsing("Metadata", |ui| {
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| {
+                metadata::metadata_ui(ui, &info.vktf.document);
+            });
+    });
+    action
+}
+
+/// The primitives of every mesh with their geometry, material and tangents.
+/// The primitives of every mesh, returns the mesh and primitive whose UV layout to show.
+fn meshes_ui(ui: &mut egui::Ui, vktf: &VktfDocument) -> Option<(usize, usize)> {
+    let mut uv_layout = None;
+    for mesh in vktf.document.meshes() {
+        let primitives = vktf.vktf.get_mesh(mesh.index()).unwrap_or_default();
+        let name = mesh
+            .name()
+            .map_or_else(|| format!("Mesh {}", mesh.index()), str::to_owned);
+        egui::CollapsingHeader::new(format!("{name} ({} primitives)", primitives.len()))
+            .id_salt(("mesh", mesh.index()))
+            .show(ui, |ui| {
+                egui::Grid::new(("primitives", mesh.index()))
+                    .num_columns(6)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for header in [
+                            "Primitive",
+                            "Vertices",
+                            "Indices",
+                            "Material",
+                            "Tangents",
+                            "",
+                        ] {
+                            ui.strong(header);
+                        }
+                        ui.end_row();
+                        for primitive in primitives {
+                            let info = &primitive.info;
+                            ui.label(info.index.to_string());
+                            let mut vertices = ui.label(info.vertices.to_string());
+                            if info.flat_normals {
+                                vertices = vertices.on_hover_text("Flat normals were generated");
+                            }
+                            if info.sparse {
+                                vertices.on_hover_text("Sparse accessors were applied");
+                            }
+                            ui.label(info.indices.map_or("-".to_owned(), |n| n.to_string()));
+                            ui.label(
+                                info.material
+                                    .map_or("Default".to_owned(), |i| i.to_string()),
+                            );
+                            ui.label(info.tangents.name());
+                            if ui.small_button("UV").clicked() {
+                                uv_layout = Some((mesh.index(), info.index));
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+    uv_layout
+}
+
+/// Picks the `KHR_materials_variants` variant of the model, if it has any.
+fn variants_ui(ui: &mut egui::Ui, info: &mut GltfRenderInfo) {
+    let variants = info.variants();
+    if variants.is_empty() {
+        return;
+    }
+    let mut variant = info.variant();
+    let name = |variant: Option<usize>| {
+        variant
+            .and_then(|i| variants.get(i))
+            .map_or("Default", String::as_str)
+    };
+    egui::ComboBox::from_label("Material variant")
+        .selected_text(name(variant))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut variant, None, "Default");
+            for (i, name) in variants.iter().enumerate() {
+                ui.selectable_value(&mut variant, Some(i), name);
+            }
+        });
+    info.set_variant(variant);
+}
+
+fn morph_ui(ui: &mut egui::Ui, morph: &mut Morph) {
+    ui.label(&morph.name);
+    for (i, weight) in morph.weights.iter_mut().enumerate() {
+        ui.add(egui::Slider::new(weight, 0.0..=1.0).text(format!("Target {i}")));
+    }
+}
+
+/// The translation of a node and its children.
+/// Playback of the `KHR_animation_pointer` animations.
+fn animations_ui(ui: &mut egui::Ui, info: &mut GltfRenderInfo) {
+    let animations = &info.vktf.animations;
+    let player = &mut info.player;
+    let Some(animation) = animations.get(player.animation) else {
+        return;
+    };
+    egui::ComboBox::from_label("Animation")
+        .selected_text(&animation.name)
+        .show_ui(ui, |ui| {
+            for (i, animation) in animations.iter().enumerate() {
+                if ui
+                    .selectable_value(&mut player.animation, i, &animation.name)
+                    .changed()
+                {
+                    player.time = 0.0;
+                    player.scrubbed = true;
+                }
+            }
+        });
+    let animation = &animations[player.animation];
+    ui.horizontal(|ui| {
+        let label = if player.playing { "Pause" } else { "Play" };
+        if ui.button(label).clicked() {
+            if !player.playing && player.time >= animation.duration {
+                player.time = 0.0;
+            }
+            player.playing = !player.playing;
+        }
+        ui.checkbox(&mut player.looping, "Loop");
+    });
+    if ui
+        .add(egui::Slider::new(&mut player.time, 0.0..=animation.duration).text("Time (s)"))
+        .changed()
+    {
+        player.scrubbed = true;
+    }
+    ui.add(
+        egui::Slider::new(&mut player.speed, 0.1..=4.0)
+            .logarithmic(true)
+            .text("Speed"),
+    );
+    ui.label(format!("{} animated properties", animation.tracks.len()))
+        .on_hover_text("A camera field of view animates the viewer camera");
+    for (pointer, reason) in &animation.ignored {
+        ui.weak(format!("Ignored {pointer}: {reason}"));
+    }
+}
+
+fn node_ui(ui: &mut egui::Ui, scene: &mut SceneGraph, index: usize) {
+    let node = &scene.nodes[index];
+    let name = node.name.clone().unwrap_or_else(|| format!("Node {index}"));
+    let children = node.children.clone();
+    egui::CollapsingHeader::new(name)
+        .id_salt(index)
+        .show(ui, |ui| {
+            let mut local = scene.nodes[index].local;
+            ui.horizontal(|ui| {
+                for (row, axis) in ["x: ", "y: ", "z: "].into_iter().enumerate() {
+                    ui.add(
+                        egui::DragValue::new(&mut local[(row, 3)])
+                            .prefix(axis)
+                            .speed(0.01),
+                    );
+                }
+            });
+            scene.set_local(index, local);
+            for child in children {
+                node_ui(ui, scene, child);
+            }
+        });
+}
+
+fn lights_ui(ui: &mut egui::Ui, lights: &mut Vec<Light>) {
+    let mut remove = None;
+    for (i, light) in lights.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.label(format!("{i}: {}", light.kind_name()));
+            if ui.small_button("Remove").clicked() {
+                remove = Some(i);
+            }
+        });
+        ui.horizontal(|ui| {
+            let mut rgb = light.color.data.0[0];
+            egui::color_picker::color_edit_button_rgb(ui, &mut rgb);
+            light.color = rgb.into();
+            ui.label("Colour");
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut light.intensity)
+                    .range(0.0..=f32::MAX)
+                    .speed(0.1),
+            );
+            ui.label("Intensity");
+        });
+    }
+    if let Some(i) = remove {
+        lights.remove(i);
+    }
+
+    if lights.is_empty() {
+        ui.label("No lights in scene");
+        if ui.button("Add sun").clicked() {
+            lights.push(Light::sun());
+        }
+    }
 }
@@ -1,37 +1,91 @@
+use clap::Parser;
 use egui_winit_vulkano::{Gui, GuiConfig};
-use frameinfo::FrameInfo;
-use gltf_viewer::{Allocators, State};
-use std::sync::Arc;
+use gltf_viewer::{
+    Allocators, GltfViewerRenderer, RenderTarget, SceneSnapshot,
+    camera::Camera,
+    frameinfo::{Depth, Msaa},
+    gpu,
+    headless::{HeadlessOptions, render_to_file},
+    recovery::{self, Recovery},
+    remote_control::{RemoteControl, RemoteStatus},
+    screenshot::Screenshot,
+};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 use vulkano::{
-    command_buffer::{
-        AutoCommandBufferBuilder, CommandBufferUsage, SubpassBeginInfo, SubpassContents,
-        allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo},
-    },
-    descriptor_set::allocator::StandardDescriptorSetAllocator,
-    device::{DeviceExtensions, DeviceFeatures},
-    format::Format,
+    Validated, Version, VulkanLibrary,
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage},
+    device::{DeviceExtensions, DeviceFeatures, physical::PhysicalDevice},
+    format::{Format, NumericFormat},
     image::ImageUsage,
     instance::{
-        InstanceCreateInfo,
+        Instance, InstanceCreateInfo,
         debug::{
             DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessengerCallback,
             DebugUtilsMessengerCreateInfo,
         },
     },
-    swapchain::Surface,
+    swapchain::{ColorSpace, Surface, SwapchainCreateInfo},
     sync::GpuFuture,
 };
 use vulkano_util::{
     context::{VulkanoConfig, VulkanoContext},
+    renderer::VulkanoWindowRenderer,
     window::{VulkanoWindows, WindowDescriptor},
 };
 use winit::{
     application::ApplicationHandler,
     event::{DeviceEvent, WindowEvent},
     event_loop::{ActiveEventLoop, EventLoop},
+    raw_window_handle::HasDisplayHandle,
+    window::WindowId,
 };
 
-mod frameinfo;
+/// Swapchain formats in order of preference. The shaders write linear colors, so without
+/// an sRGB format the image is too dark, but it still beats not starting at all.
+const SWAPCHAIN_FORMATS: [Format; 4] = [
+    Format::B8G8R8A8_SRGB,
+    Format::R8G8B8A8_SRGB,
+    Format::B8G8R8A8_UNORM,
+    Format::R8G8B8A8_UNORM,
+];
+
+/// The swapchain format of new windows. vulkano_util configures the swapchain with a
+/// function pointer, so the negotiated format is passed through here.
+static SWAPCHAIN_FORMAT: Mutex<(Format, ColorSpace)> =
+    Mutex::new((Format::B8G8R8A8_SRGB, ColorSpace::SrgbNonLinear));
+
+#[derive(Parser)]
+#[command(about = "glTF 2.0 viewer")]
+struct Args {
+    /// glTF, glb, OBJ, STL or PLY file to open
+    model: Option<PathBuf>,
+    /// Equirectangular HDR image used as the environment
+    #[arg(long)]
+    skybox: Option<PathBuf>,
+    /// Render a single frame to `--output` without opening a window
+    #[arg(long)]
+    headless: bool,
+    #[arg(long, default_value = "out.png")]
+    output: PathBuf,
+    #[arg(long, default_value_t = 1024)]
+    width: u32,
+    #[arg(long, default_value_t = 1024)]
+    height: u32,
+    /// Upload textures uncompressed even if the GPU supports BC compression
+    #[arg(long)]
+    no_texture_compression: bool,
+    /// GPU to use, its index or a part of its name
+    #[arg(long)]
+    gpu: Option<String>,
+    /// Accept HTTP requests to load models, move the camera and take screenshots on this
+    /// address, like 127.0.0.1:7878
+    #[arg(long, value_name = "ADDR")]
+    remote_control: Option<String>,
+}
 
 fn debug_info() -> DebugUtilsMessengerCreateInfo {
     DebugUtilsMessengerCreateInfo {
@@ -68,165 +122,374 @@ fn debug_info() -> DebugUtilsMessengerCreateInfo {
     }
 }
 
-struct Window {
-    gui: Gui,
-    frame_info: FrameInfo,
-    state: State,
-    frame: usize,
-    num_frames: usize,
+/// The device the context will pick, looked up beforehand to know its optional features.
+fn preferred_device(preference: Option<&str>) -> Option<Arc<PhysicalDevice>> {
+    let library = VulkanLibrary::new().ok()?;
+    let instance = Instance::new(library, InstanceCreateInfo::default()).ok()?;
+    instance
+        .enumerate_physical_devices()
+        .ok()?
+        .filter(|device| device.supported_extensions().khr_swapchain)
+        .min_by_key(|device| gpu::priority(device, preference))
+}
+
+/// Ray tracing, texture compression and large points are optional, only require them if the device can do it.
+fn optional_supported(
+    device: Option<&PhysicalDevice>,
+    version: Version,
+    extensions: &DeviceExtensions,
+    features: &DeviceFeatures,
+) -> bool {
+    device.is_some_and(|device| {
+        device.api_version() >= version
+            && device.supported_extensions().contains(extensions)
+            && device.supported_features().contains(features)
+    })
 }
-impl Window {
-    pub fn frame_index(&self) -> usize {
-        self.frame % self.num_frames
+
+fn create_context(event_loop: &impl HasDisplayHandle, preference: Option<&str>) -> VulkanoContext {
+    let debug_info = if cfg!(debug_assertions) {
+        Some(debug_info())
+    } else {
+        None
+    };
+    let mut required_extensions = Surface::required_extensions(event_loop).unwrap();
+    if debug_info.is_some() {
+        required_extensions.ext_debug_utils = true;
+    }
+    let device = preferred_device(preference);
+    let device = device.as_deref();
+    let mut device_extensions = DeviceExtensions {
+        khr_swapchain: true,
+        ..Default::default()
+    };
+    let mut device_features = DeviceFeatures {
+        sampler_anisotropy: true,
+        ..Default::default()
+    };
+    let acceleration_extensions = DeviceExtensions {
+        khr_acceleration_structure: true,
+        khr_deferred_host_operations: true,
+        ..Default::default()
+    };
+    let acceleration_features = DeviceFeatures {
+        buffer_device_address: true,
+        acceleration_structure: true,
+        ..Default::default()
+    };
+    // the ray tracing preview and the hybrid mode are enabled independently
+    let ray_tracing = [
+        (
+            DeviceExtensions {
+                khr_ray_tracing_pipeline: true,
+                ..acceleration_extensions
+            },
+            DeviceFeatures {
+                ray_tracing_pipeline: true,
+                ..acceleration_features
+            },
+        ),
+        (
+            DeviceExtensions {
+                khr_ray_query: true,
+                ..acceleration_extensions
+            },
+            DeviceFeatures {
+                ray_query: true,
+                ..acceleration_features
+            },
+        ),
+    ];
+    for (extensions, features) in ray_tracing {
+        if optional_supported(device, Version::V1_2, &extensions, &features) {
+            device_extensions = device_extensions.union(&extensions);
+            device_features = device_features.union(&features);
+        }
     }
+    let compression = DeviceFeatures {
+        texture_compression_bc: true,
+        ..Default::default()
+    };
+    if optional_supported(
+        device,
+        Version::V1_0,
+        &DeviceExtensions::empty(),
+        &compression,
+    ) {
+        device_features = device_features.union(&compression);
+    }
+    let large_points = DeviceFeatures {
+        large_points: true,
+        ..Default::default()
+    };
+    if optional_supported(
+        device,
+        Version::V1_0,
+        &DeviceExtensions::empty(),
+        &large_points,
+    ) {
+        device_features = device_features.union(&large_points);
+    }
+    let wireframe = DeviceFeatures {
+        fill_mode_non_solid: true,
+        ..Default::default()
+    };
+    if optional_supported(
+        device,
+        Version::V1_0,
+        &DeviceExtensions::empty(),
+        &wireframe,
+    ) {
+        device_features = device_features.union(&wireframe);
+    }
+    let preference = preference.map(str::to_owned);
+    VulkanoContext::new(VulkanoConfig {
+        instance_create_info: InstanceCreateInfo {
+            enabled_extensions: required_extensions,
+            enabled_layers: if debug_info.is_some() {
+                vec!["VK_LAYER_KHRONOS_validation".to_owned()]
+            } else {
+                vec![]
+            },
+            debug_utils_messengers: debug_info
+                .clone()
+                .map(|info| vec![info])
+                .unwrap_or_default(),
+            ..Default::default()
+        },
+        debug_create_info: debug_info,
+        device_extensions,
+        device_features,
+        print_device_name: true,
+        device_priority_fn: Arc::new(move |device| gpu::priority(device, preference.as_deref())),
+        ..Default::default()
+    })
+}
+
+/// Picks the preferred swapchain format the surface of a hidden window supports,
+/// otherwise the first one it reports.
+fn negotiate_swapchain_format(
+    event_loop: &ActiveEventLoop,
+    context: &VulkanoContext,
+) -> (Format, ColorSpace) {
+    let window = event_loop
+        .create_window(winit::window::Window::default_attributes().with_visible(false))
+        .unwrap();
+    let surface = Surface::from_window(context.instance().clone(), Arc::new(window)).unwrap();
+    let formats = context
+        .device()
+        .physical_device()
+        .surface_formats(&surface, Default::default())
+        .unwrap();
+    SWAPCHAIN_FORMATS
+        .into_iter()
+        .find_map(|format| {
+            formats
+                .iter()
+                .find(|(other, color_space)| {
+                    *other == format && *color_space == ColorSpace::SrgbNonLinear
+                })
+                .copied()
+        })
+        .unwrap_or(formats[0])
+}
+
+fn configure_swapchain(swapchain_info: &mut SwapchainCreateInfo) {
+    (
+        swapchain_info.image_format,
+        swapchain_info.image_color_space,
+    ) = *SWAPCHAIN_FORMAT.lock().unwrap();
+    swapchain_info.image_usage |= ImageUsage::TRANSFER_DST | ImageUsage::TRANSFER_SRC;
+}
+
+fn create_gui(
+    event_loop: &ActiveEventLoop,
+    renderer: &VulkanoWindowRenderer,
+    viewer: &GltfViewerRenderer,
+) -> Gui {
+    Gui::new_with_subpass(
+        event_loop,
+        renderer.surface(),
+        renderer.graphics_queue(),
+        viewer.subpass().clone(),
+        renderer.swapchain_format(),
+        GuiConfig {
+            allow_srgb_render_target: true,
+            ..Default::default()
+        },
+    )
+}
+
+struct Window {
+    gui: Gui,
+    viewer: GltfViewerRenderer,
 }
 
 struct App {
     context: VulkanoContext,
     windows: VulkanoWindows,
     allocators: Allocators,
-    window: Option<Window>,
+    /// The viewer of every open window, the primary one was opened first.
+    views: HashMap<WindowId, Window>,
+    /// The GPU asked for on the command line or picked in the UI.
+    gpu: Option<String>,
+    /// Drives the first window.
+    remote: Option<RemoteControl>,
+    args: Args,
 }
 impl App {
-    fn new(event_loop: &EventLoop<()>) -> Self {
-        let debug_info = if cfg!(debug_assertions) {
-            Some(debug_info())
-        } else {
-            None
-        };
-        let mut required_extensions = Surface::required_extensions(event_loop).unwrap();
-        if debug_info.is_some() {
-            required_extensions.ext_debug_utils = true;
-        }
-        let device_extensions = DeviceExtensions {
-            khr_swapchain: true,
-            khr_ray_tracing_pipeline: true,
-            khr_deferred_host_operations: true,
-            ..Default::default()
-        };
-        let device_features = DeviceFeatures {
-            ray_tracing_pipeline: true,
-            buffer_device_address: true,
-            acceleration_structure: true,
-            sampler_anisotropy: true,
-            ..Default::default()
-        };
-        let context = VulkanoContext::new(VulkanoConfig {
-            instance_create_info: InstanceCreateInfo {
-                enabled_extensions: required_extensions,
-                enabled_layers: if debug_info.is_some() {
-                    vec!["VK_LAYER_KHRONOS_validation".to_owned()]
-                } else {
-                    vec![]
-                },
-                debug_utils_messengers: debug_info
-                    .clone()
-                    .map(|info| vec![info])
-                    .unwrap_or_default(),
-                ..Default::default()
-            },
-            debug_create_info: debug_info,
-            device_extensions,
-            device_features,
-            print_device_name: true,
-            device_priority_fn: Arc::new(|_| 0),
-            ..Default::default()
-        });
+    fn new(event_loop: &EventLoop<()>, args: Args, remote: Option<RemoteControl>) -> Self {
+        let gpu = args.gpu.clone().or_else(gpu::saved_preference);
+        let context = create_context(event_loop, gpu.as_deref());
 
         let windows = VulkanoWindows::default();
 
-        let cmd_allocator = Arc::new(StandardCommandBufferAllocator::new(
-            context.device().clone(),
-            StandardCommandBufferAllocatorCreateInfo {
-                primary_buffer_count: 16,
-                secondary_buffer_count: 16,
-                ..Default::default()
-            },
-        ));
-        let set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
-            context.device().clone(),
-            Default::default(),
-        ));
-
-        let allocators = Allocators {
-            cmd: cmd_allocator,
-            mem: context.memory_allocator().clone(),
-            set: set_allocator,
-        };
+        let allocators =
+            Allocators::new(context.device().clone(), context.memory_allocator().clone());
 
         Self {
             context,
             windows,
             allocators,
-            window: None,
+            views: HashMap::new(),
+            gpu,
+            remote,
+            args,
         }
     }
 }
-impl ApplicationHandler for App {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        self.windows.create_window(
+impl App {
+    /// Creates a window with its own swapchain, UI and viewer state.
+    fn open_window(&mut self, event_loop: &ActiveEventLoop) -> WindowId {
+        // every window uses the same device, so the format only has to be found once per device
+        if self.views.is_empty() {
+            let (format, color_space) = negotiate_swapchain_format(event_loop, &self.context);
+            *SWAPCHAIN_FORMAT.lock().unwrap() = (format, color_space);
+            if format.numeric_format_color() == Some(NumericFormat::SRGB) {
+                log::info!("swapchain format {format:?} {color_space:?}");
+            } else {
+                log::warn!("no sRGB swapchain format, using {format:?}, colors will be too dark");
+            }
+        }
+        let title = match self.views.len() {
+            0 => "glTF Viewer".to_owned(),
+            n => format!("glTF Viewer ({})", n + 1),
+        };
+        let id = self.windows.create_window(
             event_loop,
             &self.context,
             &WindowDescriptor {
-                title: "glTF Viewer".into(),
+                title,
                 ..Default::default()
             },
-            |swapchain_info| {
-                swapchain_info.image_format = Format::B8G8R8A8_SRGB;
-                // swapchain_info.image_format = Format::B8G8R8A8_UNORM;
-                swapchain_info.image_usage |= ImageUsage::TRANSFER_DST;
-            },
-        );
-        let renderer = self.windows.get_primary_renderer_mut().unwrap();
-
-        let frame_info = FrameInfo::new(
-            self.allocators.mem.clone(),
-            renderer.swapchain_image_views(),
+            configure_swapchain,
         );
+        let renderer = self.windows.get_renderer_mut(id).unwrap();
 
-        let gui = Gui::new_with_subpass(
-            event_loop,
-            renderer.surface(),
-            renderer.graphics_queue(),
-            frame_info.subpass().clone(),
-            renderer.swapchain_format(),
-            GuiConfig {
-                allow_srgb_render_target: true,
-                ..Default::default()
+        let device = self.context.device();
+        let msaa = Msaa::default().or_supported(device);
+        let depth = Depth::default().or_supported(device);
+        if msaa != Msaa::default() || depth != Depth::default() {
+            log::warn!(
+                "falling back to {} anti-aliasing and {} depth",
+                msaa.name(),
+                depth.precision.name()
+            );
+        }
+        let mut viewer = GltfViewerRenderer::new(
+            &self.allocators,
+            self.context.graphics_queue().clone(),
+            Some(self.context.compute_queue().clone()),
+            RenderTarget {
+                views: renderer.swapchain_image_views().to_vec(),
+                msaa,
+                depth,
             },
+            Camera::default(),
         );
+        let state = viewer.state_mut();
+        state.load_settings();
+        if self.args.no_texture_compression {
+            state.set_texture_compression(false);
+        }
 
-        let num_frames = renderer.swapchain_image_views().len() + 1;
+        let gui = create_gui(event_loop, renderer, &viewer);
 
-        let state = State::new(
-            &self.allocators,
-            self.context.graphics_queue().clone(),
-            num_frames,
-            frame_info.subpass().clone(),
-        );
+        self.views.insert(id, Window { gui, viewer });
+        id
+    }
 
-        self.window = Some(Window {
-            gui,
-            frame_info,
-            state,
-            frame: 0,
-            num_frames,
-        });
+    /// Recreates the device and the windows on another GPU, only the scene of the window
+    /// that asked for it is kept.
+    fn switch_gpu(&mut self, event_loop: &ActiveEventLoop, gpu: String, snapshot: SceneSnapshot) {
+        self.gpu = Some(gpu);
+        self.recreate(event_loop, snapshot);
+    }
+    /// Recreates the device and opens one window with `snapshot`, also after the device was lost.
+    fn recreate(&mut self, event_loop: &ActiveEventLoop, snapshot: SceneSnapshot) -> WindowId {
+        // a lost device can't be waited on, but nothing runs on it anymore either
+        if let Err(err) = unsafe { self.context.device().wait_idle() } {
+            log::warn!("failed to wait for the device: {err}");
+        }
+        self.views.clear();
+        self.windows = VulkanoWindows::default();
+
+        self.context = create_context(event_loop, self.gpu.as_deref());
+        self.allocators = Allocators::new(
+            self.context.device().clone(),
+            self.context.memory_allocator().clone(),
+        );
+        let id = self.open_window(event_loop);
+        self.views
+            .get_mut(&id)
+            .unwrap()
+            .viewer
+            .state_mut()
+            .open_snapshot(snapshot);
+        id
+    }
+}
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if !self.views.is_empty() {
+            return;
+        }
+        let id = self.open_window(event_loop);
+        let viewer = &mut self.views.get_mut(&id).unwrap().viewer;
+        if let Some(path) = self.args.skybox.take() {
+            viewer.load_skybox(path);
+        } else {
+            viewer.load_sky(Default::default());
+        }
+        if let Some(path) = self.args.model.take() {
+            viewer.load_model(path);
+        }
     }
 
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
-        _window_id: winit::window::WindowId,
+        window_id: WindowId,
         event: WindowEvent,
     ) {
-        let renderer = self.windows.get_primary_renderer_mut().unwrap();
-        let window = self.window.as_mut().unwrap();
+        let (Some(renderer), Some(window)) = (
+            self.windows.get_renderer_mut(window_id),
+            self.views.get_mut(&window_id),
+        ) else {
+            return;
+        };
 
         window.gui.update(&event);
         match event {
             WindowEvent::CloseRequested => {
-                event_loop.exit();
+                window.viewer.state_mut().save_settings();
+                // closing the first window quits, the others are only comparisons
+                if Some(window_id) == self.windows.primary_window_id() {
+                    event_loop.exit();
+                } else {
+                    self.views.remove(&window_id);
+                    self.windows.remove_renderer(window_id);
+                }
             }
             WindowEvent::Resized(_) => {
                 renderer.resize();
@@ -235,64 +498,128 @@ impl ApplicationHandler for App {
                 renderer.resize();
             }
             WindowEvent::RedrawRequested => {
-                let frame_index = window.frame_index();
-                window.frame += 1;
+                // the egui pipeline belongs to the render pass, so the whole UI is recreated
+                if window.viewer.attachments_changed() {
+                    window
+                        .viewer
+                        .apply_attachments(renderer.swapchain_image_views());
+                    window.gui = create_gui(event_loop, renderer, &window.viewer);
+                }
 
-                window.gui.immediate_ui(|gui| {
-                    window.state.show(&gui.egui_ctx, frame_index);
-                });
+                window.viewer.show(&mut window.gui);
+                let state = window.viewer.state_mut();
+                let new_window = state.take_new_window();
+                let gpu_switch = state.take_gpu_switch().map(|gpu| (gpu, state.snapshot()));
 
-                match renderer.acquire(None, |views| {
-                    window.frame_info.recreate(views);
-                }) {
-                    Ok(before_future) => {
+                let frame = renderer
+                    .acquire(None, |views| {
+                        window.viewer.resize(views);
+                    })
+                    .map_err(Validated::Error)
+                    .and_then(|before_future| {
                         let mut builder = AutoCommandBufferBuilder::primary(
                             self.allocators.cmd.clone(),
                             renderer.graphics_queue().queue_family_index(),
                             CommandBufferUsage::OneTimeSubmit,
-                        )
-                        .unwrap();
+                        )?;
 
-                        window.state.update(&mut builder, frame_index);
+                        window.viewer.render_ui(
+                            &mut builder,
+                            renderer.image_index() as usize,
+                            &mut window.gui,
+                        );
+                        let screenshot = window.viewer.state_mut().take_screenshot().and_then(
+                            |(offset, extent, path)| {
+                                Screenshot::record(
+                                    &mut builder,
+                                    self.allocators.mem.clone(),
+                                    renderer.swapchain_image_view().image().clone(),
+                                    offset,
+                                    extent,
+                                )
+                                .map(|screenshot| (screenshot, path))
+                            },
+                        );
 
-                        builder
-                            .begin_render_pass(
-                                window
-                                    .frame_info
-                                    .render_pass_info(renderer.image_index() as usize),
-                                SubpassBeginInfo {
-                                    contents: SubpassContents::SecondaryCommandBuffers,
-                                    ..Default::default()
-                                },
-                            )
-                            .unwrap();
-                        let cb = window
-                            .gui
-                            .draw_on_subpass_image(renderer.swapchain_image_size());
-                        builder.execute_commands(cb).unwrap();
-                        builder.end_render_pass(Default::default()).unwrap();
-
-                        let cb = builder.build().unwrap();
+                        let cb = builder.build()?;
                         let after_future = before_future
                             .then_execute(renderer.graphics_queue(), cb)
-                            .unwrap();
+                            .map_err(Validated::ValidationError)?;
 
-                        renderer.present(after_future.boxed(), false);
+                        // the copy has to be finished before it is read
+                        renderer.present(after_future.boxed(), screenshot.is_some());
+                        if let Some((screenshot, path)) = screenshot {
+                            screenshot.save(path, window.viewer.state().output_transform());
+                        }
+                        Ok(())
+                    });
+                // the window stays open whenever the frame can be dropped
+                let lost = match frame {
+                    Ok(()) => None,
+                    Err(Validated::ValidationError(err)) => {
+                        log::error!("failed to draw a frame: {err}");
+                        None
                     }
-                    Err(vulkano::VulkanError::OutOfDate) => {
-                        renderer.resize();
-                    }
-                    Err(e) => panic!("Failed to acquire swapchain future: {}", e),
+                    Err(Validated::Error(err)) => match Recovery::of(&err) {
+                        Recovery::RecreateSwapchain => {
+                            renderer.resize();
+                            None
+                        }
+                        Recovery::RecreateContext => {
+                            log::error!("{err}, recreating the device");
+                            Some((err, window.viewer.state().snapshot()))
+                        }
+                        Recovery::SkipFrame => {
+                            log::error!("failed to draw a frame: {err}");
+                            if recovery::is_out_of_memory(&err) {
+                                window
+                                    .viewer
+                                    .state_mut()
+                                    .report_error(recovery::describe("Failed to draw", &err));
+                            }
+                            None
+                        }
+                    },
                 };
-                self.windows.get_primary_window().unwrap().request_redraw();
+                renderer.window().request_redraw();
+
+                if let Some(snapshot) = new_window {
+                    let id = self.open_window(event_loop);
+                    self.views
+                        .get_mut(&id)
+                        .unwrap()
+                        .viewer
+                        .state_mut()
+                        .open_snapshot(snapshot);
+                }
+                if let Some((gpu, snapshot)) = gpu_switch {
+                    self.switch_gpu(event_loop, gpu, snapshot);
+                } else if let Some((err, snapshot)) = lost {
+                    let id = self.recreate(event_loop, snapshot);
+                    let viewer = &mut self.views.get_mut(&id).unwrap().viewer;
+                    viewer.state_mut().report_error(format!(
+                        "The GPU stopped responding ({err}), the scene was opened again"
+                    ));
+                }
             }
             _ => {}
         }
     }
 
     fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
-        let window = self.windows.get_primary_window().unwrap();
-        window.request_redraw();
+        let primary = self.windows.primary_window_id();
+        if let (Some(remote), Some(window)) =
+            (&self.remote, primary.and_then(|id| self.views.get_mut(&id)))
+        {
+            let state = window.viewer.state_mut();
+            for command in remote.commands() {
+                command.apply(state);
+            }
+            remote.set_status(RemoteStatus::new(state));
+        }
+        for (_, renderer) in self.windows.iter() {
+            renderer.window().request_redraw();
+        }
     }
 
     fn device_event(
@@ -302,12 +629,9 @@ impl ApplicationHandler for App {
         event: DeviceEvent,
     ) {
         if let DeviceEvent::MouseMotion { delta } = event {
-            self.window
-                .as_mut()
-                .unwrap()
-                .gui
-                .egui_winit
-                .on_mouse_motion(delta);
+            for window in self.views.values_mut() {
+                window.gui.egui_winit.on_mouse_motion(delta);
+            }
         }
     }
 }
@@ -315,8 +639,25 @@ impl ApplicationHandler for App {
 fn main() -> anyhow::Result<()> {
     colog::init();
 
+    let args = Args::parse();
+    if args.headless {
+        return render_to_file(HeadlessOptions {
+            model: args.model,
+            skybox: args.skybox,
+            output: args.output,
+            width: args.width,
+            height: args.height,
+            gpu: args.gpu,
+        });
+    }
+
+    let remote = args
+        .remote_control
+        .as_deref()
+        .map(RemoteControl::start)
+        .transpose()?;
     let event_loop = EventLoop::new()?;
-    let mut app = App::new(&event_loop);
+    let mut app = App::new(&event_loop, args, remote);
     event_loop.run_app(&mut app)?;
 
     Ok(())
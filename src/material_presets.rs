@@ -0,0 +1,124 @@
+use crate::vktf::material::MaterialPush;
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+/// The factors of a material, without the texture coordinate sets that depend on the model.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MaterialFactors {
+    pub base_color: glm::Vec4,
+    pub emissive: glm::Vec3,
+    pub occlusion: f32,
+    pub roughness: f32,
+    pub metallic: f32,
+    pub normal_scale: f32,
+    pub transmission: f32,
+    pub thickness: f32,
+    pub attenuation_color: glm::Vec3,
+    pub attenuation_distance: f32,
+    pub ior: f32,
+}
+impl MaterialFactors {
+    pub fn new(push: &MaterialPush) -> Self {
+        Self {
+            base_color: push.bc,
+            emissive: push.em,
+            occlusion: push.ao,
+            roughness: push.rm.x,
+            metallic: push.rm.y,
+            normal_scale: push.nm,
+            transmission: push.tr,
+            thickness: push.th,
+            attenuation_color: push.at,
+            attenuation_distance: push.ad,
+            ior: push.ior,
+        }
+    }
+    /// Replaces the factors of `push`, its textures stay.
+    pub fn apply(&self, push: &mut MaterialPush) {
+        push.bc = self.base_color;
+        push.em = self.emissive;
+        push.ao = self.occlusion;
+        push.rm = glm::vec2(self.roughness, self.metallic);
+        push.nm = self.normal_scale;
+        push.tr = self.transmission;
+        push.th = self.thickness;
+        push.at = self.attenuation_color;
+        push.ad = self.attenuation_distance;
+        push.ior = self.ior;
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterialPreset {
+    pub name: String,
+    pub factors: MaterialFactors,
+}
+
+/// Named factors saved from one material and applied to others, and the factors last copied.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialPresets {
+    pub list: Vec<MaterialPreset>,
+    pub copied: Option<MaterialFactors>,
+    #[serde(skip)]
+    name: String,
+}
+impl MaterialPresets {
+    /// Copy, paste and the presets for the material editor of `push`.
+    pub fn ui(&mut self, ui: &mut egui::Ui, push: &mut MaterialPush) {
+        ui.horizontal(|ui| {
+            if ui.button("Copy").clicked() {
+                self.copied = Some(MaterialFactors::new(push));
+            }
+            if ui
+                .add_enabled(self.copied.is_some(), egui::Button::new("Paste"))
+                .on_hover_text("Replace the factors with the copied ones, the textures stay")
+                .clicked()
+            {
+                if let Some(factors) = &self.copied {
+                    factors.apply(push);
+                }
+            }
+            ui.menu_button("Presets", |ui| {
+                let mut remove = None;
+                for (i, preset) in self.list.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.button(&preset.name).clicked() {
+                            preset.factors.apply(push);
+                            ui.close_menu();
+                        }
+                        if ui.small_button("Remove").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    self.list.remove(i);
+                }
+                if !self.list.is_empty() {
+                    ui.separator();
+                }
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.name).desired_width(120.0));
+                    if ui
+                        .button("Save")
+                        .on_hover_text("Save the factors of this material, replacing a preset of the same name")
+                        .clicked()
+                    {
+                        let name = match self.name.trim() {
+                            "" => format!("Preset {}", self.list.len() + 1),
+                            name => name.to_owned(),
+                        };
+                        let factors = MaterialFactors::new(push);
+                        match self.list.iter_mut().find(|preset| preset.name == name) {
+                            Some(preset) => preset.factors = factors,
+                            None => self.list.push(MaterialPreset { name, factors }),
+                        }
+                        self.name.clear();
+                        ui.close_menu();
+                    }
+                });
+            });
+        });
+    }
+}
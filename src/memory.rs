@@ -0,0 +1,217 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+use vulkano::{
+    DeviceSize,
+    device::{Device, DeviceOwned},
+    memory::{
+        DedicatedAllocation, ExternalMemoryHandleTypes, MemoryHeapFlags, MemoryRequirements,
+        allocator::{
+            AllocationCreateInfo, AllocationType, DeviceLayout, MemoryAlloc, MemoryAllocator,
+            MemoryAllocatorError, MemoryTypeFilter, StandardMemoryAllocator,
+        },
+    },
+};
+
+/// What GPU memory is used for, as reported in the stats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryCategory {
+    Textures,
+    Geometry,
+    Environment,
+    RenderTargets,
+    AccelerationStructures,
+}
+impl MemoryCategory {
+    pub const ALL: [Self; 5] = [
+        Self::Textures,
+        Self::Geometry,
+        Self::Environment,
+        Self::RenderTargets,
+        Self::AccelerationStructures,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Textures => "Textures",
+            Self::Geometry => "Geometry",
+            Self::Environment => "Environment",
+            Self::RenderTargets => "Render targets",
+            Self::AccelerationStructures => "Acceleration structures",
+        }
+    }
+    fn index(&self) -> usize {
+        Self::ALL
+            .iter()
+            .position(|category| category == self)
+            .unwrap()
+    }
+}
+
+/// Bytes currently allocated per category.
+#[derive(Default)]
+struct Usage([AtomicU64; MemoryCategory::ALL.len()]);
+
+/// Allocates from the shared allocator and counts the memory towards its category.
+struct TrackingAllocator {
+    inner: Arc<StandardMemoryAllocator>,
+    usage: Arc<Usage>,
+    category: MemoryCategory,
+}
+impl TrackingAllocator {
+    fn track(
+        &self,
+        allocation: Result<MemoryAlloc, MemoryAllocatorError>,
+    ) -> Result<MemoryAlloc, MemoryAllocatorError> {
+        if let Ok(allocation) = &allocation {
+            self.usage.0[self.category.index()]
+                .fetch_add(allocation_size(allocation), Ordering::Relaxed);
+        }
+        allocation
+    }
+}
+unsafe impl DeviceOwned for TrackingAllocator {
+    fn device(&self) -> &Arc<Device> {
+        self.inner.device()
+    }
+}
+unsafe impl MemoryAllocator for TrackingAllocator {
+    fn find_memory_type_index(
+        &self,
+        memory_type_bits: u32,
+        filter: MemoryTypeFilter,
+    ) -> Option<u32> {
+        self.inner.find_memory_type_index(memory_type_bits, filter)
+    }
+    fn allocate_from_type(
+        &self,
+        memory_type_index: u32,
+        layout: DeviceLayout,
+        allocation_type: AllocationType,
+        never_allocate: bool,
+    ) -> Result<MemoryAlloc, MemoryAllocatorError> {
+        self.track(self.inner.allocate_from_type(
+            memory_type_index,
+            layout,
+            allocation_type,
+            never_allocate,
+        ))
+    }
+    fn allocate(
+        &self,
+        requirements: MemoryRequirements,
+        allocation_type: AllocationType,
+        create_info: AllocationCreateInfo,
+        dedicated_allocation: Option<DedicatedAllocation<'_>>,
+    ) -> Result<MemoryAlloc, MemoryAllocatorError> {
+        self.track(self.inner.allocate(
+            requirements,
+            allocation_type,
+            create_info,
+            dedicated_allocation,
+        ))
+    }
+    fn allocate_dedicated(
+        &self,
+        memory_type_index: u32,
+        allocation_size: DeviceSize,
+        dedicated_allocation: Option<DedicatedAllocation<'_>>,
+        export_handle_types: ExternalMemoryHandleTypes,
+    ) -> Result<MemoryAlloc, MemoryAllocatorError> {
+        self.track(self.inner.allocate_dedicated(
+            memory_type_index,
+            allocation_size,
+            dedicated_allocation,
+            export_handle_types,
+        ))
+    }
+    unsafe fn deallocate(&self, allocation: MemoryAlloc) {
+        self.usage.0[self.category.index()]
+            .fetch_sub(allocation_size(&allocation), Ordering::Relaxed);
+        unsafe { self.inner.deallocate(allocation) };
+    }
+}
+
+fn allocation_size(allocation: &MemoryAlloc) -> DeviceSize {
+    allocation.suballocation.as_ref().map_or_else(
+        || allocation.device_memory.allocation_size(),
+        |suballocation| suballocation.size,
+    )
+}
+
+/// Hands out an allocator per [`MemoryCategory`] and reports what each has allocated.
+#[derive(Clone)]
+pub struct MemoryTracker {
+    allocators: Vec<Arc<TrackingAllocator>>,
+    usage: Arc<Usage>,
+}
+impl MemoryTracker {
+    pub fn new(inner: Arc<StandardMemoryAllocator>) -> Self {
+        let usage = Arc::new(Usage::default());
+        let allocators = MemoryCategory::ALL
+            .into_iter()
+            .map(|category| {
+                Arc::new(TrackingAllocator {
+                    inner: inner.clone(),
+                    usage: usage.clone(),
+                    category,
+                })
+            })
+            .collect();
+        Self { allocators, usage }
+    }
+    pub fn allocator(&self, category: MemoryCategory) -> Arc<dyn MemoryAllocator> {
+        self.allocators[category.index()].clone()
+    }
+    pub fn usage(&self, category: MemoryCategory) -> DeviceSize {
+        self.usage.0[category.index()].load(Ordering::Relaxed)
+    }
+    pub fn total(&self) -> DeviceSize {
+        MemoryCategory::ALL
+            .into_iter()
+            .map(|category| self.usage(category))
+            .sum()
+    }
+    /// Size of the largest device local heap, the closest thing to the VRAM size.
+    pub fn budget(&self) -> DeviceSize {
+        self.allocators[0]
+            .device()
+            .physical_device()
+            .memory_properties()
+            .memory_heaps
+            .iter()
+            .filter(|heap| heap.flags.intersects(MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        egui::Grid::new("memory_usage")
+            .num_columns(2)
+            .show(ui, |ui| {
+                for category in MemoryCategory::ALL {
+                    ui.label(category.name());
+                    ui.label(format_bytes(self.usage(category)));
+                    ui.end_row();
+                }
+                ui.strong("Total");
+                ui.strong(format_bytes(self.total()));
+                ui.end_row();
+                ui.label("Device memory");
+                ui.label(format_bytes(self.budget()));
+                ui.end_row();
+            });
+        let budget = self.budget();
+        if budget > 0 && self.total() > budget / 10 * 9 {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                "Close to running out of device memory, try a smaller max texture size",
+            );
+        }
+    }
+}
+
+fn format_bytes(bytes: DeviceSize) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
@@ -0,0 +1,99 @@
+use gltf::json::Extras;
+use serde_json::Value;
+
+/// The asset information of a document and the `extras` of its scenes, nodes, meshes and materials.
+pub fn metadata_ui(ui: &mut egui::Ui, document: &gltf::Document) {
+    let asset = &document.as_json().asset;
+    egui::Grid::new("asset").num_columns(2).show(ui, |ui| {
+        let rows = [
+            ("Generator", asset.generator.as_deref()),
+            ("Copyright", asset.copyright.as_deref()),
+            ("Version", Some(asset.version.as_str())),
+            ("Min version", asset.min_version.as_deref()),
+        ];
+        for (label, value) in rows {
+            if let Some(value) = value {
+                ui.label(label);
+                ui.label(value);
+                ui.end_row();
+            }
+        }
+    });
+    extras_ui(ui, "Asset", &asset.extras);
+
+    let named = |kind: &str, index: usize, name: Option<&str>| match name {
+        Some(name) => format!("{kind} {index}: {name}"),
+        None => format!("{kind} {index}"),
+    };
+    let mut any = asset.extras.is_some();
+    for scene in document.scenes() {
+        any |= extras_ui(
+            ui,
+            &named("Scene", scene.index(), scene.name()),
+            scene.extras(),
+        );
+    }
+    for node in document.nodes() {
+        any |= extras_ui(ui, &named("Node", node.index(), node.name()), node.extras());
+    }
+    for mesh in document.meshes() {
+        any |= extras_ui(ui, &named("Mesh", mesh.index(), mesh.name()), mesh.extras());
+    }
+    for material in document.materials() {
+        let Some(index) = material.index() else {
+            continue;
+        };
+        any |= extras_ui(
+            ui,
+            &named("Material", index, material.name()),
+            material.extras(),
+        );
+    }
+    if !any {
+        ui.weak("No extras");
+    }
+}
+
+/// A read only tree of the `extras` of one object, returns false if it has none.
+fn extras_ui(ui: &mut egui::Ui, label: &str, extras: &Extras) -> bool {
+    let Some(raw) = extras else {
+        return false;
+    };
+    let id = ui.id().with(label);
+    match serde_json::from_str::<Value>(raw.get()) {
+        Ok(value) => value_ui(ui, label, &value, id),
+        Err(err) => {
+            ui.colored_label(ui.visuals().error_fg_color, format!("{label}: {err}"));
+        }
+    }
+    true
+}
+
+fn value_ui(ui: &mut egui::Ui, key: &str, value: &Value, id: egui::Id) {
+    match value {
+        Value::Object(map) => {
+            egui::CollapsingHeader::new(format!("{key} {{{}}}", map.len()))
+                .id_salt(id)
+                .show(ui, |ui| {
+                    for (key, value) in map {
+                        value_ui(ui, key, value, id.with(key));
+                    }
+                });
+        }
+        Value::Array(items) => {
+            egui::CollapsingHeader::new(format!("{key} [{}]", items.len()))
+                .id_salt(id)
+                .show(ui, |ui| {
+                    for (i, value) in items.iter().enumerate() {
+                        value_ui(ui, &i.to_string(), value, id.with(i));
+                    }
+                });
+        }
+        value => {
+            ui.horizontal(|ui| {
+                ui.label(format!("{key}:"));
+                ui.monospace(value.to_string());
+            });
+        }
+    }
+}
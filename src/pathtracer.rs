@@ -0,0 +1,510 @@
+use crate::{
+    Allocators,
+    acceleration::{RaytracerCamera, SceneAcceleration},
+    camera::Camera,
+    memory::MemoryCategory,
+    skybox::renderer::EnvironmentPush,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use vulkano::{
+    DeviceSize,
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, CopyImageToBufferInfo},
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::{Device, DeviceOwned},
+    format::Format,
+    image::{
+        Image, ImageCreateInfo, ImageType, ImageUsage,
+        sampler::{Sampler, SamplerCreateInfo},
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::{
+        ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo, compute::ComputePipelineCreateInfo,
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BeautySettings {
+    pub width: u32,
+    pub height: u32,
+    /// Samples per pixel before the image is saved.
+    pub samples: u32,
+    /// Indirect bounces after the primary hit.
+    pub bounces: u32,
+}
+impl Default for BeautySettings {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            samples: 256,
+            bounces: 4,
+        }
+    }
+}
+impl BeautySettings {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.width).range(1..=8192));
+            ui.label("x");
+            ui.add(egui::DragValue::new(&mut self.height).range(1..=8192));
+            ui.label("Size");
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.samples).range(1..=65536));
+            ui.label("Samples");
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.bounces).range(0..=16));
+            ui.label("Bounces");
+        });
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+struct PathTracerPush {
+    environment: EnvironmentPush,
+    sample: u32,
+    bounces: u32,
+}
+
+/// A render in progress, one sample per pixel is added every frame.
+struct BeautyJob {
+    path: PathBuf,
+    settings: BeautySettings,
+    environment: EnvironmentPush,
+    camera: Subbuffer<RaytracerCamera>,
+    /// Sum of the samples, the sample count is in alpha.
+    accumulation: Arc<ImageView>,
+    sample: u32,
+    /// Set once the copy of the result was recorded.
+    readback: Option<Subbuffer<[f32]>>,
+}
+
+/// Offline progressive path tracer that saves the current view to a file.
+/// Surfaces are diffuse with their base color and emissive factors.
+pub struct PathTracer {
+    pub settings: BeautySettings,
+    pipeline: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+    environment: Option<Arc<ImageView>>,
+    job: Option<BeautyJob>,
+    allocators: Allocators,
+}
+impl PathTracer {
+    pub fn new(allocators: &Allocators) -> Self {
+        let device = allocators.mem.device().clone();
+        let pipeline = path_tracer_pipeline(device.clone());
+        let sampler = Sampler::new(device, SamplerCreateInfo::simple_repeat_linear()).unwrap();
+
+        Self {
+            settings: BeautySettings::default(),
+            pipeline,
+            sampler,
+            environment: None,
+            job: None,
+            allocators: allocators.clone(),
+        }
+    }
+    /// Uses the skybox cube for the background and lighting.
+    pub fn set_environment(&mut self, cube: Arc<Image>) {
+        let view = ImageView::new(
+            cube.clone(),
+            ImageViewCreateInfo {
+                view_type: ImageViewType::Cube,
+                ..ImageViewCreateInfo::from_image(&cube)
+            },
+        )
+        .unwrap();
+        self.environment = Some(view);
+    }
+
+    /// Starts rendering the scene as seen by `camera` into `path`.
+    /// The file is saved as 32 bit float if its extension is `exr`, otherwise as tone mapped 8 bit.
+    pub fn start(&mut self, path: PathBuf, camera: &Camera, environment: EnvironmentPush) {
+        let settings = self.settings;
+        let aspect = settings.width as f32 / settings.height as f32;
+        let camera = Buffer::from_data(
+            self.allocators.mem.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::UNIFORM_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            RaytracerCamera::new(camera, aspect),
+        )
+        .unwrap();
+        let accumulation = Image::new(
+            self.allocators
+                .memory
+                .allocator(MemoryCategory::RenderTargets),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: Format::R32G32B32A32_SFLOAT,
+                extent: [settings.width, settings.height, 1],
+                usage: ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+
+        self.job = Some(BeautyJob {
+            path,
+            settings,
+            environment,
+            camera,
+            accumulation: ImageView::new_default(accumulation).unwrap(),
+            sample: 0,
+            readback: None,
+        });
+    }
+    pub fn rendering(&self) -> bool {
+        self.job.is_some()
+    }
+    pub fn cancel(&mut self) {
+        self.job = None;
+    }
+    /// From 0 to 1, `None` if nothing is being rendered.
+    pub fn progress(&self) -> Option<f32> {
+        self.job
+            .as_ref()
+            .map(|job| job.sample as f32 / job.settings.samples as f32)
+    }
+
+    /// Adds a sample to the render in progress.
+    /// Returns the saved file once finished, or why it couldn't be saved.
+    pub fn update<L>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        acceleration: &SceneAcceleration,
+    ) -> Option<Result<PathBuf, image::ImageError>> {
+        let job = self.job.as_mut()?;
+
+        if let Some(readback) = &job.readback {
+            // still in use until the frame that copied it is done
+            let pixels = readback.read().ok()?;
+            let result = save(&job.path, job.settings, &pixels).map(|_| job.path.clone());
+            drop(pixels);
+            self.job = None;
+            return Some(result);
+        }
+
+        let (Some(tlas), Some(geometries), Some(environment)) = (
+            &acceleration.tlas,
+            &acceleration.geometries,
+            &self.environment,
+        ) else {
+            return None;
+        };
+        let set = DescriptorSet::new(
+            self.allocators.set.clone(),
+            self.pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::acceleration_structure(0, tlas.clone()),
+                WriteDescriptorSet::buffer(1, job.camera.clone()),
+                WriteDescriptorSet::buffer(2, geometries.clone()),
+                WriteDescriptorSet::image_view_sampler(
+                    3,
+                    environment.clone(),
+                    self.sampler.clone(),
+                ),
+                WriteDescriptorSet::image_view(4, job.accumulation.clone()),
+            ],
+            [],
+        )
+        .unwrap();
+        let push = PathTracerPush {
+            environment: job.environment,
+            sample: job.sample,
+            bounces: job.settings.bounces,
+        };
+        let [width, height] = [job.settings.width, job.settings.height];
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .unwrap()
+            .push_constants(self.pipeline.layout().clone(), 0, push)
+            .unwrap();
+        unsafe { builder.dispatch([width.div_ceil(8), height.div_ceil(8), 1]) }.unwrap();
+        job.sample += 1;
+
+        if job.sample >= job.settings.samples {
+            let readback = Buffer::new_slice::<f32>(
+                self.allocators.mem.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::TRANSFER_DST,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                        | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                    ..Default::default()
+                },
+                (width * height * 4) as DeviceSize,
+            )
+            .unwrap();
+            builder
+                .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                    job.accumulation.image().clone(),
+                    readback.clone(),
+                ))
+                .unwrap();
+            job.readback = Some(readback);
+        }
+        None
+    }
+}
+
+/// Averages the accumulated samples and writes them to `path`.
+fn save(path: &Path, settings: BeautySettings, pixels: &[f32]) -> image::ImageResult<()> {
+    let average = pixels.chunks_exact(4).flat_map(|pixel| {
+        let samples = pixel[3].max(1.0);
+        [pixel[0] / samples, pixel[1] / samples, pixel[2] / samples]
+    });
+    let hdr = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("exr"));
+    if hdr {
+        let data: Vec<f32> = average.collect();
+        image::save_buffer(
+            path,
+            bytemuck::cast_slice(&data),
+            settings.width,
+            settings.height,
+            image::ColorType::Rgb32F,
+        )
+    } else {
+        let data: Vec<u8> = average
+            .collect::<Vec<_>>()
+            .chunks_exact(3)
+            .flat_map(|color| pbr_neutral_tone_mapping([color[0], color[1], color[2]]))
+            .map(|channel| (linear_to_srgb(channel) * 255.0).round() as u8)
+            .collect();
+        image::save_buffer(
+            path,
+            &data,
+            settings.width,
+            settings.height,
+            image::ColorType::Rgb8,
+        )
+    }
+}
+
+/// The same tone mapping as the glTF shader.
+fn pbr_neutral_tone_mapping(mut color: [f32; 3]) -> [f32; 3] {
+    const START_COMPRESSION: f32 = 0.8 - 0.04;
+    const DESATURATION: f32 = 0.15;
+
+    let x = color[0].min(color[1]).min(color[2]);
+    let offset = if x < 0.08 { x - 6.25 * x * x } else { 0.04 };
+    color = color.map(|c| c - offset);
+
+    let peak = color[0].max(color[1]).max(color[2]);
+    if peak < START_COMPRESSION {
+        return color;
+    }
+
+    let d = 1.0 - START_COMPRESSION;
+    let new_peak = 1.0 - d * d / (peak + d - START_COMPRESSION);
+    color = color.map(|c| c * new_peak / peak);
+
+    let g = 1.0 - 1.0 / (DESATURATION * (peak - new_peak) + 1.0);
+    color.map(|c| c + (new_peak - c) * g)
+}
+
+fn linear_to_srgb(channel: f32) -> f32 {
+    let channel = channel.clamp(0.0, 1.0);
+    if channel <= 0.0031308 {
+        channel * 12.92
+    } else {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn path_tracer_pipeline(device: Arc<Device>) -> Arc<ComputePipeline> {
+    let cs = cs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let stage = PipelineShaderStageCreateInfo::new(cs);
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+    ComputePipeline::new(
+        device,
+        None,
+        ComputePipelineCreateInfo::stage_layout(stage, layout),
+    )
+    .unwrap()
+}
+
+mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        vulkan_version: "1.2",
+        src: r#"
+#version 460
+#extension GL_EXT_ray_query : require
+#extension GL_EXT_buffer_reference : require
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(buffer_reference, std430, buffer_reference_align = 4) readonly buffer Floats {
+    float f[];
+};
+layout(buffer_reference, std430, buffer_reference_align = 4) readonly buffer Indices {
+    uint i[];
+};
+struct Geometry {
+    Floats vertices;
+    Indices indices;
+    vec4 base_color;
+    vec4 emissive;
+    uint index_size;
+};
+
+layout(set = 0, binding = 0) uniform accelerationStructureEXT tlas;
+layout(set = 0, binding = 1) uniform Camera {
+    mat4 view_inverse;
+    mat4 proj_inverse;
+} camera;
+layout(set = 0, binding = 2, std430) readonly buffer Geometries {
+    Geometry geometries[];
+};
+layout(set = 0, binding = 3) uniform samplerCube skybox;
+layout(set = 0, binding = 4, rgba32f) uniform image2D accumulation;
+
+layout(push_constant) uniform PathTracer {
+    float intensity;
+    float yaw;
+    uint sample_index;
+    uint bounces;
+} p;
+
+const float PI = 3.14159265358979323846264338327950288;
+// floats in a PrimitiveVertex, the normal follows the position
+const uint VERTEX_FLOATS = 14;
+
+// 16 bit indices are packed in pairs, non-indexed geometry draws the vertices in order
+uint vertex_index(Geometry g, uint i) {
+    if (g.index_size == 0u) {
+        return i;
+    }
+    if (g.index_size == 2u) {
+        uint pair = g.indices.i[i >> 1];
+        return (i & 1u) == 0u ? pair & 0xFFFFu : pair >> 16;
+    }
+    return g.indices.i[i];
+}
+
+vec3 vertex_normal(Geometry g, uint index) {
+    uint base = index * VERTEX_FLOATS + 3;
+    return vec3(g.vertices.f[base], g.vertices.f[base + 1], g.vertices.f[base + 2]);
+}
+
+vec3 env_direction(vec3 dir) {
+    float s = sin(p.yaw);
+    float c = cos(p.yaw);
+    return vec3(c * dir.x + s * dir.z, dir.y, -s * dir.x + c * dir.z);
+}
+
+// https://www.pcg-random.org
+uint pcg(inout uint state) {
+    state = state * 747796405u + 2891336453u;
+    uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+float rand(inout uint state) {
+    return float(pcg(state)) / 4294967296.0;
+}
+
+vec3 cosine_sample(vec3 n, inout uint state) {
+    float r = sqrt(rand(state));
+    float phi = 2.0 * PI * rand(state);
+    vec3 up = abs(n.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 t = normalize(cross(up, n));
+    vec3 b = cross(n, t);
+    return normalize(t * r * cos(phi) + b * r * sin(phi) + n * sqrt(max(1.0 - r * r, 0.0)));
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(accumulation);
+    if (any(greaterThanEqual(pixel, size))) {
+        return;
+    }
+    uint state = uint(pixel.y * size.x + pixel.x) ^ (p.sample_index * 0x9E3779B9u);
+    pcg(state);
+
+    vec2 d = (vec2(pixel) + vec2(rand(state), rand(state))) / vec2(size) * 2.0 - 1.0;
+    vec3 origin = (camera.view_inverse * vec4(0, 0, 0, 1)).xyz;
+    vec4 target = camera.proj_inverse * vec4(d.x, d.y, 1, 1);
+    vec3 direction = (camera.view_inverse * vec4(normalize(target.xyz / target.w), 0)).xyz;
+
+    vec3 radiance = vec3(0.0);
+    vec3 throughput = vec3(1.0);
+    for (uint bounce = 0u; bounce <= p.bounces; bounce++) {
+        rayQueryEXT query;
+        rayQueryInitializeEXT(query, tlas, gl_RayFlagsOpaqueEXT, 0xFF, origin, 0.0, direction, 10000.0);
+        while (rayQueryProceedEXT(query)) {}
+        if (rayQueryGetIntersectionTypeEXT(query, true) == gl_RayQueryCommittedIntersectionNoneEXT) {
+            radiance += throughput * textureLod(skybox, env_direction(direction), 0.0).rgb * p.intensity;
+            break;
+        }
+
+        float t = rayQueryGetIntersectionTEXT(query, true);
+        Geometry g = geometries[rayQueryGetIntersectionInstanceCustomIndexEXT(query, true)];
+        uint primitive = rayQueryGetIntersectionPrimitiveIndexEXT(query, true);
+        vec2 attribs = rayQueryGetIntersectionBarycentricsEXT(query, true);
+        vec3 barycentrics = vec3(1.0 - attribs.x - attribs.y, attribs.x, attribs.y);
+        vec3 n = vertex_normal(g, vertex_index(g, 3 * primitive)) * barycentrics.x
+               + vertex_normal(g, vertex_index(g, 3 * primitive + 1)) * barycentrics.y
+               + vertex_normal(g, vertex_index(g, 3 * primitive + 2)) * barycentrics.z;
+        mat4x3 world_to_object = rayQueryGetIntersectionWorldToObjectEXT(query, true);
+        // inverse transpose of the object to world matrix
+        vec3 N = normalize(n * mat3(world_to_object));
+        if (dot(N, direction) > 0.0) {
+            N = -N;
+        }
+
+        radiance += throughput * g.emissive.rgb;
+        throughput *= g.base_color.rgb;
+
+        origin = origin + direction * t + N * max(t, 1.0) * 0.0001;
+        direction = cosine_sample(N, state);
+    }
+
+    // a broken sample would ruin the whole pixel
+    if (any(isnan(radiance)) || any(isinf(radiance))) {
+        radiance = vec3(0.0);
+    }
+    vec4 sum = p.sample_index == 0u ? vec4(0.0) : imageLoad(accumulation, pixel);
+    imageStore(accumulation, pixel, sum + vec4(radiance, 1.0));
+}
+        "#,
+    }
+}
@@ -0,0 +1,780 @@
+use crate::{
+    Allocators,
+    color::OutputTransform,
+    frameinfo::{Depth, Msaa},
+    memory::MemoryCategory,
+};
+use egui_winit_vulkano::Gui;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use vulkano::{
+    buffer::BufferContents,
+    command_buffer::{
+        AutoCommandBufferBuilder, RenderPassBeginInfo, SubpassBeginInfo, SubpassEndInfo,
+    },
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::{Device, DeviceOwned},
+    format::Format,
+    image::{
+        Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount,
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+    },
+    memory::allocator::AllocationCreateInfo,
+    pipeline::{
+        DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+        graphics::{
+            GraphicsPipelineCreateInfo,
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::VertexInputState,
+            viewport::{Scissor, Viewport, ViewportState},
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    shader::EntryPoint,
+};
+
+/// The images of one frame in flight.
+struct PostTarget {
+    /// Made with the main render pass, so everything drawn in the main subpass can draw into it.
+    scene_framebuffer: Arc<Framebuffer>,
+    scene: Arc<ImageView>,
+    /// The finished image shown in the viewport, also the history of the next frame with TAA.
+    output: Arc<ImageView>,
+    framebuffer: Arc<Framebuffer>,
+    /// The output encoded for the display, if the output transform isn't sRGB.
+    display: Option<(Arc<ImageView>, Arc<Framebuffer>)>,
+}
+impl PostTarget {
+    /// The image shown in the viewport.
+    fn shown(&self) -> &Arc<ImageView> {
+        self.display.as_ref().map_or(&self.output, |(view, _)| view)
+    }
+}
+
+/// Offsets of the camera within a pixel for consecutive TAA frames, the Halton (2, 3) sequence.
+const JITTER: [[f32; 2]; 8] = [
+    [0.5, 0.333_333],
+    [0.25, 0.666_667],
+    [0.75, 0.111_111],
+    [0.125, 0.444_444],
+    [0.625, 0.777_778],
+    [0.375, 0.222_222],
+    [0.875, 0.555_556],
+    [0.062_5, 0.888_889],
+];
+/// How much of the history is kept each frame.
+const TAA_FEEDBACK: f32 = 0.9;
+
+#[repr(C)]
+#[derive(BufferContents)]
+struct TaaPush {
+    /// `0` without a valid history.
+    feedback: f32,
+}
+
+#[repr(C)]
+#[derive(BufferContents)]
+struct OutputPush {
+    /// [`OutputTransform::shader_value`].
+    transform: i32,
+}
+
+/// Full screen passes over the finished scene. While one is enabled, or the render scale isn't
+/// 100%, the scene is drawn into an offscreen image the size of the scaled viewport instead of
+/// the main subpass, and the viewport shows the output stretched over it like the ray traced
+/// preview.
+pub struct PostChain {
+    allocators: Allocators,
+    /// The main subpass the scene pipelines were made for.
+    subpass: Subpass,
+    depth: Depth,
+    antialiasing: Msaa,
+    /// Writes the output image.
+    render_pass: Arc<RenderPass>,
+    fxaa: Arc<GraphicsPipeline>,
+    taa: Arc<GraphicsPipeline>,
+    /// Copies the scene as it is when only the resolution is changed.
+    copy: Arc<GraphicsPipeline>,
+    /// Applies the output transform after the other passes.
+    transform: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    num_frames: usize,
+    /// Resolution of the scene relative to the viewport.
+    pub scale: f32,
+    /// Draws the scene offscreen without any pass, for what reads the output.
+    pub offscreen: bool,
+    pub output_transform: OutputTransform,
+    extent: [u32; 2],
+    /// Empty until the first [`resize`](Self::resize) while enabled.
+    targets: Vec<PostTarget>,
+    /// The outputs registered with egui, the same order as `targets`.
+    textures: Vec<egui::TextureId>,
+    /// The outputs were recreated and need to be registered again.
+    stale: bool,
+    /// The target whose output holds the last resolved TAA frame.
+    history: Option<usize>,
+    /// Position in [`JITTER`].
+    jitter_index: usize,
+}
+impl PostChain {
+    pub fn new(
+        allocators: &Allocators,
+        subpass: Subpass,
+        msaa: Msaa,
+        depth: Depth,
+        num_frames: usize,
+    ) -> Self {
+        let device = allocators.mem.device().clone();
+        let render_pass = output_render_pass(device.clone(), scene_format(&subpass));
+        let (fxaa, taa, copy, transform) = pipelines(&render_pass);
+        let sampler = Sampler::new(
+            device,
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        Self {
+            allocators: allocators.clone(),
+            subpass,
+            depth,
+            antialiasing: msaa,
+            render_pass,
+            fxaa,
+            taa,
+            copy,
+            transform,
+            sampler,
+            num_frames,
+            scale: 1.0,
+            offscreen: false,
+            output_transform: OutputTransform::default(),
+            extent: [0, 0],
+            targets: vec![],
+            textures: vec![],
+            stale: false,
+            history: None,
+            jitter_index: 0,
+        }
+    }
+    /// Whether the scene has to be rendered with [`begin`](Self::begin) and [`end`](Self::end).
+    pub fn active(&self) -> bool {
+        self.antialiasing.post_process()
+            || self.scale != 1.0
+            || self.offscreen
+            || self.output_transform != OutputTransform::Srgb
+    }
+    /// Subpixel offset of the projection in normalized device coordinates,
+    /// moves a little every frame while TAA accumulates the samples.
+    pub fn jitter(&self) -> glm::Vec2 {
+        if self.antialiasing != Msaa::Taa || self.targets.is_empty() {
+            return glm::Vec2::zeros();
+        }
+        let [x, y] = JITTER[self.jitter_index];
+        glm::vec2(
+            (x - 0.5) * 2.0 / self.extent[0] as f32,
+            (y - 0.5) * 2.0 / self.extent[1] as f32,
+        )
+    }
+
+    /// Follows a recreated main render pass, the [`Gui`] was recreated with it,
+    /// so the outputs are registered again with the new one.
+    pub fn set_subpass(&mut self, subpass: Subpass, msaa: Msaa, depth: Depth) {
+        let format = scene_format(&subpass);
+        if format != scene_format(&self.subpass) {
+            self.render_pass = output_render_pass(subpass.render_pass().device().clone(), format);
+            (self.fxaa, self.taa, self.copy, self.transform) = pipelines(&self.render_pass);
+        }
+        self.subpass = subpass;
+        self.depth = depth;
+        self.antialiasing = msaa;
+        self.history = None;
+        self.targets.clear();
+        self.textures.clear();
+        self.stale = true;
+    }
+    /// Recreates the images if the viewport or the render scale changed size,
+    /// or the output transform was turned on or off.
+    pub fn resize(&mut self, size: [u32; 2]) {
+        let size = size.map(|x| ((x as f32 * self.scale).round() as u32).max(1));
+        let display = self.output_transform != OutputTransform::Srgb;
+        let current = self
+            .targets
+            .first()
+            .is_some_and(|target| target.display.is_some() == display);
+        if !self.active() || (self.extent == size && current) {
+            return;
+        }
+        self.extent = size;
+        self.targets = (0..self.num_frames)
+            .map(|_| self.new_target(size))
+            .collect();
+        self.history = None;
+        self.stale = true;
+    }
+    /// Makes the outputs available to egui, replacing the old ones after a resize.
+    pub fn register(&mut self, gui: &mut Gui) {
+        if !self.stale {
+            return;
+        }
+        for texture in self.textures.drain(..) {
+            gui.unregister_user_image(texture);
+        }
+        self.textures = self
+            .targets
+            .iter()
+            .map(|target| {
+                // stretched over the viewport if the scale isn't 1
+                let filter = if self.scale == 1.0 {
+                    Filter::Nearest
+                } else {
+                    Filter::Linear
+                };
+                let sampler = SamplerCreateInfo {
+                    mag_filter: filter,
+                    min_filter: filter,
+                    ..Default::default()
+                };
+                gui.register_user_image_view(target.shown().clone(), sampler)
+            })
+            .collect();
+        self.stale = false;
+    }
+    /// `None` until the outputs of the current size are registered.
+    pub fn texture(&self, frame: usize) -> Option<egui::TextureId> {
+        self.textures.get(frame).copied()
+    }
+
+    /// Starts drawing the scene offscreen, the viewport and scissor cover the whole image.
+    /// Returns false if there is nothing to draw into yet.
+    pub fn begin<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        index: usize,
+        background: [f32; 4],
+    ) -> bool {
+        let Some(target) = self.targets.get(index) else {
+            return false;
+        };
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![
+                        Some(background.into()),
+                        Some(self.depth.clear_value().into()),
+                    ],
+                    ..RenderPassBeginInfo::framebuffer(target.scene_framebuffer.clone())
+                },
+                SubpassBeginInfo::default(),
+            )
+            .unwrap()
+            .set_viewport(0, [self.viewport()].into_iter().collect())
+            .unwrap()
+            .set_scissor(0, [Scissor::default()].into_iter().collect())
+            .unwrap();
+        true
+    }
+    /// Ends the scene and runs the passes over it into the output.
+    pub fn end<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>, index: usize) {
+        let target = &self.targets[index];
+        let scene =
+            WriteDescriptorSet::image_view_sampler(0, target.scene.clone(), self.sampler.clone());
+        let (pipeline, writes, feedback) = if self.antialiasing == Msaa::Taa {
+            // without a history the scene is bound in its place and ignored
+            let (history, feedback) = match self.history {
+                Some(history) => (self.targets[history].output.clone(), TAA_FEEDBACK),
+                None => (target.scene.clone(), 0.0),
+            };
+            let history = WriteDescriptorSet::image_view_sampler(1, history, self.sampler.clone());
+            (self.taa.clone(), vec![scene, history], Some(feedback))
+        } else if self.antialiasing == Msaa::Fxaa {
+            (self.fxaa.clone(), vec![scene], None)
+        } else {
+            (self.copy.clone(), vec![scene], None)
+        };
+        let set = DescriptorSet::new(
+            self.allocators.set.clone(),
+            pipeline.layout().set_layouts()[0].clone(),
+            writes,
+            [],
+        )
+        .unwrap();
+
+        builder
+            .end_render_pass(SubpassEndInfo::default())
+            .unwrap()
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(target.framebuffer.clone())
+                },
+                SubpassBeginInfo::default(),
+            )
+            .unwrap()
+            .set_viewport(0, [self.viewport()].into_iter().collect())
+            .unwrap()
+            .set_scissor(0, [Scissor::default()].into_iter().collect())
+            .unwrap()
+            .bind_pipeline_graphics(pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .unwrap();
+        if let Some(feedback) = feedback {
+            builder
+                .push_constants(pipeline.layout().clone(), 0, TaaPush { feedback })
+                .unwrap();
+            self.history = Some(index);
+            self.jitter_index = (self.jitter_index + 1) % JITTER.len();
+        }
+        unsafe { builder.draw(3, 1, 0, 0) }.unwrap();
+        builder.end_render_pass(SubpassEndInfo::default()).unwrap();
+
+        if let Some((_, framebuffer)) = &target.display {
+            let set = DescriptorSet::new(
+                self.allocators.set.clone(),
+                self.transform.layout().set_layouts()[0].clone(),
+                [WriteDescriptorSet::image_view_sampler(
+                    0,
+                    target.output.clone(),
+                    self.sampler.clone(),
+                )],
+                [],
+            )
+            .unwrap();
+            builder
+                .begin_render_pass(
+                    RenderPassBeginInfo {
+                        clear_values: vec![None],
+                        ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+                    },
+                    SubpassBeginInfo::default(),
+                )
+                .unwrap()
+                .set_viewport(0, [self.viewport()].into_iter().collect())
+                .unwrap()
+                .set_scissor(0, [Scissor::default()].into_iter().collect())
+                .unwrap()
+                .bind_pipeline_graphics(self.transform.clone())
+                .unwrap()
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    self.transform.layout().clone(),
+                    0,
+                    set,
+                )
+                .unwrap()
+                .push_constants(
+                    self.transform.layout().clone(),
+                    0,
+                    OutputPush {
+                        transform: self.output_transform.shader_value(),
+                    },
+                )
+                .unwrap();
+            unsafe { builder.draw(3, 1, 0, 0) }.unwrap();
+            builder.end_render_pass(SubpassEndInfo::default()).unwrap();
+        }
+    }
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+    /// The finished image of frame `index` after [`end`](Self::end), before the output transform.
+    pub fn output(&self, index: usize) -> &Arc<ImageView> {
+        &self.targets[index].output
+    }
+
+    fn viewport(&self) -> Viewport {
+        Viewport {
+            extent: self.extent.map(|x| x as f32),
+            ..Default::default()
+        }
+    }
+    fn new_target(&self, extent: [u32; 2]) -> PostTarget {
+        let image = |format, usage, samples| {
+            let image = Image::new(
+                self.allocators
+                    .memory
+                    .allocator(MemoryCategory::RenderTargets),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    format,
+                    extent: [extent[0], extent[1], 1],
+                    samples,
+                    usage,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )
+            .unwrap();
+            ImageView::new_default(image).unwrap()
+        };
+        let format = scene_format(&self.subpass);
+        let samples = self.subpass.num_samples().unwrap_or(SampleCount::Sample1);
+        let scene = image(
+            format,
+            ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
+            SampleCount::Sample1,
+        );
+        let depth = image(
+            self.depth.precision.format(),
+            ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+            samples,
+        );
+        // a multisampled main pass resolves into the scene like into the swapchain
+        let multisampled = (samples != SampleCount::Sample1).then(|| {
+            image(
+                format,
+                ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+                samples,
+            )
+        });
+        let scene_framebuffer = Framebuffer::new(
+            self.subpass.render_pass().clone(),
+            FramebufferCreateInfo {
+                attachments: multisampled
+                    .into_iter()
+                    .chain([scene.clone(), depth])
+                    .collect(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let output = image(
+            format,
+            ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
+            SampleCount::Sample1,
+        );
+        let framebuffer = Framebuffer::new(
+            self.render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![output.clone()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let display = (self.output_transform != OutputTransform::Srgb).then(|| {
+            let view = image(
+                format,
+                ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
+                SampleCount::Sample1,
+            );
+            let framebuffer = Framebuffer::new(
+                self.render_pass.clone(),
+                FramebufferCreateInfo {
+                    attachments: vec![view.clone()],
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            (view, framebuffer)
+        });
+        PostTarget {
+            scene_framebuffer,
+            scene,
+            output,
+            framebuffer,
+            display,
+        }
+    }
+}
+
+/// The color format of the main render pass, which the scene pipelines write.
+fn scene_format(subpass: &Subpass) -> Format {
+    subpass.render_pass().attachments()[0].format
+}
+
+fn output_render_pass(device: Arc<Device>, format: Format) -> Arc<RenderPass> {
+    vulkano::single_pass_renderpass!(
+        device,
+        attachments: {
+            color: {
+                format: format,
+                samples: 1,
+                load_op: DontCare,
+                store_op: Store,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {}
+        },
+    )
+    .unwrap()
+}
+
+/// The FXAA, the TAA resolve, the copy and the output transform pipeline.
+fn pipelines(
+    render_pass: &Arc<RenderPass>,
+) -> (
+    Arc<GraphicsPipeline>,
+    Arc<GraphicsPipeline>,
+    Arc<GraphicsPipeline>,
+    Arc<GraphicsPipeline>,
+) {
+    let device = render_pass.device().clone();
+    let fxaa = fxaa_fs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let taa = taa_fs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let copy = copy_fs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let transform = output_fs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    (
+        fullscreen_pipeline(render_pass.clone(), fxaa),
+        fullscreen_pipeline(render_pass.clone(), taa),
+        fullscreen_pipeline(render_pass.clone(), copy),
+        fullscreen_pipeline(render_pass.clone(), transform),
+    )
+}
+
+fn fullscreen_pipeline(render_pass: Arc<RenderPass>, fs: EntryPoint) -> Arc<GraphicsPipeline> {
+    let device = render_pass.device().clone();
+    let vs = vs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+    let subpass = Subpass::from(render_pass, 0).unwrap();
+
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(VertexInputState::default()),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap()
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+#version 450
+
+layout(location = 0) out vec2 v_uv;
+
+void main() {
+    // one triangle covering the screen
+    vec2 xy = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+    v_uv = xy * 0.5 + 0.5;
+    gl_Position = vec4(xy, 0.0, 1.0);
+}
+        "#
+    }
+}
+
+mod fxaa_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+
+layout(location = 0) out vec4 f_color;
+
+// contrast below which nothing is smoothed, absolute and relative to the brightest neighbour
+const float EDGE_MIN = 1.0 / 16.0;
+const float EDGE_RELATIVE = 1.0 / 8.0;
+// how long edges can be followed, in pixels
+const float SPAN_MAX = 8.0;
+const float REDUCE_MIN = 1.0 / 128.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+
+// perceived brightness, the scene is stored in linear colors
+float luma(vec3 color) {
+    return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(scene, 0));
+    vec4 center = texture(scene, v_uv);
+    float m = luma(center.rgb);
+    float nw = luma(textureOffset(scene, v_uv, ivec2(-1, -1)).rgb);
+    float ne = luma(textureOffset(scene, v_uv, ivec2(1, -1)).rgb);
+    float sw = luma(textureOffset(scene, v_uv, ivec2(-1, 1)).rgb);
+    float se = luma(textureOffset(scene, v_uv, ivec2(1, 1)).rgb);
+    float lo = min(m, min(min(nw, ne), min(sw, se)));
+    float hi = max(m, max(max(nw, ne), max(sw, se)));
+    if (hi - lo < max(EDGE_MIN, hi * EDGE_RELATIVE)) {
+        f_color = center;
+        return;
+    }
+
+    // blur along the edge, across the gradient of the neighbourhood
+    vec2 dir = vec2((sw + se) - (nw + ne), (nw + sw) - (ne + se));
+    float reduce = max((nw + ne + sw + se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+    dir = clamp(dir * scale, -SPAN_MAX, SPAN_MAX) * texel;
+
+    vec3 inner = 0.5 * (
+        texture(scene, v_uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture(scene, v_uv + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 outer = inner * 0.5 + 0.25 * (
+        texture(scene, v_uv - dir * 0.5).rgb +
+        texture(scene, v_uv + dir * 0.5).rgb);
+    // the wide blur crossed into another edge if it left the range of the neighbourhood
+    float l = luma(outer);
+    f_color = vec4(l < lo || l > hi ? inner : outer, center.a);
+}
+        "#
+    }
+}
+
+mod copy_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    f_color = texelFetch(scene, ivec2(gl_FragCoord.xy), 0);
+}
+        "#
+    }
+}
+
+mod taa_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+layout(set = 0, binding = 1) uniform sampler2D history;
+
+layout(push_constant) uniform Resolve {
+    float feedback;
+} resolve;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    ivec2 last = textureSize(scene, 0) - 1;
+    vec4 current = texelFetch(scene, pixel, 0);
+
+    // the history is limited to the colors around the pixel this frame,
+    // so whatever moved or was uncovered doesn't leave a trail
+    vec3 lo = current.rgb;
+    vec3 hi = current.rgb;
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            vec3 color = texelFetch(scene, clamp(pixel + ivec2(x, y), ivec2(0), last), 0).rgb;
+            lo = min(lo, color);
+            hi = max(hi, color);
+        }
+    }
+    vec3 previous = clamp(texture(history, v_uv).rgb, lo, hi);
+    f_color = vec4(mix(current.rgb, previous, resolve.feedback), current.a);
+}
+        "#
+    }
+}
+
+mod output_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+#define OUTPUT_DISPLAY_P3 1
+#define OUTPUT_REC709 2
+
+layout(location = 0) in vec2 v_uv;
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+
+layout(push_constant) uniform Output {
+    int transform;
+} o;
+
+layout(location = 0) out vec4 f_color;
+
+// linear sRGB to linear Display P3, both with a D65 white point
+const mat3 SRGB_TO_P3 = mat3(
+    0.8225, 0.0332, 0.0171,
+    0.1774, 0.9669, 0.0724,
+    0.0000, 0.0000, 0.9108
+);
+
+// the attachment applies the sRGB curve, so other curves are written through its inverse
+vec3 srgb_to_linear(vec3 color) {
+    vec3 lo = color / 12.92;
+    vec3 hi = pow((color + 0.055) / 1.055, vec3(2.4));
+    return mix(lo, hi, greaterThan(color, vec3(0.04045)));
+}
+
+void main() {
+    vec4 color = texelFetch(scene, ivec2(gl_FragCoord.xy), 0);
+    vec3 rgb = color.rgb;
+    if (o.transform == OUTPUT_DISPLAY_P3) {
+        // Display P3 uses the sRGB curve of the attachment
+        rgb = SRGB_TO_P3 * rgb;
+    } else if (o.transform == OUTPUT_REC709) {
+        // the inverse of the BT.1886 EOTF
+        rgb = srgb_to_linear(pow(clamp(rgb, 0.0, 1.0), vec3(1.0 / 2.4)));
+    }
+    f_color = vec4(rgb, color.a);
+}
+        "#
+    }
+}
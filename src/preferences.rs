@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+const SCALES: [f32; 6] = [0.75, 1.0, 1.25, 1.5, 1.75, 2.0];
+
+/// Look of the interface and which sections of the settings panel are open.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiPreferences {
+    pub theme: egui::ThemePreference,
+    /// Multiplies the scale factor of the monitor.
+    pub scale: f32,
+    /// Headers of the settings panel that were expanded.
+    pub open_sections: BTreeSet<String>,
+    /// The open sections were given to egui since the preferences were loaded.
+    #[serde(skip)]
+    restored: bool,
+}
+impl Default for UiPreferences {
+    fn default() -> Self {
+        Self {
+            theme: egui::ThemePreference::Dark,
+            scale: 1.0,
+            open_sections: BTreeSet::new(),
+            restored: false,
+        }
+    }
+}
+impl UiPreferences {
+    /// Applied every frame so the scale follows the monitor the window is on.
+    pub fn apply(&self, ctx: &egui::Context) {
+        ctx.set_theme(self.theme);
+        let native = ctx.native_pixels_per_point().unwrap_or(1.0);
+        ctx.set_pixels_per_point(native * self.scale.clamp(SCALES[0], SCALES[5]));
+    }
+
+    /// Opens the headers named in `sections` and closes the others with the next
+    /// [`persist_sections`](Self::persist_sections).
+    pub fn restore_sections(&mut self, sections: BTreeSet<String>) {
+        self.open_sections = sections;
+        self.restored = false;
+    }
+    /// Keeps the headers named `sections` in `ui` open or closed like in the last run.
+    pub fn persist_sections(&mut self, ui: &egui::Ui, sections: &[&str]) {
+        let restore = !std::mem::replace(&mut self.restored, true);
+        for &section in sections {
+            let id = ui.make_persistent_id(egui::Id::new(section));
+            let mut state = egui::collapsing_header::CollapsingState::load_with_default_open(
+                ui.ctx(),
+                id,
+                false,
+            );
+            if restore {
+                state.set_open(self.open_sections.contains(section));
+                state.store(ui.ctx());
+            } else if state.is_open() {
+                self.open_sections.insert(section.to_owned());
+            } else {
+                self.open_sections.remove(section);
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        self.theme.radio_buttons(ui);
+        egui::ComboBox::from_label("UI scale")
+            .selected_text(format!("{:.0}%", self.scale * 100.0))
+            .show_ui(ui, |ui| {
+                for scale in SCALES {
+                    ui.selectable_value(&mut self.scale, scale, format!("{:.0}%", scale * 100.0));
+                }
+            });
+    }
+}
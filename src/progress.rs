@@ -0,0 +1,109 @@
+use std::{
+    any::Any,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, Sender, channel},
+    },
+};
+
+/// A stage of a background job and how far along the whole job is.
+#[derive(Clone)]
+pub struct Progress {
+    pub stage: String,
+    /// From 0 to 1.
+    pub fraction: f32,
+}
+
+/// Job side: reports progress and checks if it should stop.
+#[derive(Clone)]
+pub struct ProgressSender {
+    sender: Sender<Progress>,
+    cancelled: Arc<AtomicBool>,
+}
+impl ProgressSender {
+    pub fn report(&self, stage: impl Into<String>, fraction: f32) {
+        // the UI may have stopped listening, that's fine
+        let _ = self.sender.send(Progress {
+            stage: stage.into(),
+            fraction,
+        });
+    }
+    pub fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// UI side: keeps the latest progress and can cancel the job.
+pub struct ProgressReceiver {
+    receiver: Receiver<Progress>,
+    cancelled: Arc<AtomicBool>,
+    latest: Progress,
+}
+impl ProgressReceiver {
+    pub fn update(&mut self) {
+        if let Some(progress) = self.receiver.try_iter().last() {
+            self.latest = progress;
+        }
+    }
+    pub fn latest(&self) -> &Progress {
+        &self.latest
+    }
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+pub fn progress() -> (ProgressSender, ProgressReceiver) {
+    let (sender, receiver) = channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    (
+        ProgressSender {
+            sender,
+            cancelled: cancelled.clone(),
+        },
+        ProgressReceiver {
+            receiver,
+            cancelled,
+            latest: Progress {
+                stage: "Starting".to_owned(),
+                fraction: 0.0,
+            },
+        },
+    )
+}
+
+/// Progress bar with a cancel button.
+pub fn progress_ui(ui: &mut egui::Ui, progress: &ProgressReceiver) {
+    let latest = progress.latest();
+    let text = if progress.is_cancelled() {
+        "Cancelling"
+    } else {
+        &latest.stage
+    };
+    ui.add(
+        egui::ProgressBar::new(latest.fraction)
+            .desired_width(120.0)
+            .text(text),
+    );
+    if ui
+        .add_enabled(!progress.is_cancelled(), egui::Button::new("Cancel"))
+        .clicked()
+    {
+        progress.cancel();
+    }
+}
+
+/// Turns the payload of a panicked job into something to show the user.
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "the loading thread panicked".to_owned()
+    }
+}
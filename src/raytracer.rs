@@ -1,31 +1,25 @@
 use crate::{
     Allocators,
-    camera::OrbitCamera,
-    gltf::{GltfRenderInfo, loader::mesh::PrimitiveVertex},
+    acceleration::{RaytracerCamera, SceneAcceleration},
+    camera::Camera,
+    memory::MemoryCategory,
+    skybox::renderer::EnvironmentPush,
 };
+use egui_winit_vulkano::Gui;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use vulkano::{
-    acceleration_structure::{
-        AccelerationStructure, AccelerationStructureBuildGeometryInfo,
-        AccelerationStructureBuildRangeInfo, AccelerationStructureBuildType,
-        AccelerationStructureCreateInfo, AccelerationStructureGeometries,
-        AccelerationStructureGeometryInstancesData, AccelerationStructureGeometryInstancesDataType,
-        AccelerationStructureGeometryTrianglesData, AccelerationStructureInstance,
-        AccelerationStructureType, BuildAccelerationStructureFlags, BuildAccelerationStructureMode,
-    },
-    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
-    command_buffer::{
-        AutoCommandBufferBuilder, CommandBufferUsage, PrimaryCommandBufferAbstract,
-        allocator::CommandBufferAllocator,
-    },
+    buffer::{Buffer, BufferCreateInfo, BufferUsage},
+    command_buffer::AutoCommandBufferBuilder,
     descriptor_set::{DescriptorSet, WriteDescriptorSet},
-    device::{Device, Queue},
+    device::Device,
     format::Format,
-    image::{Image, ImageCreateInfo, ImageUsage, view::ImageView},
-    memory::{
-        DeviceAlignment,
-        allocator::{AllocationCreateInfo, DeviceLayout, MemoryAllocator, MemoryTypeFilter},
+    image::{
+        Image, ImageCreateInfo, ImageUsage,
+        sampler::{Sampler, SamplerCreateInfo},
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
     },
+    memory::allocator::{AllocationCreateInfo, MemoryAllocator, MemoryTypeFilter},
     pipeline::{
         Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo,
         layout::PipelineDescriptorSetLayoutCreateInfo,
@@ -34,21 +28,46 @@ use vulkano::{
             ShaderBindingTable,
         },
     },
-    sync::GpuFuture,
 };
 
+/// How the viewport is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RenderMode {
+    #[default]
+    Rasterizer,
+    Raytracer,
+}
+impl RenderMode {
+    /// `supported` is false if the device can't ray trace.
+    pub fn ui(&mut self, ui: &mut egui::Ui, supported: bool) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(self, RenderMode::Rasterizer, "Rasterizer");
+            ui.add_enabled_ui(supported, |ui| {
+                ui.selectable_value(self, RenderMode::Raytracer, "Raytracer")
+                    .on_disabled_hover_text("Ray tracing is not supported by this GPU");
+            });
+        });
+    }
+}
+
+/// A simple ray traced preview: primary rays lit by the environment.
 #[derive(Clone)]
 pub struct Raytracer {
     pipeline: Arc<RayTracingPipeline>,
     shader_binding_table: ShaderBindingTable,
-    tlas: Option<Arc<AccelerationStructure>>,
+    /// Environment cube and its irradiance.
+    environment: Option<(Arc<ImageView>, Arc<ImageView>)>,
+    sampler: Arc<Sampler>,
     allocators: Allocators,
-    pub view: Arc<ImageView>,
-
-    _blas: Vec<Arc<AccelerationStructure>>,
+    /// One output image per frame in flight.
+    views: Vec<Arc<ImageView>>,
+    /// The views registered with egui, the same order as `views`.
+    textures: Vec<egui::TextureId>,
+    /// The views were recreated and need to be registered again.
+    stale: bool,
 }
 impl Raytracer {
-    pub fn new(device: &Arc<Device>, allocators: Allocators) -> Self {
+    pub fn new(device: &Arc<Device>, allocators: Allocators, num_frames: usize) -> Self {
         let raygen = raygen::load(device.clone())
             .unwrap()
             .entry_point("main")
@@ -100,137 +119,153 @@ impl Raytracer {
         let shader_binding_table =
             ShaderBindingTable::new(allocators.mem.clone(), &pipeline).unwrap();
 
-        let view = Self::new_view(allocators.mem.clone(), [1, 1]);
+        let sampler =
+            Sampler::new(device.clone(), SamplerCreateInfo::simple_repeat_linear()).unwrap();
+        let views = (0..num_frames)
+            .map(|_| {
+                Self::new_view(
+                    allocators.memory.allocator(MemoryCategory::RenderTargets),
+                    [1, 1],
+                )
+            })
+            .collect();
 
         Self {
             pipeline,
             shader_binding_table,
-            tlas: None,
+            environment: None,
+            sampler,
             allocators,
-            view,
-            _blas: vec![],
+            views,
+            textures: vec![],
+            stale: true,
         }
     }
-    pub fn build(&mut self, queue: Arc<Queue>, info: &GltfRenderInfo) {
-        let (blas, other): (Vec<_>, Vec<Vec<_>>) = info
-            .meshes
-            .iter()
-            .flat_map(|instances| {
-                instances.primatives().iter().map(|primitive| unsafe {
-                    let blas = build_acceleration_structure_triangles(
-                        primitive.vbuf().clone(),
-                        primitive.ibuf().clone(),
-                        self.allocators.mem.clone(),
-                        self.allocators.cmd.clone(),
-                        queue.device().clone(),
-                        queue.clone(),
-                    );
-                    (
-                        blas.clone(),
-                        instances
-                            .instances()
-                            .iter()
-                            .map(move |transform| AccelerationStructureInstance {
-                                acceleration_structure_reference: blas.device_address().into(),
-                                transform: transform.remove_row(3).transpose().into(),
-                                ..Default::default()
-                            })
-                            .collect(),
-                    )
-                })
-            })
-            .collect();
-
-        let tlas = unsafe {
-            build_top_level_acceleration_structure(
-                other.concat(),
-                self.allocators.mem.clone(),
-                self.allocators.cmd.clone(),
-                queue.device().clone(),
-                queue.clone(),
+    /// Uses the skybox cube and its irradiance map for the background and lighting.
+    pub fn set_environment(&mut self, cube: Arc<Image>, irradiance: Arc<Image>) {
+        let cube_view = |image: Arc<Image>| {
+            ImageView::new(
+                image.clone(),
+                ImageViewCreateInfo {
+                    view_type: ImageViewType::Cube,
+                    ..ImageViewCreateInfo::from_image(&image)
+                },
             )
+            .unwrap()
         };
-
-        self.tlas = Some(tlas);
-        self._blas = blas;
+        self.environment = Some((cube_view(cube), cube_view(irradiance)));
     }
-    pub fn render(&self, orbit_camera: OrbitCamera, aspect: f32, queue: Arc<Queue>) {
-        if let Some(tlas) = self.tlas.clone() {
-            let mut builder = AutoCommandBufferBuilder::primary(
-                self.allocators.cmd.clone(),
-                queue.queue_family_index(),
-                CommandBufferUsage::OneTimeSubmit,
+    /// True once there is something to trace.
+    pub fn ready(&self, acceleration: &SceneAcceleration) -> bool {
+        acceleration.tlas.is_some() && self.environment.is_some()
+    }
+    /// Records tracing the scene into the image of `frame`.
+    pub fn render<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        frame: usize,
+        acceleration: &SceneAcceleration,
+        camera: &Camera,
+        environment: EnvironmentPush,
+    ) {
+        let (Some(tlas), Some(geometries), Some((cube, irradiance))) = (
+            &acceleration.tlas,
+            &acceleration.geometries,
+            &self.environment,
+        ) else {
+            return;
+        };
+        let view = &self.views[frame];
+        let extent = view.image().extent();
+        let aspect = extent[0] as f32 / extent[1] as f32;
+
+        let camera = Buffer::from_data(
+            self.allocators.mem.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::UNIFORM_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            RaytracerCamera::new(camera, aspect),
+        )
+        .unwrap();
+        let scene_set = DescriptorSet::new(
+            self.allocators.set.clone(),
+            self.pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::acceleration_structure(0, tlas.clone()),
+                WriteDescriptorSet::buffer(1, camera),
+                WriteDescriptorSet::buffer(2, geometries.clone()),
+                WriteDescriptorSet::image_view_sampler(3, cube.clone(), self.sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(4, irradiance.clone(), self.sampler.clone()),
+            ],
+            [],
+        )
+        .unwrap();
+        let image_set = DescriptorSet::new(
+            self.allocators.set.clone(),
+            self.pipeline.layout().set_layouts()[1].clone(),
+            [WriteDescriptorSet::image_view(0, view.clone())],
+            [],
+        )
+        .unwrap();
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::RayTracing,
+                self.pipeline.layout().clone(),
+                0,
+                vec![scene_set, image_set],
             )
+            .unwrap()
+            .push_constants(self.pipeline.layout().clone(), 0, environment)
+            .unwrap()
+            .bind_pipeline_ray_tracing(self.pipeline.clone())
             .unwrap();
 
-            let camera = [
-                orbit_camera.look_at().try_inverse().unwrap(),
-                orbit_camera.perspective(aspect),
-            ];
-            let camera = Buffer::from_data(
-                self.allocators.mem.clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::UNIFORM_BUFFER,
-                    ..Default::default()
-                },
-                AllocationCreateInfo {
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-                camera,
-            )
-            .unwrap();
-            let scene_set = DescriptorSet::new(
-                self.allocators.set.clone(),
-                self.pipeline.layout().set_layouts()[0].clone(),
-                [
-                    WriteDescriptorSet::acceleration_structure(0, tlas),
-                    WriteDescriptorSet::buffer(1, camera),
-                ],
-                [],
-            )
-            .unwrap();
-            let image_set = DescriptorSet::new(
-                self.allocators.set.clone(),
-                self.pipeline.layout().set_layouts()[1].clone(),
-                [WriteDescriptorSet::image_view(0, self.view.clone())],
-                [],
-            )
+        unsafe { builder.trace_rays(self.shader_binding_table.addresses().clone(), extent) }
             .unwrap();
-            builder
-                .bind_descriptor_sets(
-                    PipelineBindPoint::RayTracing,
-                    self.pipeline.layout().clone(),
-                    0,
-                    vec![scene_set, image_set],
-                )
-                .unwrap()
-                .bind_pipeline_ray_tracing(self.pipeline.clone())
-                .unwrap();
-
-            unsafe {
-                builder.trace_rays(
-                    self.shader_binding_table.addresses().clone(),
-                    self.view.image().extent(),
-                )
+    }
+    /// Recreates the output images if the viewport changed size.
+    pub fn resize(&mut self, size: [u32; 2]) {
+        let size = [size[0].max(1), size[1].max(1)];
+        if self.views[0].image().extent()[..2] != size[..] {
+            for view in &mut self.views {
+                *view = Self::new_view(
+                    self.allocators
+                        .memory
+                        .allocator(MemoryCategory::RenderTargets),
+                    size,
+                );
             }
-            .unwrap();
-
-            let cb = builder.build().unwrap();
-            cb.execute(queue)
-                .unwrap()
-                .then_signal_fence_and_flush()
-                .unwrap()
-                .wait(None)
-                .unwrap();
+            self.stale = true;
         }
-        // builder.bind
     }
-    pub fn resize(&mut self, size: [u32; 2]) {
-        if self.view.image().extent()[..2] != size[..] {
-            self.view = Self::new_view(self.allocators.mem.clone(), size);
+    /// Makes the output images available to egui, replacing the old ones after a resize.
+    pub fn register(&mut self, gui: &mut Gui) {
+        if !self.stale {
+            return;
         }
+        for texture in self.textures.drain(..) {
+            gui.unregister_user_image(texture);
+        }
+        self.textures = self
+            .views
+            .iter()
+            .map(|view| gui.register_user_image_view(view.clone(), SamplerCreateInfo::default()))
+            .collect();
+        self.stale = false;
+    }
+    /// Forgets the textures of a dropped [`Gui`], they are registered again with the next one.
+    pub fn forget_textures(&mut self) {
+        self.textures.clear();
+        self.stale = true;
+    }
+    pub fn texture(&self, frame: usize) -> Option<egui::TextureId> {
+        self.textures.get(frame).copied()
     }
     fn new_view(mem_allocator: Arc<dyn MemoryAllocator>, size: [u32; 2]) -> Arc<ImageView> {
         let image = Image::new(
@@ -248,221 +283,6 @@ impl Raytracer {
     }
 }
 
-/// A helper function to build a acceleration structure and wait for its completion.
-///
-/// # Safety
-///
-/// - If you are referencing a bottom-level acceleration structure in a top-level acceleration
-///   structure, you must ensure that the bottom-level acceleration structure is kept alive.
-unsafe fn build_acceleration_structure_common(
-    geometries: AccelerationStructureGeometries,
-    primitive_count: u32,
-    ty: AccelerationStructureType,
-    memory_allocator: Arc<dyn MemoryAllocator>,
-    command_buffer_allocator: Arc<dyn CommandBufferAllocator>,
-    device: Arc<Device>,
-    queue: Arc<Queue>,
-) -> Arc<AccelerationStructure> {
-    let mut as_build_geometry_info = AccelerationStructureBuildGeometryInfo {
-        mode: BuildAccelerationStructureMode::Build,
-        flags: BuildAccelerationStructureFlags::PREFER_FAST_TRACE,
-        ..AccelerationStructureBuildGeometryInfo::new(geometries)
-    };
-
-    let as_build_sizes_info = device
-        .acceleration_structure_build_sizes(
-            AccelerationStructureBuildType::Device,
-            &as_build_geometry_info,
-            &[primitive_count],
-        )
-        .unwrap();
-
-    // We create a new scratch buffer for each acceleration structure for simplicity. You may want
-    // to reuse scratch buffers if you need to build many acceleration structures.
-    // let scratch_buffer = Buffer::new_slice::<u8>(
-    //     memory_allocator.clone(),
-    //     BufferCreateInfo {
-    //         usage: BufferUsage::SHADER_DEVICE_ADDRESS | BufferUsage::STORAGE_BUFFER,
-    //         ..Default::default()
-    //     },
-    //     AllocationCreateInfo::default(),
-    //     as_build_sizes_info.build_scratch_size,
-    // )
-    // .unwrap()
-    // .align_to(
-    //     DeviceLayout::new(
-    //         as_build_sizes_info.build_scratch_size.try_into().unwrap(),
-    //         DeviceAlignment::new(
-    //             device
-    //                 .physical_device()
-    //                 .properties()
-    //                 .min_acceleration_structure_scratch_offset_alignment
-    //                 .unwrap()
-    //                 .try_into()
-    //                 .unwrap(),
-    //         )
-    //         .unwrap(),
-    //     )
-    //     .unwrap(),
-    // );
-
-    let scratch_buffer = Buffer::new(
-        memory_allocator.clone(),
-        BufferCreateInfo {
-            usage: BufferUsage::SHADER_DEVICE_ADDRESS | BufferUsage::STORAGE_BUFFER,
-            ..Default::default()
-        },
-        AllocationCreateInfo::default(),
-        DeviceLayout::new(
-            as_build_sizes_info.build_scratch_size.try_into().unwrap(),
-            DeviceAlignment::new(
-                device
-                    .physical_device()
-                    .properties()
-                    .min_acceleration_structure_scratch_offset_alignment
-                    .unwrap()
-                    .into(),
-            )
-            .unwrap(),
-        )
-        .unwrap(),
-    )
-    .unwrap();
-
-    let as_create_info = AccelerationStructureCreateInfo {
-        ty,
-        ..AccelerationStructureCreateInfo::new(
-            Buffer::new_slice::<u8>(
-                memory_allocator,
-                BufferCreateInfo {
-                    usage: BufferUsage::ACCELERATION_STRUCTURE_STORAGE
-                        | BufferUsage::SHADER_DEVICE_ADDRESS,
-                    ..Default::default()
-                },
-                AllocationCreateInfo::default(),
-                as_build_sizes_info.acceleration_structure_size,
-            )
-            .unwrap(),
-        )
-    };
-
-    let acceleration = unsafe { AccelerationStructure::new(device, as_create_info) }.unwrap();
-
-    as_build_geometry_info.dst_acceleration_structure = Some(acceleration.clone());
-    as_build_geometry_info.scratch_data = Some(scratch_buffer.into());
-
-    let as_build_range_info = AccelerationStructureBuildRangeInfo {
-        primitive_count,
-        ..Default::default()
-    };
-
-    // For simplicity, we build a single command buffer that builds the acceleration structure,
-    // then waits for its execution to complete.
-    let mut builder = AutoCommandBufferBuilder::primary(
-        command_buffer_allocator,
-        queue.queue_family_index(),
-        CommandBufferUsage::OneTimeSubmit,
-    )
-    .unwrap();
-
-    unsafe {
-        builder
-            .build_acceleration_structure(
-                as_build_geometry_info,
-                std::iter::once(as_build_range_info).collect(),
-            )
-            .unwrap()
-    };
-
-    builder
-        .build()
-        .unwrap()
-        .execute(queue)
-        .unwrap()
-        .then_signal_fence_and_flush()
-        .unwrap()
-        .wait(None)
-        .unwrap();
-
-    acceleration
-}
-
-unsafe fn build_acceleration_structure_triangles(
-    vertex_buffer: Subbuffer<[PrimitiveVertex]>,
-    index_buffer: Subbuffer<[u32]>,
-    memory_allocator: Arc<dyn MemoryAllocator>,
-    command_buffer_allocator: Arc<dyn CommandBufferAllocator>,
-    device: Arc<Device>,
-    queue: Arc<Queue>,
-) -> Arc<AccelerationStructure> {
-    let primitive_count = (index_buffer.len() / 3) as u32;
-    let as_geometry_triangles_data = AccelerationStructureGeometryTrianglesData {
-        max_vertex: vertex_buffer.len() as _,
-        vertex_data: Some(vertex_buffer.into_bytes()),
-        vertex_stride: size_of::<PrimitiveVertex>() as _,
-        index_data: Some(index_buffer.into()),
-        ..AccelerationStructureGeometryTrianglesData::new(Format::R32G32B32_SFLOAT)
-    };
-
-    let geometries = AccelerationStructureGeometries::Triangles(vec![as_geometry_triangles_data]);
-
-    unsafe {
-        build_acceleration_structure_common(
-            geometries,
-            primitive_count,
-            AccelerationStructureType::BottomLevel,
-            memory_allocator,
-            command_buffer_allocator,
-            device,
-            queue,
-        )
-    }
-}
-
-unsafe fn build_top_level_acceleration_structure(
-    as_instances: Vec<AccelerationStructureInstance>,
-    allocator: Arc<dyn MemoryAllocator>,
-    command_buffer_allocator: Arc<dyn CommandBufferAllocator>,
-    device: Arc<Device>,
-    queue: Arc<Queue>,
-) -> Arc<AccelerationStructure> {
-    let primitive_count = as_instances.len() as u32;
-
-    let instance_buffer = Buffer::from_iter(
-        allocator.clone(),
-        BufferCreateInfo {
-            usage: BufferUsage::SHADER_DEVICE_ADDRESS
-                | BufferUsage::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY,
-            ..Default::default()
-        },
-        AllocationCreateInfo {
-            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-            ..Default::default()
-        },
-        as_instances,
-    )
-    .unwrap();
-
-    let as_geometry_instances_data = AccelerationStructureGeometryInstancesData::new(
-        AccelerationStructureGeometryInstancesDataType::Values(Some(instance_buffer)),
-    );
-
-    let geometries = AccelerationStructureGeometries::Instances(as_geometry_instances_data);
-
-    unsafe {
-        build_acceleration_structure_common(
-            geometries,
-            primitive_count,
-            AccelerationStructureType::TopLevel,
-            allocator,
-            command_buffer_allocator,
-            device,
-            queue,
-        )
-    }
-}
-
 mod raygen {
     vulkano_shaders::shader! {
         ty: "raygen",
@@ -487,7 +307,7 @@ void main() {
 
     vec4 origin = camera.view_inverse * vec4(0, 0, 0, 1);
     vec4 target = camera.proj_inverse * vec4(d.x, d.y, 1, 1);
-    vec4 direction = camera.view_inverse * vec4(normalize(target.xyz), 0);
+    vec4 direction = camera.view_inverse * vec4(normalize(target.xyz / target.w), 0);
 
     uint ray_flags = gl_RayFlagsOpaqueEXT;
     float t_min = 0.001;
@@ -519,13 +339,96 @@ mod closest_hit {
         src: r#"
 #version 460
 #extension GL_EXT_ray_tracing : require
+#extension GL_EXT_buffer_reference : require
 
 layout(location = 0) rayPayloadInEXT vec3 hit_value;
 hitAttributeEXT vec2 attribs;
 
+layout(buffer_reference, std430, buffer_reference_align = 4) readonly buffer Floats {
+    float f[];
+};
+layout(buffer_reference, std430, buffer_reference_align = 4) readonly buffer Indices {
+    uint i[];
+};
+struct Geometry {
+    Floats vertices;
+    Indices indices;
+    vec4 base_color;
+    vec4 emissive;
+    uint index_size;
+};
+layout(set = 0, binding = 2, std430) readonly buffer Geometries {
+    Geometry geometries[];
+};
+layout(set = 0, binding = 4) uniform samplerCube irradiance;
+layout(push_constant) uniform Environment {
+    float intensity;
+    float yaw;
+} env;
+
+// floats in a PrimitiveVertex, the normal follows the position
+const uint VERTEX_FLOATS = 14;
+
+// 16 bit indices are packed in pairs, non-indexed geometry draws the vertices in order
+uint vertex_index(Geometry g, uint i) {
+    if (g.index_size == 0u) {
+        return i;
+    }
+    if (g.index_size == 2u) {
+        uint pair = g.indices.i[i >> 1];
+        return (i & 1u) == 0u ? pair & 0xFFFFu : pair >> 16;
+    }
+    return g.indices.i[i];
+}
+
+vec3 vertex_normal(Geometry g, uint index) {
+    uint base = index * VERTEX_FLOATS + 3;
+    return vec3(g.vertices.f[base], g.vertices.f[base + 1], g.vertices.f[base + 2]);
+}
+
+vec3 env_direction(vec3 dir) {
+    float s = sin(env.yaw);
+    float c = cos(env.yaw);
+    return vec3(c * dir.x + s * dir.z, dir.y, -s * dir.x + c * dir.z);
+}
+
+vec3 pbr_neutral_tone_mapping(vec3 color) {
+    const float startCompression = 0.8 - 0.04;
+    const float desaturation = 0.15;
+
+    float x = min(color.r, min(color.g, color.b));
+    float offset = x < 0.08 ? x - 6.25 * x * x : 0.04;
+    color -= offset;
+
+    float peak = max(color.r, max(color.g, color.b));
+    if (peak < startCompression) return color;
+
+    const float d = 1. - startCompression;
+    float newPeak = 1. - d * d / (peak + d - startCompression);
+    color *= newPeak / peak;
+
+    float g = 1. - 1. / (desaturation * (peak - newPeak) + 1.);
+    return mix(color, newPeak * vec3(1, 1, 1), g);
+}
+
 void main() {
+    Geometry g = geometries[gl_InstanceCustomIndexEXT];
+    uint i0 = vertex_index(g, 3 * gl_PrimitiveID);
+    uint i1 = vertex_index(g, 3 * gl_PrimitiveID + 1);
+    uint i2 = vertex_index(g, 3 * gl_PrimitiveID + 2);
+
     vec3 barycentrics = vec3(1.0 - attribs.x - attribs.y, attribs.x, attribs.y);
-    hit_value = barycentrics;
+    vec3 n = vertex_normal(g, i0) * barycentrics.x
+           + vertex_normal(g, i1) * barycentrics.y
+           + vertex_normal(g, i2) * barycentrics.z;
+    // inverse transpose of the object to world matrix
+    vec3 N = normalize(n * mat3(gl_WorldToObjectEXT));
+    if (dot(N, gl_WorldRayDirectionEXT) > 0.0) {
+        N = -N;
+    }
+
+    vec3 diffuse = textureLod(irradiance, env_direction(N), 0.0).rgb * env.intensity;
+    hit_value = pbr_neutral_tone_mapping(g.base_color.rgb * diffuse);
 }
         "#,
     }
@@ -541,8 +444,18 @@ mod miss {
 
 layout(location = 0) rayPayloadInEXT vec3 hit_value;
 
+layout(set = 0, binding = 3) uniform samplerCube skybox;
+layout(push_constant) uniform Environment {
+    float intensity;
+    float yaw;
+} env;
+
 void main() {
-    hit_value = vec3(0.0, 0.0, 0.2);
+    vec3 dir = gl_WorldRayDirectionEXT;
+    float s = sin(env.yaw);
+    float c = cos(env.yaw);
+    dir = vec3(c * dir.x + s * dir.z, dir.y, -s * dir.x + c * dir.z);
+    hit_value = textureLod(skybox, dir, 0.0).rgb * env.intensity;
 }
         "#,
     }
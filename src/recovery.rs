@@ -0,0 +1,49 @@
+use vulkano::{Validated, VulkanError, memory::allocator::MemoryAllocatorError};
+
+/// How the app can go on after a Vulkan error in the frame loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// The swapchain no longer fits the window.
+    RecreateSwapchain,
+    /// The device or the surface is gone, the context and the windows are created again.
+    RecreateContext,
+    /// The frame is dropped and the next one tries again.
+    SkipFrame,
+}
+impl Recovery {
+    pub fn of(err: &VulkanError) -> Self {
+        match err {
+            VulkanError::OutOfDate => Self::RecreateSwapchain,
+            VulkanError::DeviceLost | VulkanError::SurfaceLost => Self::RecreateContext,
+            _ => Self::SkipFrame,
+        }
+    }
+}
+
+pub fn is_out_of_memory(err: &VulkanError) -> bool {
+    matches!(
+        err,
+        VulkanError::OutOfHostMemory | VulkanError::OutOfDeviceMemory
+    )
+}
+
+/// Allocations fail with out of memory errors or when a single one is larger than allowed.
+pub fn is_allocation_failure(err: &MemoryAllocatorError) -> bool {
+    match err {
+        MemoryAllocatorError::AllocateDeviceMemory(Validated::Error(err)) => is_out_of_memory(err),
+        MemoryAllocatorError::AllocateDeviceMemory(Validated::ValidationError(_)) => false,
+        _ => true,
+    }
+}
+
+/// An error for the error window, with what to try if memory ran out.
+pub fn describe(context: &str, err: &VulkanError) -> String {
+    if is_out_of_memory(err) {
+        format!("{context}: {err}. {OUT_OF_MEMORY_HINT}")
+    } else {
+        format!("{context}: {err}")
+    }
+}
+
+pub const OUT_OF_MEMORY_HINT: &str =
+    "Try a smaller max texture size, texture compression or closing other models.";
@@ -0,0 +1,256 @@
+use crate::{SkyPreset, State, screenshot};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Read,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex, mpsc},
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// A request of an external tool, applied to the first window between frames.
+#[derive(Debug, Clone)]
+pub enum RemoteCommand {
+    LoadModel(PathBuf),
+    Camera {
+        eye: glm::Vec3,
+        target: glm::Vec3,
+    },
+    Skybox(PathBuf),
+    Sky(SkyPreset),
+    /// Written to the pictures directory if `None`.
+    Screenshot(Option<PathBuf>),
+}
+impl RemoteCommand {
+    pub fn apply(self, state: &mut State) {
+        match self {
+            RemoteCommand::LoadModel(path) => state.load_model(path),
+            RemoteCommand::Camera { eye, target } => state.set_camera_pose(eye, target),
+            RemoteCommand::Skybox(path) => state.load_skybox(path),
+            RemoteCommand::Sky(preset) => state.load_sky(preset),
+            RemoteCommand::Screenshot(path) => state.request_screenshot(path),
+        }
+    }
+}
+
+/// What `GET /status` answers, so tools can wait for their loads to finish.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RemoteStatus {
+    pub loading: bool,
+    pub models: usize,
+    pub errors: Vec<String>,
+}
+impl RemoteStatus {
+    pub fn new(state: &State) -> Self {
+        Self {
+            loading: state.loading(),
+            models: state.model_count(),
+            errors: state.errors().to_vec(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PathBody {
+    path: PathBuf,
+}
+#[derive(Deserialize)]
+struct CameraBody {
+    eye: [f32; 3],
+    target: [f32; 3],
+}
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EnvironmentBody {
+    Image { path: PathBuf },
+    Sky { sky: String },
+}
+#[derive(Default, Deserialize)]
+struct ScreenshotBody {
+    name: Option<String>,
+}
+
+/// Bodies are tiny JSON objects, anything larger is refused before it is read.
+const MAX_BODY: u64 = 64 * 1024;
+
+/// An HTTP server on its own thread that lets asset pipelines, DCC plugins and CI jobs drive
+/// the viewer. Every endpoint takes and returns JSON:
+///
+/// - `POST /model` `{"path": "..."}` loads a model next to the others
+/// - `POST /camera` `{"eye": [x, y, z], "target": [x, y, z]}` moves the camera
+/// - `POST /environment` `{"path": "..."}` or `{"sky": "Sunset"}` changes the environment
+/// - `POST /screenshot` `{"name": "shot.png"}` saves the viewport in the pictures directory,
+///   the name is optional and answered with the written path
+/// - `GET /status` tells if anything is still loading and which errors occurred
+///
+/// The commands are only queued, poll the status to know when they are done. Requests need
+/// `Content-Type: application/json` and must not carry an `Origin`, so web pages open in a
+/// browser can't drive the viewer.
+pub struct RemoteControl {
+    commands: mpsc::Receiver<RemoteCommand>,
+    status: Arc<Mutex<RemoteStatus>>,
+}
+impl RemoteControl {
+    /// Listens on `addr`, like `127.0.0.1:7878`. There is no authentication,
+    /// only listen on other interfaces in trusted networks.
+    pub fn start(addr: &str) -> anyhow::Result<Self> {
+        let server = Server::http(addr)
+            .map_err(|err| anyhow::anyhow!("failed to listen on {addr}: {err}"))?;
+        log::info!("remote control listening on http://{addr}");
+        let (sender, commands) = mpsc::channel();
+        let status = Arc::new(Mutex::new(RemoteStatus::default()));
+        let shared = status.clone();
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                if !handle(request, &sender, &shared) {
+                    // the viewer was closed
+                    break;
+                }
+            }
+        });
+        Ok(Self { commands, status })
+    }
+    /// The commands received since the last call.
+    pub fn commands(&self) -> impl Iterator<Item = RemoteCommand> + '_ {
+        self.commands.try_iter()
+    }
+    pub fn set_status(&self, status: RemoteStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+}
+
+/// Answers one request, `false` once the commands can't be sent anymore.
+fn handle(
+    mut request: Request,
+    sender: &mpsc::Sender<RemoteCommand>,
+    status: &Mutex<RemoteStatus>,
+) -> bool {
+    let command = read_body(&mut request)
+        .and_then(|body| parse(request.method(), request.url(), &body, status));
+    let (code, json, sent) = match command {
+        Ok(Reply::Status(status)) => (200, serde_json::to_value(status).unwrap(), true),
+        Ok(Reply::Queued(command)) => {
+            let path = match &command {
+                RemoteCommand::Screenshot(path) => path.clone(),
+                _ => None,
+            };
+            let sent = sender.send(command).is_ok();
+            (
+                202,
+                serde_json::json!({ "queued": sent, "path": path }),
+                sent,
+            )
+        }
+        Err((code, error)) => (code, serde_json::json!({ "error": error }), true),
+    };
+    let response = Response::from_string(json.to_string())
+        .with_status_code(code)
+        .with_header("Content-Type: application/json".parse::<Header>().unwrap());
+    if let Err(err) = request.respond(response) {
+        log::warn!("failed to answer a remote control request: {err}");
+    }
+    sent
+}
+
+/// The body of a request that came from a tool, not from a web page.
+fn read_body(request: &mut Request) -> Result<String, (u16, String)> {
+    let header = |name: &str| {
+        request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv(name))
+            .map(|header| header.value.as_str().to_owned())
+    };
+    // browsers send an Origin with every cross-origin POST, tools don't
+    if header("Origin").is_some() {
+        return Err((403, "requests from web pages are not accepted".to_owned()));
+    }
+    let json = header("Content-Type").is_some_and(|value| {
+        let mime = value.split(';').next().unwrap_or_default();
+        mime.trim().eq_ignore_ascii_case("application/json")
+    });
+    if *request.method() != Method::Get && !json {
+        return Err((415, "the body must be application/json".to_owned()));
+    }
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY + 1)
+        .read_to_string(&mut body)
+        .map_err(|err| (400, format!("failed to read the request: {err}")))?;
+    if body.len() as u64 > MAX_BODY {
+        return Err((413, format!("the body is larger than {MAX_BODY} bytes")));
+    }
+    Ok(body)
+}
+
+/// Where a screenshot called `name` is written, only new PNG or OpenEXR files in the pictures
+/// directory are allowed.
+fn screenshot_path(name: &str) -> Result<PathBuf, (u16, String)> {
+    let mut components = Path::new(name).components();
+    let file = match (components.next(), components.next()) {
+        (Some(Component::Normal(file)), None) => Path::new(file),
+        _ => return Err((400, format!("{name} is not a file name"))),
+    };
+    let supported = file
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png") || ext.eq_ignore_ascii_case("exr"));
+    if !supported {
+        return Err((400, format!("{name} is not a .png or .exr file")));
+    }
+    let path = screenshot::screenshot_dir().join(file);
+    if path.exists() {
+        return Err((409, format!("{} already exists", path.display())));
+    }
+    Ok(path)
+}
+
+enum Reply {
+    Status(RemoteStatus),
+    Queued(RemoteCommand),
+}
+
+fn parse(
+    method: &Method,
+    url: &str,
+    body: &str,
+    status: &Mutex<RemoteStatus>,
+) -> Result<Reply, (u16, String)> {
+    fn json<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, (u16, String)> {
+        serde_json::from_str(body).map_err(|err| (400, format!("invalid body: {err}")))
+    }
+    let command = match (method, url) {
+        (Method::Get, "/status") => return Ok(Reply::Status(status.lock().unwrap().clone())),
+        (Method::Post, "/model") => RemoteCommand::LoadModel(json::<PathBody>(body)?.path),
+        (Method::Post, "/camera") => {
+            let camera: CameraBody = json(body)?;
+            RemoteCommand::Camera {
+                eye: camera.eye.into(),
+                target: camera.target.into(),
+            }
+        }
+        (Method::Post, "/environment") => match json(body)? {
+            EnvironmentBody::Image { path } => RemoteCommand::Skybox(path),
+            EnvironmentBody::Sky { sky } => SkyPreset::ALL
+                .into_iter()
+                .find(|preset| preset.name().eq_ignore_ascii_case(&sky))
+                .map(RemoteCommand::Sky)
+                .ok_or_else(|| (400, format!("unknown sky {sky}")))?,
+        },
+        (Method::Post, "/screenshot") => {
+            let screenshot: ScreenshotBody = if body.trim().is_empty() {
+                ScreenshotBody::default()
+            } else {
+                json(body)?
+            };
+            let path = screenshot
+                .name
+                .as_deref()
+                .map(screenshot_path)
+                .transpose()?;
+            RemoteCommand::Screenshot(path)
+        }
+        _ => return Err((404, format!("no endpoint {method} {url}"))),
+    };
+    Ok(Reply::Queued(command))
+}
@@ -0,0 +1,225 @@
+use crate::{
+    Allocators, State,
+    camera::Camera,
+    frameinfo::{Depth, FrameInfo, Msaa},
+    skybox::sky::SkyPreset,
+};
+use egui_winit_vulkano::Gui;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SubpassBeginInfo, SubpassContents,
+    },
+    device::Queue,
+    image::view::ImageView,
+    pipeline::graphics::viewport::{Scissor, Viewport},
+    render_pass::Subpass,
+};
+
+/// The images a [`GltfViewerRenderer`] draws into, like the images of a swapchain.
+/// They all need the same format and extent.
+pub struct RenderTarget {
+    pub views: Vec<Arc<ImageView>>,
+    pub msaa: Msaa,
+    pub depth: Depth,
+}
+
+/// The viewer for other vulkano apps: it owns its render pass over the images of a
+/// [`RenderTarget`] and draws the scene into them, with or without the viewer UI.
+pub struct GltfViewerRenderer {
+    state: State,
+    frame_info: FrameInfo,
+    extent: [u32; 2],
+    frame: usize,
+    num_frames: usize,
+}
+impl GltfViewerRenderer {
+    /// `queue` has to support graphics. `compute_queue` can be of another family to bake
+    /// environments and upload models next to the frames, otherwise `queue` is used.
+    pub fn new(
+        allocators: &Allocators,
+        queue: Arc<Queue>,
+        compute_queue: Option<Arc<Queue>>,
+        target: RenderTarget,
+        camera: Camera,
+    ) -> Self {
+        let device = queue.device();
+        let frame_info = FrameInfo::new(
+            allocators.mem.clone(),
+            &target.views,
+            target.msaa.or_supported(device),
+            target.depth.or_supported(device),
+        );
+        // one more frame than images so a frame can be recorded while the others are in flight
+        let num_frames = target.views.len() + 1;
+        let mut state = State::new(
+            allocators,
+            queue.clone(),
+            compute_queue.unwrap_or(queue),
+            num_frames,
+            frame_info.subpass().clone(),
+            frame_info.msaa(),
+            frame_info.depth(),
+        );
+        state.set_camera(camera);
+        let [width, height, _] = target.views[0].image().extent();
+
+        Self {
+            state,
+            frame_info,
+            extent: [width, height],
+            frame: 0,
+            num_frames,
+        }
+    }
+    /// The app and the viewer state, for everything the renderer doesn't forward.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+    pub fn state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+    /// The subpass the scene is drawn in, a [`Gui`] drawn by [`render_ui`](Self::render_ui)
+    /// has to be created for it.
+    pub fn subpass(&self) -> &Subpass {
+        self.frame_info.subpass()
+    }
+    pub fn camera(&self) -> &Camera {
+        self.state.camera()
+    }
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.state.set_camera(camera);
+    }
+
+    pub fn load_model(&mut self, path: PathBuf) {
+        self.state.load_model(path);
+    }
+    pub fn load_model_bytes(&mut self, name: impl Into<String>, bytes: impl Into<Arc<[u8]>>) {
+        self.state.load_model_bytes(name, bytes);
+    }
+    pub fn load_skybox(&mut self, path: PathBuf) {
+        self.state.load_skybox(path);
+    }
+    pub fn load_sky(&mut self, preset: SkyPreset) {
+        self.state.load_sky(preset);
+    }
+    /// Blocks until the models and the environment being loaded are ready to be shown.
+    pub fn wait_for_loads(&self) {
+        while self
+            .state
+            .skybox
+            .job
+            .as_ref()
+            .is_some_and(|job| !job.is_finished())
+            || self
+                .state
+                .viewer
+                .job
+                .as_ref()
+                .is_some_and(|job| !job.is_finished())
+        {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+    /// Failed loads and recovered GPU errors, the UI shows them until they are dismissed.
+    pub fn errors(&self) -> &[String] {
+        self.state.errors()
+    }
+
+    /// Call when the images of the target were recreated with the same format,
+    /// like a resized swapchain.
+    pub fn resize(&mut self, views: &[Arc<ImageView>]) {
+        self.frame_info.recreate(views);
+        let [width, height, _] = views[0].image().extent();
+        self.extent = [width, height];
+    }
+    /// Whether the anti-aliasing or depth buffer picked in the UI differs from the render pass.
+    pub fn attachments_changed(&self) -> bool {
+        self.state.msaa() != self.frame_info.msaa() || self.state.depth() != self.frame_info.depth()
+    }
+    /// Recreates the render pass with the attachments picked in the UI,
+    /// a [`Gui`] made for the old [`subpass`](Self::subpass) has to be recreated too.
+    pub fn apply_attachments(&mut self, views: &[Arc<ImageView>]) {
+        let (msaa, depth) = (self.state.msaa(), self.state.depth());
+        self.frame_info.set_attachments(msaa, depth, views);
+        self.state
+            .set_subpass(self.frame_info.subpass().clone(), depth);
+    }
+
+    /// Lays out the viewer UI for the next [`render_ui`](Self::render_ui).
+    pub fn show(&mut self, gui: &mut Gui) {
+        let index = self.frame_index();
+        self.state.register_textures(gui);
+        gui.immediate_ui(|gui| {
+            self.state.show(&gui.egui_ctx, index);
+        });
+    }
+    /// Draws the scene over the whole of target image `image`, without any UI.
+    pub fn render(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image: usize,
+    ) {
+        let index = self.frame_index();
+        let [width, height] = self.extent;
+        self.state.aspect = width as f32 / height as f32;
+        self.state.update(builder, index);
+        builder
+            .begin_render_pass(
+                self.frame_info
+                    .render_pass_info(image, self.state.background()),
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .set_viewport(
+                0,
+                [Viewport {
+                    extent: [width as f32, height as f32],
+                    ..Default::default()
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .set_scissor(0, [Scissor::default()].into_iter().collect())
+            .unwrap();
+        self.state.frame(index).render(builder);
+        builder.end_render_pass(Default::default()).unwrap();
+        self.end_frame(builder, index);
+    }
+    /// Draws the UI laid out by [`show`](Self::show) into target image `image`,
+    /// the scene is drawn in its viewport.
+    pub fn render_ui(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image: usize,
+        gui: &mut Gui,
+    ) {
+        let index = self.frame_index();
+        self.state.update(builder, index);
+        builder
+            .begin_render_pass(
+                self.frame_info
+                    .render_pass_info(image, self.state.background()),
+                SubpassBeginInfo {
+                    contents: SubpassContents::SecondaryCommandBuffers,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let cb = gui.draw_on_subpass_image(self.extent);
+        builder.execute_commands(cb).unwrap();
+        builder.end_render_pass(Default::default()).unwrap();
+        self.end_frame(builder, index);
+    }
+    fn frame_index(&self) -> usize {
+        self.frame % self.num_frames
+    }
+    fn end_frame<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>, index: usize) {
+        self.state.end_frame(builder, index);
+        self.frame += 1;
+    }
+}
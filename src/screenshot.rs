@@ -0,0 +1,206 @@
+use crate::color::OutputTransform;
+use image::ImageEncoder;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use vulkano::{
+    DeviceSize,
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, BufferImageCopy, CopyImageToBufferInfo},
+    format::Format,
+    image::Image,
+    memory::allocator::{AllocationCreateInfo, MemoryAllocator, MemoryTypeFilter},
+};
+
+/// How a screenshot is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ScreenshotFormat {
+    /// The tone mapped viewport.
+    #[default]
+    Png,
+    /// The colour before tone mapping as 16 bit float OpenEXR.
+    ExrHalf,
+    /// The colour before tone mapping as 32 bit float OpenEXR.
+    ExrFloat,
+}
+impl ScreenshotFormat {
+    pub const ALL: [ScreenshotFormat; 3] = [
+        ScreenshotFormat::Png,
+        ScreenshotFormat::ExrHalf,
+        ScreenshotFormat::ExrFloat,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScreenshotFormat::Png => "PNG",
+            ScreenshotFormat::ExrHalf => "EXR (half float)",
+            ScreenshotFormat::ExrFloat => "EXR (float)",
+        }
+    }
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ScreenshotFormat::Png => "png",
+            ScreenshotFormat::ExrHalf | ScreenshotFormat::ExrFloat => "exr",
+        }
+    }
+    /// The format for a screenshot written to `path`, a `.exr` path is always HDR.
+    pub fn for_path(self, path: Option<&Path>) -> Self {
+        let exr = path
+            .and_then(|path| path.extension())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("exr"));
+        match self {
+            ScreenshotFormat::Png if exr => ScreenshotFormat::ExrFloat,
+            format => format,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Screenshot format")
+            .selected_text(self.name())
+            .show_ui(ui, |ui| {
+                for format in Self::ALL {
+                    ui.selectable_value(self, format, format.name());
+                }
+            })
+            .response
+            .on_hover_text("EXR keeps the dynamic range before tone mapping for compositing");
+    }
+}
+
+/// The viewport part of a swapchain image, read back once the frame is finished.
+pub struct Screenshot {
+    buffer: Subbuffer<[u8]>,
+    extent: [u32; 2],
+    /// Red and blue have to be swapped.
+    bgra: bool,
+}
+impl Screenshot {
+    /// Copies `extent` pixels at `offset` of `image` after everything recorded so far,
+    /// `None` if the region is empty or the format is not 8 bit RGBA or BGRA.
+    pub fn record<L>(
+        builder: &mut AutoCommandBufferBuilder<L>,
+        allocator: Arc<dyn MemoryAllocator>,
+        image: Arc<Image>,
+        offset: [u32; 2],
+        extent: [u32; 2],
+    ) -> Option<Self> {
+        let bgra = match image.format() {
+            Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM => true,
+            Format::R8G8B8A8_SRGB | Format::R8G8B8A8_UNORM => false,
+            format => {
+                log::warn!("screenshots of {format:?} images are not supported");
+                return None;
+            }
+        };
+        // the viewport can reach past the image while the window is resized
+        let [width, height, _] = image.extent();
+        let offset = [offset[0].min(width), offset[1].min(height)];
+        let extent = [
+            extent[0].min(width - offset[0]),
+            extent[1].min(height - offset[1]),
+        ];
+        if extent.contains(&0) {
+            return None;
+        }
+
+        let buffer = Buffer::new_slice::<u8>(
+            allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            (extent[0] * extent[1] * 4) as DeviceSize,
+        )
+        .unwrap();
+        let region = BufferImageCopy {
+            image_subresource: image.subresource_layers(),
+            image_offset: [offset[0], offset[1], 0],
+            image_extent: [extent[0], extent[1], 1],
+            ..Default::default()
+        };
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo {
+                regions: [region].into_iter().collect(),
+                ..CopyImageToBufferInfo::image_buffer(image, buffer.clone())
+            })
+            .unwrap();
+
+        Some(Self {
+            buffer,
+            extent,
+            bgra,
+        })
+    }
+
+    /// Writes a PNG to `path` or the pictures directory in the background, tagged with the
+    /// colour space of `transform`. The command buffer of [`record`](Self::record) has to be
+    /// finished.
+    pub fn save(self, path: Option<PathBuf>, transform: OutputTransform) {
+        let mut pixels = self.buffer.read().unwrap().to_vec();
+        for pixel in pixels.chunks_exact_mut(4) {
+            if self.bgra {
+                pixel.swap(0, 2);
+            }
+            // the swapchain alpha is meaningless
+            pixel[3] = 255;
+        }
+        let [width, height] = self.extent;
+        std::thread::spawn(move || {
+            let path = path.unwrap_or_else(|| screenshot_path("png"));
+            let png = path
+                .extension()
+                .is_none_or(|ext| ext.eq_ignore_ascii_case("png"));
+            let result = if png {
+                write_png(&path, &pixels, [width, height], transform)
+            } else {
+                image::save_buffer(&path, &pixels, width, height, image::ColorType::Rgba8)
+                    .map_err(Into::into)
+            };
+            match result {
+                Ok(()) => log::info!("wrote {}", path.display()),
+                Err(err) => log::error!("failed to save screenshot {}: {err}", path.display()),
+            }
+        });
+    }
+}
+
+fn write_png(
+    path: &Path,
+    pixels: &[u8],
+    [width, height]: [u32; 2],
+    transform: OutputTransform,
+) -> anyhow::Result<()> {
+    let mut png = vec![];
+    image::codecs::png::PngEncoder::new(&mut png).write_image(
+        pixels,
+        width,
+        height,
+        image::ExtendedColorType::Rgba8,
+    )?;
+    transform.tag_png(&mut png);
+    std::fs::write(path, png)?;
+    Ok(())
+}
+
+/// The pictures directory, or the working directory if there is none.
+pub fn screenshot_dir() -> PathBuf {
+    dirs::picture_dir()
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default()
+}
+
+/// A new file in the pictures directory.
+pub fn screenshot_path(extension: &str) -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    screenshot_dir().join(format!("gltf-viewer-{secs}.{extension}"))
+}
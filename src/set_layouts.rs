@@ -24,8 +24,20 @@ pub struct SetLayouts {
     pub texture: Arc<DescriptorSetLayout>,
     pub material: Arc<DescriptorSetLayout>,
     pub environment: Arc<DescriptorSetLayout>,
+    pub lights: Arc<DescriptorSetLayout>,
+    pub morph: Arc<DescriptorSetLayout>,
 }
 impl SetLayouts {
+    /// The set layouts of the glTF pipeline.
+    pub fn gltf(&self) -> Vec<Arc<DescriptorSetLayout>> {
+        vec![
+            self.camera.clone(),
+            self.environment.clone(),
+            self.material.clone(),
+            self.lights.clone(),
+            self.morph.clone(),
+        ]
+    }
     pub fn new(device: Arc<Device>) -> Self {
         let camera = DescriptorSetLayout::new(
             device.clone(),
@@ -50,6 +62,8 @@ impl SetLayouts {
                     texture_layout(2),
                     texture_layout(3),
                     texture_layout(4),
+                    texture_layout(5),
+                    texture_layout(6),
                 ]),
                 ..Default::default()
             },
@@ -64,19 +78,71 @@ impl SetLayouts {
         )
         .unwrap();
         let environment = DescriptorSetLayout::new(
-            device,
+            device.clone(),
             DescriptorSetLayoutCreateInfo {
                 bindings: BTreeMap::from([texture_layout(0), texture_layout(1), texture_layout(2)]),
                 ..Default::default()
             },
         )
         .unwrap();
+        let lights = DescriptorSetLayout::new(
+            device.clone(),
+            DescriptorSetLayoutCreateInfo {
+                bindings: BTreeMap::from([
+                    (
+                        0,
+                        DescriptorSetLayoutBinding {
+                            stages: ShaderStages::FRAGMENT,
+                            ..DescriptorSetLayoutBinding::descriptor_type(
+                                DescriptorType::UniformBuffer,
+                            )
+                        },
+                    ),
+                    texture_layout(1),
+                    texture_layout(2),
+                    texture_layout(3),
+                    texture_layout(4),
+                ]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let morph = DescriptorSetLayout::new(
+            device,
+            DescriptorSetLayoutCreateInfo {
+                bindings: BTreeMap::from([
+                    (
+                        0,
+                        DescriptorSetLayoutBinding {
+                            stages: ShaderStages::VERTEX,
+                            ..DescriptorSetLayoutBinding::descriptor_type(
+                                DescriptorType::StorageBuffer,
+                            )
+                        },
+                    ),
+                    (
+                        1,
+                        DescriptorSetLayoutBinding {
+                            stages: ShaderStages::VERTEX,
+                            ..DescriptorSetLayoutBinding::descriptor_type(
+                                DescriptorType::UniformBuffer,
+                            )
+                        },
+                    ),
+                ]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         Self {
             camera,
             texture,
             material,
             environment,
+            lights,
+            morph,
         }
     }
 }
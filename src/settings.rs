@@ -0,0 +1,117 @@
+use crate::{
+    camera::{Bookmarks, Camera, CameraPath},
+    collision::CollisionSettings,
+    color::OutputTransform,
+    frameinfo::{Depth, Msaa},
+    material_presets::MaterialPresets,
+    pathtracer::BeautySettings,
+    preferences::UiPreferences,
+    raytracer::RenderMode,
+    screenshot::ScreenshotFormat,
+    shortcuts::Keymap,
+    skybox::{quality::IblQuality, renderer::Background},
+    viewer::{
+        debug_geometry::DebugGeometrySettings, grid::GridSettings, occlusion::OcclusionSettings,
+        points::PointSettings, shadow_catcher::ShadowCatcherSettings, ssao::SsaoSettings,
+    },
+    vktf::{
+        loader::{DiskCache, GeometryOptions, TextureOptions},
+        space::AssetSpace,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+const MAX_RECENT: usize = 10;
+
+/// Viewer state that is kept between runs, stored as JSON in the platform config directory.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub recent_models: Vec<PathBuf>,
+    pub recent_skyboxes: Vec<PathBuf>,
+    /// Units and up axis of model files that don't use meters and +Y.
+    pub asset_spaces: BTreeMap<PathBuf, AssetSpace>,
+    pub camera: Camera,
+    pub collision: CollisionSettings,
+    pub bookmarks: Bookmarks,
+    pub material_presets: MaterialPresets,
+    pub camera_path: CameraPath,
+    pub keymap: Keymap,
+    pub ui: UiPreferences,
+    /// Scripts run in the console, the newest last.
+    pub console_history: Vec<String>,
+    pub ibl_quality: IblQuality,
+    pub render_mode: RenderMode,
+    pub occlusion: OcclusionSettings,
+    pub ssao: SsaoSettings,
+    /// Resolution of the viewport relative to the window, `None` for 100%.
+    pub render_scale: Option<f32>,
+    pub beauty: BeautySettings,
+    pub textures: TextureOptions,
+    pub geometry: GeometryOptions,
+    pub disk_cache: DiskCache,
+    pub show_stats: bool,
+    pub screenshot_format: ScreenshotFormat,
+    pub output_transform: OutputTransform,
+    pub msaa: Msaa,
+    /// Name or index of the device picked in the UI.
+    pub gpu: Option<String>,
+    pub depth: Depth,
+    pub background: Background,
+    pub grid: GridSettings,
+    pub shadow_catcher: ShadowCatcherSettings,
+    pub debug_geometry: DebugGeometrySettings,
+    pub points: PointSettings,
+    /// The fragment shader file of the shader experiment.
+    pub experiment_shader: Option<PathBuf>,
+}
+impl Settings {
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("gltf-viewer").join("settings.json"))
+    }
+
+    /// Falls back to the defaults if there are no settings yet or they can't be read.
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                log::warn!("ignoring invalid settings {}: {err}", path.display());
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+    pub fn save(&self) {
+        let Some(path) = Self::path() else {
+            return;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&path, serde_json::to_string_pretty(self).unwrap()));
+        if let Err(err) = result {
+            log::warn!("failed to save settings {}: {err}", path.display());
+        }
+    }
+
+    pub fn add_recent_model(&mut self, path: &Path) {
+        add_recent(&mut self.recent_models, path);
+    }
+    pub fn add_recent_skybox(&mut self, path: &Path) {
+        add_recent(&mut self.recent_skyboxes, path);
+    }
+}
+
+/// Moves `path` to the front of `list`.
+fn add_recent(list: &mut Vec<PathBuf>, path: &Path) {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    list.retain(|recent| *recent != path);
+    list.insert(0, path);
+    list.truncate(MAX_RECENT);
+}
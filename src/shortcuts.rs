@@ -0,0 +1,189 @@
+use egui::{Key, KeyboardShortcut, Modifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Something a keyboard shortcut can do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    OpenModel,
+    FrameScene,
+    ToggleWireframe,
+    Screenshot,
+    FrontView,
+    TopView,
+    SideView,
+    Undo,
+    Redo,
+}
+impl Action {
+    pub const ALL: [Action; 9] = [
+        Action::OpenModel,
+        Action::FrameScene,
+        Action::ToggleWireframe,
+        Action::Screenshot,
+        Action::FrontView,
+        Action::TopView,
+        Action::SideView,
+        Action::Undo,
+        Action::Redo,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::OpenModel => "Open model",
+            Action::FrameScene => "Frame scene",
+            Action::ToggleWireframe => "Toggle wireframe",
+            Action::Screenshot => "Screenshot",
+            Action::FrontView => "Front view",
+            Action::TopView => "Top view",
+            Action::SideView => "Side view",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+        }
+    }
+    fn default_shortcut(&self) -> KeyboardShortcut {
+        match self {
+            Action::OpenModel => KeyboardShortcut::new(Modifiers::COMMAND, Key::O),
+            Action::FrameScene => KeyboardShortcut::new(Modifiers::NONE, Key::F),
+            Action::ToggleWireframe => KeyboardShortcut::new(Modifiers::NONE, Key::Z),
+            Action::Screenshot => KeyboardShortcut::new(Modifiers::NONE, Key::F12),
+            Action::FrontView => KeyboardShortcut::new(Modifiers::NONE, Key::Num1),
+            Action::TopView => KeyboardShortcut::new(Modifiers::NONE, Key::Num7),
+            Action::SideView => KeyboardShortcut::new(Modifiers::NONE, Key::Num3),
+            Action::Undo => KeyboardShortcut::new(Modifiers::COMMAND, Key::Z),
+            Action::Redo => KeyboardShortcut::new(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z),
+        }
+    }
+}
+
+/// The shortcut of every action, actions that were never changed use their default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keymap {
+    /// `None` if the action was unbound.
+    bindings: BTreeMap<Action, Option<KeyboardShortcut>>,
+    /// The action waiting for a key press in the settings.
+    #[serde(skip)]
+    recording: Option<Action>,
+}
+impl Keymap {
+    pub fn shortcut(&self, action: Action) -> Option<KeyboardShortcut> {
+        match self.bindings.get(&action) {
+            Some(shortcut) => *shortcut,
+            None => Some(action.default_shortcut()),
+        }
+    }
+    /// The shortcut as text for buttons and tooltips, empty if unbound.
+    pub fn label(&self, ctx: &egui::Context, action: Action) -> String {
+        self.shortcut(action)
+            .map(|shortcut| ctx.format_shortcut(&shortcut))
+            .unwrap_or_default()
+    }
+
+    /// The actions whose shortcut was pressed this frame, nothing while text is edited.
+    pub fn triggered(&self, ctx: &egui::Context) -> Vec<Action> {
+        if self.recording.is_some() || ctx.wants_keyboard_input() {
+            return vec![];
+        }
+        // shortcuts with more modifiers go first so Ctrl+O doesn't also trigger O
+        let mut shortcuts: Vec<_> = Action::ALL
+            .into_iter()
+            .filter_map(|action| self.shortcut(action).map(|shortcut| (action, shortcut)))
+            .collect();
+        shortcuts.sort_by_key(|(_, shortcut)| std::cmp::Reverse(modifier_count(shortcut)));
+        ctx.input_mut(|input| {
+            shortcuts
+                .into_iter()
+                .filter(|(_, shortcut)| input.consume_shortcut(shortcut))
+                .map(|(action, _)| action)
+                .collect()
+        })
+    }
+
+    /// The settings tab, click a shortcut and press the new key combination.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if let Some(action) = self.recording {
+            let pressed = ui.input(|input| {
+                input.events.iter().find_map(|event| match event {
+                    egui::Event::Key {
+                        key,
+                        pressed: true,
+                        modifiers,
+                        ..
+                    } => Some(KeyboardShortcut::new(*modifiers, *key)),
+                    _ => None,
+                })
+            });
+            match pressed {
+                Some(shortcut) if shortcut.logical_key == Key::Escape => self.recording = None,
+                Some(shortcut) => {
+                    self.bindings.insert(action, Some(shortcut));
+                    self.recording = None;
+                }
+                None => {}
+            }
+        }
+
+        egui::Grid::new("shortcuts").num_columns(3).show(ui, |ui| {
+            for action in Action::ALL {
+                ui.label(action.name());
+                let text = if self.recording == Some(action) {
+                    "Press a key...".to_owned()
+                } else {
+                    match self.label(ui.ctx(), action) {
+                        label if label.is_empty() => "None".to_owned(),
+                        label => label,
+                    }
+                };
+                if ui
+                    .button(text)
+                    .on_hover_text("Click and press the new shortcut, Escape cancels")
+                    .clicked()
+                {
+                    self.recording = Some(action);
+                }
+                if ui.small_button("Clear").clicked() {
+                    self.bindings.insert(action, None);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(conflict) = self.conflict() {
+            let shortcut = ui.ctx().format_shortcut(&conflict);
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!("{shortcut} is used more than once"),
+            );
+        }
+        if ui.button("Reset to defaults").clicked() {
+            self.bindings.clear();
+            self.recording = None;
+        }
+    }
+    /// A shortcut bound to several actions.
+    fn conflict(&self) -> Option<KeyboardShortcut> {
+        let shortcuts: Vec<_> = Action::ALL
+            .into_iter()
+            .filter_map(|action| self.shortcut(action))
+            .collect();
+        shortcuts
+            .iter()
+            .enumerate()
+            .find(|(i, shortcut)| shortcuts[..*i].contains(shortcut))
+            .map(|(_, shortcut)| *shortcut)
+    }
+}
+
+fn modifier_count(shortcut: &KeyboardShortcut) -> u32 {
+    let Modifiers {
+        alt,
+        ctrl,
+        shift,
+        mac_cmd,
+        command,
+    } = shortcut.modifiers;
+    [alt, ctrl || command || mac_cmd, shift]
+        .into_iter()
+        .filter(|&down| down)
+        .count() as u32
+}
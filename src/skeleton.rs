@@ -0,0 +1,104 @@
+use crate::vktf::GltfRenderInfo;
+use nalgebra_glm as glm;
+use std::collections::HashSet;
+
+const BONE_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 170, 40);
+const JOINT_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 230, 120);
+/// Half the size of a joint marker in points.
+const JOINT_SIZE: f32 = 4.0;
+/// Distance in points from the pointer at which a joint shows its name.
+const HOVER_DISTANCE: f32 = 8.0;
+
+/// Draws the joints of every skin over the viewport at `rect`, each joint connected to the
+/// closest parent in the same skin. The name of the joint under `pointer` is shown next to it.
+pub fn paint_skeletons(
+    painter: &egui::Painter,
+    rect: egui::Rect,
+    pointer: Option<egui::Pos2>,
+    models: &[GltfRenderInfo],
+    view_proj: &glm::Mat4,
+) {
+    let project = |position: glm::Vec3| {
+        let clip = view_proj * position.push(1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.xy() / clip.w;
+        Some(rect.min + egui::vec2((ndc.x + 1.0) * 0.5, (ndc.y + 1.0) * 0.5) * rect.size())
+    };
+
+    let mut hovered: Option<(f32, egui::Pos2, String)> = None;
+    for info in models {
+        let document = &info.vktf.document;
+        if document.skins().len() == 0 {
+            continue;
+        }
+        let mut parents = vec![None; info.scene.nodes.len()];
+        for (index, node) in info.scene.nodes.iter().enumerate() {
+            for child in &node.children {
+                parents[*child] = Some(index);
+            }
+        }
+        let root = info.transform().matrix();
+        let screen = |node: usize| {
+            let world = root * info.scene.nodes[node].world;
+            project(world.column(3).xyz())
+        };
+
+        for skin in document.skins() {
+            let joints: HashSet<usize> = skin.joints().map(|joint| joint.index()).collect();
+            for joint in skin.joints() {
+                let Some(position) = screen(joint.index()) else {
+                    continue;
+                };
+                // joints can hang from nodes that aren't part of the skin
+                let mut parent = parents[joint.index()];
+                while let Some(node) = parent.filter(|node| !joints.contains(node)) {
+                    parent = parents[node];
+                }
+                if let Some(parent) = parent.and_then(screen) {
+                    painter.line_segment([parent, position], egui::Stroke::new(1.5, BONE_COLOR));
+                }
+                painter.add(egui::Shape::convex_polygon(
+                    vec![
+                        position + egui::vec2(0.0, -JOINT_SIZE),
+                        position + egui::vec2(JOINT_SIZE, 0.0),
+                        position + egui::vec2(0.0, JOINT_SIZE),
+                        position + egui::vec2(-JOINT_SIZE, 0.0),
+                    ],
+                    JOINT_COLOR,
+                    egui::Stroke::new(1.0, egui::Color32::BLACK),
+                ));
+
+                let Some(distance) = pointer.map(|pointer| pointer.distance(position)) else {
+                    continue;
+                };
+                if distance < HOVER_DISTANCE && hovered.as_ref().is_none_or(|(d, ..)| distance < *d)
+                {
+                    let name = joint
+                        .name()
+                        .map_or_else(|| format!("Node {}", joint.index()), str::to_owned);
+                    let skin = skin
+                        .name()
+                        .map_or_else(|| format!("skin {}", skin.index()), str::to_owned);
+                    hovered = Some((distance, position, format!("{name} ({skin})")));
+                }
+            }
+        }
+    }
+
+    if let Some((_, position, label)) = hovered {
+        let galley = painter.layout_no_wrap(
+            label,
+            egui::FontId::proportional(13.0),
+            egui::Color32::WHITE,
+        );
+        let text = position + egui::vec2(JOINT_SIZE * 2.0, -galley.size().y * 0.5);
+        painter.rect_filled(
+            egui::Rect::from_min_size(text, galley.size()).expand(3.0),
+            3.0,
+            egui::Color32::from_black_alpha(200),
+        );
+        painter.galley(text, galley, egui::Color32::WHITE);
+    }
+}
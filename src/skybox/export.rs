@@ -0,0 +1,247 @@
+use crate::Allocators;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use vulkano::{
+    DeviceSize, Validated, VulkanError,
+    buffer::{Buffer, BufferCreateInfo, BufferUsage},
+    command_buffer::{
+        AutoCommandBufferBuilder, BufferImageCopy, CommandBufferUsage, CopyImageToBufferInfo,
+        PrimaryCommandBufferAbstract,
+    },
+    device::Queue,
+    format::Format,
+    image::{Image, ImageAspects, ImageSubresourceLayers},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    sync::GpuFuture,
+};
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+/// `VK_FORMAT_R16G16B16A16_SFLOAT`, the only format the cubemaps are made in.
+const VK_FORMAT_RGBA16F: u32 = 97;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportEnvironmentError {
+    #[error("cubemaps in {0:?} can't be exported")]
+    UnsupportedFormat(Format),
+    #[error(transparent)]
+    Vulkan(#[from] Validated<VulkanError>),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Saves the environment, irradiance and prefiltered cubemaps with all their mips as KTX2 files
+/// named after `path`, returns the written files.
+pub fn export_environment(
+    queue: Arc<Queue>,
+    allocators: &Allocators,
+    (cube, conv, filt): (&Arc<Image>, &Arc<Image>, &Arc<Image>),
+    path: &Path,
+) -> Result<Vec<PathBuf>, ExportEnvironmentError> {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "environment".to_owned());
+    let files = [
+        (cube, format!("{stem}.ktx2")),
+        (conv, format!("{stem}_irradiance.ktx2")),
+        (filt, format!("{stem}_prefiltered.ktx2")),
+    ];
+    let mut written = vec![];
+    for (image, name) in files {
+        let levels = read_back(queue.clone(), allocators, image)?;
+        let file = path.with_file_name(name);
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&file)?);
+        write_ktx2(&mut writer, image, &levels)?;
+        writer.flush()?;
+        written.push(file);
+    }
+    Ok(written)
+}
+
+/// The texels of every mip level, faces in order.
+fn read_back(
+    queue: Arc<Queue>,
+    allocators: &Allocators,
+    image: &Arc<Image>,
+) -> Result<Vec<Vec<u8>>, ExportEnvironmentError> {
+    if image.format() != Format::R16G16B16A16_SFLOAT {
+        return Err(ExportEnvironmentError::UnsupportedFormat(image.format()));
+    }
+    let texel = image.format().block_size();
+    let [size, _, _] = image.extent();
+    let sizes: Vec<DeviceSize> = (0..image.mip_levels())
+        .map(|mip| {
+            let extent = (size >> mip).max(1) as DeviceSize;
+            extent * extent * image.array_layers() as DeviceSize * texel
+        })
+        .collect();
+
+    let readback = Buffer::new_slice::<u8>(
+        allocators.mem.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        sizes.iter().sum(),
+    )
+    .unwrap();
+
+    let mut offset = 0;
+    let regions = sizes
+        .iter()
+        .enumerate()
+        .map(|(mip, bytes)| {
+            let extent = (size >> mip).max(1);
+            let region = BufferImageCopy {
+                buffer_offset: offset,
+                image_subresource: ImageSubresourceLayers {
+                    aspects: ImageAspects::COLOR,
+                    mip_level: mip as u32,
+                    array_layers: 0..image.array_layers(),
+                },
+                image_extent: [extent, extent, 1],
+                ..Default::default()
+            };
+            offset += bytes;
+            region
+        })
+        .collect();
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        allocators.cmd.clone(),
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )?;
+    builder
+        .copy_image_to_buffer(CopyImageToBufferInfo {
+            regions,
+            ..CopyImageToBufferInfo::image_buffer(image.clone(), readback.clone())
+        })
+        .unwrap();
+    builder
+        .build()?
+        .execute(queue)
+        .map_err(Validated::ValidationError)?
+        .then_signal_fence_and_flush()?
+        .wait(None)?;
+
+    let data = readback.read().unwrap();
+    let mut start = 0;
+    Ok(sizes
+        .iter()
+        .map(|bytes| {
+            let level = data[start as usize..(start + bytes) as usize].to_vec();
+            start += bytes;
+            level
+        })
+        .collect())
+}
+
+/// Writes an uncompressed RGBA16F cubemap, `levels` starts with the full size.
+fn write_ktx2(writer: &mut impl Write, image: &Image, levels: &[Vec<u8>]) -> std::io::Result<()> {
+    let [size, _, _] = image.extent();
+    let dfd = data_format_descriptor();
+    let kvd = key_value("KTXwriter", "gltf_viewer");
+
+    let index_end = 80 + 24 * levels.len();
+    let dfd_offset = index_end;
+    let kvd_offset = dfd_offset + dfd.len();
+    // mip levels are aligned to the texel size and stored smallest first
+    let mut offset = (kvd_offset + kvd.len()).next_multiple_of(8);
+    let data_start = offset;
+    let mut level_offsets = vec![0; levels.len()];
+    for (mip, level) in levels.iter().enumerate().rev() {
+        level_offsets[mip] = offset;
+        offset = (offset + level.len()).next_multiple_of(8);
+    }
+
+    let mut header = vec![];
+    header.extend_from_slice(&KTX2_IDENTIFIER);
+    for value in [
+        VK_FORMAT_RGBA16F,
+        2, // type size
+        size,
+        size,
+        0, // depth
+        0, // not an array
+        6, // faces
+        levels.len() as u32,
+        0, // no supercompression
+    ] {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [dfd_offset, dfd.len(), kvd_offset, kvd.len()] {
+        header.extend_from_slice(&(value as u32).to_le_bytes());
+    }
+    // no supercompression global data
+    header.extend_from_slice(&[0; 16]);
+    for (level, offset) in levels.iter().zip(&level_offsets) {
+        for value in [*offset, level.len(), level.len()] {
+            header.extend_from_slice(&(value as u64).to_le_bytes());
+        }
+    }
+    header.extend_from_slice(&dfd);
+    header.extend_from_slice(&kvd);
+    header.resize(data_start, 0);
+    writer.write_all(&header)?;
+
+    let mut written = data_start;
+    for (mip, level) in levels.iter().enumerate().rev() {
+        writer.write_all(&vec![0; level_offsets[mip] - written])?;
+        writer.write_all(level)?;
+        written = level_offsets[mip] + level.len();
+    }
+    Ok(())
+}
+
+/// The basic data format descriptor of linear RGBA16F.
+fn data_format_descriptor() -> Vec<u8> {
+    const FLOAT_SIGNED: u8 = 0x80 | 0x40;
+    let channels = [0u8, 1, 2, 15];
+    let block_size = 24 + 16 * channels.len() as u16;
+
+    let mut dfd = vec![];
+    dfd.extend_from_slice(&(4 + block_size as u32).to_le_bytes());
+    // Khronos vendor and basic descriptor type
+    dfd.extend_from_slice(&0u32.to_le_bytes());
+    dfd.extend_from_slice(&2u16.to_le_bytes());
+    dfd.extend_from_slice(&block_size.to_le_bytes());
+    // RGBSDA colour model, BT.709 primaries, linear transfer, straight alpha
+    dfd.extend_from_slice(&[1, 1, 1, 0]);
+    // one texel per block
+    dfd.extend_from_slice(&[0; 4]);
+    // bytes per plane
+    dfd.extend_from_slice(&[8, 0, 0, 0, 0, 0, 0, 0]);
+    for (i, channel) in channels.into_iter().enumerate() {
+        dfd.extend_from_slice(&(16 * i as u16).to_le_bytes());
+        dfd.push(15); // bit length - 1
+        dfd.push(channel | FLOAT_SIGNED);
+        dfd.extend_from_slice(&[0; 4]);
+        dfd.extend_from_slice(&(-1.0f32).to_bits().to_le_bytes());
+        dfd.extend_from_slice(&1.0f32.to_bits().to_le_bytes());
+    }
+    dfd
+}
+
+/// A key/value entry padded to four bytes.
+fn key_value(key: &str, value: &str) -> Vec<u8> {
+    let length = key.len() + 1 + value.len() + 1;
+    let mut kvd = vec![];
+    kvd.extend_from_slice(&(length as u32).to_le_bytes());
+    kvd.extend_from_slice(key.as_bytes());
+    kvd.push(0);
+    kvd.extend_from_slice(value.as_bytes());
+    kvd.push(0);
+    kvd.resize(kvd.len().next_multiple_of(4), 0);
+    kvd
+}
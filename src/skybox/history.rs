@@ -0,0 +1,269 @@
+use super::{SkyboxSource, quality::IblQuality};
+use crate::{Allocators, memory::MemoryCategory};
+use egui_winit_vulkano::Gui;
+use std::sync::Arc;
+use vulkano::{
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    format::Format,
+    image::{
+        Image, ImageCreateInfo, ImageType, ImageUsage,
+        sampler::{Sampler, SamplerCreateInfo},
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+    },
+    memory::allocator::AllocationCreateInfo,
+    pipeline::{
+        ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo, compute::ComputePipelineCreateInfo,
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+    },
+    sync::Sharing,
+};
+
+/// Environments kept at most, each holds its cubemap and baked lighting in memory.
+const MAX_ENTRIES: usize = 6;
+const THUMBNAIL_SIZE: [u32; 2] = [128, 64];
+
+/// Renders an equirectangular preview of an environment cubemap.
+#[derive(Clone)]
+pub struct ThumbnailPipeline {
+    pipeline: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+}
+impl ThumbnailPipeline {
+    pub fn new(allocators: &Allocators) -> Self {
+        let device = allocators.mem.device().clone();
+        let cs = cs::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+        let stage = PipelineShaderStageCreateInfo::new(cs);
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+        let pipeline = ComputePipeline::new(
+            device.clone(),
+            None,
+            ComputePipelineCreateInfo::stage_layout(stage, layout),
+        )
+        .unwrap();
+        let sampler = Sampler::new(device, SamplerCreateInfo::simple_repeat_linear()).unwrap();
+        Self { pipeline, sampler }
+    }
+
+    /// Records the preview of `cube`, readable by every queue family in `queue_families`.
+    pub fn render<L>(
+        &self,
+        allocators: &Allocators,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        cube: &Arc<Image>,
+        queue_families: &[u32],
+    ) -> Arc<ImageView> {
+        let image = Image::new(
+            allocators.memory.allocator(MemoryCategory::Environment),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: Format::R32G32B32A32_SFLOAT,
+                extent: [THUMBNAIL_SIZE[0], THUMBNAIL_SIZE[1], 1],
+                usage: ImageUsage::STORAGE | ImageUsage::SAMPLED,
+                sharing: if queue_families.len() > 1 {
+                    Sharing::Concurrent(queue_families.iter().copied().collect())
+                } else {
+                    Sharing::Exclusive
+                },
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+        let thumbnail = ImageView::new_default(image).unwrap();
+        let cube_view = ImageView::new(
+            cube.clone(),
+            ImageViewCreateInfo {
+                view_type: ImageViewType::Cube,
+                ..ImageViewCreateInfo::from_image(cube)
+            },
+        )
+        .unwrap();
+        let set = DescriptorSet::new(
+            allocators.set.clone(),
+            self.pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view_sampler(0, cube_view, self.sampler.clone()),
+                WriteDescriptorSet::image_view(1, thumbnail.clone()),
+            ],
+            [],
+        )
+        .unwrap();
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .unwrap();
+        let [width, height] = THUMBNAIL_SIZE;
+        unsafe { builder.dispatch([width.div_ceil(8), height.div_ceil(8), 1]) }.unwrap();
+        thumbnail
+    }
+}
+
+/// A baked environment that can be shown again without loading it.
+pub struct HistoryEntry {
+    pub source: SkyboxSource,
+    pub quality: IblQuality,
+    /// The cube, irradiance and prefiltered maps.
+    pub maps: (Arc<Image>, Arc<Image>, Arc<Image>),
+    pub thumbnail: Arc<ImageView>,
+    texture: Option<egui::TextureId>,
+}
+impl HistoryEntry {
+    pub fn name(&self) -> String {
+        match &self.source {
+            SkyboxSource::Image(path) => path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().into(),
+            ),
+            SkyboxSource::Sky(preset) => preset.name().to_owned(),
+        }
+    }
+}
+
+/// The environments loaded so far, newest first.
+#[derive(Default)]
+pub struct SkyboxHistory {
+    pub entries: Vec<HistoryEntry>,
+    /// Thumbnails of dropped entries, unregistered with the next [`register`](Self::register).
+    removed: Vec<egui::TextureId>,
+}
+impl SkyboxHistory {
+    /// Adds a loaded environment, replacing an older bake of the same source.
+    pub fn push(
+        &mut self,
+        source: SkyboxSource,
+        quality: IblQuality,
+        maps: (Arc<Image>, Arc<Image>, Arc<Image>),
+        thumbnail: Arc<ImageView>,
+    ) {
+        if let Some(i) = self.entries.iter().position(|entry| entry.source == source) {
+            self.remove(i);
+        }
+        self.entries.insert(
+            0,
+            HistoryEntry {
+                source,
+                quality,
+                maps,
+                thumbnail,
+                texture: None,
+            },
+        );
+        while self.entries.len() > MAX_ENTRIES {
+            self.remove(self.entries.len() - 1);
+        }
+    }
+    pub fn remove(&mut self, index: usize) {
+        let entry = self.entries.remove(index);
+        self.removed.extend(entry.texture);
+    }
+
+    /// Makes new thumbnails available to egui, call before [`ui`](Self::ui).
+    pub fn register(&mut self, gui: &mut Gui) {
+        for texture in self.removed.drain(..) {
+            gui.unregister_user_image(texture);
+        }
+        for entry in &mut self.entries {
+            if entry.texture.is_none() {
+                entry.texture = Some(gui.register_user_image_view(
+                    entry.thumbnail.clone(),
+                    SamplerCreateInfo::simple_repeat_linear(),
+                ));
+            }
+        }
+    }
+    /// Forgets the thumbnails of a dropped [`Gui`], they are registered again with the next one.
+    pub fn forget_textures(&mut self) {
+        self.removed.clear();
+        for entry in &mut self.entries {
+            entry.texture = None;
+        }
+    }
+
+    /// A horizontal strip of thumbnails, returns the entry that was clicked.
+    /// `current` is highlighted.
+    pub fn ui(&mut self, ui: &mut egui::Ui, current: Option<&SkyboxSource>) -> Option<usize> {
+        let mut clicked = None;
+        let mut remove = None;
+        let size = egui::vec2(THUMBNAIL_SIZE[0] as f32, THUMBNAIL_SIZE[1] as f32);
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                for (i, entry) in self.entries.iter().enumerate() {
+                    let Some(texture) = entry.texture else {
+                        continue;
+                    };
+                    let selected = current == Some(&entry.source);
+                    let image = egui::Image::new(egui::load::SizedTexture::new(texture, size));
+                    let response = ui
+                        .add(egui::ImageButton::new(image).selected(selected))
+                        .on_hover_text(format!("{} ({})", entry.name(), entry.quality.name()));
+                    if response.clicked() {
+                        clicked = Some(i);
+                    }
+                    response.context_menu(|ui| {
+                        if ui.button("Remove").clicked() {
+                            remove = Some(i);
+                            ui.close_menu();
+                        }
+                    });
+                }
+            });
+        });
+        if let Some(i) = remove {
+            self.remove(i);
+            clicked = clicked.filter(|clicked| *clicked != i);
+        }
+        clicked
+    }
+}
+
+mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(set = 0, binding = 0) uniform samplerCube cube;
+layout(set = 0, binding = 1, rgba32f) uniform writeonly image2D thumbnail;
+
+const float PI = 3.14159265358979323846264338327950288;
+
+void main() {
+    ivec2 size = imageSize(thumbnail);
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(pixel, size))) {
+        return;
+    }
+    // the inverse of the equirectangular projection the images are loaded with
+    vec2 uv = (vec2(pixel) + 0.5) / vec2(size);
+    float phi = uv.x * 2.0 * PI - PI;
+    float theta = PI / 2.0 - uv.y * PI;
+    vec3 dir = vec3(cos(theta) * cos(phi), sin(theta), cos(theta) * sin(phi));
+
+    // a mip close to the size of the preview, a quarter of it covers one face
+    float lod = log2(float(textureSize(cube, 0).x) * 4.0 / float(size.x));
+    vec3 color = textureLod(cube, dir, max(lod, 0.0)).rgb;
+    // Reinhard, so bright skies don't clip
+    imageStore(thumbnail, pixel, vec4(color / (1.0 + color), 1.0));
+}
+        "#
+    }
+}
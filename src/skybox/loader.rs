@@ -2,41 +2,52 @@ use crate::{
     Allocators,
     cubemap::{
         CubeMesh, CubemapPipelineBuilder, CubemapVertexShader,
-        filt::filter_pipeline_layout,
+        compute::CubemapComputePipeline,
+        conv::ConvolutionPush,
+        cubemap_pipeline_layout,
+        filt::FilterPush,
         renderer::{CubemapRenderPass, CubemapRenderPipeline, create_cubemap_image},
     },
+    frameinfo::Depth,
+    memory::MemoryCategory,
+    progress::ProgressSender,
     set_layouts::SetLayouts,
+    skybox::{history::ThumbnailPipeline, quality::IblQuality, sky::SkyPreset},
 };
 use image::{EncodableLayout, ImageError};
 use std::{path::Path, sync::Arc};
 use vulkano::{
-    DeviceSize,
+    DeviceSize, Validated, VulkanError,
     buffer::{Buffer, BufferCreateInfo, BufferUsage},
     command_buffer::{
-        AutoCommandBufferBuilder, BlitImageInfo, CopyBufferToImageInfo, ImageBlit,
-        PrimaryAutoCommandBuffer,
+        AutoCommandBufferBuilder, BlitImageInfo, CommandBufferUsage, CopyBufferToImageInfo,
+        ImageBlit, PrimaryAutoCommandBuffer,
     },
     descriptor_set::{
         DescriptorSet, WriteDescriptorSet, allocator::StandardDescriptorSetAllocator,
         layout::DescriptorSetLayout,
     },
-    device::DeviceOwned,
+    device::{DeviceOwned, Queue},
     format::Format,
     image::{
         Image, ImageCreateInfo, ImageSubresourceLayers, ImageType, ImageUsage,
         sampler::{Filter, Sampler, SamplerCreateInfo},
         view::{ImageView, ImageViewCreateInfo, ImageViewType},
     },
-    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    memory::allocator::{AllocationCreateInfo, MemoryAllocator, MemoryTypeFilter},
     pipeline::{Pipeline, PipelineLayout},
 };
 
 #[derive(Clone)]
 pub struct SkyboxLoader {
     pub equirectangular_renderer: CubemapRenderPipeline,
-    pub convolute_renderer: CubemapRenderPipeline,
-    pub filter_renderer: CubemapRenderPipeline,
+    pub convolute_pipeline: CubemapComputePipeline,
+    pub filter_pipeline: CubemapComputePipeline,
+    pub sky_renderer: CubemapRenderPipeline,
+    pub thumbnail: ThumbnailPipeline,
     pub allocators: Allocators,
+    /// Distinct queue families that render and bake the environment.
+    pub queue_families: Vec<u32>,
 }
 impl SkyboxLoader {
     pub fn new(
@@ -45,47 +56,78 @@ impl SkyboxLoader {
         vertex: &CubemapVertexShader,
         set_layouts: &SetLayouts,
         cube: &Arc<CubeMesh>,
+        queue_families: Vec<u32>,
     ) -> Self {
+        let device = allocators.mem.device();
         let cube_render_pass = Arc::new(CubemapRenderPass::new(
             allocators.mem.clone(),
             allocators.set.clone(),
             set_layouts.camera.clone(),
         ));
         let equirectangular_renderer = CubemapRenderPipeline {
-            pipeline: CubemapPipelineBuilder::new_equi(vertex.clone())
-                .build(cubemap_layout.clone(), cube_render_pass.subpass.clone()),
+            pipeline: CubemapPipelineBuilder::new_equi(vertex.clone()).build(
+                cubemap_layout.clone(),
+                cube_render_pass.subpass.clone(),
+                Depth::default(),
+            ),
             renderer: cube_render_pass.clone(),
             cube: cube.clone(),
         };
-        let convolute_renderer = CubemapRenderPipeline {
-            pipeline: CubemapPipelineBuilder::new_conv(vertex.clone())
-                .build(cubemap_layout.clone(), cube_render_pass.subpass.clone()),
-            renderer: cube_render_pass.clone(),
-            cube: cube.clone(),
-        };
-        let filter_pipeline =
-            filter_pipeline_layout(set_layouts.camera.clone(), set_layouts.texture.clone());
-        let filter_renderer = CubemapRenderPipeline {
-            pipeline: CubemapPipelineBuilder::new_filt(vertex.clone())
-                .build(filter_pipeline, cube_render_pass.subpass.clone()),
+        let convolute_pipeline = CubemapComputePipeline::new_conv(device.clone());
+        let filter_pipeline = CubemapComputePipeline::new_filt(device.clone());
+        // the sky uniform is a single uniform buffer like the camera
+        let sky_pipeline =
+            cubemap_pipeline_layout(set_layouts.camera.clone(), set_layouts.camera.clone());
+        let sky_renderer = CubemapRenderPipeline {
+            pipeline: CubemapPipelineBuilder::new_sky(vertex.clone()).build(
+                sky_pipeline,
+                cube_render_pass.subpass.clone(),
+                Depth::default(),
+            ),
             renderer: cube_render_pass,
             cube: cube.clone(),
         };
         Self {
             equirectangular_renderer,
-            convolute_renderer,
-            filter_renderer,
+            convolute_pipeline,
+            filter_pipeline,
+            sky_renderer,
+            thumbnail: ThumbnailPipeline::new(&allocators),
             allocators,
+            queue_families,
         }
     }
 
+    fn environment_allocator(&self) -> Arc<dyn MemoryAllocator> {
+        self.allocators
+            .memory
+            .allocator(MemoryCategory::Environment)
+    }
+
+    pub fn builder(
+        &self,
+        queue: &Queue,
+    ) -> Result<AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, Validated<VulkanError>> {
+        AutoCommandBufferBuilder::primary(
+            self.allocators.cmd.clone(),
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+    }
+
+    /// Records rendering the environment cubemap, it still needs to be [baked](Self::bake).
     pub fn load(
         &self,
         path: impl AsRef<Path>,
         builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
-    ) -> Result<(Arc<Image>, Arc<Image>, Arc<Image>), LoadSkyboxError> {
+        progress: &ProgressSender,
+    ) -> Result<Arc<Image>, LoadSkyboxError> {
         // load equirectangular texture
-        let equi = load_skybox(self.allocators.mem.clone(), path, builder)?;
+        progress.report("Decoding image", 0.0);
+        let equi = load_skybox(self.environment_allocator(), path, builder)?;
+        if progress.cancelled() {
+            return Err(LoadSkyboxError::Cancelled);
+        }
         let equi_view = ImageView::new_default(equi.clone()).unwrap();
         let equi_set = DescriptorSet::new(
             self.allocators.set.clone(),
@@ -108,39 +150,119 @@ impl SkyboxLoader {
         .unwrap();
 
         // render equirectangular texture to cubemap
+        progress.report("Rendering cubemap", 0.2);
         let mips = 5;
-        let cube = create_cubemap_image(self.allocators.mem.clone(), equi.extent()[0] / 4, mips);
+        let cube = create_cubemap_image(
+            self.environment_allocator(),
+            equi.extent()[0] / 4,
+            mips,
+            &self.queue_families,
+        );
         self.equirectangular_renderer
             .render(builder, &equi_set, &cube, 0);
         gen_mipmaps(builder, cube.clone(), mips);
 
+        Ok(cube)
+    }
+
+    /// Renders a procedural sky instead of loading an image.
+    pub fn load_sky(
+        &self,
+        preset: SkyPreset,
+        quality: IblQuality,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        progress: &ProgressSender,
+    ) -> Result<Arc<Image>, LoadSkyboxError> {
+        progress.report("Rendering sky", 0.0);
+        let uniform = Buffer::from_data(
+            self.allocators.mem.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::UNIFORM_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            preset.uniform(),
+        )
+        .unwrap();
+        let sky_set = DescriptorSet::new(
+            self.allocators.set.clone(),
+            self.sky_renderer.pipeline.layout().set_layouts()[1].clone(),
+            [WriteDescriptorSet::buffer(0, uniform)],
+            [],
+        )
+        .unwrap();
+
+        let mips = 5;
+        let size = quality.settings().prefilter_size;
+        let cube = create_cubemap_image(
+            self.environment_allocator(),
+            size,
+            mips,
+            &self.queue_families,
+        );
+        self.sky_renderer.render(builder, &sky_set, &cube, 0);
+        gen_mipmaps(builder, cube.clone(), mips);
+
+        Ok(cube)
+    }
+
+    /// Records convoluting and prefiltering `cube` for image based lighting.
+    /// Only dispatches compute work so it can run on a compute queue.
+    pub fn bake<L>(
+        &self,
+        cube: &Arc<Image>,
+        quality: IblQuality,
+        builder: &mut AutoCommandBufferBuilder<L>,
+    ) -> (Arc<Image>, Arc<Image>) {
+        let settings = quality.settings();
+        let resolution = cube.extent()[0] as f32;
+
         // convolute cubemap
-        let cube_set = cube_set(
+        let conv = create_cubemap_image(
+            self.environment_allocator(),
+            settings.irradiance_size,
+            1,
+            &self.queue_families,
+        );
+        self.convolute_pipeline.dispatch(
+            builder,
             self.allocators.set.clone(),
-            self.convolute_renderer.pipeline.layout().set_layouts()[1].clone(),
-            cube.clone(),
+            cube,
+            &conv,
+            0,
+            ConvolutionPush {
+                delta: settings.irradiance_delta,
+            },
         );
-        let conv = create_cubemap_image(self.allocators.mem.clone(), 32, 1);
-        self.convolute_renderer.render(builder, &cube_set, &conv, 0);
 
-        // don't change mips since shader expects it to be 5
-        let mips = 5;
-        // don't change size since shader expects texture to be 512x512
-        let filt = create_cubemap_image(self.allocators.mem.clone(), 512, mips);
+        let mips = settings.prefilter_mips;
+        let filt = create_cubemap_image(
+            self.environment_allocator(),
+            settings.prefilter_size,
+            mips,
+            &self.queue_families,
+        );
         for mip in 0..mips {
             let roughness = mip as f32 / (mips - 1) as f32;
-            builder
-                .push_constants(
-                    self.filter_renderer.pipeline.layout().clone(),
-                    0,
-                    [roughness],
-                )
-                .unwrap();
-            self.filter_renderer.render(builder, &cube_set, &filt, mip);
+            self.filter_pipeline.dispatch(
+                builder,
+                self.allocators.set.clone(),
+                cube,
+                &filt,
+                mip,
+                FilterPush {
+                    roughness,
+                    samples: settings.prefilter_samples,
+                    resolution,
+                },
+            );
         }
 
-        Ok((cube, conv, filt))
-        // Ok((filt.clone(), conv, filt))
+        (conv, filt)
     }
 }
 
@@ -150,9 +272,13 @@ pub enum LoadSkyboxError {
     Image(#[from] ImageError),
     #[error("equirectangular image must be 2:1")]
     WrongAspect,
+    #[error("loading was cancelled")]
+    Cancelled,
+    #[error(transparent)]
+    Vulkan(#[from] Validated<VulkanError>),
 }
 fn load_skybox<L>(
-    allocator: Arc<StandardMemoryAllocator>,
+    allocator: Arc<dyn MemoryAllocator>,
     path: impl AsRef<Path>,
     builder: &mut AutoCommandBufferBuilder<L>,
 ) -> Result<Arc<Image>, LoadSkyboxError> {
@@ -243,7 +369,7 @@ pub fn cube_set(
 pub fn gen_mipmaps<L>(builder: &mut AutoCommandBufferBuilder<L>, image: Arc<Image>, mips: u32) {
     let w = image.extent()[0];
     let h = image.extent()[1];
-    for layer in 0..6u32 {
+    for layer in 0..image.array_layers() {
         for mip in 1..mips {
             builder
                 .blit_image(BlitImageInfo {
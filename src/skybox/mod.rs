@@ -1,27 +1,69 @@
 use crate::{
     Allocators,
-    cubemap::{CubeMesh, CubemapPipelineBuilder, CubemapVertexShader, cubemap_pipeline_layout},
+    cubemap::{
+        CubeMesh, CubemapPipelineBuilder, CubemapVertexShader, cube::skybox_pipeline_layout,
+        cubemap_pipeline_layout,
+    },
+    frameinfo::Depth,
+    progress::{ProgressReceiver, panic_message, progress},
     set_layouts::SetLayouts,
 };
-use loader::{SkyboxLoader, cube_set};
-use renderer::SkyboxRenderer;
+use history::SkyboxHistory;
+use loader::{LoadSkyboxError, SkyboxLoader, cube_set};
+use probe::ReflectionProbe;
+use quality::IblQuality;
+use renderer::{Background, EnvironmentPush, SkyboxRenderer};
+use serde::{Deserialize, Serialize};
+use sky::SkyPreset;
 use std::{path::PathBuf, sync::Arc, thread::JoinHandle};
 use vulkano::{
-    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryCommandBufferAbstract},
+    Validated, VulkanError,
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
+    },
     device::{DeviceOwned, Queue},
-    image::Image,
+    image::{Image, view::ImageView},
     pipeline::Pipeline,
     render_pass::Subpass,
     sync::GpuFuture,
 };
 
+pub mod export;
+pub mod history;
 pub mod loader;
+pub mod probe;
+pub mod quality;
 pub mod renderer;
+pub mod sky;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SkyboxSource {
+    Image(PathBuf),
+    Sky(SkyPreset),
+}
+
+/// The outcome of a load.
+pub struct LoadedEnvironment {
+    /// The cube, irradiance and prefiltered maps.
+    pub maps: (Arc<Image>, Arc<Image>, Arc<Image>),
+    pub thumbnail: Arc<ImageView>,
+}
 
 pub struct Skybox {
     pub renderer: SkyboxRenderer,
     pub loader: SkyboxLoader,
-    pub job: Option<JoinHandle<(Arc<Image>, Arc<Image>, Arc<Image>)>>,
+    pub job: Option<JoinHandle<Result<LoadedEnvironment, LoadSkyboxError>>>,
+    pub progress: Option<ProgressReceiver>,
+    pub quality: IblQuality,
+    /// What the current or loading environment was made from.
+    pub source: Option<SkyboxSource>,
+    /// The cube, irradiance and prefiltered maps of the current environment.
+    pub maps: Option<(Arc<Image>, Arc<Image>, Arc<Image>)>,
+    /// Bakes the lighting, may be the graphics queue if there is no separate one.
+    pub compute_queue: Arc<Queue>,
+    pub probe: ReflectionProbe,
+    /// Loaded environments that can be switched to instantly.
+    pub history: SkyboxHistory,
 }
 impl Skybox {
     pub fn new<L>(
@@ -29,6 +71,9 @@ impl Skybox {
         builder: &mut AutoCommandBufferBuilder<L>,
         set_layouts: &SetLayouts,
         subpass: Subpass,
+        depth: Depth,
+        queue: &Queue,
+        compute_queue: Arc<Queue>,
     ) -> Self {
         let device = allocators.mem.device();
 
@@ -38,72 +83,183 @@ impl Skybox {
             cubemap_pipeline_layout(set_layouts.camera.clone(), set_layouts.texture.clone());
         let vertex = CubemapVertexShader::new(device.clone());
 
-        let skybox_pipeline = CubemapPipelineBuilder::new_cube(vertex.clone())
-            .build(cubemap_pipeline_layout.clone(), subpass);
+        let skybox_pipeline = CubemapPipelineBuilder::new_cube(vertex.clone()).build(
+            skybox_pipeline_layout(set_layouts.camera.clone(), set_layouts.texture.clone()),
+            subpass,
+            depth,
+        );
 
+        let mut queue_families = vec![queue.queue_family_index()];
+        if compute_queue.queue_family_index() != queue.queue_family_index() {
+            queue_families.push(compute_queue.queue_family_index());
+        }
         let loader = SkyboxLoader::new(
             allocators.clone(),
             &cubemap_pipeline_layout,
             &vertex,
             set_layouts,
             &cube,
+            queue_families,
         );
         let renderer = SkyboxRenderer {
             pipeline: skybox_pipeline,
             cube,
             skybox: None,
+            environment: EnvironmentPush::default(),
+            background: Background::default(),
         };
 
         Self {
             renderer,
             loader,
             job: None,
+            progress: None,
+            quality: IblQuality::default(),
+            source: None,
+            maps: None,
+            compute_queue,
+            probe: ReflectionProbe::default(),
+            history: SkyboxHistory::default(),
         }
     }
     pub fn load(&mut self, path: PathBuf, queue: Arc<Queue>) {
+        self.load_source(SkyboxSource::Image(path), queue);
+    }
+    pub fn load_sky(&mut self, preset: SkyPreset, queue: Arc<Queue>) {
+        self.load_source(SkyboxSource::Sky(preset), queue);
+    }
+    /// Rebuilds the skybox pipeline for a new main render pass.
+    pub fn set_subpass(&mut self, subpass: Subpass, depth: Depth) {
+        let pipeline = &self.renderer.pipeline;
+        let vertex = CubemapVertexShader::new(pipeline.device().clone());
+        self.renderer.pipeline = CubemapPipelineBuilder::new_cube(vertex).build(
+            pipeline.layout().clone(),
+            subpass,
+            depth,
+        );
+    }
+    /// Bakes the current environment again, e.g. after the quality was changed.
+    pub fn reload(&mut self, queue: Arc<Queue>) {
+        if let Some(source) = self.source.clone() {
+            self.load_source(source, queue);
+        }
+    }
+    /// Renders the environment cubemap on `queue` and bakes the lighting on the compute queue,
+    /// which lets the frames keep rendering when it is a separate queue.
+    fn load_source(&mut self, source: SkyboxSource, queue: Arc<Queue>) {
         if self.loading() {
             return;
         }
+        self.source = Some(source.clone());
+        let quality = self.quality;
         let loader = self.loader.clone();
+        let compute_queue = self.compute_queue.clone();
+        let (progress_sender, progress_receiver) = progress();
         let job = std::thread::spawn(move || {
-            let mut builder = AutoCommandBufferBuilder::primary(
-                loader.allocators.cmd.clone(),
-                queue.queue_family_index(),
-                CommandBufferUsage::OneTimeSubmit,
-            )
-            .unwrap();
-            let image = loader.load(path, &mut builder).unwrap();
-            let cb = builder.build().unwrap();
+            let mut builder = loader.builder(&queue)?;
+            let cube = match source {
+                SkyboxSource::Image(path) => loader.load(path, &mut builder, &progress_sender)?,
+                SkyboxSource::Sky(preset) => {
+                    loader.load_sky(preset, quality, &mut builder, &progress_sender)?
+                }
+            };
+            if progress_sender.cancelled() {
+                return Err(LoadSkyboxError::Cancelled);
+            }
+            progress_sender.report("Uploading", 0.3);
+            submit(builder, queue)?;
+            if progress_sender.cancelled() {
+                return Err(LoadSkyboxError::Cancelled);
+            }
 
-            cb.execute(queue)
-                .unwrap()
-                .then_signal_fence_and_flush()
-                .unwrap()
-                .wait(None)
-                .unwrap();
+            progress_sender.report("Baking lighting", 0.5);
+            let mut builder = loader.builder(&compute_queue)?;
+            let (conv, filt) = loader.bake(&cube, quality, &mut builder);
+            let thumbnail = loader.thumbnail.render(
+                &loader.allocators,
+                &mut builder,
+                &cube,
+                &loader.queue_families,
+            );
+            submit(builder, compute_queue)?;
 
-            image
+            progress_sender.report("Done", 1.0);
+            Ok(LoadedEnvironment {
+                maps: (cube, conv, filt),
+                thumbnail,
+            })
         });
-        self.job = Some(job)
+        self.job = Some(job);
+        self.progress = Some(progress_receiver);
     }
     pub fn loading(&self) -> bool {
         self.job.is_some()
     }
-    pub fn update(&mut self) -> Option<(Arc<Image>, Arc<Image>)> {
-        if let Some((cube, conv, filt)) = self
-            .job
-            .take_if(|job| job.is_finished())
-            .map(|job| job.join().unwrap())
-        {
-            let cube_set = cube_set(
-                self.loader.allocators.set.clone(),
-                self.renderer.pipeline.layout().set_layouts()[1].clone(),
-                cube,
-            );
-            self.renderer.skybox = Some(cube_set);
-            Some((conv, filt))
-        } else {
-            None
+    /// Returns the new cube, irradiance and prefiltered maps once loaded,
+    /// a failed load is added to `errors`.
+    pub fn update(
+        &mut self,
+        errors: &mut Vec<String>,
+    ) -> Option<(Arc<Image>, Arc<Image>, Arc<Image>)> {
+        if let Some(progress) = &mut self.progress {
+            progress.update();
+        }
+        let job = self.job.take_if(|job| job.is_finished())?;
+        self.progress = None;
+        let result = match job.join() {
+            Ok(result) => result,
+            Err(panic) => {
+                errors.push(format!("Failed to load skybox: {}", panic_message(panic)));
+                return None;
+            }
+        };
+        match result {
+            Err(LoadSkyboxError::Cancelled) => None,
+            Err(err) => {
+                errors.push(format!("Failed to load skybox: {err}"));
+                None
+            }
+            Ok(LoadedEnvironment { maps, thumbnail }) => {
+                if let Some(source) = self.source.clone() {
+                    self.history
+                        .push(source, self.quality, maps.clone(), thumbnail);
+                }
+                self.show(maps.clone());
+                Some(maps)
+            }
         }
     }
+    /// Shows an environment of the history again, returns its maps like a finished load.
+    pub fn select(&mut self, index: usize) -> Option<(Arc<Image>, Arc<Image>, Arc<Image>)> {
+        if self.loading() {
+            return None;
+        }
+        let entry = self.history.entries.get(index)?;
+        self.source = Some(entry.source.clone());
+        self.quality = entry.quality;
+        let maps = entry.maps.clone();
+        self.show(maps.clone());
+        Some(maps)
+    }
+    fn show(&mut self, (cube, conv, filt): (Arc<Image>, Arc<Image>, Arc<Image>)) {
+        let cube_set = cube_set(
+            self.loader.allocators.set.clone(),
+            self.renderer.pipeline.layout().set_layouts()[1].clone(),
+            cube.clone(),
+        );
+        self.renderer.skybox = Some(cube_set);
+        self.maps = Some((cube, conv, filt));
+    }
+}
+
+fn submit(
+    builder: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    queue: Arc<Queue>,
+) -> Result<(), Validated<VulkanError>> {
+    builder
+        .build()?
+        .execute(queue)
+        .map_err(Validated::ValidationError)?
+        .then_signal_fence_and_flush()?
+        .wait(None)
 }
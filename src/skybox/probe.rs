@@ -0,0 +1,235 @@
+use crate::{Allocators, frameinfo::Depth, memory::MemoryCategory, vktf::bounds::Aabb};
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, BlitImageInfo, ImageBlit, RenderPassBeginInfo, SubpassBeginInfo,
+        SubpassEndInfo,
+    },
+    format::ClearValue,
+    image::{
+        Image, ImageAspects, ImageCreateInfo, ImageSubresourceLayers, ImageType, ImageUsage,
+        SampleCount, sampler::Filter, view::ImageView,
+    },
+    memory::allocator::AllocationCreateInfo,
+    pipeline::graphics::viewport::{Scissor, Viewport},
+    render_pass::{AttachmentLoadOp, Framebuffer, FramebufferCreateInfo, RenderPass},
+};
+
+/// Size of the faces of a baked probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProbeResolution {
+    X128,
+    #[default]
+    X256,
+    X512,
+}
+impl ProbeResolution {
+    pub const ALL: [ProbeResolution; 3] = [
+        ProbeResolution::X128,
+        ProbeResolution::X256,
+        ProbeResolution::X512,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProbeResolution::X128 => "128",
+            ProbeResolution::X256 => "256",
+            ProbeResolution::X512 => "512",
+        }
+    }
+    pub fn size(&self) -> u32 {
+        match self {
+            ProbeResolution::X128 => 128,
+            ProbeResolution::X256 => 256,
+            ProbeResolution::X512 => 512,
+        }
+    }
+}
+
+/// The scene seen from one point baked into a cubemap, lights the models instead of the
+/// skybox so interiors reflect their own walls instead of the sky outside.
+#[derive(Debug, Default)]
+pub struct ReflectionProbe {
+    pub position: glm::Vec3,
+    pub resolution: ProbeResolution,
+    /// The environment lighting comes from the last bake instead of the skybox.
+    pub active: bool,
+    /// Baked with the next update.
+    pub requested: bool,
+}
+impl ReflectionProbe {
+    /// The 90 degree projection of the faces, with planes that fit everything in `bounds`.
+    pub fn projection(&self, bounds: &Aabb, reverse_z: bool) -> glm::Mat4 {
+        let far = if bounds.is_empty() {
+            100.0
+        } else {
+            (glm::distance(&self.position, &bounds.center()) + bounds.radius()) * 2.0
+        };
+        let near = far * 1e-4;
+        let (near, far) = if reverse_z { (far, near) } else { (near, far) };
+        glm::perspective_rh_zo(1.0, std::f32::consts::FRAC_PI_2, near, far)
+    }
+
+    /// Returns true if the skybox should light the scene again.
+    pub fn ui(&mut self, ui: &mut egui::Ui, eye: glm::Vec3) -> bool {
+        ui.horizontal(|ui| {
+            for (axis, value) in ["x: ", "y: ", "z: "]
+                .into_iter()
+                .zip(self.position.iter_mut())
+            {
+                ui.add(egui::DragValue::new(value).prefix(axis).speed(0.05));
+            }
+        });
+        egui::ComboBox::from_label("Probe resolution")
+            .selected_text(self.resolution.name())
+            .show_ui(ui, |ui| {
+                for resolution in ProbeResolution::ALL {
+                    ui.selectable_value(&mut self.resolution, resolution, resolution.name());
+                }
+            });
+        let mut restore = false;
+        ui.horizontal(|ui| {
+            if ui
+                .button("Bake probe here")
+                .on_hover_text("Capture the scene from the camera position")
+                .clicked()
+            {
+                self.position = eye;
+                self.requested = true;
+            }
+            if ui.button("Bake").clicked() {
+                self.requested = true;
+            }
+            restore = ui
+                .add_enabled(self.active, egui::Button::new("Use skybox"))
+                .clicked();
+        });
+        restore
+    }
+}
+
+/// One face of a probe, drawn with the main render pass so the scene pipelines can be used.
+pub struct ProbeTarget {
+    framebuffer: Arc<Framebuffer>,
+    /// The single sampled color attachment, the resolve target with MSAA.
+    color: Arc<Image>,
+    size: u32,
+}
+impl ProbeTarget {
+    pub fn new(allocators: &Allocators, render_pass: &Arc<RenderPass>, size: u32) -> Self {
+        let mut color = None;
+        let attachments = render_pass
+            .attachments()
+            .iter()
+            .map(|attachment| {
+                let depth = attachment.format.aspects().intersects(ImageAspects::DEPTH);
+                let resolved = !depth && attachment.samples == SampleCount::Sample1;
+                let usage = if depth {
+                    ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT
+                } else if resolved {
+                    ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC
+                } else {
+                    ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT
+                };
+                let image = Image::new(
+                    allocators.memory.allocator(MemoryCategory::RenderTargets),
+                    ImageCreateInfo {
+                        image_type: ImageType::Dim2d,
+                        format: attachment.format,
+                        extent: [size, size, 1],
+                        samples: attachment.samples,
+                        usage,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo::default(),
+                )
+                .unwrap();
+                if resolved {
+                    color = Some(image.clone());
+                }
+                ImageView::new_default(image).unwrap()
+            })
+            .collect();
+        let framebuffer = Framebuffer::new(
+            render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        Self {
+            framebuffer,
+            color: color.unwrap(),
+            size,
+        }
+    }
+
+    /// Starts drawing a face, cleared to `background`.
+    pub fn begin<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        background: [f32; 4],
+        depth: Depth,
+    ) {
+        let clear_values = self
+            .framebuffer
+            .render_pass()
+            .attachments()
+            .iter()
+            .map(|attachment| {
+                (attachment.load_op == AttachmentLoadOp::Clear).then(|| {
+                    if attachment.format.aspects().intersects(ImageAspects::DEPTH) {
+                        ClearValue::from(depth.clear_value())
+                    } else {
+                        ClearValue::from(background)
+                    }
+                })
+            })
+            .collect();
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values,
+                    ..RenderPassBeginInfo::framebuffer(self.framebuffer.clone())
+                },
+                SubpassBeginInfo::default(),
+            )
+            .unwrap()
+            .set_viewport(
+                0,
+                [Viewport {
+                    extent: [self.size as f32; 2],
+                    ..Default::default()
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .set_scissor(0, [Scissor::default()].into_iter().collect())
+            .unwrap();
+    }
+    /// Ends the face and copies it into layer `face` of `cube`, converting it to its format.
+    pub fn end<L>(&self, builder: &mut AutoCommandBufferBuilder<L>, cube: &Arc<Image>, face: u32) {
+        builder.end_render_pass(SubpassEndInfo::default()).unwrap();
+        builder
+            .blit_image(BlitImageInfo {
+                filter: Filter::Nearest,
+                regions: [ImageBlit {
+                    src_subresource: self.color.subresource_layers(),
+                    src_offsets: [[0, 0, 0], self.color.extent()],
+                    dst_subresource: ImageSubresourceLayers {
+                        mip_level: 0,
+                        array_layers: face..face + 1,
+                        ..cube.subresource_layers()
+                    },
+                    dst_offsets: [[0, 0, 0], [cube.extent()[0], cube.extent()[1], 1]],
+                    ..Default::default()
+                }]
+                .into(),
+                ..BlitImageInfo::images(self.color.clone(), cube.clone())
+            })
+            .unwrap();
+    }
+}
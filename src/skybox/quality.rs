@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+/// How much work goes into baking the image based lighting of a new environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum IblQuality {
+    Low,
+    Medium,
+    #[default]
+    High,
+}
+impl IblQuality {
+    pub const ALL: [IblQuality; 3] = [IblQuality::Low, IblQuality::Medium, IblQuality::High];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IblQuality::Low => "Low",
+            IblQuality::Medium => "Medium",
+            IblQuality::High => "High",
+        }
+    }
+
+    pub fn settings(&self) -> IblSettings {
+        match self {
+            IblQuality::Low => IblSettings {
+                irradiance_size: 16,
+                irradiance_delta: 0.1,
+                prefilter_size: 128,
+                prefilter_mips: 4,
+                prefilter_samples: 64,
+            },
+            IblQuality::Medium => IblSettings {
+                irradiance_size: 32,
+                irradiance_delta: 0.04,
+                prefilter_size: 256,
+                prefilter_mips: 5,
+                prefilter_samples: 256,
+            },
+            IblQuality::High => IblSettings {
+                irradiance_size: 32,
+                irradiance_delta: 0.01,
+                prefilter_size: 512,
+                prefilter_mips: 5,
+                prefilter_samples: 1024,
+            },
+        }
+    }
+
+    /// Returns true if the quality was changed.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let old = *self;
+        egui::ComboBox::from_label("Lighting quality")
+            .selected_text(self.name())
+            .show_ui(ui, |ui| {
+                for quality in Self::ALL {
+                    ui.selectable_value(self, quality, quality.name());
+                }
+            });
+        old != *self
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IblSettings {
+    /// Face size of the diffuse irradiance cubemap.
+    pub irradiance_size: u32,
+    /// Angle between the hemisphere samples of the irradiance convolution in radians.
+    pub irradiance_delta: f32,
+    /// Face size of the specular cubemap.
+    pub prefilter_size: u32,
+    /// One mip per roughness level, the glTF shader spreads roughness over all of them.
+    pub prefilter_mips: u32,
+    /// Importance samples per texel of the specular cubemap.
+    pub prefilter_samples: u32,
+}
@@ -1,22 +1,133 @@
 use crate::cubemap::CubeMesh;
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, sync::Arc};
 use vulkano::{
+    buffer::BufferContents,
     command_buffer::AutoCommandBufferBuilder,
     descriptor_set::DescriptorSet,
     pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint},
 };
 
+/// Art direction of the image based lighting, applied to the skybox and the glTF shading.
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+pub struct EnvironmentPush {
+    pub intensity: f32,
+    /// Rotation around the up axis in radians.
+    pub yaw: f32,
+}
+impl Default for EnvironmentPush {
+    fn default() -> Self {
+        Self {
+            intensity: 1.0,
+            yaw: 0.0,
+        }
+    }
+}
+impl EnvironmentPush {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.intensity, 0.0..=10.0).text("Intensity"));
+        ui.add(egui::Slider::new(&mut self.yaw, -PI..=PI).text("Rotation"));
+    }
+}
+
+/// How the loaded environment takes part in the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SkyboxMode {
+    /// Drawn behind the scene and lighting it.
+    #[default]
+    Shown,
+    /// Lights the scene in front of the background colour, as in product shots.
+    LightingOnly,
+    /// Neither drawn nor lighting, only the punctual lights remain.
+    Hidden,
+}
+impl SkyboxMode {
+    pub const ALL: [SkyboxMode; 3] = [
+        SkyboxMode::Shown,
+        SkyboxMode::LightingOnly,
+        SkyboxMode::Hidden,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SkyboxMode::Shown => "Shown",
+            SkyboxMode::LightingOnly => "Lighting only",
+            SkyboxMode::Hidden => "Hidden",
+        }
+    }
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Skybox")
+            .selected_text(self.name())
+            .show_ui(ui, |ui| {
+                for mode in Self::ALL {
+                    ui.selectable_value(self, mode, mode.name());
+                }
+            });
+    }
+}
+
+/// What is shown behind the scene, and whether the scene is shown at all.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Background {
+    pub skybox: SkyboxMode,
+    /// If false only the environment is drawn.
+    pub show_models: bool,
+    /// Linear colour shown where there is no skybox.
+    pub color: [f32; 3],
+}
+impl Default for Background {
+    fn default() -> Self {
+        Self {
+            skybox: SkyboxMode::Shown,
+            show_models: true,
+            color: [0.02, 0.02, 0.02],
+        }
+    }
+}
+impl Background {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        self.skybox.ui(ui);
+        ui.checkbox(&mut self.show_models, "Show models");
+        ui.horizontal(|ui| {
+            egui::color_picker::color_edit_button_rgb(ui, &mut self.color);
+            ui.label("Background colour");
+        });
+    }
+    pub fn clear_color(&self) -> [f32; 4] {
+        let [r, g, b] = self.color;
+        [r, g, b, 1.0]
+    }
+    /// Image based lighting is off while the skybox is hidden.
+    pub fn environment_intensity(&self, environment: &EnvironmentPush) -> f32 {
+        match self.skybox {
+            SkyboxMode::Shown | SkyboxMode::LightingOnly => environment.intensity,
+            SkyboxMode::Hidden => 0.0,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct SkyboxRenderer {
     pub pipeline: Arc<GraphicsPipeline>,
     pub skybox: Option<Arc<DescriptorSet>>,
     pub cube: Arc<CubeMesh>,
+    pub environment: EnvironmentPush,
+    pub background: Background,
 }
 impl SkyboxRenderer {
+    /// Draws nothing if no skybox is loaded or it is hidden.
     pub fn render<L>(&self, builder: &mut AutoCommandBufferBuilder<L>) {
-        if let Some(skybox) = self.skybox.clone() {
+        if let Some(skybox) = self
+            .skybox
+            .clone()
+            .filter(|_| self.background.skybox == SkyboxMode::Shown)
+        {
             builder
                 .bind_pipeline_graphics(self.pipeline.clone())
+                .unwrap()
+                .push_constants(self.pipeline.layout().clone(), 0, self.environment)
                 .unwrap();
             builder
                 .bind_descriptor_sets(
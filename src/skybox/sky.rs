@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use vulkano::buffer::BufferContents;
+
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+pub struct SkyUniform {
+    pub zenith: [f32; 4],
+    pub horizon: [f32; 4],
+    pub ground: [f32; 4],
+    /// Direction towards the sun, `w` is the cosine of its angular radius.
+    pub sun_direction: [f32; 4],
+    pub sun_color: [f32; 4],
+}
+
+/// Built-in environments used when no HDR is loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SkyPreset {
+    #[default]
+    Day,
+    Sunset,
+    Overcast,
+    Studio,
+}
+impl SkyPreset {
+    pub const ALL: [SkyPreset; 4] = [
+        SkyPreset::Day,
+        SkyPreset::Sunset,
+        SkyPreset::Overcast,
+        SkyPreset::Studio,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SkyPreset::Day => "Day",
+            SkyPreset::Sunset => "Sunset",
+            SkyPreset::Overcast => "Overcast",
+            SkyPreset::Studio => "Studio",
+        }
+    }
+
+    pub fn uniform(&self) -> SkyUniform {
+        // half a degree, about the size of the real sun
+        let sun_radius = 0.0087f32.cos();
+        match self {
+            SkyPreset::Day => SkyUniform {
+                zenith: [0.25, 0.45, 0.9, 0.0],
+                horizon: [0.8, 0.85, 0.95, 0.0],
+                ground: [0.3, 0.28, 0.25, 0.0],
+                sun_direction: [0.4, 0.7, 0.3, sun_radius],
+                sun_color: [200.0, 190.0, 170.0, 0.0],
+            },
+            SkyPreset::Sunset => SkyUniform {
+                zenith: [0.15, 0.2, 0.45, 0.0],
+                horizon: [1.2, 0.55, 0.25, 0.0],
+                ground: [0.15, 0.1, 0.08, 0.0],
+                sun_direction: [0.0, 0.05, 1.0, sun_radius],
+                sun_color: [300.0, 120.0, 40.0, 0.0],
+            },
+            SkyPreset::Overcast => SkyUniform {
+                zenith: [0.7, 0.72, 0.75, 0.0],
+                horizon: [0.85, 0.86, 0.88, 0.0],
+                ground: [0.25, 0.25, 0.25, 0.0],
+                sun_direction: [0.0, 1.0, 0.0, 1.0],
+                sun_color: [0.0; 4],
+            },
+            SkyPreset::Studio => SkyUniform {
+                zenith: [1.0, 1.0, 1.0, 0.0],
+                horizon: [0.5, 0.5, 0.5, 0.0],
+                ground: [0.1, 0.1, 0.1, 0.0],
+                sun_direction: [0.0, 1.0, 0.0, 1.0],
+                sun_color: [0.0; 4],
+            },
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    collections::VecDeque,
+    ops::AddAssign,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use vulkano::{
+    command_buffer::AutoCommandBufferBuilder,
+    device::{DeviceOwned, Queue},
+    query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
+    sync::PipelineStage,
+};
+
+/// Number of frames kept for the graph.
+const HISTORY: usize = 240;
+/// Frames averaged for the FPS.
+const AVERAGE: usize = 30;
+
+/// What was recorded into a frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DrawStats {
+    pub draw_calls: u32,
+    pub instances: u32,
+    pub triangles: u64,
+}
+impl DrawStats {
+    /// A single instanced draw.
+    pub fn draw(triangles: u32, instances: u32) -> Self {
+        Self {
+            draw_calls: 1,
+            instances,
+            triangles: triangles as u64 * instances as u64,
+        }
+    }
+}
+impl AddAssign for DrawStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.draw_calls += rhs.draw_calls;
+        self.instances += rhs.instances;
+        self.triangles += rhs.triangles;
+    }
+}
+
+/// Measures the GPU time of each frame with a pair of timestamps.
+struct GpuTimer {
+    pool: Arc<QueryPool>,
+    /// Nanoseconds per tick.
+    period: f32,
+    /// Bits of the timestamps that are valid.
+    mask: u64,
+    /// Whether the end timestamp of a frame was written since its queries were reset.
+    written: Vec<bool>,
+}
+impl GpuTimer {
+    /// `None` if the queue doesn't support timestamps.
+    fn new(queue: &Queue, num_frames: usize) -> Option<Self> {
+        let device = queue.device();
+        let bits = device.physical_device().queue_family_properties()
+            [queue.queue_family_index() as usize]
+            .timestamp_valid_bits?;
+        let pool = QueryPool::new(
+            device.clone(),
+            QueryPoolCreateInfo {
+                query_count: 2 * num_frames as u32,
+                ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+            },
+        )
+        .unwrap();
+        Some(Self {
+            pool,
+            period: device.physical_device().properties().timestamp_period,
+            mask: u64::MAX >> (64 - bits.min(64)),
+            written: vec![false; num_frames],
+        })
+    }
+    /// Reads the time of the last frame that used `index`, if it has finished, and starts timing again.
+    fn begin<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>, index: usize) -> Option<f32> {
+        let queries = 2 * index as u32..2 * index as u32 + 2;
+        let mut time = None;
+        if self.written[index] {
+            let mut ticks = [0u64; 2];
+            if self
+                .pool
+                .get_results(queries.clone(), &mut ticks, QueryResultFlags::empty())
+                .unwrap_or(false)
+            {
+                let elapsed = ticks[1].wrapping_sub(ticks[0]) & self.mask;
+                time = Some(elapsed as f32 * self.period * 1e-9);
+            }
+        }
+        self.written[index] = false;
+        unsafe {
+            builder
+                .reset_query_pool(self.pool.clone(), queries.clone())
+                .unwrap()
+                .write_timestamp(self.pool.clone(), queries.start, PipelineStage::TopOfPipe)
+        }
+        .unwrap();
+        time
+    }
+    fn end<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>, index: usize) {
+        unsafe {
+            builder.write_timestamp(
+                self.pool.clone(),
+                2 * index as u32 + 1,
+                PipelineStage::BottomOfPipe,
+            )
+        }
+        .unwrap();
+        self.written[index] = true;
+    }
+}
+
+/// Frame times and draw counts shown in the statistics overlay.
+pub struct Stats {
+    /// Seconds between frames.
+    frame_times: VecDeque<f32>,
+    /// Seconds the GPU spent on a frame.
+    gpu_times: VecDeque<f32>,
+    last_frame: Option<Instant>,
+    /// `None` if the GPU can't measure time.
+    timer: Option<GpuTimer>,
+    /// Filled by the render path while the frame is recorded.
+    recording: Arc<Mutex<DrawStats>>,
+    /// Everything drawn in the last recorded frame.
+    draws: DrawStats,
+    pub overlay: bool,
+}
+impl Stats {
+    pub fn new(queue: &Queue, num_frames: usize) -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(HISTORY),
+            gpu_times: VecDeque::with_capacity(HISTORY),
+            last_frame: None,
+            timer: GpuTimer::new(queue, num_frames),
+            recording: Default::default(),
+            draws: DrawStats::default(),
+            overlay: false,
+        }
+    }
+    /// Where the render path adds its draws for the current frame.
+    pub fn recorder(&self) -> Arc<Mutex<DrawStats>> {
+        self.recording.clone()
+    }
+    pub fn record(&self, draws: DrawStats) {
+        *self.recording.lock().unwrap() += draws;
+    }
+    /// Call before anything else is recorded into the frame.
+    pub fn begin_frame<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>, index: usize) {
+        let now = Instant::now();
+        if let Some(last) = self.last_frame.replace(now) {
+            push(&mut self.frame_times, (now - last).as_secs_f32());
+        }
+        if let Some(time) = self
+            .timer
+            .as_mut()
+            .and_then(|timer| timer.begin(builder, index))
+        {
+            push(&mut self.gpu_times, time);
+        }
+        self.draws = std::mem::take(&mut *self.recording.lock().unwrap());
+    }
+    /// Call after everything else is recorded into the frame.
+    pub fn end_frame<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>, index: usize) {
+        if let Some(timer) = &mut self.timer {
+            timer.end(builder, index);
+        }
+    }
+
+    /// `scene_triangles` is the size of everything loaded, drawn or not.
+    pub fn ui(&mut self, ctx: &egui::Context, scene_triangles: u64) {
+        let mut open = self.overlay;
+        egui::Window::new("Statistics")
+            .open(&mut open)
+            .resizable(false)
+            .default_pos([16.0, 16.0])
+            .show(ctx, |ui| {
+                let recent = self.frame_times.iter().rev().take(AVERAGE);
+                let count = recent.len().max(1) as f32;
+                let average = recent.sum::<f32>() / count;
+                egui::Grid::new("stats_overlay")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("FPS");
+                        ui.label(if average > 0.0 {
+                            format!("{:.0}", 1.0 / average)
+                        } else {
+                            "-".to_owned()
+                        });
+                        ui.end_row();
+                        ui.label("Frame time");
+                        ui.label(format_ms(self.frame_times.back()));
+                        ui.end_row();
+                        ui.label("GPU time");
+                        ui.label(match &self.timer {
+                            Some(_) => format_ms(self.gpu_times.back()),
+                            None => "unsupported".to_owned(),
+                        });
+                        ui.end_row();
+                        ui.label("Draw calls");
+                        ui.label(self.draws.draw_calls.to_string());
+                        ui.end_row();
+                        ui.label("Instances");
+                        ui.label(self.draws.instances.to_string());
+                        ui.end_row();
+                        ui.label("Triangles drawn");
+                        ui.label(self.draws.triangles.to_string());
+                        ui.end_row();
+                        ui.label("Triangles in scene");
+                        ui.label(scene_triangles.to_string());
+                        ui.end_row();
+                    });
+                self.graph(ui);
+            });
+        self.overlay = open;
+    }
+    /// Frame times of the last [`HISTORY`] frames, newest on the right.
+    fn graph(&self, ui: &mut egui::Ui) {
+        let (rect, _) =
+            ui.allocate_exact_size(egui::vec2(HISTORY as f32, 60.0), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+        // at least 60 FPS fits so a smooth frame rate doesn't look noisy
+        let max = self
+            .frame_times
+            .iter()
+            .chain(&self.gpu_times)
+            .fold(1.0 / 60.0, |max: f32, time| max.max(*time));
+        let line = |times: &VecDeque<f32>, color: egui::Color32| {
+            let start = HISTORY - times.len();
+            let points = times
+                .iter()
+                .enumerate()
+                .map(|(i, time)| {
+                    egui::pos2(
+                        rect.left() + (start + i) as f32 * rect.width() / HISTORY as f32,
+                        rect.bottom() - time / max * rect.height(),
+                    )
+                })
+                .collect();
+            painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, color)));
+        };
+        line(&self.frame_times, egui::Color32::LIGHT_BLUE);
+        line(&self.gpu_times, egui::Color32::ORANGE);
+        painter.text(
+            rect.left_top() + egui::vec2(4.0, 2.0),
+            egui::Align2::LEFT_TOP,
+            format!("{:.1} ms", max * 1000.0),
+            egui::FontId::monospace(10.0),
+            ui.visuals().weak_text_color(),
+        );
+
+        ui.horizontal(|ui| {
+            ui.colored_label(egui::Color32::LIGHT_BLUE, "frame");
+            if self.timer.is_some() {
+                ui.colored_label(egui::Color32::ORANGE, "GPU");
+            }
+        });
+    }
+}
+
+fn push(times: &mut VecDeque<f32>, time: f32) {
+    if times.len() == HISTORY {
+        times.pop_front();
+    }
+    times.push_back(time);
+}
+
+fn format_ms(time: Option<&f32>) -> String {
+    time.map_or_else(|| "-".to_owned(), |time| format!("{:.2} ms", time * 1000.0))
+}
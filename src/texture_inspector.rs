@@ -0,0 +1,196 @@
+use crate::vktf::GltfRenderInfo;
+use egui_winit_vulkano::Gui;
+use std::sync::Arc;
+use vulkano::{
+    format::NumericFormat,
+    image::{
+        sampler::{ComponentMapping, ComponentSwizzle, SamplerCreateInfo},
+        view::{ImageView, ImageViewCreateInfo},
+    },
+};
+
+/// Largest size of the preview in points.
+const PREVIEW_SIZE: f32 = 512.0;
+
+/// Which channels of the previewed image are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Channel {
+    #[default]
+    Rgba,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+impl Channel {
+    pub const ALL: [Channel; 5] = [
+        Channel::Rgba,
+        Channel::Red,
+        Channel::Green,
+        Channel::Blue,
+        Channel::Alpha,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Rgba => "RGBA",
+            Channel::Red => "R",
+            Channel::Green => "G",
+            Channel::Blue => "B",
+            Channel::Alpha => "A",
+        }
+    }
+    /// Single channels are shown as opaque greyscale.
+    fn component_mapping(&self) -> ComponentMapping {
+        let grey = |swizzle| ComponentMapping {
+            r: swizzle,
+            g: swizzle,
+            b: swizzle,
+            a: ComponentSwizzle::One,
+        };
+        match self {
+            Channel::Rgba => ComponentMapping::identity(),
+            Channel::Red => grey(ComponentSwizzle::Red),
+            Channel::Green => grey(ComponentSwizzle::Green),
+            Channel::Blue => grey(ComponentSwizzle::Blue),
+            Channel::Alpha => grey(ComponentSwizzle::Alpha),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for channel in Self::ALL {
+                ui.selectable_value(self, channel, channel.name());
+            }
+        });
+    }
+}
+
+/// Lists the images of the loaded models and previews one of them.
+#[derive(Default)]
+pub struct TextureInspector {
+    /// Model and image index of the previewed image.
+    selected: Option<(usize, usize)>,
+    channel: Channel,
+    /// The image the preview shows, replaced when a larger version is streamed in.
+    source: Option<Arc<ImageView>>,
+    texture: Option<egui::TextureId>,
+    /// The preview has to be registered again.
+    stale: bool,
+}
+impl TextureInspector {
+    /// Makes the preview available to egui, call before [`window`](Self::window).
+    pub fn register(&mut self, gui: &mut Gui) {
+        if !self.stale {
+            return;
+        }
+        if let Some(texture) = self.texture.take() {
+            gui.unregister_user_image(texture);
+        }
+        if let Some(source) = &self.source {
+            let image = source.image().clone();
+            let view = ImageView::new(
+                image.clone(),
+                ImageViewCreateInfo {
+                    component_mapping: self.channel.component_mapping(),
+                    ..ImageViewCreateInfo::from_image(&image)
+                },
+            )
+            .unwrap();
+            self.texture = Some(gui.register_user_image_view(view, SamplerCreateInfo::default()));
+        }
+        self.stale = false;
+    }
+    /// Forgets the preview of a dropped [`Gui`], it is registered again with the next one.
+    pub fn forget_textures(&mut self) {
+        self.texture = None;
+        self.stale = true;
+    }
+
+    /// Lists the images of every model, clicking one previews it.
+    pub fn ui(&mut self, ui: &mut egui::Ui, models: &[GltfRenderInfo]) {
+        for (m, info) in models.iter().enumerate() {
+            let name = info
+                .vktf
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            ui.strong(name);
+            if info.vktf.document.images().len() == 0 {
+                ui.label("No images");
+            }
+            for image in info.vktf.document.images() {
+                let i = image.index();
+                let name = image
+                    .name()
+                    .map_or_else(|| format!("Image {i}"), str::to_owned);
+                let details = match info.vktf.vktf.get_loaded_image(i) {
+                    Some(view) => image_details(view),
+                    None => "loading...".to_owned(),
+                };
+                let selected = self.selected == Some((m, i));
+                if ui
+                    .selectable_label(selected, format!("{name}: {details}"))
+                    .clicked()
+                {
+                    self.selected = (!selected).then_some((m, i));
+                }
+            }
+        }
+    }
+    /// The preview of the selected image.
+    pub fn window(&mut self, ctx: &egui::Context, models: &[GltfRenderInfo]) {
+        if self.selected.is_some_and(|(m, _)| m >= models.len()) {
+            self.selected = None;
+        }
+        // follow streamed in images
+        let source = self
+            .selected
+            .and_then(|(m, i)| models[m].vktf.vktf.get_loaded_image(i).cloned());
+        if source.as_ref().map(Arc::as_ptr) != self.source.as_ref().map(Arc::as_ptr) {
+            self.source = source;
+            self.stale = true;
+        }
+
+        let Some(source) = self.source.clone() else {
+            return;
+        };
+        let mut open = true;
+        let channel = self.channel;
+        egui::Window::new("Texture")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(image_details(&source));
+                self.channel.ui(ui);
+                if let Some(texture) = self.texture {
+                    let [width, height, _] = source.image().extent();
+                    let size = egui::vec2(width as f32, height as f32);
+                    let size = size * (PREVIEW_SIZE / size.max_elem()).min(1.0);
+                    ui.image(egui::load::SizedTexture::new(texture, size));
+                }
+            });
+        if self.channel != channel {
+            self.stale = true;
+        }
+        if !open {
+            self.selected = None;
+        }
+    }
+}
+
+fn image_details(view: &ImageView) -> String {
+    let image = view.image();
+    let [width, height, _] = image.extent();
+    let srgb = if image.format().numeric_format_color() == Some(NumericFormat::SRGB) {
+        ", sRGB"
+    } else {
+        ""
+    };
+    format!(
+        "{width}x{height} {:?}{srgb}, {} mips",
+        image.format(),
+        image.mip_levels()
+    )
+}
@@ -0,0 +1,138 @@
+use crate::{
+    camera::{Bookmark, Bookmarks},
+    vktf::{GltfRenderInfo, ModelTransform, material::MaterialPush},
+};
+use nalgebra_glm as glm;
+use std::path::PathBuf;
+
+/// Steps kept at most, the oldest are dropped.
+const MAX_STEPS: usize = 100;
+
+/// What can be edited in a model: its placement, its material factors and the transform and
+/// visibility of its nodes.
+#[derive(Clone, PartialEq)]
+struct ModelEdits {
+    /// Snapshots are only applied to the model they were taken of.
+    path: PathBuf,
+    transform: ModelTransform,
+    materials: Vec<MaterialPush>,
+    default_material: MaterialPush,
+    /// Local transform and visibility by node index.
+    nodes: Vec<(glm::Mat4, bool)>,
+}
+
+/// Everything the undo stack covers at one point in time.
+#[derive(Clone, PartialEq)]
+pub struct EditSnapshot {
+    models: Vec<ModelEdits>,
+    bookmarks: Vec<Bookmark>,
+}
+impl EditSnapshot {
+    pub fn new(models: &[GltfRenderInfo], bookmarks: &Bookmarks) -> Self {
+        let models = models
+            .iter()
+            .map(|info| ModelEdits {
+                path: info.vktf.path.clone(),
+                transform: info.transform(),
+                materials: info
+                    .materials
+                    .index
+                    .iter()
+                    .map(|material| material.push)
+                    .collect(),
+                default_material: info.materials.default.push,
+                nodes: info
+                    .scene
+                    .nodes
+                    .iter()
+                    .map(|node| (node.local, node.is_visible()))
+                    .collect(),
+            })
+            .collect();
+        Self {
+            models,
+            bookmarks: bookmarks.list.clone(),
+        }
+    }
+    /// Restores the snapshot, models that were loaded or removed since are left alone.
+    pub fn apply(self, models: &mut [GltfRenderInfo], bookmarks: &mut Bookmarks) {
+        for (info, edits) in models.iter_mut().zip(self.models) {
+            if info.vktf.path != edits.path {
+                continue;
+            }
+            info.set_transform(edits.transform);
+            for (material, push) in info.materials.index.iter_mut().zip(edits.materials) {
+                material.push = push;
+            }
+            info.materials.default.push = edits.default_material;
+            for (node, (local, visible)) in edits.nodes.into_iter().enumerate() {
+                info.scene.set_local(node, local);
+                info.scene.set_visible(node, visible);
+            }
+        }
+        if bookmarks.list != self.bookmarks {
+            bookmarks.list = self.bookmarks;
+            bookmarks.stop();
+        }
+    }
+    /// The same models are loaded in both, so a difference is an edit.
+    fn same_models(&self, other: &Self) -> bool {
+        self.models
+            .iter()
+            .map(|model| &model.path)
+            .eq(other.models.iter().map(|model| &model.path))
+    }
+}
+
+/// Undo and redo of the edits made in the panels. A snapshot is taken before and after
+/// the UI every frame, so loads and animations in between aren't recorded, and a drag
+/// becomes a single step once the pointer is released.
+#[derive(Default)]
+pub struct UndoStack {
+    undo: Vec<EditSnapshot>,
+    redo: Vec<EditSnapshot>,
+    /// The state before the edit the pointer is still dragging.
+    pending: Option<EditSnapshot>,
+    /// A snapshot was restored during the UI, which isn't an edit.
+    restored: bool,
+}
+impl UndoStack {
+    /// Call once per frame with the states before and after the UI.
+    pub fn record(&mut self, ctx: &egui::Context, before: EditSnapshot, after: &EditSnapshot) {
+        if std::mem::take(&mut self.restored) {
+            return;
+        }
+        if before != *after && before.same_models(after) {
+            self.pending.get_or_insert(before);
+            self.redo.clear();
+        }
+        if ctx.input(|input| input.pointer.any_down()) {
+            return;
+        }
+        if let Some(snapshot) = self.pending.take() {
+            self.undo.push(snapshot);
+            if self.undo.len() > MAX_STEPS {
+                self.undo.remove(0);
+            }
+        }
+    }
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+    /// The state to restore, `current` can be restored again with [`redo`](Self::redo).
+    pub fn undo(&mut self, current: EditSnapshot) -> Option<EditSnapshot> {
+        let snapshot = self.undo.pop()?;
+        self.redo.push(current);
+        self.restored = true;
+        Some(snapshot)
+    }
+    pub fn redo(&mut self, current: EditSnapshot) -> Option<EditSnapshot> {
+        let snapshot = self.redo.pop()?;
+        self.undo.push(current);
+        self.restored = true;
+        Some(snapshot)
+    }
+}
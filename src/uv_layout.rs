@@ -0,0 +1,714 @@
+use crate::{
+    Allocators,
+    memory::MemoryCategory,
+    vktf::{
+        GltfRenderInfo,
+        loader::{LoadGltfError, PrimitiveGeometry, PrimitiveVertex},
+    },
+};
+use egui_winit_vulkano::Gui;
+use nalgebra_glm as glm;
+use std::{path::PathBuf, sync::Arc};
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, RenderPassBeginInfo, SubpassBeginInfo, SubpassEndInfo,
+    },
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::{Device, DeviceOwned},
+    format::Format,
+    image::{
+        Image, ImageCreateInfo, ImageType, ImageUsage,
+        sampler::{Filter, Sampler, SamplerCreateInfo},
+        view::ImageView,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::{
+        DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+        graphics::{
+            GraphicsPipelineCreateInfo,
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::{Vertex, VertexDefinition, VertexInputState},
+            viewport::{Scissor, Viewport, ViewportState},
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    shader::EntryPoint,
+};
+
+/// Size of the rendered layout in pixels.
+const LAYOUT_SIZE: u32 = 1024;
+/// Size of the layout in the window in points.
+const PREVIEW_SIZE: f32 = 512.0;
+/// Cells per side of the grid the covered texture area is counted in.
+const COVERAGE_CELLS: usize = 256;
+
+/// Added for every triangle covering a pixel, so overlapping triangles are brighter.
+const FILL: [f32; 4] = [0.35, 0.15, 0.0, 0.0];
+/// The fill of triangles with flipped winding, like mirrored halves sharing texels.
+const MIRRORED_FILL: [f32; 4] = [0.0, 0.15, 0.35, 0.0];
+/// The fill of triangles reaching outside the texture, which repeat or clamp it.
+const OUTSIDE_FILL: [f32; 4] = [0.35, 0.0, 0.35, 0.0];
+const WIRE: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+
+/// Which texture coordinates are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UvSet {
+    #[default]
+    Uv0,
+    Uv1,
+}
+impl UvSet {
+    pub const ALL: [UvSet; 2] = [UvSet::Uv0, UvSet::Uv1];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UvSet::Uv0 => "UV0",
+            UvSet::Uv1 => "UV1",
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, BufferContents, Vertex)]
+struct UvVertex {
+    #[format(R32G32_SFLOAT)]
+    uv: [f32; 2],
+    #[format(R32G32B32A32_SFLOAT)]
+    color: [f32; 4],
+}
+
+/// What the layout shows about the triangles of the primitive.
+#[derive(Debug, Clone, Copy, Default)]
+struct UvStats {
+    triangles: usize,
+    /// Triangles with a corner outside of 0 to 1.
+    outside: usize,
+    mirrored: usize,
+    /// Triangles without area in the texture.
+    degenerate: usize,
+    /// Fractions of the texture covered by at least one and by more than one triangle.
+    coverage: f32,
+    overlap: f32,
+}
+
+/// The vertex buffers of the fill and the wireframe, `None` without triangles.
+struct UvBuffers {
+    fill: Option<Subbuffer<[UvVertex]>>,
+    wire: Option<Subbuffer<[UvVertex]>>,
+}
+
+/// The primitive whose layout is shown.
+struct Selection {
+    /// The model is found by path, its document is replaced while images stream in.
+    path: PathBuf,
+    name: String,
+    material: Option<usize>,
+    has_uv1: bool,
+    geometry: PrimitiveGeometry,
+    stats: UvStats,
+    buffers: Option<UvBuffers>,
+}
+
+/// The image the layout is drawn into, made when the first layout is shown.
+struct LayoutTarget {
+    view: Arc<ImageView>,
+    framebuffer: Arc<Framebuffer>,
+    background: Arc<GraphicsPipeline>,
+    fill: Arc<GraphicsPipeline>,
+    wire: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+}
+
+/// The texture coordinates of a primitive drawn over its base color texture,
+/// to find stretched, overlapping or mirrored islands.
+#[derive(Default)]
+pub struct UvLayout {
+    selected: Option<Selection>,
+    uv_set: UvSet,
+    target: Option<LayoutTarget>,
+    /// The base color image of the last drawn layout, drawn again when a larger version
+    /// is streamed in.
+    background: Option<Arc<ImageView>>,
+    /// The layout has to be drawn again.
+    dirty: bool,
+    texture: Option<egui::TextureId>,
+    /// The layout has to be registered again.
+    stale: bool,
+}
+impl UvLayout {
+    /// Shows the layout of primitive `primitive` of glTF mesh `mesh` of a model.
+    /// Reads the file again since the vertices only live on the GPU.
+    pub fn select(
+        &mut self,
+        info: &GltfRenderInfo,
+        mesh: usize,
+        primitive: usize,
+    ) -> Result<(), LoadGltfError> {
+        let geometry = info
+            .vktf
+            .read_geometry()?
+            .into_iter()
+            .nth(mesh)
+            .and_then(|primitives| primitives.into_iter().find(|p| p.index == primitive))
+            .ok_or(LoadGltfError::Changed)?;
+        let gltf_mesh = info.vktf.document.meshes().nth(mesh);
+        let gltf_primitive = gltf_mesh
+            .as_ref()
+            .and_then(|m| m.primitives().nth(primitive));
+        let mesh_name = gltf_mesh
+            .as_ref()
+            .and_then(|m| m.name())
+            .map_or_else(|| format!("Mesh {mesh}"), str::to_owned);
+        let has_uv1 = gltf_primitive
+            .as_ref()
+            .is_some_and(|p| p.get(&gltf::Semantic::TexCoords(1)).is_some());
+        if !has_uv1 {
+            self.uv_set = UvSet::Uv0;
+        }
+        self.selected = Some(Selection {
+            path: info.vktf.path.clone(),
+            name: format!("{mesh_name}, primitive {primitive}"),
+            material: gltf_primitive.and_then(|p| p.material().index()),
+            has_uv1,
+            stats: uv_stats(&geometry, self.uv_set),
+            geometry,
+            buffers: None,
+        });
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Draws the layout if the selection, the UV set or the texture changed,
+    /// call before [`register`](Self::register).
+    pub fn render<L>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        allocators: &Allocators,
+        models: &[GltfRenderInfo],
+    ) {
+        let Some(selected) = &mut self.selected else {
+            return;
+        };
+        let Some(info) = models.iter().find(|info| info.vktf.path == selected.path) else {
+            self.selected = None;
+            return;
+        };
+        let background = base_color_image(info, selected.material);
+        if !self.dirty
+            && background.as_ref().map(Arc::as_ptr) == self.background.as_ref().map(Arc::as_ptr)
+        {
+            return;
+        }
+        let target = self.target.get_or_insert_with(|| {
+            self.stale = true;
+            LayoutTarget::new(allocators)
+        });
+        let buffers = selected
+            .buffers
+            .get_or_insert_with(|| uv_buffers(allocators, &selected.geometry, self.uv_set));
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some([0.1, 0.1, 0.1, 1.0].into())],
+                    ..RenderPassBeginInfo::framebuffer(target.framebuffer.clone())
+                },
+                SubpassBeginInfo::default(),
+            )
+            .unwrap()
+            .set_viewport(
+                0,
+                [Viewport {
+                    extent: [LAYOUT_SIZE as f32; 2],
+                    ..Default::default()
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .set_scissor(0, [Scissor::default()].into_iter().collect())
+            .unwrap();
+        if let Some(image) = &background {
+            let pipeline = &target.background;
+            let set = DescriptorSet::new(
+                allocators.set.clone(),
+                pipeline.layout().set_layouts()[0].clone(),
+                [WriteDescriptorSet::image_view_sampler(
+                    0,
+                    image.clone(),
+                    target.sampler.clone(),
+                )],
+                [],
+            )
+            .unwrap();
+            builder
+                .bind_pipeline_graphics(pipeline.clone())
+                .unwrap()
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    pipeline.layout().clone(),
+                    0,
+                    set,
+                )
+                .unwrap();
+            unsafe { builder.draw(3, 1, 0, 0) }.unwrap();
+        }
+        for (pipeline, vertices) in [(&target.fill, &buffers.fill), (&target.wire, &buffers.wire)] {
+            let Some(vertices) = vertices else {
+                continue;
+            };
+            builder
+                .bind_pipeline_graphics(pipeline.clone())
+                .unwrap()
+                .bind_vertex_buffers(0, vertices.clone())
+                .unwrap();
+            unsafe { builder.draw(vertices.len() as u32, 1, 0, 0) }.unwrap();
+        }
+        builder.end_render_pass(SubpassEndInfo::default()).unwrap();
+
+        self.background = background;
+        self.dirty = false;
+    }
+
+    /// Makes the layout available to egui, call before [`window`](Self::window).
+    pub fn register(&mut self, gui: &mut Gui) {
+        if !self.stale {
+            return;
+        }
+        if let Some(texture) = self.texture.take() {
+            gui.unregister_user_image(texture);
+        }
+        if let Some(target) = &self.target {
+            self.texture = Some(
+                gui.register_user_image_view(target.view.clone(), SamplerCreateInfo::default()),
+            );
+        }
+        self.stale = false;
+    }
+    /// Forgets the layout of a dropped [`Gui`], it is registered again with the next one.
+    pub fn forget_textures(&mut self) {
+        self.texture = None;
+        self.stale = true;
+    }
+
+    pub fn window(&mut self, ctx: &egui::Context) {
+        let Some(selected) = &mut self.selected else {
+            return;
+        };
+        let mut open = true;
+        let uv_set = self.uv_set;
+        egui::Window::new("UV layout")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(&selected.name);
+                ui.add_enabled_ui(selected.has_uv1, |ui| {
+                    ui.horizontal(|ui| {
+                        for set in UvSet::ALL {
+                            ui.selectable_value(&mut self.uv_set, set, set.name());
+                        }
+                    });
+                });
+                let stats = &selected.stats;
+                egui::Grid::new("uv stats").num_columns(2).show(ui, |ui| {
+                    ui.label("Triangles");
+                    ui.label(stats.triangles.to_string());
+                    ui.end_row();
+                    ui.label("Outside 0-1")
+                        .on_hover_text("Drawn in magenta, only the part inside is shown");
+                    ui.label(stats.outside.to_string());
+                    ui.end_row();
+                    ui.label("Mirrored")
+                        .on_hover_text("Drawn in blue, the winding is flipped in the texture");
+                    ui.label(stats.mirrored.to_string());
+                    ui.end_row();
+                    ui.label("Degenerate");
+                    ui.label(stats.degenerate.to_string());
+                    ui.end_row();
+                    ui.label("Coverage");
+                    ui.label(format!("{:.1}%", stats.coverage * 100.0));
+                    ui.end_row();
+                    ui.label("Overlap")
+                        .on_hover_text("Texture area shared by triangles, drawn brighter");
+                    ui.label(format!("{:.1}%", stats.overlap * 100.0));
+                    ui.end_row();
+                });
+                if self.background.is_none() {
+                    ui.label("No base color texture");
+                }
+                if let Some(texture) = self.texture {
+                    ui.image(egui::load::SizedTexture::new(
+                        texture,
+                        egui::Vec2::splat(PREVIEW_SIZE),
+                    ));
+                }
+            });
+        if self.uv_set != uv_set {
+            selected.stats = uv_stats(&selected.geometry, self.uv_set);
+            selected.buffers = None;
+            self.dirty = true;
+        }
+        if !open {
+            self.selected = None;
+        }
+    }
+}
+
+impl LayoutTarget {
+    fn new(allocators: &Allocators) -> Self {
+        let device = allocators.mem.device().clone();
+        let image = Image::new(
+            allocators.memory.allocator(MemoryCategory::RenderTargets),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: Format::R8G8B8A8_SRGB,
+                extent: [LAYOUT_SIZE, LAYOUT_SIZE, 1],
+                usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+        let view = ImageView::new_default(image).unwrap();
+        let render_pass = render_pass(device.clone(), view.format());
+        let framebuffer = Framebuffer::new(
+            render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![view.clone()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let subpass = Subpass::from(render_pass, 0).unwrap();
+        let sampler = Sampler::new(
+            device,
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        Self {
+            view,
+            framebuffer,
+            background: background_pipeline(subpass.clone()),
+            fill: uv_pipeline(subpass.clone(), PrimitiveTopology::TriangleList),
+            wire: uv_pipeline(subpass, PrimitiveTopology::LineList),
+            sampler,
+        }
+    }
+}
+
+/// The base color image of a material once it is streamed in.
+fn base_color_image(info: &GltfRenderInfo, material: Option<usize>) -> Option<Arc<ImageView>> {
+    let material = info.vktf.document.materials().nth(material?)?;
+    let texture = material.pbr_metallic_roughness().base_color_texture()?;
+    info.vktf
+        .vktf
+        .get_loaded_image(texture.texture().source().index())
+        .cloned()
+}
+
+fn uv(vertex: &PrimitiveVertex, set: UvSet) -> glm::Vec2 {
+    match set {
+        UvSet::Uv0 => vertex.uv_0,
+        UvSet::Uv1 => vertex.uv_1,
+    }
+}
+
+/// Twice the signed area of a triangle in the texture, negative if it is mirrored.
+fn signed_area([a, b, c]: [glm::Vec2; 3]) -> f32 {
+    let (ab, ac) = (b - a, c - a);
+    ab.x * ac.y - ab.y * ac.x
+}
+
+fn is_outside(corners: &[glm::Vec2; 3]) -> bool {
+    corners
+        .iter()
+        .any(|uv| uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0)
+}
+
+/// The texture coordinates of the corners of every triangle.
+fn uv_triangles(
+    geometry: &PrimitiveGeometry,
+    set: UvSet,
+) -> impl Iterator<Item = [glm::Vec2; 3]> + '_ {
+    geometry
+        .corners()
+        .filter(|_| geometry.triangles)
+        .map(move |corners| corners.map(|i| uv(&geometry.vertices[i as usize], set)))
+}
+
+fn uv_buffers(allocators: &Allocators, geometry: &PrimitiveGeometry, set: UvSet) -> UvBuffers {
+    let mut fill = vec![];
+    let mut wire = vec![];
+    for corners in uv_triangles(geometry, set) {
+        let color = if is_outside(&corners) {
+            OUTSIDE_FILL
+        } else if signed_area(corners) < 0.0 {
+            MIRRORED_FILL
+        } else {
+            FILL
+        };
+        fill.extend(corners.map(|uv| UvVertex {
+            uv: uv.into(),
+            color,
+        }));
+        for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+            wire.extend([corners[a], corners[b]].map(|uv| UvVertex {
+                uv: uv.into(),
+                color: WIRE,
+            }));
+        }
+    }
+    let buffer = |vertices: Vec<UvVertex>| {
+        if vertices.is_empty() {
+            return None;
+        }
+        let buffer = Buffer::from_iter(
+            allocators.memory.allocator(MemoryCategory::Geometry),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            vertices,
+        )
+        .unwrap();
+        Some(buffer)
+    };
+    UvBuffers {
+        fill: buffer(fill),
+        wire: buffer(wire),
+    }
+}
+
+fn uv_stats(geometry: &PrimitiveGeometry, set: UvSet) -> UvStats {
+    let mut stats = UvStats::default();
+    // how many triangles cover the center of every cell of the texture
+    let mut cells = vec![0u8; COVERAGE_CELLS * COVERAGE_CELLS];
+    for corners in uv_triangles(geometry, set) {
+        stats.triangles += 1;
+        if is_outside(&corners) {
+            stats.outside += 1;
+        }
+        let area = signed_area(corners);
+        if area == 0.0 {
+            stats.degenerate += 1;
+            continue;
+        }
+        if area < 0.0 {
+            stats.mirrored += 1;
+        }
+
+        let scaled = corners.map(|uv| uv * COVERAGE_CELLS as f32);
+        let min = scaled[0].inf(&scaled[1]).inf(&scaled[2]);
+        let max = scaled[0].sup(&scaled[1]).sup(&scaled[2]);
+        let range = |min: f32, max: f32| {
+            let start = (min - 0.5).ceil().max(0.0) as usize;
+            let end = ((max - 0.5).floor() + 1.0).clamp(0.0, COVERAGE_CELLS as f32) as usize;
+            start..end
+        };
+        for y in range(min.y, max.y) {
+            for x in range(min.x, max.x) {
+                let center = glm::vec2(x as f32 + 0.5, y as f32 + 0.5);
+                let inside = [(0, 1), (1, 2), (2, 0)].into_iter().all(|(a, b)| {
+                    signed_area([scaled[a], scaled[b], center]) * area.signum() >= 0.0
+                });
+                if inside {
+                    let cell = &mut cells[y * COVERAGE_CELLS + x];
+                    *cell = cell.saturating_add(1);
+                }
+            }
+        }
+    }
+    let total = cells.len() as f32;
+    stats.coverage = cells.iter().filter(|&&n| n > 0).count() as f32 / total;
+    stats.overlap = cells.iter().filter(|&&n| n > 1).count() as f32 / total;
+    stats
+}
+
+fn render_pass(device: Arc<Device>, format: Format) -> Arc<RenderPass> {
+    vulkano::single_pass_renderpass!(
+        device,
+        attachments: {
+            color: {
+                format: format,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {}
+        },
+    )
+    .unwrap()
+}
+
+fn pipeline(
+    subpass: Subpass,
+    stages: [EntryPoint; 2],
+    vertex_input_state: VertexInputState,
+    topology: PrimitiveTopology,
+    blend: Option<AttachmentBlend>,
+) -> Arc<GraphicsPipeline> {
+    let device = subpass.render_pass().device().clone();
+    let stages = stages.map(PipelineShaderStageCreateInfo::new);
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState {
+                topology,
+                ..Default::default()
+            }),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend,
+                    ..Default::default()
+                },
+            )),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap()
+}
+
+/// The base color texture over the whole image, darkened so the triangles stand out.
+fn background_pipeline(subpass: Subpass) -> Arc<GraphicsPipeline> {
+    let device = subpass.render_pass().device().clone();
+    let vs = background_vs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let fs = background_fs::load(device)
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    pipeline(
+        subpass,
+        [vs, fs],
+        VertexInputState::default(),
+        PrimitiveTopology::TriangleList,
+        None,
+    )
+}
+
+/// The triangles added onto the image, or their edges.
+fn uv_pipeline(subpass: Subpass, topology: PrimitiveTopology) -> Arc<GraphicsPipeline> {
+    let device = subpass.render_pass().device().clone();
+    let vs = uv_vs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let fs = uv_fs::load(device).unwrap().entry_point("main").unwrap();
+    let vertex_input_state = UvVertex::per_vertex().definition(&vs).unwrap();
+    let blend = match topology {
+        PrimitiveTopology::TriangleList => Some(AttachmentBlend::additive()),
+        _ => None,
+    };
+    pipeline(subpass, [vs, fs], vertex_input_state, topology, blend)
+}
+
+mod background_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+#version 450
+
+layout(location = 0) out vec2 v_uv;
+
+void main() {
+    // one triangle covering the image, uv 0,0 in the top left corner like in the texture
+    v_uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(v_uv * 2.0 - 1.0, 0.0, 1.0);
+}
+        "#
+    }
+}
+
+mod background_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+
+layout(set = 0, binding = 0) uniform sampler2D base_color;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    f_color = vec4(texture(base_color, v_uv).rgb * 0.4, 1.0);
+}
+        "#
+    }
+}
+
+mod uv_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec2 uv;
+layout(location = 1) in vec4 color;
+
+layout(location = 0) out vec4 v_color;
+
+void main() {
+    v_color = color;
+    gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+        "#
+    }
+}
+
+mod uv_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    f_color = v_color;
+}
+        "#
+    }
+}
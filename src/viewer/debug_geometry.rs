@@ -0,0 +1,394 @@
+use crate::{
+    frameinfo::Depth,
+    vktf::{GltfRenderInfo, loader::LineVertex, mesh::Instance},
+};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor_set::{DescriptorSet, layout::DescriptorSetLayout},
+    device::DeviceOwned,
+    image::SampleCount,
+    memory::allocator::{AllocationCreateInfo, MemoryAllocator, MemoryTypeFilter},
+    pipeline::{
+        DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+        graphics::{
+            GraphicsPipelineCreateInfo,
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{CompareOp, DepthStencilState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::ViewportState,
+        },
+        layout::{PipelineLayoutCreateInfo, PushConstantRange},
+    },
+    render_pass::Subpass,
+    shader::ShaderStages,
+};
+
+const NORMAL_COLOR: [f32; 4] = [0.2, 0.4, 1.0, 1.0];
+const TANGENT_COLOR: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+const MESH_BOUNDS_COLOR: [f32; 4] = [1.0, 0.9, 0.2, 1.0];
+const MODEL_BOUNDS_COLOR: [f32; 4] = [0.2, 1.0, 0.4, 1.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebugGeometrySettings {
+    pub normals: bool,
+    pub tangents: bool,
+    pub bounds: bool,
+    /// Joints of skinned models, painted over the scene by the UI.
+    pub skeletons: bool,
+    /// Length of the normal and tangent lines relative to the model size.
+    pub length: f32,
+}
+impl Default for DebugGeometrySettings {
+    fn default() -> Self {
+        Self {
+            normals: false,
+            tangents: false,
+            bounds: false,
+            skeletons: false,
+            length: 0.02,
+        }
+    }
+}
+impl DebugGeometrySettings {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.normals, "Normals");
+            ui.checkbox(&mut self.tangents, "Tangents");
+            ui.checkbox(&mut self.bounds, "Bounding boxes");
+        });
+        ui.checkbox(&mut self.skeletons, "Skeletons")
+            .on_hover_text("Joints of skinned models, hover a joint for its name");
+        ui.add_enabled_ui(self.normals || self.tangents, |ui| {
+            ui.add(
+                egui::Slider::new(&mut self.length, 0.001..=0.2)
+                    .logarithmic(true)
+                    .text("Line length"),
+            );
+        });
+    }
+    fn any(&self) -> bool {
+        self.normals || self.tangents || self.bounds
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+struct LinePush {
+    color: [f32; 4],
+    box_min: [f32; 3],
+    length: f32,
+    box_max: [f32; 3],
+    /// The vertices are corners of a unit cube stretched to the box.
+    is_box: i32,
+}
+
+/// Draws vertex normals and tangents as lines and mesh and model bounds as wireframes.
+#[derive(Clone)]
+pub struct DebugGeometry {
+    pipeline: Arc<GraphicsPipeline>,
+    /// Edges of the unit cube.
+    cube: Subbuffer<[LineVertex]>,
+    /// Model bounds are already in world space.
+    identity: Subbuffer<[Instance]>,
+    pub settings: DebugGeometrySettings,
+}
+impl DebugGeometry {
+    pub fn new(
+        allocator: Arc<dyn MemoryAllocator>,
+        camera_layout: Arc<DescriptorSetLayout>,
+        subpass: Subpass,
+        depth: Depth,
+    ) -> Self {
+        let device = camera_layout.device().clone();
+        let layout = PipelineLayout::new(
+            device,
+            PipelineLayoutCreateInfo {
+                set_layouts: vec![camera_layout],
+                push_constant_ranges: vec![PushConstantRange {
+                    stages: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    offset: 0,
+                    size: std::mem::size_of::<LinePush>() as u32,
+                }],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let edges = (0..8u32).flat_map(|corner| {
+            [1, 2, 4]
+                .into_iter()
+                .filter(move |axis| corner & axis == 0)
+                .flat_map(move |axis| [corner, corner | axis])
+        });
+        let cube = edges
+            .map(|corner| LineVertex {
+                position: glm::vec3(
+                    (corner & 1) as f32,
+                    (corner >> 1 & 1) as f32,
+                    (corner >> 2 & 1) as f32,
+                ),
+                offset: glm::Vec3::zeros(),
+            })
+            .collect::<Vec<_>>();
+        let cube = vertex_buffer(allocator.clone(), cube);
+        let identity = vertex_buffer(allocator, vec![Instance::from(glm::Mat4::identity())]);
+
+        Self {
+            pipeline: line_pipeline(layout, subpass, depth),
+            cube,
+            identity,
+            settings: DebugGeometrySettings::default(),
+        }
+    }
+    /// Rebuilds the pipeline for a new main render pass.
+    pub fn set_subpass(&mut self, subpass: Subpass, depth: Depth) {
+        self.pipeline = line_pipeline(self.pipeline.layout().clone(), subpass, depth);
+    }
+    pub fn render<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        camera_set: Arc<DescriptorSet>,
+        models: &[GltfRenderInfo],
+        frame: usize,
+    ) {
+        if !self.settings.any() || models.is_empty() {
+            return;
+        }
+        let layout = self.pipeline.layout().clone();
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, layout, 0, camera_set)
+            .unwrap();
+
+        for info in models {
+            if self.settings.bounds {
+                builder
+                    .bind_vertex_buffers(0, (self.cube.clone(), self.identity.clone()))
+                    .unwrap();
+                let aabb = info.world_aabb();
+                self.draw_box(builder, aabb.min, aabb.max, MODEL_BOUNDS_COLOR, 1);
+            }
+            let length = self.settings.length * info.aabb.radius();
+            for mesh in &info.meshes {
+                let instances = mesh.instance_count();
+                mesh.bind_instances(builder, frame);
+                if let Some(lines) = mesh.lines() {
+                    if self.settings.normals {
+                        self.draw_lines(builder, lines.normals(), length, NORMAL_COLOR, instances);
+                    }
+                    if self.settings.tangents {
+                        self.draw_lines(
+                            builder,
+                            lines.tangents(),
+                            length,
+                            TANGENT_COLOR,
+                            instances,
+                        );
+                    }
+                }
+                let bounds = mesh.bounds();
+                if self.settings.bounds && !bounds.is_empty() {
+                    builder.bind_vertex_buffers(0, self.cube.clone()).unwrap();
+                    self.draw_box(
+                        builder,
+                        bounds.min,
+                        bounds.max,
+                        MESH_BOUNDS_COLOR,
+                        instances,
+                    );
+                }
+            }
+        }
+    }
+    fn draw_lines<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        lines: Subbuffer<[LineVertex]>,
+        length: f32,
+        color: [f32; 4],
+        instances: u32,
+    ) {
+        let push = LinePush {
+            color,
+            box_min: [0.0; 3],
+            length,
+            box_max: [0.0; 3],
+            is_box: 0,
+        };
+        let count = lines.len() as u32;
+        builder
+            .bind_vertex_buffers(0, lines)
+            .unwrap()
+            .push_constants(self.pipeline.layout().clone(), 0, push)
+            .unwrap();
+        unsafe { builder.draw(count, instances, 0, 0) }.unwrap();
+    }
+    /// The cube has to be bound already.
+    fn draw_box<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        min: glm::Vec3,
+        max: glm::Vec3,
+        color: [f32; 4],
+        instances: u32,
+    ) {
+        let push = LinePush {
+            color,
+            box_min: min.into(),
+            length: 0.0,
+            box_max: max.into(),
+            is_box: 1,
+        };
+        builder
+            .push_constants(self.pipeline.layout().clone(), 0, push)
+            .unwrap();
+        unsafe { builder.draw(self.cube.len() as u32, instances, 0, 0) }.unwrap();
+    }
+}
+
+fn vertex_buffer<T: BufferContents>(
+    allocator: Arc<dyn MemoryAllocator>,
+    data: Vec<T>,
+) -> Subbuffer<[T]> {
+    Buffer::from_iter(
+        allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::VERTEX_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        data,
+    )
+    .unwrap()
+}
+
+fn line_pipeline(
+    layout: Arc<PipelineLayout>,
+    subpass: Subpass,
+    depth: Depth,
+) -> Arc<GraphicsPipeline> {
+    let device = layout.device().clone();
+    let vs = vs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let fs = fs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let vertex_input_state = [LineVertex::per_vertex(), Instance::per_instance()]
+        .definition(&vs)
+        .unwrap();
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState {
+                topology: PrimitiveTopology::LineList,
+                ..Default::default()
+            }),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(depth.state(CompareOp::LessOrEqual, false)),
+                ..Default::default()
+            }),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap()
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 offset;
+
+layout(location = 2) in vec4 model_x;
+layout(location = 3) in vec4 model_y;
+layout(location = 4) in vec4 model_z;
+layout(location = 5) in vec4 model_w;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+    mat4 view_inv;
+} cam;
+layout(push_constant) uniform Line {
+    vec4 color;
+    vec3 box_min;
+    float len;
+    vec3 box_max;
+    int is_box;
+} line;
+
+void main() {
+    mat4 model = mat4(model_x, model_y, model_z, model_w);
+    vec3 pos = line.is_box != 0
+        ? mix(line.box_min, line.box_max, position)
+        : position + offset * line.len;
+    gl_Position = cam.proj * cam.view * model * vec4(pos, 1.0);
+}
+        "#
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+layout(push_constant) uniform Line {
+    vec4 color;
+    vec3 box_min;
+    float len;
+    vec3 box_max;
+    int is_box;
+} line;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    f_color = line.color;
+}
+        "#
+    }
+}
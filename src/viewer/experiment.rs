@@ -0,0 +1,217 @@
+use super::watcher::DEBOUNCE;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        mpsc::{Receiver, channel},
+    },
+    time::Instant,
+};
+use vulkano::{
+    device::Device,
+    shader::{EntryPoint, ShaderModule, ShaderModuleCreateInfo},
+};
+
+/// The shader new experiment files start from.
+const TEMPLATE: &str = include_str!("../../shaders/gltf.frag");
+
+/// A fragment shader on disk that replaces `gltf.frag` while enabled,
+/// compiled again whenever the file is saved.
+pub struct ShaderExperiment {
+    pub enabled: bool,
+    path: PathBuf,
+    /// The path being edited in the UI.
+    input: String,
+    /// `None` if the platform has no file watching.
+    watcher: Option<RecommendedWatcher>,
+    events: Receiver<notify::Result<notify::Event>>,
+    last_change: Option<Instant>,
+    /// The shader has to be compiled on the next poll.
+    dirty: bool,
+    /// Output of the last compile, shown in the console.
+    pub log: String,
+    pub failed: bool,
+}
+impl ShaderExperiment {
+    pub fn new() -> Self {
+        let (sender, events) = channel();
+        let watcher = notify::recommended_watcher(sender)
+            .inspect_err(|err| log::warn!("shader hot reloading is unavailable: {err}"))
+            .ok();
+        let mut experiment = Self {
+            enabled: false,
+            path: PathBuf::new(),
+            input: String::new(),
+            watcher,
+            events,
+            last_change: None,
+            dirty: false,
+            log: String::new(),
+            failed: false,
+        };
+        experiment.set_path(Self::default_path());
+        experiment
+    }
+    /// Next to the settings, or the working directory.
+    pub fn default_path() -> PathBuf {
+        dirs::config_dir()
+            .map(|dir| dir.join("gltf-viewer"))
+            .unwrap_or_default()
+            .join("experiment.frag")
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn set_path(&mut self, path: PathBuf) {
+        if let Some(watcher) = &mut self.watcher {
+            if let Some(dir) = self.path.parent().filter(|dir| dir.is_dir()) {
+                let _ = watcher.unwatch(dir);
+            }
+            if let Some(dir) = path.parent().filter(|dir| dir.is_dir()) {
+                if let Err(err) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    log::warn!("failed to watch {}: {err}", dir.display());
+                }
+            }
+        }
+        self.input = path.display().to_string();
+        self.path = path;
+        self.dirty = true;
+    }
+
+    /// Returns the newly compiled shader once the file was changed and has settled.
+    pub fn poll(&mut self, device: &Arc<Device>) -> Option<EntryPoint> {
+        let file = self.path.canonicalize().ok();
+        for event in self.events.try_iter() {
+            let Ok(event) = event else {
+                continue;
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            if event
+                .paths
+                .iter()
+                .any(|path| path.canonicalize().ok() == file)
+            {
+                self.last_change = Some(Instant::now());
+            }
+        }
+        if self
+            .last_change
+            .is_some_and(|time| time.elapsed() >= DEBOUNCE)
+        {
+            self.last_change = None;
+            self.dirty = true;
+        }
+
+        if !self.enabled || !std::mem::take(&mut self.dirty) {
+            return None;
+        }
+        match compile(device, &self.path) {
+            Ok((shader, warnings)) => {
+                self.log = format!("compiled {}\n{warnings}", self.path.display());
+                self.failed = false;
+                Some(shader)
+            }
+            Err(err) => {
+                self.report(err);
+                None
+            }
+        }
+    }
+    /// Shows an error in the console, the last working shader stays in use.
+    pub fn report(&mut self, err: String) {
+        self.log = err;
+        self.failed = true;
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .checkbox(&mut self.enabled, "Experiment shader")
+            .on_hover_text("Shade the models with a fragment shader from disk instead of gltf.frag")
+            .changed()
+        {
+            self.dirty = true;
+        }
+        ui.horizontal(|ui| {
+            let edited = ui.text_edit_singleline(&mut self.input).lost_focus();
+            if edited && Path::new(&self.input) != self.path {
+                self.set_path(self.input.clone().into());
+            }
+            if ui.button("Recompile").clicked() {
+                self.dirty = true;
+            }
+        });
+        if !self.path.exists()
+            && ui
+                .button("Create from gltf.frag")
+                .on_hover_text("Start from a copy of the built in shader")
+                .clicked()
+        {
+            match self.create() {
+                Ok(()) => self.set_path(self.path.clone()),
+                Err(err) => self.report(format!("failed to create {}: {err}", self.path.display())),
+            }
+        }
+        if !self.log.is_empty() {
+            let color = if self.failed {
+                ui.visuals().error_fg_color
+            } else {
+                ui.visuals().weak_text_color()
+            };
+            egui::ScrollArea::vertical()
+                .id_salt("experiment_log")
+                .max_height(150.0)
+                .show(ui, |ui| {
+                    ui.label(egui::RichText::new(&self.log).monospace().color(color));
+                });
+        }
+    }
+    fn create(&self) -> std::io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, TEMPLATE)
+    }
+}
+
+/// Compiles the GLSL fragment shader at `path`, includes are relative to its directory.
+/// Returns the entry point and the compiler warnings, or the errors as text.
+fn compile(device: &Arc<Device>, path: &Path) -> Result<(EntryPoint, String), String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+    let compiler = shaderc::Compiler::new().map_err(|err| err.to_string())?;
+    let mut options = shaderc::CompileOptions::new().map_err(|err| err.to_string())?;
+    let dir = path.parent().unwrap_or(Path::new("")).to_owned();
+    options.set_include_callback(move |name, _, _, _| {
+        let include = dir.join(name);
+        std::fs::read_to_string(&include)
+            .map(|content| shaderc::ResolvedInclude {
+                resolved_name: include.display().to_string(),
+                content,
+            })
+            .map_err(|err| format!("{}: {err}", include.display()))
+    });
+    let name = path.display().to_string();
+    let artifact = compiler
+        .compile_into_spirv(
+            &source,
+            shaderc::ShaderKind::Fragment,
+            &name,
+            "main",
+            Some(&options),
+        )
+        .map_err(|err| err.to_string())?;
+
+    let shader = unsafe {
+        ShaderModule::new(
+            device.clone(),
+            ShaderModuleCreateInfo::new(artifact.as_binary()),
+        )
+    }
+    .map_err(|err| format!("invalid SPIR-V: {err}"))?;
+    let entry_point = shader.entry_point("main").ok_or("no main function")?;
+    Ok((entry_point, artifact.get_warning_messages()))
+}
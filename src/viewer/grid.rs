@@ -0,0 +1,274 @@
+use crate::frameinfo::Depth;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use vulkano::{
+    buffer::BufferContents,
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor_set::{DescriptorSet, layout::DescriptorSetLayout},
+    device::DeviceOwned,
+    image::SampleCount,
+    pipeline::{
+        DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+        graphics::{
+            GraphicsPipelineCreateInfo,
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{CompareOp, DepthStencilState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::VertexInputState,
+            viewport::ViewportState,
+        },
+        layout::{PipelineLayoutCreateInfo, PushConstantRange},
+    },
+    render_pass::Subpass,
+    shader::ShaderStages,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GridSettings {
+    pub enabled: bool,
+    /// Distance between grid lines, every tenth line is highlighted.
+    pub spacing: f32,
+    pub opacity: f32,
+}
+impl Default for GridSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            spacing: 1.0,
+            opacity: 0.5,
+        }
+    }
+}
+impl GridSettings {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Ground grid");
+        ui.add_enabled_ui(self.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut self.spacing)
+                        .range(0.001..=1000.0)
+                        .speed(0.01),
+                );
+                ui.label("Spacing");
+            });
+            ui.add(egui::Slider::new(&mut self.opacity, 0.0..=1.0).text("Opacity"));
+        });
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+struct GridPush {
+    spacing: f32,
+    opacity: f32,
+}
+
+/// An infinite grid on the ground plane (y = 0), drawn after the scene so it blends over the sky.
+#[derive(Clone)]
+pub struct Grid {
+    pipeline: Arc<GraphicsPipeline>,
+    pub settings: GridSettings,
+}
+impl Grid {
+    pub fn new(camera_layout: Arc<DescriptorSetLayout>, subpass: Subpass, depth: Depth) -> Self {
+        let device = camera_layout.device().clone();
+        let layout = PipelineLayout::new(
+            device,
+            PipelineLayoutCreateInfo {
+                set_layouts: vec![camera_layout],
+                push_constant_ranges: vec![PushConstantRange {
+                    stages: ShaderStages::FRAGMENT,
+                    offset: 0,
+                    size: std::mem::size_of::<GridPush>() as u32,
+                }],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        Self {
+            pipeline: grid_pipeline(layout, subpass, depth),
+            settings: GridSettings::default(),
+        }
+    }
+    /// Rebuilds the pipeline for a new main render pass.
+    pub fn set_subpass(&mut self, subpass: Subpass, depth: Depth) {
+        self.pipeline = grid_pipeline(self.pipeline.layout().clone(), subpass, depth);
+    }
+    pub fn render<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        camera_set: Arc<DescriptorSet>,
+    ) {
+        if !self.settings.enabled {
+            return;
+        }
+        let layout = self.pipeline.layout().clone();
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, layout.clone(), 0, camera_set)
+            .unwrap()
+            .push_constants(
+                layout,
+                0,
+                GridPush {
+                    spacing: self.settings.spacing,
+                    opacity: self.settings.opacity,
+                },
+            )
+            .unwrap();
+        unsafe { builder.draw(3, 1, 0, 0) }.unwrap();
+    }
+}
+
+fn grid_pipeline(
+    layout: Arc<PipelineLayout>,
+    subpass: Subpass,
+    depth: Depth,
+) -> Arc<GraphicsPipeline> {
+    let device = layout.device().clone();
+    let vs = vs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let fs = fs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(VertexInputState::default()),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend: Some(AttachmentBlend::alpha()),
+                    ..Default::default()
+                },
+            )),
+            // the fragment shader writes the depth of the ground plane
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(depth.state(CompareOp::Less, false)),
+                ..Default::default()
+            }),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap()
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+#version 450
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+    mat4 view_inv;
+} cam;
+
+layout(location = 0) out vec3 v_near;
+layout(location = 1) out vec3 v_far;
+
+vec3 unproject(vec2 xy, float depth) {
+    vec4 world = inverse(cam.proj * cam.view) * vec4(xy, depth, 1.0);
+    return world.xyz / world.w;
+}
+
+void main() {
+    // one triangle covering the screen
+    vec2 xy = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+    v_near = unproject(xy, 0.0);
+    v_far = unproject(xy, 1.0);
+    gl_Position = vec4(xy, 0.0, 1.0);
+}
+        "#
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec3 v_near;
+layout(location = 1) in vec3 v_far;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+    mat4 view_inv;
+} cam;
+layout(push_constant) uniform Grid {
+    float spacing;
+    float opacity;
+} grid;
+
+layout(location = 0) out vec4 f_color;
+
+// 1 on a line of the grid with cells of size `cell`, fading to 0 within a pixel
+float lines(vec2 position, float cell) {
+    vec2 coord = position / cell;
+    vec2 width = fwidth(coord);
+    vec2 line = abs(fract(coord - 0.5) - 0.5) / width;
+    return 1.0 - min(min(line.x, line.y), 1.0);
+}
+
+void main() {
+    float t = -v_near.y / (v_far.y - v_near.y);
+    if (t <= 0.0 || t >= 1.0) {
+        discard;
+    }
+    vec3 position = v_near + t * (v_far - v_near);
+    vec4 clip = cam.proj * cam.view * vec4(position, 1.0);
+    gl_FragDepth = clip.z / clip.w;
+
+    float minor = lines(position.xz, grid.spacing);
+    float major = lines(position.xz, grid.spacing * 10.0);
+    vec3 color = vec3(0.5);
+    float alpha = max(minor * 0.4, major);
+
+    // the x axis is red and the z axis blue
+    vec2 axis = abs(position.zx) / fwidth(position.zx);
+    if (axis.x < 1.0) {
+        color = vec3(0.9, 0.2, 0.2);
+        alpha = 1.0;
+    } else if (axis.y < 1.0) {
+        color = vec3(0.2, 0.3, 0.9);
+        alpha = 1.0;
+    }
+
+    // fade out towards the horizon where the lines would alias
+    vec3 eye = cam.view_inv[3].xyz;
+    float range = grid.spacing * 200.0;
+    float fade = 1.0 - smoothstep(0.25 * range, range, distance(position.xz, eye.xz));
+    f_color = vec4(color, alpha * fade * grid.opacity);
+}
+        "#
+    }
+}
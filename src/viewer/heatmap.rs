@@ -0,0 +1,514 @@
+use crate::{
+    frameinfo::Depth,
+    progress::panic_message,
+    stats::DrawStats,
+    vktf::{
+        GltfRenderInfo,
+        deviation::{self, Deviation, DeviationError, PlacedModel},
+        loader::{PrimitiveVertex, VktfDocument},
+        mesh::Instance,
+    },
+};
+use std::{sync::Arc, thread::JoinHandle};
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor_set::{DescriptorSet, layout::DescriptorSetLayout},
+    device::DeviceOwned,
+    image::SampleCount,
+    memory::allocator::{AllocationCreateInfo, MemoryAllocator, MemoryTypeFilter},
+    pipeline::{
+        DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+        graphics::{
+            GraphicsPipelineCreateInfo,
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{CompareOp, DepthStencilState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::ViewportState,
+        },
+        layout::{PipelineLayoutCreateInfo, PushConstantRange},
+    },
+    render_pass::Subpass,
+    shader::ShaderStages,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeatmapMetric {
+    #[default]
+    Distance = 0,
+    NormalAngle,
+}
+impl HeatmapMetric {
+    pub const ALL: [HeatmapMetric; 2] = [HeatmapMetric::Distance, HeatmapMetric::NormalAngle];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HeatmapMetric::Distance => "Distance",
+            HeatmapMetric::NormalAngle => "Normal angle",
+        }
+    }
+}
+
+/// The deviation of a vertex of the candidate, bound next to its vertices.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, BufferContents, Vertex)]
+pub struct DeviationVertex {
+    /// Distance and normal angle in degrees.
+    #[format(R32G32_SFLOAT)]
+    pub deviation: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+struct HeatmapPush {
+    metric: i32,
+    /// The deviation shown in red.
+    range: f32,
+}
+
+/// A measured candidate and the reference it was measured against.
+struct Measured {
+    reference: Arc<VktfDocument>,
+    candidate: Arc<VktfDocument>,
+    vertices: Subbuffer<[DeviationVertex]>,
+    max: [f32; 2],
+    mean: [f32; 2],
+}
+
+/// Compares a candidate model to a reference, like a model before and after lossy compression,
+/// and draws the candidate coloured by how far its vertices moved from the reference surface
+/// or how much their normals turned.
+pub struct Heatmap {
+    pipeline: Arc<GraphicsPipeline>,
+    allocator: Arc<dyn MemoryAllocator>,
+    /// Model indices, picked in the UI.
+    pub reference: usize,
+    pub candidate: usize,
+    pub shown: bool,
+    pub metric: HeatmapMetric,
+    /// Fraction of the largest measured deviation that is shown in red.
+    pub range: f32,
+    job: Option<JoinHandle<Result<Deviation, DeviationError>>>,
+    /// The documents of the models being measured.
+    measuring: Option<(Arc<VktfDocument>, Arc<VktfDocument>)>,
+    measured: Option<Measured>,
+}
+impl Heatmap {
+    pub fn new(
+        allocator: Arc<dyn MemoryAllocator>,
+        camera_layout: Arc<DescriptorSetLayout>,
+        subpass: Subpass,
+        depth: Depth,
+    ) -> Self {
+        let device = camera_layout.device().clone();
+        let layout = PipelineLayout::new(
+            device,
+            PipelineLayoutCreateInfo {
+                set_layouts: vec![camera_layout],
+                push_constant_ranges: vec![PushConstantRange {
+                    stages: ShaderStages::FRAGMENT,
+                    offset: 0,
+                    size: std::mem::size_of::<HeatmapPush>() as u32,
+                }],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        Self {
+            pipeline: heatmap_pipeline(layout, subpass, depth),
+            allocator,
+            reference: 0,
+            candidate: 1,
+            shown: false,
+            metric: HeatmapMetric::default(),
+            range: 1.0,
+            job: None,
+            measuring: None,
+            measured: None,
+        }
+    }
+    /// Rebuilds the pipeline for a new main render pass.
+    pub fn set_subpass(&mut self, subpass: Subpass, depth: Depth) {
+        self.pipeline = heatmap_pipeline(self.pipeline.layout().clone(), subpass, depth);
+    }
+    pub fn measuring(&self) -> bool {
+        self.job.is_some()
+    }
+    /// Measures the picked candidate against the picked reference on another thread.
+    pub fn measure(&mut self, models: &[GltfRenderInfo]) {
+        let (Some(reference), Some(candidate)) =
+            (models.get(self.reference), models.get(self.candidate))
+        else {
+            return;
+        };
+        self.measuring = Some((reference.vktf.clone(), candidate.vktf.clone()));
+        let (reference, candidate) = (PlacedModel::new(reference), PlacedModel::new(candidate));
+        self.job = Some(std::thread::spawn(move || {
+            deviation::measure(&reference, &candidate)
+        }));
+    }
+    /// Uploads the deviation once it is measured.
+    pub fn update(&mut self, errors: &mut Vec<String>) {
+        let Some(result) = self
+            .job
+            .take_if(|job| job.is_finished())
+            .map(|job| job.join())
+        else {
+            return;
+        };
+        let (reference, candidate) = self.measuring.take().unwrap();
+        match result {
+            Ok(Ok(deviation)) => {
+                let vertices = Buffer::from_iter(
+                    self.allocator.clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::VERTEX_BUFFER,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                            | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                        ..Default::default()
+                    },
+                    deviation
+                        .vertices
+                        .into_iter()
+                        .map(|deviation| DeviationVertex { deviation }),
+                );
+                match vertices {
+                    Ok(vertices) => {
+                        self.measured = Some(Measured {
+                            reference,
+                            candidate,
+                            vertices,
+                            max: deviation.max,
+                            mean: deviation.mean,
+                        });
+                        self.shown = true;
+                    }
+                    Err(err) => errors.push(format!("Failed to upload the deviation: {err}")),
+                }
+            }
+            Ok(Err(err)) => errors.push(format!("Failed to measure the deviation: {err}")),
+            Err(panic) => errors.push(format!(
+                "Failed to measure the deviation: {}",
+                panic_message(panic)
+            )),
+        }
+    }
+
+    /// Takes the compared models out of `models` and returns the candidate drawn as a heatmap,
+    /// `None` if it isn't shown or either model was reloaded or removed since.
+    pub fn frame(&self, models: &mut Vec<GltfRenderInfo>) -> Option<HeatmapFrame> {
+        let measured = self.measured.as_ref().filter(|_| self.shown)?;
+        let is = |info: &GltfRenderInfo, vktf: &Arc<VktfDocument>| Arc::ptr_eq(&info.vktf, vktf);
+        if !models.iter().any(|info| is(info, &measured.reference)) {
+            return None;
+        }
+        let model = models
+            .iter()
+            .find(|info| is(info, &measured.candidate))?
+            .clone();
+        models.retain(|info| !is(info, &measured.reference) && !is(info, &measured.candidate));
+        let metric = self.metric as usize;
+        Some(HeatmapFrame {
+            pipeline: self.pipeline.clone(),
+            model,
+            vertices: measured.vertices.clone(),
+            push: HeatmapPush {
+                metric: self.metric as i32,
+                range: (measured.max[metric] * self.range).max(f32::MIN_POSITIVE),
+            },
+        })
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, models: &[GltfRenderInfo], loading: bool) {
+        let names: Vec<String> = models
+            .iter()
+            .map(|info| {
+                info.vktf
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            })
+            .collect();
+        model_combo(ui, "Reference", &mut self.reference, &names);
+        model_combo(ui, "Candidate", &mut self.candidate, &names);
+        ui.horizontal(|ui| {
+            let valid = self.reference != self.candidate
+                && self.reference < models.len()
+                && self.candidate < models.len();
+            if ui
+                .add_enabled(
+                    valid && !loading && !self.measuring(),
+                    egui::Button::new("Measure deviation"),
+                )
+                .on_hover_text("Reads both files again and measures the candidate at its vertices")
+                .clicked()
+            {
+                self.measure(models);
+            }
+            if self.measuring() {
+                ui.spinner();
+            }
+        });
+
+        let Some(measured) = &self.measured else {
+            return;
+        };
+        ui.checkbox(&mut self.shown, "Show heatmap");
+        ui.add_enabled_ui(self.shown, |ui| {
+            egui::ComboBox::from_label("Metric")
+                .selected_text(self.metric.name())
+                .show_ui(ui, |ui| {
+                    for metric in HeatmapMetric::ALL {
+                        ui.selectable_value(&mut self.metric, metric, metric.name());
+                    }
+                });
+            let metric = self.metric;
+            let max = measured.max[metric as usize] as f64;
+            ui.add(
+                egui::Slider::new(&mut self.range, 0.001..=1.0)
+                    .logarithmic(true)
+                    .text("Range")
+                    .custom_formatter(|range, _| format_deviation(metric, (range * max) as f32)),
+            )
+            .on_hover_text("The deviation shown in red");
+            legend(ui);
+        });
+        egui::Grid::new("deviation").show(ui, |ui| {
+            ui.label("");
+            ui.label("Largest");
+            ui.label("Mean");
+            ui.end_row();
+            for metric in HeatmapMetric::ALL {
+                let i = metric as usize;
+                ui.label(metric.name());
+                ui.label(format_deviation(metric, measured.max[i]));
+                ui.label(format_deviation(metric, measured.mean[i]));
+                ui.end_row();
+            }
+        });
+    }
+}
+
+/// The candidate of a [`Heatmap`] for one frame.
+#[derive(Clone)]
+pub struct HeatmapFrame {
+    pipeline: Arc<GraphicsPipeline>,
+    model: GltfRenderInfo,
+    vertices: Subbuffer<[DeviationVertex]>,
+    push: HeatmapPush,
+}
+impl HeatmapFrame {
+    pub fn render<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        camera_set: Arc<DescriptorSet>,
+        frame: usize,
+    ) -> DrawStats {
+        let layout = self.pipeline.layout().clone();
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, layout.clone(), 0, camera_set)
+            .unwrap()
+            .push_constants(layout, 0, self.push)
+            .unwrap();
+
+        let mut stats = DrawStats::default();
+        for mesh in &self.model.meshes {
+            let instances = mesh.instance_count();
+            mesh.bind_instances(builder, frame);
+            mesh.bind_geometry(builder);
+            // parallel to the geometry buffer, so the vertex offsets of the primitives fit
+            builder
+                .bind_vertex_buffers(2, self.vertices.clone())
+                .unwrap();
+            for (_, primitive) in mesh.primitives() {
+                primitive.draw(instances, builder);
+                stats += DrawStats::draw(primitive.triangles(), instances);
+            }
+        }
+        stats
+    }
+}
+
+fn model_combo(ui: &mut egui::Ui, label: &str, index: &mut usize, names: &[String]) {
+    egui::ComboBox::from_label(label)
+        .selected_text(names.get(*index).map_or("", String::as_str))
+        .show_ui(ui, |ui| {
+            for (i, name) in names.iter().enumerate() {
+                ui.selectable_value(index, i, format!("{i}: {name}"));
+            }
+        });
+}
+
+fn format_deviation(metric: HeatmapMetric, value: f32) -> String {
+    match metric {
+        HeatmapMetric::Distance => format!("{value:.5} m"),
+        HeatmapMetric::NormalAngle => format!("{value:.2}°"),
+    }
+}
+
+/// The colour of a deviation relative to the range, the same ramp as the fragment shader.
+fn ramp(t: f32) -> egui::Color32 {
+    let t = t.clamp(0.0, 1.0) * 4.0;
+    let green = if t < 2.0 { t } else { 4.0 - t };
+    let [r, g, b] = [t - 2.0, green, 2.0 - t].map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8);
+    egui::Color32::from_rgb(r, g, b)
+}
+
+/// The colours from no deviation to the range.
+fn legend(ui: &mut egui::Ui) {
+    let (rect, _) =
+        ui.allocate_exact_size(egui::vec2(ui.available_width(), 12.0), egui::Sense::hover());
+    let steps = 32;
+    let width = rect.width() / steps as f32;
+    for i in 0..steps {
+        let x = rect.left() + i as f32 * width;
+        let step = egui::Rect::from_x_y_ranges(x..=x + width, rect.y_range());
+        ui.painter()
+            .rect_filled(step, 0.0, ramp((i as f32 + 0.5) / steps as f32));
+    }
+}
+
+fn heatmap_pipeline(
+    layout: Arc<PipelineLayout>,
+    subpass: Subpass,
+    depth: Depth,
+) -> Arc<GraphicsPipeline> {
+    let device = layout.device().clone();
+    let vs = vs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let fs = fs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let vertex_input_state = [
+        PrimitiveVertex::per_vertex(),
+        Instance::per_instance(),
+        DeviationVertex::per_vertex(),
+    ]
+    .definition(&vs)
+    .unwrap();
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(depth.state(CompareOp::Less, true)),
+                ..Default::default()
+            }),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap()
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+
+layout(location = 2) in vec4 model_x;
+layout(location = 3) in vec4 model_y;
+layout(location = 4) in vec4 model_z;
+layout(location = 5) in vec4 model_w;
+
+layout(location = 6) in vec2 deviation;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+    mat4 view_inv;
+} cam;
+
+layout(location = 0) out vec3 v_position;
+layout(location = 1) out vec3 v_normal;
+layout(location = 2) out vec2 v_deviation;
+
+void main() {
+    mat4 model = mat4(model_x, model_y, model_z, model_w);
+    vec4 world = model * vec4(position, 1.0);
+    gl_Position = cam.proj * cam.view * world;
+    v_position = world.xyz;
+    v_normal = mat3(transpose(inverse(model))) * normal;
+    v_deviation = deviation;
+}
+        "#
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec3 v_position;
+layout(location = 1) in vec3 v_normal;
+layout(location = 2) in vec2 v_deviation;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+    mat4 view_inv;
+} cam;
+layout(push_constant) uniform Heatmap {
+    int metric;
+    float range;
+} heatmap;
+
+layout(location = 0) out vec4 f_color;
+
+// blue, cyan, green, yellow, red
+vec3 ramp(float t) {
+    t = clamp(t, 0.0, 1.0) * 4.0;
+    return clamp(vec3(t - 2.0, t < 2.0 ? t : 4.0 - t, 2.0 - t), 0.0, 1.0);
+}
+
+void main() {
+    float value = heatmap.metric == 0 ? v_deviation.x : v_deviation.y;
+    vec3 view_dir = normalize(cam.view_inv[3].xyz - v_position);
+    // lit from the camera so the shape stays readable
+    float light = 0.4 + 0.6 * abs(dot(normalize(v_normal), view_dir));
+    f_color = vec4(ramp(value / heatmap.range) * light, 1.0);
+}
+        "#
+    }
+}
@@ -1,32 +1,448 @@
 use crate::{
     Allocators,
-    vktf::{GltfRenderInfo, loader::VktfDocument},
+    memory::MemoryCategory,
+    progress::ProgressSender,
+    vktf::{
+        GltfRenderInfo,
+        cache::VktfCache,
+        loader::{
+            DecodedImage, DiskCache, GeometryOptions, LoadContext, LoadGltfError, ModelSource,
+            RemoteAccess, TextureOptions, Uploader, UriResolver, Vktf, VktfDocument,
+            load_texture_file,
+        },
+        lod::ModelLod,
+        material::{Material, TextureSlot, uses_image},
+    },
+};
+use rayon::prelude::*;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex, mpsc::Sender},
 };
-use std::{path::Path, sync::Arc};
 use vulkano::{
-    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    Validated, VulkanError,
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer,
+        PrimaryCommandBufferAbstract,
+    },
     descriptor_set::layout::DescriptorSetLayout,
+    device::{Device, DeviceOwned, Queue},
+    memory::allocator::MemoryAllocator,
+    sync::GpuFuture,
 };
 
+/// Sent by the loader thread as the model becomes available.
+pub enum LoadEvent {
+    /// The geometry is uploaded, materials use default textures for now.
+    Scene(GltfRenderInfo),
+    /// A material whose textures finished streaming in.
+    Material(usize, Material),
+    /// All images are uploaded.
+    Textures(Arc<VktfDocument>),
+}
+
+/// Size of the largest mips uploaded first when streaming.
+const PREVIEW_SIZE: u32 = 256;
+
+/// How much of the screen each material covers, updated by the viewer while textures stream in.
+pub type MaterialPriorities = Arc<Mutex<Vec<f32>>>;
+
+enum Streamed {
+    Loaded,
+    Failed(LoadGltfError),
+    /// The viewer stopped listening.
+    Closed,
+}
+
 #[derive(Clone)]
 pub struct ViewerLoader {
     pub allocators: Allocators,
     pub material_set_layout: Arc<DescriptorSetLayout>,
+    pub morph_set_layout: Arc<DescriptorSetLayout>,
+    pub num_frames: usize,
+    pub texture_options: TextureOptions,
+    pub geometry_options: GeometryOptions,
+    /// A queue of another family that records the texture copies, so big loads don't stall
+    /// rendering. `None` uploads everything on the graphics queue.
+    pub transfer_queue: Option<Arc<Queue>>,
+    /// Shared by every load so models reuse each other's samplers and descriptor sets.
+    pub cache: VktfCache,
+    /// Converted geometry and images of models opened before.
+    pub disk_cache: DiskCache,
+    /// Hosts the user allowed models to download from.
+    pub remote: RemoteAccess,
 }
 impl ViewerLoader {
+    /// Uploads the geometry first and then streams in the images one by one.
+    /// When streaming, only the small mips of every image are uploaded first, then the larger
+    /// ones are copied into the same images, ordered by the `priorities` of the materials
+    /// using them. Cancelling stops between images, what was loaded so far stays.
+    /// An image that fails to load is skipped, the first such error is returned at the end.
     pub fn load(
         &self,
-        path: impl AsRef<Path>,
-        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
-    ) -> gltf::Result<GltfRenderInfo> {
-        let vktf_document = VktfDocument::new(self.allocators.mem.clone(), builder, path)?;
+        source: &ModelSource,
+        queue: Arc<Queue>,
+        events: Sender<LoadEvent>,
+        priorities: MaterialPriorities,
+        progress: &ProgressSender,
+    ) -> Result<(), LoadGltfError> {
+        progress.report("Parsing", 0.0);
+        let mut builder = self.builder(&queue)?;
+        let (mut vktf_document, buffers) = VktfDocument::open(
+            self.allocators.memory.allocator(MemoryCategory::Geometry),
+            &mut builder,
+            source,
+            self.context(Some(progress)),
+        )?;
+        progress.report("Uploading buffers", 0.1);
+        submit(builder, queue.clone())?;
+        if progress.cancelled() {
+            return Ok(());
+        }
 
+        progress.report("Building materials", 0.2);
         let info = GltfRenderInfo::new_default(
-            self.allocators.mem.clone(),
+            self.allocators.memory.allocator(MemoryCategory::Geometry),
             self.allocators.set.clone(),
             self.material_set_layout.clone(),
-            vktf_document,
+            self.morph_set_layout.clone(),
+            self.num_frames,
+            vktf_document.clone(),
+            &self.cache,
         );
-        Ok(info)
+        if events.send(LoadEvent::Scene(info)).is_err() {
+            return Ok(());
+        }
+
+        let (preview, first_progress) = if self.texture_options.stream {
+            (Some(PREVIEW_SIZE), 0.35)
+        } else {
+            (None, 0.7)
+        };
+
+        // images are decoded a thread pool's worth at a time, then uploaded one by one
+        let batch = rayon::current_num_threads().max(1);
+        let device = queue.device().clone();
+        let mut image_error = None;
+        // decoded images whose larger mips are left to upload
+        let mut pending = vec![];
+        // duplicates of another image share its upload
+        let indices: Vec<usize> = vktf_document.vktf.unique_images().collect();
+        let num_images = indices.len();
+        for (i, chunk) in indices.chunks(batch).enumerate() {
+            if progress.cancelled() {
+                return Ok(());
+            }
+            let done = i * batch;
+            progress.report(
+                format!("Decoding images {}/{}", done + chunk.len(), num_images),
+                0.3 + first_progress * done as f32 / num_images as f32,
+            );
+            let resolver = vktf_document.resolver(Some(progress));
+            let document = &vktf_document.document;
+            let decoded =
+                self.decode_images(&device, document, chunk, &resolver, &buffers, preview);
+            for decoded in decoded {
+                let decoded = match decoded {
+                    Ok(decoded) => decoded,
+                    Err(err) => {
+                        image_error.get_or_insert(err);
+                        continue;
+                    }
+                };
+                let streamed = self.stream_image(
+                    &mut vktf_document,
+                    &queue,
+                    &events,
+                    decoded.index,
+                    |vktf, allocator, uploader| {
+                        vktf.upload_image(allocator, uploader, &decoded, preview, &self.cache)
+                    },
+                )?;
+                match streamed {
+                    Streamed::Loaded => {}
+                    Streamed::Failed(err) => {
+                        image_error.get_or_insert(err);
+                        continue;
+                    }
+                    Streamed::Closed => return Ok(()),
+                }
+                if preview.is_some_and(|size| decoded.streamed(size)) {
+                    pending.push(decoded);
+                }
+            }
+        }
+
+        // the larger mips are added to the images, the ones on screen first
+        let num_pending = pending.len();
+        while !pending.is_empty() {
+            if progress.cancelled() {
+                return Ok(());
+            }
+            let done = num_pending - pending.len();
+            progress.report(
+                format!("Streaming textures {}/{}", done + 1, num_pending),
+                0.65 + 0.35 * done as f32 / num_pending as f32,
+            );
+            let decoded = pending.remove(next_image(&vktf_document, &pending, &priorities));
+            let streamed = self.stream_image(
+                &mut vktf_document,
+                &queue,
+                &events,
+                decoded.index,
+                |vktf, allocator, uploader| {
+                    vktf.upload_remaining_mips(
+                        allocator,
+                        uploader,
+                        &decoded,
+                        PREVIEW_SIZE,
+                        &self.cache,
+                    )
+                },
+            )?;
+            match streamed {
+                Streamed::Loaded => {}
+                Streamed::Failed(err) => {
+                    image_error.get_or_insert(err);
+                }
+                Streamed::Closed => return Ok(()),
+            }
+        }
+
+        progress.report("Done", 1.0);
+        let _ = events.send(LoadEvent::Textures(Arc::new(vktf_document)));
+        image_error.map_or(Ok(()), Err)
     }
+
+    /// Records the upload of image `index` with `upload`, then sends the materials using it.
+    fn stream_image(
+        &self,
+        vktf_document: &mut VktfDocument,
+        queue: &Arc<Queue>,
+        events: &Sender<LoadEvent>,
+        index: usize,
+        upload: impl FnOnce(
+            &mut Vktf,
+            Arc<dyn MemoryAllocator>,
+            &mut Uploader<PrimaryAutoCommandBuffer>,
+        ) -> Result<(), LoadGltfError>,
+    ) -> Result<Streamed, Validated<VulkanError>> {
+        let mut builder = self.builder(queue)?;
+        let allocator = self.allocators.memory.allocator(MemoryCategory::Textures);
+        let loaded = if let Some(transfer_queue) = &self.transfer_queue {
+            let mut transfer = self.builder(transfer_queue)?;
+            let loaded = upload(
+                &mut vktf_document.vktf,
+                allocator,
+                &mut Uploader::split(
+                    &mut transfer,
+                    transfer_queue.queue_family_index(),
+                    &mut builder,
+                    queue.queue_family_index(),
+                ),
+            );
+            submit_transfer(transfer, transfer_queue.clone(), builder, queue.clone())?;
+            loaded
+        } else {
+            let loaded = upload(
+                &mut vktf_document.vktf,
+                allocator,
+                &mut Uploader::new(&mut builder),
+            );
+            submit(builder, queue.clone())?;
+            loaded
+        };
+        if let Err(err) = loaded {
+            return Ok(Streamed::Failed(err));
+        }
+
+        for material in vktf_document.document.materials() {
+            if !uses_copy(vktf_document, &material, index) {
+                continue;
+            }
+            let new = Material::new(
+                &material,
+                self.allocators.set.clone(),
+                self.material_set_layout.clone(),
+                &vktf_document.vktf,
+                &self.cache,
+            );
+            if events
+                .send(LoadEvent::Material(material.index().unwrap(), new))
+                .is_err()
+            {
+                return Ok(Streamed::Closed);
+            }
+        }
+        Ok(Streamed::Loaded)
+    }
+
+    /// Simplifies the geometry of `vktf` and waits for it to be uploaded.
+    pub fn simplify(
+        &self,
+        vktf: &VktfDocument,
+        ratio: f32,
+        queue: Arc<Queue>,
+    ) -> Result<ModelLod, LoadGltfError> {
+        let mut builder = self.builder(&queue)?;
+        let (meshes, error) = vktf.simplify(
+            self.allocators.memory.allocator(MemoryCategory::Geometry),
+            &mut builder,
+            ratio,
+            self.context(None),
+        )?;
+        submit(builder, queue)?;
+        Ok(ModelLod {
+            ratio,
+            error,
+            meshes,
+        })
+    }
+
+    /// Uploads the image at `path` and draws it in `slot` of `material`.
+    pub fn replace_texture(
+        &self,
+        material: &mut Material,
+        slot: TextureSlot,
+        path: &Path,
+        queue: Arc<Queue>,
+    ) -> Result<(), LoadGltfError> {
+        let mut builder = self.builder(&queue)?;
+        let view = load_texture_file(
+            self.allocators.memory.allocator(MemoryCategory::Textures),
+            &mut builder,
+            path,
+            slot.kind(),
+            self.texture_options,
+            &self.cache,
+        )?;
+        submit(builder, queue)?;
+        material.replace_texture(
+            slot,
+            view,
+            self.allocators.set.clone(),
+            self.material_set_layout.clone(),
+            &self.cache,
+        );
+        Ok(())
+    }
+
+    /// Decodes, resizes and compresses the images at `indices` in parallel, in the same order.
+    /// Images prepared before come from the disk cache. With a `preview` size the mips
+    /// uploaded first are downsampled as well.
+    fn decode_images(
+        &self,
+        device: &Device,
+        document: &gltf::Document,
+        indices: &[usize],
+        resolver: &UriResolver,
+        buffers: &[gltf::buffer::Data],
+        preview: Option<u32>,
+    ) -> Vec<Result<DecodedImage, LoadGltfError>> {
+        indices
+            .par_iter()
+            .map(|&index| {
+                let mut decoded = DecodedImage::load(
+                    device,
+                    document,
+                    index,
+                    resolver,
+                    buffers,
+                    self.texture_options,
+                    &self.disk_cache,
+                )?;
+                if let Some(size) = preview {
+                    decoded.prepare_preview(size);
+                }
+                Ok(decoded)
+            })
+            .collect()
+    }
+
+    fn context<'a>(&'a self, progress: Option<&'a ProgressSender>) -> LoadContext<'a> {
+        LoadContext {
+            cache: &self.cache,
+            disk_cache: &self.disk_cache,
+            remote: &self.remote,
+            progress,
+            anisotropy: self.texture_options.anisotropy,
+            geometry: self.geometry_options,
+        }
+    }
+
+    fn builder(
+        &self,
+        queue: &Queue,
+    ) -> Result<AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, Validated<VulkanError>> {
+        AutoCommandBufferBuilder::primary(
+            self.allocators.cmd.clone(),
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+    }
+}
+
+/// Runs the copies on the transfer queue and `graphics` on `queue` once they are done.
+fn submit_transfer(
+    transfer: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    transfer_queue: Arc<Queue>,
+    graphics: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    queue: Arc<Queue>,
+) -> Result<(), Validated<VulkanError>> {
+    transfer
+        .build()?
+        .execute(transfer_queue)
+        .map_err(Validated::ValidationError)?
+        .then_signal_semaphore_and_flush()?
+        .then_execute(queue, graphics.build()?)
+        .map_err(Validated::ValidationError)?
+        .then_signal_fence_and_flush()?
+        .wait(None)
+}
+
+/// Whether `material` reads image `index` or one of its duplicates.
+fn uses_copy(vktf_document: &VktfDocument, material: &gltf::Material, index: usize) -> bool {
+    vktf_document
+        .vktf
+        .image_copies(index)
+        .any(|copy| uses_image(material, copy))
+}
+
+/// The position in `pending` of the image used by the materials covering most of the screen.
+fn next_image(
+    vktf_document: &VktfDocument,
+    pending: &[DecodedImage],
+    priorities: &MaterialPriorities,
+) -> usize {
+    let priorities = priorities.lock().unwrap().clone();
+    let priority = |index: usize| {
+        vktf_document
+            .document
+            .materials()
+            .filter(|material| uses_copy(vktf_document, material, index))
+            .filter_map(|material| priorities.get(material.index()?).copied())
+            .fold(0.0, f32::max)
+    };
+    let mut next = 0;
+    let mut best = priority(pending[0].index);
+    for (i, decoded) in pending.iter().enumerate().skip(1) {
+        let priority = priority(decoded.index);
+        if priority > best {
+            next = i;
+            best = priority;
+        }
+    }
+    next
+}
+
+fn submit(
+    builder: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    queue: Arc<Queue>,
+) -> Result<(), Validated<VulkanError>> {
+    builder
+        .build()?
+        .execute(queue)
+        .map_err(Validated::ValidationError)?
+        .then_signal_fence_and_flush()?
+        .wait(None)
 }
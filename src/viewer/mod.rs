@@ -1,21 +1,90 @@
-use crate::{Allocators, set_layouts::SetLayouts, vktf::GltfRenderInfo};
-use loader::ViewerLoader;
+use crate::{
+    Allocators,
+    frameinfo::Depth,
+    memory::MemoryCategory,
+    progress::{ProgressReceiver, panic_message, progress},
+    recovery,
+    set_layouts::SetLayouts,
+    vktf::{
+        GltfRenderInfo, ModelTransform,
+        loader::{LoadGltfError, ModelSource},
+        material::TextureSlot,
+        space::AssetSpace,
+    },
+};
+use debug_geometry::DebugGeometry;
+use experiment::ShaderExperiment;
+use grid::Grid;
+use heatmap::Heatmap;
+use loader::{LoadEvent, MaterialPriorities, ViewerLoader};
+use nalgebra_glm as glm;
+use occlusion::TracedOcclusion;
+use points::Points;
 use renderer::ViewerRenderer;
-use std::{path::PathBuf, sync::Arc, thread::JoinHandle};
+use shadow::Shadows;
+use shadow_catcher::ShadowCatcher;
+use ssao::Ssao;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        mpsc::{Receiver, channel},
+    },
+    thread::JoinHandle,
+};
+use transmission::Transmission;
 use vulkano::{
-    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryCommandBufferAbstract},
-    device::Queue,
+    command_buffer::AutoCommandBufferBuilder,
+    device::{DeviceOwned, Queue},
     render_pass::Subpass,
-    sync::GpuFuture,
 };
+use watcher::ModelWatcher;
 
+pub mod debug_geometry;
+pub mod experiment;
+pub mod grid;
+pub mod heatmap;
 pub mod loader;
+pub mod occlusion;
+pub mod points;
 pub mod renderer;
+pub mod shadow;
+pub mod shadow_catcher;
+pub mod ssao;
+pub mod transmission;
+pub mod watcher;
 
 pub struct Viewer {
     pub renderer: ViewerRenderer,
     pub loader: ViewerLoader,
-    pub job: Option<JoinHandle<GltfRenderInfo>>,
+    pub shadows: Shadows,
+    pub transmission: Transmission,
+    pub occlusion: TracedOcclusion,
+    pub ssao: Ssao,
+    pub grid: Grid,
+    pub shadow_catcher: ShadowCatcher,
+    pub debug_geometry: DebugGeometry,
+    pub points: Points,
+    pub heatmap: Heatmap,
+    pub job: Option<JoinHandle<Result<(), LoadGltfError>>>,
+    events: Option<Receiver<LoadEvent>>,
+    pub progress: Option<ProgressReceiver>,
+    /// `None` if the platform has no file watching.
+    pub watcher: Option<ModelWatcher>,
+    pub experiment: ShaderExperiment,
+    /// The model the loader is streaming into.
+    loading_index: Option<usize>,
+    /// The model being replaced by the current load, if any.
+    reload_index: Option<usize>,
+    /// Decides which textures of the loading model stream in first.
+    priorities: Option<MaterialPriorities>,
+    /// Every model is drawn `replicas`×`replicas` times to stress instancing.
+    replicas: u32,
+    /// Units and up axis of the files that don't use meters and +Y.
+    pub spaces: BTreeMap<PathBuf, AssetSpace>,
+    /// A model that downloads from hosts the user hasn't allowed yet, and those hosts.
+    pub remote_prompt: Option<(PathBuf, Vec<String>)>,
 }
 impl Viewer {
     pub fn new<L>(
@@ -23,59 +92,303 @@ impl Viewer {
         builder: &mut AutoCommandBufferBuilder<L>,
         set_layouts: &SetLayouts,
         subpass: Subpass,
+        depth: Depth,
+        num_frames: usize,
     ) -> Self {
-        let renderer = ViewerRenderer::new(allocators, builder, set_layouts, subpass);
+        let grid = Grid::new(set_layouts.camera.clone(), subpass.clone(), depth);
+        let shadow_catcher = ShadowCatcher::new(
+            set_layouts.camera.clone(),
+            set_layouts.lights.clone(),
+            subpass.clone(),
+            depth,
+        );
+        let debug_geometry = DebugGeometry::new(
+            allocators.memory.allocator(MemoryCategory::Geometry),
+            set_layouts.camera.clone(),
+            subpass.clone(),
+            depth,
+        );
+        let points = Points::new(set_layouts.camera.clone(), subpass.clone(), depth);
+        let heatmap = Heatmap::new(
+            allocators.memory.allocator(MemoryCategory::Geometry),
+            set_layouts.camera.clone(),
+            subpass.clone(),
+            depth,
+        );
+        let renderer = ViewerRenderer::new(allocators, builder, set_layouts, subpass, depth);
+        let shadows = Shadows::new(
+            allocators.memory.allocator(MemoryCategory::RenderTargets),
+            set_layouts.morph.clone(),
+            num_frames,
+        );
+        let transmission = Transmission::new(allocators, set_layouts, num_frames, depth);
+        let occlusion = TracedOcclusion::new(allocators, num_frames);
+        let ssao = Ssao::new(allocators, set_layouts.morph.clone(), num_frames);
         let loader = ViewerLoader {
             allocators: allocators.clone(),
             material_set_layout: set_layouts.material.clone(),
+            morph_set_layout: set_layouts.morph.clone(),
+            num_frames,
+            texture_options: Default::default(),
+            geometry_options: Default::default(),
+            transfer_queue: None,
+            cache: Default::default(),
+            disk_cache: Default::default(),
+            remote: Default::default(),
         };
 
         Self {
             renderer,
             loader,
+            shadows,
+            transmission,
+            occlusion,
+            ssao,
+            grid,
+            shadow_catcher,
+            debug_geometry,
+            points,
+            heatmap,
             job: None,
+            events: None,
+            progress: None,
+            watcher: ModelWatcher::new()
+                .inspect_err(|err| log::warn!("hot reloading is unavailable: {err}"))
+                .ok(),
+            experiment: ShaderExperiment::new(),
+            loading_index: None,
+            reload_index: None,
+            priorities: None,
+            replicas: 1,
+            spaces: BTreeMap::new(),
+            remote_prompt: None,
         }
     }
     pub fn loading(&self) -> bool {
         self.job.is_some()
     }
+    /// Loads another model next to the ones already shown.
     pub fn load(&mut self, path: PathBuf, queue: Arc<Queue>) {
+        self.load_source(ModelSource::File(path), queue);
+    }
+    /// Loads a .gltf or .glb from memory, `name` is shown in the UI.
+    /// Its URIs can only be data URIs or downloads, and it isn't reloaded on changes.
+    pub fn load_bytes(&mut self, name: impl Into<String>, bytes: Arc<[u8]>, queue: Arc<Queue>) {
+        let name = name.into();
+        self.load_source(ModelSource::Bytes { name, bytes }, queue);
+    }
+    fn load_source(&mut self, source: ModelSource, queue: Arc<Queue>) {
         if self.loading() {
             return;
         }
+        self.reload_index = None;
         let loader = self.loader.clone();
+        let (sender, receiver) = channel();
+        let (progress_sender, progress_receiver) = progress();
+        let priorities = MaterialPriorities::default();
+        self.priorities = Some(priorities.clone());
         let job = std::thread::spawn(move || {
-            let mut builder = AutoCommandBufferBuilder::primary(
-                loader.allocators.cmd.clone(),
-                queue.queue_family_index(),
-                CommandBufferUsage::OneTimeSubmit,
-            )
-            .unwrap();
-            let info = loader.load(path, &mut builder).unwrap();
-            let cb = builder.build().unwrap();
-
-            cb.execute(queue)
-                .unwrap()
-                .then_signal_fence_and_flush()
-                .unwrap()
-                .wait(None)
-                .unwrap();
-
-            info
+            loader.load(&source, queue, sender, priorities, &progress_sender)
         });
 
         self.job = Some(job);
+        self.events = Some(receiver);
+        self.progress = Some(progress_receiver);
     }
-    pub fn update(&mut self) -> bool {
-        if let Some(info) = self
+    /// Loads model `index` again in place, keeping its transform.
+    pub fn reload(&mut self, index: usize, queue: Arc<Queue>) {
+        if self.loading() {
+            return;
+        }
+        if let Some(info) = self.renderer.models.get(index) {
+            self.load_source(info.vktf.source(), queue);
+            self.reload_index = Some(index);
+        }
+    }
+    /// Reloads a model if it was changed on disk.
+    pub fn poll_reload(&mut self, queue: Arc<Queue>) {
+        if self.loading() {
+            return;
+        }
+        if let Some(index) = self.watcher.as_mut().and_then(|watcher| watcher.poll()) {
+            log::info!("model {index} changed on disk, reloading");
+            self.reload(index, queue);
+        }
+    }
+    /// Swaps in the experiment shader when it was recompiled, or back to the default one.
+    pub fn poll_experiment(&mut self) {
+        if !self.experiment.enabled {
+            self.renderer.experiment = None;
+        }
+        let device = self.renderer.pipeline.pipeline.device().clone();
+        if let Some(fs) = self.experiment.poll(&device) {
+            if let Err(err) = self.renderer.set_experiment(Some(fs)) {
+                self.experiment
+                    .report(format!("failed to create the pipeline: {err}"));
+            }
+        }
+    }
+    pub fn replicas(&self) -> u32 {
+        self.replicas
+    }
+    pub fn set_replicas(&mut self, replicas: u32) {
+        self.replicas = replicas;
+        for info in &mut self.renderer.models {
+            info.set_replicas(replicas);
+        }
+    }
+    /// Generates simplified geometry for model `index` and draws it instead.
+    pub fn simplify(&mut self, index: usize, queue: Arc<Queue>) -> Result<(), LoadGltfError> {
+        if self.loading() {
+            return Ok(());
+        }
+        let info = &mut self.renderer.models[index];
+        let lod = self.loader.simplify(&info.vktf, info.lod_ratio, queue)?;
+        info.set_lod(lod);
+        Ok(())
+    }
+    /// Replaces a texture of `material` of model `index` with an image file,
+    /// `None` for the default material.
+    pub fn replace_texture(
+        &mut self,
+        index: usize,
+        material: Option<usize>,
+        slot: TextureSlot,
+        path: &Path,
+        queue: Arc<Queue>,
+    ) -> Result<(), LoadGltfError> {
+        if self.loading() {
+            return Ok(());
+        }
+        let Some(info) = self.renderer.models.get_mut(index) else {
+            return Ok(());
+        };
+        let material = match material {
+            Some(material) => &mut info.materials.index[material],
+            None => &mut info.materials.default,
+        };
+        self.loader.replace_texture(material, slot, path, queue)
+    }
+    pub fn remove(&mut self, index: usize) {
+        if self.loading() {
+            return;
+        }
+        self.renderer.models.remove(index);
+        if let Some(watcher) = &mut self.watcher {
+            watcher.clear();
+        }
+        self.watch();
+    }
+    /// Keeps the units and up axis of every model for the next time its file is loaded.
+    pub fn remember_spaces(&mut self) {
+        for info in &self.renderer.models {
+            let space = info.transform().space;
+            let path = &info.vktf.path;
+            if space == AssetSpace::default() {
+                self.spaces.remove(path);
+            } else if self.spaces.get(path) != Some(&space) {
+                self.spaces.insert(path.clone(), space);
+            }
+        }
+    }
+    fn watch(&mut self) {
+        if let Some(watcher) = &mut self.watcher {
+            watcher.watch(
+                self.renderer
+                    .models
+                    .iter()
+                    .map(|info| (info.vktf.path.as_path(), &info.vktf.document)),
+            );
+        }
+    }
+    /// Applies whatever the loader has sent so far, a failed load is added to `errors`.
+    /// Returns `true` when a new model was added, reloads don't count.
+    pub fn update(&mut self, errors: &mut Vec<String>) -> bool {
+        let result = self
             .job
             .take_if(|job| job.is_finished())
-            .map(|job| job.join().unwrap())
+            .map(|job| job.join());
+        if let Some(progress) = &mut self.progress {
+            progress.update();
+        }
+
+        let mut new_model = false;
+        if let Some(events) = self.events.take() {
+            for event in events.try_iter() {
+                match event {
+                    LoadEvent::Scene(mut info) => {
+                        info.set_replicas(self.replicas);
+                        match self.reload_index {
+                            Some(index) => {
+                                info.set_transform(self.renderer.models[index].transform());
+                                self.renderer.models[index] = info;
+                                self.loading_index = Some(index);
+                            }
+                            None => {
+                                if let Some(space) = self.spaces.get(&info.vktf.path) {
+                                    let space = *space;
+                                    info.set_transform(ModelTransform {
+                                        space,
+                                        ..info.transform()
+                                    });
+                                }
+                                self.renderer.models.push(info);
+                                self.loading_index = Some(self.renderer.models.len() - 1);
+                                new_model = true;
+                            }
+                        }
+                        self.watch();
+                    }
+                    LoadEvent::Material(index, material) => {
+                        if let Some(info) = self.loading_model() {
+                            info.materials.index[index].update_textures(material);
+                        }
+                    }
+                    LoadEvent::Textures(vktf) => {
+                        if let Some(info) = self.loading_model() {
+                            info.vktf = vktf;
+                        }
+                    }
+                }
+            }
+            self.events = Some(events);
+        }
+        if let Some(result) = result {
+            self.events = None;
+            self.progress = None;
+            self.loading_index = None;
+            self.reload_index = None;
+            self.priorities = None;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(LoadGltfError::RemoteNotAllowed { path, hosts })) => {
+                    self.remote_prompt = Some((path, hosts));
+                }
+                Ok(Err(err)) if err.is_out_of_memory() => errors.push(format!(
+                    "Failed to load glTF: {err}. {}",
+                    recovery::OUT_OF_MEMORY_HINT
+                )),
+                Ok(Err(err)) => errors.push(format!("Failed to load glTF: {err}")),
+                Err(panic) => errors.push(format!("Failed to load glTF: {}", panic_message(panic))),
+            }
+        }
+
+        new_model
+    }
+    /// Lets the loader stream in the textures that cover the most of the screen first.
+    pub fn prioritize(&self, view_proj: &glm::Mat4) {
+        let Some(priorities) = &self.priorities else {
+            return;
+        };
+        if let Some(info) = self
+            .loading_index
+            .and_then(|index| self.renderer.models.get(index))
         {
-            self.renderer.info = Some(info);
-            true
-        } else {
-            false
+            *priorities.lock().unwrap() = info.material_coverage(view_proj);
         }
     }
+    fn loading_model(&mut self) -> Option<&mut GltfRenderInfo> {
+        self.loading_index
+            .and_then(|index| self.renderer.models.get_mut(index))
+    }
 }
@@ -0,0 +1,439 @@
+use crate::{
+    Allocators,
+    acceleration::{RaytracerCamera, SceneAcceleration},
+    camera::Camera,
+    memory::MemoryCategory,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage},
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor_set::{DescriptorSet, WriteDescriptorSet},
+    device::{Device, DeviceOwned},
+    format::Format,
+    image::{
+        Image, ImageCreateInfo, ImageType, ImageUsage,
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::{
+        ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo, compute::ComputePipelineCreateInfo,
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+    },
+};
+
+const OCCLUSION_FORMAT: Format = Format::R32_SFLOAT;
+
+/// What the hybrid mode traces, the rest of the shading stays rasterized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OcclusionMode {
+    #[default]
+    Off,
+    /// Darkens the environment lighting on top of the material occlusion.
+    AmbientOcclusion,
+    /// Replaces the shadow map of the primary directional light.
+    Shadows,
+}
+impl OcclusionMode {
+    pub const ALL: [Self; 3] = [Self::Off, Self::AmbientOcclusion, Self::Shadows];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::AmbientOcclusion => "Ambient occlusion",
+            Self::Shadows => "Shadows",
+        }
+    }
+    /// The value the fragment shader switches on.
+    pub fn shader_value(&self) -> i32 {
+        match self {
+            Self::Off => 0,
+            Self::AmbientOcclusion => 1,
+            Self::Shadows => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OcclusionSettings {
+    pub mode: OcclusionMode,
+    /// Rays per pixel.
+    pub rays: u32,
+    /// How far ambient occlusion rays look for occluders.
+    pub radius: f32,
+}
+impl Default for OcclusionSettings {
+    fn default() -> Self {
+        Self {
+            mode: OcclusionMode::Off,
+            rays: 4,
+            radius: 1.0,
+        }
+    }
+}
+impl OcclusionSettings {
+    /// `supported` is false if the device has no ray queries.
+    pub fn ui(&mut self, ui: &mut egui::Ui, supported: bool) {
+        ui.add_enabled_ui(supported, |ui| {
+            egui::ComboBox::from_label("Ray traced")
+                .selected_text(self.mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in OcclusionMode::ALL {
+                        ui.selectable_value(&mut self.mode, mode, mode.name());
+                    }
+                })
+                .response
+                .on_disabled_hover_text("Ray queries are not supported by this GPU");
+        });
+        if self.mode == OcclusionMode::Off {
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.rays).range(1..=64));
+            ui.label("Rays");
+        });
+        if self.mode == OcclusionMode::AmbientOcclusion {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut self.radius)
+                        .range(0.01..=100.0)
+                        .speed(0.01),
+                );
+                ui.label("Radius");
+            });
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+struct OcclusionPush {
+    /// Direction the primary directional light shines in.
+    light_direction: [f32; 3],
+    mode: i32,
+    rays: u32,
+    radius: f32,
+}
+
+/// Traces ambient occlusion or shadow rays from the visible surfaces into a screen sized texture
+/// that the glTF pipeline samples.
+pub struct TracedOcclusion {
+    pub settings: OcclusionSettings,
+    /// `None` if the device has no ray queries.
+    pipeline: Option<Arc<ComputePipeline>>,
+    sampler: Arc<Sampler>,
+    /// One target per frame in flight.
+    targets: Vec<Arc<ImageView>>,
+    /// The viewport size in pixels, the targets follow it on [`resize`](Self::resize).
+    pub extent: [u32; 2],
+    allocators: Allocators,
+}
+impl TracedOcclusion {
+    pub fn new(allocators: &Allocators, num_frames: usize) -> Self {
+        let device = allocators.mem.device().clone();
+        let pipeline = device
+            .enabled_extensions()
+            .khr_ray_query
+            .then(|| occlusion_pipeline(device.clone()));
+
+        let sampler = Sampler::new(
+            device,
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let targets = (0..num_frames)
+            .map(|_| new_target(allocators, [1, 1]))
+            .collect();
+
+        Self {
+            settings: OcclusionSettings::default(),
+            pipeline,
+            sampler,
+            targets,
+            extent: [1, 1],
+            allocators: allocators.clone(),
+        }
+    }
+    pub fn supported(&self) -> bool {
+        self.pipeline.is_some()
+    }
+    /// The mode in effect, `Off` if it is unsupported.
+    pub fn mode(&self) -> OcclusionMode {
+        if self.supported() {
+            self.settings.mode
+        } else {
+            OcclusionMode::Off
+        }
+    }
+
+    /// Recreates the targets if the viewport changed size.
+    /// Returns `true` when descriptor sets referencing the targets must be rebuilt.
+    pub fn resize(&mut self) -> bool {
+        let extent = [self.extent[0].max(1), self.extent[1].max(1)];
+        if !self.supported() || self.targets[0].image().extent()[..2] == extent[..] {
+            return false;
+        }
+        for target in &mut self.targets {
+            *target = new_target(&self.allocators, extent);
+        }
+        true
+    }
+
+    pub fn write(&self, binding: u32, index: usize) -> WriteDescriptorSet {
+        WriteDescriptorSet::image_view_sampler(
+            binding,
+            self.targets[index].clone(),
+            self.sampler.clone(),
+        )
+    }
+
+    /// Records tracing into the target of `index`, `light_direction` is needed for shadows.
+    pub fn render<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        index: usize,
+        acceleration: &SceneAcceleration,
+        camera: &Camera,
+        light_direction: [f32; 3],
+    ) {
+        let (Some(pipeline), Some(tlas), Some(geometries)) =
+            (&self.pipeline, &acceleration.tlas, &acceleration.geometries)
+        else {
+            return;
+        };
+        let target = &self.targets[index];
+        let extent = target.image().extent();
+        let aspect = extent[0] as f32 / extent[1] as f32;
+
+        let camera = Buffer::from_data(
+            self.allocators.mem.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::UNIFORM_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            RaytracerCamera::new(camera, aspect),
+        )
+        .unwrap();
+        let set = DescriptorSet::new(
+            self.allocators.set.clone(),
+            pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::acceleration_structure(0, tlas.clone()),
+                WriteDescriptorSet::buffer(1, camera),
+                WriteDescriptorSet::buffer(2, geometries.clone()),
+                WriteDescriptorSet::image_view(3, target.clone()),
+            ],
+            [],
+        )
+        .unwrap();
+        let push = OcclusionPush {
+            light_direction,
+            mode: self.settings.mode.shader_value(),
+            rays: self.settings.rays,
+            radius: self.settings.radius,
+        };
+
+        builder
+            .bind_pipeline_compute(pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .unwrap()
+            .push_constants(pipeline.layout().clone(), 0, push)
+            .unwrap();
+        unsafe { builder.dispatch([extent[0].div_ceil(8), extent[1].div_ceil(8), 1]) }.unwrap();
+    }
+}
+
+fn new_target(allocators: &Allocators, extent: [u32; 2]) -> Arc<ImageView> {
+    let image = Image::new(
+        allocators.memory.allocator(MemoryCategory::RenderTargets),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: OCCLUSION_FORMAT,
+            extent: [extent[0], extent[1], 1],
+            usage: ImageUsage::STORAGE | ImageUsage::SAMPLED,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+    ImageView::new_default(image).unwrap()
+}
+
+fn occlusion_pipeline(device: Arc<Device>) -> Arc<ComputePipeline> {
+    let cs = cs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let stage = PipelineShaderStageCreateInfo::new(cs);
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+    ComputePipeline::new(
+        device,
+        None,
+        ComputePipelineCreateInfo::stage_layout(stage, layout),
+    )
+    .unwrap()
+}
+
+mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        vulkan_version: "1.2",
+        src: r#"
+#version 460
+#extension GL_EXT_ray_query : require
+#extension GL_EXT_buffer_reference : require
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(buffer_reference, std430, buffer_reference_align = 4) readonly buffer Floats {
+    float f[];
+};
+layout(buffer_reference, std430, buffer_reference_align = 4) readonly buffer Indices {
+    uint i[];
+};
+struct Geometry {
+    Floats vertices;
+    Indices indices;
+    vec4 base_color;
+    vec4 emissive;
+};
+
+layout(set = 0, binding = 0) uniform accelerationStructureEXT tlas;
+layout(set = 0, binding = 1) uniform Camera {
+    mat4 view_inverse;
+    mat4 proj_inverse;
+} camera;
+layout(set = 0, binding = 2, std430) readonly buffer Geometries {
+    Geometry geometries[];
+};
+layout(set = 0, binding = 3, r32f) uniform writeonly image2D target;
+
+#define MODE_AMBIENT_OCCLUSION 1
+#define MODE_SHADOWS 2
+layout(push_constant) uniform Occlusion {
+    vec3 light_direction;
+    int mode;
+    uint rays;
+    float radius;
+} o;
+
+const float PI = 3.14159265358979323846264338327950288;
+// floats in a PrimitiveVertex, starting with the position
+const uint VERTEX_FLOATS = 14;
+// angular radius of the soft shadow cone
+const float LIGHT_RADIUS = 0.02;
+
+vec3 vertex_position(Geometry g, uint index) {
+    uint base = index * VERTEX_FLOATS;
+    return vec3(g.vertices.f[base], g.vertices.f[base + 1], g.vertices.f[base + 2]);
+}
+
+// stable per pixel noise so the result doesn't flicker
+float hash(uvec3 v) {
+    v = v * 1664525u + 1013904223u;
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    v ^= v >> 16u;
+    v.x += v.y * v.z;
+    return float(v.x) / 4294967295.0;
+}
+
+mat3 basis(vec3 n) {
+    vec3 up = abs(n.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 t = normalize(cross(up, n));
+    return mat3(t, cross(n, t), n);
+}
+
+bool occluded(vec3 origin, vec3 direction, float t_max) {
+    rayQueryEXT query;
+    rayQueryInitializeEXT(
+        query, tlas,
+        gl_RayFlagsOpaqueEXT | gl_RayFlagsTerminateOnFirstHitEXT,
+        0xFF, origin, 0.0, direction, t_max);
+    while (rayQueryProceedEXT(query)) {}
+    return rayQueryGetIntersectionTypeEXT(query, true) != gl_RayQueryCommittedIntersectionNoneEXT;
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(target);
+    if (any(greaterThanEqual(pixel, size))) {
+        return;
+    }
+
+    vec2 d = (vec2(pixel) + 0.5) / vec2(size) * 2.0 - 1.0;
+    vec3 origin = (camera.view_inverse * vec4(0, 0, 0, 1)).xyz;
+    vec4 view_target = camera.proj_inverse * vec4(d.x, d.y, 1, 1);
+    vec3 direction = (camera.view_inverse * vec4(normalize(view_target.xyz / view_target.w), 0)).xyz;
+
+    rayQueryEXT query;
+    rayQueryInitializeEXT(query, tlas, gl_RayFlagsOpaqueEXT, 0xFF, origin, 0.001, direction, 10000.0);
+    while (rayQueryProceedEXT(query)) {}
+    if (rayQueryGetIntersectionTypeEXT(query, true) == gl_RayQueryCommittedIntersectionNoneEXT) {
+        imageStore(target, pixel, vec4(1.0));
+        return;
+    }
+
+    float t = rayQueryGetIntersectionTEXT(query, true);
+    Geometry g = geometries[rayQueryGetIntersectionInstanceCustomIndexEXT(query, true)];
+    uint primitive = rayQueryGetIntersectionPrimitiveIndexEXT(query, true);
+    vec3 p0 = vertex_position(g, g.indices.i[3 * primitive]);
+    vec3 p1 = vertex_position(g, g.indices.i[3 * primitive + 1]);
+    vec3 p2 = vertex_position(g, g.indices.i[3 * primitive + 2]);
+    mat4x3 world_to_object = rayQueryGetIntersectionWorldToObjectEXT(query, true);
+    // inverse transpose of the object to world matrix
+    vec3 N = normalize(cross(p1 - p0, p2 - p0) * mat3(world_to_object));
+    if (dot(N, direction) > 0.0) {
+        N = -N;
+    }
+    vec3 position = origin + direction * t + N * max(t, 1.0) * 0.0005;
+
+    uint rays = max(o.rays, 1u);
+    float visible = 0.0;
+    for (uint i = 0u; i < rays; i++) {
+        vec2 xi = vec2(hash(uvec3(pixel, 2u * i)), hash(uvec3(pixel, 2u * i + 1u)));
+        float phi = 2.0 * PI * xi.y;
+        if (o.mode == MODE_SHADOWS) {
+            vec3 L = -normalize(o.light_direction);
+            float r = LIGHT_RADIUS * sqrt(xi.x);
+            vec3 ray = normalize(basis(L) * vec3(r * cos(phi), r * sin(phi), 1.0));
+            visible += occluded(position, ray, 10000.0) ? 0.0 : 1.0;
+        } else {
+            // cosine weighted hemisphere
+            float r = sqrt(xi.x);
+            vec3 ray = basis(N) * vec3(r * cos(phi), r * sin(phi), sqrt(1.0 - xi.x));
+            visible += occluded(position, ray, o.radius) ? 0.0 : 1.0;
+        }
+    }
+    imageStore(target, pixel, vec4(visible / float(rays)));
+}
+        "#,
+    }
+}
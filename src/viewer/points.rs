@@ -0,0 +1,248 @@
+use crate::{
+    frameinfo::Depth,
+    stats::DrawStats,
+    vktf::{GltfRenderInfo, loader::PointVertex, mesh::Instance},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use vulkano::{
+    buffer::BufferContents,
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor_set::{DescriptorSet, layout::DescriptorSetLayout},
+    device::DeviceOwned,
+    image::SampleCount,
+    pipeline::{
+        DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+        graphics::{
+            GraphicsPipelineCreateInfo,
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{CompareOp, DepthStencilState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::ViewportState,
+        },
+        layout::{PipelineLayoutCreateInfo, PushConstantRange},
+    },
+    render_pass::Subpass,
+    shader::ShaderStages,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PointSettings {
+    /// Diameter in pixels.
+    pub size: f32,
+}
+impl Default for PointSettings {
+    fn default() -> Self {
+        Self { size: 2.0 }
+    }
+}
+impl PointSettings {
+    /// `max_size` is 1 if the device can't draw larger points.
+    pub fn ui(&mut self, ui: &mut egui::Ui, max_size: f32) {
+        ui.add_enabled(
+            max_size > 1.0,
+            egui::Slider::new(&mut self.size, 1.0..=max_size.min(64.0)).text("Point size"),
+        )
+        .on_disabled_hover_text("Large points are not supported by the device");
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+struct PointPush {
+    size: f32,
+}
+
+/// Draws the point primitives of every model with their vertex colors.
+#[derive(Clone)]
+pub struct Points {
+    pipeline: Arc<GraphicsPipeline>,
+    /// Largest point size the device can draw.
+    pub max_size: f32,
+    pub settings: PointSettings,
+}
+impl Points {
+    pub fn new(camera_layout: Arc<DescriptorSetLayout>, subpass: Subpass, depth: Depth) -> Self {
+        let device = camera_layout.device().clone();
+        let max_size = if device.enabled_features().large_points {
+            device.physical_device().properties().point_size_range[1]
+        } else {
+            1.0
+        };
+        let layout = PipelineLayout::new(
+            device,
+            PipelineLayoutCreateInfo {
+                set_layouts: vec![camera_layout],
+                push_constant_ranges: vec![PushConstantRange {
+                    stages: ShaderStages::VERTEX,
+                    offset: 0,
+                    size: std::mem::size_of::<PointPush>() as u32,
+                }],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        Self {
+            pipeline: point_pipeline(layout, subpass, depth),
+            max_size,
+            settings: PointSettings::default(),
+        }
+    }
+    /// Rebuilds the pipeline for a new main render pass.
+    pub fn set_subpass(&mut self, subpass: Subpass, depth: Depth) {
+        self.pipeline = point_pipeline(self.pipeline.layout().clone(), subpass, depth);
+    }
+    pub fn render<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        camera_set: Arc<DescriptorSet>,
+        models: &[GltfRenderInfo],
+        frame: usize,
+    ) -> DrawStats {
+        let mut stats = DrawStats::default();
+        if models.iter().all(|info| info.points() == 0) {
+            return stats;
+        }
+        let layout = self.pipeline.layout().clone();
+        let push = PointPush {
+            size: self.settings.size.clamp(1.0, self.max_size),
+        };
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, layout.clone(), 0, camera_set)
+            .unwrap()
+            .push_constants(layout, 0, push)
+            .unwrap();
+
+        for mesh in models.iter().flat_map(|info| &info.meshes) {
+            let Some(points) = mesh.points() else {
+                continue;
+            };
+            let instances = mesh.instance_count();
+            mesh.bind_instances(builder, frame);
+            builder.bind_vertex_buffers(0, points.vertices()).unwrap();
+            unsafe { builder.draw(points.count(), instances, 0, 0) }.unwrap();
+            stats += DrawStats::draw(0, instances);
+        }
+        stats
+    }
+}
+
+fn point_pipeline(
+    layout: Arc<PipelineLayout>,
+    subpass: Subpass,
+    depth: Depth,
+) -> Arc<GraphicsPipeline> {
+    let device = layout.device().clone();
+    let vs = vs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let fs = fs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let vertex_input_state = [PointVertex::per_vertex(), Instance::per_instance()]
+        .definition(&vs)
+        .unwrap();
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState {
+                topology: PrimitiveTopology::PointList,
+                ..Default::default()
+            }),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(depth.state(CompareOp::Less, true)),
+                ..Default::default()
+            }),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap()
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec4 color;
+
+layout(location = 2) in vec4 model_x;
+layout(location = 3) in vec4 model_y;
+layout(location = 4) in vec4 model_z;
+layout(location = 5) in vec4 model_w;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+    mat4 view_inv;
+} cam;
+layout(push_constant) uniform Point {
+    float size;
+} point;
+
+layout(location = 0) out vec4 v_color;
+
+void main() {
+    mat4 model = mat4(model_x, model_y, model_z, model_w);
+    gl_Position = cam.proj * cam.view * model * vec4(position, 1.0);
+    gl_PointSize = point.size;
+    v_color = color;
+}
+        "#
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    // round points
+    if (length(gl_PointCoord - 0.5) > 0.5) {
+        discard;
+    }
+    f_color = vec4(v_color.rgb, 1.0);
+}
+        "#
+    }
+}
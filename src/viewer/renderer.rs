@@ -1,11 +1,18 @@
 use crate::{
     Allocators,
+    frameinfo::Depth,
     set_layouts::SetLayouts,
-    vktf::{GltfPipeline, GltfRenderInfo},
+    stats::DrawStats,
+    vktf::{
+        GltfPipeline, GltfRenderInfo,
+        debug::DebugView,
+        material_override::{MaterialOverride, OverrideMaterials},
+    },
 };
 use image::EncodableLayout;
 use std::sync::Arc;
 use vulkano::{
+    Validated, VulkanError,
     buffer::{Buffer, BufferCreateInfo, BufferUsage},
     command_buffer::{AutoCommandBufferBuilder, CopyBufferToImageInfo},
     descriptor_set::{DescriptorSet, WriteDescriptorSet, allocator::DescriptorSetAllocator},
@@ -19,16 +26,26 @@ use vulkano::{
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
     pipeline::{Pipeline, PipelineBindPoint},
     render_pass::Subpass,
+    shader::EntryPoint,
 };
 
 #[derive(Clone)]
 pub struct ViewerRenderer {
     pub pipeline: GltfPipeline,
     pub env_set: Arc<DescriptorSet>,
-    pub info: Option<GltfRenderInfo>,
+    pub models: Vec<GltfRenderInfo>,
+    pub debug_view: DebugView,
+    pub material_override: MaterialOverride,
+    override_materials: OverrideMaterials,
+    /// Draws only the edges of the triangles if the device supports it.
+    pub wireframe: bool,
     pub sampler: Arc<Sampler>,
     pub lut_write: WriteDescriptorSet,
     pub set_allocator: Arc<dyn DescriptorSetAllocator>,
+    /// Replaces `pipeline` with a fragment shader from disk, kept to rebuild it.
+    pub experiment: Option<(EntryPoint, GltfPipeline)>,
+    subpass: Subpass,
+    depth: Depth,
 }
 impl ViewerRenderer {
     pub fn new<L>(
@@ -36,17 +53,11 @@ impl ViewerRenderer {
         builder: &mut AutoCommandBufferBuilder<L>,
         set_layouts: &SetLayouts,
         subpass: Subpass,
+        depth: Depth,
     ) -> Self {
         let device = allocators.mem.device();
-        let pipeline = GltfPipeline::new(
-            device.clone(),
-            vec![
-                set_layouts.camera.clone(),
-                set_layouts.environment.clone(),
-                set_layouts.material.clone(),
-            ],
-            subpass.clone(),
-        );
+        let pipeline =
+            GltfPipeline::new(device.clone(), set_layouts.gltf(), subpass.clone(), depth);
 
         let env_image = Image::new(
             allocators.mem.clone(),
@@ -138,26 +149,105 @@ impl ViewerRenderer {
         )
         .unwrap();
 
+        let override_materials =
+            OverrideMaterials::new(allocators, builder, set_layouts.material.clone());
+
         Self {
             pipeline,
-            info: None,
+            models: vec![],
+            debug_view: DebugView::default(),
+            material_override: MaterialOverride::default(),
+            override_materials,
+            wireframe: false,
             env_set,
             sampler,
             set_allocator: allocators.set.clone(),
             lut_write,
+            experiment: None,
+            subpass,
+            depth,
         }
     }
 
-    pub fn render<L>(&self, builder: &mut AutoCommandBufferBuilder<L>) {
-        if let Some(gltf_info) = self.info.clone() {
-            let layout = self.pipeline.pipeline.layout().clone();
-            builder
-                .bind_descriptor_sets(PipelineBindPoint::Graphics, layout, 1, self.env_set.clone())
-                .unwrap();
-            self.pipeline.render(gltf_info, builder);
+    pub fn render<L>(&self, builder: &mut AutoCommandBufferBuilder<L>, frame: usize) -> DrawStats {
+        let pipeline = self
+            .experiment
+            .as_ref()
+            .map_or(&self.pipeline, |(_, pipeline)| pipeline);
+        self.render_with(pipeline, builder, frame, false)
+    }
+    /// Renders the scene with a pipeline made for another subpass.
+    pub fn render_with<L>(
+        &self,
+        pipeline: &GltfPipeline,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        frame: usize,
+        opaque_only: bool,
+    ) -> DrawStats {
+        if self.models.is_empty() {
+            return DrawStats::default();
         }
+        let layout = pipeline.pipeline.layout().clone();
+        builder
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, layout, 1, self.env_set.clone())
+            .unwrap();
+        let (material_override, debug_view) = match self
+            .override_materials
+            .get(self.material_override, self.debug_view)
+        {
+            Some((material, view)) => (Some(material), view),
+            None => (None, self.debug_view),
+        };
+        pipeline.render(
+            &self.models,
+            debug_view,
+            material_override,
+            frame,
+            opaque_only,
+            self.wireframe,
+            builder,
+        )
     }
 
+    /// The main subpass the pipelines were made for.
+    pub fn subpass(&self) -> &Subpass {
+        &self.subpass
+    }
+    /// Rebuilds the pipeline for a new main render pass.
+    pub fn set_subpass(&mut self, subpass: Subpass, depth: Depth) {
+        let pipeline = &self.pipeline.pipeline;
+        self.pipeline = GltfPipeline::new(
+            pipeline.device().clone(),
+            pipeline.layout().set_layouts().to_vec(),
+            subpass.clone(),
+            depth,
+        );
+        self.subpass = subpass;
+        self.depth = depth;
+        if let Some((fs, _)) = self.experiment.take() {
+            if let Err(err) = self.set_experiment(Some(fs)) {
+                log::warn!("dropped the experiment shader: {err}");
+            }
+        }
+    }
+    /// Draws the models with `fs` instead of `gltf.frag`, or goes back to it with `None`.
+    /// The current shader stays if the pipeline can't be made.
+    pub fn set_experiment(&mut self, fs: Option<EntryPoint>) -> Result<(), Validated<VulkanError>> {
+        let Some(fs) = fs else {
+            self.experiment = None;
+            return Ok(());
+        };
+        let pipeline = &self.pipeline.pipeline;
+        let experiment = GltfPipeline::with_fragment_shader(
+            pipeline.device().clone(),
+            pipeline.layout().set_layouts().to_vec(),
+            self.subpass.clone(),
+            self.depth,
+            fs.clone(),
+        )?;
+        self.experiment = Some((fs, experiment));
+        Ok(())
+    }
     pub fn new_env(&mut self, diffuse: Arc<Image>, specular: Arc<Image>) {
         let diffuse_view = ImageView::new(
             diffuse.clone(),
@@ -0,0 +1,379 @@
+use crate::{
+    stats::DrawStats,
+    vktf::{GltfRenderInfo, loader::PrimitiveVertex, mesh::Instance},
+};
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use vulkano::{
+    buffer::BufferContents,
+    command_buffer::{
+        AutoCommandBufferBuilder, RenderPassBeginInfo, SubpassBeginInfo, SubpassEndInfo,
+    },
+    descriptor_set::{WriteDescriptorSet, layout::DescriptorSetLayout},
+    device::{Device, DeviceOwned},
+    format::Format,
+    image::{
+        Image, ImageCreateInfo, ImageType, ImageUsage,
+        sampler::{
+            BorderColor, Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode,
+        },
+        view::ImageView,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryAllocator},
+    pipeline::{
+        DynamicState, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+        graphics::{
+            GraphicsPipelineCreateInfo,
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::{CullMode, RasterizationState},
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::{Scissor, Viewport, ViewportState},
+        },
+        layout::{PipelineLayoutCreateInfo, PushConstantRange},
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    shader::ShaderStages,
+};
+
+const SHADOW_FORMAT: Format = Format::D32_SFLOAT;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    pub resolution: u32,
+    pub bias: f32,
+    /// Half size of the orthographic box around the camera target.
+    pub extent: f32,
+}
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            resolution: 2048,
+            bias: 0.002,
+            extent: 10.0,
+        }
+    }
+}
+impl ShadowSettings {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Enabled");
+        egui::ComboBox::from_label("Resolution")
+            .selected_text(format!("{0}x{0}", self.resolution))
+            .show_ui(ui, |ui| {
+                for resolution in [512, 1024, 2048, 4096] {
+                    ui.selectable_value(
+                        &mut self.resolution,
+                        resolution,
+                        format!("{0}x{0}", resolution),
+                    );
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut self.bias)
+                    .range(0.0..=0.1)
+                    .speed(0.0001),
+            );
+            ui.label("Bias");
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut self.extent)
+                    .range(0.1..=f32::MAX)
+                    .speed(0.1),
+            );
+            ui.label("Extent");
+        });
+    }
+}
+
+#[repr(C)]
+#[derive(BufferContents)]
+struct ShadowPush {
+    view_proj: glm::Mat4,
+}
+
+pub fn light_view_proj(direction: &glm::Vec3, center: &glm::Vec3, extent: f32) -> glm::Mat4 {
+    let direction = direction.normalize();
+    let up = if direction.y.abs() > 0.99 {
+        glm::Vec3::z()
+    } else {
+        glm::Vec3::y()
+    };
+    let eye = center - direction * extent * 2.0;
+    let view = glm::look_at_rh(&eye, center, &up);
+    let proj = glm::ortho_rh_zo(-extent, extent, -extent, extent, 0.0, extent * 4.0);
+    proj * view
+}
+
+struct ShadowMap {
+    view: Arc<ImageView>,
+    framebuffer: Arc<Framebuffer>,
+}
+impl ShadowMap {
+    fn new(
+        allocator: Arc<dyn MemoryAllocator>,
+        render_pass: Arc<RenderPass>,
+        resolution: u32,
+    ) -> Self {
+        let image = Image::new(
+            allocator,
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: SHADOW_FORMAT,
+                extent: [resolution, resolution, 1],
+                usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+        let view = ImageView::new_default(image).unwrap();
+        let framebuffer = Framebuffer::new(
+            render_pass,
+            FramebufferCreateInfo {
+                attachments: vec![view.clone()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        Self { view, framebuffer }
+    }
+}
+
+pub struct Shadows {
+    pub settings: ShadowSettings,
+    pipeline: Arc<GraphicsPipeline>,
+    subpass: Subpass,
+    sampler: Arc<Sampler>,
+    maps: Vec<ShadowMap>,
+    allocator: Arc<dyn MemoryAllocator>,
+}
+impl Shadows {
+    pub fn new(
+        allocator: Arc<dyn MemoryAllocator>,
+        morph_layout: Arc<DescriptorSetLayout>,
+        num_frames: usize,
+    ) -> Self {
+        let device = allocator.device().clone();
+        let settings = ShadowSettings::default();
+
+        let render_pass = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                depth: {
+                    format: SHADOW_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [],
+                depth_stencil: {depth},
+            },
+        )
+        .unwrap();
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+        let pipeline = shadow_pipeline(device.clone(), morph_layout, subpass.clone());
+
+        let sampler = Sampler::new(
+            device,
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                mipmap_mode: SamplerMipmapMode::Nearest,
+                address_mode: [SamplerAddressMode::ClampToBorder; 3],
+                border_color: BorderColor::FloatOpaqueWhite,
+                compare: Some(CompareOp::LessOrEqual),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let maps = (0..num_frames)
+            .map(|_| ShadowMap::new(allocator.clone(), render_pass.clone(), settings.resolution))
+            .collect();
+
+        Self {
+            settings,
+            pipeline,
+            subpass,
+            sampler,
+            maps,
+            allocator,
+        }
+    }
+
+    /// Recreates the shadow maps if the resolution setting changed.
+    /// Returns `true` when descriptor sets referencing the maps must be rebuilt.
+    pub fn resize(&mut self) -> bool {
+        let resolution = self.settings.resolution;
+        if self.maps[0].view.image().extent()[0] == resolution {
+            return false;
+        }
+        for map in &mut self.maps {
+            *map = ShadowMap::new(
+                self.allocator.clone(),
+                self.subpass.render_pass().clone(),
+                resolution,
+            );
+        }
+        true
+    }
+
+    pub fn write(&self, binding: u32, index: usize) -> WriteDescriptorSet {
+        WriteDescriptorSet::image_view_sampler(
+            binding,
+            self.maps[index].view.clone(),
+            self.sampler.clone(),
+        )
+    }
+
+    pub fn render<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        index: usize,
+        models: &[GltfRenderInfo],
+        view_proj: glm::Mat4,
+    ) -> DrawStats {
+        let resolution = self.settings.resolution as f32;
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some(1f32.into())],
+                    ..RenderPassBeginInfo::framebuffer(self.maps[index].framebuffer.clone())
+                },
+                SubpassBeginInfo::default(),
+            )
+            .unwrap()
+            .set_viewport(
+                0,
+                vec![Viewport {
+                    extent: [resolution, resolution],
+                    ..Default::default()
+                }]
+                .into(),
+            )
+            .unwrap()
+            .set_scissor(0, vec![Scissor::default()].into())
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .push_constants(self.pipeline.layout().clone(), 0, ShadowPush { view_proj })
+            .unwrap();
+        let mut stats = DrawStats::default();
+        for mesh in models.iter().flat_map(|info| &info.meshes) {
+            stats += mesh.render_depth(builder, self.pipeline.layout(), index);
+        }
+        builder.end_render_pass(SubpassEndInfo::default()).unwrap();
+        stats
+    }
+}
+
+fn shadow_pipeline(
+    device: Arc<Device>,
+    morph_layout: Arc<DescriptorSetLayout>,
+    subpass: Subpass,
+) -> Arc<GraphicsPipeline> {
+    let vs = vs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let fs = fs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+
+    let vertex_input_state = [PrimitiveVertex::per_vertex(), Instance::per_instance()]
+        .definition(&vs)
+        .unwrap();
+
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineLayoutCreateInfo {
+            set_layouts: vec![morph_layout],
+            push_constant_ranges: vec![PushConstantRange {
+                stages: ShaderStages::VERTEX,
+                offset: 0,
+                size: std::mem::size_of::<ShadowPush>() as u32,
+            }],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            rasterization_state: Some(RasterizationState {
+                cull_mode: CullMode::None,
+                ..Default::default()
+            }),
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(DepthState::simple()),
+                ..Default::default()
+            }),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap()
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        include: ["shaders"],
+        src: r#"
+#version 450
+
+#define MORPH_SET 0
+#include "morph.glsl"
+
+layout(location = 0) in vec3 position;
+
+layout(location = 5) in vec4 model_x;
+layout(location = 6) in vec4 model_y;
+layout(location = 7) in vec4 model_z;
+layout(location = 8) in vec4 model_w;
+
+layout(push_constant) uniform Shadow {
+    mat4 view_proj;
+} s;
+
+void main() {
+    mat4 model = mat4(model_x, model_y, model_z, model_w);
+    gl_Position = s.view_proj * model * vec4(position + morph_delta(MORPH_POSITION), 1.0);
+}
+        "#
+    }
+}
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+void main() {}
+        "#
+    }
+}
@@ -0,0 +1,340 @@
+use crate::{frameinfo::Depth, vktf::bounds::Aabb};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use vulkano::{
+    buffer::BufferContents,
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor_set::{DescriptorSet, layout::DescriptorSetLayout},
+    device::DeviceOwned,
+    image::SampleCount,
+    pipeline::{
+        DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+        graphics::{
+            GraphicsPipelineCreateInfo,
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{CompareOp, DepthStencilState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::VertexInputState,
+            viewport::ViewportState,
+        },
+        layout::{PipelineLayoutCreateInfo, PushConstantRange},
+    },
+    render_pass::Subpass,
+    shader::ShaderStages,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShadowCatcherSettings {
+    pub enabled: bool,
+    /// A soft dark spot under the models that needs no lights.
+    pub blob: bool,
+    /// The shadow map of the primary directional light.
+    pub shadow_map: bool,
+    pub opacity: f32,
+    /// Size of the blob relative to the footprint of the models.
+    pub blob_size: f32,
+}
+impl Default for ShadowCatcherSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            blob: true,
+            shadow_map: true,
+            opacity: 0.6,
+            blob_size: 1.2,
+        }
+    }
+}
+impl ShadowCatcherSettings {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Shadow catcher")
+            .on_hover_text("A transparent ground under the models that only shows their shadows");
+        ui.add_enabled_ui(self.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.blob, "Contact blob");
+                ui.checkbox(&mut self.shadow_map, "Shadow map");
+            });
+            ui.add(egui::Slider::new(&mut self.opacity, 0.0..=1.0).text("Shadow opacity"));
+            ui.add_enabled(
+                self.blob,
+                egui::Slider::new(&mut self.blob_size, 0.5..=3.0).text("Blob size"),
+            );
+        });
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+struct CatcherPush {
+    center: [f32; 2],
+    radius: [f32; 2],
+    height: f32,
+    blob: f32,
+    shadow_map: f32,
+}
+
+/// A ground plane at the bottom of the models that is transparent except for their shadows,
+/// drawn after the scene like the grid.
+#[derive(Clone)]
+pub struct ShadowCatcher {
+    pipeline: Arc<GraphicsPipeline>,
+    pub settings: ShadowCatcherSettings,
+    /// World bounds of all models, the plane is placed below them.
+    pub bounds: Aabb,
+}
+impl ShadowCatcher {
+    pub fn new(
+        camera_layout: Arc<DescriptorSetLayout>,
+        lights_layout: Arc<DescriptorSetLayout>,
+        subpass: Subpass,
+        depth: Depth,
+    ) -> Self {
+        let device = camera_layout.device().clone();
+        let layout = PipelineLayout::new(
+            device,
+            PipelineLayoutCreateInfo {
+                set_layouts: vec![camera_layout, lights_layout],
+                push_constant_ranges: vec![PushConstantRange {
+                    stages: ShaderStages::FRAGMENT,
+                    offset: 0,
+                    size: std::mem::size_of::<CatcherPush>() as u32,
+                }],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        Self {
+            pipeline: catcher_pipeline(layout, subpass, depth),
+            settings: ShadowCatcherSettings::default(),
+            bounds: Aabb::empty(),
+        }
+    }
+    /// Rebuilds the pipeline for a new main render pass.
+    pub fn set_subpass(&mut self, subpass: Subpass, depth: Depth) {
+        self.pipeline = catcher_pipeline(self.pipeline.layout().clone(), subpass, depth);
+    }
+    pub fn render<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        camera_set: Arc<DescriptorSet>,
+        lights_set: Arc<DescriptorSet>,
+    ) {
+        if !self.settings.enabled || self.bounds.is_empty() {
+            return;
+        }
+        let center = self.bounds.center();
+        let half = (self.bounds.max - self.bounds.min) * 0.5 * self.settings.blob_size;
+        let push = CatcherPush {
+            center: [center.x, center.z],
+            radius: [half.x.max(0.001), half.z.max(0.001)],
+            height: self.bounds.min.y,
+            blob: if self.settings.blob {
+                self.settings.opacity
+            } else {
+                0.0
+            },
+            shadow_map: if self.settings.shadow_map {
+                self.settings.opacity
+            } else {
+                0.0
+            },
+        };
+        let layout = self.pipeline.layout().clone();
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                layout.clone(),
+                0,
+                vec![camera_set, lights_set],
+            )
+            .unwrap()
+            .push_constants(layout, 0, push)
+            .unwrap();
+        unsafe { builder.draw(3, 1, 0, 0) }.unwrap();
+    }
+}
+
+fn catcher_pipeline(
+    layout: Arc<PipelineLayout>,
+    subpass: Subpass,
+    depth: Depth,
+) -> Arc<GraphicsPipeline> {
+    let device = layout.device().clone();
+    let vs = vs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let fs = fs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(VertexInputState::default()),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend: Some(AttachmentBlend::alpha()),
+                    ..Default::default()
+                },
+            )),
+            // the fragment shader writes the depth of the plane
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(depth.state(CompareOp::Less, false)),
+                ..Default::default()
+            }),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap()
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+#version 450
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+    mat4 view_inv;
+} cam;
+
+layout(location = 0) out vec3 v_near;
+layout(location = 1) out vec3 v_far;
+
+vec3 unproject(vec2 xy, float depth) {
+    vec4 world = inverse(cam.proj * cam.view) * vec4(xy, depth, 1.0);
+    return world.xyz / world.w;
+}
+
+void main() {
+    // one triangle covering the screen
+    vec2 xy = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+    v_near = unproject(xy, 0.0);
+    v_far = unproject(xy, 1.0);
+    gl_Position = vec4(xy, 0.0, 1.0);
+}
+        "#
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec3 v_near;
+layout(location = 1) in vec3 v_far;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+    mat4 view_inv;
+} cam;
+
+#define MAX_LIGHTS 16
+#define TRACED_SHADOWS 2
+struct Light {
+    vec3 position;
+    float range;
+    vec3 direction;
+    float intensity;
+    vec3 color;
+    int kind;
+    float inner_cone_cos;
+    float outer_cone_cos;
+};
+layout(set = 1, binding = 0) uniform Lights {
+    Light lights[MAX_LIGHTS];
+    mat4 shadow_view_proj;
+    uint count;
+    int shadow_light;
+    float shadow_bias;
+    float env_intensity;
+    float env_yaw;
+    int traced_occlusion;
+    int ssao;
+} l;
+layout(set = 1, binding = 1) uniform sampler2DShadow shadow_map;
+
+layout(push_constant) uniform Catcher {
+    vec2 center;
+    vec2 radius;
+    float height;
+    // opacity of each kind of shadow, 0 if it is off
+    float blob;
+    float shadow_map;
+} catcher;
+
+layout(location = 0) out vec4 f_color;
+
+// 3x3 PCF like the models, 1 if the shadow map wasn't rendered this frame
+float lit(vec3 position) {
+    if (l.shadow_light < 0 || l.traced_occlusion == TRACED_SHADOWS) {
+        return 1.0;
+    }
+    vec4 light_space = l.shadow_view_proj * vec4(position, 1.0);
+    vec3 ndc = light_space.xyz / light_space.w;
+    vec2 uv = ndc.xy * 0.5 + 0.5;
+    if (ndc.z > 1.0) {
+        return 1.0;
+    }
+    vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0));
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            lit += texture(shadow_map, vec3(uv + vec2(x, y) * texel, ndc.z - l.shadow_bias));
+        }
+    }
+    return lit / 9.0;
+}
+
+void main() {
+    float t = (catcher.height - v_near.y) / (v_far.y - v_near.y);
+    if (t <= 0.0 || t >= 1.0) {
+        discard;
+    }
+    vec3 position = v_near + t * (v_far - v_near);
+    vec4 clip = cam.proj * cam.view * vec4(position, 1.0);
+    gl_FragDepth = clip.z / clip.w;
+
+    float d = length((position.xz - catcher.center) / catcher.radius);
+    float blob = (1.0 - smoothstep(0.2, 1.0, d)) * catcher.blob;
+    float cast = (1.0 - lit(position)) * catcher.shadow_map;
+    float alpha = max(blob, cast);
+    if (alpha <= 0.0) {
+        discard;
+    }
+    f_color = vec4(0.0, 0.0, 0.0, alpha);
+}
+        "#
+    }
+}
@@ -0,0 +1,626 @@
+use crate::{
+    Allocators,
+    memory::MemoryCategory,
+    stats::DrawStats,
+    vktf::{GltfRenderInfo, loader::PrimitiveVertex, mesh::Instance},
+};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use vulkano::{
+    buffer::BufferContents,
+    command_buffer::{
+        AutoCommandBufferBuilder, RenderPassBeginInfo, SubpassBeginInfo, SubpassEndInfo,
+    },
+    descriptor_set::{DescriptorSet, WriteDescriptorSet, layout::DescriptorSetLayout},
+    device::{Device, DeviceOwned},
+    format::Format,
+    image::{
+        Image, ImageCreateInfo, ImageType, ImageUsage,
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+    },
+    memory::allocator::AllocationCreateInfo,
+    pipeline::{
+        ComputePipeline, DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint,
+        PipelineLayout, PipelineShaderStageCreateInfo,
+        compute::ComputePipelineCreateInfo,
+        graphics::{
+            GraphicsPipelineCreateInfo,
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{DepthState, DepthStencilState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::{CullMode, FrontFace, RasterizationState},
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::{Scissor, Viewport, ViewportState},
+        },
+        layout::{
+            PipelineDescriptorSetLayoutCreateInfo, PipelineLayoutCreateInfo, PushConstantRange,
+        },
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    shader::{EntryPoint, ShaderStages},
+};
+
+/// View space normal in rgb and view depth in alpha, `0` depth where nothing was drawn.
+const GBUFFER_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+const DEPTH_FORMAT: Format = Format::D32_SFLOAT;
+const AO_FORMAT: Format = Format::R32_SFLOAT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SsaoSettings {
+    pub enabled: bool,
+    /// How far from a surface occluders are looked for, in scene units.
+    pub radius: f32,
+    /// Scales the darkening, `1` is physically motivated.
+    pub intensity: f32,
+}
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            radius: 0.5,
+            intensity: 1.0,
+        }
+    }
+}
+impl SsaoSettings {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Enabled")
+            .on_hover_text("Darkens the environment lighting in creases and corners");
+        ui.add_enabled_ui(self.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut self.radius)
+                        .range(0.01..=100.0)
+                        .speed(0.01),
+                );
+                ui.label("Radius");
+            });
+            ui.add(egui::Slider::new(&mut self.intensity, 0.0..=4.0).text("Intensity"));
+        });
+    }
+}
+
+#[repr(C)]
+#[derive(BufferContents)]
+struct GbufferPush {
+    view: glm::Mat4,
+    proj: glm::Mat4,
+}
+#[repr(C)]
+#[derive(Clone, Copy, BufferContents)]
+struct SsaoPush {
+    proj: glm::Mat4,
+    radius: f32,
+    intensity: f32,
+}
+
+/// The screen sized images of one frame in flight.
+struct SsaoTarget {
+    gbuffer: Arc<ImageView>,
+    framebuffer: Arc<Framebuffer>,
+    /// Noisy occlusion straight from the samples.
+    raw: Arc<ImageView>,
+    /// Blurred visibility sampled by the glTF pipeline.
+    blurred: Arc<ImageView>,
+}
+impl SsaoTarget {
+    fn new(allocators: &Allocators, render_pass: Arc<RenderPass>, extent: [u32; 2]) -> Self {
+        let image = |format, usage| {
+            let image = Image::new(
+                allocators.memory.allocator(MemoryCategory::RenderTargets),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    format,
+                    extent: [extent[0], extent[1], 1],
+                    usage,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )
+            .unwrap();
+            ImageView::new_default(image).unwrap()
+        };
+        let gbuffer = image(
+            GBUFFER_FORMAT,
+            ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
+        );
+        let depth = image(DEPTH_FORMAT, ImageUsage::DEPTH_STENCIL_ATTACHMENT);
+        let framebuffer = Framebuffer::new(
+            render_pass,
+            FramebufferCreateInfo {
+                attachments: vec![gbuffer.clone(), depth],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        Self {
+            gbuffer,
+            framebuffer,
+            raw: image(AO_FORMAT, ImageUsage::STORAGE | ImageUsage::SAMPLED),
+            blurred: image(AO_FORMAT, ImageUsage::STORAGE | ImageUsage::SAMPLED),
+        }
+    }
+}
+
+/// Screen space ambient occlusion: the models are drawn once more into a normal and depth buffer,
+/// occlusion is estimated from it and blurred into a texture that the glTF pipeline samples.
+pub struct Ssao {
+    pub settings: SsaoSettings,
+    gbuffer_pipeline: Arc<GraphicsPipeline>,
+    subpass: Subpass,
+    ssao_pipeline: Arc<ComputePipeline>,
+    blur_pipeline: Arc<ComputePipeline>,
+    /// Nearest for the compute passes, linear for the glTF pipeline.
+    nearest: Arc<Sampler>,
+    linear: Arc<Sampler>,
+    /// One target per frame in flight.
+    targets: Vec<SsaoTarget>,
+    /// The viewport size in pixels, the targets follow it on [`resize`](Self::resize).
+    pub extent: [u32; 2],
+    allocators: Allocators,
+}
+impl Ssao {
+    pub fn new(
+        allocators: &Allocators,
+        morph_layout: Arc<DescriptorSetLayout>,
+        num_frames: usize,
+    ) -> Self {
+        let device = allocators.mem.device().clone();
+        let render_pass = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                gbuffer: {
+                    format: GBUFFER_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+                depth: {
+                    format: DEPTH_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+            },
+            pass: {
+                color: [gbuffer],
+                depth_stencil: {depth},
+            },
+        )
+        .unwrap();
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+        let gbuffer_pipeline = gbuffer_pipeline(device.clone(), morph_layout, subpass.clone());
+        let ssao_pipeline = compute_pipeline(
+            device.clone(),
+            ssao_cs::load(device.clone())
+                .unwrap()
+                .entry_point("main")
+                .unwrap(),
+        );
+        let blur_pipeline = compute_pipeline(
+            device.clone(),
+            blur_cs::load(device.clone())
+                .unwrap()
+                .entry_point("main")
+                .unwrap(),
+        );
+
+        let sampler = |filter| {
+            Sampler::new(
+                device.clone(),
+                SamplerCreateInfo {
+                    mag_filter: filter,
+                    min_filter: filter,
+                    address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+        let targets = (0..num_frames)
+            .map(|_| SsaoTarget::new(allocators, render_pass.clone(), [1, 1]))
+            .collect();
+
+        Self {
+            settings: SsaoSettings::default(),
+            gbuffer_pipeline,
+            subpass,
+            ssao_pipeline,
+            blur_pipeline,
+            nearest: sampler(Filter::Nearest),
+            linear: sampler(Filter::Linear),
+            targets,
+            extent: [1, 1],
+            allocators: allocators.clone(),
+        }
+    }
+
+    /// Recreates the targets if the viewport changed size while enabled.
+    /// Returns `true` when descriptor sets referencing the targets must be rebuilt.
+    pub fn resize(&mut self) -> bool {
+        let extent = [self.extent[0].max(1), self.extent[1].max(1)];
+        if !self.settings.enabled || self.targets[0].blurred.image().extent()[..2] == extent[..] {
+            return false;
+        }
+        for target in &mut self.targets {
+            *target = SsaoTarget::new(&self.allocators, self.subpass.render_pass().clone(), extent);
+        }
+        true
+    }
+
+    pub fn write(&self, binding: u32, index: usize) -> WriteDescriptorSet {
+        WriteDescriptorSet::image_view_sampler(
+            binding,
+            self.targets[index].blurred.clone(),
+            self.linear.clone(),
+        )
+    }
+
+    /// Records the normal and depth pass, the occlusion and the blur into the target of `index`.
+    /// `view` and `proj` have to be the camera of the frame without reverse-Z.
+    pub fn render<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        index: usize,
+        models: &[GltfRenderInfo],
+        view: glm::Mat4,
+        proj: glm::Mat4,
+    ) -> DrawStats {
+        let target = &self.targets[index];
+        let [width, height, _] = target.gbuffer.image().extent();
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some([0.0; 4].into()), Some(1f32.into())],
+                    ..RenderPassBeginInfo::framebuffer(target.framebuffer.clone())
+                },
+                SubpassBeginInfo::default(),
+            )
+            .unwrap()
+            .set_viewport(
+                0,
+                vec![Viewport {
+                    extent: [width as f32, height as f32],
+                    ..Default::default()
+                }]
+                .into(),
+            )
+            .unwrap()
+            .set_scissor(0, vec![Scissor::default()].into())
+            .unwrap()
+            .bind_pipeline_graphics(self.gbuffer_pipeline.clone())
+            .unwrap()
+            .push_constants(
+                self.gbuffer_pipeline.layout().clone(),
+                0,
+                GbufferPush { view, proj },
+            )
+            .unwrap();
+        let mut stats = DrawStats::default();
+        for mesh in models.iter().flat_map(|info| &info.meshes) {
+            stats += mesh.render_depth(builder, self.gbuffer_pipeline.layout(), index);
+        }
+        builder.end_render_pass(SubpassEndInfo::default()).unwrap();
+
+        let push = SsaoPush {
+            proj,
+            radius: self.settings.radius,
+            intensity: self.settings.intensity,
+        };
+        let gbuffer = || {
+            WriteDescriptorSet::image_view_sampler(0, target.gbuffer.clone(), self.nearest.clone())
+        };
+        let passes = [
+            (
+                &self.ssao_pipeline,
+                vec![
+                    gbuffer(),
+                    WriteDescriptorSet::image_view(2, target.raw.clone()),
+                ],
+            ),
+            (
+                &self.blur_pipeline,
+                vec![
+                    gbuffer(),
+                    WriteDescriptorSet::image_view_sampler(
+                        1,
+                        target.raw.clone(),
+                        self.nearest.clone(),
+                    ),
+                    WriteDescriptorSet::image_view(2, target.blurred.clone()),
+                ],
+            ),
+        ];
+        for (pipeline, writes) in passes {
+            let layout = pipeline.layout().clone();
+            let set = DescriptorSet::new(
+                self.allocators.set.clone(),
+                layout.set_layouts()[0].clone(),
+                writes,
+                [],
+            )
+            .unwrap();
+            builder
+                .bind_pipeline_compute(pipeline.clone())
+                .unwrap()
+                .bind_descriptor_sets(PipelineBindPoint::Compute, layout.clone(), 0, set)
+                .unwrap()
+                .push_constants(layout, 0, push)
+                .unwrap();
+            unsafe { builder.dispatch([width.div_ceil(8), height.div_ceil(8), 1]) }.unwrap();
+        }
+        stats
+    }
+}
+
+fn gbuffer_pipeline(
+    device: Arc<Device>,
+    morph_layout: Arc<DescriptorSetLayout>,
+    subpass: Subpass,
+) -> Arc<GraphicsPipeline> {
+    let vs = vs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let fs = fs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+
+    let vertex_input_state = [PrimitiveVertex::per_vertex(), Instance::per_instance()]
+        .definition(&vs)
+        .unwrap();
+
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineLayoutCreateInfo {
+            set_layouts: vec![morph_layout],
+            push_constant_ranges: vec![PushConstantRange {
+                stages: ShaderStages::VERTEX,
+                offset: 0,
+                size: std::mem::size_of::<GbufferPush>() as u32,
+            }],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            // same culling as the glTF pipeline so hidden back faces don't occlude
+            rasterization_state: Some(RasterizationState {
+                cull_mode: CullMode::Back,
+                front_face: FrontFace::CounterClockwise,
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(DepthState::simple()),
+                ..Default::default()
+            }),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap()
+}
+
+fn compute_pipeline(device: Arc<Device>, cs: EntryPoint) -> Arc<ComputePipeline> {
+    let stage = PipelineShaderStageCreateInfo::new(cs);
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+    ComputePipeline::new(
+        device,
+        None,
+        ComputePipelineCreateInfo::stage_layout(stage, layout),
+    )
+    .unwrap()
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        include: ["shaders"],
+        src: r#"
+#version 450
+
+#define MORPH_SET 0
+#include "morph.glsl"
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+
+layout(location = 5) in vec4 model_x;
+layout(location = 6) in vec4 model_y;
+layout(location = 7) in vec4 model_z;
+layout(location = 8) in vec4 model_w;
+
+layout(push_constant) uniform Camera {
+    mat4 view;
+    mat4 proj;
+} cam;
+
+layout(location = 0) out vec3 f_normal;
+layout(location = 1) out float f_depth;
+
+void main() {
+    mat4 model_view = cam.view * mat4(model_x, model_y, model_z, model_w);
+    vec4 pos = model_view * vec4(position + morph_delta(MORPH_POSITION), 1.0);
+    f_normal = transpose(inverse(mat3(model_view))) * (normal + morph_delta(MORPH_NORMAL));
+    f_depth = pos.z;
+    gl_Position = cam.proj * pos;
+}
+        "#
+    }
+}
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+#version 450
+
+layout(location = 0) in vec3 normal;
+layout(location = 1) in float depth;
+
+layout(location = 0) out vec4 f_gbuffer;
+
+void main() {
+    f_gbuffer = vec4(normalize(normal), depth);
+}
+        "#
+    }
+}
+
+mod ssao_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(set = 0, binding = 0) uniform sampler2D gbuffer;
+layout(set = 0, binding = 2, r32f) uniform writeonly image2D target;
+
+layout(push_constant) uniform Ssao {
+    mat4 proj;
+    float radius;
+    float intensity;
+} s;
+
+const uint SAMPLES = 16u;
+
+// stable per pixel noise, the blur hides the pattern
+float hash(uvec3 v) {
+    v = v * 1664525u + 1013904223u;
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    v ^= v >> 16u;
+    v.x += v.y * v.z;
+    return float(v.x) / 4294967295.0;
+}
+
+vec3 view_position(mat4 proj_inv, vec2 uv, float depth) {
+    vec4 ray = proj_inv * vec4(uv * 2.0 - 1.0, 1.0, 1.0);
+    ray.xyz /= ray.w;
+    return ray.xyz * (depth / ray.z);
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(target);
+    if (any(greaterThanEqual(pixel, size))) {
+        return;
+    }
+    vec2 uv = (vec2(pixel) + 0.5) / vec2(size);
+    vec4 center = texture(gbuffer, uv);
+    if (center.a == 0.0) {
+        imageStore(target, pixel, vec4(1.0));
+        return;
+    }
+    vec3 P = view_position(inverse(s.proj), uv, center.a);
+    vec3 N = normalize(center.xyz);
+
+    // randomly rotated tangent frame per pixel
+    vec3 random = normalize(vec3(hash(uvec3(pixel, 0u)), hash(uvec3(pixel, 1u)), 0.5) * 2.0 - 1.0);
+    vec3 T = normalize(random - N * dot(random, N));
+    mat3 tbn = mat3(T, cross(N, T), N);
+
+    float occlusion = 0.0;
+    for (uint i = 0u; i < SAMPLES; i++) {
+        float u = hash(uvec3(pixel, 2u * i + 2u));
+        float v = hash(uvec3(pixel, 2u * i + 3u));
+        float phi = 6.28318530718 * u;
+        float r = sqrt(v);
+        vec3 offset = vec3(r * cos(phi), r * sin(phi), sqrt(1.0 - v));
+        // more samples close to the surface
+        float scale = float(i + 1u) / float(SAMPLES);
+        vec3 S = P + tbn * offset * s.radius * mix(0.1, 1.0, scale * scale);
+
+        vec4 clip = s.proj * vec4(S, 1.0);
+        vec2 sample_uv = clip.xy / clip.w * 0.5 + 0.5;
+        float scene = texture(gbuffer, sample_uv).a;
+        if (scene == 0.0) {
+            continue;
+        }
+        float range = smoothstep(0.0, 1.0, s.radius / abs(P.z - scene));
+        occlusion += (scene < S.z - 0.01 * s.radius ? 1.0 : 0.0) * range;
+    }
+    float visibility = 1.0 - s.intensity * occlusion / float(SAMPLES);
+    imageStore(target, pixel, vec4(clamp(visibility, 0.0, 1.0)));
+}
+        "#
+    }
+}
+
+mod blur_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(set = 0, binding = 0) uniform sampler2D gbuffer;
+layout(set = 0, binding = 1) uniform sampler2D source;
+layout(set = 0, binding = 2, r32f) uniform writeonly image2D target;
+
+layout(push_constant) uniform Ssao {
+    mat4 proj;
+    float radius;
+    float intensity;
+} s;
+
+// 5x5 blur that doesn't bleed across depth edges
+void main() {
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(target);
+    if (any(greaterThanEqual(pixel, size))) {
+        return;
+    }
+    float depth = texelFetch(gbuffer, pixel, 0).a;
+    float sum = 0.0;
+    float weights = 0.0;
+    for (int y = -2; y <= 2; y++) {
+        for (int x = -2; x <= 2; x++) {
+            ivec2 p = clamp(pixel + ivec2(x, y), ivec2(0), size - 1);
+            float d = texelFetch(gbuffer, p, 0).a;
+            float w = 1.0 / (1e-4 + abs(depth - d) / s.radius);
+            sum += texelFetch(source, p, 0).r * w;
+            weights += w;
+        }
+    }
+    imageStore(target, pixel, vec4(sum / weights));
+}
+        "#
+    }
+}
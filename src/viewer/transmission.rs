@@ -0,0 +1,268 @@
+use crate::{
+    Allocators,
+    cubemap::{CubemapPipelineBuilder, CubemapVertexShader, cube::skybox_pipeline_layout},
+    frameinfo::Depth,
+    memory::MemoryCategory,
+    set_layouts::SetLayouts,
+    skybox::loader::gen_mipmaps,
+    vktf::GltfPipeline,
+};
+use std::sync::Arc;
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, RenderPassBeginInfo, SubpassBeginInfo, SubpassEndInfo,
+    },
+    descriptor_set::WriteDescriptorSet,
+    device::DeviceOwned,
+    format::Format,
+    image::{
+        Image, ImageCreateInfo, ImageSubresourceRange, ImageType, ImageUsage,
+        sampler::{
+            Filter, LOD_CLAMP_NONE, Sampler, SamplerAddressMode, SamplerCreateInfo,
+            SamplerMipmapMode,
+        },
+        view::{ImageView, ImageViewCreateInfo},
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryAllocator},
+    pipeline::{
+        GraphicsPipeline,
+        graphics::viewport::{Scissor, Viewport},
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+};
+
+const TRANSMISSION_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+const DEPTH_FORMAT: Format = Format::D32_SFLOAT;
+/// The copy is square, it is sampled with the camera projection so the aspect ratio doesn't matter.
+const TRANSMISSION_SIZE: u32 = 1024;
+
+/// Copy of the opaque scene of one frame.
+struct TransmissionTarget {
+    image: Arc<Image>,
+    view: Arc<ImageView>,
+    framebuffer: Arc<Framebuffer>,
+}
+impl TransmissionTarget {
+    fn new(allocator: Arc<dyn MemoryAllocator>, render_pass: Arc<RenderPass>) -> Self {
+        let mips = TRANSMISSION_SIZE.ilog2() + 1;
+        let image = Image::new(
+            allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: TRANSMISSION_FORMAT,
+                extent: [TRANSMISSION_SIZE, TRANSMISSION_SIZE, 1],
+                mip_levels: mips,
+                usage: ImageUsage::COLOR_ATTACHMENT
+                    | ImageUsage::SAMPLED
+                    | ImageUsage::TRANSFER_SRC
+                    | ImageUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+        let depth = Image::new(
+            allocator,
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: DEPTH_FORMAT,
+                extent: [TRANSMISSION_SIZE, TRANSMISSION_SIZE, 1],
+                usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+
+        let view = ImageView::new_default(image.clone()).unwrap();
+        let attachment = ImageView::new(
+            image.clone(),
+            ImageViewCreateInfo {
+                subresource_range: ImageSubresourceRange {
+                    mip_levels: 0..1,
+                    ..image.subresource_range()
+                },
+                ..ImageViewCreateInfo::from_image(&image)
+            },
+        )
+        .unwrap();
+        let framebuffer = Framebuffer::new(
+            render_pass,
+            FramebufferCreateInfo {
+                attachments: vec![attachment, ImageView::new_default(depth).unwrap()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        Self {
+            image,
+            view,
+            framebuffer,
+        }
+    }
+}
+
+/// Renders the opaque part of the scene so transmissive materials can sample what is behind them.
+pub struct Transmission {
+    pub pipeline: GltfPipeline,
+    pub skybox_pipeline: Arc<GraphicsPipeline>,
+    subpass: Subpass,
+    /// Matches the main render pass since the camera is shared.
+    depth: Depth,
+    sampler: Arc<Sampler>,
+    targets: Vec<TransmissionTarget>,
+    /// Bound in place of the copy while the copy itself is being rendered.
+    empty: Arc<ImageView>,
+}
+impl Transmission {
+    pub fn new(
+        allocators: &Allocators,
+        set_layouts: &SetLayouts,
+        num_frames: usize,
+        depth: Depth,
+    ) -> Self {
+        let device = allocators.mem.device().clone();
+
+        let render_pass = vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    format: TRANSMISSION_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+                depth: {
+                    format: DEPTH_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {depth},
+            },
+        )
+        .unwrap();
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+        let pipeline =
+            GltfPipeline::new(device.clone(), set_layouts.gltf(), subpass.clone(), depth);
+        let skybox_pipeline =
+            CubemapPipelineBuilder::new_cube(CubemapVertexShader::new(device.clone())).build(
+                skybox_pipeline_layout(set_layouts.camera.clone(), set_layouts.texture.clone()),
+                subpass.clone(),
+                depth,
+            );
+
+        let sampler = Sampler::new(
+            device,
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                mipmap_mode: SamplerMipmapMode::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                lod: 0.0..=LOD_CLAMP_NONE,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let targets = (0..num_frames)
+            .map(|_| {
+                TransmissionTarget::new(
+                    allocators.memory.allocator(MemoryCategory::RenderTargets),
+                    render_pass.clone(),
+                )
+            })
+            .collect();
+
+        let empty = Image::new(
+            allocators.mem.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: TRANSMISSION_FORMAT,
+                extent: [1, 1, 1],
+                usage: ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+
+        Self {
+            pipeline,
+            skybox_pipeline,
+            subpass,
+            depth,
+            sampler,
+            targets,
+            empty: ImageView::new_default(empty).unwrap(),
+        }
+    }
+
+    /// Rebuilds the pipelines after the depth of the main render pass changed.
+    pub fn set_depth(&mut self, depth: Depth) {
+        let pipeline = &self.pipeline.pipeline;
+        self.pipeline = GltfPipeline::new(
+            pipeline.device().clone(),
+            pipeline.layout().set_layouts().to_vec(),
+            self.subpass.clone(),
+            depth,
+        );
+        let vertex = CubemapVertexShader::new(pipeline.device().clone());
+        self.skybox_pipeline = CubemapPipelineBuilder::new_cube(vertex).build(
+            self.skybox_pipeline.layout().clone(),
+            self.subpass.clone(),
+            depth,
+        );
+        self.depth = depth;
+    }
+
+    pub fn write(&self, binding: u32, index: usize) -> WriteDescriptorSet {
+        WriteDescriptorSet::image_view_sampler(
+            binding,
+            self.targets[index].view.clone(),
+            self.sampler.clone(),
+        )
+    }
+    pub fn write_empty(&self, binding: u32) -> WriteDescriptorSet {
+        WriteDescriptorSet::image_view_sampler(binding, self.empty.clone(), self.sampler.clone())
+    }
+
+    pub fn begin<L>(&self, builder: &mut AutoCommandBufferBuilder<L>, index: usize) {
+        let size = TRANSMISSION_SIZE as f32;
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![
+                        Some([0.0, 0.0, 0.0, 1.0].into()),
+                        Some(self.depth.clear_value().into()),
+                    ],
+                    ..RenderPassBeginInfo::framebuffer(self.targets[index].framebuffer.clone())
+                },
+                SubpassBeginInfo::default(),
+            )
+            .unwrap()
+            .set_viewport(
+                0,
+                vec![Viewport {
+                    extent: [size, size],
+                    ..Default::default()
+                }]
+                .into(),
+            )
+            .unwrap()
+            .set_scissor(0, vec![Scissor::default()].into())
+            .unwrap();
+    }
+    /// Ends the render pass and generates the mips used for rough transmission.
+    pub fn end<L>(&self, builder: &mut AutoCommandBufferBuilder<L>, index: usize) {
+        builder.end_render_pass(SubpassEndInfo::default()).unwrap();
+        let image = self.targets[index].image.clone();
+        let mips = image.mip_levels();
+        gen_mipmaps(builder, image, mips);
+    }
+}
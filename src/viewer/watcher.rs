@@ -0,0 +1,127 @@
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, channel},
+    time::{Duration, Instant},
+};
+
+/// Exporters often write a file in several steps, wait for them to finish before reloading.
+pub const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Watches the loaded glTF files and their external buffers and images for changes.
+pub struct ModelWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    /// Directories are watched instead of the files so files replaced by a rename are still seen.
+    dirs: HashSet<PathBuf>,
+    /// The models using each file.
+    files: HashMap<PathBuf, Vec<usize>>,
+    changed: BTreeSet<usize>,
+    last_change: Option<Instant>,
+    pub enabled: bool,
+}
+impl ModelWatcher {
+    pub fn new() -> notify::Result<Self> {
+        let (sender, events) = channel();
+        Ok(Self {
+            watcher: notify::recommended_watcher(sender)?,
+            events,
+            dirs: HashSet::new(),
+            files: HashMap::new(),
+            changed: BTreeSet::new(),
+            last_change: None,
+            enabled: true,
+        })
+    }
+
+    /// Replaces the watched files with those of `models`, given as their path and document.
+    pub fn watch<'a>(&mut self, models: impl Iterator<Item = (&'a Path, &'a gltf::Document)>) {
+        for dir in self.dirs.drain() {
+            let _ = self.watcher.unwatch(&dir);
+        }
+        self.files.clear();
+
+        let mut count = 0;
+        for (model, (path, document)) in models.enumerate() {
+            count += 1;
+            for file in model_files(path, document) {
+                let Ok(file) = file.canonicalize() else {
+                    continue;
+                };
+                if let Some(dir) = file.parent() {
+                    if !self.dirs.contains(dir) {
+                        match self.watcher.watch(dir, RecursiveMode::NonRecursive) {
+                            Ok(()) => {
+                                self.dirs.insert(dir.to_owned());
+                            }
+                            Err(err) => log::warn!("failed to watch {}: {err}", dir.display()),
+                        }
+                    }
+                }
+                self.files.entry(file).or_default().push(model);
+            }
+        }
+        self.changed.retain(|model| *model < count);
+    }
+    /// Forgets pending changes, e.g. after the models were reordered.
+    pub fn clear(&mut self) {
+        self.changed.clear();
+        self.last_change = None;
+    }
+
+    /// Returns a model whose files changed once they have settled.
+    pub fn poll(&mut self) -> Option<usize> {
+        for event in self.events.try_iter() {
+            let Ok(event) = event else {
+                continue;
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            for path in &event.paths {
+                if let Some(models) = self.files.get(path) {
+                    self.changed.extend(models);
+                    self.last_change = Some(Instant::now());
+                }
+            }
+        }
+
+        if !self.enabled
+            || self
+                .last_change
+                .is_none_or(|time| time.elapsed() < DEBOUNCE)
+        {
+            return None;
+        }
+        let model = self.changed.pop_first();
+        if self.changed.is_empty() {
+            self.last_change = None;
+        }
+        model
+    }
+}
+
+/// The glTF file and the external files it references.
+fn model_files<'a>(
+    path: &'a Path,
+    document: &'a gltf::Document,
+) -> impl Iterator<Item = PathBuf> + 'a {
+    let base = path.parent().unwrap_or(Path::new(""));
+    let buffers = document
+        .buffers()
+        .filter_map(|buffer| match buffer.source() {
+            gltf::buffer::Source::Uri(uri) => Some(uri),
+            gltf::buffer::Source::Bin => None,
+        });
+    let images = document.images().filter_map(|image| match image.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri),
+        gltf::image::Source::View { .. } => None,
+    });
+    std::iter::once(path.to_owned()).chain(
+        buffers
+            .chain(images)
+            .filter(|uri| !uri.starts_with("data:"))
+            .map(move |uri| base.join(uri)),
+    )
+}
@@ -0,0 +1,175 @@
+use super::{
+    GltfRenderInfo,
+    loader::{LightProperty, MaterialProperty, NodeProperty, PointerTarget},
+    material::MaterialPush,
+};
+use nalgebra_glm as glm;
+use std::collections::BTreeMap;
+
+/// Translation, rotation and scale of an animated node.
+#[derive(Debug, Clone, Copy)]
+struct Trs {
+    translation: glm::Vec3,
+    rotation: glm::Quat,
+    scale: glm::Vec3,
+}
+impl Trs {
+    fn new(node: &gltf::Node) -> Self {
+        let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+        Self {
+            translation: translation.into(),
+            rotation: glm::quat(x, y, z, w),
+            scale: scale.into(),
+        }
+    }
+    fn matrix(&self) -> glm::Mat4 {
+        glm::translation(&self.translation)
+            * glm::quat_to_mat4(&self.rotation)
+            * glm::scaling(&self.scale)
+    }
+}
+
+/// Playback of the `KHR_animation_pointer` animations of a model.
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    /// Index into the pointer animations of the document.
+    pub animation: usize,
+    /// Seconds since the start.
+    pub time: f32,
+    pub playing: bool,
+    pub speed: f32,
+    pub looping: bool,
+    /// The time was changed by hand and has to be applied while paused.
+    pub scrubbed: bool,
+    /// Animated nodes by glTF index, the components that aren't animated keep their loaded value.
+    nodes: BTreeMap<usize, Trs>,
+}
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self {
+            animation: 0,
+            time: 0.0,
+            playing: false,
+            speed: 1.0,
+            looping: true,
+            scrubbed: false,
+            nodes: BTreeMap::new(),
+        }
+    }
+}
+
+impl GltfRenderInfo {
+    /// Advances the selected animation by `dt` seconds and writes its values into the materials,
+    /// nodes, morph weights and lights. Returns the animated camera field of view.
+    pub fn animate(&mut self, dt: f32) -> Option<f32> {
+        let vktf = self.vktf.clone();
+        let animation = vktf.animations.get(self.player.animation)?;
+        let player = &mut self.player;
+        if player.playing {
+            player.time += dt * player.speed;
+            if player.time > animation.duration {
+                if player.looping && animation.duration > 0.0 {
+                    player.time %= animation.duration;
+                } else {
+                    player.time = animation.duration;
+                    player.playing = false;
+                }
+            }
+        } else if !std::mem::take(&mut player.scrubbed) {
+            return None;
+        }
+
+        let mut fov = None;
+        for track in &animation.tracks {
+            let value = track.sample(self.player.time);
+            match track.target {
+                PointerTarget::Material(material, property) => {
+                    if let Some(material) = self.materials.index.get_mut(material) {
+                        apply_material(&mut material.push, property, &value);
+                    }
+                }
+                PointerTarget::Node(node, NodeProperty::Weights) => {
+                    let mesh = vktf.document.nodes().nth(node).and_then(|node| node.mesh());
+                    if let Some(mesh) = mesh {
+                        self.set_weights(mesh.index(), &value);
+                    }
+                }
+                PointerTarget::Node(node, NodeProperty::Visible) => {
+                    if node < self.scene.nodes.len() {
+                        self.scene.set_visible(node, value[0] != 0.0);
+                    }
+                }
+                PointerTarget::Node(node, property) => {
+                    let Some(gltf_node) = vktf.document.nodes().nth(node) else {
+                        continue;
+                    };
+                    let trs = self
+                        .player
+                        .nodes
+                        .entry(node)
+                        .or_insert_with(|| Trs::new(&gltf_node));
+                    match property {
+                        NodeProperty::Translation => trs.translation = glm::make_vec3(&value),
+                        NodeProperty::Rotation => {
+                            trs.rotation = glm::quat(value[0], value[1], value[2], value[3]);
+                        }
+                        _ => trs.scale = glm::make_vec3(&value),
+                    }
+                    let local = trs.matrix();
+                    self.scene.set_local(node, local);
+                }
+                PointerTarget::MeshWeights(mesh) => self.set_weights(mesh, &value),
+                PointerTarget::CameraFov(_) => fov = Some(value[0]),
+                PointerTarget::Light(light, property) => {
+                    // lights are numbered by the nodes using them
+                    let nodes = vktf
+                        .document
+                        .nodes()
+                        .filter(|node| node.light().map(|light| light.index()) == Some(light));
+                    for node in nodes {
+                        let light = self.scene.nodes[node.index()]
+                            .light
+                            .and_then(|light| self.lights.get_mut(light));
+                        let Some(light) = light else {
+                            continue;
+                        };
+                        match property {
+                            LightProperty::Color => light.color = glm::make_vec3(&value),
+                            LightProperty::Intensity => light.intensity = value[0],
+                            LightProperty::Range => light.range = value[0],
+                        }
+                    }
+                }
+            }
+        }
+        fov
+    }
+    fn set_weights(&mut self, mesh: usize, weights: &[f32]) {
+        let morph = self
+            .meshes
+            .iter_mut()
+            .find(|m| m.index == mesh)
+            .and_then(|mesh| mesh.morph.as_mut());
+        if let Some(morph) = morph {
+            for (dst, src) in morph.weights.iter_mut().zip(weights) {
+                *dst = *src;
+            }
+        }
+    }
+}
+
+fn apply_material(push: &mut MaterialPush, property: MaterialProperty, value: &[f32]) {
+    match property {
+        MaterialProperty::BaseColor => push.bc = glm::make_vec4(value),
+        MaterialProperty::Emissive => push.em = glm::make_vec3(value),
+        MaterialProperty::Roughness => push.rm.x = value[0],
+        MaterialProperty::Metallic => push.rm.y = value[0],
+        MaterialProperty::NormalScale => push.nm = value[0],
+        MaterialProperty::OcclusionStrength => push.ao = value[0],
+        MaterialProperty::Transmission => push.tr = value[0],
+        MaterialProperty::Thickness => push.th = value[0],
+        MaterialProperty::AttenuationColor => push.at = glm::make_vec3(value),
+        MaterialProperty::AttenuationDistance => push.ad = value[0],
+        MaterialProperty::Ior => push.ior = value[0],
+    }
+}
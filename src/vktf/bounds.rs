@@ -0,0 +1,94 @@
+use nalgebra_glm as glm;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+}
+impl Aabb {
+    pub fn empty() -> Self {
+        Self {
+            min: glm::Vec3::repeat(f32::INFINITY),
+            max: glm::Vec3::repeat(f32::NEG_INFINITY),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+    pub fn extend(&mut self, point: &glm::Vec3) {
+        self.min = glm::min2(&self.min, point);
+        self.max = glm::max2(&self.max, point);
+    }
+    pub fn union(&mut self, other: &Aabb) {
+        if !other.is_empty() {
+            self.extend(&other.min);
+            self.extend(&other.max);
+        }
+    }
+    /// The axis aligned box containing all 8 transformed corners.
+    pub fn transform(&self, transform: &glm::Mat4) -> Self {
+        let mut aabb = Self::empty();
+        if self.is_empty() {
+            return aabb;
+        }
+        for corner in self.corners() {
+            aabb.extend(&(transform * corner.push(1.0)).xyz());
+        }
+        aabb
+    }
+    /// Fraction of the screen covered by the box, `1` if the camera is inside it.
+    pub fn screen_coverage(&self, view_proj: &glm::Mat4) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let mut min = glm::Vec2::repeat(f32::INFINITY);
+        let mut max = glm::Vec2::repeat(f32::NEG_INFINITY);
+        let mut behind = 0;
+        for corner in self.corners() {
+            let clip = view_proj * corner.push(1.0);
+            if clip.w <= 0.0 {
+                behind += 1;
+                continue;
+            }
+            let ndc = clip.xy() / clip.w;
+            min = glm::min2(&min, &ndc);
+            max = glm::max2(&max, &ndc);
+        }
+        match behind {
+            0 => {}
+            8 => return 0.0,
+            _ => return 1.0,
+        }
+        let size = glm::clamp(&max, -1.0, 1.0) - glm::clamp(&min, -1.0, 1.0);
+        size.x.max(0.0) * size.y.max(0.0) / 4.0
+    }
+    fn corners(&self) -> impl Iterator<Item = glm::Vec3> {
+        (0..8).map(|i| {
+            glm::vec3(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        })
+    }
+    pub fn center(&self) -> glm::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+    /// Radius of the bounding sphere around [`Aabb::center`].
+    pub fn radius(&self) -> f32 {
+        (self.max - self.min).norm() * 0.5
+    }
+}
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+impl From<gltf::mesh::Bounds<[f32; 3]>> for Aabb {
+    fn from(value: gltf::mesh::Bounds<[f32; 3]>) -> Self {
+        Self {
+            min: value.min.into(),
+            max: value.max.into(),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use super::loader::{Anisotropy, NormalMipmaps};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+};
+use vulkano::{
+    descriptor_set::{
+        DescriptorSet, WriteDescriptorSet, allocator::DescriptorSetAllocator,
+        layout::DescriptorSetLayout,
+    },
+    device::Device,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode},
+        view::ImageView,
+    },
+};
+
+/// The parts of a sampler that differ between glTF samplers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerKey {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    pub mipmap_mode: SamplerMipmapMode,
+    pub address_mode: [SamplerAddressMode; 3],
+    pub anisotropy: Anisotropy,
+}
+
+/// An image view and the sampler it is read with.
+pub type TextureBinding = (Arc<ImageView>, Arc<Sampler>);
+
+/// Identifies a descriptor set by the layout and the objects written to it.
+/// The addresses stay valid as the cached set keeps the objects alive.
+type SetKey = (usize, Vec<(usize, usize)>);
+
+/// Samplers and material descriptor sets shared between all loaded models.
+/// Clones share the same caches.
+#[derive(Clone, Default)]
+pub struct VktfCache {
+    samplers: Arc<Mutex<HashMap<SamplerKey, Arc<Sampler>>>>,
+    sets: Arc<Mutex<HashMap<SetKey, Arc<DescriptorSet>>>>,
+    normal_mipmaps: Arc<OnceLock<NormalMipmaps>>,
+}
+impl VktfCache {
+    pub fn sampler(&self, device: &Arc<Device>, key: SamplerKey) -> Arc<Sampler> {
+        self.samplers
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| {
+                // blocky nearest filtered textures are meant to stay that way
+                let anisotropy = (key.min_filter == Filter::Linear)
+                    .then(|| key.anisotropy.max_anisotropy(device))
+                    .flatten();
+                Sampler::new(
+                    device.clone(),
+                    SamplerCreateInfo {
+                        mag_filter: key.mag_filter,
+                        min_filter: key.min_filter,
+                        mipmap_mode: key.mipmap_mode,
+                        address_mode: key.address_mode,
+                        anisotropy,
+                        ..SamplerCreateInfo::simple_repeat_linear()
+                    },
+                )
+                .unwrap()
+            })
+            .clone()
+    }
+    /// Reuses the set if another material binds the same textures,
+    /// binding `i` of the set is `textures[i]`.
+    pub fn material_set(
+        &self,
+        allocator: Arc<dyn DescriptorSetAllocator>,
+        layout: Arc<DescriptorSetLayout>,
+        textures: Vec<TextureBinding>,
+    ) -> Arc<DescriptorSet> {
+        let key = (
+            Arc::as_ptr(&layout) as usize,
+            textures
+                .iter()
+                .map(|(view, sampler)| (Arc::as_ptr(view) as usize, Arc::as_ptr(sampler) as usize))
+                .collect(),
+        );
+        let mut sets = self.sets.lock().unwrap();
+        if let Some(set) = sets.get(&key) {
+            return set.clone();
+        }
+        // sets no longer used by any material go back to the pool
+        sets.retain(|_, set| Arc::strong_count(set) > 1);
+
+        let set = DescriptorSet::new(
+            allocator,
+            layout,
+            textures
+                .into_iter()
+                .enumerate()
+                .map(|(binding, (view, sampler))| {
+                    WriteDescriptorSet::image_view_sampler(binding as u32, view, sampler)
+                }),
+            [],
+        )
+        .unwrap();
+        sets.insert(key, set.clone());
+        set
+    }
+    /// Created the first time a normal map is loaded.
+    pub fn normal_mipmaps(&self, device: &Arc<Device>) -> &NormalMipmaps {
+        self.normal_mipmaps
+            .get_or_init(|| NormalMipmaps::new(device.clone()))
+    }
+    /// Number of cached samplers and descriptor sets.
+    pub fn counts(&self) -> (usize, usize) {
+        (
+            self.samplers.lock().unwrap().len(),
+            self.sets.lock().unwrap().len(),
+        )
+    }
+}
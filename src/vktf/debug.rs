@@ -0,0 +1,78 @@
+use vulkano::buffer::BufferContents;
+
+/// Intermediate shading inputs that can be output instead of the final colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebugView {
+    #[default]
+    None = 0,
+    BaseColor,
+    Normal,
+    GeometryNormal,
+    Tangent,
+    Handedness,
+    Uv0,
+    Uv1,
+    Metallic,
+    Roughness,
+    Occlusion,
+    Emissive,
+    /// The lit colour before tone mapping in bands of one EV.
+    Exposure,
+}
+impl DebugView {
+    pub const ALL: [DebugView; 13] = [
+        DebugView::None,
+        DebugView::BaseColor,
+        DebugView::Normal,
+        DebugView::GeometryNormal,
+        DebugView::Tangent,
+        DebugView::Handedness,
+        DebugView::Uv0,
+        DebugView::Uv1,
+        DebugView::Metallic,
+        DebugView::Roughness,
+        DebugView::Occlusion,
+        DebugView::Emissive,
+        DebugView::Exposure,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DebugView::None => "None",
+            DebugView::BaseColor => "Base colour",
+            DebugView::Normal => "Shading normal",
+            DebugView::GeometryNormal => "Geometry normal",
+            DebugView::Tangent => "Tangent",
+            DebugView::Handedness => "Tangent handedness",
+            DebugView::Uv0 => "UV 0",
+            DebugView::Uv1 => "UV 1",
+            DebugView::Metallic => "Metallic",
+            DebugView::Roughness => "Roughness",
+            DebugView::Occlusion => "Occlusion",
+            DebugView::Emissive => "Emissive",
+            DebugView::Exposure => "False colour EV",
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Debug view")
+            .selected_text(self.name())
+            .show_ui(ui, |ui| {
+                for view in Self::ALL {
+                    ui.selectable_value(self, view, view.name());
+                }
+            });
+    }
+}
+
+/// Pushed after [`MaterialPush`](super::material::MaterialPush) once per frame.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, BufferContents)]
+pub struct DebugPush {
+    pub view: i32,
+}
+impl From<DebugView> for DebugPush {
+    fn from(value: DebugView) -> Self {
+        Self { view: value as i32 }
+    }
+}
@@ -0,0 +1,372 @@
+use super::{
+    GltfRenderInfo,
+    loader::{LoadGltfError, PrimitiveGeometry, VktfDocument},
+};
+use nalgebra_glm as glm;
+use rayon::prelude::*;
+use std::sync::Arc;
+
+/// Cells along the longest side of the grid at most.
+const MAX_CELLS: f32 = 256.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DeviationError {
+    #[error(transparent)]
+    Load(#[from] LoadGltfError),
+    #[error("the reference has no triangles")]
+    NoReference,
+}
+
+/// A model as it was placed in the scene when the measurement started.
+pub struct PlacedModel {
+    vktf: Arc<VktfDocument>,
+    /// The world transforms of the instances of every mesh.
+    meshes: Vec<(usize, Vec<glm::Mat4>)>,
+}
+impl PlacedModel {
+    pub fn new(info: &GltfRenderInfo) -> Self {
+        let root = info.transform().matrix();
+        let meshes = info
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let transforms = mesh.transforms().iter().map(|t| root * t).collect();
+                (mesh.index, transforms)
+            })
+            .collect();
+        Self {
+            vktf: info.vktf.clone(),
+            meshes,
+        }
+    }
+    /// Reads the file again for the triangles of every instance in world space.
+    pub(crate) fn read_triangles(&self) -> Result<Vec<Triangle>, LoadGltfError> {
+        Ok(self.triangles(&self.vktf.read_geometry()?))
+    }
+    /// The triangles of every instance in world space.
+    fn triangles(&self, geometry: &[Vec<PrimitiveGeometry>]) -> Vec<Triangle> {
+        let mut triangles = vec![];
+        for (mesh, transforms) in &self.meshes {
+            for primitive in geometry[*mesh].iter().filter(|p| p.triangles) {
+                for transform in transforms {
+                    let normal_matrix = normal_matrix(transform);
+                    let vertices: Vec<_> = primitive
+                        .vertices
+                        .iter()
+                        .map(|vertex| {
+                            (
+                                (transform * vertex.position.push(1.0)).xyz(),
+                                normal_matrix * vertex.normal,
+                            )
+                        })
+                        .collect();
+                    triangles.extend(primitive.corners().map(|corners| {
+                        let [a, b, c] = corners.map(|i| vertices[i as usize]);
+                        Triangle {
+                            positions: [a.0, b.0, c.0],
+                            normals: [a.1, b.1, c.1],
+                        }
+                    }));
+                }
+            }
+        }
+        triangles
+    }
+}
+
+/// How far the surface of a candidate model is from a reference, measured at the vertices of
+/// the candidate in its rest pose.
+pub struct Deviation {
+    /// The distance to the closest point of the reference and the angle between the normals
+    /// there in degrees, for every vertex of the candidate's geometry buffer.
+    /// Vertices of an instanced mesh keep the largest deviation of its instances.
+    pub vertices: Vec<[f32; 2]>,
+    pub max: [f32; 2],
+    pub mean: [f32; 2],
+}
+
+/// Reads both files again and measures the candidate against the reference,
+/// which can take a while for scans.
+pub fn measure(
+    reference: &PlacedModel,
+    candidate: &PlacedModel,
+) -> Result<Deviation, DeviationError> {
+    let triangles = reference.triangles(&reference.vktf.read_geometry()?);
+    if triangles.is_empty() {
+        return Err(DeviationError::NoReference);
+    }
+    let grid = TriangleGrid::new(triangles);
+
+    let geometry = candidate.vktf.read_geometry()?;
+    let mut offsets = vec![];
+    let mut count = 0;
+    for primitives in &geometry {
+        let mesh: Vec<_> = primitives
+            .iter()
+            .map(|primitive| {
+                let offset = count;
+                count += primitive.vertices.len();
+                offset
+            })
+            .collect();
+        offsets.push(mesh);
+    }
+
+    let mut vertices = vec![[0.0f32; 2]; count];
+    let mut max = [0.0f32; 2];
+    let mut sum = [0.0f64; 2];
+    let mut measured = 0;
+    for (mesh, transforms) in &candidate.meshes {
+        let transforms: Vec<_> = transforms.iter().map(|t| (t, normal_matrix(t))).collect();
+        for (primitive, offset) in geometry[*mesh].iter().zip(&offsets[*mesh]) {
+            if !primitive.triangles || transforms.is_empty() {
+                continue;
+            }
+            let deviations: Vec<[f32; 2]> = primitive
+                .vertices
+                .par_iter()
+                .map(|vertex| {
+                    transforms
+                        .iter()
+                        .map(|(transform, normal_matrix)| {
+                            let position = (*transform * vertex.position.push(1.0)).xyz();
+                            grid.deviation(&position, &(normal_matrix * vertex.normal))
+                        })
+                        .fold([0.0, 0.0], |a, b| [a[0].max(b[0]), a[1].max(b[1])])
+                })
+                .collect();
+            for deviation in &deviations {
+                for (i, value) in deviation.iter().enumerate() {
+                    max[i] = max[i].max(*value);
+                    sum[i] += *value as f64;
+                }
+            }
+            measured += deviations.len();
+            vertices[*offset..*offset + deviations.len()].copy_from_slice(&deviations);
+        }
+    }
+    let mean = sum.map(|sum| (sum / measured.max(1) as f64) as f32);
+    Ok(Deviation {
+        vertices,
+        max,
+        mean,
+    })
+}
+
+fn normal_matrix(transform: &glm::Mat4) -> glm::Mat3 {
+    glm::mat4_to_mat3(&glm::inverse_transpose(*transform))
+}
+
+pub(crate) struct Triangle {
+    positions: [glm::Vec3; 3],
+    normals: [glm::Vec3; 3],
+}
+
+/// Triangles sorted into the cells of a uniform grid they overlap, so the closest one
+/// to a point is found by searching the cells in growing rings around it.
+pub(crate) struct TriangleGrid {
+    triangles: Vec<Triangle>,
+    min: glm::Vec3,
+    cell: f32,
+    size: [usize; 3],
+    /// Triangle indices by cell.
+    cells: Vec<Vec<u32>>,
+}
+impl TriangleGrid {
+    pub(crate) fn new(triangles: Vec<Triangle>) -> Self {
+        let mut min = glm::Vec3::repeat(f32::INFINITY);
+        let mut max = glm::Vec3::repeat(f32::NEG_INFINITY);
+        for position in triangles.iter().flat_map(|t| &t.positions) {
+            min = min.inf(position);
+            max = max.sup(position);
+        }
+        let extent = max - min;
+        let longest = extent.max();
+        // about one triangle per cell, flat models still get a few cells across
+        let padded = extent.map(|e| e.max(longest / 64.0));
+        let volume = padded.x * padded.y * padded.z;
+        let cell = (volume / triangles.len() as f32)
+            .cbrt()
+            .max(longest / MAX_CELLS);
+        let cell = if cell.is_normal() { cell } else { 1.0 };
+        let size = [0, 1, 2].map(|i| (extent[i] / cell) as usize + 1);
+
+        let mut grid = Self {
+            triangles: vec![],
+            min,
+            cell,
+            size,
+            cells: vec![vec![]; size[0] * size[1] * size[2]],
+        };
+        for (index, triangle) in triangles.iter().enumerate() {
+            let [a, b, c] = &triangle.positions;
+            let lo = grid.coords(&a.inf(b).inf(c));
+            let hi = grid.coords(&a.sup(b).sup(c));
+            for z in lo[2]..=hi[2] {
+                for y in lo[1]..=hi[1] {
+                    for x in lo[0]..=hi[0] {
+                        let cell = grid.index([x, y, z]);
+                        grid.cells[cell].push(index as u32);
+                    }
+                }
+            }
+        }
+        grid.triangles = triangles;
+        grid
+    }
+    /// The cell of a point, points outside are moved into the grid.
+    fn coords(&self, point: &glm::Vec3) -> [usize; 3] {
+        [0, 1, 2].map(|i| {
+            let cell = ((point[i] - self.min[i]) / self.cell).max(0.0) as usize;
+            cell.min(self.size[i] - 1)
+        })
+    }
+    fn index(&self, [x, y, z]: [usize; 3]) -> usize {
+        (z * self.size[1] + y) * self.size[0] + x
+    }
+    /// The distance to the closest triangle and the angle in degrees between `normal`
+    /// and the normal interpolated there.
+    fn deviation(&self, point: &glm::Vec3, normal: &glm::Vec3) -> [f32; 2] {
+        let center = self.coords(point);
+        let mut best: Option<(f32, usize, glm::Vec3)> = None;
+        let rings = self.size.into_iter().max().unwrap();
+        for ring in 0..rings {
+            // every cell of a farther ring is at least this far away
+            if best.is_some_and(|(distance, ..)| distance <= (ring as f32 - 1.0) * self.cell) {
+                break;
+            }
+            self.ring(center, ring, |cell| {
+                for &index in &self.cells[cell] {
+                    let triangle = &self.triangles[index as usize];
+                    let weights = closest_point(point, &triangle.positions);
+                    let closest = triangle.positions[0] * weights.x
+                        + triangle.positions[1] * weights.y
+                        + triangle.positions[2] * weights.z;
+                    let distance = glm::distance(point, &closest);
+                    if best.is_none_or(|(best, ..)| distance < best) {
+                        best = Some((distance, index as usize, weights));
+                    }
+                }
+            });
+        }
+        let Some((distance, index, weights)) = best else {
+            return [0.0, 0.0];
+        };
+        let normals = &self.triangles[index].normals;
+        let reference = normals[0] * weights.x + normals[1] * weights.y + normals[2] * weights.z;
+        let angle = match (
+            normal.try_normalize(f32::EPSILON),
+            reference.try_normalize(f32::EPSILON),
+        ) {
+            (Some(a), Some(b)) => a.dot(&b).clamp(-1.0, 1.0).acos().to_degrees(),
+            _ => 0.0,
+        };
+        [distance, angle]
+    }
+    /// Moves a sphere at `center` with `radius` out of the triangles it intersects, one after
+    /// another from the closest, and returns its new center.
+    pub(crate) fn push_out(&self, center: &glm::Vec3, radius: f32) -> glm::Vec3 {
+        let max = self.min + glm::Vec3::from(self.size.map(|size| size as f32)) * self.cell;
+        let outside =
+            (0..3).any(|i| center[i] + radius < self.min[i] || center[i] - radius > max[i]);
+        if outside {
+            return *center;
+        }
+        let lo = self.coords(&center.add_scalar(-radius));
+        let hi = self.coords(&center.add_scalar(radius));
+        let mut indices = vec![];
+        for z in lo[2]..=hi[2] {
+            for y in lo[1]..=hi[1] {
+                for x in lo[0]..=hi[0] {
+                    indices.extend_from_slice(&self.cells[self.index([x, y, z])]);
+                }
+            }
+        }
+        indices.sort_unstable();
+        indices.dedup();
+
+        let closest = |center: &glm::Vec3, index: u32| {
+            let positions = &self.triangles[index as usize].positions;
+            let weights = closest_point(center, positions);
+            positions[0] * weights.x + positions[1] * weights.y + positions[2] * weights.z
+        };
+        indices
+            .sort_by_cached_key(|&index| glm::distance2(center, &closest(center, index)).to_bits());
+        let mut center = *center;
+        for index in indices {
+            let offset = center - closest(&center, index);
+            let distance = offset.norm();
+            // a center exactly on the surface has no side to be pushed to
+            if distance < radius && distance > f32::EPSILON {
+                center += offset * ((radius - distance) / distance);
+            }
+        }
+        center
+    }
+    /// Calls `f` with the cells exactly `ring` cells away from `center` along some axis.
+    fn ring(&self, center: [usize; 3], ring: usize, mut f: impl FnMut(usize)) {
+        let range =
+            |i: usize| center[i].saturating_sub(ring)..=(center[i] + ring).min(self.size[i] - 1);
+        for z in range(2) {
+            for y in range(1) {
+                for x in range(0) {
+                    let on_ring = [x, y, z]
+                        .into_iter()
+                        .zip(center)
+                        .any(|(c, center)| c.abs_diff(center) == ring);
+                    if on_ring {
+                        f(self.index([x, y, z]));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The barycentric weights of the point of a triangle closest to `p`,
+/// after Real-Time Collision Detection by Christer Ericson.
+fn closest_point(p: &glm::Vec3, [a, b, c]: &[glm::Vec3; 3]) -> glm::Vec3 {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(&ap);
+    let d2 = ac.dot(&ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return glm::vec3(1.0, 0.0, 0.0);
+    }
+    let bp = p - b;
+    let d3 = ab.dot(&bp);
+    let d4 = ac.dot(&bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return glm::vec3(0.0, 1.0, 0.0);
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        return glm::vec3(1.0 - v, v, 0.0);
+    }
+    let cp = p - c;
+    let d5 = ab.dot(&cp);
+    let d6 = ac.dot(&cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return glm::vec3(0.0, 0.0, 1.0);
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        return glm::vec3(1.0 - w, 0.0, w);
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return glm::vec3(0.0, 1.0 - w, w);
+    }
+    let denom = 1.0 / (va + vb + vc);
+    if !denom.is_finite() {
+        // degenerate triangle
+        return glm::vec3(1.0, 0.0, 0.0);
+    }
+    let v = vb * denom;
+    let w = vc * denom;
+    glm::vec3(1.0 - v - w, v, w)
+}
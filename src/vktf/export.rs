@@ -0,0 +1,265 @@
+use super::{
+    GltfRenderInfo,
+    loader::{LoadGltfError, is_remote},
+    material::MaterialPush,
+};
+use base64::Engine;
+use gltf::json::{self, Index, validation::USize64};
+use nalgebra_glm as glm;
+use std::{borrow::Cow, path::Path};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error(transparent)]
+    Gltf(#[from] gltf::Error),
+    #[error(transparent)]
+    Load(#[from] LoadGltfError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("failed to read image {index}: {source}")]
+    Image {
+        index: usize,
+        source: std::io::Error,
+    },
+    #[error("image {0} has an invalid data URI")]
+    DataUri(usize),
+    #[error("the binary chunk is larger than 4 GiB")]
+    TooLarge,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Writes the model with the edited material factors as a .glb,
+/// with every buffer and image embedded in the binary chunk.
+/// `KHR_animation_pointer` channels are not written. With `bake_pose` the nodes and morph
+/// weights are written as they are shown and the animations are left out.
+pub fn export_glb(info: &GltfRenderInfo, path: &Path, bake_pose: bool) -> Result<(), ExportError> {
+    let source = info.vktf.path.as_path();
+    let base = source.parent();
+    let blob = info.vktf.reopen()?.0.blob;
+    let resolver = info.vktf.resolver(None);
+    let buffers = resolver.import_buffers(&info.vktf.document, blob)?;
+
+    let mut root = info.vktf.document.clone().into_json();
+    for (material, edited) in root.materials.iter_mut().zip(&info.materials.index) {
+        apply_material(material, &edited.push, &mut root.extensions_used);
+    }
+    if bake_pose {
+        apply_pose(&mut root, info);
+    }
+
+    // every buffer is copied into the binary chunk, the views are moved along
+    let mut bin = vec![];
+    let mut offsets = vec![];
+    for data in &buffers {
+        offsets.push(bin.len() as u64);
+        bin.extend_from_slice(data);
+        pad(&mut bin);
+    }
+    for view in &mut root.buffer_views {
+        let offset = offsets[view.buffer.value()] + view.byte_offset.map_or(0, |offset| offset.0);
+        view.buffer = Index::new(0);
+        view.byte_offset = Some(USize64(offset));
+    }
+
+    // images referenced by URI become buffer views too
+    for (index, image) in root.images.iter_mut().enumerate() {
+        let Some(uri) = image.uri.take() else {
+            continue;
+        };
+        let (bytes, mime_type) = if is_remote(&uri) {
+            (resolver.read(&uri)?, None)
+        } else {
+            read_uri(&uri, base, index)?
+        };
+        let offset = bin.len() as u64;
+        bin.extend_from_slice(&bytes);
+        pad(&mut bin);
+        image.buffer_view = Some(Index::new(root.buffer_views.len() as u32));
+        image.mime_type = mime_type
+            .or_else(|| image.mime_type.take().map(|mime_type| mime_type.0))
+            .or_else(|| guess_mime_type(&uri))
+            .map(json::image::MimeType);
+        root.buffer_views.push(json::buffer::View {
+            buffer: Index::new(0),
+            byte_length: USize64(bytes.len() as u64),
+            byte_offset: Some(USize64(offset)),
+            byte_stride: None,
+            name: None,
+            target: None,
+            extensions: Default::default(),
+            extras: Default::default(),
+        });
+    }
+
+    root.buffers = if bin.is_empty() {
+        vec![]
+    } else {
+        vec![json::Buffer {
+            byte_length: USize64(bin.len() as u64),
+            name: None,
+            uri: None,
+            extensions: Default::default(),
+            extras: Default::default(),
+        }]
+    };
+
+    let json = serde_json::to_vec(&root)?;
+    // the chunks are padded to four bytes when written
+    let length = 12 + 8 + json.len().next_multiple_of(4) + 8 + bin.len();
+    let glb = gltf::binary::Glb {
+        header: gltf::binary::Header {
+            magic: *b"glTF",
+            version: 2,
+            length: length.try_into().map_err(|_| ExportError::TooLarge)?,
+        },
+        json: Cow::Owned(json),
+        bin: (!bin.is_empty()).then_some(Cow::Owned(bin)),
+    };
+    glb.to_writer(std::fs::File::create(path)?)?;
+    Ok(())
+}
+
+/// Writes the factors of the UI back, textures are left as they are.
+fn apply_material(
+    material: &mut json::Material,
+    push: &MaterialPush,
+    extensions_used: &mut Vec<String>,
+) {
+    let spec_gloss = material
+        .extensions
+        .as_mut()
+        .and_then(|extensions| extensions.pbr_specular_glossiness.as_mut());
+    match spec_gloss {
+        // roughness and metallic were converted and can't be written back
+        Some(sg) => {
+            sg.diffuse_factor = json::extensions::material::PbrDiffuseFactor(push.bc.into())
+        }
+        None => {
+            let pbr = &mut material.pbr_metallic_roughness;
+            pbr.base_color_factor = json::material::PbrBaseColorFactor(push.bc.into());
+            pbr.roughness_factor = json::material::StrengthFactor(push.rm.x);
+            pbr.metallic_factor = json::material::StrengthFactor(push.rm.y);
+        }
+    }
+    material.emissive_factor = json::material::EmissiveFactor(push.em.into());
+    if let Some(occlusion) = &mut material.occlusion_texture {
+        occlusion.strength = json::material::StrengthFactor(push.ao);
+    }
+    if let Some(normal) = &mut material.normal_texture {
+        normal.scale = push.nm;
+    }
+
+    // extensions are only added for values that differ from the defaults
+    if material.extensions.is_none() && push.tr == 0.0 && push.th == 0.0 && push.ior == 1.5 {
+        return;
+    }
+    let extensions = material.extensions.get_or_insert_with(Default::default);
+
+    if push.tr > 0.0 || extensions.transmission.is_some() {
+        let transmission = extensions.transmission.get_or_insert_with(Default::default);
+        transmission.transmission_factor = json::extensions::material::TransmissionFactor(push.tr);
+        use_extension(extensions_used, "KHR_materials_transmission");
+    }
+    if push.th > 0.0 || extensions.volume.is_some() {
+        let volume = extensions.volume.get_or_insert_with(Default::default);
+        volume.thickness_factor = json::extensions::material::ThicknessFactor(push.th);
+        volume.attenuation_color = json::extensions::material::AttenuationColor(push.at.into());
+        // zero means no attenuation, which glTF stores as the default
+        if push.ad > 0.0 {
+            volume.attenuation_distance = json::extensions::material::AttenuationDistance(push.ad);
+        }
+        use_extension(extensions_used, "KHR_materials_volume");
+    }
+    if push.ior != 1.5 || extensions.ior.is_some() {
+        let ior = extensions.ior.get_or_insert_with(Default::default);
+        ior.ior = json::extensions::material::IndexOfRefraction(push.ior);
+        use_extension(extensions_used, "KHR_materials_ior");
+    }
+}
+
+/// Replaces the rest pose with the current node transforms and morph weights, including
+/// the node edits, and removes the animations that would move them again.
+fn apply_pose(root: &mut json::Root, info: &GltfRenderInfo) {
+    let nodes = info.scene.nodes.iter().zip(info.vktf.document.nodes());
+    for (node, (shown, loaded)) in root.nodes.iter_mut().zip(nodes) {
+        if glm::Mat4::from(loaded.transform().matrix()) == shown.local {
+            continue;
+        }
+        let matrix = shown.local.into();
+        let (translation, rotation, scale) = gltf::scene::Transform::Matrix { matrix }.decomposed();
+        node.matrix = None;
+        node.translation = Some(translation);
+        node.rotation = Some(json::scene::UnitQuaternion(rotation));
+        node.scale = Some(scale);
+    }
+
+    // the viewer keeps one set of weights per mesh, weights of the nodes would override them
+    let mut morphed = vec![];
+    for mesh in &info.meshes {
+        let (Some(morph), Some(json_mesh)) = (&mesh.morph, root.meshes.get_mut(mesh.index)) else {
+            continue;
+        };
+        json_mesh.weights = Some(morph.weights.clone());
+        morphed.push(mesh.index);
+    }
+    for node in &mut root.nodes {
+        if node
+            .mesh
+            .is_some_and(|mesh| morphed.contains(&mesh.value()))
+        {
+            node.weights = None;
+        }
+    }
+
+    root.animations.clear();
+    root.extensions_used
+        .retain(|extension| extension != "KHR_animation_pointer");
+    root.extensions_required
+        .retain(|extension| extension != "KHR_animation_pointer");
+}
+
+fn use_extension(extensions_used: &mut Vec<String>, name: &str) {
+    if !extensions_used.iter().any(|used| used == name) {
+        extensions_used.push(name.to_owned());
+    }
+}
+
+/// The bytes of a data URI or a file next to the model, and the MIME type if the URI has one.
+fn read_uri(
+    uri: &str,
+    base: Option<&Path>,
+    index: usize,
+) -> Result<(Vec<u8>, Option<String>), ExportError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (mime_type, data) = data
+            .split_once(";base64,")
+            .ok_or(ExportError::DataUri(index))?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .map_err(|_| ExportError::DataUri(index))?;
+        return Ok((bytes, Some(mime_type.to_owned()).filter(|m| !m.is_empty())));
+    }
+    let uri = urlencoding::decode(uri).unwrap_or(Cow::Borrowed(uri));
+    let path = base.unwrap_or(Path::new("")).join(uri.as_ref());
+    let bytes = std::fs::read(path).map_err(|source| ExportError::Image { index, source })?;
+    Ok((bytes, None))
+}
+
+/// Embedded images need a MIME type.
+fn guess_mime_type(uri: &str) -> Option<String> {
+    let extension = Path::new(uri).extension()?.to_str()?.to_ascii_lowercase();
+    let mime_type = match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "ktx2" => "image/ktx2",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        _ => return None,
+    };
+    Some(mime_type.to_owned())
+}
+
+fn pad(bin: &mut Vec<u8>) {
+    bin.resize(bin.len().next_multiple_of(4), 0);
+}
@@ -0,0 +1,143 @@
+use nalgebra_glm as glm;
+use vulkano::buffer::BufferContents;
+
+pub const MAX_LIGHTS: usize = 16;
+
+pub const LIGHT_DIRECTIONAL: i32 = 0;
+pub const LIGHT_POINT: i32 = 1;
+pub const LIGHT_SPOT: i32 = 2;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, BufferContents)]
+pub struct Light {
+    pub position: glm::Vec3,
+    /// `0.0` means infinite range
+    pub range: f32,
+    pub direction: glm::Vec3,
+    pub intensity: f32,
+    pub color: glm::Vec3,
+    pub kind: i32,
+    pub inner_cone_cos: f32,
+    pub outer_cone_cos: f32,
+    _pad: [f32; 2],
+}
+impl Light {
+    pub fn new(light: &gltf::khr_lights_punctual::Light, transform: &glm::Mat4) -> Self {
+        let mut slf = Self {
+            range: light.range().unwrap_or(0.0),
+            intensity: light.intensity(),
+            color: light.color().into(),
+            ..Default::default()
+        };
+        match light.kind() {
+            gltf::khr_lights_punctual::Kind::Directional => {
+                slf.kind = LIGHT_DIRECTIONAL;
+            }
+            gltf::khr_lights_punctual::Kind::Point => {
+                slf.kind = LIGHT_POINT;
+            }
+            gltf::khr_lights_punctual::Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                slf.kind = LIGHT_SPOT;
+                slf.inner_cone_cos = inner_cone_angle.cos();
+                slf.outer_cone_cos = outer_cone_angle.cos();
+            }
+        }
+        slf.place(transform);
+
+        slf
+    }
+    /// Moves the light to the node transform, lights point down the node's -Z axis.
+    pub fn place(&mut self, transform: &glm::Mat4) {
+        self.position = transform.column(3).xyz();
+        self.direction = transform.transform_vector(&-glm::Vec3::z()).normalize();
+    }
+    pub fn sun() -> Self {
+        Self {
+            direction: glm::vec3(-0.5, -1.0, -0.3).normalize(),
+            intensity: 3.0,
+            ..Default::default()
+        }
+    }
+    /// The light moved by a model's root transform.
+    pub fn transformed(&self, transform: &glm::Mat4) -> Self {
+        Self {
+            position: (transform * self.position.push(1.0)).xyz(),
+            direction: transform.transform_vector(&self.direction).normalize(),
+            ..*self
+        }
+    }
+    pub fn kind_name(&self) -> &'static str {
+        match self.kind {
+            LIGHT_DIRECTIONAL => "Directional",
+            LIGHT_POINT => "Point",
+            LIGHT_SPOT => "Spot",
+            _ => "Unknown",
+        }
+    }
+}
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            position: glm::Vec3::zeros(),
+            range: 0.0,
+            direction: -glm::Vec3::z(),
+            intensity: 1.0,
+            color: glm::vec3(1.0, 1.0, 1.0),
+            kind: LIGHT_DIRECTIONAL,
+            inner_cone_cos: 1.0,
+            outer_cone_cos: std::f32::consts::FRAC_PI_4.cos(),
+            _pad: [0.0; 2],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, BufferContents)]
+pub struct LightsUniform {
+    pub lights: [Light; MAX_LIGHTS],
+    pub shadow_view_proj: glm::Mat4,
+    pub count: u32,
+    /// index of the light casting shadows, `-1` if none
+    pub shadow_light: i32,
+    pub shadow_bias: f32,
+    pub env_intensity: f32,
+    /// Rotation of the environment around the up axis in radians.
+    pub env_yaw: f32,
+    /// What the ray traced occlusion map holds, `0` if it is unused.
+    pub traced_occlusion: i32,
+    /// `1` if the screen space ambient occlusion map is filled.
+    pub ssao: i32,
+    /// `0` to write the colour before tone mapping, for HDR captures.
+    pub tone_mapping: i32,
+}
+impl LightsUniform {
+    pub fn new(lights: &[Light]) -> Self {
+        let mut slf = Self {
+            lights: [Light::default(); MAX_LIGHTS],
+            shadow_view_proj: glm::identity(),
+            count: lights.len().min(MAX_LIGHTS) as u32,
+            shadow_light: -1,
+            shadow_bias: 0.0,
+            env_intensity: 1.0,
+            env_yaw: 0.0,
+            traced_occlusion: 0,
+            ssao: 0,
+            tone_mapping: 1,
+        };
+        for (dst, src) in slf.lights.iter_mut().zip(lights) {
+            *dst = *src;
+        }
+        slf
+    }
+    /// The first directional light, which is the one casting shadows.
+    pub fn primary_directional(lights: &[Light]) -> Option<(usize, &Light)> {
+        lights
+            .iter()
+            .take(MAX_LIGHTS)
+            .enumerate()
+            .find(|(_, light)| light.kind == LIGHT_DIRECTIONAL)
+    }
+}
@@ -0,0 +1,479 @@
+use super::{
+    LoadGltfError,
+    pointer::{PointerChannel, take_pointer_channels},
+    spec_gloss::srgb_to_linear,
+};
+use serde_json::{Value, json};
+use std::{borrow::Cow, path::Path};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConvertError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Obj(#[from] tobj::LoadError),
+    #[error("the STL file is malformed")]
+    Stl,
+    #[error("the PLY file is malformed")]
+    Ply,
+    #[error("the file contains no triangles or points")]
+    Empty,
+}
+
+/// Opens a glTF file, OBJ, STL and PLY files are converted to an equivalent glTF
+/// without materials so they are drawn with the default one.
+/// Also returns the `KHR_animation_pointer` channels, which aren't part of the document.
+pub fn open_model(path: &Path) -> Result<(gltf::Gltf, Vec<PointerChannel>), LoadGltfError> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    let glb = match extension.as_deref() {
+        Some("obj") => convert_obj(path)?,
+        Some("stl") => convert_stl(&std::fs::read(path).map_err(ConvertError::from)?)?,
+        Some("ply") => convert_ply(&std::fs::read(path).map_err(ConvertError::from)?)?,
+        _ => return open_gltf(path),
+    };
+    Ok((gltf::Gltf::from_slice(&glb)?, vec![]))
+}
+
+/// Parses a .gltf or .glb with the pointer channels taken out of its JSON.
+fn open_gltf(path: &Path) -> Result<(gltf::Gltf, Vec<PointerChannel>), LoadGltfError> {
+    let bytes = std::fs::read(path).map_err(gltf::Error::Io)?;
+    open_model_slice(&bytes)
+}
+
+/// Parses a .gltf or .glb in memory like [`open_model`].
+pub fn open_model_slice(bytes: &[u8]) -> Result<(gltf::Gltf, Vec<PointerChannel>), LoadGltfError> {
+    let (json, blob) = if bytes.starts_with(b"glTF") {
+        let glb = gltf::binary::Glb::from_slice(bytes)?;
+        (glb.json, glb.bin.map(Cow::into_owned))
+    } else {
+        (Cow::Borrowed(bytes), None)
+    };
+    let mut root: Value = serde_json::from_slice(&json).map_err(gltf::Error::Deserialize)?;
+    let channels = take_pointer_channels(&mut root);
+    let root = serde_json::from_value(root).map_err(gltf::Error::Deserialize)?;
+    let document = gltf::Document::from_json(root)?;
+    Ok((gltf::Gltf { document, blob }, channels))
+}
+
+/// Every OBJ object becomes a mesh, normals and texture coordinates are kept if present.
+fn convert_obj(path: &Path) -> Result<Vec<u8>, ConvertError> {
+    let (models, _materials) = tobj::load_obj(
+        path,
+        &tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ..Default::default()
+        },
+    )?;
+    let mut builder = GlbBuilder::default();
+    for model in models {
+        let mesh = model.mesh;
+        if mesh.indices.is_empty() {
+            continue;
+        }
+        let mut attributes = serde_json::Map::new();
+        attributes.insert("POSITION".to_owned(), builder.positions(&mesh.positions));
+        if !mesh.normals.is_empty() {
+            let normals = builder.floats(&mesh.normals, "VEC3", 34962);
+            attributes.insert("NORMAL".to_owned(), normals);
+        }
+        if !mesh.texcoords.is_empty() {
+            // OBJ puts the texture origin at the bottom
+            let uvs: Vec<f32> = mesh
+                .texcoords
+                .chunks_exact(2)
+                .flat_map(|uv| [uv[0], 1.0 - uv[1]])
+                .collect();
+            attributes.insert("TEXCOORD_0".to_owned(), builder.floats(&uvs, "VEC2", 34962));
+        }
+        let indices = builder.indices(&mesh.indices);
+        builder.mesh(
+            &model.name,
+            json!({ "attributes": attributes, "indices": indices }),
+        );
+    }
+    builder.finish()
+}
+
+/// Binary and ASCII STL, the facets are drawn unindexed with flat normals.
+fn convert_stl(data: &[u8]) -> Result<Vec<u8>, ConvertError> {
+    let binary_count = data
+        .get(80..84)
+        .map(|count| u32::from_le_bytes(count.try_into().unwrap()) as usize);
+    let positions: Vec<f32> = match binary_count {
+        // ASCII files can start with "solid" too, the size tells them apart
+        Some(count) if data.len() == 84 + count * 50 => data[84..]
+            .chunks_exact(50)
+            .flat_map(|facet| facet[12..48].chunks_exact(4))
+            .map(|float| f32::from_le_bytes(float.try_into().unwrap()))
+            .collect(),
+        _ => {
+            let text = std::str::from_utf8(data).map_err(|_| ConvertError::Stl)?;
+            let mut positions = vec![];
+            for line in text.lines() {
+                let mut words = line.split_whitespace();
+                if words.next() != Some("vertex") {
+                    continue;
+                }
+                for word in words.take(3) {
+                    positions.push(word.parse().map_err(|_| ConvertError::Stl)?);
+                }
+            }
+            if positions.len() % 9 != 0 {
+                return Err(ConvertError::Stl);
+            }
+            positions
+        }
+    };
+    if positions.is_empty() {
+        return Err(ConvertError::Empty);
+    }
+
+    let mut builder = GlbBuilder::default();
+    let position = builder.positions(&positions);
+    builder.mesh("", json!({ "attributes": { "POSITION": position } }));
+    builder.finish()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PlyType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+impl PlyType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return None,
+        })
+    }
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+    /// Integer colors are scaled to 0..1.
+    fn normalize(self, value: f64) -> f32 {
+        match self {
+            Self::U8 => (value / u8::MAX as f64) as f32,
+            Self::U16 => (value / u16::MAX as f64) as f32,
+            _ => value as f32,
+        }
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    ty: PlyType,
+    /// The type of the length of list properties.
+    list: Option<PlyType>,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+enum PlyReader<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { data: &'a [u8], big_endian: bool },
+}
+impl PlyReader<'_> {
+    fn read(&mut self, ty: PlyType) -> Result<f64, ConvertError> {
+        match self {
+            Self::Ascii(words) => words
+                .next()
+                .and_then(|word| word.parse().ok())
+                .ok_or(ConvertError::Ply),
+            Self::Binary { data, big_endian } => {
+                let size = ty.size();
+                let current = *data;
+                let (bytes, rest) = current.split_at_checked(size).ok_or(ConvertError::Ply)?;
+                *data = rest;
+                let mut le = [0; 8];
+                le[..size].copy_from_slice(bytes);
+                if *big_endian {
+                    le[..size].reverse();
+                }
+                let [b0, b1, b2, b3, ..] = le;
+                Ok(match ty {
+                    PlyType::I8 => b0 as i8 as f64,
+                    PlyType::U8 => b0 as f64,
+                    PlyType::I16 => i16::from_le_bytes([b0, b1]) as f64,
+                    PlyType::U16 => u16::from_le_bytes([b0, b1]) as f64,
+                    PlyType::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    PlyType::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    PlyType::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    PlyType::F64 => f64::from_le_bytes(le),
+                })
+            }
+        }
+    }
+    /// Reads all properties of one element, lists are skipped.
+    fn read_element(
+        &mut self,
+        properties: &[PlyProperty],
+        values: &mut Vec<f64>,
+    ) -> Result<(), ConvertError> {
+        values.clear();
+        for property in properties {
+            match property.list {
+                Some(length) => {
+                    let length = self.read(length)? as usize;
+                    for _ in 0..length {
+                        self.read(property.ty)?;
+                    }
+                    values.push(0.0);
+                }
+                None => values.push(self.read(property.ty)?),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// ASCII and binary PLY, the vertices become a point cloud with their colors, faces are ignored.
+fn convert_ply(data: &[u8]) -> Result<Vec<u8>, ConvertError> {
+    let header_end = data
+        .windows(b"end_header".len())
+        .position(|window| window == b"end_header")
+        .ok_or(ConvertError::Ply)?;
+    let body_start = data[header_end..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map(|newline| header_end + newline + 1)
+        .ok_or(ConvertError::Ply)?;
+    let header = std::str::from_utf8(&data[..header_end]).map_err(|_| ConvertError::Ply)?;
+
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err(ConvertError::Ply);
+    }
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = vec![];
+    for line in lines {
+        let words: Vec<_> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", name, _] => format = Some(*name),
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| ConvertError::Ply)?,
+                properties: vec![],
+            }),
+            ["property", "list", length, ty, name] => {
+                let element = elements.last_mut().ok_or(ConvertError::Ply)?;
+                element.properties.push(PlyProperty {
+                    name: name.to_string(),
+                    ty: PlyType::parse(ty).ok_or(ConvertError::Ply)?,
+                    list: Some(PlyType::parse(length).ok_or(ConvertError::Ply)?),
+                });
+            }
+            ["property", ty, name] => {
+                let element = elements.last_mut().ok_or(ConvertError::Ply)?;
+                element.properties.push(PlyProperty {
+                    name: name.to_string(),
+                    ty: PlyType::parse(ty).ok_or(ConvertError::Ply)?,
+                    list: None,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    let body = &data[body_start..];
+    let mut reader = match format {
+        Some("ascii") => PlyReader::Ascii(
+            std::str::from_utf8(body)
+                .map_err(|_| ConvertError::Ply)?
+                .split_ascii_whitespace(),
+        ),
+        Some("binary_little_endian") => PlyReader::Binary {
+            data: body,
+            big_endian: false,
+        },
+        Some("binary_big_endian") => PlyReader::Binary {
+            data: body,
+            big_endian: true,
+        },
+        _ => return Err(ConvertError::Ply),
+    };
+
+    // elements are stored in header order, only the ones before the vertices have to be read
+    let mut values = vec![];
+    let mut positions = vec![];
+    let mut colors = vec![];
+    for element in &elements {
+        if element.name != "vertex" {
+            for _ in 0..element.count {
+                reader.read_element(&element.properties, &mut values)?;
+            }
+            continue;
+        }
+        let find = |names: &[&str]| {
+            element.properties.iter().position(|property| {
+                property.list.is_none() && names.contains(&property.name.as_str())
+            })
+        };
+        let [Some(x), Some(y), Some(z)] = [find(&["x"]), find(&["y"]), find(&["z"])] else {
+            return Err(ConvertError::Ply);
+        };
+        let rgb = [
+            find(&["red", "diffuse_red", "r"]),
+            find(&["green", "diffuse_green", "g"]),
+            find(&["blue", "diffuse_blue", "b"]),
+        ];
+        let alpha = find(&["alpha", "a"]);
+        positions.reserve(element.count * 3);
+        for _ in 0..element.count {
+            reader.read_element(&element.properties, &mut values)?;
+            positions.extend([x, y, z].map(|i| values[i] as f32));
+            if let [Some(r), Some(g), Some(b)] = rgb {
+                // glTF vertex colors are linear
+                let channel = |i: usize| element.properties[i].ty.normalize(values[i]);
+                colors.extend([r, g, b].map(|i| srgb_to_linear(channel(i))));
+                colors.push(alpha.map_or(1.0, channel));
+            }
+        }
+        break;
+    }
+    if positions.is_empty() {
+        return Err(ConvertError::Empty);
+    }
+
+    let mut builder = GlbBuilder::default();
+    let mut attributes = serde_json::Map::new();
+    attributes.insert("POSITION".to_owned(), builder.positions(&positions));
+    if !colors.is_empty() {
+        attributes.insert("COLOR_0".to_owned(), builder.floats(&colors, "VEC4", 34962));
+    }
+    // mode 0 draws points
+    builder.mesh("", json!({ "attributes": attributes, "mode": 0 }));
+    builder.finish()
+}
+
+/// Collects meshes and their data into a .glb with one node per mesh.
+#[derive(Default)]
+struct GlbBuilder {
+    bin: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+    meshes: Vec<Value>,
+}
+impl GlbBuilder {
+    /// Returns the index of the accessor.
+    fn accessor(&mut self, bytes: &[u8], target: u32, accessor: Value) -> Value {
+        let offset = self.bin.len();
+        self.bin.extend_from_slice(bytes);
+        self.bin.resize(self.bin.len().next_multiple_of(4), 0);
+        let mut accessor = accessor;
+        accessor["bufferView"] = self.views.len().into();
+        self.views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.accessors.push(accessor);
+        (self.accessors.len() - 1).into()
+    }
+    fn floats(&mut self, data: &[f32], ty: &str, target: u32) -> Value {
+        let components = match ty {
+            "VEC2" => 2,
+            "VEC4" => 4,
+            _ => 3,
+        };
+        self.accessor(
+            bytemuck::cast_slice(data),
+            target,
+            json!({
+                "componentType": 5126,
+                "count": data.len() / components,
+                "type": ty,
+            }),
+        )
+    }
+    /// Positions need their bounds.
+    fn positions(&mut self, data: &[f32]) -> Value {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for position in data.chunks_exact(3) {
+            for i in 0..3 {
+                min[i] = min[i].min(position[i]);
+                max[i] = max[i].max(position[i]);
+            }
+        }
+        let accessor = self.floats(data, "VEC3", 34962);
+        let index = accessor.as_u64().unwrap() as usize;
+        self.accessors[index]["min"] = json!(min);
+        self.accessors[index]["max"] = json!(max);
+        accessor
+    }
+    fn indices(&mut self, data: &[u32]) -> Value {
+        self.accessor(
+            bytemuck::cast_slice(data),
+            34963,
+            json!({
+                "componentType": 5125,
+                "count": data.len(),
+                "type": "SCALAR",
+            }),
+        )
+    }
+    fn mesh(&mut self, name: &str, primitive: Value) {
+        let mut mesh = json!({ "primitives": [primitive] });
+        if !name.is_empty() {
+            mesh["name"] = name.into();
+        }
+        self.meshes.push(mesh);
+    }
+    fn finish(self) -> Result<Vec<u8>, ConvertError> {
+        if self.meshes.is_empty() {
+            return Err(ConvertError::Empty);
+        }
+        let nodes: Vec<_> = (0..self.meshes.len())
+            .map(|mesh| json!({ "mesh": mesh }))
+            .collect();
+        let root = json!({
+            "asset": { "version": "2.0", "generator": "gltf_viewer" },
+            "scene": 0,
+            "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+            "nodes": nodes,
+            "meshes": self.meshes,
+            "accessors": self.accessors,
+            "bufferViews": self.views,
+            "buffers": [{ "byteLength": self.bin.len() }],
+        });
+        let json = serde_json::to_vec(&root).unwrap();
+        let length = 12 + 8 + json.len().next_multiple_of(4) + 8 + self.bin.len();
+        let glb = gltf::binary::Glb {
+            header: gltf::binary::Header {
+                magic: *b"glTF",
+                version: 2,
+                length: length as u32,
+            },
+            json: Cow::Owned(json),
+            bin: Some(Cow::Owned(self.bin)),
+        };
+        Ok(glb.to_vec().unwrap())
+    }
+}
@@ -0,0 +1,70 @@
+use super::{TextureKind, is_remote, texture_kind};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::Path,
+};
+
+/// For every image the index of the first image with the same encoded bytes and kind,
+/// so each distinct image is decoded and uploaded once.
+/// Materials reading the copies then bind the same views and share descriptor sets.
+pub fn image_aliases(
+    document: &gltf::Document,
+    base: Option<&Path>,
+    buffers: &[gltf::buffer::Data],
+) -> Vec<usize> {
+    let mut first = HashMap::new();
+    document
+        .images()
+        .map(|image| {
+            let index = image.index();
+            // spec-gloss textures are converted per material, they are kept apart
+            if is_spec_gloss(document, index) {
+                return index;
+            }
+            let Some(bytes) = source_bytes(&image, base, buffers) else {
+                return index;
+            };
+            let mut hasher = DefaultHasher::new();
+            bytes.hash(&mut hasher);
+            let key: (TextureKind, usize, u64) =
+                (texture_kind(document, index), bytes.len(), hasher.finish());
+            *first.entry(key).or_insert(index)
+        })
+        .collect()
+}
+
+/// The encoded bytes of an image, `None` if its file can't be read.
+/// Data and remote URIs are hashed as they are written.
+pub(super) fn source_bytes<'a>(
+    image: &gltf::Image,
+    base: Option<&Path>,
+    buffers: &'a [gltf::buffer::Data],
+) -> Option<Cow<'a, [u8]>> {
+    match image.source() {
+        gltf::image::Source::View { view, .. } => {
+            let buffer = buffers.get(view.buffer().index())?;
+            let bytes = buffer.get(view.offset()..view.offset() + view.length())?;
+            Some(Cow::Borrowed(bytes))
+        }
+        // remote images would have to be downloaded twice
+        gltf::image::Source::Uri { uri, .. } if uri.starts_with("data:") || is_remote(uri) => {
+            Some(Cow::Owned(uri.as_bytes().to_vec()))
+        }
+        gltf::image::Source::Uri { uri, .. } => {
+            let uri = urlencoding::decode(uri).unwrap_or(Cow::Borrowed(uri));
+            let path = base.unwrap_or(Path::new("")).join(uri.as_ref());
+            std::fs::read(path).ok().map(Cow::Owned)
+        }
+    }
+}
+
+pub(super) fn is_spec_gloss(document: &gltf::Document, index: usize) -> bool {
+    document.materials().any(|material| {
+        material
+            .pbr_specular_glossiness()
+            .and_then(|sg| sg.specular_glossiness_texture())
+            .is_some_and(|tex| tex.texture().source().index() == index)
+    })
+}
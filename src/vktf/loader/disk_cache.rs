@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+/// Bumped when what is written changes, older entries are then never looked up.
+const VERSION: u32 = 2;
+
+/// Converted meshes and images stored on disk by a hash of what they were made from,
+/// so reopening a model skips reading the geometry and decoding, resizing and compressing
+/// the images. The hash isn't stable between builds, their entries are simply missed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiskCache {
+    pub enabled: bool,
+    /// Number of entries and their total size in bytes, counted when first shown and kept up
+    /// to date by the writes of every clone. `None` until counted.
+    #[serde(skip)]
+    usage: Arc<Mutex<Option<(usize, u64)>>>,
+}
+impl Default for DiskCache {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            usage: Default::default(),
+        }
+    }
+}
+impl DiskCache {
+    fn dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("gltf-viewer").join("assets"))
+    }
+    /// The key of an entry made from `source`.
+    pub fn key(source: impl Hash) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (VERSION, source).hash(&mut hasher);
+        hasher.finish()
+    }
+    /// `None` if disabled or there is no entry.
+    pub fn read(&self, key: u64) -> Option<Vec<u8>> {
+        if !self.enabled {
+            return None;
+        }
+        std::fs::read(Self::dir()?.join(format!("{key:016x}"))).ok()
+    }
+    /// Failures are only logged, the entry is made again next time.
+    pub fn write(&self, key: u64, bytes: &[u8]) {
+        let Some(dir) = Self::dir().filter(|_| self.enabled) else {
+            return;
+        };
+        let path = dir.join(format!("{key:016x}"));
+        let replaced = std::fs::metadata(&path).ok().map(|metadata| metadata.len());
+        // renamed into place so a half written entry is never read
+        let partial = path.with_extension("partial");
+        let result = std::fs::create_dir_all(&dir)
+            .and_then(|_| std::fs::write(&partial, bytes))
+            .and_then(|_| std::fs::rename(&partial, &path));
+        if let Err(err) = result {
+            log::warn!("failed to write cache entry {}: {err}", path.display());
+            return;
+        }
+        if let Some((count, size)) = self.usage.lock().unwrap().as_mut() {
+            *count += replaced.is_none() as usize;
+            *size = size.saturating_sub(replaced.unwrap_or(0)) + bytes.len() as u64;
+        }
+    }
+    /// Number of entries and their total size in bytes, counted again after [`DiskCache::refresh`].
+    pub fn usage(&self) -> (usize, u64) {
+        *self.usage.lock().unwrap().get_or_insert_with(Self::count)
+    }
+    /// Counts the entries again the next time the usage is asked for, for when another
+    /// process changed them.
+    pub fn refresh(&self) {
+        *self.usage.lock().unwrap() = None;
+    }
+    fn count() -> (usize, u64) {
+        let Some(entries) = Self::dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+            return (0, 0);
+        };
+        entries
+            .filter_map(|entry| entry.ok()?.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .fold((0, 0), |(count, size), metadata| {
+                (count + 1, size + metadata.len())
+            })
+    }
+    pub fn clear(&self) -> std::io::Result<()> {
+        let result = match Self::dir() {
+            Some(dir) if dir.exists() => std::fs::remove_dir_all(dir),
+            _ => Ok(()),
+        };
+        self.refresh();
+        result
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Cache converted models")
+            .on_hover_text("Store the converted geometry and textures on disk to reopen faster");
+        ui.horizontal(|ui| {
+            let (count, size) = self.usage();
+            ui.label(format!(
+                "Cache: {count} entries, {:.1} MiB",
+                size as f64 / (1024.0 * 1024.0)
+            ));
+            if ui
+                .add_enabled(count > 0, egui::Button::new("Clear"))
+                .clicked()
+            {
+                if let Err(err) = self.clear() {
+                    log::error!("failed to clear the cache: {err}");
+                }
+            }
+            if ui
+                .button("Refresh")
+                .on_hover_text("Count the entries again")
+                .clicked()
+            {
+                self.refresh();
+            }
+        });
+    }
+}
+
+/// Packs cache entries.
+#[derive(Default)]
+pub struct Writer(pub Vec<u8>);
+impl Writer {
+    pub fn u32(&mut self, value: u32) {
+        self.0.extend(value.to_le_bytes());
+    }
+    pub fn u64(&mut self, value: u64) {
+        self.0.extend(value.to_le_bytes());
+    }
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.0.extend(bytes);
+    }
+    pub fn pod<T: bytemuck::Pod>(&mut self, items: &[T]) {
+        self.bytes(bytemuck::cast_slice(items));
+    }
+    /// `u64::MAX` stands for `None`.
+    pub fn option(&mut self, value: Option<usize>) {
+        self.u64(value.map_or(u64::MAX, |value| value as u64));
+    }
+}
+
+/// Unpacks what a [`Writer`] packed, `None` if the entry is cut short.
+pub struct Reader<'a>(pub &'a [u8]);
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+    pub fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+    pub fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+    pub fn usize(&mut self) -> Option<usize> {
+        self.u64()?.try_into().ok()
+    }
+    pub fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.usize()?;
+        self.take(len)
+    }
+    /// The bytes of an entry aren't aligned for `T`.
+    pub fn pod<T: bytemuck::Pod>(&mut self) -> Option<Vec<T>> {
+        let bytes = self.bytes()?;
+        if bytes.len() % size_of::<T>() != 0 {
+            return None;
+        }
+        Some(
+            bytes
+                .chunks_exact(size_of::<T>())
+                .map(bytemuck::pod_read_unaligned)
+                .collect(),
+        )
+    }
+    pub fn option(&mut self) -> Option<Option<usize>> {
+        match self.u64()? {
+            u64::MAX => Some(None),
+            value => Some(Some(value.try_into().ok()?)),
+        }
+    }
+}
@@ -1,34 +1,466 @@
-use image::EncodableLayout;
-use std::sync::Arc;
+use super::{
+    LoadGltfError, TextureOptions,
+    disk_cache::{Reader, Writer},
+    spec_gloss::srgb_to_linear,
+};
+use crate::vktf::cache::VktfCache;
+use intel_tex_2::{RgSurface, RgbaSurface, bc5, bc7};
+use rayon::prelude::*;
+use std::{
+    borrow::Cow,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use vulkano::{
+    DeviceSize,
     buffer::{Buffer, BufferCreateInfo, BufferUsage},
     command_buffer::{
-        AutoCommandBufferBuilder, BlitImageInfo, CopyBufferToImageInfo, CopyImageInfo, ImageBlit,
+        AutoCommandBufferBuilder, BlitImageInfo, BufferImageCopy, CopyBufferToImageInfo, ImageBlit,
     },
-    format::Format,
+    device::{Device, DeviceOwned},
+    format::{Format, FormatFeatures},
     image::{
-        Image, ImageCreateInfo, ImageSubresourceLayers, ImageType, ImageUsage, sampler::Filter,
+        Image, ImageCreateInfo, ImageSubresourceLayers, ImageSubresourceRange, ImageType,
+        ImageUsage,
+        sampler::Filter,
+        view::{ImageView, ImageViewCreateInfo},
     },
     memory::allocator::{AllocationCreateInfo, MemoryAllocator, MemoryTypeFilter},
+    sync::Sharing,
 };
 
+/// What a texture is used for, decides its format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureKind {
+    /// sRGB colours.
+    Color,
+    /// Linear data like metallic-roughness or occlusion.
+    Data,
+    /// Tangent space normals, only x and y are kept when compressed.
+    Normal,
+}
+impl TextureKind {
+    fn compressed_format(&self) -> Format {
+        match self {
+            Self::Color => Format::BC7_SRGB_BLOCK,
+            Self::Data => Format::BC7_UNORM_BLOCK,
+            Self::Normal => Format::BC5_UNORM_BLOCK,
+        }
+    }
+}
+
+/// Bits per channel of a decoded image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Precision {
+    Unorm8,
+    Unorm16,
+    Float,
+}
+impl Precision {
+    fn new(format: gltf::image::Format) -> Self {
+        use gltf::image::Format;
+        match format {
+            Format::R8 | Format::R8G8 | Format::R8G8B8 | Format::R8G8B8A8 => Self::Unorm8,
+            Format::R16 | Format::R16G16 | Format::R16G16B16 | Format::R16G16B16A16 => {
+                Self::Unorm16
+            }
+            Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => Self::Float,
+        }
+    }
+}
+
+/// The uncompressed format keeping `precision`, 8 bits if the device can't sample, filter
+/// and blit the wider one. 16 bit colours are stored linear since there is no sRGB format.
+fn pixel_format(device: &Device, kind: TextureKind, precision: Precision) -> Format {
+    let wide = match precision {
+        Precision::Unorm8 => None,
+        Precision::Unorm16 => Some(Format::R16G16B16A16_UNORM),
+        Precision::Float => Some(Format::R32G32B32A32_SFLOAT),
+    };
+    let required = FormatFeatures::SAMPLED_IMAGE
+        | FormatFeatures::SAMPLED_IMAGE_FILTER_LINEAR
+        | FormatFeatures::BLIT_SRC
+        | FormatFeatures::BLIT_DST
+        | FormatFeatures::TRANSFER_SRC
+        | FormatFeatures::TRANSFER_DST;
+    let supported = |format: &Format| {
+        device
+            .physical_device()
+            .format_properties(*format)
+            .is_ok_and(|properties| properties.optimal_tiling_features.contains(required))
+    };
+    match wide.filter(supported) {
+        Some(format) => format,
+        None if kind == TextureKind::Color => Format::R8G8B8A8_SRGB,
+        None => Format::R8G8B8A8_UNORM,
+    }
+}
+
+/// Block compression needs the feature and whole blocks.
+fn compressed_format(device: &Device, kind: TextureKind, extent: [u32; 2]) -> Option<Format> {
+    (device.enabled_features().texture_compression_bc && extent[0] >= 4 && extent[1] >= 4)
+        .then(|| kind.compressed_format())
+}
+
+/// An image decoded, resized and, if compressed, block encoded on the CPU.
+/// Making one needs no command buffer, so many can be made in parallel before uploading them.
+pub struct CpuImage {
+    extent: [u32; 2],
+    format: Format,
+    filter_normals: bool,
+    data: CpuImageData,
+    /// A smaller mip of the pixels made ahead of time, uploaded before the larger ones.
+    preview: Option<(u32, Vec<u8>)>,
+}
+enum CpuImageData {
+    /// The top mip in the format of the image, the others are blitted or filtered on the GPU.
+    Pixels(Vec<u8>),
+    /// Every mip level encoded, with the offset of each level.
+    Blocks(Vec<u8>, Vec<DeviceSize>),
+}
+
+/// The formats [`prepare_image`] makes, stored by position in the disk cache.
+const CACHED_FORMATS: [Format; 7] = [
+    Format::R8G8B8A8_SRGB,
+    Format::R8G8B8A8_UNORM,
+    Format::BC7_SRGB_BLOCK,
+    Format::BC7_UNORM_BLOCK,
+    Format::BC5_UNORM_BLOCK,
+    Format::R16G16B16A16_UNORM,
+    Format::R32G32B32A32_SFLOAT,
+];
+
+impl CpuImage {
+    pub(super) fn write(&self, writer: &mut Writer) {
+        writer.u32(self.extent[0]);
+        writer.u32(self.extent[1]);
+        let format = CACHED_FORMATS.iter().position(|f| *f == self.format);
+        writer.u32(format.unwrap() as u32);
+        writer.u32(self.filter_normals as u32);
+        match &self.data {
+            CpuImageData::Pixels(pixels) => {
+                writer.u32(0);
+                writer.bytes(pixels);
+            }
+            CpuImageData::Blocks(blocks, offsets) => {
+                writer.u32(1);
+                writer.bytes(blocks);
+                writer.pod(offsets);
+            }
+        }
+    }
+    pub(super) fn read(reader: &mut Reader) -> Option<Self> {
+        let extent = [reader.u32()?, reader.u32()?];
+        let format = *CACHED_FORMATS.get(reader.u32()? as usize)?;
+        let filter_normals = reader.u32()? != 0;
+        let data = match reader.u32()? {
+            0 => {
+                let pixels = reader.bytes()?;
+                let size = extent[0] as DeviceSize * extent[1] as DeviceSize * format.block_size();
+                if pixels.len() as DeviceSize != size {
+                    return None;
+                }
+                CpuImageData::Pixels(pixels.to_vec())
+            }
+            1 => CpuImageData::Blocks(reader.bytes()?.to_vec(), reader.pod()?),
+            _ => return None,
+        };
+        Some(Self {
+            extent,
+            format,
+            filter_normals,
+            data,
+            preview: None,
+        })
+    }
+
+    pub(super) fn mips(&self) -> u32 {
+        self.extent[0].max(self.extent[1]).ilog2() + 1
+    }
+    /// The largest mip that is at most `size` large.
+    pub(super) fn first_mip(&self, size: u32) -> u32 {
+        let [w, h] = self.extent;
+        (0..self.mips())
+            .find(|&mip| (w >> mip).max(h >> mip) <= size)
+            .unwrap_or(self.mips() - 1)
+    }
+    /// Downsamples the pixels of the mip uploaded first for `size`, compressed images have
+    /// every mip already.
+    pub(super) fn prepare_preview(&mut self, size: u32) {
+        let mip = self.first_mip(size);
+        if mip > 0 && matches!(self.data, CpuImageData::Pixels(_)) {
+            self.preview = Some((mip, self.mip_pixels(mip)));
+        }
+    }
+    /// Mip `mip` of the pixels, downsampled on the CPU.
+    fn mip_pixels(&self, mip: u32) -> Vec<u8> {
+        let CpuImageData::Pixels(pixels) = &self.data else {
+            unreachable!("compressed images have every mip");
+        };
+        let [w, h] = self.extent;
+        let image = match self.format {
+            Format::R16G16B16A16_UNORM => image::DynamicImage::ImageRgba16(
+                image::ImageBuffer::from_vec(w, h, bytemuck::pod_collect_to_vec(pixels)).unwrap(),
+            ),
+            Format::R32G32B32A32_SFLOAT => image::DynamicImage::ImageRgba32F(
+                image::ImageBuffer::from_vec(w, h, bytemuck::pod_collect_to_vec(pixels)).unwrap(),
+            ),
+            _ => image::DynamicImage::ImageRgba8(
+                image::ImageBuffer::from_vec(w, h, pixels.clone()).unwrap(),
+            ),
+        };
+        match image.thumbnail_exact((w >> mip).max(1), (h >> mip).max(1)) {
+            image::DynamicImage::ImageRgba16(image) => bytemuck::pod_collect_to_vec(image.as_raw()),
+            image::DynamicImage::ImageRgba32F(image) => {
+                bytemuck::pod_collect_to_vec(image.as_raw())
+            }
+            image => {
+                let mut rgba8 = image.into_rgba8();
+                if self.filter_normals {
+                    renormalize(&mut rgba8, true);
+                }
+                rgba8.into_raw()
+            }
+        }
+    }
+}
+
+/// Does the CPU side of [`create_vk_image`].
+pub fn prepare_image(
+    device: &Device,
+    data: gltf::image::Data,
+    kind: TextureKind,
+    options: TextureOptions,
+) -> CpuImage {
+    let mut w = data.width.next_power_of_two();
+    let mut h = data.height.next_power_of_two();
+    if let Some(max_size) = options.max_size {
+        while w.max(h) > max_size.max(1) {
+            w = (w / 2).max(1);
+            h = (h / 2).max(1);
+        }
+    }
+
+    // 16 bit and float images are neither block compressed nor filtered, which keep 8 bits
+    let precision = if options.keep_precision {
+        Precision::new(data.format)
+    } else {
+        Precision::Unorm8
+    };
+    let resized = convert_image(data).resize_exact(w, h, image::imageops::FilterType::Lanczos3);
+    // the alpha of normal maps is unused, the filtered mips keep the length of the normals in it
+    let filter_normals = kind == TextureKind::Normal && options.filter_normals;
+
+    let format = pixel_format(device, kind, precision);
+    let pixels = match format {
+        Format::R16G16B16A16_UNORM => {
+            let mut rgba16 = resized.into_rgba16();
+            for pixel in rgba16.pixels_mut() {
+                if kind == TextureKind::Color {
+                    for c in &mut pixel.0[..3] {
+                        *c = (srgb_to_linear(*c as f32 / 65535.0) * 65535.0).round() as u16;
+                    }
+                } else if kind == TextureKind::Normal {
+                    pixel[3] = u16::MAX;
+                }
+            }
+            bytemuck::pod_collect_to_vec(rgba16.as_raw())
+        }
+        Format::R32G32B32A32_SFLOAT => {
+            let mut rgba32f = resized.into_rgba32f();
+            if kind == TextureKind::Normal {
+                for pixel in rgba32f.pixels_mut() {
+                    pixel[3] = 1.0;
+                }
+            }
+            bytemuck::pod_collect_to_vec(rgba32f.as_raw())
+        }
+        _ => {
+            let mut rgba8 = resized.into_rgba8();
+            if kind == TextureKind::Normal {
+                for pixel in rgba8.pixels_mut() {
+                    pixel[3] = u8::MAX;
+                }
+            }
+
+            let compressed = compressed_format(device, kind, [w, h]);
+            if let Some(format) = compressed.filter(|_| options.compress) {
+                let (blocks, offsets) = compress_mips(&rgba8, format, filter_normals);
+                return CpuImage {
+                    extent: [w, h],
+                    format,
+                    filter_normals,
+                    data: CpuImageData::Blocks(blocks, offsets),
+                    preview: None,
+                };
+            }
+            rgba8.into_raw()
+        }
+    };
+
+    CpuImage {
+        extent: [w, h],
+        format,
+        // the filter works on 8 bit images
+        filter_normals: filter_normals && format == Format::R8G8B8A8_UNORM,
+        data: CpuImageData::Pixels(pixels),
+        preview: None,
+    }
+}
+
+/// The command buffers image uploads are recorded into.
+/// The copies can go to a transfer queue so the graphics queue only generates the mips.
+pub struct Uploader<'a, L> {
+    transfer: &'a mut AutoCommandBufferBuilder<L>,
+    /// The graphics builder and the queue families of both, `None` if everything is recorded
+    /// into `transfer`.
+    graphics: Option<(&'a mut AutoCommandBufferBuilder<L>, [u32; 2])>,
+}
+impl<'a, L> Uploader<'a, L> {
+    /// Records everything into one command buffer of a graphics queue.
+    pub fn new(builder: &'a mut AutoCommandBufferBuilder<L>) -> Self {
+        Self {
+            transfer: builder,
+            graphics: None,
+        }
+    }
+    /// Records the copies into `transfer` and the rest into `graphics`, which has to be
+    /// submitted after it with a semaphore.
+    pub fn split(
+        transfer: &'a mut AutoCommandBufferBuilder<L>,
+        transfer_family: u32,
+        graphics: &'a mut AutoCommandBufferBuilder<L>,
+        graphics_family: u32,
+    ) -> Self {
+        Self {
+            transfer,
+            graphics: Some((graphics, [transfer_family, graphics_family])),
+        }
+    }
+    fn graphics(&mut self) -> &mut AutoCommandBufferBuilder<L> {
+        match &mut self.graphics {
+            Some((graphics, _)) => &mut **graphics,
+            None => &mut *self.transfer,
+        }
+    }
+    /// Images written by the copies are read by both queues.
+    fn sharing<T>(&self) -> Sharing<T>
+    where
+        T: FromIterator<u32> + IntoIterator<Item = u32>,
+    {
+        match self.graphics {
+            Some((_, families)) if families[0] != families[1] => {
+                Sharing::Concurrent(families.into_iter().collect())
+            }
+            _ => Sharing::Exclusive,
+        }
+    }
+}
+
 pub fn create_vk_image<L>(
     allocator: Arc<dyn MemoryAllocator>,
     builder: &mut AutoCommandBufferBuilder<L>,
     data: gltf::image::Data,
-    is_srgb: bool,
-) -> Arc<Image> {
-    let w = data.width.next_power_of_two();
-    let h = data.height.next_power_of_two();
+    kind: TextureKind,
+    options: TextureOptions,
+    cache: &VktfCache,
+) -> Result<Arc<Image>, LoadGltfError> {
+    let image = prepare_image(allocator.device(), data, kind, options);
+    upload_image(allocator, &mut Uploader::new(builder), &image, 0, cache)
+}
 
-    let rgba8 = convert_image(data)
-        .resize_exact(w, h, image::imageops::FilterType::Lanczos3)
-        .to_rgba8();
+/// Creates the image of one made by [`prepare_image`] with room for every mip and records
+/// the upload of the mips from `first` on. The larger ones can be added with [`upload_mips`].
+pub fn upload_image<L>(
+    allocator: Arc<dyn MemoryAllocator>,
+    uploader: &mut Uploader<L>,
+    image: &CpuImage,
+    first: u32,
+    cache: &VktfCache,
+) -> Result<Arc<Image>, LoadGltfError> {
+    let [w, h] = image.extent;
+    let mut usage = ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED;
+    // uncompressed mips are blitted or filtered from the ones before
+    if let CpuImageData::Pixels(_) = image.data {
+        usage |= ImageUsage::TRANSFER_SRC;
+        if image.filter_normals {
+            usage |= ImageUsage::STORAGE;
+        }
+    }
+    let vk_image = Image::new(
+        allocator.clone(),
+        ImageCreateInfo {
+            usage,
+            image_type: ImageType::Dim2d,
+            format: image.format,
+            mip_levels: image.mips(),
+            extent: [w, h, 1],
+            sharing: uploader.sharing(),
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )?;
+    upload_mips(
+        allocator,
+        uploader,
+        image,
+        &vk_image,
+        first..image.mips(),
+        cache,
+    )?;
+    Ok(vk_image)
+}
 
-    let format = if is_srgb {
-        Format::R8G8B8A8_SRGB
-    } else {
-        Format::R8G8B8A8_UNORM
+/// Records the upload of `mips` of `image` into `target`, which [`upload_image`] made for it.
+/// The first of uncompressed mips is copied and the others are generated from it.
+pub fn upload_mips<L>(
+    allocator: Arc<dyn MemoryAllocator>,
+    uploader: &mut Uploader<L>,
+    image: &CpuImage,
+    target: &Arc<Image>,
+    mips: Range<u32>,
+    cache: &VktfCache,
+) -> Result<(), LoadGltfError> {
+    if mips.is_empty() {
+        return Ok(());
+    }
+    let [w, h] = image.extent;
+    let extent = |mip: u32| [(w >> mip).max(1), (h >> mip).max(1), 1];
+    let layers = |mip: u32| ImageSubresourceLayers {
+        mip_level: mip,
+        ..target.subresource_layers()
+    };
+    let (bytes, regions): (Cow<[u8]>, Vec<_>) = match &image.data {
+        CpuImageData::Blocks(blocks, offsets) => {
+            let start = offsets[mips.start as usize];
+            let end = offsets
+                .get(mips.end as usize)
+                .map_or(blocks.len(), |&end| end as usize);
+            let regions = mips
+                .clone()
+                .map(|mip| BufferImageCopy {
+                    buffer_offset: offsets[mip as usize] - start,
+                    image_subresource: layers(mip),
+                    image_extent: extent(mip),
+                    ..Default::default()
+                })
+                .collect();
+            (Cow::Borrowed(&blocks[start as usize..end]), regions)
+        }
+        CpuImageData::Pixels(pixels) => {
+            let pixels = match &image.preview {
+                _ if mips.start == 0 => Cow::Borrowed(pixels.as_slice()),
+                Some((mip, preview)) if *mip == mips.start => Cow::Borrowed(preview.as_slice()),
+                _ => Cow::Owned(image.mip_pixels(mips.start)),
+            };
+            let region = BufferImageCopy {
+                image_subresource: layers(mips.start),
+                image_extent: extent(mips.start),
+                ..Default::default()
+            };
+            (pixels, vec![region])
+        }
     };
 
     let stage_buffer = Buffer::from_iter(
@@ -42,86 +474,254 @@ pub fn create_vk_image<L>(
                 | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
             ..Default::default()
         },
-        rgba8.as_bytes().iter().copied(),
-    )
-    .unwrap();
+        bytes.iter().copied(),
+    )?;
+    uploader
+        .transfer
+        .copy_buffer_to_image(CopyBufferToImageInfo {
+            regions: regions.into(),
+            ..CopyBufferToImageInfo::buffer_image(stage_buffer, target.clone())
+        })
+        .unwrap();
+    if let CpuImageData::Blocks(..) = image.data {
+        return Ok(());
+    }
 
-    let mips = w.max(h).ilog2() + 1;
+    // blits and the normal filter need the graphics queue
+    let builder = uploader.graphics();
+    let generated = mips.start + 1..mips.end;
+    if image.filter_normals {
+        cache
+            .normal_mipmaps(allocator.device())
+            .generate(builder, target, generated);
+    } else {
+        for mip in generated {
+            let [src_w, src_h, _] = extent(mip - 1);
+            let [dst_w, dst_h, _] = extent(mip);
+            builder
+                .blit_image(BlitImageInfo {
+                    filter: Filter::Linear,
+                    regions: [ImageBlit {
+                        src_subresource: layers(mip - 1),
+                        dst_subresource: layers(mip),
+                        src_offsets: [[0, 0, 0], [src_w, src_h, 1]],
+                        dst_offsets: [[0, 0, 0], [dst_w, dst_h, 1]],
+                        ..Default::default()
+                    }]
+                    .into(),
+                    ..BlitImageInfo::images(target.clone(), target.clone())
+                })
+                .unwrap();
+        }
+    }
+    Ok(())
+}
 
-    let stage_image = vulkano::image::Image::new(
-        allocator.clone(),
-        ImageCreateInfo {
-            usage: ImageUsage::TRANSFER_DST | ImageUsage::TRANSFER_SRC | ImageUsage::SAMPLED,
-            image_type: ImageType::Dim2d,
-            format,
-            mip_levels: mips,
-            extent: [w, h, 1],
-            ..Default::default()
+/// A view of the mips of `image` from `first` on, the ones before may not be uploaded yet.
+pub fn mip_view(image: Arc<Image>, first: u32) -> Arc<ImageView> {
+    let subresource_range = ImageSubresourceRange {
+        mip_levels: first..image.mip_levels(),
+        ..image.subresource_range()
+    };
+    ImageView::new(
+        image.clone(),
+        ImageViewCreateInfo {
+            subresource_range,
+            ..ImageViewCreateInfo::from_image(&image)
         },
-        AllocationCreateInfo::default(),
     )
-    .unwrap();
+    .unwrap()
+}
 
-    builder
-        .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
-            stage_buffer,
-            stage_image.clone(),
-        ))
-        .unwrap();
+/// Adds the mips larger than `size` of `image` to the image of `view`, uploaded before by
+/// [`upload_image`] from the first mip fitting `size`, and views all of them.
+pub fn upload_remaining_mips<L>(
+    allocator: Arc<dyn MemoryAllocator>,
+    uploader: &mut Uploader<L>,
+    image: &CpuImage,
+    view: &mut Arc<ImageView>,
+    size: u32,
+    cache: &VktfCache,
+) -> Result<(), LoadGltfError> {
+    let first = image.first_mip(size);
+    if first == 0 {
+        return Ok(());
+    }
+    let target = view.image().clone();
+    upload_mips(allocator, uploader, image, &target, 0..first, cache)?;
+    *view = ImageView::new_default(target).unwrap();
+    Ok(())
+}
 
-    for mip in 1..mips {
-        builder
-            .blit_image(BlitImageInfo {
-                filter: Filter::Linear,
-                regions: [ImageBlit {
-                    src_subresource: ImageSubresourceLayers {
-                        mip_level: mip - 1,
-                        ..stage_image.subresource_layers()
-                    },
-                    dst_subresource: ImageSubresourceLayers {
-                        mip_level: mip,
-                        ..stage_image.subresource_layers()
-                    },
-                    src_offsets: [
-                        [0, 0, 0],
-                        [(w >> (mip - 1)).max(1), (h >> (mip - 1)).max(1), 1],
-                    ],
-                    dst_offsets: [[0, 0, 0], [(w >> mip).max(1), (h >> mip).max(1), 1]],
-                    ..Default::default()
-                }]
-                .into(),
-                ..BlitImageInfo::images(stage_image.clone(), stage_image.clone())
-            })
-            .unwrap();
+/// Uploads an image file to be used as a texture of `kind`.
+pub fn load_texture_file<L>(
+    allocator: Arc<dyn MemoryAllocator>,
+    builder: &mut AutoCommandBufferBuilder<L>,
+    path: &Path,
+    kind: TextureKind,
+    options: TextureOptions,
+    cache: &VktfCache,
+) -> Result<Arc<ImageView>, LoadGltfError> {
+    let decoded = image::open(path).map_err(|source| decode_error(path.to_owned(), source))?;
+    let data = image_data(decoded);
+    let image = create_vk_image(allocator, builder, data, kind, options, cache)?;
+    Ok(ImageView::new_default(image).unwrap())
+}
+
+/// The error of an image that failed to decode, AVIF images can't be decoded without the
+/// `avif` feature.
+pub(super) fn decode_error(path: PathBuf, source: image::ImageError) -> LoadGltfError {
+    match &source {
+        image::ImageError::Unsupported(err)
+            if !cfg!(feature = "avif")
+                && matches!(
+                    err.format_hint(),
+                    image::error::ImageFormatHint::Exact(image::ImageFormat::Avif)
+                ) =>
+        {
+            LoadGltfError::AvifDisabled { path }
+        }
+        _ => LoadGltfError::ImageFile { path, source },
     }
+}
 
-    let vk_image = vulkano::image::Image::new(
-        allocator.clone(),
-        ImageCreateInfo {
-            usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
-            image_type: ImageType::Dim2d,
-            format,
-            mip_levels: mips,
-            extent: [w, h, 1],
-            ..Default::default()
+/// Encodes every mip level on the CPU since compressed images can't be blitted.
+/// The levels are encoded in parallel.
+fn compress_mips(
+    rgba8: &image::RgbaImage,
+    format: Format,
+    filter_normals: bool,
+) -> (Vec<u8>, Vec<DeviceSize>) {
+    let (w, h) = rgba8.dimensions();
+    let mips = w.max(h).ilog2() + 1;
+
+    let levels: Vec<Vec<u8>> = (0..mips)
+        .into_par_iter()
+        .map(|mip| {
+            let extent = [(w >> mip).max(1), (h >> mip).max(1)];
+            let mut level = if mip == 0 {
+                rgba8.clone()
+            } else {
+                image::imageops::resize(
+                    rgba8,
+                    extent[0],
+                    extent[1],
+                    image::imageops::FilterType::Triangle,
+                )
+            };
+            // BC5 has no alpha for the length, so only the direction is kept
+            if filter_normals && mip > 0 {
+                renormalize(&mut level, false);
+            }
+            // the last mips are smaller than a block
+            if extent[0] < 4 || extent[1] < 4 {
+                let mut padded = image::RgbaImage::new(extent[0].max(4), extent[1].max(4));
+                image::imageops::replace(&mut padded, &level, 0, 0);
+                level = padded;
+            }
+            encode_blocks(&level, format)
+        })
+        .collect();
+
+    let mut blocks = vec![];
+    let mut offsets = vec![];
+    for level in levels {
+        offsets.push(blocks.len() as DeviceSize);
+        blocks.extend(level);
+    }
+    (blocks, offsets)
+}
+
+/// Makes the normals of a downsampled normal map unit length again, optionally keeping their
+/// length in alpha like the filtered mips made on the GPU.
+fn renormalize(image: &mut image::RgbaImage, length_in_alpha: bool) {
+    for pixel in image.pixels_mut() {
+        let [x, y, z] = [pixel[0], pixel[1], pixel[2]].map(|c| c as f32 / 127.5 - 1.0);
+        let len = (x * x + y * y + z * z).sqrt();
+        if len > 1e-4 {
+            for (c, v) in pixel.0.iter_mut().zip([x, y, z]) {
+                *c = ((v / len + 1.0) * 127.5).round() as u8;
+            }
+        }
+        if length_in_alpha {
+            pixel[3] = (len.min(1.0) * 255.0).round() as u8;
+        }
+    }
+}
+
+/// `image` has to be a whole number of 4x4 blocks.
+fn encode_blocks(image: &image::RgbaImage, format: Format) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    if format == Format::BC5_UNORM_BLOCK {
+        let rg: Vec<u8> = image
+            .pixels()
+            .flat_map(|pixel| [pixel[0], pixel[1]])
+            .collect();
+        return bc5::compress_blocks(&RgSurface {
+            data: &rg,
+            width,
+            height,
+            stride: width * 2,
+        });
+    }
+    let settings = if image.pixels().all(|pixel| pixel[3] == u8::MAX) {
+        bc7::opaque_ultra_fast_settings()
+    } else {
+        bc7::alpha_ultra_fast_settings()
+    };
+    bc7::compress_blocks(
+        &settings,
+        &RgbaSurface {
+            data: image.as_raw(),
+            width,
+            height,
+            stride: width * 4,
         },
-        AllocationCreateInfo::default(),
     )
-    .unwrap();
+}
 
-    let mut info = CopyImageInfo::images(stage_image, vk_image.clone());
-    for mip in 0..mips {
-        info.regions[0].src_subresource.mip_level = mip;
-        info.regions[0].dst_subresource.mip_level = mip;
-        builder.copy_image(info.clone()).unwrap();
-        info.regions[0].extent[0] = (info.regions[0].extent[0] >> 1).max(1);
-        info.regions[0].extent[1] = (info.regions[0].extent[1] >> 1).max(1);
+/// The pixels of a decoded image in the closest format glTF has, other formats become RGBA8.
+pub(super) fn image_data(image: image::DynamicImage) -> gltf::image::Data {
+    use gltf::image::Format;
+    use image::DynamicImage;
+    let (width, height) = (image.width(), image.height());
+    let (format, pixels) = match image {
+        DynamicImage::ImageLuma8(image) => (Format::R8, image.into_raw()),
+        DynamicImage::ImageLumaA8(image) => (Format::R8G8, image.into_raw()),
+        DynamicImage::ImageRgb8(image) => (Format::R8G8B8, image.into_raw()),
+        DynamicImage::ImageLuma16(image) => {
+            (Format::R16, bytemuck::pod_collect_to_vec(image.as_raw()))
+        }
+        DynamicImage::ImageLumaA16(image) => {
+            (Format::R16G16, bytemuck::pod_collect_to_vec(image.as_raw()))
+        }
+        DynamicImage::ImageRgb16(image) => (
+            Format::R16G16B16,
+            bytemuck::pod_collect_to_vec(image.as_raw()),
+        ),
+        DynamicImage::ImageRgba16(image) => (
+            Format::R16G16B16A16,
+            bytemuck::pod_collect_to_vec(image.as_raw()),
+        ),
+        DynamicImage::ImageRgb32F(image) => (
+            Format::R32G32B32FLOAT,
+            bytemuck::pod_collect_to_vec(image.as_raw()),
+        ),
+        DynamicImage::ImageRgba32F(image) => (
+            Format::R32G32B32A32FLOAT,
+            bytemuck::pod_collect_to_vec(image.as_raw()),
+        ),
+        image => (Format::R8G8B8A8, image.into_rgba8().into_raw()),
+    };
+    gltf::image::Data {
+        width,
+        height,
+        format,
+        pixels,
     }
-
-    vk_image
 }
 
-fn convert_image(data: gltf::image::Data) -> image::DynamicImage {
+pub fn convert_image(data: gltf::image::Data) -> image::DynamicImage {
     match data.format {
         gltf::image::Format::R8 => image::DynamicImage::ImageLuma8(
             image::ImageBuffer::from_vec(data.width, data.height, data.pixels).unwrap(),
@@ -136,28 +736,52 @@ fn convert_image(data: gltf::image::Data) -> image::DynamicImage {
             image::ImageBuffer::from_vec(data.width, data.height, data.pixels).unwrap(),
         ),
         gltf::image::Format::R16 => image::DynamicImage::ImageLuma16(
-            image::ImageBuffer::from_vec(data.width, data.height, bytemuck::cast_vec(data.pixels))
-                .unwrap(),
+            image::ImageBuffer::from_vec(
+                data.width,
+                data.height,
+                bytemuck::pod_collect_to_vec(&data.pixels),
+            )
+            .unwrap(),
         ),
         gltf::image::Format::R16G16 => image::DynamicImage::ImageLumaA16(
-            image::ImageBuffer::from_vec(data.width, data.height, bytemuck::cast_vec(data.pixels))
-                .unwrap(),
+            image::ImageBuffer::from_vec(
+                data.width,
+                data.height,
+                bytemuck::pod_collect_to_vec(&data.pixels),
+            )
+            .unwrap(),
         ),
         gltf::image::Format::R16G16B16 => image::DynamicImage::ImageRgb16(
-            image::ImageBuffer::from_vec(data.width, data.height, bytemuck::cast_vec(data.pixels))
-                .unwrap(),
+            image::ImageBuffer::from_vec(
+                data.width,
+                data.height,
+                bytemuck::pod_collect_to_vec(&data.pixels),
+            )
+            .unwrap(),
         ),
         gltf::image::Format::R16G16B16A16 => image::DynamicImage::ImageRgba16(
-            image::ImageBuffer::from_vec(data.width, data.height, bytemuck::cast_vec(data.pixels))
-                .unwrap(),
+            image::ImageBuffer::from_vec(
+                data.width,
+                data.height,
+                bytemuck::pod_collect_to_vec(&data.pixels),
+            )
+            .unwrap(),
         ),
         gltf::image::Format::R32G32B32FLOAT => image::DynamicImage::ImageRgb32F(
-            image::ImageBuffer::from_vec(data.width, data.height, bytemuck::cast_vec(data.pixels))
-                .unwrap(),
+            image::ImageBuffer::from_vec(
+                data.width,
+                data.height,
+                bytemuck::pod_collect_to_vec(&data.pixels),
+            )
+            .unwrap(),
         ),
         gltf::image::Format::R32G32B32A32FLOAT => image::DynamicImage::ImageRgba32F(
-            image::ImageBuffer::from_vec(data.width, data.height, bytemuck::cast_vec(data.pixels))
-                .unwrap(),
+            image::ImageBuffer::from_vec(
+                data.width,
+                data.height,
+                bytemuck::pod_collect_to_vec(&data.pixels),
+            )
+            .unwrap(),
         ),
     }
 }
@@ -0,0 +1,69 @@
+use gltf::{
+    accessor::{DataType, Iter},
+    animation::util::Rotations,
+};
+use nalgebra_glm as glm;
+
+/// Transforms of the `EXT_mesh_gpu_instancing` instances of `node` relative to the node,
+/// `None` if the node doesn't use the extension or its accessors can't be read.
+pub fn read_instances<'a>(
+    node: &gltf::Node<'a>,
+    document: &'a gltf::Document,
+    buffers: &'a [gltf::buffer::Data],
+) -> Option<Vec<glm::Mat4>> {
+    let attributes = node
+        .extensions()?
+        .get("EXT_mesh_gpu_instancing")?
+        .get("attributes")?;
+    let accessor = |name: &str| {
+        let index = attributes.get(name)?.as_u64()?;
+        document.accessors().nth(index as usize)
+    };
+    let get_buffer = |buffer: gltf::Buffer<'a>| buffers.get(buffer.index()).map(|d| d.0.as_slice());
+
+    // every attribute has the same count, missing ones use the identity
+    let count = ["TRANSLATION", "ROTATION", "SCALE"]
+        .into_iter()
+        .filter_map(accessor)
+        .map(|accessor| accessor.count())
+        .max()?;
+    let translations: Vec<[f32; 3]> = match accessor("TRANSLATION") {
+        Some(accessor) => Iter::new(accessor, get_buffer)?.collect(),
+        None => vec![[0.0; 3]; count],
+    };
+    let rotations: Vec<[f32; 4]> = match accessor("ROTATION") {
+        Some(accessor) => read_rotations(accessor, get_buffer)?.into_f32().collect(),
+        None => vec![[0.0, 0.0, 0.0, 1.0]; count],
+    };
+    let scales: Vec<[f32; 3]> = match accessor("SCALE") {
+        Some(accessor) => Iter::new(accessor, get_buffer)?.collect(),
+        None => vec![[1.0; 3]; count],
+    };
+
+    let instances = translations
+        .into_iter()
+        .zip(rotations)
+        .zip(scales)
+        .map(|((translation, [x, y, z, w]), scale)| {
+            glm::translation(&glm::make_vec3(&translation))
+                * glm::quat_to_mat4(&glm::quat(x, y, z, w))
+                * glm::scaling(&glm::make_vec3(&scale))
+        })
+        .collect();
+    Some(instances)
+}
+
+/// Rotations can also be stored as normalized integers.
+fn read_rotations<'a>(
+    accessor: gltf::Accessor<'a>,
+    get_buffer: impl Clone + Fn(gltf::Buffer<'a>) -> Option<&'a [u8]>,
+) -> Option<Rotations<'a>> {
+    Some(match accessor.data_type() {
+        DataType::I8 => Rotations::I8(Iter::new(accessor, get_buffer)?),
+        DataType::U8 => Rotations::U8(Iter::new(accessor, get_buffer)?),
+        DataType::I16 => Rotations::I16(Iter::new(accessor, get_buffer)?),
+        DataType::U16 => Rotations::U16(Iter::new(accessor, get_buffer)?),
+        DataType::F32 => Rotations::F32(Iter::new(accessor, get_buffer)?),
+        DataType::U32 => return None,
+    })
+}
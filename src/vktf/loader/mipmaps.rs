@@ -0,0 +1,137 @@
+use std::{ops::Range, sync::Arc};
+use vulkano::{
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor_set::{
+        DescriptorSet, WriteDescriptorSet,
+        allocator::{StandardDescriptorSetAllocator, StandardDescriptorSetAllocatorCreateInfo},
+    },
+    device::Device,
+    image::{
+        Image, ImageSubresourceRange,
+        view::{ImageView, ImageViewCreateInfo},
+    },
+    pipeline::{
+        ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo, compute::ComputePipelineCreateInfo,
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+    },
+};
+
+/// Downsamples normal maps so every mip holds unit normals, with the length of the
+/// averaged normals in alpha. The shader turns that length into extra roughness (Toksvig),
+/// so bumpy surfaces lose their detail as a wider highlight instead of shimmering.
+pub struct NormalMipmaps {
+    pipeline: Arc<ComputePipeline>,
+    set_allocator: Arc<StandardDescriptorSetAllocator>,
+}
+impl NormalMipmaps {
+    pub fn new(device: Arc<Device>) -> Self {
+        let cs = cs::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+        let stage = PipelineShaderStageCreateInfo::new(cs);
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+        let pipeline = ComputePipeline::new(
+            device.clone(),
+            None,
+            ComputePipelineCreateInfo::stage_layout(stage, layout),
+        )
+        .unwrap();
+        let set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
+            device,
+            StandardDescriptorSetAllocatorCreateInfo::default(),
+        ));
+
+        Self {
+            pipeline,
+            set_allocator,
+        }
+    }
+
+    /// Fills `mips` each from the one before, `image` needs storage usage and an `rgba8` format.
+    pub fn generate<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        image: &Arc<Image>,
+        mips: Range<u32>,
+    ) {
+        let mip_view = |mip: u32| {
+            ImageView::new(
+                image.clone(),
+                ImageViewCreateInfo {
+                    subresource_range: ImageSubresourceRange {
+                        mip_levels: mip..mip + 1,
+                        ..image.subresource_range()
+                    },
+                    ..ImageViewCreateInfo::from_image(image)
+                },
+            )
+            .unwrap()
+        };
+        let layout = self.pipeline.layout().clone();
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap();
+        for mip in mips {
+            let set = DescriptorSet::new(
+                self.set_allocator.clone(),
+                layout.set_layouts()[0].clone(),
+                [
+                    WriteDescriptorSet::image_view(0, mip_view(mip - 1)),
+                    WriteDescriptorSet::image_view(1, mip_view(mip)),
+                ],
+                [],
+            )
+            .unwrap();
+            let [width, height, _] = image.extent();
+            let extent = [(width >> mip).max(1), (height >> mip).max(1)];
+            builder
+                .bind_descriptor_sets(PipelineBindPoint::Compute, layout.clone(), 0, set)
+                .unwrap();
+            unsafe { builder.dispatch([extent[0].div_ceil(8), extent[1].div_ceil(8), 1]) }.unwrap();
+        }
+    }
+}
+
+mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D source;
+layout(set = 0, binding = 1, rgba8) uniform writeonly image2D target;
+
+void main() {
+    ivec2 id = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(id, imageSize(target)))) {
+        return;
+    }
+    ivec2 source_max = imageSize(source) - 1;
+
+    // the unit normals scaled by their length average like the full resolution normals would
+    vec3 sum = vec3(0.0);
+    for (int y = 0; y < 2; y++) {
+        for (int x = 0; x < 2; x++) {
+            vec4 texel = imageLoad(source, min(id * 2 + ivec2(x, y), source_max));
+            vec3 n = texel.xyz * 2.0 - 1.0;
+            sum += normalize(n + vec3(0.0, 0.0, 1e-6)) * texel.a;
+        }
+    }
+    vec3 average = sum / 4.0;
+    float len = length(average);
+    vec3 n = len > 1e-4 ? average / len : vec3(0.0, 0.0, 1.0);
+    imageStore(target, id, vec4(n * 0.5 + 0.5, len));
+}
+"#
+    }
+}
@@ -1,29 +1,66 @@
-use std::{path::Path, sync::Arc};
+use crate::{progress::ProgressSender, recovery, vktf::cache::VktfCache};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use vulkano::{
+    Validated, VulkanError,
+    buffer::AllocateBufferError,
     command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
     device::{Device, DeviceOwned},
     format::Format,
     image::{
-        Image, ImageCreateInfo, ImageUsage,
-        sampler::{Sampler, SamplerAddressMode, SamplerCreateInfo},
-        view::ImageView,
+        AllocateImageError, Image, ImageCreateInfo, ImageUsage, sampler::Sampler, view::ImageView,
     },
     memory::allocator::{AllocationCreateInfo, MemoryAllocator},
 };
 
+mod convert;
+mod dedup;
+mod disk_cache;
 mod image;
+mod instancing;
+mod mipmaps;
+mod pointer;
 mod primitive;
+mod remote;
 mod sampler;
+mod spec_gloss;
 
+pub use convert::*;
+use dedup::*;
+pub use disk_cache::DiskCache;
+use disk_cache::{Reader, Writer};
 use image::*;
+pub use image::{TextureKind, Uploader, load_texture_file};
+use instancing::*;
+pub use mipmaps::*;
+pub use pointer::*;
 pub use primitive::*;
+pub use remote::{RemoteAccess, UriResolver, is_remote};
+pub use sampler::Anisotropy;
 use sampler::*;
+pub use spec_gloss::*;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Vktf {
     samplers: Vec<Arc<Sampler>>,
-    images: Vec<Arc<ImageView>>,
+    /// `None` until the image has been streamed in.
+    images: Vec<Option<Arc<ImageView>>>,
+    /// By image index, the image with the same content that is uploaded in its place.
+    aliases: Vec<usize>,
     meshes: Vec<Vec<Primitive>>,
+    /// Debug lines by mesh index.
+    lines: Vec<Option<VertexLines>>,
+    /// Point clouds by mesh index.
+    points: Vec<Option<PointCloud>>,
+    /// Metallic-roughness textures converted from spec-gloss materials, by material index.
+    spec_gloss: HashMap<usize, Arc<ImageView>>,
+    /// `EXT_mesh_gpu_instancing` transforms by node index.
+    instances: HashMap<usize, Vec<glm::Mat4>>,
 
     default_sampler: Option<Arc<Sampler>>,
     default_image: Option<Arc<ImageView>>,
@@ -35,21 +72,436 @@ impl Vktf {
             None => self.default_sampler.as_ref(),
         }
     }
+    /// Images that haven't been streamed in yet fall back to the default image.
     pub fn get_image(&self, index: Option<usize>) -> Option<&Arc<ImageView>> {
         match index {
-            Some(i) => self.images.get(i),
+            Some(i) => self
+                .images
+                .get(self.image_alias(i))
+                .and_then(Option::as_ref)
+                .or(self.default_image.as_ref()),
             None => self.default_image.as_ref(),
         }
     }
+    /// `None` until the image has been streamed in.
+    pub fn get_loaded_image(&self, index: usize) -> Option<&Arc<ImageView>> {
+        self.images
+            .get(self.image_alias(index))
+            .and_then(Option::as_ref)
+    }
+    pub fn is_image_loaded(&self, index: usize) -> bool {
+        self.images
+            .get(self.image_alias(index))
+            .is_some_and(Option::is_some)
+    }
+    /// The image uploaded for image `index`, which is `index` unless it duplicates another one.
+    pub fn image_alias(&self, index: usize) -> usize {
+        self.aliases.get(index).copied().unwrap_or(index)
+    }
+    /// The images that are uploaded, the others share their views.
+    pub fn unique_images(&self) -> impl Iterator<Item = usize> {
+        (0..self.images.len()).filter(move |&i| self.image_alias(i) == i)
+    }
+    /// Every image drawn with the upload of image `index`, including itself.
+    pub fn image_copies(&self, index: usize) -> impl Iterator<Item = usize> {
+        (0..self.images.len()).filter(move |&i| self.image_alias(i) == index)
+    }
+    /// Number of images that share the upload of an identical one.
+    pub fn duplicate_images(&self) -> usize {
+        self.images.len() - self.unique_images().count()
+    }
+    /// The triangle primitives of every mesh.
+    /// The image at `index` if it has been streamed in, without the fallback.
+    pub fn loaded_image(&self, index: usize) -> Option<&Arc<ImageView>> {
+        self.get_loaded_image(index)
+    }
+    pub fn primitives(&self) -> impl Iterator<Item = &Primitive> {
+        self.meshes.iter().flatten()
+    }
     pub fn get_mesh(&self, index: usize) -> Option<&[Primitive]> {
         self.meshes.get(index).map(Vec::as_slice)
     }
+    pub fn get_lines(&self, mesh: usize) -> Option<&VertexLines> {
+        self.lines.get(mesh).and_then(Option::as_ref)
+    }
+    pub fn get_points(&self, mesh: usize) -> Option<&PointCloud> {
+        self.points.get(mesh).and_then(Option::as_ref)
+    }
+    pub fn get_spec_gloss(&self, material: usize) -> Option<&Arc<ImageView>> {
+        self.spec_gloss.get(&material)
+    }
+    /// Transforms of the GPU instances of a node relative to the node.
+    pub fn get_instances(&self, node: usize) -> Option<&[glm::Mat4]> {
+        self.instances.get(&node).map(Vec::as_slice)
+    }
+
+    /// Uploads image `index` of `document`, also converting it for any spec-gloss material using it.
+    pub fn load_image<L>(
+        &mut self,
+        allocator: Arc<dyn MemoryAllocator>,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        document: &gltf::Document,
+        index: usize,
+        data: gltf::image::Data,
+        options: TextureOptions,
+        cache: &VktfCache,
+    ) -> Result<(), LoadGltfError> {
+        let decoded = DecodedImage::new(allocator.device(), document, index, data, options);
+        self.upload_image(
+            allocator,
+            &mut Uploader::new(builder),
+            &decoded,
+            None,
+            cache,
+        )
+    }
+
+    /// Uploads an image prepared with [`DecodedImage::new`]. With a `size` only the mips at most
+    /// that large are uploaded and viewed, [`Vktf::upload_remaining_mips`] adds the others.
+    pub fn upload_image<L>(
+        &mut self,
+        allocator: Arc<dyn MemoryAllocator>,
+        uploader: &mut Uploader<L>,
+        decoded: &DecodedImage,
+        size: Option<u32>,
+        cache: &VktfCache,
+    ) -> Result<(), LoadGltfError> {
+        let first = |image: &CpuImage| size.map_or(0, |size| image.first_mip(size));
+        for (material, image) in &decoded.spec_gloss {
+            let vk_image = upload_image(allocator.clone(), uploader, image, first(image), cache)?;
+            self.spec_gloss
+                .insert(*material, mip_view(vk_image, first(image)));
+        }
+        let image = &decoded.image;
+        let vk_image = upload_image(allocator, uploader, image, first(image), cache)?;
+        self.images[decoded.index] = Some(mip_view(vk_image, first(image)));
+        Ok(())
+    }
+    /// Copies the mips larger than `size` of `decoded` into the images [`Vktf::upload_image`]
+    /// made for it with the same `size`.
+    pub fn upload_remaining_mips<L>(
+        &mut self,
+        allocator: Arc<dyn MemoryAllocator>,
+        uploader: &mut Uploader<L>,
+        decoded: &DecodedImage,
+        size: u32,
+        cache: &VktfCache,
+    ) -> Result<(), LoadGltfError> {
+        for (material, image) in &decoded.spec_gloss {
+            if let Some(view) = self.spec_gloss.get_mut(material) {
+                upload_remaining_mips(allocator.clone(), uploader, image, view, size, cache)?;
+            }
+        }
+        if let Some(view) = &mut self.images[decoded.index] {
+            upload_remaining_mips(allocator, uploader, &decoded.image, view, size, cache)?;
+        }
+        Ok(())
+    }
+}
+
+/// A glTF image and its spec-gloss conversions ready to be uploaded with [`Vktf::upload_image`].
+/// Only uses the CPU so images can be decoded on many threads.
+pub struct DecodedImage {
+    pub index: usize,
+    /// Size of the image in the file.
+    pub extent: [u32; 2],
+    image: CpuImage,
+    /// Metallic-roughness textures by material index.
+    spec_gloss: Vec<(usize, CpuImage)>,
+}
+impl DecodedImage {
+    pub fn new(
+        device: &Device,
+        document: &gltf::Document,
+        index: usize,
+        data: gltf::image::Data,
+        options: TextureOptions,
+    ) -> Self {
+        let mut spec_gloss = vec![];
+        for material in document.materials() {
+            let Some(sg) = material.pbr_specular_glossiness() else {
+                continue;
+            };
+            let Some(tex) = sg.specular_glossiness_texture() else {
+                continue;
+            };
+            if tex.texture().source().index() != index {
+                continue;
+            }
+            let converted = convert_spec_gloss(&data, sg.specular_factor(), sg.glossiness_factor());
+            spec_gloss.push((
+                material.index().unwrap(),
+                prepare_image(device, converted, TextureKind::Data, options),
+            ));
+        }
+
+        let kind = texture_kind(document, index);
+        Self {
+            index,
+            extent: [data.width, data.height],
+            image: prepare_image(device, data, kind, options),
+            spec_gloss,
+        }
+    }
+    /// Whether [`Vktf::upload_image`] leaves out mips larger than `size`.
+    pub fn streamed(&self, size: u32) -> bool {
+        self.image.first_mip(size) > 0
+    }
+    /// Downsamples the mips uploaded first for `size`, so uploading them doesn't wait on it.
+    pub fn prepare_preview(&mut self, size: u32) {
+        self.image.prepare_preview(size);
+        for (_, image) in &mut self.spec_gloss {
+            image.prepare_preview(size);
+        }
+    }
+    /// Decodes image `index` of `document`, read through `resolver`.
+    /// Reads it from `disk_cache` if the same bytes were prepared the same way before,
+    /// and stores it otherwise.
+    pub fn load(
+        device: &Device,
+        document: &gltf::Document,
+        index: usize,
+        resolver: &UriResolver,
+        buffers: &[gltf::buffer::Data],
+        options: TextureOptions,
+        disk_cache: &DiskCache,
+    ) -> Result<Self, LoadGltfError> {
+        let image = document.images().nth(index).unwrap();
+        // spec-gloss conversions depend on the materials, they are always made again
+        let key = (disk_cache.enabled && !is_spec_gloss(document, index))
+            .then(|| source_bytes(&image, resolver.base, buffers))
+            .flatten()
+            .map(|bytes| {
+                DiskCache::key((
+                    "image",
+                    bytes,
+                    texture_kind(document, index),
+                    options.max_size,
+                    options.compress,
+                    options.filter_normals,
+                    options.keep_precision,
+                    device.enabled_features().texture_compression_bc,
+                ))
+            });
+        if let Some(bytes) = key.and_then(|key| disk_cache.read(key)) {
+            if let Some((extent, image)) = read_cached_image(&mut Reader(&bytes)) {
+                return Ok(Self {
+                    index,
+                    extent,
+                    image,
+                    spec_gloss: vec![],
+                });
+            }
+        }
+
+        let data = resolver.read_image(document, index, buffers)?;
+        let decoded = Self::new(device, document, index, data, options);
+        if let Some(key) = key {
+            let mut writer = Writer::default();
+            writer.u32(decoded.extent[0]);
+            writer.u32(decoded.extent[1]);
+            decoded.image.write(&mut writer);
+            disk_cache.write(key, &writer.0);
+        }
+        Ok(decoded)
+    }
+}
+
+/// The size in the file and the image written by [`DecodedImage::load`].
+fn read_cached_image(reader: &mut Reader) -> Option<([u32; 2], CpuImage)> {
+    Some(([reader.u32()?, reader.u32()?], CpuImage::read(reader)?))
+}
+
+/// How images are uploaded.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextureOptions {
+    /// Larger images are halved until they fit.
+    pub max_size: Option<u32>,
+    /// Use BC7, or BC5 for normal maps, if the device supports it.
+    pub compress: bool,
+    /// Upload the small mips of all images first, then the larger ones.
+    pub stream: bool,
+    /// Keep the normals of normal map mips unit length and widen highlights where
+    /// they average out, instead of blending them like colours.
+    pub filter_normals: bool,
+    /// Upload 16 bit and float images as they are instead of converting them to 8 bits.
+    pub keep_precision: bool,
+    pub anisotropy: Anisotropy,
+}
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            max_size: None,
+            compress: true,
+            stream: true,
+            filter_normals: true,
+            keep_precision: true,
+            anisotropy: Anisotropy::default(),
+        }
+    }
+}
+impl TextureOptions {
+    const MAX_SIZES: [Option<u32>; 5] = [None, Some(512), Some(1024), Some(2048), Some(4096)];
+
+    /// Only applies to models loaded afterwards.
+    pub fn ui(&mut self, ui: &mut egui::Ui, compression_supported: bool) {
+        let name = |size: Option<u32>| size.map_or("Unlimited".to_owned(), |size| size.to_string());
+        egui::ComboBox::from_label("Max texture size")
+            .selected_text(name(self.max_size))
+            .show_ui(ui, |ui| {
+                for size in Self::MAX_SIZES {
+                    ui.selectable_value(&mut self.max_size, size, name(size));
+                }
+            });
+        ui.add_enabled(
+            compression_supported,
+            egui::Checkbox::new(&mut self.compress, "Compress textures"),
+        )
+        .on_disabled_hover_text("BC texture compression is not supported by this GPU");
+        ui.checkbox(&mut self.stream, "Stream textures")
+            .on_hover_text("Show low resolution textures first, the ones on screen sharpen first");
+        ui.checkbox(&mut self.filter_normals, "Filter normal maps")
+            .on_hover_text("Renormalize normal map mips and add the lost detail to the roughness");
+        ui.checkbox(&mut self.keep_precision, "Keep 16 bit and float textures")
+            .on_hover_text("Upload them uncompressed instead of converting them to 8 bits");
+        self.anisotropy.ui(ui);
+        ui.label("Applies to models loaded afterwards");
+    }
+}
+
+/// How the geometry is prepared.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeometryOptions {
+    /// Merge equal vertices and reorder them for the vertex cache, see [`PrimitiveData::optimize`].
+    pub optimize: bool,
+}
+impl GeometryOptions {
+    /// Only applies to models loaded afterwards.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.optimize, "Optimize vertex order")
+            .on_hover_text(
+                "Reorder vertices for the vertex cache while loading, helps heavy scans",
+            );
+    }
+}
+
+/// Colour textures are sRGB, data textures are linear.
+fn texture_kind(document: &gltf::Document, index: usize) -> TextureKind {
+    let mut normals = vec![];
+    let mut linear = vec![];
+    for material in document.materials() {
+        if let Some(tex) = material
+            .pbr_metallic_roughness()
+            .metallic_roughness_texture()
+        {
+            linear.push(tex.texture());
+        }
+        if let Some(tex) = material.occlusion_texture() {
+            linear.push(tex.texture());
+        }
+        if let Some(tex) = material.normal_texture() {
+            normals.push(tex.texture());
+        }
+        if let Some(tex) = material
+            .transmission()
+            .and_then(|tr| tr.transmission_texture())
+        {
+            linear.push(tex.texture());
+        }
+        if let Some(tex) = material.volume().and_then(|th| th.thickness_texture()) {
+            linear.push(tex.texture());
+        }
+    }
+    let uses =
+        |textures: &[gltf::Texture]| textures.iter().any(|tex| tex.source().index() == index);
+    if uses(&normals) {
+        TextureKind::Normal
+    } else if uses(&linear) {
+        TextureKind::Data
+    } else {
+        TextureKind::Color
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoadGltfError {
+    #[error(transparent)]
+    Gltf(#[from] gltf::Error),
+    #[error("failed to load image {index}: {source}")]
+    Image { index: usize, source: gltf::Error },
+    #[error("invalid data URI {0}...")]
+    DataUri(String),
+    #[error("{} downloads from {}", path.display(), hosts.join(", "))]
+    RemoteNotAllowed { path: PathBuf, hosts: Vec<String> },
+    #[error("failed to download {uri}: {message}")]
+    Download { uri: String, message: String },
+    #[error("failed to open {}: {source}", path.display())]
+    ImageFile {
+        path: PathBuf,
+        source: ::image::ImageError,
+    },
+    #[error("{} is an AVIF image, build with the avif feature to decode it", path.display())]
+    AvifDisabled { path: PathBuf },
+    #[error("primitive {primitive} of mesh {mesh} has no positions or uses an unsupported mode")]
+    UnsupportedPrimitive { mesh: usize, primitive: usize },
+    #[error(
+        "primitive {primitive} of mesh {mesh} uses vertex {index}, but has {vertices} vertices"
+    )]
+    IndexOutOfRange {
+        mesh: usize,
+        primitive: usize,
+        index: u32,
+        vertices: usize,
+    },
+    #[error("the model has {vertices} vertices and {indices} indices, more than can be drawn")]
+    TooLarge { vertices: usize, indices: usize },
+    #[error("failed to convert the model: {0}")]
+    Convert(#[from] ConvertError),
+    #[error("the file doesn't contain a scene")]
+    NoScene,
+    #[error("the file changed since it was loaded")]
+    Changed,
+    #[error(transparent)]
+    Vulkan(#[from] Validated<VulkanError>),
+    #[error("failed to allocate an image: {0}")]
+    AllocateImage(#[from] Validated<AllocateImageError>),
+    #[error("failed to allocate a buffer: {0}")]
+    AllocateBuffer(#[from] Validated<AllocateBufferError>),
+}
+
+impl LoadGltfError {
+    /// The GPU or the host ran out of memory, or an allocation was larger than allowed.
+    pub fn is_out_of_memory(&self) -> bool {
+        match self {
+            Self::Vulkan(Validated::Error(err)) => recovery::is_out_of_memory(err),
+            Self::AllocateImage(Validated::Error(err)) => match err {
+                AllocateImageError::CreateImage(err) | AllocateImageError::BindMemory(err) => {
+                    recovery::is_out_of_memory(err)
+                }
+                AllocateImageError::AllocateMemory(err) => recovery::is_allocation_failure(err),
+            },
+            Self::AllocateBuffer(Validated::Error(err)) => match err {
+                AllocateBufferError::CreateBuffer(err) | AllocateBufferError::BindMemory(err) => {
+                    recovery::is_out_of_memory(err)
+                }
+                AllocateBufferError::AllocateMemory(err) => recovery::is_allocation_failure(err),
+            },
+            Self::TooLarge { .. } => true,
+            _ => false,
+        }
+    }
 }
 
 pub struct Loader<'a, L> {
     device: Arc<Device>,
     allocator: Arc<dyn MemoryAllocator>,
     builder: &'a mut AutoCommandBufferBuilder<L>,
+    cache: &'a VktfCache,
+    disk_cache: &'a DiskCache,
+    anisotropy: Anisotropy,
+    geometry: GeometryOptions,
 
     vktf: Vktf,
 }
@@ -57,95 +509,162 @@ impl<'a, L> Loader<'a, L> {
     pub fn new(
         allocator: Arc<dyn MemoryAllocator>,
         builder: &'a mut AutoCommandBufferBuilder<L>,
+        cache: &'a VktfCache,
+        disk_cache: &'a DiskCache,
+        anisotropy: Anisotropy,
+        geometry: GeometryOptions,
     ) -> Self {
         Self {
             device: allocator.device().clone(),
             allocator,
             builder,
+            cache,
+            disk_cache,
+            anisotropy,
+            geometry,
             vktf: Vktf::default(),
         }
     }
+    /// Loads everything but the images, which are streamed in with [`Vktf::load_image`].
+    /// Image files are looked up relative to `base` to find duplicates.
     pub fn load(
         mut self,
         document: &gltf::Document,
+        base: Option<&Path>,
         buffers: &[gltf::buffer::Data],
-        images: Vec<gltf::image::Data>,
-    ) -> Vktf {
-        self.load_meshes(document, buffers);
-        self.load_images(document, images);
+    ) -> Result<Vktf, LoadGltfError> {
+        self.vktf.images = vec![None; document.images().len()];
+        self.vktf.aliases = image_aliases(document, base, buffers);
+        self.load_meshes(document, buffers)?;
         self.load_samplers(document);
+        self.load_instances(document, buffers);
         self.load_defaults();
-        self.vktf
+        Ok(self.vktf)
     }
 
     fn load_samplers(&mut self, document: &gltf::Document) {
         for sampler in document.samplers() {
-            self.vktf
-                .samplers
-                .push(create_vk_sampler(self.device.clone(), &sampler));
+            self.vktf.samplers.push(create_vk_sampler(
+                &self.device,
+                &sampler,
+                self.anisotropy,
+                self.cache,
+            ));
         }
     }
-    fn load_images(&mut self, document: &gltf::Document, images: Vec<gltf::image::Data>) {
-        let mut is_srgb = vec![true; images.len()];
-        for material in document.materials() {
-            if let Some(tex) = material
-                .pbr_metallic_roughness()
-                .metallic_roughness_texture()
-            {
-                is_srgb[tex.texture().source().index()] = false;
-            }
-            if let Some(tex) = material.occlusion_texture() {
-                is_srgb[tex.texture().source().index()] = false;
-            }
-            if let Some(tex) = material.normal_texture() {
-                is_srgb[tex.texture().source().index()] = false;
+    fn load_instances(&mut self, document: &gltf::Document, buffers: &[gltf::buffer::Data]) {
+        for node in document.nodes().filter(|node| node.mesh().is_some()) {
+            if let Some(instances) = read_instances(&node, document, buffers) {
+                self.vktf.instances.insert(node.index(), instances);
             }
         }
+    }
+    fn load_meshes(
+        &mut self,
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<(), LoadGltfError> {
+        let meshes = self.read_cached_meshes(document, buffers)?;
+        let points = document
+            .meshes()
+            .map(|mesh| {
+                let points = mesh
+                    .primitives()
+                    .filter(is_points)
+                    .map(|primitive| {
+                        read_points(&primitive, buffers).ok_or(
+                            LoadGltfError::UnsupportedPrimitive {
+                                mesh: mesh.index(),
+                                primitive: primitive.index(),
+                            },
+                        )
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(points.concat())
+            })
+            .collect::<Result<Vec<Vec<PointVertex>>, _>>()?;
 
-        for (data, is_srgb) in images.into_iter().zip(is_srgb) {
-            let image = create_vk_image(self.allocator.clone(), self.builder, data, is_srgb);
-            let view = ImageView::new_default(image).unwrap();
-            self.vktf.images.push(view);
+        // all primitives share one vertex and one index buffer with 32 bit offsets
+        let vertices = meshes
+            .iter()
+            .flatten()
+            .map(PrimitiveData::vertex_count)
+            .sum();
+        let indices = meshes
+            .iter()
+            .flatten()
+            .map(PrimitiveData::index_count)
+            .sum();
+        if vertices > i32::MAX as usize || indices > u32::MAX as usize {
+            return Err(LoadGltfError::TooLarge { vertices, indices });
         }
+        self.vktf.lines = upload_vertex_lines(&meshes, self);
+        self.vktf.meshes = upload_primitives(meshes, self);
+        self.vktf.points = upload_points(points, self);
+        Ok(())
     }
-    fn load_meshes(&mut self, document: &gltf::Document, buffers: &[gltf::buffer::Data]) {
-        for mesh in document.meshes() {
-            let primitives = mesh
-                .primitives()
-                .map(|primitive| Primitive::from_loader(&primitive, buffers, self).unwrap()) // TODO: do smt better than unwrap
-                .collect();
-            self.vktf.meshes.push(primitives);
+    /// [`read_meshes`] through the disk cache, keyed by the whole document and its buffers.
+    fn read_cached_meshes(
+        &self,
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Vec<Vec<PrimitiveData>>, LoadGltfError> {
+        if !self.disk_cache.enabled {
+            return read_meshes(document, buffers, self.geometry);
+        }
+        let json = serde_json::to_vec(document.as_json()).unwrap_or_default();
+        let buffers_bytes: Vec<&[u8]> = buffers.iter().map(|data| data.0.as_slice()).collect();
+        let key = DiskCache::key(("meshes", json, buffers_bytes, self.geometry.optimize));
+        if let Some(bytes) = self.disk_cache.read(key) {
+            let mut reader = Reader(&bytes);
+            if let Some(meshes) = read_cached(&mut reader) {
+                return Ok(meshes);
+            }
+            log::warn!("ignoring an invalid cached model");
         }
+
+        let meshes = read_meshes(document, buffers, self.geometry)?;
+        let mut writer = Writer::default();
+        writer.u64(meshes.len() as u64);
+        for primitives in &meshes {
+            writer.u64(primitives.len() as u64);
+            for data in primitives {
+                data.write(&mut writer);
+            }
+        }
+        self.disk_cache.write(key, &writer.0);
+        Ok(meshes)
+    }
+    /// Uploads simplified copies of the triangle primitives of every mesh,
+    /// returns them by mesh index with the largest relative error.
+    pub fn load_simplified(
+        mut self,
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+        ratio: f32,
+    ) -> Result<(Vec<Vec<Primitive>>, f32), LoadGltfError> {
+        let mut error = 0.0f32;
+        let meshes = read_meshes(document, buffers, self.geometry)?
+            .iter()
+            .map(|primitives| {
+                primitives
+                    .iter()
+                    .map(|data| {
+                        let (data, data_error) = data.simplify(ratio);
+                        error = error.max(data_error);
+                        data
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok((upload_primitives(meshes, &mut self), error))
     }
     fn load_defaults(&mut self) {
-        let address_mode = [
-            convert_wrap(gltf::texture::WrappingMode::default()),
-            convert_wrap(gltf::texture::WrappingMode::default()),
-            SamplerAddressMode::ClampToEdge,
-        ];
-        let mag_filter = DEFAULT_MAG;
-        let (min_filter, mipmap_mode) = (DEFAULT_MIN, DEFAULT_MIPMAP);
-        let anisotropy = Some(
-            self.device
-                .physical_device()
-                .properties()
-                .max_sampler_anisotropy,
-        );
-
-        self.vktf.default_sampler = Some(
-            Sampler::new(
-                self.device.clone(),
-                SamplerCreateInfo {
-                    mag_filter,
-                    min_filter,
-                    mipmap_mode,
-                    address_mode,
-                    anisotropy,
-                    ..SamplerCreateInfo::simple_repeat_linear()
-                },
-            )
-            .unwrap(),
-        );
+        self.vktf.default_sampler = Some(default_vk_sampler(
+            &self.device,
+            self.anisotropy,
+            self.cache,
+        ));
 
         let image = Image::new(
             self.allocator.clone(),
@@ -163,21 +682,255 @@ impl<'a, L> Loader<'a, L> {
     }
 }
 
+/// The triangle primitives of every mesh, points are read separately.
+fn read_meshes(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    options: GeometryOptions,
+) -> Result<Vec<Vec<PrimitiveData>>, LoadGltfError> {
+    document
+        .meshes()
+        .map(|mesh| {
+            mesh.primitives()
+                .filter(|primitive| !is_points(primitive))
+                .map(|primitive| {
+                    PrimitiveData::new(mesh.index(), &primitive, buffers).map(|mut data| {
+                        if options.optimize {
+                            data.optimize();
+                        }
+                        data
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The meshes written by [`Loader::read_cached_meshes`].
+fn read_cached(reader: &mut Reader) -> Option<Vec<Vec<PrimitiveData>>> {
+    (0..reader.u64()?)
+        .map(|_| {
+            (0..reader.u64()?)
+                .map(|_| PrimitiveData::read(reader))
+                .collect()
+        })
+        .collect()
+}
+
+/// Where a document is read from.
+#[derive(Clone)]
+pub enum ModelSource {
+    File(PathBuf),
+    /// A .gltf or .glb in memory, named in the UI. Its URIs can only be data URIs or downloads.
+    Bytes {
+        name: String,
+        bytes: Arc<[u8]>,
+    },
+}
+
+/// What a load shares with the others and how it prepares the document.
+#[derive(Clone, Copy)]
+pub struct LoadContext<'a> {
+    pub cache: &'a VktfCache,
+    pub disk_cache: &'a DiskCache,
+    pub remote: &'a RemoteAccess,
+    pub progress: Option<&'a ProgressSender>,
+    pub anisotropy: Anisotropy,
+    pub geometry: GeometryOptions,
+}
+
+#[derive(Clone)]
 pub struct VktfDocument {
     pub vktf: Vktf,
     pub document: gltf::Document,
+    /// The file the document was loaded from, only a name if it was loaded from memory.
+    pub path: PathBuf,
+    /// The file in memory, `None` if it is read from `path`.
+    pub bytes: Option<Arc<[u8]>>,
+    pub geometry: GeometryOptions,
+    /// Animations with `KHR_animation_pointer` channels.
+    pub animations: Vec<PointerAnimation>,
+    /// Hosts the buffers and images may be downloaded from when the file is read again.
+    pub remote: RemoteAccess,
 }
 impl VktfDocument {
+    /// Parses the file and uploads its geometry.
+    /// Returns the buffers as they are needed to decode the images later.
     pub fn new(
         allocator: Arc<dyn MemoryAllocator>,
         builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
         path: impl AsRef<Path>,
-    ) -> gltf::Result<Self> {
-        let (document, buffers, images) = gltf::import(path)?;
+        context: LoadContext,
+    ) -> Result<(Self, Vec<gltf::buffer::Data>), LoadGltfError> {
+        let parsed = open_model(path.as_ref())?;
+        Self::load(
+            allocator,
+            builder,
+            parsed,
+            path.as_ref().to_owned(),
+            None,
+            context,
+        )
+    }
+    /// Like [`VktfDocument::new`] for a .gltf or .glb in memory, `name` is shown in the UI.
+    pub fn from_slice(
+        allocator: Arc<dyn MemoryAllocator>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        bytes: Arc<[u8]>,
+        name: &str,
+        context: LoadContext,
+    ) -> Result<(Self, Vec<gltf::buffer::Data>), LoadGltfError> {
+        let parsed = open_model_slice(&bytes)?;
+        Self::load(
+            allocator,
+            builder,
+            parsed,
+            name.into(),
+            Some(bytes),
+            context,
+        )
+    }
+    /// Reads the document from `source`.
+    pub fn open(
+        allocator: Arc<dyn MemoryAllocator>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        source: &ModelSource,
+        context: LoadContext,
+    ) -> Result<(Self, Vec<gltf::buffer::Data>), LoadGltfError> {
+        match source {
+            ModelSource::File(path) => Self::new(allocator, builder, path, context),
+            ModelSource::Bytes { name, bytes } => {
+                Self::from_slice(allocator, builder, bytes.clone(), name, context)
+            }
+        }
+    }
+    fn load(
+        allocator: Arc<dyn MemoryAllocator>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        (gltf::Gltf { document, blob }, pointers): (gltf::Gltf, Vec<PointerChannel>),
+        path: PathBuf,
+        bytes: Option<Arc<[u8]>>,
+        context: LoadContext,
+    ) -> Result<(Self, Vec<gltf::buffer::Data>), LoadGltfError> {
+        if document.default_scene().is_none() && document.scenes().len() == 0 {
+            return Err(LoadGltfError::NoScene);
+        }
+        context.remote.check(&document, &path)?;
+        // files in memory have nothing next to them
+        let resolver = UriResolver {
+            base: path.parent().filter(|_| bytes.is_none()),
+            remote: context.remote,
+            progress: context.progress,
+        };
+        let buffers = resolver.import_buffers(&document, blob)?;
 
-        let loader = Loader::new(allocator, builder);
-        let vktf = loader.load(&document, &buffers, images);
+        let loader = Loader::new(
+            allocator,
+            builder,
+            context.cache,
+            context.disk_cache,
+            context.anisotropy,
+            context.geometry,
+        );
+        let vktf = loader.load(&document, resolver.base, &buffers)?;
+        let animations = read_pointer_animations(&document, &buffers, &pointers);
 
-        Ok(Self { document, vktf })
+        Ok((
+            Self {
+                document,
+                vktf,
+                path,
+                bytes,
+                geometry: context.geometry,
+                animations,
+                remote: context.remote.clone(),
+            },
+            buffers,
+        ))
+    }
+    /// How the document was opened, to open it again.
+    pub fn source(&self) -> ModelSource {
+        match &self.bytes {
+            Some(bytes) => ModelSource::Bytes {
+                name: self.path.to_string_lossy().into_owned(),
+                bytes: bytes.clone(),
+            },
+            None => ModelSource::File(self.path.clone()),
+        }
+    }
+    /// Parses the file or the bytes again.
+    pub fn reopen(&self) -> Result<(gltf::Gltf, Vec<PointerChannel>), LoadGltfError> {
+        match &self.bytes {
+            Some(bytes) => open_model_slice(bytes),
+            None => open_model(&self.path),
+        }
+    }
+    /// Reads the buffers and images of the file.
+    pub fn resolver<'a>(&'a self, progress: Option<&'a ProgressSender>) -> UriResolver<'a> {
+        UriResolver {
+            base: self.path.parent().filter(|_| self.bytes.is_none()),
+            remote: &self.remote,
+            progress,
+        }
+    }
+    /// Reads the file again and uploads the geometry with about `ratio` of the triangles.
+    /// Returns the primitives by mesh index with the largest relative error.
+    pub fn simplify<L>(
+        &self,
+        allocator: Arc<dyn MemoryAllocator>,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        ratio: f32,
+        context: LoadContext,
+    ) -> Result<(Vec<Vec<Primitive>>, f32), LoadGltfError> {
+        let (gltf::Gltf { document, blob }, _) = self.reopen()?;
+        let buffers = self.resolver(None).import_buffers(&document, blob)?;
+        // simplified copies aren't worth caching
+        let mut disk_cache = DiskCache::default();
+        disk_cache.enabled = false;
+        let loader = Loader::new(
+            allocator,
+            builder,
+            context.cache,
+            &disk_cache,
+            context.anisotropy,
+            self.geometry,
+        );
+        let (meshes, error) = loader.load_simplified(&document, &buffers, ratio)?;
+        // the vertices have to line up with the loaded ones
+        let vertices = |meshes: &[Vec<Primitive>]| -> Vec<usize> {
+            meshes
+                .iter()
+                .flatten()
+                .map(|primitive| primitive.info.vertices)
+                .collect()
+        };
+        if vertices(&meshes) != vertices(&self.vktf.meshes) {
+            return Err(LoadGltfError::Changed);
+        }
+        Ok((meshes, error))
+    }
+    /// Reads the file again for the vertices of the primitives by mesh index,
+    /// in the order they are in the geometry buffer.
+    pub fn read_geometry(&self) -> Result<Vec<Vec<PrimitiveGeometry>>, LoadGltfError> {
+        let (gltf::Gltf { document, blob }, _) = self.reopen()?;
+        let buffers = self.resolver(None).import_buffers(&document, blob)?;
+        let meshes: Vec<Vec<PrimitiveGeometry>> = read_meshes(&document, &buffers, self.geometry)?
+            .into_iter()
+            .map(|primitives| {
+                primitives
+                    .into_iter()
+                    .map(PrimitiveGeometry::from)
+                    .collect()
+            })
+            .collect();
+        let read = meshes
+            .iter()
+            .flatten()
+            .map(|geometry| geometry.vertices.len());
+        let loaded = self.vktf.meshes.iter().flatten();
+        if !read.eq(loaded.map(|primitive| primitive.info.vertices)) {
+            return Err(LoadGltfError::Changed);
+        }
+        Ok(meshes)
     }
 }
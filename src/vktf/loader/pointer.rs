@@ -0,0 +1,366 @@
+use gltf::{
+    accessor::{DataType, Dimensions, Iter},
+    animation::Interpolation,
+};
+use serde_json::Value;
+
+const EXTENSION: &str = "KHR_animation_pointer";
+
+/// A `KHR_animation_pointer` channel, taken out of the JSON before parsing
+/// as gltf requires every channel to target a node.
+#[derive(Debug, Clone)]
+pub struct PointerChannel {
+    pub animation: usize,
+    pub sampler: usize,
+    pub pointer: String,
+}
+
+/// Removes the channels with a `pointer` path from `root` and returns them.
+pub fn take_pointer_channels(root: &mut Value) -> Vec<PointerChannel> {
+    let mut channels = vec![];
+    let Some(animations) = root.get_mut("animations").and_then(Value::as_array_mut) else {
+        return channels;
+    };
+    for (animation, json) in animations.iter_mut().enumerate() {
+        let Some(list) = json.get_mut("channels").and_then(Value::as_array_mut) else {
+            continue;
+        };
+        list.retain(|channel| {
+            let target = &channel["target"];
+            if target["path"] != "pointer" {
+                return true;
+            }
+            let pointer = target["extensions"][EXTENSION]["pointer"].as_str();
+            let sampler = channel["sampler"].as_u64();
+            if let (Some(pointer), Some(sampler)) = (pointer, sampler) {
+                channels.push(PointerChannel {
+                    animation,
+                    sampler: sampler as usize,
+                    pointer: pointer.to_owned(),
+                });
+            }
+            false
+        });
+    }
+    // the channels are handled here, gltf would refuse the required extension
+    if let Some(required) = root
+        .get_mut("extensionsRequired")
+        .and_then(Value::as_array_mut)
+    {
+        required.retain(|extension| extension != EXTENSION);
+    }
+    channels
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaterialProperty {
+    BaseColor,
+    Emissive,
+    Roughness,
+    Metallic,
+    NormalScale,
+    OcclusionStrength,
+    Transmission,
+    Thickness,
+    AttenuationColor,
+    AttenuationDistance,
+    Ior,
+}
+impl MaterialProperty {
+    fn parse(path: &[&str]) -> Option<Self> {
+        Some(match path {
+            ["pbrMetallicRoughness", "baseColorFactor"] => Self::BaseColor,
+            ["pbrMetallicRoughness", "roughnessFactor"] => Self::Roughness,
+            ["pbrMetallicRoughness", "metallicFactor"] => Self::Metallic,
+            ["emissiveFactor"] => Self::Emissive,
+            ["normalTexture", "scale"] => Self::NormalScale,
+            ["occlusionTexture", "strength"] => Self::OcclusionStrength,
+            [
+                "extensions",
+                "KHR_materials_transmission",
+                "transmissionFactor",
+            ] => Self::Transmission,
+            ["extensions", "KHR_materials_volume", "thicknessFactor"] => Self::Thickness,
+            ["extensions", "KHR_materials_volume", "attenuationColor"] => Self::AttenuationColor,
+            ["extensions", "KHR_materials_volume", "attenuationDistance"] => {
+                Self::AttenuationDistance
+            }
+            ["extensions", "KHR_materials_ior", "ior"] => Self::Ior,
+            _ => return None,
+        })
+    }
+    fn width(&self) -> usize {
+        match self {
+            Self::BaseColor => 4,
+            Self::Emissive | Self::AttenuationColor => 3,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeProperty {
+    Translation,
+    Rotation,
+    Scale,
+    /// Morph weights of the mesh of the node.
+    Weights,
+    /// `KHR_node_visibility`
+    Visible,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightProperty {
+    Color,
+    Intensity,
+    Range,
+}
+
+/// What a pointer animates, by glTF index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerTarget {
+    Material(usize, MaterialProperty),
+    Node(usize, NodeProperty),
+    MeshWeights(usize),
+    /// The viewer has one camera, every glTF camera drives it.
+    CameraFov(usize),
+    Light(usize, LightProperty),
+}
+impl PointerTarget {
+    /// `None` if the property isn't shown by the viewer.
+    pub fn parse(pointer: &str) -> Option<Self> {
+        let path: Vec<&str> = pointer.strip_prefix('/')?.split('/').collect();
+        let index = |i: usize| path.get(i)?.parse::<usize>().ok();
+        Some(match path.as_slice() {
+            ["materials", _, property @ ..] => {
+                Self::Material(index(1)?, MaterialProperty::parse(property)?)
+            }
+            ["nodes", _, property @ ..] => {
+                let property = match property {
+                    ["translation"] => NodeProperty::Translation,
+                    ["rotation"] => NodeProperty::Rotation,
+                    ["scale"] => NodeProperty::Scale,
+                    ["weights"] => NodeProperty::Weights,
+                    ["extensions", "KHR_node_visibility", "visible"] => NodeProperty::Visible,
+                    _ => return None,
+                };
+                Self::Node(index(1)?, property)
+            }
+            ["meshes", _, "weights"] => Self::MeshWeights(index(1)?),
+            ["cameras", _, "perspective", "yfov"] => Self::CameraFov(index(1)?),
+            ["extensions", "KHR_lights_punctual", "lights", _, property] => {
+                let property = match *property {
+                    "color" => LightProperty::Color,
+                    "intensity" => LightProperty::Intensity,
+                    "range" => LightProperty::Range,
+                    _ => return None,
+                };
+                Self::Light(index(3)?, property)
+            }
+            _ => return None,
+        })
+    }
+    /// Components per keyframe, `None` if it depends on the mesh.
+    fn width(&self) -> Option<usize> {
+        Some(match self {
+            Self::Material(_, property) => property.width(),
+            Self::Node(_, NodeProperty::Translation | NodeProperty::Scale) => 3,
+            Self::Node(_, NodeProperty::Rotation) => 4,
+            Self::Node(_, NodeProperty::Weights) | Self::MeshWeights(_) => return None,
+            Self::Light(_, LightProperty::Color) => 3,
+            _ => 1,
+        })
+    }
+}
+
+/// The keyframes of one pointer.
+#[derive(Debug, Clone)]
+pub struct PointerTrack {
+    pub target: PointerTarget,
+    times: Vec<f32>,
+    /// `width` components per keyframe, with an in and out tangent around each for cubic splines.
+    values: Vec<f32>,
+    width: usize,
+    interpolation: Interpolation,
+}
+impl PointerTrack {
+    /// The value at `time`, held before the first and after the last keyframe.
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        let cubic = self.interpolation == Interpolation::CubicSpline;
+        let stride = if cubic { 3 } else { 1 };
+        let key =
+            |i: usize, part: usize| &self.values[(i * stride + part) * self.width..][..self.width];
+        let value = |i: usize| key(i, if cubic { 1 } else { 0 });
+
+        let next = self.times.partition_point(|t| *t <= time);
+        if next == 0 {
+            return value(0).to_vec();
+        }
+        if next == self.times.len() {
+            return value(next - 1).to_vec();
+        }
+        let prev = next - 1;
+        let dt = self.times[next] - self.times[prev];
+        let t = if dt > 0.0 {
+            (time - self.times[prev]) / dt
+        } else {
+            0.0
+        };
+        let mut out: Vec<f32> = match self.interpolation {
+            Interpolation::Step => return value(prev).to_vec(),
+            Interpolation::Linear => {
+                let (a, b) = (value(prev), value(next));
+                // the shorter way around for rotations
+                let sign = if self.is_rotation() && dot(a, b) < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                a.iter()
+                    .zip(b)
+                    .map(|(a, b)| a + (b * sign - a) * t)
+                    .collect()
+            }
+            Interpolation::CubicSpline => {
+                let t2 = t * t;
+                let t3 = t2 * t;
+                let (p0, m0) = (value(prev), key(prev, 2));
+                let (p1, m1) = (value(next), key(next, 0));
+                (0..self.width)
+                    .map(|i| {
+                        (2.0 * t3 - 3.0 * t2 + 1.0) * p0[i]
+                            + (t3 - 2.0 * t2 + t) * m0[i] * dt
+                            + (-2.0 * t3 + 3.0 * t2) * p1[i]
+                            + (t3 - t2) * m1[i] * dt
+                    })
+                    .collect()
+            }
+        };
+        if self.is_rotation() {
+            let length = dot(&out, &out).sqrt();
+            if length > 0.0 {
+                out.iter_mut().for_each(|x| *x /= length);
+            }
+        }
+        out
+    }
+    fn is_rotation(&self) -> bool {
+        matches!(self.target, PointerTarget::Node(_, NodeProperty::Rotation))
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// An animation with at least one `KHR_animation_pointer` channel.
+#[derive(Debug, Clone)]
+pub struct PointerAnimation {
+    /// Index of the glTF animation.
+    pub index: usize,
+    pub name: String,
+    /// Time of the last keyframe in seconds.
+    pub duration: f32,
+    pub tracks: Vec<PointerTrack>,
+    /// Pointers that can't be shown, with the reason.
+    pub ignored: Vec<(String, &'static str)>,
+}
+
+/// Reads the keyframes of `channels`, grouped by animation.
+pub fn read_pointer_animations(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    channels: &[PointerChannel],
+) -> Vec<PointerAnimation> {
+    let mut animations: Vec<PointerAnimation> = vec![];
+    for channel in channels {
+        let Some(animation) = document.animations().nth(channel.animation) else {
+            continue;
+        };
+        let position = match animations.iter().position(|a| a.index == channel.animation) {
+            Some(position) => position,
+            None => {
+                animations.push(PointerAnimation {
+                    index: channel.animation,
+                    name: animation
+                        .name()
+                        .map_or_else(|| format!("Animation {}", channel.animation), str::to_owned),
+                    duration: 0.0,
+                    tracks: vec![],
+                    ignored: vec![],
+                });
+                animations.len() - 1
+            }
+        };
+        let entry = &mut animations[position];
+        let track = PointerTarget::parse(&channel.pointer)
+            .ok_or("unsupported property")
+            .and_then(|target| {
+                let sampler = animation
+                    .samplers()
+                    .nth(channel.sampler)
+                    .ok_or("missing sampler")?;
+                read_track(target, &sampler, buffers)
+            });
+        match track {
+            Ok(track) => {
+                entry.duration = entry.duration.max(*track.times.last().unwrap());
+                entry.tracks.push(track);
+            }
+            Err(reason) => entry.ignored.push((channel.pointer.clone(), reason)),
+        }
+    }
+    animations
+}
+
+fn read_track(
+    target: PointerTarget,
+    sampler: &gltf::animation::Sampler,
+    buffers: &[gltf::buffer::Data],
+) -> Result<PointerTrack, &'static str> {
+    let times = read_floats(sampler.input(), buffers).ok_or("unreadable keyframe times")?;
+    let values = read_floats(sampler.output(), buffers).ok_or("unreadable keyframe values")?;
+    let interpolation = sampler.interpolation();
+    let stride = if interpolation == Interpolation::CubicSpline {
+        3
+    } else {
+        1
+    };
+    if times.is_empty() {
+        return Err("no keyframes");
+    }
+    let width = target
+        .width()
+        .unwrap_or(values.len() / (times.len() * stride));
+    if width == 0 || values.len() != times.len() * stride * width {
+        return Err("keyframe values don't match the property");
+    }
+    Ok(PointerTrack {
+        target,
+        times,
+        values,
+        width,
+        interpolation,
+    })
+}
+
+/// The components of a float accessor, or of an unsigned byte one for booleans.
+fn read_floats(accessor: gltf::Accessor, buffers: &[gltf::buffer::Data]) -> Option<Vec<f32>> {
+    let get_buffer = |buffer: gltf::Buffer| buffers.get(buffer.index()).map(|d| d.0.as_slice());
+    Some(match (accessor.data_type(), accessor.dimensions()) {
+        (DataType::F32, Dimensions::Scalar) => Iter::<f32>::new(accessor, get_buffer)?.collect(),
+        (DataType::F32, Dimensions::Vec2) => Iter::<[f32; 2]>::new(accessor, get_buffer)?
+            .flatten()
+            .collect(),
+        (DataType::F32, Dimensions::Vec3) => Iter::<[f32; 3]>::new(accessor, get_buffer)?
+            .flatten()
+            .collect(),
+        (DataType::F32, Dimensions::Vec4) => Iter::<[f32; 4]>::new(accessor, get_buffer)?
+            .flatten()
+            .collect(),
+        (DataType::U8, Dimensions::Scalar) => Iter::<u8>::new(accessor, get_buffer)?
+            .map(f32::from)
+            .collect(),
+        _ => return None,
+    })
+}
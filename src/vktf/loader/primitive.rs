@@ -1,15 +1,22 @@
-use super::Loader;
+use super::{
+    LoadGltfError, Loader,
+    disk_cache::{Reader, Writer},
+};
+use crate::vktf::{
+    bounds::Aabb,
+    morph::{MAX_MORPH_TARGETS, MorphTargets},
+};
 use nalgebra_glm as glm;
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 use vulkano::{
-    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, IndexBuffer, Subbuffer},
     command_buffer::{AutoCommandBufferBuilder, CopyBufferInfo},
     memory::allocator::{AllocationCreateInfo, MemoryAllocator, MemoryTypeFilter},
     pipeline::graphics::vertex_input::Vertex,
 };
 
 #[repr(C)]
-#[derive(Debug, Default, BufferContents, Vertex)]
+#[derive(Debug, Default, Clone, Copy, BufferContents, Vertex)]
 pub struct PrimitiveVertex {
     #[format(R32G32B32_SFLOAT)]
     pub position: glm::Vec3,
@@ -23,10 +30,80 @@ pub struct PrimitiveVertex {
     pub uv_1: glm::Vec2,
 }
 
+/// Where the tangents of a primitive came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TangentSource {
+    Provided,
+    /// Generated with MikkTSpace for the normal map.
+    Generated,
+    /// Not needed without a normal map.
+    None,
+}
+impl TangentSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TangentSource::Provided => "Provided",
+            TangentSource::Generated => "Generated",
+            TangentSource::None => "None",
+        }
+    }
+}
+
+/// What was read and generated for a primitive, shown in the scene panel.
+#[derive(Debug, Clone, Copy)]
+pub struct PrimitiveInfo {
+    /// Index of the primitive in its glTF mesh.
+    pub index: usize,
+    /// The uploaded vertices, with flat normals every triangle has its own.
+    pub vertices: usize,
+    /// `None` if the vertices are drawn in order.
+    pub indices: Option<usize>,
+    /// `None` for the default material.
+    pub material: Option<usize>,
+    pub tangents: TangentSource,
+    /// The primitive had no normals, so flat ones were generated.
+    pub flat_normals: bool,
+    /// Some attribute, the indices or a morph target replace values with a sparse accessor.
+    pub sparse: bool,
+    /// Set if the vertices were reordered for the vertex cache.
+    pub vertex_cache: Option<VertexCacheStats>,
+}
+
+/// Vertices of a cache with this many entries are shaded once.
+const VERTEX_CACHE_SIZE: u32 = 16;
+
+/// Vertex shader invocations of a primitive before and after optimizing it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VertexCacheStats {
+    pub triangles: usize,
+    pub before: usize,
+    pub after: usize,
+}
+impl VertexCacheStats {
+    /// Average cache miss ratio, the vertices shaded per triangle, before optimizing.
+    pub fn acmr_before(&self) -> f32 {
+        self.before as f32 / self.triangles.max(1) as f32
+    }
+    pub fn acmr_after(&self) -> f32 {
+        self.after as f32 / self.triangles.max(1) as f32
+    }
+}
+impl std::ops::AddAssign for VertexCacheStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.triangles += rhs.triangles;
+        self.before += rhs.before;
+        self.after += rhs.after;
+    }
+}
+
 struct PrimitiveVertexDataBuilder<'a, 's, F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>> {
     vertices: Vec<PrimitiveVertex>,
+    /// Sequential if the primitive isn't indexed.
     indices: Vec<u32>,
+    indexed: bool,
     nm_set: i32,
+    /// Set if flat normals were generated, the original vertex of each vertex.
+    flat: Option<Vec<u32>>,
     reader: gltf::mesh::Reader<'a, 's, F>,
 }
 impl<'a, 's, F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>>
@@ -41,66 +118,85 @@ impl<'a, 's, F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>>
             })
             .collect();
 
-        let indices: Vec<_> = reader
-            .read_indices()
-            .map(|i| i.into_u32().collect())
-            .unwrap_or_else(|| (0..vertices.len() as u32).collect());
+        let indices: Option<Vec<u32>> = reader.read_indices().map(|i| i.into_u32().collect());
+        let indexed = indices.is_some();
+        let indices = indices.unwrap_or_else(|| (0..vertices.len() as u32).collect());
 
         Some(Self {
             vertices,
             indices,
+            indexed,
             reader,
             nm_set,
+            flat: None,
         })
     }
-    fn set_normals(&mut self) {
-        match self.reader.read_normals() {
-            Some(normals) => {
-                for (i, normal) in normals.enumerate() {
-                    self.vertices[i].normal = normal.into();
-                }
+    /// Without normals every triangle gets its own vertices with the face normal,
+    /// so the other attributes have to be read before.
+    fn set_normals(&mut self, mode: gltf::mesh::Mode) {
+        if let Some(normals) = self.reader.read_normals() {
+            for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+                vertex.normal = normal.into();
             }
-            None => {
-                unimplemented!("calculate flat normals and ignore provided tangents")
+            return;
+        }
+        if mode != gltf::mesh::Mode::Triangles {
+            return;
+        }
+
+        let source = std::mem::take(&mut self.indices);
+        let mut vertices: Vec<_> = source.iter().map(|&i| self.vertices[i as usize]).collect();
+        for triangle in vertices.chunks_exact_mut(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i].position);
+            let normal = (b - a)
+                .cross(&(c - a))
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_default();
+            for vertex in triangle {
+                vertex.normal = normal;
             }
         }
+        self.indices = (0..vertices.len() as u32).collect();
+        self.indexed = false;
+        self.vertices = vertices;
+        self.flat = Some(source);
     }
     fn set_textures_sets(&mut self) {
-        for (i, tex) in self
-            .reader
-            .read_tex_coords(0)
-            .into_iter()
-            .flat_map(|iter| iter.into_f32())
-            .enumerate()
-        {
-            self.vertices[i].uv_0 = tex.into();
+        for (vertex, tex) in self.vertices.iter_mut().zip(
+            self.reader
+                .read_tex_coords(0)
+                .into_iter()
+                .flat_map(|iter| iter.into_f32()),
+        ) {
+            vertex.uv_0 = tex.into();
         }
-        for (i, tex) in self
-            .reader
-            .read_tex_coords(1)
-            .into_iter()
-            .flat_map(|iter| iter.into_f32())
-            .enumerate()
-        {
-            self.vertices[i].uv_1 = tex.into();
+        for (vertex, tex) in self.vertices.iter_mut().zip(
+            self.reader
+                .read_tex_coords(1)
+                .into_iter()
+                .flat_map(|iter| iter.into_f32()),
+        ) {
+            vertex.uv_1 = tex.into();
         }
     }
-    fn set_tangents(&mut self) {
-        match self.reader.read_tangents() {
+    fn set_tangents(&mut self) -> TangentSource {
+        // provided tangents don't fit generated normals
+        match self.reader.read_tangents().filter(|_| self.flat.is_none()) {
             // use provided tangents
             Some(tangents) => {
-                for (i, tangent) in tangents.enumerate() {
-                    self.vertices[i].tangent = tangent.into();
+                for (vertex, tangent) in self.vertices.iter_mut().zip(tangents) {
+                    vertex.tangent = tangent.into();
                 }
+                TangentSource::Provided
             }
-            None => {
-                if self.nm_set >= 0 {
-                    assert!(
-                        mikktspace::generate_tangents(self),
-                        "generating tangents failed"
-                    );
-                }
+            None if self.nm_set >= 0 => {
+                assert!(
+                    mikktspace::generate_tangents(self),
+                    "generating tangents failed"
+                );
+                TangentSource::Generated
             }
+            None => TangentSource::None,
         }
     }
 }
@@ -161,18 +257,74 @@ impl<'a, 's, F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>> mikktspace::Ge
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct Primitive {
-    vbuf: Subbuffer<[PrimitiveVertex]>,
-    ibuf: Subbuffer<[u32]>,
-    ilen: u32,
+fn read_morph_targets(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    vertex_count: usize,
+) -> Option<(Vec<glm::Vec4>, u32)> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|d| d.0.as_slice()));
+    let targets: Vec<_> = reader.read_morph_targets().collect();
+    if targets.is_empty() {
+        return None;
+    }
+    if targets.len() > MAX_MORPH_TARGETS {
+        log::warn!(
+            "primitive has {} morph targets, only the first {MAX_MORPH_TARGETS} are used",
+            targets.len()
+        );
+    }
+    let count = targets.len().min(MAX_MORPH_TARGETS);
+
+    let mut deltas = vec![glm::Vec4::zeros(); vertex_count * count * 3];
+    for (t, (positions, normals, tangents)) in targets.into_iter().take(count).enumerate() {
+        let attributes = [
+            positions.map(|iter| iter.collect::<Vec<_>>()),
+            normals.map(|iter| iter.collect::<Vec<_>>()),
+            tangents.map(|iter| iter.collect::<Vec<_>>()),
+        ];
+        for (a, values) in attributes.into_iter().enumerate() {
+            for (v, value) in values.into_iter().flatten().enumerate().take(vertex_count) {
+                deltas[(v * count + t) * 3 + a] = glm::vec4(value[0], value[1], value[2], 0.0);
+            }
+        }
+    }
+
+    Some((deltas, count as u32))
 }
-impl Primitive {
-    pub(super) fn from_loader<L>(
+
+/// Sparse accessors are substituted by the reader, this is only shown in the scene panel.
+fn uses_sparse(primitive: &gltf::Primitive) -> bool {
+    primitive
+        .attributes()
+        .map(|(_, accessor)| accessor)
+        .chain(primitive.indices())
+        .chain(primitive.morph_targets().flat_map(|target| {
+            [target.positions(), target.normals(), target.tangents()]
+                .into_iter()
+                .flatten()
+        }))
+        .any(|accessor| accessor.sparse().is_some())
+}
+
+/// Largest error relative to the size of a primitive that simplifying may cause.
+const SIMPLIFY_MAX_ERROR: f32 = 0.05;
+
+/// The vertices and indices of a primitive before they are packed into [`GeometryBuffers`].
+pub(super) struct PrimitiveData {
+    vertices: Vec<PrimitiveVertex>,
+    /// Relative to the first vertex of the primitive, `None` if the vertices are drawn in order.
+    indices: Option<Vec<u32>>,
+    morph: Option<(Vec<glm::Vec4>, u32)>,
+    info: PrimitiveInfo,
+    /// Only triangle lists can be simplified.
+    triangles: bool,
+}
+impl PrimitiveData {
+    pub(super) fn new(
+        mesh: usize,
         primitive: &gltf::Primitive,
         buffers: &[gltf::buffer::Data],
-        loader: &mut Loader<L>,
-    ) -> Option<Self> {
+    ) -> Result<Self, LoadGltfError> {
         let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|d| d.0.as_slice()));
 
         let mut vertex_data = PrimitiveVertexDataBuilder::new(
@@ -182,37 +334,698 @@ impl Primitive {
                 .normal_texture()
                 .map(|nm| nm.tex_coord() as i32)
                 .unwrap_or(-1),
-        )?;
-        vertex_data.set_normals();
+        )
+        .ok_or(LoadGltfError::UnsupportedPrimitive {
+            mesh,
+            primitive: primitive.index(),
+        })?;
+        let vertex_count = vertex_data.vertices.len();
+        // a bad sparse substitution can point past the vertices
+        if let Some(&index) = vertex_data
+            .indices
+            .iter()
+            .find(|&&index| index as usize >= vertex_count)
+        {
+            return Err(LoadGltfError::IndexOutOfRange {
+                mesh,
+                primitive: primitive.index(),
+                index,
+                vertices: vertex_count,
+            });
+        }
         vertex_data.set_textures_sets();
-        vertex_data.set_tangents();
+        vertex_data.set_normals(primitive.mode());
+        let tangents = vertex_data.set_tangents();
 
-        let vbuf = stage(
-            loader.builder,
-            loader.allocator.clone(),
-            BufferUsage::VERTEX_BUFFER,
-            vertex_data.vertices,
-        );
-        let ibuf = stage(
-            loader.builder,
-            loader.allocator.clone(),
-            BufferUsage::INDEX_BUFFER,
-            vertex_data.indices,
+        let morph = read_morph_targets(primitive, buffers, vertex_count).map(|(deltas, count)| {
+            let stride = count as usize * 3;
+            let deltas = match &vertex_data.flat {
+                Some(source) => source
+                    .iter()
+                    .flat_map(|&v| &deltas[v as usize * stride..][..stride])
+                    .copied()
+                    .collect(),
+                None => deltas,
+            };
+            (deltas, count)
+        });
+        let info = PrimitiveInfo {
+            index: primitive.index(),
+            vertices: vertex_data.vertices.len(),
+            indices: vertex_data.indexed.then_some(vertex_data.indices.len()),
+            material: primitive.material().index(),
+            tangents,
+            flat_normals: vertex_data.flat.is_some(),
+            sparse: uses_sparse(primitive),
+            vertex_cache: None,
+        };
+        Ok(Self {
+            vertices: vertex_data.vertices,
+            indices: vertex_data.indexed.then_some(vertex_data.indices),
+            morph,
+            info,
+            triangles: primitive.mode() == gltf::mesh::Mode::Triangles,
+        })
+    }
+    /// Merges equal vertices, then reorders the indices for the vertex cache and the vertices
+    /// in the order they are used. Vertices with morph targets aren't merged.
+    pub(super) fn optimize(&mut self) {
+        if !self.triangles {
+            return;
+        }
+        let indices = self
+            .indices
+            .take()
+            .unwrap_or_else(|| (0..self.vertices.len() as u32).collect());
+        let before = shaded_vertices(&indices, self.vertices.len());
+
+        let (indices, vertex_count) = match self.morph {
+            Some(_) => (indices, self.vertices.len()),
+            None => {
+                let (count, remap) = unique_vertices(&self.vertices);
+                self.vertices = remap_items(&self.vertices, 1, &remap, count);
+                (
+                    meshopt::remap_index_buffer(Some(&indices), count, &remap),
+                    count,
+                )
+            }
+        };
+        let indices = meshopt::optimize_vertex_cache(&indices, vertex_count);
+        let remap = meshopt::optimize_vertex_fetch_remap(&indices, vertex_count);
+        let used = remap.iter().filter(|&&to| to != u32::MAX).count();
+        let indices = meshopt::remap_index_buffer(Some(&indices), vertex_count, &remap);
+        self.vertices = remap_items(&self.vertices, 1, &remap, used);
+        if let Some((deltas, count)) = &mut self.morph {
+            *deltas = remap_items(deltas, *count as usize * 3, &remap, used);
+        }
+
+        self.info.vertices = used;
+        self.info.indices = Some(indices.len());
+        self.info.vertex_cache = Some(VertexCacheStats {
+            triangles: indices.len() / 3,
+            before,
+            after: shaded_vertices(&indices, used),
+        });
+        self.indices = Some(indices);
+    }
+    /// A copy with about `ratio` of the triangles, returns it with the relative error.
+    /// The vertices are kept in place so the morph sets of the original still fit.
+    pub(super) fn simplify(&self, ratio: f32) -> (Self, f32) {
+        if !self.triangles {
+            let data = Self {
+                vertices: self.vertices.clone(),
+                indices: self.indices.clone(),
+                morph: None,
+                ..*self
+            };
+            return (data, 0.0);
+        }
+        let indices = match &self.indices {
+            Some(indices) => indices.clone(),
+            None => (0..self.vertices.len() as u32).collect(),
+        };
+        let positions: Vec<[f32; 3]> = self
+            .vertices
+            .iter()
+            .map(|vertex| vertex.position.into())
+            .collect();
+        let adapter = meshopt::VertexDataAdapter::new(
+            bytemuck::cast_slice(&positions),
+            std::mem::size_of::<[f32; 3]>(),
+            0,
+        )
+        .unwrap();
+        let target = (indices.len() as f32 * ratio) as usize / 3 * 3;
+        let mut error = 0.0;
+        let indices = meshopt::simplify(
+            &indices,
+            &adapter,
+            target,
+            SIMPLIFY_MAX_ERROR,
+            meshopt::SimplifyOptions::empty(),
+            Some(&mut error),
         );
+        let info = PrimitiveInfo {
+            indices: Some(indices.len()),
+            ..self.info
+        };
+        let data = Self {
+            vertices: self.vertices.clone(),
+            indices: Some(indices),
+            morph: None,
+            info,
+            triangles: true,
+        };
+        (data, error)
+    }
+    pub(super) fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+    pub(super) fn write(&self, writer: &mut Writer) {
+        let vertices: Vec<[f32; 14]> = self
+            .vertices
+            .iter()
+            .map(|vertex| {
+                let mut floats = [0.0; 14];
+                for (float, value) in floats.iter_mut().zip(
+                    vertex
+                        .position
+                        .iter()
+                        .chain(&vertex.normal)
+                        .chain(&vertex.tangent)
+                        .chain(&vertex.uv_0)
+                        .chain(&vertex.uv_1),
+                ) {
+                    *float = *value;
+                }
+                floats
+            })
+            .collect();
+        writer.pod(&vertices);
+        writer.u32(self.indices.is_some() as u32);
+        writer.pod(self.indices.as_deref().unwrap_or_default());
+        let (deltas, count) = self
+            .morph
+            .as_ref()
+            .map_or((&[][..], 0), |(deltas, count)| (deltas.as_slice(), *count));
+        let deltas: Vec<[f32; 4]> = deltas.iter().map(|delta| (*delta).into()).collect();
+        writer.pod(&deltas);
+        writer.u32(count);
 
+        let info = &self.info;
+        writer.u64(info.index as u64);
+        writer.u64(info.vertices as u64);
+        writer.option(info.indices);
+        writer.option(info.material);
+        writer.u32(info.tangents as u32);
+        writer.u32(info.flat_normals as u32);
+        writer.u32(info.sparse as u32);
+        let stats = info
+            .vertex_cache
+            .map(|stats| [stats.triangles, stats.before, stats.after]);
+        writer.u32(stats.is_some() as u32);
+        for value in stats.unwrap_or_default() {
+            writer.u64(value as u64);
+        }
+        writer.u32(self.triangles as u32);
+    }
+    pub(super) fn read(reader: &mut Reader) -> Option<Self> {
+        let vertices = reader
+            .pod::<[f32; 14]>()?
+            .into_iter()
+            .map(|f| PrimitiveVertex {
+                position: glm::vec3(f[0], f[1], f[2]),
+                normal: glm::vec3(f[3], f[4], f[5]),
+                tangent: glm::vec4(f[6], f[7], f[8], f[9]),
+                uv_0: glm::vec2(f[10], f[11]),
+                uv_1: glm::vec2(f[12], f[13]),
+            })
+            .collect();
+        let indexed = reader.u32()? != 0;
+        let indices = Some(reader.pod()?).filter(|_| indexed);
+        let deltas: Vec<[f32; 4]> = reader.pod()?;
+        let count = reader.u32()?;
+        let morph = (count > 0).then(|| (deltas.into_iter().map(glm::Vec4::from).collect(), count));
+
+        let index = reader.usize()?;
+        let vertex_count = reader.usize()?;
+        let index_count = reader.option()?;
+        let material = reader.option()?;
+        let tangents = match reader.u32()? {
+            0 => TangentSource::Provided,
+            1 => TangentSource::Generated,
+            _ => TangentSource::None,
+        };
+        let flat_normals = reader.u32()? != 0;
+        let sparse = reader.u32()? != 0;
+        let has_stats = reader.u32()? != 0;
+        let stats = [reader.usize()?, reader.usize()?, reader.usize()?];
+        let vertex_cache = has_stats.then_some(VertexCacheStats {
+            triangles: stats[0],
+            before: stats[1],
+            after: stats[2],
+        });
         Some(Self {
-            ilen: ibuf.len() as u32,
-            vbuf,
-            ibuf,
+            vertices,
+            indices,
+            morph,
+            info: PrimitiveInfo {
+                index,
+                vertices: vertex_count,
+                indices: index_count,
+                material,
+                tangents,
+                flat_normals,
+                sparse,
+                vertex_cache,
+            },
+            triangles: reader.u32()? != 0,
+        })
+    }
+    pub(super) fn index_count(&self) -> usize {
+        self.indices.as_ref().map_or(0, Vec::len)
+    }
+}
+
+/// The vertices of a primitive as they are uploaded, for measurements on the CPU.
+pub struct PrimitiveGeometry {
+    /// Index of the primitive in its glTF mesh.
+    pub index: usize,
+    pub vertices: Vec<PrimitiveVertex>,
+    /// `None` if the vertices are drawn in order.
+    pub indices: Option<Vec<u32>>,
+    /// Only triangle lists are drawn.
+    pub triangles: bool,
+}
+impl From<PrimitiveData> for PrimitiveGeometry {
+    fn from(data: PrimitiveData) -> Self {
+        Self {
+            index: data.info.index,
+            vertices: data.vertices,
+            indices: data.indices,
+            triangles: data.triangles,
+        }
+    }
+}
+impl PrimitiveGeometry {
+    /// The vertex indices of every triangle, only meaningful for [`triangles`](Self::triangles).
+    pub fn corners(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        let indices: Box<dyn Iterator<Item = u32> + '_> = match &self.indices {
+            Some(indices) => Box::new(indices.iter().copied()),
+            None => Box::new(0..self.vertices.len() as u32),
+        };
+        let mut indices = indices.peekable();
+        std::iter::from_fn(move || {
+            indices.peek()?;
+            Some([indices.next()?, indices.next()?, indices.next()?])
         })
     }
-    pub fn render<L>(self, instances: u32, builder: &mut AutoCommandBufferBuilder<L>) {
+}
+
+fn shaded_vertices(indices: &[u32], vertex_count: usize) -> usize {
+    meshopt::analyze_vertex_cache(indices, vertex_count, VERTEX_CACHE_SIZE, 0, 0)
+        .vertices_transformed as usize
+}
+
+/// The index of the first equal vertex among the unique ones for every vertex,
+/// with the number of unique vertices.
+fn unique_vertices(vertices: &[PrimitiveVertex]) -> (usize, Vec<u32>) {
+    let mut unique = HashMap::new();
+    let remap = vertices
+        .iter()
+        .map(|vertex| {
+            let key: Vec<u32> = vertex
+                .position
+                .iter()
+                .chain(&vertex.normal)
+                .chain(&vertex.tangent)
+                .chain(&vertex.uv_0)
+                .chain(&vertex.uv_1)
+                .map(|value| value.to_bits())
+                .collect();
+            let next = unique.len() as u32;
+            *unique.entry(key).or_insert(next)
+        })
+        .collect();
+    (unique.len(), remap)
+}
+
+/// Moves every run of `stride` items to the place `remap` gives it, unused ones are dropped.
+fn remap_items<T: Copy + Default>(
+    items: &[T],
+    stride: usize,
+    remap: &[u32],
+    count: usize,
+) -> Vec<T> {
+    let mut remapped = vec![T::default(); count * stride];
+    for (from, &to) in remap.iter().enumerate() {
+        if to != u32::MAX {
+            remapped[to as usize * stride..][..stride]
+                .copy_from_slice(&items[from * stride..][..stride]);
+        }
+    }
+    remapped
+}
+
+/// One vertex and one index buffer shared by all primitives of a model.
+#[derive(Clone, Debug)]
+pub struct GeometryBuffers {
+    vertices: Subbuffer<[PrimitiveVertex]>,
+    /// `None` if no primitive is indexed.
+    indices: Option<IndexBuffer>,
+}
+impl GeometryBuffers {
+    pub fn bind<L>(&self, builder: &mut AutoCommandBufferBuilder<L>) {
         builder
-            .bind_vertex_buffers(0, self.vbuf)
-            .unwrap()
-            .bind_index_buffer(self.ibuf)
+            .bind_vertex_buffers(0, self.vertices.clone())
             .unwrap();
-        unsafe { builder.draw_indexed(self.ilen, instances, 0, 0, 0) }.unwrap();
+        if let Some(indices) = &self.indices {
+            builder.bind_index_buffer(indices.clone()).unwrap();
+        }
+    }
+}
+
+fn slice_indices(indices: &IndexBuffer, range: std::ops::Range<u64>) -> IndexBuffer {
+    match indices {
+        IndexBuffer::U8(buffer) => IndexBuffer::U8(buffer.clone().slice(range)),
+        IndexBuffer::U16(buffer) => IndexBuffer::U16(buffer.clone().slice(range)),
+        IndexBuffer::U32(buffer) => IndexBuffer::U32(buffer.clone().slice(range)),
+    }
+}
+
+/// Packs the primitives of every mesh into shared [`GeometryBuffers`].
+pub(super) fn upload_primitives<L>(
+    meshes: Vec<Vec<PrimitiveData>>,
+    loader: &mut Loader<L>,
+) -> Vec<Vec<Primitive>> {
+    if meshes.iter().all(Vec::is_empty) {
+        return meshes.into_iter().map(|_| vec![]).collect();
+    }
+
+    // the ray tracer builds acceleration structures from the same buffers
+    let rt_usage = if loader
+        .device
+        .enabled_extensions()
+        .khr_acceleration_structure
+    {
+        BufferUsage::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY
+            | BufferUsage::SHADER_DEVICE_ADDRESS
+    } else {
+        BufferUsage::empty()
+    };
+
+    // indices are relative to the primitive so 16 bits are enough if every primitive is small,
+    // ranges start at even indices for the ray tracers which read them as 32 bit words
+    let short = meshes
+        .iter()
+        .flatten()
+        .all(|data| data.vertices.len() <= u16::MAX as usize + 1);
+    let mut ranges = vec![];
+    let mut indices: Vec<u32> = vec![];
+    for data in meshes.iter().flatten() {
+        let start = indices.len() as u64;
+        indices.extend(data.indices.iter().flatten());
+        ranges.push(start..indices.len() as u64);
+        if short && indices.len() % 2 == 1 {
+            indices.push(0);
+        }
+    }
+    let index_usage = BufferUsage::INDEX_BUFFER | rt_usage;
+    let geometry = GeometryBuffers {
+        vertices: stage(
+            loader.builder,
+            loader.allocator.clone(),
+            BufferUsage::VERTEX_BUFFER | rt_usage,
+            meshes
+                .iter()
+                .flatten()
+                .flat_map(|data| data.vertices.iter().copied())
+                .collect(),
+        ),
+        indices: match indices.is_empty() {
+            true => None,
+            false if short => Some(IndexBuffer::U16(stage(
+                loader.builder,
+                loader.allocator.clone(),
+                index_usage,
+                indices.into_iter().map(|i| i as u16).collect(),
+            ))),
+            false => Some(IndexBuffer::U32(stage(
+                loader.builder,
+                loader.allocator.clone(),
+                index_usage,
+                indices,
+            ))),
+        },
+    };
+
+    let mut ranges = ranges.into_iter();
+    let mut vertex_offset = 0;
+    meshes
+        .into_iter()
+        .map(|primitives| {
+            primitives
+                .into_iter()
+                .map(|data| {
+                    let vertex_count = data.vertices.len() as u64;
+                    let range = ranges.next().unwrap();
+                    let morph = data.morph.map(|(deltas, count)| MorphTargets {
+                        deltas: stage(
+                            loader.builder,
+                            loader.allocator.clone(),
+                            BufferUsage::STORAGE_BUFFER,
+                            morph_deltas(vertex_offset as u32, deltas),
+                        ),
+                        count,
+                    });
+                    let primitive = Primitive {
+                        vbuf: geometry
+                            .vertices
+                            .clone()
+                            .slice(vertex_offset..vertex_offset + vertex_count),
+                        ibuf: data.indices.as_ref().map(|_| {
+                            slice_indices(geometry.indices.as_ref().unwrap(), range.clone())
+                        }),
+                        geometry: geometry.clone(),
+                        vertex_offset: vertex_offset as i32,
+                        first_index: range.start as u32,
+                        count: match data.indices {
+                            Some(_) => range.end - range.start,
+                            None => vertex_count,
+                        } as u32,
+                        morph,
+                        info: data.info,
+                    };
+                    vertex_offset += vertex_count;
+                    primitive
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// End point of a debug line, the far end is moved along `offset` by the line length.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, BufferContents, Vertex)]
+pub struct LineVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub position: glm::Vec3,
+    #[format(R32G32B32_SFLOAT)]
+    pub offset: glm::Vec3,
+}
+
+/// Normal and tangent lines of every vertex of a mesh.
+#[derive(Clone, Debug)]
+pub struct VertexLines {
+    /// The normal lines followed by the same number of tangent lines.
+    vertices: Subbuffer<[LineVertex]>,
+}
+impl VertexLines {
+    pub fn normals(&self) -> Subbuffer<[LineVertex]> {
+        let half = self.vertices.len() / 2;
+        self.vertices.clone().slice(..half)
+    }
+    pub fn tangents(&self) -> Subbuffer<[LineVertex]> {
+        let half = self.vertices.len() / 2;
+        self.vertices.clone().slice(half..)
+    }
+}
+
+/// Generates the [`VertexLines`] of every mesh into one buffer, `None` for meshes without vertices.
+pub(super) fn upload_vertex_lines<L>(
+    meshes: &[Vec<PrimitiveData>],
+    loader: &mut Loader<L>,
+) -> Vec<Option<VertexLines>> {
+    let line = |position: glm::Vec3, direction: glm::Vec3| {
+        [
+            LineVertex {
+                position,
+                offset: glm::Vec3::zeros(),
+            },
+            LineVertex {
+                position,
+                offset: direction,
+            },
+        ]
+    };
+    let mut lines = vec![];
+    let mut ranges = vec![];
+    for primitives in meshes {
+        let start = lines.len() as u64;
+        let vertices = || primitives.iter().flat_map(|data| &data.vertices);
+        lines.extend(vertices().flat_map(|vertex| line(vertex.position, vertex.normal)));
+        lines.extend(vertices().flat_map(|vertex| line(vertex.position, vertex.tangent.xyz())));
+        ranges.push(start..lines.len() as u64);
+    }
+    if lines.is_empty() {
+        return ranges.into_iter().map(|_| None).collect();
+    }
+
+    let buffer = stage(
+        loader.builder,
+        loader.allocator.clone(),
+        BufferUsage::VERTEX_BUFFER,
+        lines,
+    );
+    ranges
+        .into_iter()
+        .map(|range| {
+            (!range.is_empty()).then(|| VertexLines {
+                vertices: buffer.clone().slice(range),
+            })
+        })
+        .collect()
+}
+
+/// Point primitives are drawn with their own pipeline instead of as [`Primitive`]s.
+pub fn is_points(primitive: &gltf::Primitive) -> bool {
+    primitive.mode() == gltf::mesh::Mode::Points
+}
+
+/// A point with the vertex color multiplied by the base color of its material.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, BufferContents, Vertex)]
+pub struct PointVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub position: glm::Vec3,
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: glm::Vec4,
+}
+
+/// Reads the points of a point primitive, `None` if it has no positions.
+pub(super) fn read_points(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+) -> Option<Vec<PointVertex>> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|d| d.0.as_slice()));
+    let factor = glm::Vec4::from(
+        primitive
+            .material()
+            .pbr_metallic_roughness()
+            .base_color_factor(),
+    );
+    let mut points: Vec<_> = reader
+        .read_positions()?
+        .map(|position| PointVertex {
+            position: position.into(),
+            color: factor,
+        })
+        .collect();
+    if let Some(colors) = reader.read_colors(0) {
+        for (point, color) in points.iter_mut().zip(colors.into_rgba_f32()) {
+            point.color = factor.component_mul(&glm::Vec4::from(color));
+        }
+    }
+    Some(points)
+}
+
+/// All point primitives of a mesh.
+#[derive(Clone, Debug)]
+pub struct PointCloud {
+    vertices: Subbuffer<[PointVertex]>,
+    /// Bounds in mesh space.
+    bounds: Aabb,
+}
+impl PointCloud {
+    pub fn vertices(&self) -> Subbuffer<[PointVertex]> {
+        self.vertices.clone()
+    }
+    pub fn count(&self) -> u32 {
+        self.vertices.len() as u32
+    }
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+/// Packs the points of every mesh into one buffer, `None` for meshes without points.
+pub(super) fn upload_points<L>(
+    meshes: Vec<Vec<PointVertex>>,
+    loader: &mut Loader<L>,
+) -> Vec<Option<PointCloud>> {
+    let mut ranges = vec![];
+    let mut bounds = vec![];
+    let mut start = 0;
+    for points in &meshes {
+        let mut aabb = Aabb::empty();
+        for point in points {
+            aabb.extend(&point.position);
+        }
+        bounds.push(aabb);
+        ranges.push(start..start + points.len() as u64);
+        start += points.len() as u64;
+    }
+    if start == 0 {
+        return ranges.into_iter().map(|_| None).collect();
+    }
+
+    let buffer = stage(
+        loader.builder,
+        loader.allocator.clone(),
+        BufferUsage::VERTEX_BUFFER,
+        meshes.into_iter().flatten().collect(),
+    );
+    ranges
+        .into_iter()
+        .zip(bounds)
+        .map(|(range, bounds)| {
+            (!range.is_empty()).then(|| PointCloud {
+                vertices: buffer.clone().slice(range),
+                bounds,
+            })
+        })
+        .collect()
+}
+
+/// The shader finds the deltas of a vertex relative to the base vertex in the first element.
+fn morph_deltas(base_vertex: u32, deltas: Vec<glm::Vec4>) -> Vec<glm::Vec4> {
+    std::iter::once(glm::vec4(f32::from_bits(base_vertex), 0.0, 0.0, 0.0))
+        .chain(deltas)
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct Primitive {
+    geometry: GeometryBuffers,
+    /// The ranges of `geometry` used by this primitive.
+    vbuf: Subbuffer<[PrimitiveVertex]>,
+    /// `None` if the vertices are drawn in order.
+    ibuf: Option<IndexBuffer>,
+    vertex_offset: i32,
+    first_index: u32,
+    /// Number of indices, or vertices if the primitive isn't indexed.
+    count: u32,
+    pub morph: Option<MorphTargets>,
+    pub info: PrimitiveInfo,
+}
+impl Primitive {
+    pub fn vertices(&self) -> &Subbuffer<[PrimitiveVertex]> {
+        &self.vbuf
+    }
+    pub fn indices(&self) -> Option<&IndexBuffer> {
+        self.ibuf.as_ref()
+    }
+    pub fn geometry(&self) -> &GeometryBuffers {
+        &self.geometry
+    }
+    pub fn triangles(&self) -> u32 {
+        self.count / 3
+    }
+    /// The [geometry](Self::geometry) has to be bound already.
+    pub fn draw<L>(&self, instances: u32, builder: &mut AutoCommandBufferBuilder<L>) {
+        match self.ibuf {
+            Some(_) => unsafe {
+                builder.draw_indexed(
+                    self.count,
+                    instances,
+                    self.first_index,
+                    self.vertex_offset,
+                    0,
+                )
+            },
+            None => unsafe { builder.draw(self.count, instances, self.vertex_offset as u32, 0) },
+        }
+        .unwrap();
     }
 }
 
@@ -253,3 +1066,170 @@ fn stage<L, T: BufferContents>(
 
     buf
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use serde_json::json;
+
+    const FLOAT: u32 = 5126;
+    const UNSIGNED_SHORT: u32 = 5123;
+
+    /// One buffer with a view of each added slice.
+    #[derive(Default)]
+    struct Fixture {
+        bytes: Vec<u8>,
+        views: Vec<serde_json::Value>,
+    }
+    impl Fixture {
+        fn view<T: bytemuck::NoUninit>(&mut self, data: &[T]) -> usize {
+            let data: &[u8] = bytemuck::cast_slice(data);
+            let offset = self.bytes.len();
+            self.bytes.extend_from_slice(data);
+            // accessors are aligned to their component size
+            self.bytes.resize(self.bytes.len().next_multiple_of(4), 0);
+            self.views.push(json!({
+                "buffer": 0,
+                "byteOffset": offset,
+                "byteLength": data.len(),
+            }));
+            self.views.len() - 1
+        }
+    }
+
+    /// The strip of the SimpleSparseAccessor sample: 14 vertices in two rows where the
+    /// positions 8, 10 and 12 are moved up by a sparse accessor. Normal 8 is flipped by
+    /// another one and the first index is replaced with `first_index`.
+    fn sparse_strip(first_index: u16) -> (gltf::Document, Vec<gltf::buffer::Data>) {
+        let mut fixture = Fixture::default();
+        let positions: Vec<[f32; 3]> = (0..2)
+            .flat_map(|y| (0..7).map(move |x| [x as f32, y as f32, 0.0]))
+            .collect();
+        let indices: Vec<u16> = (0..6)
+            .flat_map(|i| [i, i + 1, i + 8, i, i + 8, i + 7])
+            .collect();
+
+        let positions = fixture.view(&positions);
+        let normals = fixture.view(&[[0.0f32, 0.0, 1.0]; 14]);
+        let indices = fixture.view(&indices);
+        let position_indices = fixture.view(&[8u16, 10, 12]);
+        let position_values = fixture.view(&[[1.0f32, 2.0, 0.0], [3.0, 3.0, 0.0], [5.0, 4.0, 0.0]]);
+        let normal_indices = fixture.view(&[8u16]);
+        let normal_values = fixture.view(&[[0.0f32, 0.0, -1.0]]);
+        let index_indices = fixture.view(&[0u16]);
+        let index_values = fixture.view(&[first_index]);
+
+        let json = json!({
+            "asset": { "version": "2.0" },
+            "buffers": [{
+                "byteLength": fixture.bytes.len(),
+                "uri": format!(
+                    "data:application/octet-stream;base64,{}",
+                    base64::engine::general_purpose::STANDARD.encode(&fixture.bytes)
+                ),
+            }],
+            "bufferViews": fixture.views,
+            "accessors": [
+                {
+                    "bufferView": positions,
+                    "componentType": FLOAT,
+                    "count": 14,
+                    "type": "VEC3",
+                    "min": [0.0, 0.0, 0.0],
+                    "max": [6.0, 4.0, 0.0],
+                    "sparse": {
+                        "count": 3,
+                        "indices": {
+                            "bufferView": position_indices,
+                            "componentType": UNSIGNED_SHORT,
+                        },
+                        "values": { "bufferView": position_values },
+                    },
+                },
+                {
+                    "bufferView": normals,
+                    "componentType": FLOAT,
+                    "count": 14,
+                    "type": "VEC3",
+                    "sparse": {
+                        "count": 1,
+                        "indices": {
+                            "bufferView": normal_indices,
+                            "componentType": UNSIGNED_SHORT,
+                        },
+                        "values": { "bufferView": normal_values },
+                    },
+                },
+                {
+                    "bufferView": indices,
+                    "componentType": UNSIGNED_SHORT,
+                    "count": 36,
+                    "type": "SCALAR",
+                    "sparse": {
+                        "count": 1,
+                        "indices": {
+                            "bufferView": index_indices,
+                            "componentType": UNSIGNED_SHORT,
+                        },
+                        "values": { "bufferView": index_values },
+                    },
+                },
+            ],
+            "meshes": [{
+                "primitives": [{
+                    "attributes": { "POSITION": 0, "NORMAL": 1 },
+                    "indices": 2,
+                }],
+            }],
+        });
+        let gltf = gltf::Gltf::from_slice(json.to_string().as_bytes()).unwrap();
+        (gltf.document, vec![gltf::buffer::Data(fixture.bytes)])
+    }
+
+    fn read(first_index: u16) -> Result<PrimitiveData, LoadGltfError> {
+        let (document, buffers) = sparse_strip(first_index);
+        let primitive = document
+            .meshes()
+            .next()
+            .unwrap()
+            .primitives()
+            .next()
+            .unwrap();
+        PrimitiveData::new(0, &primitive, &buffers)
+    }
+
+    #[test]
+    fn substitutes_sparse_values() {
+        let data = read(3).unwrap();
+        let positions: Vec<_> = data.vertices.iter().map(|v| v.position).collect();
+        assert_eq!(positions.len(), 14);
+        assert_eq!(positions[7], glm::vec3(0.0, 1.0, 0.0));
+        assert_eq!(positions[8], glm::vec3(1.0, 2.0, 0.0));
+        assert_eq!(positions[9], glm::vec3(2.0, 1.0, 0.0));
+        assert_eq!(positions[10], glm::vec3(3.0, 3.0, 0.0));
+        assert_eq!(positions[12], glm::vec3(5.0, 4.0, 0.0));
+
+        assert_eq!(data.vertices[8].normal, glm::vec3(0.0, 0.0, -1.0));
+        assert_eq!(data.vertices[9].normal, glm::vec3(0.0, 0.0, 1.0));
+
+        let indices = data.indices.as_deref().unwrap();
+        assert_eq!(indices.len(), 36);
+        assert_eq!(&indices[..6], &[3, 1, 8, 0, 8, 7]);
+        assert!(data.info.sparse);
+        assert!(!data.info.flat_normals);
+    }
+
+    #[test]
+    fn sparse_index_out_of_range() {
+        assert!(matches!(
+            read(14),
+            Err(LoadGltfError::IndexOutOfRange {
+                mesh: 0,
+                primitive: 0,
+                index: 14,
+                vertices: 14,
+            })
+        ));
+    }
+}
@@ -0,0 +1,224 @@
+use super::{
+    LoadGltfError,
+    image::{decode_error, image_data},
+};
+use crate::progress::ProgressSender;
+use base64::Engine;
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    io::Read,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// Downloads are read in pieces of this size to report their progress.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// The most memory reserved up front, the `Content-Length` comes from the server.
+const MAX_RESERVE: usize = 64 << 20;
+/// Larger downloads fail instead of filling the memory.
+const MAX_DOWNLOAD: usize = 1 << 30;
+
+pub fn is_remote(uri: &str) -> bool {
+    uri.starts_with("http://") || uri.starts_with("https://")
+}
+
+/// The host of an http(s) URI.
+fn host(uri: &str) -> &str {
+    let rest = uri.split_once("://").map_or(uri, |(_, rest)| rest);
+    rest.split(['/', '?', '#']).next().unwrap_or(rest)
+}
+
+/// The hosts http(s) buffers and images may be downloaded from.
+/// Clones share the same hosts, so one the user allowed stays allowed for every load.
+#[derive(Clone, Default)]
+pub struct RemoteAccess {
+    allowed: Arc<Mutex<BTreeSet<String>>>,
+}
+impl RemoteAccess {
+    pub fn allow(&self, hosts: impl IntoIterator<Item = String>) {
+        self.allowed.lock().unwrap().extend(hosts);
+    }
+    /// Fails with the hosts that weren't allowed yet so the user can be asked.
+    pub fn check(&self, document: &gltf::Document, path: &Path) -> Result<(), LoadGltfError> {
+        let allowed = self.allowed.lock().unwrap();
+        let hosts: BTreeSet<String> = remote_uris(document)
+            .map(|uri| host(uri).to_owned())
+            .filter(|host| !allowed.contains(host))
+            .collect();
+        if hosts.is_empty() {
+            Ok(())
+        } else {
+            Err(LoadGltfError::RemoteNotAllowed {
+                path: path.to_owned(),
+                hosts: hosts.into_iter().collect(),
+            })
+        }
+    }
+    fn is_allowed(&self, uri: &str) -> bool {
+        self.allowed.lock().unwrap().contains(host(uri))
+    }
+}
+
+/// The http(s) URIs of the buffers and images of `document`.
+fn remote_uris<'a>(document: &'a gltf::Document) -> impl Iterator<Item = &'a str> {
+    let buffers = document
+        .buffers()
+        .filter_map(|buffer| match buffer.source() {
+            gltf::buffer::Source::Uri(uri) => Some(uri),
+            gltf::buffer::Source::Bin => None,
+        });
+    let images = document.images().filter_map(|image| match image.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri),
+        gltf::image::Source::View { .. } => None,
+    });
+    buffers.chain(images).filter(|uri| is_remote(uri))
+}
+
+/// Reads the buffers and images of a model from embedded data, files next to it or,
+/// if [`RemoteAccess`] allows it, downloads.
+/// Data URIs are decoded in memory, so they work wherever the model is opened from.
+pub struct UriResolver<'a> {
+    /// The directory of the model.
+    pub base: Option<&'a Path>,
+    pub remote: &'a RemoteAccess,
+    /// Shows the downloads, which are the slowest part of loading.
+    pub progress: Option<&'a ProgressSender>,
+}
+impl UriResolver<'_> {
+    pub fn read(&self, uri: &str) -> Result<Vec<u8>, LoadGltfError> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let (_, data) = data
+                .split_once(";base64,")
+                .ok_or_else(|| LoadGltfError::DataUri(truncate(uri)))?;
+            return base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .map_err(|_| LoadGltfError::DataUri(truncate(uri)));
+        }
+        if is_remote(uri) {
+            return self.download(uri);
+        }
+        let decoded = urlencoding::decode(uri).unwrap_or(Cow::Borrowed(uri));
+        let path = self.base.unwrap_or(Path::new("")).join(decoded.as_ref());
+        std::fs::read(path).map_err(|err| gltf::Error::Io(err).into())
+    }
+    fn download(&self, uri: &str) -> Result<Vec<u8>, LoadGltfError> {
+        if !self.remote.is_allowed(uri) {
+            return Err(LoadGltfError::RemoteNotAllowed {
+                path: uri.into(),
+                hosts: vec![host(uri).to_owned()],
+            });
+        }
+        let error = |message: String| LoadGltfError::Download {
+            uri: uri.to_owned(),
+            message,
+        };
+        let too_large = || format!("larger than {} MiB", MAX_DOWNLOAD >> 20);
+        let response = ureq::get(uri)
+            .call()
+            .map_err(|err| error(err.to_string()))?;
+        let length: Option<usize> = response
+            .header("Content-Length")
+            .and_then(|length| length.parse().ok());
+        if length.is_some_and(|length| length > MAX_DOWNLOAD) {
+            return Err(error(too_large()));
+        }
+        let name = uri.rsplit('/').next().unwrap_or(uri);
+
+        let mut reader = response.into_reader();
+        let mut bytes = Vec::with_capacity(length.unwrap_or(0).min(MAX_RESERVE));
+        let mut chunk = vec![0; CHUNK_SIZE];
+        loop {
+            if self.progress.is_some_and(ProgressSender::cancelled) {
+                return Err(error("cancelled".to_owned()));
+            }
+            let read = reader
+                .read(&mut chunk)
+                .map_err(|err| error(err.to_string()))?;
+            if read == 0 {
+                break;
+            }
+            if bytes.len() + read > MAX_DOWNLOAD {
+                return Err(error(too_large()));
+            }
+            bytes.extend_from_slice(&chunk[..read]);
+            if let Some(progress) = self.progress {
+                let fraction = length.map_or(0.0, |length| bytes.len() as f32 / length as f32);
+                progress.report(
+                    format!("Downloading {name} ({} KiB)", bytes.len() / 1024),
+                    fraction.min(1.0),
+                );
+            }
+        }
+        Ok(bytes)
+    }
+    /// Like [`gltf::import_buffers`] with the URIs read by [`UriResolver::read`].
+    pub fn import_buffers(
+        &self,
+        document: &gltf::Document,
+        mut blob: Option<Vec<u8>>,
+    ) -> Result<Vec<gltf::buffer::Data>, LoadGltfError> {
+        document
+            .buffers()
+            .map(|buffer| {
+                let mut data = match buffer.source() {
+                    gltf::buffer::Source::Uri(uri) => self.read(uri)?,
+                    gltf::buffer::Source::Bin => blob.take().ok_or(gltf::Error::MissingBlob)?,
+                };
+                if data.len() < buffer.length() {
+                    return Err(gltf::Error::BufferLength {
+                        buffer: buffer.index(),
+                        expected: buffer.length(),
+                        actual: data.len(),
+                    }
+                    .into());
+                }
+                // accessors are read in 4 byte words
+                data.resize(data.len().next_multiple_of(4), 0);
+                Ok(gltf::buffer::Data(data))
+            })
+            .collect()
+    }
+    /// Decodes image `index`, downloading it first if it is remote. Besides PNG and JPEG,
+    /// WebP of `EXT_texture_webp` and, with the `avif` feature, AVIF are decoded. 16 bit and
+    /// float images keep their precision.
+    pub fn read_image(
+        &self,
+        document: &gltf::Document,
+        index: usize,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<gltf::image::Data, LoadGltfError> {
+        let image = document.images().nth(index).unwrap();
+        let (encoded, path) = match image.source() {
+            gltf::image::Source::Uri { uri, .. } => {
+                let path = if uri.starts_with("data:") {
+                    format!("image {index}")
+                } else {
+                    uri.to_owned()
+                };
+                (Cow::Owned(self.read(uri)?), path)
+            }
+            gltf::image::Source::View { view, .. } => {
+                let buffer = &buffers[view.buffer().index()];
+                let end = view.offset() + view.length();
+                let bytes = buffer.get(view.offset()..end).ok_or(LoadGltfError::Image {
+                    index,
+                    source: gltf::Error::BufferLength {
+                        buffer: view.buffer().index(),
+                        expected: end,
+                        actual: buffer.len(),
+                    },
+                })?;
+                (Cow::Borrowed(bytes), format!("image {index}"))
+            }
+        };
+        let decoded = ::image::load_from_memory(&encoded)
+            .map_err(|source| decode_error(path.into(), source))?;
+        Ok(image_data(decoded))
+    }
+}
+
+/// Data URIs can be megabytes long, errors only show their start.
+fn truncate(uri: &str) -> String {
+    uri.chars().take(48).collect()
+}
@@ -1,42 +1,120 @@
+use crate::vktf::cache::{SamplerKey, VktfCache};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use vulkano::{
     device::Device,
-    image::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode},
+    image::sampler::{Filter, Sampler, SamplerAddressMode, SamplerMipmapMode},
 };
 
 pub const DEFAULT_MAG: Filter = Filter::Linear;
 pub const DEFAULT_MIN: Filter = Filter::Linear;
 pub const DEFAULT_MIPMAP: SamplerMipmapMode = SamplerMipmapMode::Linear;
 
-pub fn create_vk_sampler(device: Arc<Device>, sampler: &gltf::texture::Sampler) -> Arc<Sampler> {
-    let address_mode = [
-        convert_wrap(sampler.wrap_s()),
-        convert_wrap(sampler.wrap_t()),
-        SamplerAddressMode::ClampToEdge,
+/// Anisotropic filtering of every model texture, limited to what the device supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Anisotropy {
+    Off,
+    X2,
+    X4,
+    X8,
+    #[default]
+    X16,
+}
+impl Anisotropy {
+    pub const ALL: [Anisotropy; 5] = [
+        Anisotropy::Off,
+        Anisotropy::X2,
+        Anisotropy::X4,
+        Anisotropy::X8,
+        Anisotropy::X16,
     ];
-    let mag_filter = sampler
-        .mag_filter()
-        .map(convert_mag_filter)
-        .unwrap_or(DEFAULT_MAG);
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Anisotropy::Off => "Off",
+            Anisotropy::X2 => "2x",
+            Anisotropy::X4 => "4x",
+            Anisotropy::X8 => "8x",
+            Anisotropy::X16 => "16x",
+        }
+    }
+    pub fn samples(&self) -> f32 {
+        match self {
+            Anisotropy::Off => 1.0,
+            Anisotropy::X2 => 2.0,
+            Anisotropy::X4 => 4.0,
+            Anisotropy::X8 => 8.0,
+            Anisotropy::X16 => 16.0,
+        }
+    }
+    /// The sampler setting on `device`, `None` if it is off or not supported.
+    pub fn max_anisotropy(&self, device: &Device) -> Option<f32> {
+        if !device.enabled_features().sampler_anisotropy {
+            return None;
+        }
+        let max = device.physical_device().properties().max_sampler_anisotropy;
+        Some(self.samples().min(max)).filter(|samples| *samples > 1.0)
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Anisotropic filtering")
+            .selected_text(self.name())
+            .show_ui(ui, |ui| {
+                for anisotropy in Self::ALL {
+                    ui.selectable_value(self, anisotropy, anisotropy.name());
+                }
+            })
+            .response
+            .on_hover_text("Sharper textures at grazing angles, lower is faster");
+    }
+}
+
+pub fn create_vk_sampler(
+    device: &Arc<Device>,
+    sampler: &gltf::texture::Sampler,
+    anisotropy: Anisotropy,
+    cache: &VktfCache,
+) -> Arc<Sampler> {
     let (min_filter, mipmap_mode) = sampler
         .min_filter()
         .map(convert_min_filter)
         .unwrap_or((DEFAULT_MIN, DEFAULT_MIPMAP));
-
-    let anisotropy = Some(device.physical_device().properties().max_sampler_anisotropy);
-
-    Sampler::new(
+    cache.sampler(
         device,
-        SamplerCreateInfo {
-            mag_filter,
+        SamplerKey {
+            mag_filter: sampler
+                .mag_filter()
+                .map(convert_mag_filter)
+                .unwrap_or(DEFAULT_MAG),
             min_filter,
             mipmap_mode,
-            address_mode,
+            address_mode: [
+                convert_wrap(sampler.wrap_s()),
+                convert_wrap(sampler.wrap_t()),
+                SamplerAddressMode::ClampToEdge,
+            ],
+            anisotropy,
+        },
+    )
+}
+
+/// Used by textures without a sampler.
+pub fn default_vk_sampler(
+    device: &Arc<Device>,
+    anisotropy: Anisotropy,
+    cache: &VktfCache,
+) -> Arc<Sampler> {
+    let wrap = convert_wrap(gltf::texture::WrappingMode::default());
+    cache.sampler(
+        device,
+        SamplerKey {
+            mag_filter: DEFAULT_MAG,
+            min_filter: DEFAULT_MIN,
+            mipmap_mode: DEFAULT_MIPMAP,
+            address_mode: [wrap, wrap, SamplerAddressMode::ClampToEdge],
             anisotropy,
-            ..SamplerCreateInfo::simple_repeat_linear()
         },
     )
-    .unwrap()
 }
 
 pub fn convert_wrap(wrap: gltf::texture::WrappingMode) -> SamplerAddressMode {
@@ -0,0 +1,55 @@
+use super::image::convert_image;
+
+/// Bakes a KHR_materials_pbrSpecularGlossiness texture and its factors into a
+/// linear metallic-roughness texture (roughness in G, metallic in B).
+pub fn convert_spec_gloss(
+    data: &gltf::image::Data,
+    specular_factor: [f32; 3],
+    glossiness_factor: f32,
+) -> gltf::image::Data {
+    let rgba = convert_image(gltf::image::Data {
+        pixels: data.pixels.clone(),
+        format: data.format,
+        width: data.width,
+        height: data.height,
+    })
+    .to_rgba32f();
+
+    let pixels = rgba
+        .pixels()
+        .flat_map(|pixel| {
+            let specular = [0, 1, 2].map(|i| srgb_to_linear(pixel[i]) * specular_factor[i]);
+            let (roughness, metallic) = roughness_metallic(specular, pixel[3] * glossiness_factor);
+            [255, to_u8(roughness), to_u8(metallic), 255]
+        })
+        .collect();
+
+    gltf::image::Data {
+        pixels,
+        format: gltf::image::Format::R8G8B8A8,
+        width: data.width,
+        height: data.height,
+    }
+}
+
+/// Approximates roughness and metallic from a specular colour and glossiness.
+/// Specular values above the 4% of common dielectrics are treated as metal.
+pub fn roughness_metallic(specular: [f32; 3], glossiness: f32) -> (f32, f32) {
+    const DIELECTRIC_SPECULAR: f32 = 0.04;
+    let max_specular = specular[0].max(specular[1]).max(specular[2]);
+    let metallic =
+        ((max_specular - DIELECTRIC_SPECULAR) / (1.0 - DIELECTRIC_SPECULAR)).clamp(0.0, 1.0);
+    (1.0 - glossiness.clamp(0.0, 1.0), metallic)
+}
+
+pub(super) fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn to_u8(c: f32) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
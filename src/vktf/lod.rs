@@ -0,0 +1,12 @@
+use super::loader::Primitive;
+
+/// Simplified geometry of a model, previewed in place of the loaded one.
+#[derive(Clone)]
+pub struct ModelLod {
+    /// Fraction of the triangles that was aimed for.
+    pub ratio: f32,
+    /// Largest error of the simplified primitives relative to their size.
+    pub error: f32,
+    /// Triangle primitives by glTF mesh index, in their own geometry buffers.
+    pub meshes: Vec<Vec<Primitive>>,
+}
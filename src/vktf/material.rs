@@ -1,18 +1,20 @@
-use super::loader::{Vktf, VktfDocument};
+use super::{
+    cache::{TextureBinding, VktfCache},
+    loader::{TextureKind, Vktf, VktfDocument, roughness_metallic},
+};
 use nalgebra_glm as glm;
 use std::sync::Arc;
 use vulkano::{
     buffer::BufferContents,
-    command_buffer::AutoCommandBufferBuilder,
     descriptor_set::{
         DescriptorSet, WriteDescriptorSet, allocator::DescriptorSetAllocator,
         layout::DescriptorSetLayout,
     },
-    pipeline::{PipelineBindPoint, PipelineLayout},
+    image::view::ImageView,
 };
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy, BufferContents)]
+#[derive(Debug, Clone, Copy, PartialEq, BufferContents)]
 pub struct MaterialPush {
     pub bc: glm::Vec4,
     pub em: glm::Vec3,
@@ -25,6 +27,19 @@ pub struct MaterialPush {
     pub ao_set: i32,
     pub em_set: i32,
     pub nm_set: i32,
+
+    /// volume attenuation colour
+    pub at: glm::Vec3,
+    /// volume attenuation distance, `0.0` means no attenuation
+    pub ad: f32,
+    /// transmission factor
+    pub tr: f32,
+    /// volume thickness factor
+    pub th: f32,
+    pub ior: f32,
+
+    pub tr_set: i32,
+    pub th_set: i32,
 }
 impl MaterialPush {
     pub fn new(material: &gltf::Material) -> Self {
@@ -56,6 +71,42 @@ impl MaterialPush {
         if let Some(nm_set) = material.normal_texture() {
             slf.nm_set = nm_set.tex_coord() as i32;
         }
+        if let Some(transmission) = material.transmission() {
+            slf.tr = transmission.transmission_factor();
+            if let Some(tr_set) = transmission.transmission_texture() {
+                slf.tr_set = tr_set.tex_coord() as i32;
+            }
+        }
+        if let Some(volume) = material.volume() {
+            slf.th = volume.thickness_factor();
+            slf.at = volume.attenuation_color().into();
+            if volume.attenuation_distance().is_finite() {
+                slf.ad = volume.attenuation_distance();
+            }
+            if let Some(th_set) = volume.thickness_texture() {
+                slf.th_set = th_set.tex_coord() as i32;
+            }
+        }
+        if let Some(ior) = material.ior() {
+            slf.ior = ior;
+        }
+        if let Some(sg) = material.pbr_specular_glossiness() {
+            slf.bc = sg.diffuse_factor().into();
+            slf.bc_set = sg.diffuse_texture().map_or(-1, |bc| bc.tex_coord() as i32);
+            // the factors are baked into the converted texture
+            match sg.specular_glossiness_texture() {
+                Some(rm_set) => {
+                    slf.rm = glm::vec2(1.0, 1.0);
+                    slf.rm_set = rm_set.tex_coord() as i32;
+                }
+                None => {
+                    let (roughness, metallic) =
+                        roughness_metallic(sg.specular_factor(), sg.glossiness_factor());
+                    slf.rm = glm::vec2(roughness, metallic);
+                    slf.rm_set = -1;
+                }
+            }
+        }
 
         slf
     }
@@ -73,6 +124,67 @@ impl Default for MaterialPush {
             ao_set: -1,
             em_set: -1,
             nm_set: -1,
+            at: glm::vec3(1.0, 1.0, 1.0),
+            ad: 0.0,
+            tr: 0.0,
+            th: 0.0,
+            ior: 1.5,
+            tr_set: -1,
+            th_set: -1,
+        }
+    }
+}
+
+/// The textures of a material in the order they are bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureSlot {
+    BaseColor,
+    MetallicRoughness,
+    Occlusion,
+    Emissive,
+    Normal,
+    Transmission,
+    Thickness,
+}
+impl TextureSlot {
+    pub const ALL: [TextureSlot; 7] = [
+        TextureSlot::BaseColor,
+        TextureSlot::MetallicRoughness,
+        TextureSlot::Occlusion,
+        TextureSlot::Emissive,
+        TextureSlot::Normal,
+        TextureSlot::Transmission,
+        TextureSlot::Thickness,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextureSlot::BaseColor => "Base colour",
+            TextureSlot::MetallicRoughness => "Metallic roughness",
+            TextureSlot::Occlusion => "Occlusion",
+            TextureSlot::Emissive => "Emissive",
+            TextureSlot::Normal => "Normal",
+            TextureSlot::Transmission => "Transmission",
+            TextureSlot::Thickness => "Thickness",
+        }
+    }
+    pub fn kind(&self) -> TextureKind {
+        match self {
+            TextureSlot::BaseColor | TextureSlot::Emissive => TextureKind::Color,
+            TextureSlot::Normal => TextureKind::Normal,
+            _ => TextureKind::Data,
+        }
+    }
+    /// The texture coordinate set of the slot, `-1` without a texture.
+    fn tex_coord<'a>(&self, push: &'a mut MaterialPush) -> &'a mut i32 {
+        match self {
+            TextureSlot::BaseColor => &mut push.bc_set,
+            TextureSlot::MetallicRoughness => &mut push.rm_set,
+            TextureSlot::Occlusion => &mut push.ao_set,
+            TextureSlot::Emissive => &mut push.em_set,
+            TextureSlot::Normal => &mut push.nm_set,
+            TextureSlot::Transmission => &mut push.tr_set,
+            TextureSlot::Thickness => &mut push.th_set,
         }
     }
 }
@@ -81,6 +193,8 @@ impl Default for MaterialPush {
 pub struct Material {
     pub push: MaterialPush,
     pub set: Arc<DescriptorSet>,
+    /// What `set` was written with, by [`TextureSlot`].
+    bindings: Vec<TextureBinding>,
 }
 impl Material {
     pub fn new(
@@ -88,6 +202,7 @@ impl Material {
         allocator: Arc<dyn DescriptorSetAllocator>,
         layout: Arc<DescriptorSetLayout>,
         vktf: &Vktf,
+        cache: &VktfCache,
     ) -> Self {
         let pbr = material.pbr_metallic_roughness();
         let bc = pbr.base_color_texture().map(|bc| bc.texture());
@@ -95,41 +210,168 @@ impl Material {
         let ao = material.occlusion_texture().map(|ao| ao.texture());
         let em = material.emissive_texture().map(|em| em.texture());
         let nm = material.normal_texture().map(|nm| nm.texture());
+        let tr = material
+            .transmission()
+            .and_then(|tr| tr.transmission_texture())
+            .map(|tr| tr.texture());
+        let th = material
+            .volume()
+            .and_then(|th| th.thickness_texture())
+            .map(|th| th.texture());
+        let sg = material.pbr_specular_glossiness();
+        let bc = match &sg {
+            Some(sg) => sg.diffuse_texture().map(|bc| bc.texture()),
+            None => bc,
+        };
+        let spec_gloss = material.index().and_then(|i| vktf.get_spec_gloss(i));
+        let rm_binding = match (
+            spec_gloss,
+            sg.and_then(|sg| sg.specular_glossiness_texture()),
+        ) {
+            (Some(view), Some(tex)) => (
+                view.clone(),
+                vktf.get_sampler(tex.texture().sampler().index())
+                    .unwrap()
+                    .clone(),
+            ),
+            _ => texture_binding(rm.as_ref(), vktf),
+        };
+        let bindings = vec![
+            texture_binding(bc.as_ref(), vktf),
+            rm_binding,
+            texture_binding(ao.as_ref(), vktf),
+            texture_binding(em.as_ref(), vktf),
+            texture_binding(nm.as_ref(), vktf),
+            texture_binding(tr.as_ref(), vktf),
+            texture_binding(th.as_ref(), vktf),
+        ];
+        let set = cache.material_set(allocator, layout, bindings.clone());
+
+        // textures that haven't been streamed in yet are disabled
+        let loaded = |texture: &Option<gltf::Texture>| {
+            texture
+                .as_ref()
+                .is_some_and(|texture| vktf.is_image_loaded(texture.source().index()))
+        };
+        let mut push = MaterialPush::new(material);
+        if !loaded(&bc) {
+            push.bc_set = -1;
+        }
+        if !loaded(&rm) && spec_gloss.is_none() {
+            push.rm_set = -1;
+        }
+        if !loaded(&ao) {
+            push.ao_set = -1;
+        }
+        if !loaded(&em) {
+            push.em_set = -1;
+        }
+        if !loaded(&nm) {
+            push.nm_set = -1;
+        }
+        if !loaded(&tr) {
+            push.tr_set = -1;
+        }
+        if !loaded(&th) {
+            push.th_set = -1;
+        }
+
+        Self {
+            push,
+            set,
+            bindings,
+        }
+    }
+    /// A material that isn't part of a document, `bindings` has one texture per [`TextureSlot`].
+    pub fn from_bindings(
+        push: MaterialPush,
+        bindings: Vec<TextureBinding>,
+        allocator: Arc<dyn DescriptorSetAllocator>,
+        layout: Arc<DescriptorSetLayout>,
+    ) -> Self {
         let set = DescriptorSet::new(
             allocator,
             layout,
-            [
-                write_descriptor_set(0, bc.as_ref(), vktf),
-                write_descriptor_set(1, rm.as_ref(), vktf),
-                write_descriptor_set(2, ao.as_ref(), vktf),
-                write_descriptor_set(3, em.as_ref(), vktf),
-                write_descriptor_set(4, nm.as_ref(), vktf),
-            ],
+            bindings
+                .iter()
+                .enumerate()
+                .map(|(binding, (view, sampler))| {
+                    WriteDescriptorSet::image_view_sampler(
+                        binding as u32,
+                        view.clone(),
+                        sampler.clone(),
+                    )
+                }),
             [],
         )
         .unwrap();
         Self {
-            push: MaterialPush::new(material),
+            push,
             set,
+            bindings,
         }
     }
-
-    pub fn set<L>(self, builder: &mut AutoCommandBufferBuilder<L>, layout: Arc<PipelineLayout>) {
-        builder
-            .bind_descriptor_sets(PipelineBindPoint::Graphics, layout.clone(), 2, self.set)
-            .unwrap()
-            .push_constants(layout, 0, self.push)
-            .unwrap();
+    /// Takes the textures of a material rebuilt after streaming, keeping the edited factors.
+    pub fn update_textures(&mut self, other: Material) {
+        self.push.bc_set = other.push.bc_set;
+        self.push.rm_set = other.push.rm_set;
+        self.push.ao_set = other.push.ao_set;
+        self.push.em_set = other.push.em_set;
+        self.push.nm_set = other.push.nm_set;
+        self.push.tr_set = other.push.tr_set;
+        self.push.th_set = other.push.th_set;
+        self.set = other.set;
+        self.bindings = other.bindings;
+    }
+    /// Draws `view` in `slot` with the sampler of the texture it replaces,
+    /// from the first texture coordinates if there was no texture.
+    pub fn replace_texture(
+        &mut self,
+        slot: TextureSlot,
+        view: Arc<ImageView>,
+        allocator: Arc<dyn DescriptorSetAllocator>,
+        layout: Arc<DescriptorSetLayout>,
+        cache: &VktfCache,
+    ) {
+        self.bindings[slot as usize].0 = view;
+        self.set = cache.material_set(allocator, layout, self.bindings.clone());
+        let tex_coord = slot.tex_coord(&mut self.push);
+        *tex_coord = (*tex_coord).max(0);
     }
 }
 
-fn write_descriptor_set(
-    binding: u32,
-    texture: Option<&gltf::Texture>,
-    vktf: &Vktf,
-) -> WriteDescriptorSet {
-    WriteDescriptorSet::image_view_sampler(
-        binding,
+/// Whether any texture of `material` is sourced from image `index`.
+pub fn uses_image(material: &gltf::Material, index: usize) -> bool {
+    let pbr = material.pbr_metallic_roughness();
+    let sg = material.pbr_specular_glossiness();
+    [
+        pbr.base_color_texture().map(|tex| tex.texture()),
+        pbr.metallic_roughness_texture().map(|tex| tex.texture()),
+        material.occlusion_texture().map(|tex| tex.texture()),
+        material.emissive_texture().map(|tex| tex.texture()),
+        material.normal_texture().map(|tex| tex.texture()),
+        material
+            .transmission()
+            .and_then(|tr| tr.transmission_texture())
+            .map(|tex| tex.texture()),
+        material
+            .volume()
+            .and_then(|th| th.thickness_texture())
+            .map(|tex| tex.texture()),
+        sg.as_ref()
+            .and_then(|sg| sg.diffuse_texture())
+            .map(|tex| tex.texture()),
+        sg.as_ref()
+            .and_then(|sg| sg.specular_glossiness_texture())
+            .map(|tex| tex.texture()),
+    ]
+    .into_iter()
+    .flatten()
+    .any(|texture| texture.source().index() == index)
+}
+
+fn texture_binding(texture: Option<&gltf::Texture>, vktf: &Vktf) -> TextureBinding {
+    (
         vktf.get_image(texture.map(|t| t.source().index()))
             .unwrap()
             .clone(),
@@ -150,31 +392,28 @@ impl Materials {
         allocator: Arc<dyn DescriptorSetAllocator>,
         layout: Arc<DescriptorSetLayout>,
         vktf: &VktfDocument,
+        cache: &VktfCache,
     ) -> Self {
         let index = vktf
             .document
             .materials()
-            .map(|mat| Material::new(&mat, allocator.clone(), layout.clone(), &vktf.vktf))
+            .map(|mat| Material::new(&mat, allocator.clone(), layout.clone(), &vktf.vktf, cache))
             .collect();
+        let bindings = vec![texture_binding(None, &vktf.vktf); 7];
         let default = Material {
             push: MaterialPush::default(),
-            set: DescriptorSet::new(
-                allocator,
-                layout,
-                [
-                    write_descriptor_set(0, None, &vktf.vktf),
-                    write_descriptor_set(1, None, &vktf.vktf),
-                    write_descriptor_set(2, None, &vktf.vktf),
-                    write_descriptor_set(3, None, &vktf.vktf),
-                    write_descriptor_set(4, None, &vktf.vktf),
-                ],
-                [],
-            )
-            .unwrap(),
+            set: cache.material_set(allocator, layout, bindings.clone()),
+            bindings,
         };
 
         Self { default, index }
     }
+    pub fn has_transmission(&self) -> bool {
+        self.index
+            .iter()
+            .chain([&self.default])
+            .any(|material| material.push.tr > 0.0)
+    }
     pub fn get(&self, index: Option<usize>) -> Option<&Material> {
         match index {
             Some(i) => self.index.get(i),
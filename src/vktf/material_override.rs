@@ -0,0 +1,179 @@
+use super::{
+    debug::DebugView,
+    material::{Material, MaterialPush},
+};
+use crate::{Allocators, skybox::loader::gen_mipmaps};
+use image::{EncodableLayout, RgbaImage};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage},
+    command_buffer::{AutoCommandBufferBuilder, CopyBufferToImageInfo},
+    descriptor_set::layout::DescriptorSetLayout,
+    format::Format,
+    image::{
+        Image, ImageCreateInfo, ImageUsage,
+        sampler::{Sampler, SamplerCreateInfo},
+        view::ImageView,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+};
+
+/// Size of the checker texture in pixels.
+const CHECKER_SIZE: u32 = 512;
+/// Cells along each side of the checker texture, so along one unit of UV.
+const CHECKER_CELLS: u32 = 16;
+
+/// Draws every mesh with the same material instead of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MaterialOverride {
+    #[default]
+    None,
+    /// Neutral grey to judge the shapes under the lighting.
+    Clay,
+    /// Vertex normals as colours.
+    Normals,
+    /// A checkerboard on the first UV set to check texel density and stretching.
+    CheckerUv,
+}
+impl MaterialOverride {
+    pub const ALL: [MaterialOverride; 4] = [
+        MaterialOverride::None,
+        MaterialOverride::Clay,
+        MaterialOverride::Normals,
+        MaterialOverride::CheckerUv,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaterialOverride::None => "None",
+            MaterialOverride::Clay => "Clay",
+            MaterialOverride::Normals => "Normals",
+            MaterialOverride::CheckerUv => "Checker UV",
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Material override")
+            .selected_text(self.name())
+            .show_ui(ui, |ui| {
+                for mode in Self::ALL {
+                    ui.selectable_value(self, mode, mode.name());
+                }
+            });
+    }
+}
+
+/// The materials of the [`MaterialOverride`] modes.
+#[derive(Clone)]
+pub struct OverrideMaterials {
+    clay: Material,
+    checker: Material,
+}
+impl OverrideMaterials {
+    pub fn new<L>(
+        allocators: &Allocators,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        layout: Arc<DescriptorSetLayout>,
+    ) -> Self {
+        let device = allocators.mem.device();
+        let sampler =
+            Sampler::new(device.clone(), SamplerCreateInfo::simple_repeat_linear()).unwrap();
+        let white = RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]));
+        let white = upload(allocators, builder, &white);
+
+        let checker = RgbaImage::from_fn(CHECKER_SIZE, CHECKER_SIZE, |x, y| {
+            let cell = CHECKER_SIZE / CHECKER_CELLS;
+            let (cx, cy) = (x / cell, y / cell);
+            if (cx + cy) % 2 == 0 {
+                image::Rgba([40, 40, 40, 255])
+            } else {
+                // the light cells go red along u and green along v to show the orientation
+                let u = (cx * 255 / (CHECKER_CELLS - 1)) as u8;
+                let v = (cy * 255 / (CHECKER_CELLS - 1)) as u8;
+                image::Rgba([160 + u / 3, 160 + v / 3, 160, 255])
+            }
+        });
+        let checker = upload(allocators, builder, &checker);
+
+        let push = MaterialPush {
+            bc: glm::vec4(0.6, 0.6, 0.6, 1.0),
+            rm: glm::vec2(0.6, 0.0),
+            ..Default::default()
+        };
+        let bindings = vec![(white, sampler.clone()); 7];
+        let clay = Material::from_bindings(
+            push,
+            bindings.clone(),
+            allocators.set.clone(),
+            layout.clone(),
+        );
+
+        let mut checker_bindings = bindings;
+        checker_bindings[0] = (checker, sampler);
+        let checker = Material::from_bindings(
+            MaterialPush {
+                bc: glm::vec4(1.0, 1.0, 1.0, 1.0),
+                bc_set: 0,
+                ..push
+            },
+            checker_bindings,
+            allocators.set.clone(),
+            layout,
+        );
+
+        Self { clay, checker }
+    }
+
+    /// The material that replaces all others and the debug view it's drawn with,
+    /// `None` draws the models as they are.
+    pub fn get(&self, mode: MaterialOverride, view: DebugView) -> Option<(&Material, DebugView)> {
+        match mode {
+            MaterialOverride::None => None,
+            MaterialOverride::Clay => Some((&self.clay, view)),
+            MaterialOverride::Normals => Some((&self.clay, DebugView::Normal)),
+            MaterialOverride::CheckerUv => Some((&self.checker, view)),
+        }
+    }
+}
+
+/// An sRGB texture with a full mip chain.
+fn upload<L>(
+    allocators: &Allocators,
+    builder: &mut AutoCommandBufferBuilder<L>,
+    pixels: &RgbaImage,
+) -> Arc<ImageView> {
+    let stage = Buffer::from_iter(
+        allocators.mem.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        pixels.as_bytes().iter().copied(),
+    )
+    .unwrap();
+    let mips = pixels.width().max(pixels.height()).ilog2() + 1;
+    let image = Image::new(
+        allocators.mem.clone(),
+        ImageCreateInfo {
+            usage: ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+            format: Format::R8G8B8A8_SRGB,
+            extent: [pixels.width(), pixels.height(), 1],
+            mip_levels: mips,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+    builder
+        .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(stage, image.clone()))
+        .unwrap();
+    gen_mipmaps(builder, image.clone(), mips);
+    ImageView::new_default(image).unwrap()
+}
@@ -1,11 +1,21 @@
-use super::{loader::Primitive, material::Materials};
+use super::{
+    bounds::Aabb,
+    loader::{PointCloud, Primitive, VertexLines},
+    morph::Morph,
+};
+use crate::stats::DrawStats;
 use nalgebra_glm as glm;
 use std::sync::Arc;
 use vulkano::{
-    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
-    command_buffer::AutoCommandBufferBuilder,
+    DeviceSize,
+    buffer::{
+        Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer,
+        allocator::SubbufferAllocator,
+    },
+    command_buffer::{AutoCommandBufferBuilder, CopyBufferInfo},
+    descriptor_set::DescriptorSet,
     memory::allocator::{AllocationCreateInfo, MemoryAllocator, MemoryTypeFilter},
-    pipeline::{PipelineLayout, graphics::vertex_input::Vertex},
+    pipeline::{PipelineBindPoint, PipelineLayout, graphics::vertex_input::Vertex},
 };
 
 #[repr(C)]
@@ -34,68 +44,308 @@ impl From<glm::Mat4> for Instance {
 #[derive(Clone)]
 pub struct MaterialPrimitive {
     material: Option<usize>,
+    /// The material of the file, shown when no variant is picked.
+    loaded: Option<usize>,
+    /// `KHR_materials_variants` variant and the material it uses.
+    mappings: Vec<(usize, usize)>,
     primitive: Primitive,
+    /// One morph set per frame.
+    morph_sets: Vec<Arc<DescriptorSet>>,
+}
+impl MaterialPrimitive {
+    fn bind_morph<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        layout: &Arc<PipelineLayout>,
+        set: u32,
+        frame: usize,
+    ) {
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                layout.clone(),
+                set,
+                self.morph_sets[frame].clone(),
+            )
+            .unwrap();
+    }
 }
 
 #[derive(Clone)]
 pub struct Mesh {
+    /// Index of the glTF mesh.
+    pub index: usize,
     primitives: Vec<MaterialPrimitive>,
-    instances: Subbuffer<[Instance]>,
+    /// One instance buffer per frame, `transforms` once for every root.
+    instances: Vec<Subbuffer<[Instance]>>,
+    /// Instance transforms relative to the model root.
+    transforms: Vec<glm::Mat4>,
+    /// The model transform of every replica.
+    roots: Vec<glm::Mat4>,
+    /// Instances that changed since each frame's buffer was last written.
+    pending: Vec<Vec<u32>>,
+    /// Bounds of the triangle primitives and points in mesh space.
+    bounds: Aabb,
+    allocator: Arc<dyn MemoryAllocator>,
     len: u32,
+    pub morph: Option<Morph>,
+    lines: Option<VertexLines>,
+    points: Option<PointCloud>,
 }
 impl Mesh {
     pub fn new<'a>(
+        index: usize,
         allocator: Arc<dyn MemoryAllocator>,
-        primitives: impl Iterator<Item = (gltf::Primitive<'a>, Primitive)>,
+        primitives: impl Iterator<Item = (gltf::Primitive<'a>, Primitive, Vec<Arc<DescriptorSet>>)>,
         instances: Vec<glm::Mat4>,
+        morph: Option<Morph>,
+        lines: Option<VertexLines>,
+        points: Option<PointCloud>,
+        num_frames: usize,
     ) -> Self {
-        let instance_buffer = Buffer::from_iter(
-            allocator.clone(),
-            BufferCreateInfo {
-                usage: BufferUsage::VERTEX_BUFFER,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                ..Default::default()
-            },
-            instances.iter().copied().map(Instance::from),
-        )
-        .unwrap();
+        let roots = vec![glm::identity()];
+        let instance_buffers = (0..num_frames)
+            .map(|_| instance_buffer(allocator.clone(), &instances, &roots))
+            .collect();
+        let mut bounds = points.as_ref().map_or(Aabb::empty(), PointCloud::bounds);
         let primitives = primitives
-            .filter_map(|(gltf, primitive)| {
+            .filter_map(|(gltf, primitive, morph_sets)| {
                 if gltf.mode() != gltf::mesh::Mode::Triangles {
                     None
                 } else {
+                    bounds.union(&Aabb::from(gltf.bounding_box()));
+                    let mappings = gltf
+                        .mappings()
+                        .flat_map(|mapping| {
+                            let material = mapping.material().index();
+                            mapping
+                                .variants()
+                                .iter()
+                                .filter_map(move |variant| Some((*variant as usize, material?)))
+                        })
+                        .collect();
                     Some(MaterialPrimitive {
                         material: gltf.material().index(),
+                        loaded: gltf.material().index(),
+                        mappings,
                         primitive,
+                        morph_sets,
                     })
                 }
             })
             .collect();
         Mesh {
+            index,
             primitives,
-            len: instance_buffer.len() as u32,
-            instances: instance_buffer,
+            len: instances.len() as u32,
+            instances: instance_buffers,
+            transforms: instances,
+            roots,
+            pending: vec![vec![]; num_frames],
+            bounds,
+            allocator,
+            morph,
+            lines,
+            points,
+        }
+    }
+    /// Moves all instances by the model root transform, once per root if the model is replicated.
+    /// A new buffer is made since the old one may still be in use by frames in flight.
+    pub fn set_roots(&mut self, roots: &[glm::Mat4]) {
+        self.roots = roots.to_vec();
+        for buffer in &mut self.instances {
+            *buffer = instance_buffer(self.allocator.clone(), &self.transforms, roots);
+        }
+        for pending in &mut self.pending {
+            pending.clear();
+        }
+        self.len = (self.transforms.len() * roots.len()) as u32;
+    }
+    /// Replaces the transforms of the instances from `first` on, the buffers are updated
+    /// by [`Mesh::upload`] as each frame comes around.
+    pub fn set_transforms(&mut self, first: usize, transforms: &[glm::Mat4]) {
+        self.transforms[first..first + transforms.len()].copy_from_slice(transforms);
+        let changed = (0..self.roots.len()).flat_map(|root| {
+            let start = root * self.transforms.len() + first;
+            (start..start + transforms.len()).map(|i| i as u32)
+        });
+        let changed: Vec<_> = changed.collect();
+        for pending in &mut self.pending {
+            pending.extend(&changed);
+        }
+    }
+    /// Copies the instances that changed into the buffer of `frame`, one copy per run of instances.
+    pub fn upload<L>(
+        &mut self,
+        allocator: &SubbufferAllocator,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        frame: usize,
+    ) {
+        let mut pending = std::mem::take(&mut self.pending[frame]);
+        if pending.is_empty() {
+            return;
+        }
+        pending.sort_unstable();
+        pending.dedup();
+        for run in pending.chunk_by(|a, b| a + 1 == *b) {
+            let (start, count) = (run[0] as DeviceSize, run.len() as DeviceSize);
+            let stage = allocator.allocate_slice::<Instance>(count).unwrap();
+            for (dst, i) in stage.write().unwrap().iter_mut().zip(run) {
+                *dst = self.instance(*i as usize);
+            }
+            let dst = self.instances[frame].clone().slice(start..start + count);
+            builder
+                .copy_buffer(CopyBufferInfo::buffers(stage, dst))
+                .unwrap();
+        }
+    }
+    fn instance(&self, index: usize) -> Instance {
+        let root = &self.roots[index / self.transforms.len()];
+        Instance::from(root * self.transforms[index % self.transforms.len()])
+    }
+
+    /// The triangle primitives and the index of their material.
+    pub fn primitives(&self) -> impl Iterator<Item = (Option<usize>, &Primitive)> {
+        self.primitives
+            .iter()
+            .map(|primitive| (primitive.material, &primitive.primitive))
+    }
+    /// Switches the primitives to the materials of a `KHR_materials_variants` variant,
+    /// primitives without one for it and `None` use the material of the file.
+    pub fn set_variant(&mut self, variant: Option<usize>) {
+        for primitive in &mut self.primitives {
+            primitive.material = variant
+                .and_then(|variant| {
+                    primitive
+                        .mappings
+                        .iter()
+                        .find(|(mapped, _)| *mapped == variant)
+                })
+                .map_or(primitive.loaded, |(_, material)| Some(*material));
         }
     }
+    /// Draws `primitives` instead, they have to have the same vertices as the loaded ones
+    /// and every mesh of the model has to be switched to the same geometry buffers.
+    pub fn set_primitives(&mut self, primitives: &[Primitive]) {
+        for material_primitive in &mut self.primitives {
+            if let Some(primitive) = matching(primitives, &material_primitive.primitive) {
+                material_primitive.primitive = primitive.clone();
+            }
+        }
+    }
+    /// Instance transforms relative to the model root.
+    pub fn transforms(&self) -> &[glm::Mat4] {
+        &self.transforms
+    }
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+    pub fn instance_count(&self) -> u32 {
+        self.len
+    }
+    /// Normal and tangent lines for the debug overlay.
+    pub fn lines(&self) -> Option<&VertexLines> {
+        self.lines.as_ref()
+    }
+    pub fn points(&self) -> Option<&PointCloud> {
+        self.points.as_ref()
+    }
+    /// Points of all instances.
+    pub fn point_count(&self) -> u64 {
+        self.points
+            .as_ref()
+            .map_or(0, |points| points.count() as u64 * self.len as u64)
+    }
+    /// Triangles of all instances.
+    pub fn triangles(&self) -> u64 {
+        self.primitives
+            .iter()
+            .map(|primitive| primitive.primitive.triangles() as u64 * self.len as u64)
+            .sum()
+    }
+
+    /// Triangles of all instances if `primitives` were drawn instead.
+    pub fn triangles_of(&self, primitives: &[Primitive]) -> u64 {
+        self.primitives
+            .iter()
+            .filter_map(|primitive| matching(primitives, &primitive.primitive))
+            .map(|primitive| primitive.triangles() as u64 * self.len as u64)
+            .sum()
+    }
 
-    pub fn render<L>(
-        self,
+    /// Binds the vertex and index buffers shared by all meshes of the model.
+    pub fn bind_geometry<L>(&self, builder: &mut AutoCommandBufferBuilder<L>) {
+        if let Some(primitive) = self.primitives.first() {
+            primitive.primitive.geometry().bind(builder);
+        }
+    }
+    /// Binds the instance buffer of `frame` for [`Mesh::draw`].
+    pub fn bind_instances<L>(&self, builder: &mut AutoCommandBufferBuilder<L>, frame: usize) {
+        builder
+            .bind_vertex_buffers(1, self.instances[frame].clone())
+            .unwrap();
+    }
+    /// Draws triangle primitive `index`, its material has to be bound already.
+    pub fn draw<L>(
+        &self,
+        index: usize,
         builder: &mut AutoCommandBufferBuilder<L>,
-        materials: &Materials,
         layout: &Arc<PipelineLayout>,
-    ) {
-        builder.bind_vertex_buffers(1, self.instances).unwrap();
-        for primitive in self.primitives {
-            materials
-                .get(primitive.material)
-                .unwrap()
-                .clone()
-                .set(builder, layout.clone());
-            primitive.primitive.render(self.len, builder);
+        frame: usize,
+    ) -> DrawStats {
+        let primitive = &self.primitives[index];
+        primitive.bind_morph(builder, layout, 4, frame);
+        primitive.primitive.draw(self.len, builder);
+        DrawStats::draw(primitive.primitive.triangles(), self.len)
+    }
+    /// Draws the geometry without binding any materials.
+    /// `layout` must have the morph set layout at set 0.
+    pub fn render_depth<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        layout: &Arc<PipelineLayout>,
+        frame: usize,
+    ) -> DrawStats {
+        self.bind_instances(builder, frame);
+        self.bind_geometry(builder);
+        let mut stats = DrawStats::default();
+        for primitive in &self.primitives {
+            primitive.bind_morph(builder, layout, 0, frame);
+            primitive.primitive.draw(self.len, builder);
+            stats += DrawStats::draw(primitive.primitive.triangles(), self.len);
         }
+        stats
     }
 }
+
+/// The primitive of `primitives` made from the same glTF primitive as `primitive`.
+fn matching<'a>(primitives: &'a [Primitive], primitive: &Primitive) -> Option<&'a Primitive> {
+    primitives
+        .iter()
+        .find(|other| other.info.index == primitive.info.index)
+}
+
+fn instance_buffer(
+    allocator: Arc<dyn MemoryAllocator>,
+    transforms: &[glm::Mat4],
+    roots: &[glm::Mat4],
+) -> Subbuffer<[Instance]> {
+    Buffer::from_iter(
+        allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::VERTEX_BUFFER | BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        roots.iter().flat_map(|root| {
+            transforms
+                .iter()
+                .map(move |transform| Instance::from(root * transform))
+        }),
+    )
+    .unwrap()
+}
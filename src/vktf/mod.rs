@@ -1,135 +1,414 @@
-use loader::{PrimitiveVertex, VktfDocument};
-use material::{MaterialPush, Materials};
+use crate::{frameinfo::Depth, stats::DrawStats};
+use animation::AnimationPlayer;
+use bounds::Aabb;
+use cache::VktfCache;
+use debug::{DebugPush, DebugView};
+use light::Light;
+use loader::{PrimitiveVertex, VertexCacheStats, VktfDocument, is_points};
+use lod::ModelLod;
+use material::{Material, MaterialPush, Materials};
 use mesh::{Instance, Mesh};
+use morph::MorphLoader;
 use nalgebra_glm as glm;
+use scene::SceneGraph;
+use serde::{Deserialize, Serialize};
+use space::AssetSpace;
 use std::sync::Arc;
 use vulkano::{
+    Validated, VulkanError,
+    buffer::allocator::SubbufferAllocator,
     command_buffer::AutoCommandBufferBuilder,
-    descriptor_set::{allocator::DescriptorSetAllocator, layout::DescriptorSetLayout},
-    device::Device,
+    descriptor_set::{
+        DescriptorSet, allocator::DescriptorSetAllocator, layout::DescriptorSetLayout,
+    },
+    device::{Device, DeviceOwned},
     image::SampleCount,
     memory::allocator::MemoryAllocator,
     pipeline::{
-        DynamicState, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+        DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
         graphics::{
             GraphicsPipelineCreateInfo,
             color_blend::{ColorBlendAttachmentState, ColorBlendState},
-            depth_stencil::{DepthState, DepthStencilState},
+            depth_stencil::{CompareOp, DepthStencilState},
             input_assembly::InputAssemblyState,
             multisample::MultisampleState,
-            rasterization::{CullMode, FrontFace, RasterizationState},
+            rasterization::{CullMode, FrontFace, PolygonMode, RasterizationState},
             vertex_input::{Vertex, VertexDefinition},
             viewport::ViewportState,
         },
         layout::{PipelineLayoutCreateInfo, PushConstantRange},
     },
     render_pass::Subpass,
-    shader::ShaderStages,
+    shader::{EntryPoint, ShaderStages},
 };
 
+pub mod animation;
+pub mod bounds;
+pub mod cache;
+pub mod debug;
+pub mod deviation;
+pub mod export;
+pub mod light;
 pub mod loader;
+pub mod lod;
 pub mod material;
+pub mod material_override;
 pub mod mesh;
+pub mod morph;
+pub mod report;
+pub mod scene;
+pub mod space;
+
+/// Places a whole model in the world.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelTransform {
+    pub translation: glm::Vec3,
+    /// Rotation around the up axis in radians.
+    pub yaw: f32,
+    pub scale: f32,
+    pub space: AssetSpace,
+}
+impl ModelTransform {
+    pub fn matrix(&self) -> glm::Mat4 {
+        self.placement() * self.space.matrix()
+    }
+    /// The matrix without the conversion of the file units and up axis.
+    fn placement(&self) -> glm::Mat4 {
+        let matrix = glm::translation(&self.translation);
+        let matrix = glm::rotate_y(&matrix, self.yaw);
+        glm::scale(&matrix, &glm::Vec3::repeat(self.scale))
+    }
+}
+impl Default for ModelTransform {
+    fn default() -> Self {
+        Self {
+            translation: glm::Vec3::zeros(),
+            yaw: 0.0,
+            scale: 1.0,
+            space: AssetSpace::default(),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct GltfRenderInfo {
     pub meshes: Vec<Mesh>,
     pub materials: Materials,
+    /// Lights relative to the model root.
+    pub lights: Vec<Light>,
+    pub scene: SceneGraph,
+    /// Bounds of all mesh instances relative to the model root.
+    pub aabb: Aabb,
     pub vktf: Arc<VktfDocument>,
+    transform: ModelTransform,
+    /// The model is drawn this many times along both sides of a grid to stress instancing.
+    replicas: u32,
+    /// Fraction of the triangles the next simplification aims for.
+    pub lod_ratio: f32,
+    lod: Option<Arc<ModelLod>>,
+    lod_shown: bool,
+    /// The picked `KHR_materials_variants` variant.
+    variant: Option<usize>,
+    pub player: AnimationPlayer,
 }
 impl GltfRenderInfo {
     pub fn new_default(
         mem_allocator: Arc<dyn MemoryAllocator>,
         set_allocator: Arc<dyn DescriptorSetAllocator>,
         layout: Arc<DescriptorSetLayout>,
+        morph_layout: Arc<DescriptorSetLayout>,
+        num_frames: usize,
         vktf: VktfDocument,
+        cache: &VktfCache,
     ) -> GltfRenderInfo {
-        let materials = Materials::new(set_allocator, layout, &vktf);
+        let materials = Materials::new(set_allocator.clone(), layout, &vktf, cache);
+        let morph_loader = MorphLoader::new(
+            mem_allocator.clone(),
+            set_allocator,
+            morph_layout,
+            num_frames,
+        );
 
-        let scene = vktf.document.default_scene().unwrap();
-        let mut builder = GltfRenderInfoBuilder { instances: vec![] };
-        Self::iter_nodes(scene.nodes(), &glm::identity(), &mut builder);
+        let scene = vktf
+            .document
+            .default_scene()
+            .or_else(|| vktf.document.scenes().next())
+            .unwrap();
+        let (scene, instances, lights) = SceneGraph::new(&vktf.document, &scene, &vktf.vktf);
+        let lights = lights
+            .iter()
+            .map(|(node, light)| Light::new(light, &scene.nodes[*node].world))
+            .collect();
 
-        let meshes = builder
-            .instances
+        let meshes: Vec<_> = instances
             .into_iter()
             .map(|(index, instances)| {
-                let primitives = vktf
-                    .document
-                    .meshes()
-                    .nth(index)
-                    .unwrap()
+                let mesh = vktf.document.meshes().nth(index).unwrap();
+                let vk_primitives = vktf.vktf.get_mesh(index).unwrap();
+                let (morph, morph_sets) = morph_loader.load(&mesh, vk_primitives);
+                let primitives = mesh
                     .primitives()
-                    .zip(vktf.vktf.get_mesh(index).unwrap().iter().cloned());
-                Mesh::new(mem_allocator.clone(), primitives, instances)
+                    .filter(|primitive| !is_points(primitive))
+                    .zip(vk_primitives.iter().cloned())
+                    .zip(morph_sets)
+                    .map(|((gltf, primitive), sets)| (gltf, primitive, sets));
+                Mesh::new(
+                    index,
+                    mem_allocator.clone(),
+                    primitives,
+                    instances,
+                    morph,
+                    vktf.vktf.get_lines(index).cloned(),
+                    vktf.vktf.get_points(index).cloned(),
+                    num_frames,
+                )
             })
             .collect();
+        let aabb = meshes_aabb(&meshes);
 
         Self {
             meshes,
             materials,
+            lights,
+            scene,
+            aabb,
             vktf: Arc::new(vktf),
+            transform: ModelTransform::default(),
+            replicas: 1,
+            lod_ratio: 0.5,
+            lod: None,
+            lod_shown: false,
+            variant: None,
+            player: AnimationPlayer::default(),
+        }
+    }
+    pub fn transform(&self) -> ModelTransform {
+        self.transform
+    }
+    pub fn set_transform(&mut self, transform: ModelTransform) {
+        if self.transform == transform {
+            return;
         }
+        self.transform = transform;
+        self.update_instances();
+    }
+    /// Names of the `KHR_materials_variants` variants of the file.
+    pub fn variants(&self) -> Vec<String> {
+        self.vktf
+            .document
+            .variants()
+            .map(|variants| variants.map(|variant| variant.name().to_owned()).collect())
+            .unwrap_or_default()
+    }
+    pub fn variant(&self) -> Option<usize> {
+        self.variant
     }
-    fn iter_nodes<'a>(
-        nodes: impl Iterator<Item = gltf::Node<'a>>,
-        transform: &glm::Mat4,
-        builder: &mut GltfRenderInfoBuilder,
+    /// Shows the materials of a variant, `None` for the materials of the file.
+    pub fn set_variant(&mut self, variant: Option<usize>) {
+        if self.variant == variant {
+            return;
+        }
+        self.variant = variant;
+        for mesh in &mut self.meshes {
+            mesh.set_variant(variant);
+        }
+    }
+    pub fn replicas(&self) -> u32 {
+        self.replicas
+    }
+    pub fn set_replicas(&mut self, replicas: u32) {
+        let replicas = replicas.max(1);
+        if self.replicas == replicas {
+            return;
+        }
+        self.replicas = replicas;
+        self.update_instances();
+    }
+    fn update_instances(&mut self) {
+        let roots = self.roots();
+        for mesh in &mut self.meshes {
+            mesh.set_roots(&roots);
+        }
+    }
+    /// The transform of every replica, they are placed next to each other on the ground.
+    fn roots(&self) -> Vec<glm::Mat4> {
+        let matrix = self.transform.matrix();
+        if self.replicas == 1 || self.aabb.is_empty() {
+            return vec![matrix];
+        }
+        let space = self.transform.space.matrix();
+        let aabb = self.aabb.transform(&space);
+        let size = aabb.max - aabb.min;
+        let spacing = size.x.max(size.z) * 1.25;
+        let placement = self.transform.placement();
+        let n = self.replicas;
+        let offset = (n - 1) as f32 / 2.0;
+        (0..n * n)
+            .map(|i| {
+                let x = ((i % n) as f32 - offset) * spacing;
+                let z = ((i / n) as f32 - offset) * spacing;
+                placement * glm::translation(&glm::vec3(x, 0.0, z)) * space
+            })
+            .collect()
+    }
+    /// Applies the node edits since the last call and copies the instances that moved
+    /// into the buffers of `frame`.
+    pub fn upload<L>(
+        &mut self,
+        allocator: &SubbufferAllocator,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        frame: usize,
     ) {
-        for node in nodes {
-            let transform = transform * glm::Mat4::from(node.transform().matrix());
-            if let Some(mesh) = node.mesh() {
-                builder.add_mesh(mesh.index(), transform);
+        let moved = self.scene.update();
+        for node in moved.iter().map(|index| &self.scene.nodes[*index]) {
+            if let Some(range) = node.instances {
+                let transforms = node.instance_transforms();
+                self.meshes[range.mesh].set_transforms(range.first, &transforms);
             }
-            Self::iter_nodes(node.children(), &transform, builder);
+            // lights removed in the UI aren't moved anymore
+            if let Some(light) = node.light.and_then(|light| self.lights.get_mut(light)) {
+                light.place(&node.world);
+            }
+        }
+        if !moved.is_empty() {
+            self.aabb = meshes_aabb(&self.meshes);
+        }
+        for mesh in &mut self.meshes {
+            mesh.upload(allocator, builder, frame);
+        }
+    }
+    pub fn world_aabb(&self) -> Aabb {
+        let mut aabb = Aabb::empty();
+        for root in self.roots() {
+            aabb.union(&self.aabb.transform(&root));
+        }
+        aabb
+    }
+    /// Fraction of the screen covered by the meshes using each material.
+    pub fn material_coverage(&self, view_proj: &glm::Mat4) -> Vec<f32> {
+        let mut coverage = vec![0.0; self.vktf.document.materials().len()];
+        let root = self.transform.matrix();
+        for mesh in &self.meshes {
+            let bounds = mesh.bounds();
+            let covered: f32 = mesh
+                .transforms()
+                .iter()
+                .map(|transform| {
+                    bounds
+                        .transform(&(root * transform))
+                        .screen_coverage(view_proj)
+                })
+                .sum();
+            for material in mesh.primitives().filter_map(|(material, _)| material) {
+                coverage[material] += covered;
+            }
+        }
+        coverage
+    }
+    pub fn lod(&self) -> Option<&Arc<ModelLod>> {
+        self.lod.as_ref()
+    }
+    pub fn lod_shown(&self) -> bool {
+        self.lod_shown
+    }
+    /// Replaces the simplified geometry and draws it.
+    pub fn set_lod(&mut self, lod: ModelLod) {
+        self.lod = Some(Arc::new(lod));
+        self.show_lod(true);
+    }
+    /// Switches between the simplified and the loaded geometry.
+    pub fn show_lod(&mut self, shown: bool) {
+        self.lod_shown = shown && self.lod.is_some();
+        for mesh in &mut self.meshes {
+            let primitives = match &self.lod {
+                Some(lod) if self.lod_shown => &lod.meshes[mesh.index],
+                _ => self.vktf.vktf.get_mesh(mesh.index).unwrap(),
+            };
+            mesh.set_primitives(primitives);
+        }
+    }
+    /// Triangles of all mesh instances, loaded and simplified, `None` if there is no simplification.
+    pub fn lod_triangles(&self) -> Option<(u64, u64)> {
+        let lod = self.lod.as_ref()?;
+        let mut original = 0;
+        let mut simplified = 0;
+        for mesh in &self.meshes {
+            original += mesh.triangles_of(self.vktf.vktf.get_mesh(mesh.index).unwrap());
+            simplified += mesh.triangles_of(&lod.meshes[mesh.index]);
         }
+        Some((original, simplified))
+    }
+    /// Summed over the loaded primitives, `None` if they weren't optimized.
+    pub fn vertex_cache(&self) -> Option<VertexCacheStats> {
+        self.vktf
+            .vktf
+            .primitives()
+            .filter_map(|primitive| primitive.info.vertex_cache)
+            .reduce(|mut sum, stats| {
+                sum += stats;
+                sum
+            })
+    }
+    /// Triangles of all mesh instances.
+    pub fn triangles(&self) -> u64 {
+        self.meshes.iter().map(Mesh::triangles).sum()
+    }
+    /// Points of all mesh instances.
+    pub fn points(&self) -> u64 {
+        self.meshes.iter().map(Mesh::point_count).sum()
+    }
+    pub fn world_lights(&self) -> impl Iterator<Item = Light> {
+        let matrix = self.transform.matrix();
+        self.lights
+            .iter()
+            .map(move |light| light.transformed(&matrix))
     }
 }
 
-struct GltfRenderInfoBuilder {
-    instances: Vec<(usize, Vec<glm::Mat4>)>,
-}
-impl GltfRenderInfoBuilder {
-    pub fn add_mesh(&mut self, index: usize, transform: glm::Mat4) {
-        match self.instances.binary_search_by_key(&index, |(i, _)| *i) {
-            Ok(i) => {
-                self.instances[i].1.push(transform);
-            }
-            Err(i) => {
-                self.instances.insert(i, (index, vec![transform]));
-            }
+/// Bounds of all mesh instances relative to the model root.
+fn meshes_aabb(meshes: &[Mesh]) -> Aabb {
+    let mut aabb = Aabb::empty();
+    for mesh in meshes {
+        // hidden instances are collapsed to the origin
+        for transform in mesh
+            .transforms()
+            .iter()
+            .filter(|t| **t != glm::Mat4::zeros())
+        {
+            aabb.union(&mesh.bounds().transform(transform));
         }
     }
+    aabb
 }
 
 #[derive(Clone)]
 pub struct GltfPipeline {
     pub pipeline: Arc<GraphicsPipeline>,
+    /// Same as `pipeline` but only draws the edges, `None` if the device can't.
+    pub wireframe: Option<Arc<GraphicsPipeline>>,
 }
 impl GltfPipeline {
     pub fn new(
         device: Arc<Device>,
         set_layouts: Vec<Arc<DescriptorSetLayout>>,
         subpass: Subpass,
+        depth: Depth,
     ) -> Self {
-        let vs = vs::load(device.clone())
-            .unwrap()
-            .entry_point("main")
-            .unwrap();
         let fs = fs::load(device.clone())
             .unwrap()
             .entry_point("main")
             .unwrap();
-
-        let vertex_input_state = [PrimitiveVertex::per_vertex(), Instance::per_instance()]
-            .definition(&vs)
-            .unwrap();
-
-        let stages = [
-            PipelineShaderStageCreateInfo::new(vs),
-            PipelineShaderStageCreateInfo::new(fs),
-        ];
-
+        Self::with_fragment_shader(device, set_layouts, subpass, depth, fs).unwrap()
+    }
+    /// Uses `fs` instead of `gltf.frag`, it has to have the same inputs, sets and push constants.
+    pub fn with_fragment_shader(
+        device: Arc<Device>,
+        set_layouts: Vec<Arc<DescriptorSetLayout>>,
+        subpass: Subpass,
+        depth: Depth,
+        fs: EntryPoint,
+    ) -> Result<Self, Validated<VulkanError>> {
         let layout = PipelineLayout::new(
             device.clone(),
             PipelineLayoutCreateInfo {
@@ -137,60 +416,172 @@ impl GltfPipeline {
                 push_constant_ranges: vec![PushConstantRange {
                     stages: ShaderStages::FRAGMENT,
                     offset: 0,
-                    size: std::mem::size_of::<MaterialPush>() as u32,
+                    size: (std::mem::size_of::<MaterialPush>() + std::mem::size_of::<DebugPush>())
+                        as u32,
                 }],
                 ..Default::default()
             },
         )
         .unwrap();
 
-        let pipeline = GraphicsPipeline::new(
-            device,
-            None,
-            GraphicsPipelineCreateInfo {
-                stages: stages.into_iter().collect(),
-                vertex_input_state: Some(vertex_input_state),
-                input_assembly_state: Some(InputAssemblyState::default()),
-                viewport_state: Some(ViewportState::default()),
-                multisample_state: Some(MultisampleState {
-                    rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
-                    ..Default::default()
-                }),
-                rasterization_state: Some(RasterizationState {
-                    front_face: FrontFace::CounterClockwise,
-                    cull_mode: CullMode::Back,
-                    ..Default::default()
-                }),
-                color_blend_state: Some(ColorBlendState::with_attachment_states(
-                    subpass.num_color_attachments(),
-                    ColorBlendAttachmentState::default(),
-                )),
-                depth_stencil_state: Some(DepthStencilState {
-                    depth: Some(DepthState::simple()),
-                    ..Default::default()
-                }),
-                dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
-                    .into_iter()
-                    .collect(),
-                subpass: Some(subpass.into()),
-                ..GraphicsPipelineCreateInfo::layout(layout)
-            },
-        )
-        .unwrap();
+        let pipeline = gltf_pipeline(
+            layout.clone(),
+            subpass.clone(),
+            PolygonMode::Fill,
+            depth,
+            fs.clone(),
+        )?;
+        let wireframe = device
+            .enabled_features()
+            .fill_mode_non_solid
+            .then(|| gltf_pipeline(layout, subpass, PolygonMode::Line, depth, fs))
+            .transpose()?;
 
-        Self { pipeline }
+        Ok(Self {
+            pipeline,
+            wireframe,
+        })
     }
-    pub fn render<L>(&self, info: GltfRenderInfo, builder: &mut AutoCommandBufferBuilder<L>) {
+    pub fn render<L>(
+        &self,
+        models: &[GltfRenderInfo],
+        debug_view: DebugView,
+        material_override: Option<&Material>,
+        frame: usize,
+        opaque_only: bool,
+        wireframe: bool,
+        builder: &mut AutoCommandBufferBuilder<L>,
+    ) -> DrawStats {
+        let pipeline = match &self.wireframe {
+            Some(pipeline) if wireframe => pipeline,
+            _ => &self.pipeline,
+        };
         builder
-            .bind_pipeline_graphics(self.pipeline.clone())
+            .bind_pipeline_graphics(pipeline.clone())
+            .unwrap()
+            .push_constants(
+                self.pipeline.layout().clone(),
+                std::mem::size_of::<MaterialPush>() as u32,
+                DebugPush::from(debug_view),
+            )
             .unwrap();
-        // TODO: dont rebind and repush materials when not needed
-        for mesh in info.meshes {
-            mesh.render(builder, &info.materials, self.pipeline.layout());
+
+        // group the primitives by material so each material is only bound once
+        let mut draws = vec![];
+        for (model, info) in models.iter().enumerate() {
+            for (mesh, primitives) in info.meshes.iter().enumerate() {
+                for (primitive, (material, _)) in primitives.primitives().enumerate() {
+                    let transmissive = info.materials.get(material).unwrap().push.tr > 0.0;
+                    if opaque_only && transmissive && material_override.is_none() {
+                        continue;
+                    }
+                    draws.push((model, material, mesh, primitive));
+                }
+            }
         }
+        draws.sort_by_key(|&(model, material, mesh, _)| (model, material, mesh));
+
+        let layout = self.pipeline.layout();
+        let mut bound_set: Option<&Arc<DescriptorSet>> = None;
+        let mut pushed: Option<MaterialPush> = None;
+        let mut bound_mesh = None;
+        let mut stats = DrawStats::default();
+        for (model, material, mesh, primitive) in draws {
+            let info = &models[model];
+            let material =
+                material_override.unwrap_or_else(|| info.materials.get(material).unwrap());
+            if bound_set.is_none_or(|set| !Arc::ptr_eq(set, &material.set)) {
+                builder
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        layout.clone(),
+                        2,
+                        material.set.clone(),
+                    )
+                    .unwrap();
+                bound_set = Some(&material.set);
+            }
+            if pushed != Some(material.push) {
+                builder
+                    .push_constants(layout.clone(), 0, material.push)
+                    .unwrap();
+                pushed = Some(material.push);
+            }
+            let mesh_ref = &info.meshes[mesh];
+            if bound_mesh.is_none_or(|(bound_model, _)| bound_model != model) {
+                mesh_ref.bind_geometry(builder);
+            }
+            if bound_mesh != Some((model, mesh)) {
+                mesh_ref.bind_instances(builder, frame);
+                bound_mesh = Some((model, mesh));
+            }
+            stats += mesh_ref.draw(primitive, builder, layout, frame);
+        }
+        stats
     }
 }
 
+fn gltf_pipeline(
+    layout: Arc<PipelineLayout>,
+    subpass: Subpass,
+    polygon_mode: PolygonMode,
+    depth: Depth,
+    fs: EntryPoint,
+) -> Result<Arc<GraphicsPipeline>, Validated<VulkanError>> {
+    let device = layout.device().clone();
+    let vs = vs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+
+    let vertex_input_state = [PrimitiveVertex::per_vertex(), Instance::per_instance()]
+        .definition(&vs)
+        .unwrap();
+
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                ..Default::default()
+            }),
+            rasterization_state: Some(RasterizationState {
+                polygon_mode,
+                front_face: FrontFace::CounterClockwise,
+                // back faces are part of the wireframe
+                cull_mode: match polygon_mode {
+                    PolygonMode::Fill => CullMode::Back,
+                    _ => CullMode::None,
+                },
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default(),
+            )),
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(depth.state(CompareOp::Less, true)),
+                ..Default::default()
+            }),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+}
+
 mod vs {
     vulkano_shaders::shader! {
         ty: "vertex",
@@ -0,0 +1,206 @@
+use super::loader::Primitive;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use vulkano::{
+    buffer::{
+        Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer,
+        allocator::SubbufferAllocator,
+    },
+    command_buffer::{AutoCommandBufferBuilder, CopyBufferInfo},
+    descriptor_set::{
+        DescriptorSet, WriteDescriptorSet, allocator::DescriptorSetAllocator,
+        layout::DescriptorSetLayout,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryAllocator, MemoryTypeFilter},
+};
+
+pub const MAX_MORPH_TARGETS: usize = 32;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, BufferContents)]
+pub struct MorphUniform {
+    pub count: u32,
+    _pad: [u32; 3],
+    pub weights: [f32; MAX_MORPH_TARGETS],
+}
+impl MorphUniform {
+    pub fn new(weights: &[f32]) -> Self {
+        let mut slf = Self {
+            count: weights.len().min(MAX_MORPH_TARGETS) as u32,
+            _pad: [0; 3],
+            weights: [0.0; MAX_MORPH_TARGETS],
+        };
+        for (dst, src) in slf.weights.iter_mut().zip(weights) {
+            *dst = *src;
+        }
+        slf
+    }
+}
+
+/// Position, normal and tangent deltas of a primitive, laid out as `[vertex][target][attribute]`
+/// after one element holding the base vertex of the primitive.
+#[derive(Clone, Debug)]
+pub struct MorphTargets {
+    pub deltas: Subbuffer<[glm::Vec4]>,
+    pub count: u32,
+}
+
+/// The weights of a mesh with morph targets.
+#[derive(Clone)]
+pub struct Morph {
+    pub name: String,
+    pub weights: Vec<f32>,
+    buffers: Vec<Subbuffer<MorphUniform>>,
+}
+impl Morph {
+    pub fn upload<L>(
+        &self,
+        allocator: &SubbufferAllocator,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        index: usize,
+    ) {
+        let buffer = allocator.allocate_sized().unwrap();
+        *buffer.write().unwrap() = MorphUniform::new(&self.weights);
+        builder
+            .copy_buffer(CopyBufferInfo::buffers(buffer, self.buffers[index].clone()))
+            .unwrap();
+    }
+}
+
+/// Creates the per frame morph descriptor sets of every primitive.
+pub struct MorphLoader {
+    mem_allocator: Arc<dyn MemoryAllocator>,
+    set_allocator: Arc<dyn DescriptorSetAllocator>,
+    layout: Arc<DescriptorSetLayout>,
+    num_frames: usize,
+    /// Used by primitives without morph targets.
+    empty: Arc<DescriptorSet>,
+}
+impl MorphLoader {
+    pub fn new(
+        mem_allocator: Arc<dyn MemoryAllocator>,
+        set_allocator: Arc<dyn DescriptorSetAllocator>,
+        layout: Arc<DescriptorSetLayout>,
+        num_frames: usize,
+    ) -> Self {
+        let deltas = Buffer::from_iter(
+            mem_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            [glm::Vec4::zeros()],
+        )
+        .unwrap();
+        let weights = Buffer::from_data(
+            mem_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::UNIFORM_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            MorphUniform::new(&[]),
+        )
+        .unwrap();
+        let empty = DescriptorSet::new(
+            set_allocator.clone(),
+            layout.clone(),
+            [
+                WriteDescriptorSet::buffer(0, deltas),
+                WriteDescriptorSet::buffer(1, weights),
+            ],
+            [],
+        )
+        .unwrap();
+
+        Self {
+            mem_allocator,
+            set_allocator,
+            layout,
+            num_frames,
+            empty,
+        }
+    }
+
+    /// Returns the mesh weights, if any primitive has morph targets,
+    /// and the descriptor sets of each primitive for every frame.
+    pub fn load(
+        &self,
+        mesh: &gltf::Mesh,
+        primitives: &[Primitive],
+    ) -> (Option<Morph>, Vec<Vec<Arc<DescriptorSet>>>) {
+        let count = primitives
+            .iter()
+            .filter_map(|primitive| primitive.morph.as_ref())
+            .map(|morph| morph.count)
+            .max()
+            .unwrap_or(0) as usize;
+        if count == 0 {
+            let sets = vec![vec![self.empty.clone(); self.num_frames]; primitives.len()];
+            return (None, sets);
+        }
+
+        let mut weights = mesh.weights().map(<[f32]>::to_vec).unwrap_or_default();
+        weights.resize(count, 0.0);
+
+        let buffers: Vec<_> = (0..self.num_frames)
+            .map(|_| {
+                Buffer::from_data(
+                    self.mem_allocator.clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::UNIFORM_BUFFER | BufferUsage::TRANSFER_DST,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                            | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                        ..Default::default()
+                    },
+                    MorphUniform::new(&weights),
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let sets = primitives
+            .iter()
+            .map(|primitive| match &primitive.morph {
+                Some(targets) => buffers
+                    .iter()
+                    .map(|buffer| {
+                        DescriptorSet::new(
+                            self.set_allocator.clone(),
+                            self.layout.clone(),
+                            [
+                                WriteDescriptorSet::buffer(0, targets.deltas.clone()),
+                                WriteDescriptorSet::buffer(1, buffer.clone()),
+                            ],
+                            [],
+                        )
+                        .unwrap()
+                    })
+                    .collect(),
+                None => vec![self.empty.clone(); self.num_frames],
+            })
+            .collect();
+
+        let morph = Morph {
+            name: mesh
+                .name()
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("Mesh {}", mesh.index())),
+            weights,
+            buffers,
+        };
+        (Some(morph), sets)
+    }
+}
@@ -0,0 +1,188 @@
+use super::GltfRenderInfo;
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReportError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A summary of a loaded model for asset checks outside the viewer.
+#[derive(Debug, Serialize)]
+pub struct ModelReport {
+    pub path: String,
+    pub generator: Option<String>,
+    pub version: String,
+    pub extensions_used: Vec<String>,
+    pub extensions_required: Vec<String>,
+    /// Of all mesh instances in the shown scene.
+    pub triangles: u64,
+    /// Of the textures that are loaded.
+    pub texture_bytes: u64,
+    pub meshes: Vec<MeshReport>,
+    pub textures: Vec<TextureReport>,
+    pub materials: Vec<MaterialReport>,
+    pub animations: Vec<AnimationReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MeshReport {
+    pub index: usize,
+    pub name: Option<String>,
+    pub primitives: usize,
+    /// As uploaded, after generating flat normals or optimizing.
+    pub vertices: usize,
+    /// Of one instance.
+    pub triangles: u64,
+    /// Instances in the shown scene.
+    pub instances: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TextureReport {
+    /// Index of the glTF image.
+    pub index: usize,
+    pub name: Option<String>,
+    /// `None` if the image isn't loaded yet or failed to load.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub format: Option<String>,
+    pub mip_levels: Option<u32>,
+    /// GPU memory including all mips.
+    pub bytes: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaterialReport {
+    pub index: usize,
+    pub name: Option<String>,
+    pub alpha_mode: String,
+    pub double_sided: bool,
+    pub extensions: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnimationReport {
+    pub index: usize,
+    pub name: Option<String>,
+    pub channels: usize,
+    /// Time of the last keyframe in seconds.
+    pub duration: f32,
+}
+
+impl ModelReport {
+    pub fn new(info: &GltfRenderInfo) -> Self {
+        let document = &info.vktf.document;
+        let json = document.as_json();
+
+        let meshes = document
+            .meshes()
+            .map(|mesh| {
+                let primitives = info.vktf.vktf.get_mesh(mesh.index()).unwrap_or_default();
+                MeshReport {
+                    index: mesh.index(),
+                    name: mesh.name().map(str::to_owned),
+                    primitives: mesh.primitives().len(),
+                    vertices: primitives.iter().map(|p| p.info.vertices).sum(),
+                    triangles: primitives.iter().map(|p| p.triangles() as u64).sum(),
+                    instances: info
+                        .meshes
+                        .iter()
+                        .find(|m| m.index == mesh.index())
+                        .map_or(0, |m| m.instance_count()),
+                }
+            })
+            .collect();
+
+        let textures: Vec<_> = document
+            .images()
+            .map(|image| {
+                let view = info.vktf.vktf.loaded_image(image.index());
+                let extent = view.map(|view| view.image().extent());
+                TextureReport {
+                    index: image.index(),
+                    name: image.name().map(str::to_owned),
+                    width: extent.map(|[width, ..]| width),
+                    height: extent.map(|[_, height, _]| height),
+                    format: view.map(|view| format!("{:?}", view.format())),
+                    mip_levels: view.map(|view| view.image().mip_levels()),
+                    bytes: view.map(|view| {
+                        view.image()
+                            .memory_requirements()
+                            .iter()
+                            .map(|requirements| requirements.layout.size())
+                            .sum()
+                    }),
+                }
+            })
+            .collect();
+
+        let materials = document
+            .materials()
+            .zip(&json.materials)
+            .enumerate()
+            .map(|(index, (material, json))| MaterialReport {
+                index,
+                name: material.name().map(str::to_owned),
+                alpha_mode: format!("{:?}", material.alpha_mode()),
+                double_sided: material.double_sided(),
+                extensions: serde_json::to_value(json)
+                    .ok()
+                    .and_then(|json| {
+                        let extensions = json.get("extensions")?.as_object()?;
+                        Some(extensions.keys().cloned().collect())
+                    })
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        // pointer channels aren't part of the document
+        let mut pointers = BTreeMap::new();
+        for animation in &info.vktf.animations {
+            pointers.insert(animation.index, animation);
+        }
+        let animations = document
+            .animations()
+            .map(|animation| {
+                let pointer = pointers.get(&animation.index());
+                let duration = animation
+                    .channels()
+                    .filter_map(|channel| channel.sampler().input().max())
+                    .filter_map(|max| max.get(0)?.as_f64())
+                    .map(|max| max as f32)
+                    .chain(pointer.map(|pointer| pointer.duration))
+                    .fold(0.0, f32::max);
+                AnimationReport {
+                    index: animation.index(),
+                    name: animation.name().map(str::to_owned),
+                    channels: animation.channels().count()
+                        + pointer.map_or(0, |p| p.tracks.len() + p.ignored.len()),
+                    duration,
+                }
+            })
+            .collect();
+
+        Self {
+            path: info.vktf.path.display().to_string(),
+            generator: json.asset.generator.clone(),
+            version: json.asset.version.clone(),
+            extensions_used: json.extensions_used.clone(),
+            extensions_required: json.extensions_required.clone(),
+            triangles: info.triangles(),
+            texture_bytes: textures.iter().filter_map(|texture| texture.bytes).sum(),
+            meshes,
+            textures,
+            materials,
+            animations,
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), ReportError> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+}
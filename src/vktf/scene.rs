@@ -0,0 +1,177 @@
+use super::loader::Vktf;
+use nalgebra_glm as glm;
+use std::collections::BTreeMap;
+
+/// Where the instances of a node are in the instance list of its mesh.
+#[derive(Debug, Clone, Copy)]
+pub struct InstanceRange {
+    /// Index into the meshes of the model.
+    pub mesh: usize,
+    pub first: usize,
+    pub count: usize,
+}
+
+#[derive(Clone)]
+pub struct SceneNode {
+    pub name: Option<String>,
+    pub local: glm::Mat4,
+    /// Relative to the model root.
+    pub world: glm::Mat4,
+    pub children: Vec<usize>,
+    /// `EXT_mesh_gpu_instancing` transforms relative to the node, empty without the extension.
+    gpu_instances: Vec<glm::Mat4>,
+    pub instances: Option<InstanceRange>,
+    /// Index into the lights of the model.
+    pub light: Option<usize>,
+    /// `KHR_node_visibility`, a hidden node also hides its children.
+    visible: bool,
+    /// The node and all of its parents are visible.
+    shown: bool,
+    dirty: bool,
+}
+impl SceneNode {
+    /// Set by `KHR_node_visibility`, animations or [`SceneGraph::set_visible`],
+    /// the node is still hidden if one of its parents is.
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+    /// Transforms of the mesh instances of this node relative to the model root.
+    /// Hidden instances collapse to a point so the ranges of the mesh stay the same.
+    pub fn instance_transforms(&self) -> Vec<glm::Mat4> {
+        if !self.shown {
+            vec![glm::Mat4::zeros(); self.gpu_instances.len().max(1)]
+        } else if self.gpu_instances.is_empty() {
+            vec![self.world]
+        } else {
+            self.gpu_instances
+                .iter()
+                .map(|instance| self.world * instance)
+                .collect()
+        }
+    }
+}
+
+/// The node hierarchy of the shown scene with the world transforms of the last update,
+/// so an edited node only moves its own instances and those of its children.
+#[derive(Clone)]
+pub struct SceneGraph {
+    /// By glTF node index, nodes outside the scene are never updated.
+    pub nodes: Vec<SceneNode>,
+    pub roots: Vec<usize>,
+}
+impl SceneGraph {
+    /// Also returns the instance transforms of every mesh in the scene by mesh index,
+    /// and the lights with the node each belongs to.
+    pub fn new<'a>(
+        document: &'a gltf::Document,
+        scene: &gltf::Scene,
+        vktf: &Vktf,
+    ) -> (
+        Self,
+        BTreeMap<usize, Vec<glm::Mat4>>,
+        Vec<(usize, gltf::khr_lights_punctual::Light<'a>)>,
+    ) {
+        let nodes = document
+            .nodes()
+            .map(|node| SceneNode {
+                name: node.name().map(str::to_owned),
+                local: glm::Mat4::from(node.transform().matrix()),
+                world: glm::identity(),
+                children: node.children().map(|child| child.index()).collect(),
+                gpu_instances: vktf
+                    .get_instances(node.index())
+                    .map(<[_]>::to_vec)
+                    .unwrap_or_default(),
+                instances: None,
+                light: None,
+                visible: node
+                    .extensions()
+                    .and_then(|extensions| extensions.get("KHR_node_visibility"))
+                    .and_then(|visibility| visibility.get("visible"))
+                    .and_then(|visible| visible.as_bool())
+                    .unwrap_or(true),
+                shown: true,
+                dirty: true,
+            })
+            .collect();
+        let mut graph = Self {
+            nodes,
+            roots: scene.nodes().map(|node| node.index()).collect(),
+        };
+        graph.update();
+
+        // instances are numbered per mesh in the order the nodes are visited
+        let mut meshes = BTreeMap::<usize, Vec<glm::Mat4>>::new();
+        let mut ranges = vec![];
+        let mut lights = vec![];
+        let mut stack: Vec<_> = graph.roots.iter().rev().copied().collect();
+        while let Some(index) = stack.pop() {
+            let gltf_node = document.nodes().nth(index).unwrap();
+            let node = &graph.nodes[index];
+            if let Some(mesh) = gltf_node.mesh() {
+                let transforms = meshes.entry(mesh.index()).or_default();
+                let first = transforms.len();
+                transforms.extend(node.instance_transforms());
+                ranges.push((index, mesh.index(), first, transforms.len() - first));
+            }
+            if let Some(light) = gltf_node.light() {
+                lights.push((index, light));
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        for (node, mesh, first, count) in ranges {
+            graph.nodes[node].instances = Some(InstanceRange {
+                mesh: meshes.keys().position(|index| *index == mesh).unwrap(),
+                first,
+                count,
+            });
+        }
+        for (i, (node, _)) in lights.iter().enumerate() {
+            graph.nodes[*node].light = Some(i);
+        }
+        (graph, meshes, lights)
+    }
+
+    /// Changes the transform of a node relative to its parent, applied by [`update`](Self::update).
+    pub fn set_local(&mut self, node: usize, local: glm::Mat4) {
+        if self.nodes[node].local != local {
+            self.nodes[node].local = local;
+            self.nodes[node].dirty = true;
+        }
+    }
+
+    /// Shows or hides a node and its children, applied by [`update`](Self::update).
+    pub fn set_visible(&mut self, node: usize, visible: bool) {
+        if self.nodes[node].visible != visible {
+            self.nodes[node].visible = visible;
+            self.nodes[node].dirty = true;
+        }
+    }
+
+    /// Recomputes the world transforms below edited nodes, returns the nodes that moved.
+    pub fn update(&mut self) -> Vec<usize> {
+        let mut moved = vec![];
+        let mut stack: Vec<_> = self
+            .roots
+            .iter()
+            .map(|root| (*root, glm::identity(), true, false))
+            .collect();
+        while let Some((index, parent, parent_shown, parent_moved)) = stack.pop() {
+            let node = &mut self.nodes[index];
+            if node.dirty || parent_moved {
+                node.world = parent * node.local;
+                node.shown = parent_shown && node.visible;
+                node.dirty = false;
+                moved.push(index);
+            }
+            let (world, shown) = (node.world, node.shown);
+            let node_moved = moved.last() == Some(&index);
+            stack.extend(
+                node.children
+                    .iter()
+                    .map(|child| (*child, world, shown, node_moved)),
+            );
+        }
+        moved
+    }
+}
@@ -0,0 +1,90 @@
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::FRAC_PI_2;
+
+/// The length of one unit of the file, glTF uses meters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Units {
+    #[default]
+    Meters,
+    Centimeters,
+    Millimeters,
+    Inches,
+}
+impl Units {
+    pub const ALL: [Units; 4] = [
+        Units::Meters,
+        Units::Centimeters,
+        Units::Millimeters,
+        Units::Inches,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Units::Meters => "m",
+            Units::Centimeters => "cm",
+            Units::Millimeters => "mm",
+            Units::Inches => "inch",
+        }
+    }
+    /// Meters per unit.
+    pub fn scale(&self) -> f32 {
+        match self {
+            Units::Meters => 1.0,
+            Units::Centimeters => 0.01,
+            Units::Millimeters => 0.001,
+            Units::Inches => 0.0254,
+        }
+    }
+}
+
+/// The axis the file points up, glTF uses +Y.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum UpAxis {
+    #[default]
+    Y,
+    Z,
+}
+impl UpAxis {
+    pub const ALL: [UpAxis; 2] = [UpAxis::Y, UpAxis::Z];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UpAxis::Y => "Y-up",
+            UpAxis::Z => "Z-up",
+        }
+    }
+}
+
+/// How the file was authored, converted to meters and Y-up below the model transform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AssetSpace {
+    pub units: Units,
+    pub up: UpAxis,
+}
+impl AssetSpace {
+    pub fn matrix(&self) -> glm::Mat4 {
+        let scale = glm::scaling(&glm::Vec3::repeat(self.units.scale()));
+        match self.up {
+            UpAxis::Y => scale,
+            // +Z becomes +Y and +Y becomes -Z
+            UpAxis::Z => glm::rotate_x(&scale, -FRAC_PI_2),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Units")
+                .selected_text(self.units.name())
+                .show_ui(ui, |ui| {
+                    for units in Units::ALL {
+                        ui.selectable_value(&mut self.units, units, units.name());
+                    }
+                });
+            for up in UpAxis::ALL {
+                ui.radio_value(&mut self.up, up, up.name());
+            }
+        });
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{
+    camera::{Bookmarks, Camera},
+    material_presets::MaterialFactors,
+    skybox::{SkyboxSource, renderer::Background},
+    vktf::{GltfRenderInfo, ModelTransform, material_override::MaterialOverride},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WorkspaceError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A model of a workspace, loaded again from its file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceModel {
+    pub path: PathBuf,
+    pub transform: ModelTransform,
+    /// Factors of the materials by glTF index, as edited in the panel.
+    pub materials: Vec<MaterialFactors>,
+    pub default_material: MaterialFactors,
+    /// The picked material variant.
+    #[serde(default)]
+    pub variant: Option<usize>,
+}
+impl WorkspaceModel {
+    pub fn new(info: &GltfRenderInfo) -> Self {
+        Self {
+            path: info.vktf.path.clone(),
+            transform: info.transform(),
+            materials: info
+                .materials
+                .index
+                .iter()
+                .map(|material| MaterialFactors::new(&material.push))
+                .collect(),
+            default_material: MaterialFactors::new(&info.materials.default.push),
+            variant: info.variant(),
+        }
+    }
+    /// Places the loaded model and sets its material factors, the file may have changed since.
+    pub fn apply(&self, info: &mut GltfRenderInfo) {
+        info.set_transform(self.transform);
+        for (material, factors) in info.materials.index.iter_mut().zip(&self.materials) {
+            factors.apply(&mut material.push);
+        }
+        self.default_material
+            .apply(&mut info.materials.default.push);
+        if self
+            .variant
+            .is_none_or(|variant| variant < info.variants().len())
+        {
+            info.set_variant(self.variant);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceEnvironment {
+    /// `None` for the default sky.
+    pub source: Option<SkyboxSource>,
+    pub intensity: f32,
+    /// Rotation around the up axis in radians.
+    pub yaw: f32,
+    pub background: Background,
+}
+impl Default for WorkspaceEnvironment {
+    fn default() -> Self {
+        Self {
+            source: None,
+            intensity: 1.0,
+            yaw: 0.0,
+            background: Background::default(),
+        }
+    }
+}
+
+/// Which panels and windows are open and where the windows are.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiLayout {
+    /// Headers of the settings panel that are expanded.
+    pub sections: BTreeSet<String>,
+    pub stats: bool,
+    pub histogram: bool,
+    pub console: bool,
+    /// The folder of the gallery, if it is open.
+    pub gallery: Option<PathBuf>,
+    /// Top left corner of the shown windows.
+    pub windows: Vec<(egui::Id, egui::Pos2)>,
+}
+impl UiLayout {
+    pub fn window_positions(ctx: &egui::Context) -> Vec<(egui::Id, egui::Pos2)> {
+        ctx.memory(|memory| {
+            memory
+                .layer_ids()
+                .filter(|layer| {
+                    layer.order == egui::Order::Middle && memory.areas().is_visible(layer)
+                })
+                .filter_map(|layer| Some((layer.id, memory.area_rect(layer.id)?.min)))
+                .collect()
+        })
+    }
+}
+
+/// The loaded models with their placement and material edits, the environment, the camera
+/// and the layout of the UI, to set up a comparison again later. Stored as JSON.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Workspace {
+    pub models: Vec<WorkspaceModel>,
+    pub environment: WorkspaceEnvironment,
+    pub camera: Camera,
+    pub bookmarks: Bookmarks,
+    pub material_override: MaterialOverride,
+    pub layout: UiLayout,
+}
+impl Workspace {
+    pub fn save(&self, path: &Path) -> Result<(), WorkspaceError> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+    pub fn load(path: &Path) -> Result<Self, WorkspaceError> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+/// The parts of an opened workspace that wait for their models to load and their windows
+/// to be shown.
+#[derive(Default)]
+pub struct PendingWorkspace {
+    models: VecDeque<WorkspaceModel>,
+    windows: Vec<(egui::Id, egui::Pos2)>,
+}
+impl PendingWorkspace {
+    /// Replaces what is left of the last opened workspace.
+    pub fn start(&mut self, models: Vec<WorkspaceModel>, windows: Vec<(egui::Id, egui::Pos2)>) {
+        self.models = models.into();
+        self.windows = windows;
+    }
+    /// Call when `info` is added to the scene, it is set up like in the workspace if it is
+    /// one of its models.
+    pub fn model_loaded(&mut self, info: &mut GltfRenderInfo) {
+        let Some(i) = self
+            .models
+            .iter()
+            .position(|model| model.path == info.vktf.path)
+        else {
+            return;
+        };
+        if let Some(model) = self.models.remove(i) {
+            model.apply(info);
+        }
+    }
+    /// Moves the windows shown since the last call, call every frame before the UI.
+    pub fn place_windows(&mut self, ctx: &egui::Context) {
+        if self.windows.is_empty() {
+            return;
+        }
+        ctx.memory_mut(|memory| {
+            let areas = memory.areas_mut();
+            self.windows.retain(|&(id, pos)| {
+                let Some(mut state) = areas.get(id).copied() else {
+                    return true;
+                };
+                state.pivot = egui::Align2::LEFT_TOP;
+                state.pivot_pos = Some(pos);
+                areas.set_state(egui::LayerId::new(egui::Order::Middle, id), state);
+                false
+            });
+        });
+    }
+}